@@ -4,6 +4,7 @@
 use crate::{Push, XSpanIdString};
 use futures::FutureExt;
 use hyper::Request;
+use std::fmt;
 use std::marker::PhantomData;
 
 /// Middleware wrapper service, that should be used as the outermost layer in a
@@ -101,3 +102,201 @@ where
         self.inner.call((req, context))
     }
 }
+
+/// Middleware wrapper service, that should be used as the outermost layer in a
+/// stack of hyper services. Behaves like `AddContextMakeService`, but also uses
+/// the supplied extractor to convert the connection target (e.g. the remote
+/// address, or the peer credentials of a unix socket) into a value which is
+/// added to the context of every request on that connection.
+///
+/// ```rust
+/// # use std::net::SocketAddr;
+/// # use swagger::{AddContextWithTargetMakeService, XSpanIdString};
+/// # #[derive(Clone, Debug, Default)]
+/// # struct RemoteAddr(Option<SocketAddr>);
+/// swagger::new_context_type!(MyContext, MyEmptyContext, RemoteAddr, XSpanIdString);
+///
+/// # fn wrap<T>(make_service: T) {
+/// let make_service = AddContextWithTargetMakeService::<_, MyEmptyContext, _>::new(
+///     make_service,
+///     |target: &SocketAddr| RemoteAddr(Some(*target)),
+/// );
+/// # }
+/// ```
+pub struct AddContextWithTargetMakeService<T, C, F> {
+    inner: T,
+    extractor: F,
+    marker: PhantomData<C>,
+}
+
+impl<T, C, F> AddContextWithTargetMakeService<T, C, F> {
+    /// Create a new AddContextWithTargetMakeService struct wrapping a value,
+    /// using `extractor` to obtain the context value for each connection.
+    pub fn new(inner: T, extractor: F) -> Self {
+        AddContextWithTargetMakeService {
+            inner,
+            extractor,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, C, F> fmt::Debug for AddContextWithTargetMakeService<T, C, F>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AddContextWithTargetMakeService")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Inner, Context, Target, Extractor, Value> hyper::service::Service<Target>
+    for AddContextWithTargetMakeService<Inner, Context, Extractor>
+where
+    Context: Default + Push<Value> + 'static + Send,
+    Context::Result: Push<XSpanIdString>,
+    <Context::Result as Push<XSpanIdString>>::Result: Send + 'static,
+    Extractor: Fn(&Target) -> Value,
+    Value: Clone + Send + 'static,
+    Inner: hyper::service::Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Error = Inner::Error;
+    type Response = AddContextWithTargetService<Inner::Response, Context, Value>;
+    type Future = futures::future::BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let value = (self.extractor)(&target);
+        Box::pin(
+            self.inner
+                .call(target)
+                .map(|s| Ok(AddContextWithTargetService::new(s?, value))),
+        )
+    }
+}
+
+/// Middleware wrapper service, that should be used as the outermost layer in a
+/// stack of hyper services. Adds a context containing a per-connection value
+/// to a plain `hyper::Request`. The `AddContextWithTargetService` struct should
+/// not usually be used directly - when constructing a hyper stack use
+/// `AddContextWithTargetMakeService`, which will create `AddContextWithTargetService`
+/// instances as needed.
+pub struct AddContextWithTargetService<T, C, V> {
+    inner: T,
+    value: V,
+    marker: PhantomData<C>,
+}
+
+impl<T, C, V> AddContextWithTargetService<T, C, V> {
+    /// Create a new AddContextWithTargetService struct wrapping a value, adding
+    /// `value` to the context of each request.
+    pub fn new(inner: T, value: V) -> Self {
+        AddContextWithTargetService {
+            inner,
+            value,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, C, V> fmt::Debug for AddContextWithTargetService<T, C, V>
+where
+    T: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AddContextWithTargetService")
+            .field("inner", &self.inner)
+            .field("value", &self.value)
+            .finish()
+    }
+}
+
+impl<Inner, Context, Value, Body> hyper::service::Service<Request<Body>>
+    for AddContextWithTargetService<Inner, Context, Value>
+where
+    Context: Default + Push<Value> + Send + 'static,
+    Context::Result: Push<XSpanIdString>,
+    <Context::Result as Push<XSpanIdString>>::Result: Send + 'static,
+    Value: Clone,
+    Inner: hyper::service::Service<(
+        Request<Body>,
+        <Context::Result as Push<XSpanIdString>>::Result,
+    )>,
+{
+    type Response = Inner::Response;
+    type Error = Inner::Error;
+    type Future = Inner::Future;
+
+    fn call(&self, req: Request<Body>) -> Self::Future {
+        let x_span_id = XSpanIdString::get_or_generate(&req);
+        let context = Context::default().push(self.value.clone()).push(x_span_id);
+
+        self.inner.call((req, context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Has;
+    use hyper::service::Service;
+    use std::net::SocketAddr;
+
+    #[derive(Clone, Debug, Default, PartialEq)]
+    struct RemoteAddr(Option<SocketAddr>);
+
+    crate::new_context_type!(TestContext, TestEmptyContext, RemoteAddr, XSpanIdString);
+
+    struct MakeTestService;
+
+    impl<Target> Service<Target> for MakeTestService {
+        type Response = TestService;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _target: Target) -> Self::Future {
+            futures::future::ok(TestService)
+        }
+    }
+
+    struct TestService;
+
+    type TestRequest = (
+        Request<()>,
+        TestContext<XSpanIdString, TestContext<RemoteAddr, TestEmptyContext>>,
+    );
+
+    impl Service<TestRequest> for TestService {
+        type Response = (RemoteAddr, String);
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (_, context): TestRequest) -> Self::Future {
+            let remote_addr: &RemoteAddr = context.get();
+            let x_span_id: &XSpanIdString = context.get();
+            futures::future::ok((remote_addr.clone(), x_span_id.0.clone()))
+        }
+    }
+
+    #[tokio::test]
+    async fn adds_target_to_context() {
+        let make_service = AddContextWithTargetMakeService::<_, TestEmptyContext, _>::new(
+            MakeTestService,
+            |target: &SocketAddr| RemoteAddr(Some(*target)),
+        );
+
+        let addr: SocketAddr = "192.0.2.1:1234".parse().unwrap();
+        let service = make_service.call(addr).await.unwrap();
+        let request = Request::get("/")
+            .header(crate::X_SPAN_ID, "span")
+            .body(())
+            .unwrap();
+
+        let (remote_addr, x_span_id) = service.call(request).await.unwrap();
+        assert_eq!(remote_addr, RemoteAddr(Some(addr)));
+        assert_eq!(x_span_id, "span");
+    }
+}
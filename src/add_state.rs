@@ -0,0 +1,200 @@
+//! Hyper service that adds shared application state to the context of each
+//! request and passes it on to a wrapped service.
+//!
+//! This allows API implementations to reach shared state, such as database
+//! pools or configuration, through the context, e.g. via `Has<Arc<AppState>>`.
+
+use crate::Push;
+use futures::FutureExt;
+use hyper::Request;
+use std::marker::PhantomData;
+
+/// Middleware wrapper service which adds a clone of some shared state to the
+/// context of every request. The state will usually be an `Arc`, so cloning it
+/// is cheap.
+#[derive(Debug)]
+pub struct AddStateMakeService<T, S, C>
+where
+    C: Push<S> + Send + 'static,
+    C::Result: Send + 'static,
+{
+    inner: T,
+    state: S,
+    marker: PhantomData<C>,
+}
+
+impl<T, S, C> AddStateMakeService<T, S, C>
+where
+    C: Push<S> + Send + 'static,
+    C::Result: Send + 'static,
+{
+    /// Create a new AddStateMakeService struct wrapping a value, adding `state`
+    /// to each request context.
+    pub fn new(inner: T, state: S) -> Self {
+        AddStateMakeService {
+            inner,
+            state,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<Inner, State, Context, Target> hyper::service::Service<Target>
+    for AddStateMakeService<Inner, State, Context>
+where
+    Context: Push<State> + Send + 'static,
+    Context::Result: Send + 'static,
+    State: Clone + Send + 'static,
+    Inner: hyper::service::Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Error = Inner::Error;
+    type Response = AddStateService<Inner::Response, State, Context>;
+    type Future = futures::future::BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let state = self.state.clone();
+        Box::pin(
+            self.inner
+                .call(target)
+                .map(|s| Ok(AddStateService::new(s?, state))),
+        )
+    }
+}
+
+/// Middleware wrapper service which adds a clone of some shared state to the
+/// context of every request. Servers will normally want to use
+/// `AddStateMakeService`, which will create an `AddStateService` for each
+/// connection.
+#[derive(Debug)]
+pub struct AddStateService<T, S, C>
+where
+    C: Push<S> + Send + 'static,
+    C::Result: Send + 'static,
+{
+    inner: T,
+    state: S,
+    marker: PhantomData<C>,
+}
+
+impl<T, S, C> AddStateService<T, S, C>
+where
+    C: Push<S> + Send + 'static,
+    C::Result: Send + 'static,
+{
+    /// Create a new AddStateService struct wrapping a value, adding `state`
+    /// to each request context.
+    pub fn new(inner: T, state: S) -> Self {
+        AddStateService {
+            inner,
+            state,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, S, C> Clone for AddStateService<T, S, C>
+where
+    T: Clone,
+    S: Clone,
+    C: Push<S> + Send + 'static,
+    C::Result: Send + 'static,
+{
+    fn clone(&self) -> Self {
+        Self::new(self.inner.clone(), self.state.clone())
+    }
+}
+
+impl<Inner, State, Context, Body> hyper::service::Service<(Request<Body>, Context)>
+    for AddStateService<Inner, State, Context>
+where
+    Context: Push<State> + Send + 'static,
+    Context::Result: Send + 'static,
+    State: Clone,
+    Inner: hyper::service::Service<(Request<Body>, Context::Result)>,
+{
+    type Response = Inner::Response;
+    type Error = Inner::Error;
+    type Future = Inner::Future;
+
+    fn call(&self, (req, context): (Request<Body>, Context)) -> Self::Future {
+        self.inner.call((req, context.push(self.state.clone())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Has, StackBuilder, XSpanIdString};
+    use hyper::service::Service;
+    use std::sync::Arc;
+
+    #[derive(Debug, Default)]
+    struct AppState {
+        name: String,
+    }
+
+    crate::new_context_type!(TestContext, TestEmptyContext, Arc<AppState>, XSpanIdString);
+
+    struct MakeTestService;
+
+    impl<Target> Service<Target> for MakeTestService {
+        type Response = TestService;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _target: Target) -> Self::Future {
+            futures::future::ok(TestService)
+        }
+    }
+
+    struct TestService;
+
+    impl<C> Service<(Request<()>, C)> for TestService
+    where
+        C: Has<Arc<AppState>>,
+    {
+        type Response = String;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (_, context): (Request<()>, C)) -> Self::Future {
+            let state: &Arc<AppState> = context.get();
+            futures::future::ok(state.name.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn adds_state_to_context() {
+        let state = Arc::new(AppState {
+            name: "pets".to_string(),
+        });
+        let make_service =
+            AddStateMakeService::<_, _, TestEmptyContext>::new(MakeTestService, state);
+        let service = make_service.call(()).await.unwrap();
+
+        let name = service
+            .call((Request::get("/").body(()).unwrap(), TestEmptyContext))
+            .await
+            .unwrap();
+        assert_eq!(name, "pets");
+    }
+
+    #[tokio::test]
+    async fn adds_state_in_stack() {
+        let state = Arc::new(AppState {
+            name: "pets".to_string(),
+        });
+        let make_service = StackBuilder::new(MakeTestService)
+            .with_state(state)
+            .with_context::<TestEmptyContext>()
+            .build();
+        let service = make_service.call(()).await.unwrap();
+
+        let name = service
+            .call(Request::get("/").body(()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(name, "pets");
+    }
+}
@@ -0,0 +1,204 @@
+//! Map of the properties of an object not described by its schema.
+#[cfg(feature = "serdejson")]
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+#[cfg(feature = "serdejson")]
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::fmt;
+#[cfg(feature = "serdejson")]
+use std::marker::PhantomData;
+
+/// The additional properties of an object, for schemas with
+/// `additionalProperties`, in the order they were received.
+///
+/// Flattened into a model, it captures every field which isn't one of the
+/// model's own, and writes them back out when the model is serialized, so
+/// that they survive a round trip:
+///
+/// ```
+/// # use serde::{Deserialize, Serialize};
+/// # use swagger::AdditionalProperties;
+/// #[derive(Deserialize, Serialize)]
+/// struct Pet {
+///     name: String,
+///     #[serde(flatten)]
+///     additional_properties: AdditionalProperties<serde_json::Value>,
+/// }
+///
+/// let json = r#"{"name":"Rex","tricks":["sit"],"age":3}"#;
+/// let pet: Pet = serde_json::from_str(json).unwrap();
+/// assert_eq!(pet.additional_properties.get("age"), Some(&serde_json::json!(3)));
+/// assert_eq!(serde_json::to_string(&pet).unwrap(), json);
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct AdditionalProperties<T> {
+    properties: Vec<(String, T)>,
+}
+
+impl<T> Default for AdditionalProperties<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> AdditionalProperties<T> {
+    /// Create an empty map.
+    pub fn new() -> Self {
+        AdditionalProperties {
+            properties: Vec::new(),
+        }
+    }
+
+    /// The number of properties.
+    pub fn len(&self) -> usize {
+        self.properties.len()
+    }
+
+    /// Whether there are no properties.
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+
+    /// The value of a property.
+    pub fn get(&self, name: &str) -> Option<&T> {
+        self.properties
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value)
+    }
+
+    /// The value of a property, mutably.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut T> {
+        self.properties
+            .iter_mut()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Set a property, returning its previous value. New properties are
+    /// added at the end, but replacing a property keeps its position.
+    pub fn insert(&mut self, name: impl Into<String>, value: T) -> Option<T> {
+        let name = name.into();
+        match self.get_mut(&name) {
+            Some(existing) => Some(std::mem::replace(existing, value)),
+            None => {
+                self.properties.push((name, value));
+                None
+            }
+        }
+    }
+
+    /// Remove a property, returning its value.
+    pub fn remove(&mut self, name: &str) -> Option<T> {
+        let index = self.properties.iter().position(|(n, _)| n == name)?;
+        Some(self.properties.remove(index).1)
+    }
+
+    /// Iterate over the properties, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &T)> {
+        self.properties
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for AdditionalProperties<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<T, K: Into<String>> FromIterator<(K, T)> for AdditionalProperties<T> {
+    fn from_iter<I: IntoIterator<Item = (K, T)>>(iter: I) -> Self {
+        let mut properties = Self::new();
+        properties.extend(iter);
+        properties
+    }
+}
+
+impl<T, K: Into<String>> Extend<(K, T)> for AdditionalProperties<T> {
+    fn extend<I: IntoIterator<Item = (K, T)>>(&mut self, iter: I) {
+        for (name, value) in iter {
+            self.insert(name, value);
+        }
+    }
+}
+
+impl<T> IntoIterator for AdditionalProperties<T> {
+    type Item = (String, T);
+    type IntoIter = std::vec::IntoIter<(String, T)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.properties.into_iter()
+    }
+}
+
+#[cfg(feature = "serdejson")]
+impl<T: Serialize> Serialize for AdditionalProperties<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for (name, value) in self.iter() {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(feature = "serdejson")]
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for AdditionalProperties<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PropertiesVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for PropertiesVisitor<T> {
+            type Value = AdditionalProperties<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("an object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+                let mut properties = AdditionalProperties::new();
+                while let Some((name, value)) = access.next_entry::<String, T>()? {
+                    properties.insert(name, value);
+                }
+                Ok(properties)
+            }
+        }
+
+        deserializer.deserialize_map(PropertiesVisitor(PhantomData))
+    }
+}
+
+#[cfg(all(test, feature = "serdejson"))]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Labels {
+        #[serde(rename = "type")]
+        type_: String,
+        #[serde(flatten)]
+        additional_properties: AdditionalProperties<String>,
+    }
+
+    #[test]
+    fn round_trips_unknown_fields_in_order() {
+        let json = r#"{"zone":"b","type":"vm","region":"eu","app":"web"}"#;
+        let mut labels: Labels = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            labels.additional_properties.iter().collect::<Vec<_>>(),
+            [
+                ("zone", &"b".to_string()),
+                ("region", &"eu".to_string()),
+                ("app", &"web".to_string())
+            ]
+        );
+
+        labels.additional_properties.insert("zone", "a".to_string());
+        labels.additional_properties.remove("app");
+        assert_eq!(
+            serde_json::to_string(&labels).unwrap(),
+            r#"{"type":"vm","zone":"a","region":"eu"}"#
+        );
+    }
+}
@@ -1,5 +1,9 @@
-#[cfg(feature = "serdejson")]
-use base64::{engine::general_purpose::STANDARD, DecodeError, Engine};
+use base64::alphabet;
+use base64::engine::general_purpose::{
+    GeneralPurpose, GeneralPurposeConfig, STANDARD, URL_SAFE_NO_PAD,
+};
+use base64::engine::DecodePaddingMode;
+use base64::{DecodeError, Engine};
 #[cfg(feature = "serdevalid")]
 use paste;
 #[cfg(feature = "serdevalid")]
@@ -15,8 +19,42 @@ use std::ops::{Deref, DerefMut};
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 /// Base64-encoded byte array
+///
+/// This is encoded using the standard base64 alphabet, with padding. When
+/// decoding, the URL-safe alphabet is accepted too, and padding is optional.
+/// To encode using the URL-safe alphabet, use `to_url_safe`, or the
+/// `url_safe` serde helpers.
 pub struct ByteArray(pub Vec<u8>);
 
+/// Engines used for decoding, which accept input with or without padding.
+const STANDARD_DECODE: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+const URL_SAFE_DECODE: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+impl ByteArray {
+    /// Encode using the URL-safe base64 alphabet, without padding, as
+    /// described in RFC 4648 section 5.
+    pub fn to_url_safe(&self) -> String {
+        URL_SAFE_NO_PAD.encode(&self.0)
+    }
+
+    /// Decode from base64, using either the standard or the URL-safe
+    /// alphabet, with or without padding.
+    pub fn decode(s: &str) -> Result<Self, DecodeError> {
+        let engine = if s.contains(['-', '_']) {
+            &URL_SAFE_DECODE
+        } else {
+            &STANDARD_DECODE
+        };
+        Ok(ByteArray(engine.decode(s)?))
+    }
+}
+
 #[cfg(feature = "serdejson")]
 impl Serialize for ByteArray {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -34,10 +72,33 @@ impl<'de> Deserialize<'de> for ByteArray {
         D: Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        match STANDARD.decode(s) {
-            Ok(bin) => Ok(ByteArray(bin)),
-            _ => Err(D::Error::custom("invalid base64")),
-        }
+        ByteArray::decode(&s).map_err(|_| D::Error::custom("invalid base64"))
+    }
+}
+
+/// Serde helpers to encode a `ByteArray` using the URL-safe base64 alphabet,
+/// for use with `#[serde(with = "swagger::base64_format::url_safe")]`.
+#[cfg(feature = "serdejson")]
+pub mod url_safe {
+    use super::ByteArray;
+    use serde::de::{Deserialize, Deserializer, Error};
+    use serde::ser::Serializer;
+
+    /// Serialize a `ByteArray` using the URL-safe base64 alphabet.
+    pub fn serialize<S>(value: &ByteArray, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&value.to_url_safe())
+    }
+
+    /// Deserialize a `ByteArray` using either base64 alphabet.
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<ByteArray, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        ByteArray::decode(&s).map_err(|_| D::Error::custom("invalid base64"))
     }
 }
 
@@ -96,7 +157,7 @@ impl std::str::FromStr for ByteArray {
     type Err = DecodeError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self(STANDARD.decode(s)?))
+        Self::decode(s)
     }
 }
 
@@ -119,6 +180,39 @@ impl DerefMut for ByteArray {
     }
 }
 
+#[cfg(test)]
+#[cfg(feature = "serdejson")]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Token {
+        #[serde(with = "url_safe")]
+        token: ByteArray,
+        checksum: ByteArray,
+    }
+
+    #[test]
+    fn url_safe_byte_array() {
+        let bytes = ByteArray(vec![0xfb, 0xff, 0x01]);
+        assert_eq!(bytes.to_string(), "+/8B");
+        assert_eq!(bytes.to_url_safe(), "-_8B");
+        assert_eq!("-_8B".parse::<ByteArray>().unwrap(), bytes);
+        assert_eq!("AQ".parse::<ByteArray>().unwrap(), ByteArray(vec![1]));
+        assert!("!!".parse::<ByteArray>().is_err());
+
+        let token = Token {
+            token: bytes.clone(),
+            checksum: bytes,
+        };
+        let json = serde_json::to_string(&token).unwrap();
+        assert_eq!(json, r#"{"token":"-_8B","checksum":"+/8B"}"#);
+        let token: Token = serde_json::from_str(&json).unwrap();
+        assert_eq!(token.token, token.checksum);
+    }
+}
+
 #[cfg(test)]
 #[cfg(feature = "serdevalid")]
 mod serde_tests {
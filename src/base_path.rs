@@ -0,0 +1,243 @@
+//! Hyper service serving an API under a base path.
+//!
+//! Behind a path-based ingress, an API may receive requests for
+//! `/api/v1/pets` while the generated service routes `/pets`.
+//! `BasePathService` strips the base path from request paths before passing
+//! requests on, and rejects requests outside the base path with
+//! `404 Not Found`.
+//!
+//! Optionally, the base path is added to absolute paths in the `Location`,
+//! `Content-Location` and `Link` headers of responses, so that clients are
+//! sent links they can follow.
+//!
+//! ```rust
+//! # use swagger::base_path::BasePathService;
+//! # struct Api;
+//! let service = BasePathService::new(Api, "/api/v1").rewrite_links(true);
+//! ```
+
+use crate::composites::{matches_base_path, strip_base_path};
+use crate::response::json_error;
+use crate::{Has, XSpanIdString};
+use futures::future::{BoxFuture, FutureExt};
+use hyper::header::{HeaderMap, HeaderValue, CONTENT_LOCATION, LINK, LOCATION};
+use hyper::service::Service;
+use hyper::{Request, Response, StatusCode};
+use std::sync::Arc;
+
+/// Add the base path to an absolute path. Other URIs - including
+/// network-path references such as `//example.com/` - are unchanged.
+fn prefix(base_path: &str, uri: &str) -> Option<String> {
+    (uri.starts_with('/') && !uri.starts_with("//")).then(|| format!("{}{}", base_path, uri))
+}
+
+/// Add the base path to the URI references of a `Link` header, such as
+/// `</pets?page=2>; rel="next"`.
+fn prefix_links(base_path: &str, links: &str) -> String {
+    let mut rewritten = String::with_capacity(links.len());
+    let mut rest = links;
+    while let Some(start) = rest.find('<') {
+        let (before, after) = rest.split_at(start + 1);
+        rewritten.push_str(before);
+        let end = after.find('>').unwrap_or(after.len());
+        let (uri, after) = after.split_at(end);
+        rewritten.push_str(&prefix(base_path, uri).unwrap_or_else(|| uri.to_string()));
+        rest = after;
+    }
+    rewritten.push_str(rest);
+    rewritten
+}
+
+/// Add the base path to the links in response headers.
+fn rewrite_links(base_path: &str, headers: &mut HeaderMap) {
+    for name in [LOCATION, CONTENT_LOCATION] {
+        let value = headers
+            .get(&name)
+            .and_then(|value| prefix(base_path, value.to_str().ok()?))
+            .and_then(|value| HeaderValue::from_str(&value).ok());
+        if let Some(value) = value {
+            headers.insert(name, value);
+        }
+    }
+
+    let links: Vec<HeaderValue> = headers
+        .get_all(LINK)
+        .iter()
+        .filter_map(|value| {
+            let value = value.to_str().ok()?;
+            HeaderValue::from_str(&prefix_links(base_path, value)).ok()
+        })
+        .collect();
+    if !links.is_empty() {
+        headers.remove(LINK);
+        for link in links {
+            headers.append(LINK, link);
+        }
+    }
+}
+
+/// Middleware wrapper service which strips a base path from request paths.
+#[derive(Debug)]
+pub struct BasePathMakeService<T> {
+    inner: T,
+    base_path: Arc<str>,
+    rewrite_links: bool,
+}
+
+impl<T> BasePathMakeService<T> {
+    /// Create a new BasePathMakeService, serving the API under `base_path`.
+    pub fn new(inner: T, base_path: &str) -> Self {
+        BasePathMakeService {
+            inner,
+            base_path: base_path.trim_end_matches('/').into(),
+            rewrite_links: false,
+        }
+    }
+
+    /// Add the base path to absolute paths in the `Location`,
+    /// `Content-Location` and `Link` headers of responses.
+    pub fn rewrite_links(mut self, rewrite_links: bool) -> Self {
+        self.rewrite_links = rewrite_links;
+        self
+    }
+}
+
+impl<Inner, Target> Service<Target> for BasePathMakeService<Inner>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = BasePathService<Inner::Response>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let base_path = self.base_path.clone();
+        let rewrite_links = self.rewrite_links;
+        Box::pin(self.inner.call(target).map(move |s| {
+            Ok(BasePathService {
+                inner: s?,
+                base_path,
+                rewrite_links,
+            })
+        }))
+    }
+}
+
+/// Middleware wrapper service which strips a base path from request paths.
+/// Servers will normally want to use `BasePathMakeService`, which will create
+/// a `BasePathService` for each connection.
+#[derive(Debug, Clone)]
+pub struct BasePathService<T> {
+    inner: T,
+    base_path: Arc<str>,
+    rewrite_links: bool,
+}
+
+impl<T> BasePathService<T> {
+    /// Create a new BasePathService, serving the API under `base_path`.
+    pub fn new(inner: T, base_path: &str) -> Self {
+        BasePathService {
+            inner,
+            base_path: base_path.trim_end_matches('/').into(),
+            rewrite_links: false,
+        }
+    }
+
+    /// Add the base path to absolute paths in the `Location`,
+    /// `Content-Location` and `Link` headers of responses.
+    pub fn rewrite_links(mut self, rewrite_links: bool) -> Self {
+        self.rewrite_links = rewrite_links;
+        self
+    }
+}
+
+impl<T, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for BasePathService<T>
+where
+    C: Has<XSpanIdString>,
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    T::Error: Send + 'static,
+    ResBody: From<String> + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        if !matches_base_path(req.uri().path(), &self.base_path) {
+            return Box::pin(futures::future::ok(json_error(
+                StatusCode::NOT_FOUND,
+                "Not Found",
+                Some(&context.get().0),
+            )));
+        }
+
+        let response = self
+            .inner
+            .call((strip_base_path(req, &self.base_path), context));
+        if !self.rewrite_links {
+            return Box::pin(response);
+        }
+        let base_path = self.base_path.clone();
+        Box::pin(response.map(move |response| {
+            let mut response = response?;
+            rewrite_links(&base_path, response.headers_mut());
+            Ok(response)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContextBuilder, EmptyContext, Push};
+
+    struct TestApi;
+
+    impl<C> Service<(Request<()>, C)> for TestApi {
+        type Response = Response<String>;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, _): (Request<()>, C)) -> Self::Future {
+            let response = Response::builder()
+                .header(LOCATION, "/pets/2")
+                .header(CONTENT_LOCATION, "https://example.com/pets")
+                .header(
+                    LINK,
+                    "</pets?page=2>; rel=\"next\", <//cdn/a>; rel=\"icon\"",
+                )
+                .body(req.uri().to_string())
+                .unwrap();
+            futures::future::ok(response)
+        }
+    }
+
+    #[tokio::test]
+    async fn strips_base_path() {
+        let service = BasePathService::new(TestApi, "/api/v1/").rewrite_links(true);
+        let call = |uri: &str| {
+            let context: ContextBuilder<XSpanIdString, EmptyContext> =
+                EmptyContext.push(XSpanIdString("span".to_string()));
+            service.call((Request::get(uri).body(()).unwrap(), context))
+        };
+
+        let response = call("/api/v1/pets?limit=1").await.unwrap();
+        assert_eq!(response.body(), "/pets?limit=1");
+        assert_eq!(response.headers()[LOCATION], "/api/v1/pets/2");
+        assert_eq!(
+            response.headers()[CONTENT_LOCATION],
+            "https://example.com/pets"
+        );
+        assert_eq!(
+            response.headers()[LINK],
+            "</api/v1/pets?page=2>; rel=\"next\", <//cdn/a>; rel=\"icon\""
+        );
+
+        let response = call("/api/v1").await.unwrap();
+        assert_eq!(response.body(), "/");
+        let response = call("/api/v10/pets").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,166 @@
+//! Collecting bodies into memory without trusting their size.
+//!
+//! `http_body_util::BodyExt::collect` buffers however much the peer sends.
+//! `collect_limited` fails as soon as a body is known to be larger than a
+//! limit - either from its size hint, or once that much has been read - and
+//! `collect_limited_with_timeout` also bounds how long the peer can take to
+//! send it.
+//!
+//! ```rust
+//! # use swagger::body_ext::{collect_limited, CollectError};
+//! # use http_body_util::Full;
+//! # use hyper::body::Bytes;
+//! # futures::executor::block_on(async {
+//! let body = Full::new(Bytes::from("hello"));
+//! assert_eq!(collect_limited(body, 16).await.unwrap(), "hello");
+//!
+//! let body = Full::new(Bytes::from("hello"));
+//! assert!(matches!(collect_limited(body, 4).await, Err(CollectError::TooLarge(4))));
+//! # });
+//! ```
+
+use http_body_util::BodyExt as _;
+use hyper::body::{Body, Buf, Bytes};
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
+#[cfg(any(feature = "client", feature = "server"))]
+use std::time::Duration;
+
+/// Error collecting a body.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CollectError<E> {
+    /// The body was larger than the limit, in bytes.
+    TooLarge(u64),
+    /// The body wasn't received within the timeout.
+    #[cfg(any(feature = "client", feature = "server"))]
+    TimedOut(Duration),
+    /// Reading the body failed.
+    Body(E),
+}
+
+impl<E: fmt::Display> fmt::Display for CollectError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CollectError::TooLarge(limit) => write!(f, "Body larger than {} bytes", limit),
+            #[cfg(any(feature = "client", feature = "server"))]
+            CollectError::TimedOut(timeout) => {
+                write!(f, "Body not received within {:?}", timeout)
+            }
+            CollectError::Body(e) => write!(f, "Failed to read body: {}", e),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for CollectError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CollectError::Body(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl<E> CollectError<E>
+where
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    /// Convert into a boxed error. Errors reading the body are returned
+    /// unchanged, rather than wrapped.
+    pub fn into_boxed(self) -> Box<dyn Error + Send + Sync> {
+        match self {
+            CollectError::TooLarge(limit) => Box::new(CollectError::<Infallible>::TooLarge(limit)),
+            #[cfg(any(feature = "client", feature = "server"))]
+            CollectError::TimedOut(timeout) => {
+                Box::new(CollectError::<Infallible>::TimedOut(timeout))
+            }
+            CollectError::Body(e) => e.into(),
+        }
+    }
+}
+
+/// Collect a body into `Bytes`, failing with `CollectError::TooLarge` if it
+/// is longer than `limit` bytes. Trailers are discarded.
+pub async fn collect_limited<B: Body>(
+    body: B,
+    limit: u64,
+) -> Result<Bytes, CollectError<B::Error>> {
+    if body.size_hint().lower() > limit {
+        return Err(CollectError::TooLarge(limit));
+    }
+
+    let mut body = std::pin::pin!(body);
+    let mut collected = Vec::new();
+    while let Some(frame) = body.frame().await {
+        let frame = frame.map_err(CollectError::Body)?;
+        if let Ok(mut data) = frame.into_data() {
+            if (collected.len() + data.remaining()) as u64 > limit {
+                return Err(CollectError::TooLarge(limit));
+            }
+            while data.has_remaining() {
+                let chunk = data.chunk();
+                collected.extend_from_slice(chunk);
+                let len = chunk.len();
+                data.advance(len);
+            }
+        }
+    }
+    Ok(collected.into())
+}
+
+/// Collect a body into `Bytes` as `collect_limited` does, also failing with
+/// `CollectError::TimedOut` if the whole body isn't received within
+/// `timeout`.
+#[cfg(any(feature = "client", feature = "server"))]
+pub async fn collect_limited_with_timeout<B: Body>(
+    body: B,
+    limit: u64,
+    timeout: Duration,
+) -> Result<Bytes, CollectError<B::Error>> {
+    tokio::time::timeout(timeout, collect_limited(body, limit))
+        .await
+        .unwrap_or(Err(CollectError::TimedOut(timeout)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use http_body_util::StreamBody;
+    use hyper::body::Frame;
+
+    fn chunked(chunks: &'static [&'static str]) -> impl Body<Data = Bytes, Error = Infallible> {
+        let frames = chunks
+            .iter()
+            .map(|chunk| Ok(Frame::data(Bytes::from(*chunk))));
+        StreamBody::new(stream::iter(frames))
+    }
+
+    #[tokio::test]
+    async fn limits_streamed_bodies() {
+        let body = collect_limited(chunked(&["1234", "5678"]), 8)
+            .await
+            .unwrap();
+        assert_eq!(body, "12345678");
+
+        let result = collect_limited(chunked(&["1234", "5678", "9"]), 8).await;
+        assert!(matches!(result, Err(CollectError::TooLarge(8))));
+    }
+
+    #[cfg(any(feature = "client", feature = "server"))]
+    #[tokio::test(start_paused = true)]
+    async fn times_out_slow_bodies() {
+        let frames = stream::once(async {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            Ok::<_, Infallible>(Frame::data(Bytes::from("late")))
+        });
+        let result =
+            collect_limited_with_timeout(StreamBody::new(frames), 8, Duration::from_secs(1)).await;
+        assert!(matches!(result, Err(CollectError::TimedOut(_))));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Body not received within 1s"
+        );
+    }
+}
@@ -0,0 +1,378 @@
+//! Hyper service limiting the size of request bodies.
+//!
+//! `BodyLimitService` rejects requests whose `Content-Length` is over the
+//! limit for the operation with `413 Content Too Large`, before any of the
+//! body is read. Bodies without a `Content-Length` are counted as they're
+//! streamed - once the limit is passed, reading the body fails with
+//! `BodyTooLarge`, and the API's response is replaced with a `413`.
+//!
+//! ```rust
+//! # use swagger::body_limit::BodyLimits;
+//! let limits = BodyLimits::new(1024 * 1024).operation("uploadFile", 100 * 1024 * 1024);
+//! ```
+
+use crate::response::json_error;
+use crate::{Has, RequestParser, XSpanIdString};
+use futures::future::{BoxFuture, FutureExt};
+use hyper::body::{Body, Buf, Frame, SizeHint};
+use hyper::header::CONTENT_LENGTH;
+use hyper::service::Service;
+use hyper::{Request, Response, StatusCode};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+/// The maximum request body size for each operation of an API.
+#[derive(Debug, Clone)]
+pub struct BodyLimits {
+    default: u64,
+    operations: HashMap<&'static str, u64>,
+}
+
+impl BodyLimits {
+    /// Create a configuration where every operation has the given limit, in
+    /// bytes.
+    pub fn new(default: u64) -> Self {
+        BodyLimits {
+            default,
+            operations: HashMap::new(),
+        }
+    }
+
+    /// Set the limit for an operation, identified by the ID returned by the
+    /// API's `RequestParser`.
+    pub fn operation(mut self, operation_id: &'static str, limit: u64) -> Self {
+        self.operations.insert(operation_id, limit);
+        self
+    }
+
+    fn limit(&self, operation_id: Option<&'static str>) -> u64 {
+        operation_id
+            .and_then(|id| self.operations.get(id))
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// Error returned when reading a request body over the size limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BodyTooLarge(pub u64);
+
+impl fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Request body larger than {} bytes", self.0)
+    }
+}
+
+impl Error for BodyTooLarge {}
+
+/// Request body which fails once more than a limit has been read.
+pub struct LimitedBody<B> {
+    inner: Pin<Box<B>>,
+    remaining: u64,
+    limit: u64,
+    exceeded: Arc<AtomicBool>,
+}
+
+impl<B> LimitedBody<B> {
+    /// Limit `body` to `limit` bytes.
+    pub fn new(body: B, limit: u64) -> Self {
+        LimitedBody {
+            inner: Box::pin(body),
+            remaining: limit,
+            limit,
+            exceeded: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl<B> fmt::Debug for LimitedBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LimitedBody")
+            .field("limit", &self.limit)
+            .field("remaining", &self.remaining)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B> Body for LimitedBody<B>
+where
+    B: Body,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    type Data = B::Data;
+    type Error = Box<dyn Error + Send + Sync>;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = &mut *self;
+        match ready!(this.inner.as_mut().poll_frame(cx)) {
+            Some(Ok(frame)) => {
+                let len = frame.data_ref().map_or(0, |data| data.remaining() as u64);
+                match this.remaining.checked_sub(len) {
+                    Some(remaining) => {
+                        this.remaining = remaining;
+                        Poll::Ready(Some(Ok(frame)))
+                    }
+                    None => {
+                        this.exceeded.store(true, Ordering::Release);
+                        Poll::Ready(Some(Err(Box::new(BodyTooLarge(this.limit)))))
+                    }
+                }
+            }
+            Some(Err(e)) => Poll::Ready(Some(Err(e.into()))),
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Middleware wrapper service which limits the size of request bodies.
+pub struct BodyLimitMakeService<T, RP> {
+    inner: T,
+    limits: Arc<BodyLimits>,
+    marker: PhantomData<fn(RP)>,
+}
+
+impl<T, RP> BodyLimitMakeService<T, RP> {
+    /// Create a new BodyLimitMakeService, using `RP` to identify the
+    /// operation of each request.
+    pub fn new(inner: T, limits: BodyLimits) -> Self {
+        BodyLimitMakeService {
+            inner,
+            limits: Arc::new(limits),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, RP> fmt::Debug for BodyLimitMakeService<T, RP>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BodyLimitMakeService")
+            .field("inner", &self.inner)
+            .field("limits", &self.limits)
+            .finish()
+    }
+}
+
+impl<Inner, RP, Target> Service<Target> for BodyLimitMakeService<Inner, RP>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = BodyLimitService<Inner::Response, RP>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let limits = self.limits.clone();
+        Box::pin(self.inner.call(target).map(|s| {
+            Ok(BodyLimitService {
+                inner: s?,
+                limits,
+                marker: PhantomData,
+            })
+        }))
+    }
+}
+
+/// Middleware wrapper service which limits the size of request bodies,
+/// passing them to the API as `LimitedBody`s. Servers will normally want to
+/// use `BodyLimitMakeService`, which will create a `BodyLimitService` for
+/// each connection.
+pub struct BodyLimitService<T, RP> {
+    inner: T,
+    limits: Arc<BodyLimits>,
+    marker: PhantomData<fn(RP)>,
+}
+
+impl<T, RP> BodyLimitService<T, RP> {
+    /// Create a new BodyLimitService, using `RP` to identify the operation of
+    /// each request.
+    pub fn new(inner: T, limits: BodyLimits) -> Self {
+        BodyLimitService {
+            inner,
+            limits: Arc::new(limits),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, RP> Clone for BodyLimitService<T, RP>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        BodyLimitService {
+            inner: self.inner.clone(),
+            limits: self.limits.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, RP> fmt::Debug for BodyLimitService<T, RP>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BodyLimitService")
+            .field("inner", &self.inner)
+            .field("limits", &self.limits)
+            .finish()
+    }
+}
+
+impl<T, RP, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for BodyLimitService<T, RP>
+where
+    RP: RequestParser<ReqBody>,
+    T: Service<(Request<LimitedBody<ReqBody>>, C), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    T::Error: Send + 'static,
+    ResBody: From<String> + Send + 'static,
+    C: Has<XSpanIdString>,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let limit = self.limits.limit(RP::parse_operation_id(&req));
+        let x_span_id = Has::<XSpanIdString>::get(&context).0.clone();
+        let too_large = move || {
+            let message = format!("Request body larger than {} bytes", limit);
+            json_error(StatusCode::PAYLOAD_TOO_LARGE, &message, Some(&x_span_id))
+        };
+
+        let content_length = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        if content_length.is_some_and(|length| length > limit) {
+            return Box::pin(futures::future::ok(too_large()));
+        }
+
+        let (parts, body) = req.into_parts();
+        let body = LimitedBody::new(body, limit);
+        let exceeded = body.exceeded.clone();
+        let response = self.inner.call((Request::from_parts(parts, body), context));
+        Box::pin(async move {
+            let response = response.await?;
+            if exceeded.load(Ordering::Acquire) {
+                Ok(too_large())
+            } else {
+                Ok(response)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContextBuilder, EmptyContext, Push};
+    use futures::stream;
+    use http_body_util::{BodyExt, Full, StreamBody};
+    use hyper::body::Bytes;
+
+    type TestContext = ContextBuilder<XSpanIdString, EmptyContext>;
+    type TestBody = http_body_util::combinators::UnsyncBoxBody<Bytes, std::convert::Infallible>;
+
+    struct TestParser;
+
+    impl<B> RequestParser<B> for TestParser {
+        fn parse_operation_id(req: &Request<B>) -> Option<&'static str> {
+            match req.uri().path() {
+                "/upload" => Some("upload"),
+                _ => None,
+            }
+        }
+    }
+
+    /// Service which reads the body, responding with its length or the error.
+    struct ReadService;
+
+    impl Service<(Request<LimitedBody<TestBody>>, TestContext)> for ReadService {
+        type Response = Response<String>;
+        type Error = ();
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, _): (Request<LimitedBody<TestBody>>, TestContext)) -> Self::Future {
+            Box::pin(async move {
+                let body = match req.into_body().collect().await {
+                    Ok(body) => body.to_bytes().len().to_string(),
+                    Err(e) => e.to_string(),
+                };
+                Ok(Response::new(body))
+            })
+        }
+    }
+
+    fn request(path: &str, body: TestBody) -> (Request<TestBody>, TestContext) {
+        let mut request = Request::post(path);
+        if let Some(length) = body.size_hint().exact() {
+            request = request.header(CONTENT_LENGTH, length);
+        }
+        (
+            request.body(body).unwrap(),
+            EmptyContext.push(XSpanIdString("span".to_string())),
+        )
+    }
+
+    #[tokio::test]
+    async fn limits_bodies() {
+        let limits = BodyLimits::new(4).operation("upload", 8);
+        let service = BodyLimitService::<_, TestParser>::new(ReadService, limits);
+        let full = |body: &'static str| Full::new(Bytes::from(body)).boxed_unsync();
+        let chunked = |chunks: &'static [&'static str]| {
+            let frames = chunks
+                .iter()
+                .map(|chunk| Ok(Frame::data(Bytes::from(*chunk))));
+            StreamBody::new(stream::iter(frames)).boxed_unsync()
+        };
+
+        let response = service.call(request("/pets", full("1234"))).await.unwrap();
+        assert_eq!(response.body(), "4");
+
+        let response = service.call(request("/pets", full("12345"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            response.body(),
+            r#"{"code":413,"message":"Request body larger than 4 bytes","x-span-id":"span"}"#
+        );
+
+        let response = service
+            .call(request("/upload", chunked(&["1234", "5678"])))
+            .await
+            .unwrap();
+        assert_eq!(response.body(), "8");
+
+        let response = service
+            .call(request("/upload", chunked(&["1234", "5678", "9"])))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            response.body(),
+            r#"{"code":413,"message":"Request body larger than 8 bytes","x-span-id":"span"}"#
+        );
+    }
+}
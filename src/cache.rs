@@ -0,0 +1,612 @@
+//! Client middleware caching responses, as described in RFC 9111.
+//!
+//! `CacheService` stores cacheable responses to `GET` requests, serves them
+//! while they are fresh, and revalidates them with the server using
+//! `If-None-Match` and `If-Modified-Since` once they are stale. Responses are
+//! stored in a `CacheStore` - `MemoryStore` is an in-memory LRU store.
+//!
+//! By default the cache behaves as a shared cache, so that it's safe to use
+//! for clients making requests on behalf of several users: responses marked
+//! `private`, or to requests with an `Authorization` header, aren't stored
+//! unless the response explicitly allows it.
+//!
+//! Only responses with a known length of at most `max_body_size` are stored,
+//! as they have to be buffered.
+//!
+//! ```rust
+//! # use swagger::cache::{CacheService, MemoryStore};
+//! # fn wrap<T>(client: T) {
+//! let client = CacheService::new(client, MemoryStore::new(1000)).shared(false);
+//! # }
+//! ```
+
+use crate::body_ext::{collect_limited, CollectError};
+use futures::future::{self, BoxFuture, FutureExt};
+use headers::{Age, CacheControl, Date, Expires, HeaderMapExt, Vary};
+use http_body_util::{Either, Full};
+use hyper::body::{Body, Bytes};
+use hyper::header::{
+    HeaderName, HeaderValue, AUTHORIZATION, CONTENT_LENGTH, ETAG, IF_MATCH, IF_MODIFIED_SINCE,
+    IF_NONE_MATCH, IF_RANGE, IF_UNMODIFIED_SINCE, LAST_MODIFIED, RANGE,
+};
+use hyper::service::Service;
+use hyper::{HeaderMap, Method, Request, Response, StatusCode};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Default maximum size of response bodies which are stored.
+const DEFAULT_MAX_BODY_SIZE: u64 = 1024 * 1024;
+
+/// A response stored in a cache.
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    /// The response's status code.
+    pub status: StatusCode,
+    /// The response's headers.
+    pub headers: HeaderMap,
+    /// The response's body.
+    pub body: Bytes,
+    /// The values of the request headers named by the response's `Vary`
+    /// header, which must match for the response to be reused.
+    pub vary: Vec<(HeaderName, Option<HeaderValue>)>,
+    /// When the request which produced the response was sent.
+    pub request_time: SystemTime,
+    /// When the response was received.
+    pub response_time: SystemTime,
+}
+
+impl CachedResponse {
+    /// Whether the response can be used for a request with these headers.
+    fn matches(&self, headers: &HeaderMap) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| headers.get(name) == value.as_ref())
+    }
+
+    /// The age of the response - RFC 9111 section 4.2.3
+    fn current_age(&self, now: SystemTime) -> Duration {
+        let date = self
+            .headers
+            .typed_get::<Date>()
+            .map(SystemTime::from)
+            .unwrap_or(self.response_time);
+        let apparent_age = self.response_time.duration_since(date).unwrap_or_default();
+        let age_value = self
+            .headers
+            .typed_get::<Age>()
+            .map(|age| Duration::from_secs(age.as_secs()))
+            .unwrap_or_default();
+        let response_delay = self
+            .response_time
+            .duration_since(self.request_time)
+            .unwrap_or_default();
+        let resident_time = now.duration_since(self.response_time).unwrap_or_default();
+
+        apparent_age.max(age_value + response_delay) + resident_time
+    }
+
+    /// How long the response is fresh for - RFC 9111 section 4.2.1
+    fn freshness_lifetime(&self, shared: bool) -> Duration {
+        let cache_control = self.headers.typed_get::<CacheControl>();
+        if let Some(cache_control) = &cache_control {
+            if let Some(s_max_age) = cache_control.s_max_age().filter(|_| shared) {
+                return s_max_age;
+            }
+            if let Some(max_age) = cache_control.max_age() {
+                return max_age;
+            }
+        }
+
+        match self.headers.typed_get::<Expires>() {
+            Some(expires) => {
+                let date = self
+                    .headers
+                    .typed_get::<Date>()
+                    .map(SystemTime::from)
+                    .unwrap_or(self.response_time);
+                SystemTime::from(expires)
+                    .duration_since(date)
+                    .unwrap_or_default()
+            }
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Whether the response can be used without revalidation.
+    fn is_fresh(&self, request: &Option<CacheControl>, shared: bool, now: SystemTime) -> bool {
+        let response = self.headers.typed_get::<CacheControl>();
+        if response.as_ref().is_some_and(CacheControl::no_cache)
+            || request.as_ref().is_some_and(CacheControl::no_cache)
+        {
+            return false;
+        }
+
+        let age = self.current_age(now);
+        let max_age = request.as_ref().and_then(CacheControl::max_age);
+        age < self.freshness_lifetime(shared) && max_age.is_none_or(|max_age| age <= max_age)
+    }
+
+    fn to_response(&self, now: SystemTime) -> Response<Full<Bytes>> {
+        let mut response = Response::new(Full::new(self.body.clone()));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+            .headers_mut()
+            .typed_insert(Age::from_secs(self.current_age(now).as_secs()));
+        response
+    }
+}
+
+/// Storage for cached responses.
+///
+/// Implementations may evict responses at any time.
+pub trait CacheStore: Send + Sync + 'static {
+    /// Get the response stored for a key.
+    fn get(&self, key: &str) -> Option<CachedResponse>;
+
+    /// Store a response, replacing any response already stored for the key.
+    fn put(&self, key: String, response: CachedResponse);
+
+    /// Remove the response stored for a key.
+    fn remove(&self, key: &str);
+}
+
+/// In-memory `CacheStore`, evicting the least recently used response once
+/// full.
+pub struct MemoryStore {
+    capacity: usize,
+    lru: Mutex<Lru>,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<String, (CachedResponse, u64)>,
+    order: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl Lru {
+    fn touch(&mut self, key: &str) -> Option<&CachedResponse> {
+        self.tick += 1;
+        let (response, used) = self.entries.get_mut(key)?;
+        self.order.remove(used);
+        *used = self.tick;
+        self.order.insert(self.tick, key.to_string());
+        Some(response)
+    }
+}
+
+impl MemoryStore {
+    /// Create a store holding up to `capacity` responses.
+    pub fn new(capacity: usize) -> Self {
+        MemoryStore {
+            capacity,
+            lru: Mutex::new(Lru::default()),
+        }
+    }
+
+    /// The number of responses stored.
+    pub fn len(&self) -> usize {
+        self.lru.lock().unwrap().entries.len()
+    }
+
+    /// Whether the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl fmt::Debug for MemoryStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryStore")
+            .field("capacity", &self.capacity)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl CacheStore for MemoryStore {
+    fn get(&self, key: &str) -> Option<CachedResponse> {
+        self.lru.lock().unwrap().touch(key).cloned()
+    }
+
+    fn put(&self, key: String, response: CachedResponse) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut lru = self.lru.lock().unwrap();
+        lru.tick += 1;
+        let tick = lru.tick;
+        if let Some((_, used)) = lru.entries.insert(key.clone(), (response, tick)) {
+            lru.order.remove(&used);
+        }
+        lru.order.insert(tick, key);
+
+        while lru.entries.len() > self.capacity {
+            if let Some((_, oldest)) = lru.order.pop_first() {
+                lru.entries.remove(&oldest);
+            }
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        let mut lru = self.lru.lock().unwrap();
+        if let Some((_, used)) = lru.entries.remove(key) {
+            lru.order.remove(&used);
+        }
+    }
+}
+
+/// Client middleware caching responses.
+///
+/// Put it outside `RetryService` and `HedgeService`, so that fresh responses
+/// are served without going through them, and inside `RewriteService`, so
+/// that responses are stored under the URI they were fetched from.
+#[derive(Debug)]
+pub struct CacheService<T, S = MemoryStore> {
+    inner: T,
+    store: Arc<S>,
+    shared: bool,
+    max_body_size: u64,
+}
+
+impl<T, S> Clone for CacheService<T, S>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        CacheService {
+            inner: self.inner.clone(),
+            store: self.store.clone(),
+            shared: self.shared,
+            max_body_size: self.max_body_size,
+        }
+    }
+}
+
+impl<T, S> CacheService<T, S> {
+    /// Create a new CacheService, storing responses in `store`.
+    pub fn new(inner: T, store: S) -> Self {
+        CacheService {
+            inner,
+            store: Arc::new(store),
+            shared: true,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Whether the cache is shared between users. Shared caches don't store
+    /// responses marked `private`, or responses to authenticated requests
+    /// unless the response explicitly allows it. Defaults to true.
+    pub fn shared(mut self, shared: bool) -> Self {
+        self.shared = shared;
+        self
+    }
+
+    /// Set the maximum size of response bodies which are stored. Defaults to
+    /// 1MiB.
+    pub fn max_body_size(mut self, max_body_size: u64) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// The store holding cached responses.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+}
+
+/// Whether a response may be stored - RFC 9111 section 3
+fn is_storable<B: Body>(
+    response: &Response<B>,
+    authorized: bool,
+    shared: bool,
+    max_body_size: u64,
+) -> bool {
+    // Status codes which are heuristically cacheable - RFC 9110 section 15.1
+    let status_cacheable = matches!(
+        response.status().as_u16(),
+        200 | 203 | 204 | 300 | 301 | 308 | 404 | 405 | 410 | 414 | 501
+    );
+
+    let headers = response.headers();
+    let cache_control = headers.typed_get::<CacheControl>();
+    let cache_control = cache_control.as_ref();
+    if !status_cacheable
+        || cache_control.is_some_and(CacheControl::no_store)
+        || (shared && cache_control.is_some_and(CacheControl::private))
+        || headers
+            .typed_get::<Vary>()
+            .is_some_and(|vary| vary.is_any())
+    {
+        return false;
+    }
+
+    if shared && authorized {
+        let allowed = cache_control
+            .is_some_and(|cc| cc.public() || cc.must_revalidate() || cc.s_max_age().is_some());
+        if !allowed {
+            return false;
+        }
+    }
+
+    // Responses without explicit freshness or a validator would never be
+    // used, as heuristic freshness isn't supported.
+    let useful = cache_control.is_some_and(|cc| cc.max_age().is_some() || cc.s_max_age().is_some())
+        || headers.contains_key(hyper::header::EXPIRES)
+        || headers.contains_key(ETAG)
+        || headers.contains_key(LAST_MODIFIED);
+
+    useful
+        && response
+            .body()
+            .size_hint()
+            .upper()
+            .is_some_and(|size| size <= max_body_size)
+}
+
+/// Update a stored response with the headers of a 304 response - RFC 9111
+/// section 4.3.4
+fn freshen(cached: &mut CachedResponse, headers: &HeaderMap) {
+    for name in headers.keys() {
+        if name != CONTENT_LENGTH {
+            cached.headers.remove(name);
+        }
+    }
+    for (name, value) in headers {
+        if name != CONTENT_LENGTH {
+            cached.headers.append(name, value.clone());
+        }
+    }
+}
+
+impl<T, S, B, C, ResBody> Service<(Request<B>, C)> for CacheService<T, S>
+where
+    T: Service<(Request<B>, C), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    T::Error: Into<Box<dyn Error + Send + Sync>>,
+    S: CacheStore,
+    ResBody: Body<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    type Response = Response<Either<Full<Bytes>, ResBody>>;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (mut req, context): (Request<B>, C)) -> Self::Future {
+        let key = req.uri().to_string();
+        let store = self.store.clone();
+
+        if req.method() != Method::GET {
+            let invalidate = !req.method().is_safe();
+            return self
+                .inner
+                .call((req, context))
+                .map(move |result| {
+                    let response = result.map_err(Into::into)?;
+                    // Unsafe requests invalidate stored responses - RFC 9111
+                    // section 4.4
+                    if invalidate
+                        && (response.status().is_success() || response.status().is_redirection())
+                    {
+                        store.remove(&key);
+                    }
+                    Ok(response.map(Either::Right))
+                })
+                .boxed();
+        }
+
+        let request_cache_control = req.headers().typed_get::<CacheControl>();
+        let conditional = [
+            IF_MATCH,
+            IF_NONE_MATCH,
+            IF_MODIFIED_SINCE,
+            IF_UNMODIFIED_SINCE,
+            IF_RANGE,
+            RANGE,
+        ]
+        .iter()
+        .any(|name| req.headers().contains_key(name));
+        if conditional
+            || request_cache_control
+                .as_ref()
+                .is_some_and(CacheControl::no_store)
+        {
+            return self
+                .inner
+                .call((req, context))
+                .map(|result| Ok(result.map_err(Into::into)?.map(Either::Right)))
+                .boxed();
+        }
+
+        let now = SystemTime::now();
+        let cached = store
+            .get(&key)
+            .filter(|cached| cached.matches(req.headers()));
+        if let Some(cached) = &cached {
+            if cached.is_fresh(&request_cache_control, self.shared, now) {
+                return future::ok(cached.to_response(now).map(Either::Left)).boxed();
+            }
+
+            // Revalidate the stored response
+            if let Some(etag) = cached.headers.get(ETAG) {
+                req.headers_mut().insert(IF_NONE_MATCH, etag.clone());
+            }
+            if let Some(last_modified) = cached.headers.get(LAST_MODIFIED) {
+                req.headers_mut()
+                    .insert(IF_MODIFIED_SINCE, last_modified.clone());
+            }
+        }
+
+        let authorized = req.headers().contains_key(AUTHORIZATION);
+        let request_headers = req.headers().clone();
+        let shared = self.shared;
+        let max_body_size = self.max_body_size;
+        let future = self.inner.call((req, context));
+
+        Box::pin(async move {
+            let response = future.await.map_err(Into::into)?;
+            let response_time = SystemTime::now();
+
+            if response.status() == StatusCode::NOT_MODIFIED {
+                if let Some(mut cached) = cached {
+                    freshen(&mut cached, response.headers());
+                    cached.request_time = now;
+                    cached.response_time = response_time;
+                    let fresh = cached.to_response(response_time);
+                    store.put(key, cached);
+                    return Ok(fresh.map(Either::Left));
+                }
+            }
+
+            if !is_storable(&response, authorized, shared, max_body_size) {
+                return Ok(response.map(Either::Right));
+            }
+
+            let (parts, body) = response.into_parts();
+            let body = collect_limited(body, max_body_size)
+                .await
+                .map_err(CollectError::into_boxed)?;
+
+            let vary = parts
+                .headers
+                .typed_get::<Vary>()
+                .map(|vary| {
+                    vary.iter_strs()
+                        .filter_map(|name| HeaderName::from_bytes(name.as_bytes()).ok())
+                        .map(|name| {
+                            let value = request_headers.get(&name).cloned();
+                            (name, value)
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            store.put(
+                key,
+                CachedResponse {
+                    status: parts.status,
+                    headers: parts.headers.clone(),
+                    body: body.clone(),
+                    vary,
+                    request_time: now,
+                    response_time,
+                },
+            );
+
+            Ok(Response::from_parts(parts, Either::Left(Full::new(body))))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Service counting requests, and responding with the given headers -
+    /// or 304 if the request has a matching `If-None-Match` header.
+    #[derive(Clone)]
+    struct Origin {
+        calls: Arc<AtomicUsize>,
+        headers: Vec<(HeaderName, &'static str)>,
+    }
+
+    impl Service<(Request<()>, ())> for Origin {
+        type Response = Response<Full<Bytes>>;
+        type Error = Infallible;
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, _): (Request<()>, ())) -> Self::Future {
+            let calls = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            let mut response = Response::new(Full::new(Bytes::from(format!("call {}", calls))));
+            for (name, value) in &self.headers {
+                response
+                    .headers_mut()
+                    .insert(name, HeaderValue::from_static(value));
+            }
+            if req.headers().get(IF_NONE_MATCH) == response.headers().get(ETAG)
+                && req.headers().contains_key(IF_NONE_MATCH)
+            {
+                *response.status_mut() = StatusCode::NOT_MODIFIED;
+                *response.body_mut() = Full::new(Bytes::new());
+            }
+            Box::pin(future::ok(response))
+        }
+    }
+
+    async fn get(service: &CacheService<Origin>) -> (StatusCode, Bytes) {
+        let response = service.call((Request::new(()), ())).await.unwrap();
+        let status = response.status();
+        (
+            status,
+            response.into_body().collect().await.unwrap().to_bytes(),
+        )
+    }
+
+    #[tokio::test]
+    async fn serves_fresh_responses() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let origin = Origin {
+            calls: calls.clone(),
+            headers: vec![(hyper::header::CACHE_CONTROL, "max-age=60")],
+        };
+        let service = CacheService::new(origin, MemoryStore::new(10));
+
+        assert_eq!(get(&service).await, (StatusCode::OK, Bytes::from("call 1")));
+        assert_eq!(get(&service).await, (StatusCode::OK, Bytes::from("call 1")));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let origin = Origin {
+            calls: calls.clone(),
+            headers: vec![(hyper::header::CACHE_CONTROL, "no-store, max-age=60")],
+        };
+        let service = CacheService::new(origin, MemoryStore::new(10));
+        assert_eq!(get(&service).await, (StatusCode::OK, Bytes::from("call 2")));
+        assert_eq!(get(&service).await, (StatusCode::OK, Bytes::from("call 3")));
+        assert!(service.store().is_empty());
+    }
+
+    #[tokio::test]
+    async fn revalidates_stale_responses() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let origin = Origin {
+            calls: calls.clone(),
+            headers: vec![(hyper::header::CACHE_CONTROL, "no-cache"), (ETAG, "\"v1\"")],
+        };
+        let service = CacheService::new(origin, MemoryStore::new(10));
+
+        assert_eq!(get(&service).await, (StatusCode::OK, Bytes::from("call 1")));
+        // The origin responds 304, so the stored body is used
+        assert_eq!(get(&service).await, (StatusCode::OK, Bytes::from("call 1")));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let store = MemoryStore::new(2);
+        let response = CachedResponse {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+            vary: Vec::new(),
+            request_time: SystemTime::now(),
+            response_time: SystemTime::now(),
+        };
+
+        store.put("a".to_string(), response.clone());
+        store.put("b".to_string(), response.clone());
+        assert!(store.get("a").is_some());
+        store.put("c".to_string(), response);
+
+        assert!(store.get("a").is_some());
+        assert!(store.get("b").is_none());
+        assert!(store.get("c").is_some());
+    }
+}
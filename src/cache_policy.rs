@@ -0,0 +1,407 @@
+//! Hyper service applying caching policies to responses.
+//!
+//! Policies are set per operation, using the API's `RequestParser`, with a
+//! default for other operations. A policy sets the `Cache-Control` header,
+//! and optionally `Expires`, `Pragma` and `Surrogate-Control` (for CDNs).
+//! Policies are only applied to successful and `304 Not Modified`
+//! responses, and never replace a `Cache-Control` header set by the API.
+//!
+//! Responses to authenticated requests - those with `AuthData` in their
+//! context - get `no-store`, unless their operation has its own policy, so
+//! that private data isn't cached by accident.
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! # use swagger::cache_policy::{CachePolicies, CachePolicy};
+//! let policies = CachePolicies::new()
+//!     .operation("listPets", CachePolicy::public(Duration::from_secs(60)).expires(true))
+//!     .operation("getPet", CachePolicy::private(Duration::from_secs(10)).must_revalidate())
+//!     .operation("getLogo", CachePolicy::public(Duration::from_secs(3600))
+//!         .surrogate(Duration::from_secs(86400)));
+//! ```
+
+use crate::{AuthData, Has, RequestParser};
+use futures::future::{BoxFuture, FutureExt};
+use headers::{Expires, HeaderMapExt};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, CACHE_CONTROL, PRAGMA};
+use hyper::service::Service;
+use hyper::{Request, Response, StatusCode};
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Header - `Surrogate-Control` - used to control caching by CDNs.
+pub const SURROGATE_CONTROL: &str = "Surrogate-Control";
+
+/// Who may cache a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Visibility {
+    NoStore,
+    NoCache,
+    Private,
+    Public,
+}
+
+/// A caching policy for responses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachePolicy {
+    visibility: Visibility,
+    max_age: Option<Duration>,
+    s_maxage: Option<Duration>,
+    stale_while_revalidate: Option<Duration>,
+    must_revalidate: bool,
+    immutable: bool,
+    expires: bool,
+    surrogate: Option<Duration>,
+}
+
+impl CachePolicy {
+    fn new(visibility: Visibility, max_age: Option<Duration>) -> Self {
+        CachePolicy {
+            visibility,
+            max_age,
+            s_maxage: None,
+            stale_while_revalidate: None,
+            must_revalidate: false,
+            immutable: false,
+            expires: false,
+            surrogate: None,
+        }
+    }
+
+    /// Responses mustn't be stored by any cache - `no-store`, with
+    /// `Pragma: no-cache` for HTTP/1.0 caches.
+    pub fn no_store() -> Self {
+        Self::new(Visibility::NoStore, None)
+    }
+
+    /// Responses may be stored, but must be revalidated before each use -
+    /// `no-cache`, with `Pragma: no-cache` for HTTP/1.0 caches.
+    pub fn no_cache() -> Self {
+        Self::new(Visibility::NoCache, None)
+    }
+
+    /// Responses may be stored by the client's cache only, for `max_age`.
+    pub fn private(max_age: Duration) -> Self {
+        Self::new(Visibility::Private, Some(max_age))
+    }
+
+    /// Responses may be stored by any cache, for `max_age`.
+    pub fn public(max_age: Duration) -> Self {
+        Self::new(Visibility::Public, Some(max_age))
+    }
+
+    /// Set a different maximum age for shared caches - `s-maxage`.
+    pub fn s_maxage(mut self, s_maxage: Duration) -> Self {
+        self.s_maxage = Some(s_maxage);
+        self
+    }
+
+    /// Allow stale responses to be used while they are revalidated in the
+    /// background - `stale-while-revalidate`.
+    pub fn stale_while_revalidate(mut self, duration: Duration) -> Self {
+        self.stale_while_revalidate = Some(duration);
+        self
+    }
+
+    /// Stale responses mustn't be used without revalidation -
+    /// `must-revalidate`.
+    pub fn must_revalidate(mut self) -> Self {
+        self.must_revalidate = true;
+        self
+    }
+
+    /// Responses won't change while fresh - `immutable`.
+    pub fn immutable(mut self) -> Self {
+        self.immutable = true;
+        self
+    }
+
+    /// Also set an `Expires` header, for HTTP/1.0 caches.
+    pub fn expires(mut self, expires: bool) -> Self {
+        self.expires = expires;
+        self
+    }
+
+    /// Set a `Surrogate-Control` header, allowing CDNs to store responses for
+    /// a different time to other caches.
+    pub fn surrogate(mut self, max_age: Duration) -> Self {
+        self.surrogate = Some(max_age);
+        self
+    }
+
+    /// The `Cache-Control` header value for this policy.
+    pub fn cache_control(&self) -> String {
+        let mut directives = vec![match self.visibility {
+            Visibility::NoStore => "no-store".to_string(),
+            Visibility::NoCache => "no-cache".to_string(),
+            Visibility::Private => "private".to_string(),
+            Visibility::Public => "public".to_string(),
+        }];
+        let seconds = |name: &str, duration: Option<Duration>| {
+            duration.map(|duration| format!("{}={}", name, duration.as_secs()))
+        };
+        directives.extend(seconds("max-age", self.max_age));
+        directives.extend(seconds("s-maxage", self.s_maxage));
+        directives.extend(seconds(
+            "stale-while-revalidate",
+            self.stale_while_revalidate,
+        ));
+        if self.must_revalidate {
+            directives.push("must-revalidate".to_string());
+        }
+        if self.immutable {
+            directives.push("immutable".to_string());
+        }
+        directives.join(", ")
+    }
+
+    /// Set the headers for this policy on a response.
+    fn apply(&self, headers: &mut HeaderMap) {
+        if let Ok(value) = HeaderValue::from_str(&self.cache_control()) {
+            headers.insert(CACHE_CONTROL, value);
+        }
+        if matches!(self.visibility, Visibility::NoStore | Visibility::NoCache) {
+            headers.insert(PRAGMA, HeaderValue::from_static("no-cache"));
+        }
+        if self.expires {
+            let expires = SystemTime::now() + self.max_age.unwrap_or_default();
+            headers.typed_insert(Expires::from(expires));
+        }
+        if let Some(surrogate) = self.surrogate {
+            headers.insert(
+                HeaderName::from_static("surrogate-control"),
+                HeaderValue::from_str(&format!("max-age={}", surrogate.as_secs()))
+                    .expect("max-age is a valid header value"),
+            );
+        }
+    }
+}
+
+/// The caching policy of each operation of an API.
+#[derive(Debug, Clone, Default)]
+pub struct CachePolicies {
+    default: Option<CachePolicy>,
+    operations: HashMap<&'static str, CachePolicy>,
+}
+
+impl CachePolicies {
+    /// Create an empty table, which only sets `no-store` on responses to
+    /// authenticated requests.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the policy for operations without their own policy.
+    pub fn default_policy(mut self, policy: CachePolicy) -> Self {
+        self.default = Some(policy);
+        self
+    }
+
+    /// Set the policy for an operation ID, as returned by the API's
+    /// `RequestParser`.
+    pub fn operation(mut self, operation_id: &'static str, policy: CachePolicy) -> Self {
+        self.operations.insert(operation_id, policy);
+        self
+    }
+
+    /// The policy for a request.
+    fn policy(&self, operation_id: Option<&str>, authenticated: bool) -> Option<CachePolicy> {
+        match operation_id.and_then(|id| self.operations.get(id)) {
+            Some(policy) => Some(policy.clone()),
+            None if authenticated => Some(CachePolicy::no_store()),
+            None => self.default.clone(),
+        }
+    }
+}
+
+/// Middleware wrapper service which applies caching policies to responses.
+pub struct CachePolicyMakeService<T, RP> {
+    inner: T,
+    policies: Arc<CachePolicies>,
+    marker: PhantomData<fn(RP)>,
+}
+
+impl<T, RP> CachePolicyMakeService<T, RP> {
+    /// Create a new CachePolicyMakeService.
+    pub fn new(inner: T, policies: CachePolicies) -> Self {
+        CachePolicyMakeService {
+            inner,
+            policies: Arc::new(policies),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: fmt::Debug, RP> fmt::Debug for CachePolicyMakeService<T, RP> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachePolicyMakeService")
+            .field("inner", &self.inner)
+            .field("policies", &self.policies)
+            .finish()
+    }
+}
+
+impl<Inner, RP, Target> Service<Target> for CachePolicyMakeService<Inner, RP>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = CachePolicyService<Inner::Response, RP>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let policies = self.policies.clone();
+        Box::pin(self.inner.call(target).map(|s| {
+            Ok(CachePolicyService {
+                inner: s?,
+                policies,
+                marker: PhantomData,
+            })
+        }))
+    }
+}
+
+/// Middleware wrapper service which applies caching policies to responses.
+/// Servers will normally want to use `CachePolicyMakeService`, which will
+/// create a `CachePolicyService` for each connection.
+pub struct CachePolicyService<T, RP> {
+    inner: T,
+    policies: Arc<CachePolicies>,
+    marker: PhantomData<fn(RP)>,
+}
+
+impl<T, RP> CachePolicyService<T, RP> {
+    /// Create a new CachePolicyService.
+    pub fn new(inner: T, policies: CachePolicies) -> Self {
+        CachePolicyService {
+            inner,
+            policies: Arc::new(policies),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Clone, RP> Clone for CachePolicyService<T, RP> {
+    fn clone(&self) -> Self {
+        CachePolicyService {
+            inner: self.inner.clone(),
+            policies: self.policies.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: fmt::Debug, RP> fmt::Debug for CachePolicyService<T, RP> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachePolicyService")
+            .field("inner", &self.inner)
+            .field("policies", &self.policies)
+            .finish()
+    }
+}
+
+impl<T, RP, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for CachePolicyService<T, RP>
+where
+    RP: RequestParser<ReqBody>,
+    C: Has<Option<AuthData>>,
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let authenticated = context.get().is_some();
+        let policy = self
+            .policies
+            .policy(RP::parse_operation_id(&req), authenticated);
+        Box::pin(self.inner.call((req, context)).map(move |response| {
+            let mut response = response?;
+            let cacheable =
+                response.status().is_success() || response.status() == StatusCode::NOT_MODIFIED;
+            if let Some(policy) = policy {
+                if cacheable && !response.headers().contains_key(CACHE_CONTROL) {
+                    policy.apply(response.headers_mut());
+                }
+            }
+            Ok(response)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContextBuilder, EmptyContext, Push};
+
+    struct TestParser;
+
+    impl<B> RequestParser<B> for TestParser {
+        fn parse_operation_id(req: &Request<B>) -> Option<&'static str> {
+            match req.uri().path() {
+                "/pets" => Some("listPets"),
+                "/error" => Some("error"),
+                _ => None,
+            }
+        }
+    }
+
+    struct TestApi;
+
+    impl<C> Service<(Request<()>, C)> for TestApi {
+        type Response = Response<()>;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, _): (Request<()>, C)) -> Self::Future {
+            let mut response = Response::new(());
+            if req.uri().path() == "/error" {
+                *response.status_mut() = StatusCode::NOT_FOUND;
+            }
+            futures::future::ok(response)
+        }
+    }
+
+    #[tokio::test]
+    async fn applies_policies() {
+        let policies = CachePolicies::new()
+            .default_policy(CachePolicy::no_cache())
+            .operation(
+                "listPets",
+                CachePolicy::public(Duration::from_secs(60))
+                    .s_maxage(Duration::from_secs(300))
+                    .stale_while_revalidate(Duration::from_secs(30))
+                    .expires(true)
+                    .surrogate(Duration::from_secs(3600)),
+            )
+            .operation("error", CachePolicy::public(Duration::from_secs(60)));
+        let service = CachePolicyService::<_, TestParser>::new(TestApi, policies);
+        let call = |path: &str, auth: Option<AuthData>| {
+            let context: ContextBuilder<Option<AuthData>, EmptyContext> = EmptyContext.push(auth);
+            service.call((Request::get(path).body(()).unwrap(), context))
+        };
+
+        let response = call("/pets", Some(AuthData::apikey("key"))).await.unwrap();
+        let headers = response.headers();
+        assert_eq!(
+            headers[CACHE_CONTROL],
+            "public, max-age=60, s-maxage=300, stale-while-revalidate=30"
+        );
+        assert!(headers.typed_get::<Expires>().is_some());
+        assert_eq!(headers[SURROGATE_CONTROL], "max-age=3600");
+        assert!(headers.get(PRAGMA).is_none());
+
+        let response = call("/other", None).await.unwrap();
+        assert_eq!(response.headers()[CACHE_CONTROL], "no-cache");
+        assert_eq!(response.headers()[PRAGMA], "no-cache");
+        let response = call("/other", Some(AuthData::apikey("key"))).await.unwrap();
+        assert_eq!(response.headers()[CACHE_CONTROL], "no-store");
+        let response = call("/error", None).await.unwrap();
+        assert!(response.headers().get(CACHE_CONTROL).is_none());
+    }
+}
@@ -0,0 +1,72 @@
+//! CBOR request and response bodies, as described in RFC 8949.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+
+/// The `application/cbor` media type.
+pub const APPLICATION_CBOR: &str = "application/cbor";
+
+/// Whether a `Content-Type` is CBOR: `application/cbor`, or a type with the
+/// `+cbor` suffix.
+pub fn is_cbor(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence == APPLICATION_CBOR || essence.ends_with("+cbor")
+}
+
+/// Error returned when a CBOR body can't be written or read.
+#[derive(Debug)]
+pub struct CborError(String);
+
+impl fmt::Display for CborError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid CBOR: {}", self.0)
+    }
+}
+
+impl std::error::Error for CborError {}
+
+/// Serialize `value` as CBOR.
+pub fn to_vec<T: Serialize>(value: &T) -> Result<Vec<u8>, CborError> {
+    let mut body = Vec::new();
+    ciborium::into_writer(value, &mut body).map_err(|e| CborError(e.to_string()))?;
+    Ok(body)
+}
+
+/// Deserialize a CBOR body.
+pub fn from_slice<T: DeserializeOwned>(body: &[u8]) -> Result<T, CborError> {
+    ciborium::from_reader(body).map_err(|e| CborError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Pet {
+        id: u64,
+        name: String,
+    }
+
+    #[test]
+    fn round_trips() {
+        let pet = Pet {
+            id: 1,
+            name: "Rex".to_string(),
+        };
+        let body = to_vec(&pet).unwrap();
+        // A two entry map, keyed by field name
+        assert_eq!(&body[..4], b"\xa2\x62id");
+        assert_eq!(from_slice::<Pet>(&body).unwrap(), pet);
+        assert!(from_slice::<Pet>(b"\xff").is_err());
+
+        assert!(is_cbor("application/cbor; charset=binary"));
+        assert!(!is_cbor("application/json"));
+    }
+}
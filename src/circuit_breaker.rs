@@ -0,0 +1,448 @@
+//! Client middleware stopping requests to failing hosts.
+//!
+//! `CircuitBreakerService` tracks the outcome of requests to each host. When
+//! too many fail, the host's circuit opens, and requests are rejected
+//! immediately with a `CircuitOpen` error rather than waiting for the host to
+//! fail them. After a while the circuit becomes half-open, letting a limited
+//! number of probe requests through - if they succeed the circuit closes
+//! again, and otherwise it reopens.
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! # use swagger::circuit_breaker::{CircuitBreakers, CircuitBreakerPolicy};
+//! let breakers = CircuitBreakers::new(
+//!     CircuitBreakerPolicy::new()
+//!         .consecutive_failures(5)
+//!         .error_rate(0.5, 20)
+//!         .open_duration(Duration::from_secs(10)),
+//! );
+//!
+//! // `breakers` can be cloned and kept to report the state of each circuit.
+//! assert_eq!(breakers.state("api.example.com"), None);
+//! ```
+
+use futures::future::BoxFuture;
+use hyper::service::Service;
+use hyper::{Request, Response};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// State of the circuit for a host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests are allowed.
+    Closed,
+    /// Requests are rejected.
+    Open,
+    /// A limited number of probe requests are allowed.
+    HalfOpen,
+}
+
+/// Error returned when a request is rejected because the circuit for its host
+/// is open.
+#[derive(Debug, Clone)]
+pub struct CircuitOpen {
+    /// The host whose circuit is open.
+    pub host: String,
+}
+
+impl fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Circuit open for {}", self.host)
+    }
+}
+
+impl Error for CircuitOpen {}
+
+/// Policy deciding when a circuit opens, and how it recovers.
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerPolicy {
+    consecutive_failures: Option<u32>,
+    error_rate: Option<(f64, usize)>,
+    open_duration: Duration,
+    half_open_requests: u32,
+}
+
+impl Default for CircuitBreakerPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CircuitBreakerPolicy {
+    /// Create a new policy, opening the circuit after 5 consecutive failures
+    /// for 30 seconds, then allowing 1 probe request.
+    ///
+    /// A request fails if no response is received, or the response has a
+    /// `5xx` status.
+    pub fn new() -> Self {
+        CircuitBreakerPolicy {
+            consecutive_failures: Some(5),
+            error_rate: None,
+            open_duration: Duration::from_secs(30),
+            half_open_requests: 1,
+        }
+    }
+
+    /// Open the circuit after `failures` consecutive failures.
+    pub fn consecutive_failures(mut self, failures: u32) -> Self {
+        self.consecutive_failures = Some(failures);
+        self
+    }
+
+    /// Open the circuit when at least `rate` of the last `window` requests
+    /// have failed.
+    pub fn error_rate(mut self, rate: f64, window: usize) -> Self {
+        self.error_rate = Some((rate, window));
+        self
+    }
+
+    /// Set how long the circuit stays open before allowing probe requests.
+    pub fn open_duration(mut self, open_duration: Duration) -> Self {
+        self.open_duration = open_duration;
+        self
+    }
+
+    /// Set the number of probe requests allowed while half-open. All must
+    /// succeed for the circuit to close.
+    pub fn half_open_requests(mut self, requests: u32) -> Self {
+        self.half_open_requests = requests.max(1);
+        self
+    }
+}
+
+#[derive(Debug)]
+enum Circuit {
+    Closed {
+        consecutive_failures: u32,
+        outcomes: VecDeque<bool>,
+    },
+    Open {
+        until: Instant,
+    },
+    HalfOpen {
+        in_flight: u32,
+        successes: u32,
+    },
+}
+
+impl Circuit {
+    fn closed() -> Self {
+        Circuit::Closed {
+            consecutive_failures: 0,
+            outcomes: VecDeque::new(),
+        }
+    }
+
+    fn state(&self) -> CircuitState {
+        match self {
+            Circuit::Closed { .. } => CircuitState::Closed,
+            Circuit::Open { .. } => CircuitState::Open,
+            Circuit::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+}
+
+/// Circuits for each host, shared by all services using the same policy.
+/// Clones share the same circuits, so can be used to report their state, e.g.
+/// to a metrics system.
+#[derive(Debug, Clone)]
+pub struct CircuitBreakers {
+    policy: Arc<CircuitBreakerPolicy>,
+    circuits: Arc<Mutex<HashMap<String, Circuit>>>,
+}
+
+impl CircuitBreakers {
+    /// Create a new set of circuits, using `policy`.
+    pub fn new(policy: CircuitBreakerPolicy) -> Self {
+        CircuitBreakers {
+            policy: Arc::new(policy),
+            circuits: Arc::default(),
+        }
+    }
+
+    /// State of the circuit for a host, or `None` if no requests have been
+    /// sent to it.
+    pub fn state(&self, host: &str) -> Option<CircuitState> {
+        let mut circuits = self.circuits.lock().unwrap();
+        let circuit = circuits.get_mut(host)?;
+        self.expire(circuit);
+        Some(circuit.state())
+    }
+
+    /// State of the circuit for each host requests have been sent to.
+    pub fn states(&self) -> Vec<(String, CircuitState)> {
+        let mut circuits = self.circuits.lock().unwrap();
+        circuits
+            .iter_mut()
+            .map(|(host, circuit)| {
+                self.expire(circuit);
+                (host.clone(), circuit.state())
+            })
+            .collect()
+    }
+
+    /// Move an open circuit to half-open once the open duration has passed.
+    fn expire(&self, circuit: &mut Circuit) {
+        if let Circuit::Open { until } = circuit {
+            if Instant::now() >= *until {
+                *circuit = Circuit::HalfOpen {
+                    in_flight: 0,
+                    successes: 0,
+                };
+            }
+        }
+    }
+
+    fn acquire(&self, host: &str) -> Result<Permit, CircuitOpen> {
+        let mut circuits = self.circuits.lock().unwrap();
+        let circuit = circuits
+            .entry(host.to_string())
+            .or_insert_with(Circuit::closed);
+        self.expire(circuit);
+
+        match circuit {
+            Circuit::Closed { .. } => {}
+            Circuit::HalfOpen { in_flight, .. } if *in_flight < self.policy.half_open_requests => {
+                *in_flight += 1;
+            }
+            _ => {
+                return Err(CircuitOpen {
+                    host: host.to_string(),
+                })
+            }
+        }
+
+        Ok(Permit {
+            breakers: self.clone(),
+            host: host.to_string(),
+            probe: circuit.state() == CircuitState::HalfOpen,
+            recorded: false,
+        })
+    }
+
+    fn record(&self, host: &str, probe: bool, success: bool) {
+        let mut circuits = self.circuits.lock().unwrap();
+        let circuit = match circuits.get_mut(host) {
+            Some(circuit) => circuit,
+            None => return,
+        };
+        let policy = &self.policy;
+        let open = Circuit::Open {
+            until: Instant::now() + policy.open_duration,
+        };
+
+        match circuit {
+            Circuit::Closed {
+                consecutive_failures,
+                outcomes,
+            } => {
+                *consecutive_failures = if success {
+                    0
+                } else {
+                    *consecutive_failures + 1
+                };
+
+                let mut trip = policy
+                    .consecutive_failures
+                    .is_some_and(|max| *consecutive_failures >= max);
+
+                if let Some((rate, window)) = policy.error_rate {
+                    outcomes.push_back(success);
+                    while outcomes.len() > window {
+                        outcomes.pop_front();
+                    }
+                    let failures = outcomes.iter().filter(|success| !**success).count();
+                    trip |=
+                        outcomes.len() >= window && failures as f64 >= rate * outcomes.len() as f64;
+                }
+
+                if trip {
+                    *circuit = open;
+                }
+            }
+            Circuit::HalfOpen {
+                in_flight,
+                successes,
+            } if probe => {
+                *in_flight -= 1;
+                if !success {
+                    *circuit = open;
+                } else {
+                    *successes += 1;
+                    if *successes >= policy.half_open_requests {
+                        *circuit = Circuit::closed();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Permission to send a request, which releases the probe slot if the
+/// request is cancelled before its outcome is recorded.
+struct Permit {
+    breakers: CircuitBreakers,
+    host: String,
+    probe: bool,
+    recorded: bool,
+}
+
+impl Permit {
+    fn record(mut self, success: bool) {
+        self.recorded = true;
+        self.breakers.record(&self.host, self.probe, success);
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if !self.recorded && self.probe {
+            let mut circuits = self.breakers.circuits.lock().unwrap();
+            if let Some(Circuit::HalfOpen { in_flight, .. }) = circuits.get_mut(&self.host) {
+                *in_flight -= 1;
+            }
+        }
+    }
+}
+
+/// Client middleware applying per-host circuit breakers to requests.
+///
+/// Put it outside `RetryService`, so that requests to a host whose circuit is
+/// open fail at once rather than being retried, and a request only counts as
+/// failed once its retries are exhausted. Put it inside `RewriteService`, so
+/// that it tracks the hosts requests are actually sent to.
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerService<T> {
+    inner: T,
+    breakers: CircuitBreakers,
+}
+
+impl<T> CircuitBreakerService<T> {
+    /// Create a new CircuitBreakerService, using `breakers`.
+    pub fn new(inner: T, breakers: CircuitBreakers) -> Self {
+        CircuitBreakerService { inner, breakers }
+    }
+}
+
+impl<T, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for CircuitBreakerService<T>
+where
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    T::Error: Into<Box<dyn Error + Send + Sync>>,
+    ResBody: Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let host = req
+            .uri()
+            .authority()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+
+        let permit = match self.breakers.acquire(&host) {
+            Ok(permit) => permit,
+            Err(e) => return Box::pin(futures::future::err(e.into())),
+        };
+
+        let future = self.inner.call((req, context));
+        Box::pin(async move {
+            let result = future.await;
+            let success = match &result {
+                Ok(response) => !response.status().is_server_error(),
+                Err(_) => false,
+            };
+            permit.record(success);
+            result.map_err(Into::into)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::StatusCode;
+
+    #[derive(Clone)]
+    struct TestService(Arc<Mutex<StatusCode>>);
+
+    impl Service<(Request<()>, ())> for TestService {
+        type Response = Response<()>;
+        type Error = std::io::Error;
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _req: (Request<()>, ())) -> Self::Future {
+            let mut response = Response::new(());
+            *response.status_mut() = *self.0.lock().unwrap();
+            futures::future::ok(response)
+        }
+    }
+
+    fn request(host: &str) -> (Request<()>, ()) {
+        (
+            Request::get(format!("http://{}/pets", host))
+                .body(())
+                .unwrap(),
+            (),
+        )
+    }
+
+    #[tokio::test]
+    async fn opens_and_recovers() {
+        let status = Arc::new(Mutex::new(StatusCode::SERVICE_UNAVAILABLE));
+        let breakers = CircuitBreakers::new(
+            CircuitBreakerPolicy::new()
+                .consecutive_failures(2)
+                .open_duration(Duration::from_millis(20)),
+        );
+        let service = CircuitBreakerService::new(TestService(status.clone()), breakers.clone());
+
+        for _ in 0..2 {
+            let response = service.call(request("failing")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        }
+        assert_eq!(breakers.state("failing"), Some(CircuitState::Open));
+
+        let error = service.call(request("failing")).await.unwrap_err();
+        assert!(error.downcast_ref::<CircuitOpen>().is_some());
+
+        // Other hosts are unaffected
+        assert!(service.call(request("other")).await.is_ok());
+        assert_eq!(breakers.state("other"), Some(CircuitState::Closed));
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(breakers.state("failing"), Some(CircuitState::HalfOpen));
+
+        *status.lock().unwrap() = StatusCode::OK;
+        assert!(service.call(request("failing")).await.is_ok());
+        assert_eq!(breakers.state("failing"), Some(CircuitState::Closed));
+    }
+
+    #[tokio::test]
+    async fn opens_on_error_rate() {
+        let status = Arc::new(Mutex::new(StatusCode::OK));
+        let breakers = CircuitBreakers::new(
+            CircuitBreakerPolicy::new()
+                .consecutive_failures(u32::MAX)
+                .error_rate(0.5, 4),
+        );
+        let service = CircuitBreakerService::new(TestService(status.clone()), breakers.clone());
+
+        for code in [StatusCode::OK, StatusCode::BAD_GATEWAY, StatusCode::OK] {
+            *status.lock().unwrap() = code;
+            service.call(request("host")).await.unwrap();
+        }
+        assert_eq!(breakers.state("host"), Some(CircuitState::Closed));
+
+        *status.lock().unwrap() = StatusCode::BAD_GATEWAY;
+        service.call(request("host")).await.unwrap();
+        assert_eq!(breakers.state("host"), Some(CircuitState::Open));
+    }
+}
@@ -0,0 +1,207 @@
+//! Builder for hyper-util clients, exposing connection pool settings.
+
+use crate::Connector;
+use hyper::body::Body;
+use hyper_util::client::legacy::connect::{Connect, HttpConnector};
+use hyper_util::client::legacy::{Builder, Client};
+use hyper_util::rt::{TokioExecutor, TokioTimer};
+use std::time::Duration;
+
+/// Builder for hyper-util `Client`s, for use by generated clients.
+///
+/// The client uses the tokio runtime for its executor and timers, so that
+/// timer based settings such as the pool idle timeout take effect.
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use swagger::client::ClientBuilder;
+/// # use http_body_util::Full;
+/// # use hyper::body::Bytes;
+/// let client = ClientBuilder::new()
+///     .pool_max_idle_per_host(8)
+///     .pool_idle_timeout(Duration::from_secs(30))
+///     .connect_timeout(Duration::from_secs(5))
+///     .build_http::<Full<Bytes>>();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+    builder: Builder,
+    connect_timeout: Option<Duration>,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientBuilder {
+    /// Create a new builder, with hyper-util's default settings.
+    pub fn new() -> Self {
+        let mut builder = Client::builder(TokioExecutor::new());
+        builder
+            .timer(TokioTimer::new())
+            .pool_timer(TokioTimer::new());
+        ClientBuilder {
+            builder,
+            connect_timeout: None,
+        }
+    }
+
+    /// Set the maximum number of idle connections kept per host. By default,
+    /// this is unlimited.
+    pub fn pool_max_idle_per_host(mut self, max_idle: usize) -> Self {
+        self.builder.pool_max_idle_per_host(max_idle);
+        self
+    }
+
+    /// Set how long idle connections are kept in the pool. By default, this
+    /// is 90 seconds.
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.builder.pool_idle_timeout(timeout);
+        self
+    }
+
+    /// Set the timeout for establishing TCP connections, for clients created
+    /// using `build_http`. Other connectors should be configured with
+    /// `Connector::builder().connect_timeout` instead.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Use HTTP/2 with prior knowledge for all requests, rather than HTTP/1.1
+    /// or negotiating the protocol using ALPN. This is needed for cleartext
+    /// HTTP/2 (h2c) services.
+    #[cfg(feature = "http2")]
+    pub fn http2_prior_knowledge(mut self, enabled: bool) -> Self {
+        self.builder.http2_only(enabled);
+        self
+    }
+
+    /// Set the interval between HTTP/2 keep-alive pings. By default, pings
+    /// aren't sent.
+    #[cfg(feature = "http2")]
+    pub fn http2_keep_alive_interval(mut self, interval: Duration) -> Self {
+        self.builder.http2_keep_alive_interval(interval);
+        self
+    }
+
+    /// Set how long to wait for a response to an HTTP/2 keep-alive ping before
+    /// closing the connection. By default, this is 20 seconds.
+    #[cfg(feature = "http2")]
+    pub fn http2_keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.builder.http2_keep_alive_timeout(timeout);
+        self
+    }
+
+    /// Send HTTP/2 keep-alive pings even when there are no requests in
+    /// flight. By default, pings are only sent while requests are in flight.
+    #[cfg(feature = "http2")]
+    pub fn http2_keep_alive_while_idle(mut self, enabled: bool) -> Self {
+        self.builder.http2_keep_alive_while_idle(enabled);
+        self
+    }
+
+    /// Get the underlying hyper-util builder, for settings not exposed here.
+    pub fn hyper_builder(&mut self) -> &mut Builder {
+        &mut self.builder
+    }
+
+    /// Build a client using the given connector.
+    pub fn build<C, B>(&self, connector: C) -> Client<C, B>
+    where
+        C: Connect + Clone,
+        B: Body + Send,
+        B::Data: Send,
+    {
+        self.builder.build(connector)
+    }
+
+    /// Build a client for plain HTTP.
+    pub fn build_http<B>(&self) -> Client<HttpConnector, B>
+    where
+        B: Body + Send,
+        B::Data: Send,
+    {
+        let mut connector = Connector::builder();
+        if let Some(timeout) = self.connect_timeout {
+            connector = connector.connect_timeout(timeout);
+        }
+        self.build(connector.build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Empty, Full};
+    use hyper::body::Bytes;
+    use hyper::{Request, Response};
+    use hyper_util::rt::TokioIo;
+    use std::convert::Infallible;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn sends_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service =
+                hyper::service::service_fn(|_req: Request<hyper::body::Incoming>| async {
+                    Ok::<_, Infallible>(Response::new(Full::new(Bytes::from("ok"))))
+                });
+            hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+                .unwrap();
+        });
+
+        let client = ClientBuilder::new()
+            .pool_max_idle_per_host(1)
+            .pool_idle_timeout(Duration::from_secs(1))
+            .connect_timeout(Duration::from_secs(1))
+            .build_http::<Empty<Bytes>>();
+
+        // Both requests use the same pooled connection, as the server only
+        // accepts one.
+        for _ in 0..2 {
+            let response = client
+                .get(format!("http://{}/", addr).parse().unwrap())
+                .await
+                .unwrap();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, "ok");
+        }
+    }
+
+    #[cfg(feature = "http2")]
+    #[tokio::test]
+    async fn http2_prior_knowledge() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service =
+                hyper::service::service_fn(|req: Request<hyper::body::Incoming>| async move {
+                    let version = format!("{:?}", req.version());
+                    Ok::<_, Infallible>(Response::new(Full::new(Bytes::from(version))))
+                });
+            hyper::server::conn::http2::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+                .unwrap();
+        });
+
+        let client = ClientBuilder::new()
+            .http2_prior_knowledge(true)
+            .build_http::<Empty<Bytes>>();
+        let response = client
+            .get(format!("http://{}/", addr).parse().unwrap())
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "HTTP/2.0");
+    }
+}
@@ -0,0 +1,476 @@
+//! Hyper service working out the client of each request, behind proxies.
+//!
+//! Behind a load balancer or reverse proxy, the remote address of a
+//! connection is the proxy's, and the scheme and host the client used are
+//! only known from the headers the proxy adds - the RFC 7239 `Forwarded`
+//! header, or the legacy `X-Forwarded-For`, `X-Forwarded-Proto` and
+//! `X-Forwarded-Host` headers.
+//!
+//! `ClientInfoService` adds a `ClientInfo` entry to the context of each
+//! request, with the effective client IP address, scheme and host, for
+//! logging, rate limiting and generating absolute URLs. These headers can be
+//! set by anyone, so they are only believed when the connection comes from a
+//! trusted proxy. The forwarding chain is followed back through trusted
+//! proxies, and the first untrusted address is taken to be the client.
+//!
+//! The remote address is taken from the `ConnectInfo<SocketAddr>` added to
+//! the context by `IntoMakeServiceWithConnectInfo`.
+//!
+//! ```rust
+//! # use swagger::client_info::ClientInfoService;
+//! # struct Api;
+//! # type Context = swagger::EmptyContext;
+//! let service = ClientInfoService::<_, Context>::new(Api)
+//!     .trust("10.0.0.0/8".parse().unwrap())
+//!     .trust("::1/128".parse().unwrap());
+//! ```
+
+use crate::make::ConnectInfo;
+use crate::{Has, Push};
+use hyper::header::{HeaderMap, HOST};
+use hyper::service::Service;
+use hyper::Request;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+
+/// The effective client of a request, added to the context by
+/// `ClientInfoService`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+    /// The IP address of the client.
+    pub ip: IpAddr,
+    /// The scheme the client used, such as `https`.
+    pub scheme: String,
+    /// The host the client requested, if known.
+    pub host: Option<String>,
+}
+
+impl ClientInfo {
+    /// The origin the client used, such as `https://example.com`, for
+    /// generating absolute URLs.
+    pub fn origin(&self) -> Option<String> {
+        self.host
+            .as_ref()
+            .map(|host| format!("{}://{}", self.scheme, host))
+    }
+}
+
+/// Error returned when parsing an invalid `IpNetwork`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidNetwork(String);
+
+impl fmt::Display for InvalidNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid IP network: {}", self.0)
+    }
+}
+
+impl Error for InvalidNetwork {}
+
+/// A range of IP addresses, such as `10.0.0.0/8`, or a single address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    /// Whether the network contains an address. IPv4-mapped IPv6 addresses
+    /// are treated as IPv4 addresses.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, canonical(addr)) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<IpAddr> for IpNetwork {
+    fn from(addr: IpAddr) -> Self {
+        let addr = canonical(addr);
+        let prefix = if addr.is_ipv4() { 32 } else { 128 };
+        IpNetwork { addr, prefix }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = InvalidNetwork;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidNetwork(s.to_string());
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let network = IpNetwork::from(addr.parse::<IpAddr>().map_err(|_| invalid())?);
+        match prefix {
+            Some(prefix) => {
+                let prefix = prefix.parse::<u8>().map_err(|_| invalid())?;
+                // The prefix of a mapped address counts the IPv6 bits
+                let prefix = match (addr.parse::<IpAddr>(), network.addr) {
+                    (Ok(IpAddr::V6(_)), IpAddr::V4(_)) => prefix.checked_sub(96),
+                    _ => Some(prefix),
+                }
+                .filter(|prefix| *prefix <= network.prefix)
+                .ok_or_else(invalid)?;
+                Ok(IpNetwork { prefix, ..network })
+            }
+            None => Ok(network),
+        }
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Convert IPv4-mapped IPv6 addresses to IPv4 addresses.
+fn canonical(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => v6
+            .to_ipv4_mapped()
+            .map(IpAddr::V4)
+            .unwrap_or(IpAddr::V6(v6)),
+        addr => addr,
+    }
+}
+
+/// Parse a node from a forwarding header, such as `192.0.2.1`,
+/// `192.0.2.1:4711` or `[2001:db8::1]:4711`. Obfuscated and `unknown` nodes
+/// have no address.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split(']').next()?.parse().ok().map(canonical);
+    }
+    node.parse::<IpAddr>()
+        .or_else(|_| node.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .ok()
+        .map(canonical)
+}
+
+/// A hop recorded by a proxy: the address it received the request from, and
+/// the scheme and host the request was made with.
+#[derive(Debug, Default)]
+struct Hop {
+    node: Option<IpAddr>,
+    scheme: Option<String>,
+    host: Option<String>,
+}
+
+/// Parse the hops recorded in `Forwarded` headers.
+fn forwarded_hops(headers: &HeaderMap) -> Vec<Hop> {
+    headers
+        .get_all(hyper::header::FORWARDED)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|element| {
+            let mut hop = Hop::default();
+            for pair in element.split(';') {
+                let Some((name, value)) = pair.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim().trim_matches('"');
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "for" => hop.node = parse_node(value),
+                    "proto" => hop.scheme = Some(value.to_ascii_lowercase()),
+                    "host" => hop.host = Some(value.to_string()),
+                    _ => {}
+                }
+            }
+            hop
+        })
+        .collect()
+}
+
+/// The comma-separated values of a header.
+fn header_list(headers: &HeaderMap, name: &str) -> Vec<String> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// Parse the hops recorded in `X-Forwarded-*` headers. The scheme and host
+/// headers are matched up with `X-Forwarded-For` if they have as many
+/// values, and otherwise the last value applies to every hop.
+fn x_forwarded_hops(headers: &HeaderMap) -> Vec<Hop> {
+    let nodes = header_list(headers, X_FORWARDED_FOR);
+    let schemes = header_list(headers, X_FORWARDED_PROTO);
+    let hosts = header_list(headers, X_FORWARDED_HOST);
+    let pick = |values: &[String], index: usize| {
+        if values.len() == nodes.len() {
+            values.get(index).cloned()
+        } else {
+            values.last().cloned()
+        }
+    };
+    nodes
+        .iter()
+        .enumerate()
+        .map(|(index, node)| Hop {
+            node: parse_node(node),
+            scheme: pick(&schemes, index).map(|scheme| scheme.to_ascii_lowercase()),
+            host: pick(&hosts, index),
+        })
+        .collect()
+}
+
+/// Work out the client of a request received from `peer`.
+fn client_info<B>(req: &Request<B>, peer: IpAddr, trusted: &[IpNetwork]) -> ClientInfo {
+    let mut client = ClientInfo {
+        ip: canonical(peer),
+        scheme: req.uri().scheme_str().unwrap_or("http").to_string(),
+        host: req
+            .uri()
+            .authority()
+            .map(|authority| authority.to_string())
+            .or_else(|| {
+                req.headers()
+                    .get(HOST)
+                    .and_then(|host| host.to_str().ok())
+                    .map(str::to_string)
+            }),
+    };
+
+    let is_trusted = |addr: IpAddr| trusted.iter().any(|network| network.contains(addr));
+    if !is_trusted(client.ip) {
+        return client;
+    }
+
+    let mut hops = forwarded_hops(req.headers());
+    if hops.is_empty() {
+        hops = x_forwarded_hops(req.headers());
+    }
+
+    // Each hop was recorded by the proxy it was sent to, so is only believed
+    // while walking back through trusted proxies.
+    for hop in hops.into_iter().rev() {
+        let Some(node) = hop.node else {
+            break;
+        };
+        client.ip = node;
+        if let Some(scheme) = hop.scheme {
+            client.scheme = scheme;
+        }
+        if let Some(host) = hop.host {
+            client.host = Some(host);
+        }
+        if !is_trusted(node) {
+            break;
+        }
+    }
+    client
+}
+
+/// Middleware wrapper service which adds the effective `ClientInfo` to the
+/// context of each request.
+#[derive(Debug)]
+pub struct ClientInfoMakeService<T, C> {
+    inner: T,
+    trusted: Vec<IpNetwork>,
+    marker: PhantomData<fn(C)>,
+}
+
+impl<T, C> ClientInfoMakeService<T, C> {
+    /// Create a new ClientInfoMakeService, trusting no proxies.
+    pub fn new(inner: T) -> Self {
+        ClientInfoMakeService {
+            inner,
+            trusted: Vec::new(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Trust the forwarding headers of requests from proxies in `network`.
+    pub fn trust(mut self, network: IpNetwork) -> Self {
+        self.trusted.push(network);
+        self
+    }
+}
+
+impl<Inner, C, Target> Service<Target> for ClientInfoMakeService<Inner, C>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = ClientInfoService<Inner::Response, C>;
+    type Error = Inner::Error;
+    type Future = futures::future::BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        use futures::FutureExt;
+        let trusted: Arc<[IpNetwork]> = self.trusted.clone().into();
+        Box::pin(self.inner.call(target).map(move |s| {
+            Ok(ClientInfoService {
+                inner: s?,
+                trusted,
+                marker: PhantomData,
+            })
+        }))
+    }
+}
+
+/// Middleware wrapper service which adds the effective `ClientInfo` to the
+/// context of each request. Servers will normally want to use
+/// `ClientInfoMakeService`, which will create a `ClientInfoService` for each
+/// connection.
+#[derive(Debug)]
+pub struct ClientInfoService<T, C> {
+    inner: T,
+    trusted: Arc<[IpNetwork]>,
+    marker: PhantomData<fn(C)>,
+}
+
+impl<T, C> ClientInfoService<T, C> {
+    /// Create a new ClientInfoService, trusting no proxies.
+    pub fn new(inner: T) -> Self {
+        ClientInfoService {
+            inner,
+            trusted: Arc::new([]),
+            marker: PhantomData,
+        }
+    }
+
+    /// Trust the forwarding headers of requests from proxies in `network`.
+    pub fn trust(mut self, network: IpNetwork) -> Self {
+        let mut trusted = self.trusted.to_vec();
+        trusted.push(network);
+        self.trusted = trusted.into();
+        self
+    }
+}
+
+impl<T: Clone, C> Clone for ClientInfoService<T, C> {
+    fn clone(&self) -> Self {
+        ClientInfoService {
+            inner: self.inner.clone(),
+            trusted: self.trusted.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, C, ReqBody> Service<(Request<ReqBody>, C)> for ClientInfoService<T, C>
+where
+    C: Has<ConnectInfo<SocketAddr>> + Push<ClientInfo>,
+    T: Service<(Request<ReqBody>, C::Result)>,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = T::Future;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let ConnectInfo(peer) = *context.get();
+        let client = client_info(&req, peer.ip(), &self.trusted);
+        self.inner.call((req, context.push(client)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::new_context_type!(
+        TestContext,
+        TestEmptyContext,
+        ConnectInfo<SocketAddr>,
+        ClientInfo
+    );
+
+    #[test]
+    fn parses_networks() {
+        let network: IpNetwork = "10.0.0.0/8".parse().unwrap();
+        assert!(network.contains("10.1.2.3".parse().unwrap()));
+        assert!(network.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!network.contains("11.0.0.1".parse().unwrap()));
+        let network: IpNetwork = "2001:db8::/32".parse().unwrap();
+        assert!(network.contains("2001:db8::1".parse().unwrap()));
+        assert!(!network.contains("2001:db9::1".parse().unwrap()));
+        let network: IpNetwork = "0.0.0.0/0".parse().unwrap();
+        assert!(network.contains("192.0.2.1".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<IpNetwork>().is_err());
+        assert!("proxy".parse::<IpNetwork>().is_err());
+    }
+
+    struct TestApi;
+
+    impl<C: Has<ClientInfo>> Service<(Request<()>, C)> for TestApi {
+        type Response = ClientInfo;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (_, context): (Request<()>, C)) -> Self::Future {
+            futures::future::ok(context.get().clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn follows_trusted_proxies() {
+        let service = ClientInfoService::new(TestApi)
+            .trust("10.0.0.0/8".parse().unwrap())
+            .trust("192.0.2.10".parse().unwrap());
+        let call = |peer: &str, headers: &[(&str, &str)]| {
+            let mut request = Request::get("/pets").header(HOST, "internal:8080");
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+            let peer: SocketAddr = peer.parse().unwrap();
+            let context = TestEmptyContext.push(ConnectInfo(peer));
+            service.call((request.body(()).unwrap(), context))
+        };
+
+        let forwarded = [(
+            "forwarded",
+            "for=198.51.100.7, for=\"[2001:db8::1]:4711\";proto=https;host=api.example.com, \
+             for=192.0.2.10",
+        )];
+        let client = call("10.0.0.1:443", &forwarded).await.unwrap();
+        assert_eq!(client.ip, "2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!(client.origin().as_deref(), Some("https://api.example.com"));
+
+        // Untrusted peers can't spoof their address
+        let client = call("203.0.113.5:443", &forwarded).await.unwrap();
+        assert_eq!(client.ip, "203.0.113.5".parse::<IpAddr>().unwrap());
+        assert_eq!(client.origin().as_deref(), Some("http://internal:8080"));
+
+        let legacy = [
+            ("x-forwarded-for", "198.51.100.7, 10.0.0.2"),
+            ("x-forwarded-proto", "https"),
+            ("x-forwarded-host", "api.example.com"),
+        ];
+        let client = call("10.0.0.1:443", &legacy).await.unwrap();
+        assert_eq!(client.ip, "198.51.100.7".parse::<IpAddr>().unwrap());
+        assert_eq!(client.origin().as_deref(), Some("https://api.example.com"));
+
+        let unknown = [("forwarded", "for=unknown, for=10.0.0.2")];
+        let client = call("10.0.0.1:443", &unknown).await.unwrap();
+        assert_eq!(client.ip, "10.0.0.2".parse::<IpAddr>().unwrap());
+    }
+}
@@ -0,0 +1,256 @@
+//! Client middleware logging requests and responses.
+//!
+//! `LoggingService` logs each request's method and URI, and the response's
+//! status and latency, at `info` level using the `log` crate. Headers are
+//! logged at `debug` level, and bodies - truncated to a configurable length -
+//! can optionally be logged at `debug` level once they have been streamed.
+//!
+//! Secrets are redacted from URIs, headers and bodies using a `Redactor`.
+//!
+//! ```rust
+//! # use swagger::client_logging::LoggingService;
+//! # use swagger::redaction::Redactor;
+//! # fn wrap<T>(client: T) {
+//! let client = LoggingService::new(client)
+//!     .redactor(Redactor::new().field("ssn"))
+//!     .log_bodies(1024);
+//! # }
+//! ```
+
+use crate::redaction::Redactor;
+use futures::future::BoxFuture;
+use futures::ready;
+use hyper::body::{Body, Buf, Frame, SizeHint};
+use hyper::service::Service;
+use hyper::{Request, Response};
+use std::error::Error;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+/// Target used for log messages.
+const LOG_TARGET: &str = "swagger::client";
+
+/// Body which records the start of its data as it is streamed, and logs it
+/// once the body is finished with.
+pub struct LogBody<B> {
+    inner: Pin<Box<B>>,
+    label: String,
+    redactor: Redactor,
+    max_len: usize,
+    captured: Vec<u8>,
+    len: u64,
+}
+
+impl<B> LogBody<B> {
+    fn new(inner: B, label: String, redactor: Redactor, max_len: usize) -> Self {
+        LogBody {
+            inner: Box::pin(inner),
+            label,
+            redactor,
+            max_len,
+            captured: Vec::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<B> fmt::Debug for LogBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogBody")
+            .field("label", &self.label)
+            .field("max_len", &self.max_len)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B> Body for LogBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = &mut *self;
+        let frame = ready!(this.inner.as_mut().poll_frame(cx));
+        if let Some(Ok(frame)) = &frame {
+            if let Some(data) = frame.data_ref() {
+                this.len += data.remaining() as u64;
+                let wanted = this.max_len.saturating_sub(this.captured.len());
+                let chunk = data.chunk();
+                this.captured
+                    .extend_from_slice(&chunk[..wanted.min(chunk.len())]);
+            }
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B> Drop for LogBody<B> {
+    fn drop(&mut self) {
+        if self.max_len > 0 && self.len > 0 {
+            let truncated = if self.len > self.captured.len() as u64 {
+                format!(" (truncated from {} bytes)", self.len)
+            } else {
+                String::new()
+            };
+            log::debug!(
+                target: LOG_TARGET,
+                "{} body{}: {}",
+                self.label,
+                truncated,
+                self.redactor.body(&self.captured)
+            );
+        }
+    }
+}
+
+/// Client middleware logging requests and responses.
+///
+/// Put it innermost, just outside the `DropContextService`, to log every
+/// request sent - including retries, hedged requests and redirects - or
+/// outermost to log each call made by the generated client once.
+#[derive(Debug, Clone)]
+pub struct LoggingService<T> {
+    inner: T,
+    redactor: Redactor,
+    max_body_len: usize,
+}
+
+impl<T> LoggingService<T> {
+    /// Create a new LoggingService, using the default `Redactor` and not
+    /// logging bodies.
+    pub fn new(inner: T) -> Self {
+        LoggingService {
+            inner,
+            redactor: Redactor::new(),
+            max_body_len: 0,
+        }
+    }
+
+    /// Set the rules used to redact secrets.
+    pub fn redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// Log up to `max_len` bytes of request and response bodies.
+    pub fn log_bodies(mut self, max_len: usize) -> Self {
+        self.max_body_len = max_len;
+        self
+    }
+}
+
+impl<T, B, C, ResBody> Service<(Request<B>, C)> for LoggingService<T>
+where
+    T: Service<(Request<LogBody<B>>, C), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    T::Error: Into<Box<dyn Error + Send + Sync>>,
+    ResBody: Send + 'static,
+{
+    type Response = Response<LogBody<ResBody>>;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<B>, C)) -> Self::Future {
+        let description = format!("{} {}", req.method(), self.redactor.uri(req.uri()));
+        log::info!(target: LOG_TARGET, "Sending {}", description);
+        log::debug!(
+            target: LOG_TARGET,
+            "Request headers for {}: {}",
+            description,
+            self.redactor.headers(req.headers())
+        );
+
+        let redactor = self.redactor.clone();
+        let max_body_len = self.max_body_len;
+        let req = req.map(|body| {
+            LogBody::new(
+                body,
+                format!("Request {}", description),
+                redactor.clone(),
+                max_body_len,
+            )
+        });
+
+        let start = Instant::now();
+        let future = self.inner.call((req, context));
+        Box::pin(async move {
+            match future.await {
+                Ok(response) => {
+                    log::info!(
+                        target: LOG_TARGET,
+                        "Received {} for {} in {:?}",
+                        response.status(),
+                        description,
+                        start.elapsed()
+                    );
+                    log::debug!(
+                        target: LOG_TARGET,
+                        "Response headers for {}: {}",
+                        description,
+                        redactor.headers(response.headers())
+                    );
+                    let label = format!("Response {}", description);
+                    Ok(response.map(|body| LogBody::new(body, label, redactor, max_body_len)))
+                }
+                Err(e) => {
+                    let e = e.into();
+                    log::warn!(
+                        target: LOG_TARGET,
+                        "{} failed after {:?}: {}",
+                        description,
+                        start.elapsed(),
+                        e
+                    );
+                    Err(e)
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::Bytes;
+    use std::convert::Infallible;
+
+    #[derive(Clone)]
+    struct Echo;
+
+    impl Service<(Request<LogBody<Full<Bytes>>>, ())> for Echo {
+        type Response = Response<LogBody<Full<Bytes>>>;
+        type Error = Infallible;
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, _): (Request<LogBody<Full<Bytes>>>, ())) -> Self::Future {
+            Box::pin(async move { Ok(Response::new(req.into_body())) })
+        }
+    }
+
+    #[tokio::test]
+    async fn bodies_pass_through() {
+        let service = LoggingService::new(Echo).log_bodies(4);
+        let response = service
+            .call((Request::new(Full::new(Bytes::from("password=x"))), ()))
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "password=x");
+    }
+}
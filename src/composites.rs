@@ -2,9 +2,13 @@
 //!
 //! Use by passing `hyper::server::MakeService` instances to a `CompositeMakeService`
 //! together with the base path for requests that should be handled by that service.
+//!
+//! Requests are dispatched to the service with the longest base path matching the
+//! request path. Base paths are matched on whole path segments, so `/api` matches
+//! `/api` and `/api/pets`, but not `/apis`.
 use futures::future::{BoxFuture, FutureExt, TryFutureExt};
 use hyper::service::Service;
-use hyper::{Request, Response, StatusCode};
+use hyper::{Request, Response, StatusCode, Uri};
 use std::fmt;
 use std::future::Future;
 use std::net::SocketAddr;
@@ -34,7 +38,7 @@ pub trait HasRemoteAddr {
     fn remote_addr(&self) -> Option<SocketAddr>;
 }
 
-impl<'a> HasRemoteAddr for &'a Option<SocketAddr> {
+impl HasRemoteAddr for &Option<SocketAddr> {
     fn remote_addr(&self) -> Option<SocketAddr> {
         **self
     }
@@ -47,7 +51,7 @@ impl HasRemoteAddr for Option<SocketAddr> {
 }
 
 #[cfg(feature = "uds")]
-impl<'a> HasRemoteAddr for &'a tokio::net::UnixStream {
+impl HasRemoteAddr for &tokio::net::UnixStream {
     fn remote_addr(&self) -> Option<SocketAddr> {
         None
     }
@@ -121,13 +125,56 @@ pub type CompositeMakeServiceEntry<Target, ReqBody, ResBody, Error, MakeError> =
     Box<dyn CompositedMakeService<Target, ReqBody, ResBody, Error, MakeError> + Send>,
 );
 
+/// Returns true if `base_path` is a prefix of `path` ending on a path segment boundary.
+pub(crate) fn matches_base_path(path: &str, base_path: &str) -> bool {
+    match path.strip_prefix(base_path) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/') || base_path.ends_with('/'),
+        None => false,
+    }
+}
+
+/// Remove `base_path` from the start of the request path, preserving the query string.
+///
+/// The remaining path always starts with a `/`. Requests whose URI can't be rewritten
+/// this way, such as `CONNECT` requests for an authority rather than a path, are
+/// returned unchanged.
+pub(crate) fn strip_base_path<B>(mut req: Request<B>, base_path: &str) -> Request<B> {
+    if let Ok(uri) = without_base_path(req.uri(), base_path) {
+        *req.uri_mut() = uri;
+    }
+    req
+}
+
+/// The URI with `base_path` removed from the start of its path.
+fn without_base_path(uri: &Uri, base_path: &str) -> Result<Uri, hyper::http::Error> {
+    let path = uri.path().get(base_path.len()..).unwrap_or_default();
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    };
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path,
+    };
+
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse()?);
+    Ok(Uri::from_parts(parts)?)
+}
+
 /// Wraps a vector of pairs, each consisting of a base path as a `&'static str`
 /// and a `MakeService` instance. Implements `Deref<Vec>` and `DerefMut<Vec>` so
 /// these can be manipulated using standard `Vec` methods.
 ///
 /// The `Service` returned by calling `make_service()` will pass an incoming
-/// request to the first `Service` in the list for which the associated
-/// base path is a prefix of the request path.
+/// request to the `Service` with the longest base path matching the request
+/// path. If no base path matches, the request is passed to the fallback
+/// `Service` if one has been configured, or a "not found" response is returned.
+///
+/// If prefix stripping is enabled, the matched base path is removed from the
+/// request URI before it is passed on, so each service sees paths relative to
+/// the point at which it is mounted.
 ///
 /// Example Usage
 /// =============
@@ -136,18 +183,24 @@ pub type CompositeMakeServiceEntry<Target, ReqBody, ResBody, Error, MakeError> =
 /// let my_make_service1 = MakeService1::new();
 /// let my_make_service2 = MakeService2::new();
 ///
-/// let mut composite_make_service = CompositeMakeService::new();
-/// composite_make_service.push(("/base/path/1", my_make_service1));
-/// composite_make_service.push(("/base/path/2", my_make_service2));
+/// let mut composite_make_service = CompositeMakeService::new().strip_prefix(true);
+/// composite_make_service.push(("/base/path/1", Box::new(my_make_service1)));
+/// composite_make_service.push(("/base/path/2", Box::new(my_make_service2)));
+/// composite_make_service.set_fallback(AddContextMakeService::<_, EmptyContext>::new(
+///     MakeFallback::new(DefaultFallback::new()),
+/// ));
 ///
 /// // use as you would any `MakeService` instance
 /// ```
-#[derive(Default)]
-pub struct CompositeMakeService<Target, ReqBody, ResBody, Error, MakeError>(
-    CompositeMakeServiceVec<Target, ReqBody, ResBody, Error, MakeError>,
-)
+pub struct CompositeMakeService<Target, ReqBody, ResBody, Error, MakeError>
 where
-    ResBody: NotFound<ResBody>;
+    ResBody: NotFound<ResBody>,
+{
+    services: CompositeMakeServiceVec<Target, ReqBody, ResBody, Error, MakeError>,
+    fallback:
+        Option<Box<dyn CompositedMakeService<Target, ReqBody, ResBody, Error, MakeError> + Send>>,
+    strip_prefix: bool,
+}
 
 impl<Target, ReqBody, ResBody, Error, MakeError>
     CompositeMakeService<Target, ReqBody, ResBody, Error, MakeError>
@@ -156,13 +209,43 @@ where
 {
     /// create an empty `CompositeMakeService`
     pub fn new() -> Self {
-        CompositeMakeService(Vec::new())
+        CompositeMakeService {
+            services: Vec::new(),
+            fallback: None,
+            strip_prefix: false,
+        }
+    }
+
+    /// Configure whether the matched base path is stripped from the request URI
+    /// before the request is passed to the composited service.
+    pub fn strip_prefix(mut self, strip_prefix: bool) -> Self {
+        self.strip_prefix = strip_prefix;
+        self
+    }
+
+    /// Set the `MakeService` used to handle requests which don't match any base path.
+    pub fn set_fallback<M>(&mut self, fallback: M)
+    where
+        M: CompositedMakeService<Target, ReqBody, ResBody, Error, MakeError> + Send + 'static,
+    {
+        self.fallback = Some(Box::new(fallback));
     }
 }
 
-impl<ReqBody, ResBody, Error, MakeError> Service<Option<SocketAddr>>
-    for CompositeMakeService<Option<SocketAddr>, ReqBody, ResBody, Error, MakeError>
+impl<Target, ReqBody, ResBody, Error, MakeError> Default
+    for CompositeMakeService<Target, ReqBody, ResBody, Error, MakeError>
 where
+    ResBody: NotFound<ResBody>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Target, ReqBody, ResBody, Error, MakeError> Service<Target>
+    for CompositeMakeService<Target, ReqBody, ResBody, Error, MakeError>
+where
+    Target: Clone,
     ReqBody: 'static,
     ResBody: NotFound<ResBody> + 'static,
     MakeError: Send + 'static,
@@ -172,17 +255,28 @@ where
     type Response = CompositeService<ReqBody, ResBody, Error>;
     type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
 
-    fn call(&self, target: Option<SocketAddr>) -> Self::Future {
-        let mut services = Vec::with_capacity(self.0.len());
-        for (path, service) in &self.0 {
+    fn call(&self, target: Target) -> Self::Future {
+        let mut services = Vec::with_capacity(self.services.len());
+        for (path, service) in &self.services {
             let path: &'static str = path;
-            services.push(service.call(target).map_ok(move |s| (path, s)));
+            services.push(service.call(target.clone()).map_ok(move |s| (path, s)));
         }
-        Box::pin(futures::future::join_all(services).map(|results| {
-            let services: Result<Vec<_>, MakeError> = results.into_iter().collect();
+        let fallback = self.fallback.as_ref().map(|fallback| fallback.call(target));
+        let strip_prefix = self.strip_prefix;
 
-            Ok(CompositeService(services?))
-        }))
+        Box::pin(
+            futures::future::join(
+                futures::future::try_join_all(services),
+                futures::future::OptionFuture::from(fallback),
+            )
+            .map(move |(services, fallback)| {
+                Ok(CompositeService {
+                    services: services?,
+                    fallback: fallback.transpose()?,
+                    strip_prefix,
+                })
+            }),
+        )
     }
 }
 
@@ -193,7 +287,11 @@ where
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         // Get vector of base paths
-        let str_vec: Vec<&'static str> = self.0.iter().map(|&(base_path, _)| base_path).collect();
+        let str_vec: Vec<&'static str> = self
+            .services
+            .iter()
+            .map(|&(base_path, _)| base_path)
+            .collect();
         write!(
             f,
             "CompositeMakeService accepting base paths: {:?}",
@@ -210,7 +308,7 @@ where
     type Target = CompositeMakeServiceVec<Target, ReqBody, ResBody, Error, MakeError>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.services
     }
 }
 
@@ -220,15 +318,20 @@ where
     ResBody: NotFound<ResBody>,
 {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.services
     }
 }
 
 /// Wraps a vector of pairs, each consisting of a base path as a `&'static str`
 /// and a `Service` instance.
-pub struct CompositeService<ReqBody, ResBody, Error>(CompositeServiceVec<ReqBody, ResBody, Error>)
+pub struct CompositeService<ReqBody, ResBody, Error>
 where
-    ResBody: NotFound<ResBody>;
+    ResBody: NotFound<ResBody>,
+{
+    services: CompositeServiceVec<ReqBody, ResBody, Error>,
+    fallback: Option<Box<dyn CompositedService<ReqBody, ResBody, Error> + Send>>,
+    strip_prefix: bool,
+}
 
 impl<ReqBody, ResBody, Error> Service<Request<ReqBody>>
     for CompositeService<ReqBody, ResBody, Error>
@@ -241,13 +344,24 @@ where
     type Future = BoxFuture<'static, Result<Response<ResBody>, Error>>;
 
     fn call(&self, req: Request<ReqBody>) -> Self::Future {
-        for &(base_path, ref service) in &self.0 {
-            if req.uri().path().starts_with(base_path) {
-                return service.call(req);
+        let matched = self
+            .services
+            .iter()
+            .filter(|(base_path, _)| matches_base_path(req.uri().path(), base_path))
+            .max_by_key(|(base_path, _)| base_path.len());
+
+        match (matched, &self.fallback) {
+            (Some((base_path, service)), _) => {
+                let req = if self.strip_prefix {
+                    strip_base_path(req, base_path)
+                } else {
+                    req
+                };
+                service.call(req)
             }
+            (None, Some(fallback)) => fallback.call(req),
+            (None, None) => Box::pin(futures::future::ok(ResBody::not_found())),
         }
-
-        Box::pin(futures::future::ok(ResBody::not_found()))
     }
 }
 
@@ -257,7 +371,11 @@ where
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         // Get vector of base paths
-        let str_vec: Vec<&'static str> = self.0.iter().map(|&(base_path, _)| base_path).collect();
+        let str_vec: Vec<&'static str> = self
+            .services
+            .iter()
+            .map(|&(base_path, _)| base_path)
+            .collect();
         write!(f, "CompositeService accepting base paths: {:?}", str_vec,)
     }
 }
@@ -269,7 +387,7 @@ where
 {
     type Target = CompositeServiceVec<ReqBody, ResBody, Error>;
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.services
     }
 }
 
@@ -279,6 +397,104 @@ where
     Error: 'static,
 {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.services
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::Bytes;
+
+    type TestComposite =
+        CompositeMakeService<Option<SocketAddr>, Full<Bytes>, Full<Bytes>, String, String>;
+
+    /// Make service whose services respond with their name and the path they saw.
+    struct MakeEchoService(&'static str);
+
+    impl<Target> Service<Target> for MakeEchoService {
+        type Response = EchoService;
+        type Error = String;
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _target: Target) -> Self::Future {
+            futures::future::ok(EchoService(self.0))
+        }
+    }
+
+    struct EchoService(&'static str);
+
+    impl Service<Request<Full<Bytes>>> for EchoService {
+        type Response = Response<Full<Bytes>>;
+        type Error = String;
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, req: Request<Full<Bytes>>) -> Self::Future {
+            let body = format!("{} {}", self.0, req.uri());
+            futures::future::ok(Response::new(Full::from(body)))
+        }
+    }
+
+    async fn send(
+        service: &CompositeService<Full<Bytes>, Full<Bytes>, String>,
+        uri: &str,
+    ) -> (StatusCode, String) {
+        let response = Service::call(service, Request::get(uri).body(Full::default()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn longest_prefix_wins() {
+        let mut make_service = TestComposite::new();
+        make_service.push(("/api", Box::new(MakeEchoService("api"))));
+        make_service.push(("/api/v2", Box::new(MakeEchoService("v2"))));
+        let service = Service::call(&make_service, None).await.unwrap();
+
+        assert_eq!(send(&service, "/api/v2/pets").await.1, "v2 /api/v2/pets");
+        assert_eq!(send(&service, "/api/v1/pets").await.1, "api /api/v1/pets");
+        assert_eq!(send(&service, "/api/v2").await.1, "v2 /api/v2");
+        assert_eq!(send(&service, "/api/v22").await.1, "api /api/v22");
+        assert_eq!(send(&service, "/apis").await.0, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn strips_prefix() {
+        let mut make_service = TestComposite::new().strip_prefix(true);
+        make_service.push(("/api/v1", Box::new(MakeEchoService("v1"))));
+        make_service.push(("/health/", Box::new(MakeEchoService("health"))));
+        let service = Service::call(&make_service, None).await.unwrap();
+
+        assert_eq!(
+            send(&service, "/api/v1/pets?limit=1").await.1,
+            "v1 /pets?limit=1"
+        );
+        assert_eq!(send(&service, "/api/v1").await.1, "v1 /");
+        assert_eq!(send(&service, "/health/live").await.1, "health /live");
+
+        // An authority-form target has no path to strip, so is passed on as is
+        let mut make_service = TestComposite::new().strip_prefix(true);
+        make_service.push(("", Box::new(MakeEchoService("tunnel"))));
+        let service = Service::call(&make_service, None).await.unwrap();
+        let request = Request::connect("example.com:443")
+            .body(Full::default())
+            .unwrap();
+        let response = Service::call(&service, request).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "tunnel example.com:443");
+    }
+
+    #[tokio::test]
+    async fn uses_fallback() {
+        let mut make_service = TestComposite::new();
+        make_service.push(("/api", Box::new(MakeEchoService("api"))));
+        make_service.set_fallback(MakeEchoService("fallback"));
+        let service = Service::call(&make_service, None).await.unwrap();
+
+        assert_eq!(send(&service, "/other").await.1, "fallback /other");
     }
 }
@@ -0,0 +1,394 @@
+//! HTTP content codings, for compressing and decompressing message bodies.
+//!
+//! Each coding is enabled by a crate feature: **gzip** (`gzip` and
+//! `deflate`), **brotli** (`br`) and **zstd** (`zstd`).
+
+use futures::ready;
+use hyper::body::{Body, Buf, Bytes, Frame, SizeHint};
+use hyper::header::{HeaderMap, HeaderValue};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A content coding, as used in the `Content-Encoding` and `Accept-Encoding`
+/// headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Coding {
+    /// The gzip format - RFC 1952
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// The zlib format - RFC 1950
+    #[cfg(feature = "gzip")]
+    Deflate,
+    /// The Brotli format - RFC 7932
+    #[cfg(feature = "brotli")]
+    Brotli,
+    /// The Zstandard format - RFC 8878
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Coding {
+    /// All codings supported by this build, in order of preference.
+    pub const SUPPORTED: &'static [Coding] = &[
+        #[cfg(feature = "zstd")]
+        Coding::Zstd,
+        #[cfg(feature = "brotli")]
+        Coding::Brotli,
+        #[cfg(feature = "gzip")]
+        Coding::Gzip,
+        #[cfg(feature = "gzip")]
+        Coding::Deflate,
+    ];
+
+    /// The name of the coding, as used in HTTP headers.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "gzip")]
+            Coding::Gzip => "gzip",
+            #[cfg(feature = "gzip")]
+            Coding::Deflate => "deflate",
+            #[cfg(feature = "brotli")]
+            Coding::Brotli => "br",
+            #[cfg(feature = "zstd")]
+            Coding::Zstd => "zstd",
+        }
+    }
+
+    /// Look up a coding by name. Returns `None` for `identity`, and for
+    /// codings which aren't supported by this build.
+    pub fn from_name(name: &str) -> Option<Coding> {
+        let name = name.trim();
+        // x-gzip is a legacy alias for gzip - RFC 9110 section 8.4.1.3
+        let name = if name.eq_ignore_ascii_case("x-gzip") {
+            "gzip"
+        } else {
+            name
+        };
+        Coding::SUPPORTED
+            .iter()
+            .copied()
+            .find(|coding| coding.as_str().eq_ignore_ascii_case(name))
+    }
+
+    /// An `Accept-Encoding` header value listing the given codings.
+    pub fn accept_encoding(codings: &[Coding]) -> HeaderValue {
+        let value = codings
+            .iter()
+            .map(Coding::as_str)
+            .collect::<Vec<_>>()
+            .join(", ");
+        HeaderValue::from_str(&value).expect("Coding names are valid header values")
+    }
+
+    fn decoder(self) -> Box<dyn Coder> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Coding::Gzip => Box::new(flate2::write::MultiGzDecoder::new(Vec::new())),
+            #[cfg(feature = "gzip")]
+            Coding::Deflate => Box::new(flate2::write::ZlibDecoder::new(Vec::new())),
+            #[cfg(feature = "brotli")]
+            Coding::Brotli => Box::new(brotli::DecompressorWriter::new(Vec::new(), 4096)),
+            #[cfg(feature = "zstd")]
+            Coding::Zstd => Box::new(
+                zstd::stream::write::Decoder::new(Vec::new())
+                    .expect("Zstandard decoder can be created"),
+            ),
+        }
+    }
+
+    fn encoder(self) -> Box<dyn Coder> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Coding::Gzip => Box::new(flate2::write::GzEncoder::new(
+                Vec::new(),
+                flate2::Compression::default(),
+            )),
+            #[cfg(feature = "gzip")]
+            Coding::Deflate => Box::new(flate2::write::ZlibEncoder::new(
+                Vec::new(),
+                flate2::Compression::default(),
+            )),
+            #[cfg(feature = "brotli")]
+            Coding::Brotli => Box::new(brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22)),
+            #[cfg(feature = "zstd")]
+            Coding::Zstd => Box::new(
+                zstd::stream::write::Encoder::new(Vec::new(), 0)
+                    .expect("Zstandard encoder can be created"),
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Coding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Compressor or decompressor writing its output to a buffer, which can be
+/// drained as input is written.
+trait Coder: Write + Send {
+    fn output(&mut self) -> &mut Vec<u8>;
+
+    fn finish(self: Box<Self>) -> io::Result<Vec<u8>>;
+}
+
+#[cfg(feature = "gzip")]
+impl Coder for flate2::write::MultiGzDecoder<Vec<u8>> {
+    fn output(&mut self) -> &mut Vec<u8> {
+        self.get_mut()
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<Vec<u8>> {
+        (*self).finish()
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl Coder for flate2::write::ZlibDecoder<Vec<u8>> {
+    fn output(&mut self) -> &mut Vec<u8> {
+        self.get_mut()
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<Vec<u8>> {
+        (*self).finish()
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl Coder for flate2::write::GzEncoder<Vec<u8>> {
+    fn output(&mut self) -> &mut Vec<u8> {
+        self.get_mut()
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<Vec<u8>> {
+        (*self).finish()
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl Coder for flate2::write::ZlibEncoder<Vec<u8>> {
+    fn output(&mut self) -> &mut Vec<u8> {
+        self.get_mut()
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<Vec<u8>> {
+        (*self).finish()
+    }
+}
+
+#[cfg(feature = "brotli")]
+impl Coder for brotli::DecompressorWriter<Vec<u8>> {
+    fn output(&mut self) -> &mut Vec<u8> {
+        self.get_mut()
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<Vec<u8>> {
+        self.into_inner()
+            .map_err(|_| io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated Brotli stream"))
+    }
+}
+
+#[cfg(feature = "brotli")]
+impl Coder for brotli::CompressorWriter<Vec<u8>> {
+    fn output(&mut self) -> &mut Vec<u8> {
+        self.get_mut()
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<Vec<u8>> {
+        Ok(self.into_inner())
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Coder for zstd::stream::write::Decoder<'static, Vec<u8>> {
+    fn output(&mut self) -> &mut Vec<u8> {
+        self.get_mut()
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<Vec<u8>> {
+        self.flush()?;
+        Ok(self.into_inner())
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Coder for zstd::stream::write::Encoder<'static, Vec<u8>> {
+    fn output(&mut self) -> &mut Vec<u8> {
+        self.get_mut()
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<Vec<u8>> {
+        (*self).finish()
+    }
+}
+
+/// Body which compresses or decompresses another body as it is streamed.
+///
+/// Trailers are passed through unchanged.
+pub struct CodingBody<B> {
+    inner: Pin<Box<B>>,
+    coder: Option<Box<dyn Coder>>,
+    passthrough: bool,
+    trailers: Option<HeaderMap>,
+}
+
+impl<B> CodingBody<B> {
+    /// Pass the body through unchanged.
+    pub fn identity(body: B) -> Self {
+        CodingBody {
+            inner: Box::pin(body),
+            coder: None,
+            passthrough: true,
+            trailers: None,
+        }
+    }
+
+    /// Decompress a body encoded with the given coding.
+    pub fn decode(body: B, coding: Coding) -> Self {
+        CodingBody {
+            inner: Box::pin(body),
+            coder: Some(coding.decoder()),
+            passthrough: false,
+            trailers: None,
+        }
+    }
+
+    /// Compress a body with the given coding.
+    pub fn encode(body: B, coding: Coding) -> Self {
+        CodingBody {
+            inner: Box::pin(body),
+            coder: Some(coding.encoder()),
+            passthrough: false,
+            trailers: None,
+        }
+    }
+}
+
+impl<B> CodingBody<B> {
+    /// Finish compressing or decompressing, returning any remaining output.
+    fn finish(&mut self) -> io::Result<Option<Bytes>> {
+        match self.coder.take() {
+            Some(coder) => {
+                let output = coder.finish()?;
+                Ok((!output.is_empty()).then(|| output.into()))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl<B> fmt::Debug for CodingBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CodingBody")
+            .field("passthrough", &self.passthrough)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B> Body for CodingBody<B>
+where
+    B: Body,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    type Data = Bytes;
+    type Error = Box<dyn Error + Send + Sync>;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        let this = &mut *self;
+        if let Some(trailers) = this.trailers.take() {
+            return Poll::Ready(Some(Ok(Frame::trailers(trailers))));
+        }
+
+        loop {
+            match ready!(this.inner.as_mut().poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(mut data) => {
+                        let data = data.copy_to_bytes(data.remaining());
+                        match &mut this.coder {
+                            None if this.passthrough => {
+                                return Poll::Ready(Some(Ok(Frame::data(data))));
+                            }
+                            // Data after the trailers is ignored
+                            None => continue,
+                            Some(coder) => {
+                                coder.write_all(&data)?;
+                                let output = std::mem::take(coder.output());
+                                if !output.is_empty() {
+                                    return Poll::Ready(Some(Ok(Frame::data(output.into()))));
+                                }
+                            }
+                        }
+                    }
+                    Err(frame) => {
+                        let Ok(trailers) = frame.into_trailers() else {
+                            continue;
+                        };
+                        // Any remaining output must be sent before the trailers
+                        if let Some(output) = this.finish()? {
+                            this.trailers = Some(trailers);
+                            return Poll::Ready(Some(Ok(Frame::data(output))));
+                        }
+                        return Poll::Ready(Some(Ok(Frame::trailers(trailers))));
+                    }
+                },
+                Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                None => return Poll::Ready(this.finish()?.map(|output| Ok(Frame::data(output)))),
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.coder.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        if self.passthrough {
+            self.inner.size_hint()
+        } else {
+            SizeHint::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+
+    #[tokio::test]
+    async fn round_trip() {
+        let original = "swagger ".repeat(1000);
+
+        for coding in Coding::SUPPORTED {
+            let encoded = CodingBody::encode(Full::new(Bytes::from(original.clone())), *coding)
+                .collect()
+                .await
+                .unwrap()
+                .to_bytes();
+            assert!(encoded.len() < original.len(), "{} didn't compress", coding);
+
+            let decoded = CodingBody::decode(Full::new(encoded), *coding)
+                .collect()
+                .await
+                .unwrap()
+                .to_bytes();
+            assert_eq!(decoded, original.as_bytes(), "{} didn't round-trip", coding);
+        }
+    }
+
+    #[test]
+    fn names() {
+        for coding in Coding::SUPPORTED {
+            assert_eq!(Coding::from_name(coding.as_str()), Some(*coding));
+        }
+        assert_eq!(Coding::from_name("identity"), None);
+    }
+}
@@ -0,0 +1,374 @@
+//! Hyper service limiting the number of requests being handled concurrently.
+//!
+//! When a limit is reached, further requests are rejected with
+//! `503 Service Unavailable` and a `Retry-After` header, rather than being
+//! queued. Limits can be set globally, and per-operation using the API's
+//! `RequestParser`.
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! # use swagger::concurrency_limit::ConcurrencyLimits;
+//! let limits = ConcurrencyLimits::new()
+//!     .max_in_flight(100)
+//!     .route_max_in_flight("uploadFile", 5)
+//!     .retry_after(Duration::from_secs(2));
+//!
+//! // `limits` can be cloned and kept to report the number of requests in flight.
+//! assert_eq!(limits.in_flight(), 0);
+//! ```
+
+use crate::response::json_error;
+use crate::{Has, RequestParser, XSpanIdString};
+use futures::future::{BoxFuture, FutureExt};
+use hyper::header::{HeaderValue, RETRY_AFTER};
+use hyper::service::Service;
+use hyper::{Request, Response, StatusCode};
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Counter of requests in flight, with an optional maximum.
+#[derive(Debug, Clone)]
+struct Limit {
+    max: Option<usize>,
+    current: Arc<AtomicUsize>,
+}
+
+impl Limit {
+    fn new(max: Option<usize>) -> Self {
+        Limit {
+            max,
+            current: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Try to take a slot, returning a guard which releases it when dropped.
+    fn acquire(&self) -> Option<LimitGuard> {
+        let max = self.max.unwrap_or(usize::MAX);
+        self.current
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+                (current < max).then_some(current + 1)
+            })
+            .ok()
+            .map(|_| LimitGuard(self.current.clone()))
+    }
+
+    fn in_flight(&self) -> usize {
+        self.current.load(Ordering::Acquire)
+    }
+}
+
+/// Releases a slot taken from a `Limit` when dropped.
+#[derive(Debug)]
+struct LimitGuard(Arc<AtomicUsize>);
+
+impl Drop for LimitGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Concurrency limits, shared by all connections. Clones share the same
+/// counters, so can be used to report the number of requests in flight, e.g.
+/// to a metrics system.
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimits {
+    global: Limit,
+    routes: HashMap<&'static str, Limit>,
+    retry_after: Duration,
+}
+
+impl Default for ConcurrencyLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConcurrencyLimits {
+    /// Create a new set of limits, with no limits configured, and a
+    /// `Retry-After` of 1 second.
+    pub fn new() -> Self {
+        ConcurrencyLimits {
+            global: Limit::new(None),
+            routes: HashMap::new(),
+            retry_after: Duration::from_secs(1),
+        }
+    }
+
+    /// Set the maximum number of requests in flight across all operations.
+    pub fn max_in_flight(mut self, max: usize) -> Self {
+        self.global = Limit::new(Some(max));
+        self
+    }
+
+    /// Set the maximum number of requests in flight for a given operation ID,
+    /// as returned by the API's `RequestParser`.
+    pub fn route_max_in_flight(mut self, operation_id: &'static str, max: usize) -> Self {
+        self.routes.insert(operation_id, Limit::new(Some(max)));
+        self
+    }
+
+    /// Set the delay suggested to clients in the `Retry-After` header when a
+    /// request is rejected. This is rounded up to a whole number of seconds.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Number of requests currently in flight across all operations.
+    pub fn in_flight(&self) -> usize {
+        self.global.in_flight()
+    }
+
+    /// Number of requests currently in flight for an operation ID, or `None`
+    /// if the operation doesn't have a limit configured.
+    pub fn route_in_flight(&self, operation_id: &str) -> Option<usize> {
+        self.routes.get(operation_id).map(Limit::in_flight)
+    }
+
+    fn acquire(&self, operation_id: Option<&str>) -> Option<(LimitGuard, Option<LimitGuard>)> {
+        let global = self.global.acquire()?;
+        let route = match operation_id.and_then(|id| self.routes.get(id)) {
+            Some(limit) => Some(limit.acquire()?),
+            None => None,
+        };
+        Some((global, route))
+    }
+
+    fn retry_after_secs(&self) -> u64 {
+        let secs = self.retry_after.as_secs();
+        if self.retry_after.subsec_nanos() > 0 {
+            secs + 1
+        } else {
+            secs
+        }
+    }
+}
+
+/// Middleware wrapper service which rejects requests with `503 Service
+/// Unavailable` when too many requests are in flight.
+pub struct ConcurrencyLimitMakeService<T, RP> {
+    inner: T,
+    limits: ConcurrencyLimits,
+    marker: PhantomData<fn(RP)>,
+}
+
+impl<T, RP> ConcurrencyLimitMakeService<T, RP> {
+    /// Create a new ConcurrencyLimitMakeService, enforcing `limits` across all
+    /// connections.
+    pub fn new(inner: T, limits: ConcurrencyLimits) -> Self {
+        ConcurrencyLimitMakeService {
+            inner,
+            limits,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, RP> fmt::Debug for ConcurrencyLimitMakeService<T, RP>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConcurrencyLimitMakeService")
+            .field("inner", &self.inner)
+            .field("limits", &self.limits)
+            .finish()
+    }
+}
+
+impl<Inner, RP, Target> Service<Target> for ConcurrencyLimitMakeService<Inner, RP>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = ConcurrencyLimitService<Inner::Response, RP>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let limits = self.limits.clone();
+        Box::pin(
+            self.inner
+                .call(target)
+                .map(|s| Ok(ConcurrencyLimitService::new(s?, limits))),
+        )
+    }
+}
+
+/// Middleware wrapper service which rejects requests with `503 Service
+/// Unavailable` when too many requests are in flight. Servers will normally
+/// want to use `ConcurrencyLimitMakeService`, which will create a
+/// `ConcurrencyLimitService` for each connection.
+///
+/// A request counts as in flight until the wrapped service has returned its
+/// response, not including the time taken to stream the response body.
+pub struct ConcurrencyLimitService<T, RP> {
+    inner: T,
+    limits: ConcurrencyLimits,
+    marker: PhantomData<fn(RP)>,
+}
+
+impl<T, RP> ConcurrencyLimitService<T, RP> {
+    /// Create a new ConcurrencyLimitService
+    pub fn new(inner: T, limits: ConcurrencyLimits) -> Self {
+        ConcurrencyLimitService {
+            inner,
+            limits,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, RP> Clone for ConcurrencyLimitService<T, RP>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        Self::new(self.inner.clone(), self.limits.clone())
+    }
+}
+
+impl<T, RP> fmt::Debug for ConcurrencyLimitService<T, RP>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConcurrencyLimitService")
+            .field("inner", &self.inner)
+            .field("limits", &self.limits)
+            .finish()
+    }
+}
+
+impl<T, RP, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for ConcurrencyLimitService<T, RP>
+where
+    RP: RequestParser<ReqBody>,
+    C: Has<XSpanIdString>,
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    T::Error: Send + 'static,
+    ResBody: From<String> + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let operation_id = if self.limits.routes.is_empty() {
+            None
+        } else {
+            RP::parse_operation_id(&req)
+        };
+
+        match self.limits.acquire(operation_id) {
+            Some(guard) => {
+                let future = self.inner.call((req, context));
+                Box::pin(async move {
+                    let response = future.await;
+                    drop(guard);
+                    response
+                })
+            }
+            None => {
+                let x_span_id = Has::<XSpanIdString>::get(&context);
+                let mut response = json_error(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "Too many requests in flight",
+                    Some(&x_span_id.0),
+                );
+                response.headers_mut().insert(
+                    RETRY_AFTER,
+                    HeaderValue::from(self.limits.retry_after_secs()),
+                );
+                Box::pin(futures::future::ok(response))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContextBuilder, EmptyContext, Push};
+    use std::sync::Mutex;
+    use tokio::sync::oneshot;
+
+    struct TestParser;
+
+    impl<B> RequestParser<B> for TestParser {
+        fn parse_operation_id(req: &Request<B>) -> Option<&'static str> {
+            match req.uri().path() {
+                "/upload" => Some("upload"),
+                _ => Some("other"),
+            }
+        }
+    }
+
+    type TestContext = ContextBuilder<XSpanIdString, EmptyContext>;
+
+    /// Service which doesn't respond until told to.
+    #[derive(Clone, Default)]
+    struct BlockingService(Arc<Mutex<Vec<oneshot::Sender<()>>>>);
+
+    impl BlockingService {
+        fn release(&self) {
+            self.0.lock().unwrap().clear();
+        }
+    }
+
+    impl Service<(Request<()>, TestContext)> for BlockingService {
+        type Response = Response<String>;
+        type Error = ();
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _req: (Request<()>, TestContext)) -> Self::Future {
+            let (tx, rx) = oneshot::channel();
+            self.0.lock().unwrap().push(tx);
+            Box::pin(async move {
+                let _ = rx.await;
+                Ok(Response::new("done".to_string()))
+            })
+        }
+    }
+
+    fn request(path: &str) -> (Request<()>, TestContext) {
+        (
+            Request::get(path).body(()).unwrap(),
+            EmptyContext.push(XSpanIdString("span".to_string())),
+        )
+    }
+
+    #[tokio::test]
+    async fn sheds_load() {
+        let inner = BlockingService::default();
+        let limits = ConcurrencyLimits::new()
+            .max_in_flight(2)
+            .route_max_in_flight("upload", 1)
+            .retry_after(Duration::from_millis(1500));
+        let service: ConcurrencyLimitService<_, TestParser> =
+            ConcurrencyLimitService::new(inner.clone(), limits.clone());
+
+        let first = service.call(request("/upload"));
+        assert_eq!(limits.in_flight(), 1);
+        assert_eq!(limits.route_in_flight("upload"), Some(1));
+
+        let rejected = service.call(request("/upload")).await.unwrap();
+        assert_eq!(rejected.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(rejected.headers()[RETRY_AFTER], "2");
+        assert_eq!(limits.in_flight(), 1);
+
+        let second = service.call(request("/pets"));
+        let rejected = service.call(request("/pets")).await.unwrap();
+        assert_eq!(rejected.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        inner.release();
+        assert_eq!(first.await.unwrap().status(), StatusCode::OK);
+        assert_eq!(second.await.unwrap().status(), StatusCode::OK);
+        assert_eq!(limits.in_flight(), 0);
+        assert_eq!(limits.route_in_flight("upload"), Some(0));
+    }
+}
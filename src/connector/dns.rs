@@ -0,0 +1,244 @@
+//! DNS resolvers for connectors.
+
+use futures::future::{self, BoxFuture, FutureExt};
+use hyper_util::client::legacy::connect::dns::{GaiResolver, Name};
+use std::collections::HashMap;
+use std::error::Error;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower_service::Service;
+
+/// DNS resolver which resolves some hostnames to fixed addresses, and
+/// delegates all other lookups to another resolver - by default, the system's
+/// `getaddrinfo`.
+///
+/// Only name resolution is affected - the URI's hostname is still used for
+/// TLS SNI and certificate verification, so this is suitable for talking to a
+/// particular instance of a service (e.g. during a blue/green deployment)
+/// without disabling TLS checks.
+///
+/// ```rust
+/// # use swagger::Connector;
+/// # use swagger::connector::StaticResolver;
+/// let resolver = StaticResolver::new()
+///     .override_host("api.example.com", ["10.0.0.5".parse().unwrap()]);
+/// let connector = Connector::builder().resolver(resolver).build();
+/// ```
+///
+/// Custom resolvers can be used instead by implementing
+/// `tower_service::Service<Name>`, and passing them to `Builder::resolver`.
+#[derive(Clone, Debug)]
+pub struct StaticResolver<R = GaiResolver> {
+    overrides: Arc<HashMap<String, Vec<IpAddr>>>,
+    fallback: R,
+}
+
+impl StaticResolver {
+    /// Create a resolver which uses `getaddrinfo` for hosts without an
+    /// override.
+    pub fn new() -> Self {
+        Self::with_fallback(GaiResolver::new())
+    }
+}
+
+impl Default for StaticResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R> StaticResolver<R> {
+    /// Create a resolver which uses `fallback` for hosts without an override.
+    pub fn with_fallback(fallback: R) -> Self {
+        StaticResolver {
+            overrides: Arc::new(HashMap::new()),
+            fallback,
+        }
+    }
+
+    /// Resolve `host` to the given addresses, rather than looking it up.
+    ///
+    /// Connections use the port from the request URI.
+    pub fn override_host<I>(mut self, host: &str, addrs: I) -> Self
+    where
+        I: IntoIterator<Item = IpAddr>,
+    {
+        Arc::make_mut(&mut self.overrides)
+            .insert(host.to_ascii_lowercase(), addrs.into_iter().collect());
+        self
+    }
+}
+
+impl<R> Service<Name> for StaticResolver<R>
+where
+    R: Service<Name> + Clone + Send + 'static,
+    R::Response: Iterator<Item = SocketAddr>,
+    R::Error: Into<Box<dyn Error + Send + Sync>>,
+    R::Future: Send,
+{
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        if let Some(addrs) = self.overrides.get(&name.as_str().to_ascii_lowercase()) {
+            // Port 0 is replaced with the port from the URI by the connector
+            let addrs: Vec<_> = addrs.iter().map(|ip| SocketAddr::new(*ip, 0)).collect();
+            return future::ok(addrs.into_iter()).boxed();
+        }
+
+        let mut fallback = self.fallback.clone();
+        async move {
+            future::poll_fn(|cx| fallback.poll_ready(cx))
+                .await
+                .map_err(Into::into)?;
+            let addrs = fallback.call(name).await.map_err(Into::into)?;
+            Ok(addrs.collect::<Vec<_>>().into_iter())
+        }
+        .boxed()
+    }
+}
+
+/// DNS resolver which interleaves IPv6 and IPv4 addresses, starting with IPv6,
+/// as described in RFC 8305 section 4, so that connectors using Happy Eyeballs
+/// try IPv6 first and don't wait on every address of a broken family before
+/// trying the other. Used by `Builder::happy_eyeballs`.
+#[derive(Clone, Debug, Default)]
+pub struct HappyEyeballsResolver<R = GaiResolver> {
+    inner: R,
+}
+
+impl<R> HappyEyeballsResolver<R> {
+    /// Create a resolver reordering the addresses returned by `inner`.
+    pub fn new(inner: R) -> Self {
+        HappyEyeballsResolver { inner }
+    }
+}
+
+impl<R> Service<Name> for HappyEyeballsResolver<R>
+where
+    R: Service<Name> + Clone + Send + 'static,
+    R::Response: Iterator<Item = SocketAddr>,
+    R::Error: Into<Box<dyn Error + Send + Sync>>,
+    R::Future: Send,
+{
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let mut inner = self.inner.clone();
+        async move {
+            future::poll_fn(|cx| inner.poll_ready(cx))
+                .await
+                .map_err(Into::into)?;
+            let addrs = inner.call(name).await.map_err(Into::into)?;
+            Ok(interleave(addrs).into_iter())
+        }
+        .boxed()
+    }
+}
+
+/// Alternate between IPv6 and IPv4 addresses, starting with IPv6, keeping the
+/// resolver's preference within each family.
+fn interleave(addrs: impl Iterator<Item = SocketAddr>) -> Vec<SocketAddr> {
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.partition(SocketAddr::is_ipv6);
+    let mut v6 = v6.into_iter();
+    let mut v4 = v4.into_iter();
+    let mut addrs = Vec::with_capacity(v6.len() + v4.len());
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => return addrs,
+            (a, b) => addrs.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[tokio::test]
+    async fn overrides_hosts() {
+        let mut resolver =
+            StaticResolver::new().override_host("Service.Example", ["192.0.2.1".parse().unwrap()]);
+
+        let addrs: Vec<_> = resolver
+            .call(Name::from_str("service.example").unwrap())
+            .await
+            .unwrap()
+            .collect();
+        assert_eq!(addrs, vec!["192.0.2.1:0".parse().unwrap()]);
+
+        let addrs: Vec<_> = resolver
+            .call(Name::from_str("localhost").unwrap())
+            .await
+            .unwrap()
+            .collect();
+        assert!(addrs.iter().all(|addr| addr.ip().is_loopback()));
+    }
+
+    #[tokio::test]
+    async fn interleaves_address_families() {
+        let mut resolver = HappyEyeballsResolver::new(StaticResolver::new().override_host(
+            "dual.example",
+            [
+                "192.0.2.1".parse().unwrap(),
+                "192.0.2.2".parse().unwrap(),
+                "2001:db8::1".parse().unwrap(),
+                "192.0.2.3".parse().unwrap(),
+                "2001:db8::2".parse().unwrap(),
+            ],
+        ));
+
+        let addrs: Vec<SocketAddr> = resolver
+            .call(Name::from_str("dual.example").unwrap())
+            .await
+            .unwrap()
+            .collect();
+        assert_eq!(
+            addrs,
+            vec![
+                "[2001:db8::1]:0".parse().unwrap(),
+                "192.0.2.1:0".parse().unwrap(),
+                "[2001:db8::2]:0".parse().unwrap(),
+                "192.0.2.2:0".parse().unwrap(),
+                "192.0.2.3:0".parse().unwrap(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_other_family() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // The IPv6 address is tried first, and can't be connected to
+        let resolver = StaticResolver::new().override_host(
+            "dual.example",
+            ["127.0.0.1".parse().unwrap(), "2001:db8::1".parse().unwrap()],
+        );
+        let mut connector = crate::Connector::builder()
+            .resolver(resolver)
+            .happy_eyeballs(std::time::Duration::from_millis(50))
+            .build();
+
+        let uri = format!("http://dual.example:{}/", port).parse().unwrap();
+        let (stream, accepted) = tokio::join!(connector.call(uri), listener.accept());
+        let stream = stream.unwrap();
+        assert_eq!(
+            stream.inner().peer_addr().unwrap(),
+            accepted.unwrap().0.local_addr().unwrap()
+        );
+    }
+}
@@ -0,0 +1,671 @@
+//! Utility methods for instantiating common connectors for clients.
+#[cfg(all(
+    any(target_os = "macos", target_os = "windows", target_os = "ios"),
+    feature = "tls"
+))]
+use std::convert::From as _;
+#[cfg(all(
+    not(any(target_os = "macos", target_os = "windows", target_os = "ios")),
+    feature = "tls"
+))]
+use std::path::{Path, PathBuf};
+
+#[cfg(any(feature = "rustls", feature = "tls"))]
+use std::fmt;
+use std::time::Duration;
+
+use hyper_util::client::legacy::connect::dns::GaiResolver;
+use hyper_util::client::legacy::connect::HttpConnector;
+
+mod dns;
+pub use self::dns::{HappyEyeballsResolver, StaticResolver};
+
+#[cfg(any(
+    feature = "rustls",
+    all(
+        feature = "tls",
+        not(any(target_os = "macos", target_os = "windows", target_os = "ios"))
+    )
+))]
+mod pinning;
+#[cfg(any(
+    feature = "rustls",
+    all(
+        feature = "tls",
+        not(any(target_os = "macos", target_os = "windows", target_os = "ios"))
+    )
+))]
+pub use self::pinning::{InvalidPin, SpkiPin};
+
+#[cfg(feature = "rustls")]
+mod rustls;
+#[cfg(feature = "rustls")]
+pub use self::rustls::RustlsBuilder;
+
+#[cfg(feature = "proxy")]
+mod proxy;
+#[cfg(feature = "proxy")]
+pub use self::proxy::ProxyConnector;
+
+#[cfg(all(feature = "uds", unix))]
+mod uds;
+#[cfg(all(feature = "uds", unix))]
+pub use self::uds::{UnixConnector, UNIX_SCHEME};
+
+/// HTTP Connector construction
+#[derive(Debug)]
+pub struct Connector;
+
+impl Connector {
+    /// Alows building a HTTP(S) connector. Used for instantiating clients with custom
+    /// connectors.
+    pub fn builder() -> Builder {
+        Builder {
+            resolver: GaiResolver::new(),
+            connect_timeout: None,
+            happy_eyeballs_timeout: None,
+        }
+    }
+}
+
+/// Builder for HTTP(S) connectors
+#[derive(Debug)]
+pub struct Builder<R = GaiResolver> {
+    resolver: R,
+    connect_timeout: Option<Duration>,
+    happy_eyeballs_timeout: Option<Duration>,
+}
+
+impl<R> Builder<R> {
+    /// Set the timeout for establishing TCP connections. By default, there is
+    /// no timeout.
+    ///
+    /// This applies to HTTPS connectors built from this builder too. Use
+    /// `TimeoutService` to bound the total time taken by a request.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Use a different DNS resolver - e.g. a `StaticResolver` to send requests
+    /// for some hosts to fixed addresses. By default, `getaddrinfo` is used.
+    pub fn resolver<R2>(self, resolver: R2) -> Builder<R2> {
+        Builder {
+            resolver,
+            connect_timeout: self.connect_timeout,
+            happy_eyeballs_timeout: self.happy_eyeballs_timeout,
+        }
+    }
+
+    /// Race IPv6 and IPv4 connection attempts, as described in RFC 8305
+    /// ("Happy Eyeballs"), to avoid long delays on dual-stack networks where
+    /// one address family is broken.
+    ///
+    /// IPv6 addresses are tried first. If no connection has been established
+    /// after `delay`, IPv4 addresses are tried at the same time, and whichever
+    /// connects first is used. RFC 8305 recommends a delay of 250ms.
+    ///
+    /// Without this, the connector falls back to the other address family
+    /// after 300ms, preferring the family of the first address returned by
+    /// the resolver.
+    pub fn happy_eyeballs(self, delay: Duration) -> Builder<HappyEyeballsResolver<R>> {
+        Builder {
+            resolver: HappyEyeballsResolver::new(self.resolver),
+            connect_timeout: self.connect_timeout,
+            happy_eyeballs_timeout: Some(delay),
+        }
+    }
+
+    fn http_connector(self) -> HttpConnector<R> {
+        let mut http = HttpConnector::new_with_resolver(self.resolver);
+        http.set_connect_timeout(self.connect_timeout);
+        if let Some(timeout) = self.happy_eyeballs_timeout {
+            http.set_happy_eyeballs_timeout(Some(timeout));
+        }
+        http
+    }
+
+    /// Use HTTPS instead of HTTP
+    #[cfg(feature = "tls")]
+    pub fn https(self) -> HttpsBuilder<R> {
+        let mut http = self.http_connector();
+        http.enforce_http(false);
+        HttpsBuilder {
+            http,
+            #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+            server_cert: None,
+            #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+            client_cert: None,
+            #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+            pins: pinning::Pins::default(),
+            #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+            server_name: None,
+            #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+            certificate_name: None,
+            root_certificates: Vec::new(),
+            accept_invalid_certs: false,
+            identity: None,
+            alpn_protocols: None,
+        }
+    }
+
+    /// Use HTTPS, using rustls rather than the platform's TLS implementation
+    #[cfg(feature = "rustls")]
+    pub fn rustls(self) -> RustlsBuilder<R> {
+        let mut http = self.http_connector();
+        http.enforce_http(false);
+        RustlsBuilder::new(http)
+    }
+
+    /// Use HTTP over Unix Domain Sockets, rather than TCP
+    #[cfg(all(feature = "uds", unix))]
+    pub fn uds(self) -> UnixConnector {
+        UnixConnector::new()
+    }
+
+    /// Build a HTTP connector
+    pub fn build(self) -> HttpConnector<R> {
+        self.http_connector()
+    }
+}
+
+/// Error returned when an ALPN protocol name is empty or longer than 255
+/// bytes, so can't be offered.
+#[cfg(any(feature = "rustls", feature = "tls"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidAlpnProtocol(String);
+
+#[cfg(any(feature = "rustls", feature = "tls"))]
+impl fmt::Display for InvalidAlpnProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid ALPN protocol name: {:?}", self.0)
+    }
+}
+
+#[cfg(any(feature = "rustls", feature = "tls"))]
+impl std::error::Error for InvalidAlpnProtocol {}
+
+/// Collect ALPN protocol names, checking that each can be sent with a
+/// one-byte length prefix.
+#[cfg(any(feature = "rustls", feature = "tls"))]
+fn alpn_protocols<I, P>(protocols: I) -> Result<Vec<Vec<u8>>, InvalidAlpnProtocol>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<[u8]>,
+{
+    protocols
+        .into_iter()
+        .map(|protocol| {
+            let protocol = protocol.as_ref();
+            if protocol.is_empty() || protocol.len() > usize::from(u8::MAX) {
+                return Err(InvalidAlpnProtocol(
+                    String::from_utf8_lossy(protocol).into_owned(),
+                ));
+            }
+            Ok(protocol.to_vec())
+        })
+        .collect()
+}
+
+/// Builder for HTTPS connectors using the platform's TLS implementation - OpenSSL
+/// on Linux, and native-tls elsewhere.
+///
+/// This offers the same options as the rustls `RustlsBuilder`, for deployments
+/// which must use the system TLS stack (e.g. corporate CA stores, or FIPS
+/// builds).
+///
+/// ```rust
+/// # use swagger::Connector;
+/// # fn build(ca_pem: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+/// let connector = Connector::builder()
+///     .https()
+///     .add_root_certificate_pem(ca_pem)
+///     .alpn_protocols(["http/1.1"])?
+///     .build();
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "tls")]
+#[derive(Debug)]
+pub struct HttpsBuilder<R = GaiResolver> {
+    http: HttpConnector<R>,
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+    server_cert: Option<PathBuf>,
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+    client_cert: Option<(PathBuf, PathBuf)>,
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+    pins: pinning::Pins,
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+    server_name: Option<String>,
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+    certificate_name: Option<String>,
+    root_certificates: Vec<Vec<u8>>,
+    accept_invalid_certs: bool,
+    identity: Option<ClientIdentity>,
+    alpn_protocols: Option<Vec<Vec<u8>>>,
+}
+
+/// Client certificate and key for Mutual TLS
+#[cfg(feature = "tls")]
+enum ClientIdentity {
+    Pem { cert: Vec<u8>, key: Vec<u8> },
+    Pkcs12 { der: Vec<u8>, password: String },
+}
+
+#[cfg(feature = "tls")]
+impl fmt::Debug for ClientIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Avoid logging private keys or passwords
+        match self {
+            ClientIdentity::Pem { .. } => f.write_str("Pem"),
+            ClientIdentity::Pkcs12 { .. } => f.write_str("Pkcs12"),
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+impl<R> HttpsBuilder<R> {
+    /// Pin the CA certificate for the server's certificate.
+    ///
+    /// # Arguments
+    ///
+    /// * `ca_certificate` - Path to CA certificate used to authenticate the server
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+    pub fn pin_server_certificate<CA>(mut self, ca_certificate: CA) -> Self
+    where
+        CA: AsRef<Path>,
+    {
+        self.server_cert = Some(ca_certificate.as_ref().to_owned());
+        self
+    }
+
+    /// Provide the Client Certificate and Key for the connection for Mutual TLS
+    ///
+    /// # Arguments
+    ///
+    /// * `client_key` - Path to the client private key
+    /// * `client_certificate` - Path to the client's public certificate associated with the private key
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+    pub fn client_authentication<K, C>(mut self, client_key: K, client_certificate: C) -> Self
+    where
+        K: AsRef<Path>,
+        C: AsRef<Path>,
+    {
+        self.client_cert = Some((
+            client_key.as_ref().to_owned(),
+            client_certificate.as_ref().to_owned(),
+        ));
+        self
+    }
+
+    /// Pin the public key of a host's certificate chain. Can be called
+    /// several times to allow multiple keys, e.g. for a backup key.
+    ///
+    /// Connections to the host fail unless one of the certificates in the
+    /// chain presented by the server has a pinned public key. Not supported
+    /// by native-tls.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - Name of the server, as used to verify its certificate
+    /// * `pin` - Hash of the public key, e.g. `"sha256/...".parse()?`
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+    pub fn pin_sha256(mut self, host: &str, pin: SpkiPin) -> Self {
+        self.pins.add(host, [pin]);
+        self
+    }
+
+    /// Send `name` as the TLS server name (SNI), rather than the host of the
+    /// request URI - e.g. when connecting to a CDN edge, or a service mesh
+    /// sidecar, by IP address. Not supported by native-tls.
+    ///
+    /// The server's certificate is verified against this name too, unless
+    /// `certificate_name` is set. Only the TLS handshake is affected - the
+    /// connector can't change the request's `Host` header, which the client
+    /// takes from the request URI unless the request already has one.
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+    pub fn server_name(mut self, name: &str) -> Self {
+        self.server_name = Some(name.to_string());
+        self
+    }
+
+    /// Verify the server's certificate against `name`, rather than the TLS
+    /// server name. Not supported by native-tls.
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+    pub fn certificate_name(mut self, name: &str) -> Self {
+        self.certificate_name = Some(name.to_string());
+        self
+    }
+
+    /// Trust the CA certificates in a PEM file, in addition to the platform's
+    /// root certificates.
+    ///
+    /// # Arguments
+    ///
+    /// * `pem` - PEM encoded CA certificates used to authenticate the server
+    pub fn add_root_certificate_pem(mut self, pem: &[u8]) -> Self {
+        self.root_certificates.push(pem.to_vec());
+        self
+    }
+
+    /// Don't verify the server's certificate at all.
+    ///
+    /// This makes the connection vulnerable to man-in-the-middle attacks, and
+    /// should only be used against test rigs.
+    pub fn danger_accept_invalid_certs(mut self, accept_invalid_certs: bool) -> Self {
+        self.accept_invalid_certs = accept_invalid_certs;
+        self
+    }
+
+    /// Provide the client certificate and key for the connection for Mutual TLS
+    ///
+    /// # Arguments
+    ///
+    /// * `cert_pem` - PEM encoded client certificate, followed by any intermediate certificates
+    /// * `key_pem` - PEM encoded private key associated with the client certificate
+    pub fn pinned_client_cert(mut self, cert_pem: &[u8], key_pem: &[u8]) -> Self {
+        self.identity = Some(ClientIdentity::Pem {
+            cert: cert_pem.to_vec(),
+            key: key_pem.to_vec(),
+        });
+        self
+    }
+
+    /// Provide the client certificate and key for the connection for Mutual TLS
+    /// as a PKCS#12 bundle
+    ///
+    /// # Arguments
+    ///
+    /// * `der` - DER encoded PKCS#12 bundle containing the client certificate and private key
+    /// * `password` - Password used to decrypt the bundle
+    pub fn client_pkcs12(mut self, der: &[u8], password: &str) -> Self {
+        self.identity = Some(ClientIdentity::Pkcs12 {
+            der: der.to_vec(),
+            password: password.to_string(),
+        });
+        self
+    }
+
+    /// Only offer HTTP/1.1 using ALPN, for servers which misbehave when
+    /// HTTP/2 is offered.
+    pub fn http1_only(mut self) -> Self {
+        self.alpn_protocols = Some(vec![b"http/1.1".to_vec()]);
+        self
+    }
+
+    /// Only offer HTTP/2 using ALPN.
+    pub fn http2_only(mut self) -> Self {
+        self.alpn_protocols = Some(vec![b"h2".to_vec()]);
+        self
+    }
+
+    /// Set the protocols offered using ALPN, in order of preference. By
+    /// default, ALPN isn't used.
+    ///
+    /// Fails if a protocol name is empty or longer than 255 bytes.
+    pub fn alpn_protocols<I, P>(mut self, protocols: I) -> Result<Self, InvalidAlpnProtocol>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<[u8]>,
+    {
+        self.alpn_protocols = Some(alpn_protocols(protocols)?);
+        Ok(self)
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+    /// Build the HTTPS connector. Will fail if the provided certificates/keys can't be loaded
+    /// or the SSL connector can't be created
+    pub fn build(
+        self,
+    ) -> Result<
+        hyper_openssl::client::legacy::HttpsConnector<HttpConnector<R>>,
+        openssl::error::ErrorStack,
+    >
+    where
+        R: Clone,
+    {
+        let http = self.http.clone();
+        self.build_with_connector(http)
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+    /// Build the HTTPS connector, wrapping a different underlying connector, e.g. a
+    /// `ProxyConnector`. Will fail if the provided certificates/keys can't be loaded
+    /// or the SSL connector can't be created
+    pub fn build_with_connector<C>(
+        self,
+        connector: C,
+    ) -> Result<hyper_openssl::client::legacy::HttpsConnector<C>, openssl::error::ErrorStack> {
+        // SSL implementation
+        let mut ssl = openssl::ssl::SslConnector::builder(openssl::ssl::SslMethod::tls())?;
+
+        if let Some(ca_certificate) = self.server_cert {
+            // Server authentication
+            ssl.set_ca_file(ca_certificate)?;
+        }
+
+        if let Some((client_key, client_certificate)) = self.client_cert {
+            // Client authentication
+            ssl.set_private_key_file(client_key, openssl::ssl::SslFiletype::PEM)?;
+            ssl.set_certificate_chain_file(client_certificate)?;
+            ssl.check_private_key()?;
+        }
+
+        match self.identity {
+            Some(ClientIdentity::Pem { cert, key }) => {
+                let mut chain = openssl::x509::X509::stack_from_pem(&cert)?.into_iter();
+                if let Some(certificate) = chain.next() {
+                    ssl.set_certificate(&certificate)?;
+                }
+                for certificate in chain {
+                    ssl.add_extra_chain_cert(certificate)?;
+                }
+                let key = openssl::pkey::PKey::private_key_from_pem(&key)?;
+                ssl.set_private_key(&key)?;
+                ssl.check_private_key()?;
+            }
+            Some(ClientIdentity::Pkcs12 { der, password }) => {
+                let pkcs12 = openssl::pkcs12::Pkcs12::from_der(&der)?.parse2(&password)?;
+                if let Some(certificate) = pkcs12.cert {
+                    ssl.set_certificate(&certificate)?;
+                }
+                for certificate in pkcs12.ca.into_iter().flatten() {
+                    ssl.add_extra_chain_cert(certificate)?;
+                }
+                if let Some(key) = pkcs12.pkey {
+                    ssl.set_private_key(&key)?;
+                }
+                ssl.check_private_key()?;
+            }
+            None => {}
+        }
+
+        for pem in &self.root_certificates {
+            for certificate in openssl::x509::X509::stack_from_pem(pem)? {
+                ssl.cert_store_mut().add_cert(certificate)?;
+            }
+        }
+
+        if self.accept_invalid_certs {
+            ssl.set_verify(openssl::ssl::SslVerifyMode::NONE);
+        }
+
+        if let Some(protocols) = self.alpn_protocols {
+            // ALPN protocols are sent as a list of length-prefixed strings
+            let mut wire = Vec::new();
+            for protocol in protocols {
+                wire.push(u8::try_from(protocol.len()).expect("ALPN protocols are validated"));
+                wire.extend(protocol);
+            }
+            ssl.set_alpn_protos(&wire)?;
+        }
+
+        let mut connector =
+            hyper_openssl::client::legacy::HttpsConnector::with_connector(connector, ssl)?;
+
+        if !self.pins.is_empty() || self.server_name.is_some() || self.certificate_name.is_some() {
+            let pins = self.pins;
+            let accept_invalid_certs = self.accept_invalid_certs;
+            let server_name = self.server_name;
+            let certificate_name = self.certificate_name.or_else(|| server_name.clone());
+            connector.set_callback(move |config, uri| {
+                let host = uri.host().unwrap_or_default();
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+
+                if let Some(name) = &server_name {
+                    config.set_use_server_name_indication(false);
+                    if name.parse::<std::net::IpAddr>().is_err() {
+                        config.set_hostname(name)?;
+                    }
+                }
+
+                let host = match &certificate_name {
+                    Some(name) if !accept_invalid_certs => {
+                        config.set_verify_hostname(false);
+                        match name.parse() {
+                            Ok(ip) => config.param_mut().set_ip(ip)?,
+                            Err(_) => config.param_mut().set_host(name)?,
+                        }
+                        name.clone()
+                    }
+                    Some(name) => name.clone(),
+                    None => host.to_string(),
+                };
+
+                if !pins.is_empty() {
+                    let pins = pins.clone();
+                    config.set_verify_callback(
+                        openssl::ssl::SslVerifyMode::PEER,
+                        move |ok, ctx| {
+                            if !ok && !accept_invalid_certs {
+                                return false;
+                            }
+                            // The chain is checked once it has been verified,
+                            // at the end entity
+                            if ctx.error_depth() != 0 {
+                                return true;
+                            }
+                            let hashes =
+                                ctx.chain().into_iter().flatten().filter_map(|certificate| {
+                                    let spki =
+                                        certificate.public_key().ok()?.public_key_to_der().ok()?;
+                                    Some(openssl::sha::sha256(&spki))
+                                });
+                            pins.check(&host, hashes)
+                        },
+                    );
+                }
+                Ok(())
+            });
+        }
+
+        Ok(connector)
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+    /// Build the HTTPS connector. Will fail if the provided certificates can't be loaded
+    /// or the SSL connector can't be created.
+    pub fn build(self) -> Result<hyper_tls::HttpsConnector<HttpConnector<R>>, native_tls::Error>
+    where
+        R: Clone,
+    {
+        let http = self.http.clone();
+        self.build_with_connector(http)
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+    /// Build the HTTPS connector, wrapping a different underlying connector, e.g. a
+    /// `ProxyConnector`. Will fail if the provided certificates can't be loaded
+    /// or the SSL connector can't be created.
+    pub fn build_with_connector<C>(
+        self,
+        connector: C,
+    ) -> Result<hyper_tls::HttpsConnector<C>, native_tls::Error> {
+        let mut tls = native_tls::TlsConnector::builder();
+
+        for pem in &self.root_certificates {
+            tls.add_root_certificate(native_tls::Certificate::from_pem(pem)?);
+        }
+
+        tls.danger_accept_invalid_certs(self.accept_invalid_certs);
+
+        match &self.identity {
+            Some(ClientIdentity::Pem { cert, key }) => {
+                tls.identity(native_tls::Identity::from_pkcs8(cert, key)?);
+            }
+            Some(ClientIdentity::Pkcs12 { der, password }) => {
+                tls.identity(native_tls::Identity::from_pkcs12(der, password)?);
+            }
+            None => {}
+        }
+
+        if let Some(protocols) = &self.alpn_protocols {
+            let protocols: Vec<String> = protocols
+                .iter()
+                .map(|p| String::from_utf8_lossy(p).into_owned())
+                .collect();
+            let protocols: Vec<&str> = protocols.iter().map(String::as_str).collect();
+            tls.request_alpns(&protocols);
+        }
+
+        let tls = tls.build()?.into();
+        let mut connector = hyper_tls::HttpsConnector::from((connector, tls));
+        connector.https_only(true);
+        Ok(connector)
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "tls",
+    not(any(target_os = "macos", target_os = "windows", target_os = "ios"))
+))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_alpn_protocols() {
+        assert!(Connector::builder()
+            .https()
+            .alpn_protocols(["h2", "http/1.1"])
+            .is_ok());
+        assert!(Connector::builder().https().alpn_protocols([""]).is_err());
+        assert!(Connector::builder()
+            .https()
+            .alpn_protocols([[b'a'; 256]])
+            .is_err());
+    }
+
+    #[test]
+    fn client_certificate() {
+        let client = rcgen::generate_simple_self_signed(vec!["client".to_string()]).unwrap();
+        let cert_pem = client.cert.pem();
+        let key_pem = client.signing_key.serialize_pem();
+
+        assert!(Connector::builder()
+            .https()
+            .pinned_client_cert(cert_pem.as_bytes(), key_pem.as_bytes())
+            .build()
+            .is_ok());
+
+        let pkcs12 = openssl::pkcs12::Pkcs12::builder()
+            .name("client")
+            .cert(&openssl::x509::X509::from_pem(cert_pem.as_bytes()).unwrap())
+            .pkey(&openssl::pkey::PKey::private_key_from_pem(key_pem.as_bytes()).unwrap())
+            .build2("secret")
+            .unwrap()
+            .to_der()
+            .unwrap();
+
+        assert!(Connector::builder()
+            .https()
+            .client_pkcs12(&pkcs12, "secret")
+            .build()
+            .is_ok());
+        assert!(Connector::builder()
+            .https()
+            .client_pkcs12(&pkcs12, "wrong")
+            .build()
+            .is_err());
+    }
+}
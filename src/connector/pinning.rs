@@ -0,0 +1,114 @@
+//! Public key pinning, as described in RFC 7469.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// A pin of a server's public key: the SHA-256 hash of a certificate's DER
+/// encoded SubjectPublicKeyInfo (SPKI).
+///
+/// Pins are parsed from the base64 encoded hash, as used in the `pin-sha256`
+/// directive of the `Public-Key-Pins` header, and printed by e.g.
+///
+/// ```text
+/// openssl x509 -in cert.pem -pubkey -noout \
+///   | openssl pkey -pubin -outform der \
+///   | openssl dgst -sha256 -binary | base64
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpkiPin([u8; 32]);
+
+impl SpkiPin {
+    /// Create a pin from the SHA-256 hash of a SubjectPublicKeyInfo.
+    pub fn from_sha256(hash: [u8; 32]) -> Self {
+        SpkiPin(hash)
+    }
+}
+
+impl fmt::Debug for SpkiPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SpkiPin({})", self)
+    }
+}
+
+impl fmt::Display for SpkiPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&STANDARD.encode(self.0))
+    }
+}
+
+/// Error returned when a pin can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidPin(String);
+
+impl fmt::Display for InvalidPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid pin-sha256 value: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidPin {}
+
+impl FromStr for SpkiPin {
+    type Err = InvalidPin;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s.strip_prefix("sha256/").unwrap_or(s);
+        let hash = STANDARD.decode(s).map_err(|_| InvalidPin(s.to_string()))?;
+        let hash = hash.try_into().map_err(|_| InvalidPin(s.to_string()))?;
+        Ok(SpkiPin(hash))
+    }
+}
+
+/// Pins for each host.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Pins(HashMap<String, Vec<SpkiPin>>);
+
+impl Pins {
+    pub(super) fn add(&mut self, host: &str, pins: impl IntoIterator<Item = SpkiPin>) {
+        self.0
+            .entry(host.to_ascii_lowercase())
+            .or_default()
+            .extend(pins);
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Check the SPKI hashes of a server's certificate chain against the
+    /// pins for the host. Connections to hosts without pins are allowed.
+    pub(super) fn check(&self, host: &str, mut hashes: impl Iterator<Item = [u8; 32]>) -> bool {
+        match self.0.get(&host.to_ascii_lowercase()) {
+            Some(pins) => hashes.any(|hash| pins.contains(&SpkiPin(hash))),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pins() {
+        let pin: SpkiPin = "sha256/AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="
+            .parse()
+            .unwrap();
+        assert_eq!(pin, SpkiPin::from_sha256([0; 32]));
+        assert_eq!(
+            pin.to_string(),
+            "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="
+        );
+        assert!("AAAA".parse::<SpkiPin>().is_err());
+
+        let mut pins = Pins::default();
+        pins.add("Example.com", [pin]);
+        assert!(pins.check("example.com", [[1; 32], [0; 32]].into_iter()));
+        assert!(!pins.check("example.com", [[1; 32]].into_iter()));
+        assert!(pins.check("other.com", [[1; 32]].into_iter()));
+    }
+}
@@ -0,0 +1,637 @@
+//! HTTPS connectors using rustls.
+
+use super::pinning::{Pins, SpkiPin};
+use super::InvalidAlpnProtocol;
+use hyper_rustls::{FixedServerNameResolver, HttpsConnector};
+use hyper_util::client::legacy::connect::dns::GaiResolver;
+use hyper_util::client::legacy::connect::HttpConnector;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use std::sync::Arc;
+
+/// Builder for HTTPS connectors using rustls
+///
+/// By default, the server is authenticated using the platform's native root
+/// certificates, and both HTTP/2 and HTTP/1.1 are offered using ALPN, if the
+/// corresponding crate features are enabled.
+///
+/// ```rust
+/// # use swagger::Connector;
+/// # fn build(ca_pem: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+/// let connector = Connector::builder()
+///     .rustls()
+///     .native_roots(false)
+///     .add_root_certificate_pem(ca_pem)
+///     .alpn_protocols(["http/1.1"])?
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RustlsBuilder<R = GaiResolver> {
+    http: HttpConnector<R>,
+    native_roots: bool,
+    webpki_roots: bool,
+    root_certificates: Vec<Vec<u8>>,
+    accept_invalid_certs: bool,
+    client_cert: Option<(Vec<u8>, Vec<u8>)>,
+    alpn_protocols: Vec<Vec<u8>>,
+    https_only: bool,
+    pins: Pins,
+    server_name: Option<String>,
+    certificate_name: Option<String>,
+}
+
+impl<R> RustlsBuilder<R> {
+    pub(super) fn new(http: HttpConnector<R>) -> Self {
+        let mut alpn_protocols = Vec::new();
+        if cfg!(feature = "http2") {
+            alpn_protocols.push(b"h2".to_vec());
+        }
+        if cfg!(feature = "http1") {
+            alpn_protocols.push(b"http/1.1".to_vec());
+        }
+
+        RustlsBuilder {
+            http,
+            native_roots: true,
+            webpki_roots: false,
+            root_certificates: Vec::new(),
+            accept_invalid_certs: false,
+            client_cert: None,
+            alpn_protocols,
+            https_only: false,
+            pins: Pins::default(),
+            server_name: None,
+            certificate_name: None,
+        }
+    }
+
+    /// Trust the platform's native root certificates. Enabled by default.
+    pub fn native_roots(mut self, enabled: bool) -> Self {
+        self.native_roots = enabled;
+        self
+    }
+
+    /// Trust the Mozilla root certificates bundled with the `webpki-roots`
+    /// crate. Disabled by default.
+    pub fn webpki_roots(mut self, enabled: bool) -> Self {
+        self.webpki_roots = enabled;
+        self
+    }
+
+    /// Trust the CA certificates in a PEM file, in addition to any other
+    /// roots configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `pem` - PEM encoded CA certificates used to authenticate the server
+    pub fn add_root_certificate_pem(mut self, pem: &[u8]) -> Self {
+        self.root_certificates.push(pem.to_vec());
+        self
+    }
+
+    /// Don't verify the server's certificate at all.
+    ///
+    /// This makes the connection vulnerable to man-in-the-middle attacks, and
+    /// should only be used against test rigs.
+    pub fn danger_accept_invalid_certs(mut self, accept_invalid_certs: bool) -> Self {
+        self.accept_invalid_certs = accept_invalid_certs;
+        self
+    }
+
+    /// Provide the client certificate and key for the connection for Mutual TLS
+    ///
+    /// Unlike `HttpsBuilder`, this builder has no `client_pkcs12` - rustls
+    /// can't load PKCS#12 bundles, so they must be converted to PEM first,
+    /// e.g. with `openssl pkcs12 -in client.p12 -nodes`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cert_pem` - PEM encoded client certificate, followed by any intermediate certificates
+    /// * `key_pem` - PEM encoded private key associated with the client certificate
+    pub fn pinned_client_cert(mut self, cert_pem: &[u8], key_pem: &[u8]) -> Self {
+        self.client_cert = Some((cert_pem.to_vec(), key_pem.to_vec()));
+        self
+    }
+
+    /// Pin the public key of a host's certificate chain. Can be called
+    /// several times to allow multiple keys, e.g. for a backup key.
+    ///
+    /// Connections to the host fail unless one of the certificates in the
+    /// chain presented by the server has a pinned public key. The chain is
+    /// still verified against the trusted roots first, unless
+    /// `danger_accept_invalid_certs` is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - Name of the server, as used to verify its certificate
+    /// * `pin` - Hash of the public key, e.g. `"sha256/...".parse()?`
+    pub fn pin_sha256(mut self, host: &str, pin: SpkiPin) -> Self {
+        self.pins.add(host, [pin]);
+        self
+    }
+
+    /// Send `name` as the TLS server name (SNI), rather than the host of the
+    /// request URI - e.g. when connecting to a CDN edge, or a service mesh
+    /// sidecar, by IP address.
+    ///
+    /// The server's certificate is verified against this name too, unless
+    /// `certificate_name` is set. Only the TLS handshake is affected - the
+    /// connector can't change the request's `Host` header, which the client
+    /// takes from the request URI unless the request already has one.
+    pub fn server_name(mut self, name: &str) -> Self {
+        self.server_name = Some(name.to_string());
+        self
+    }
+
+    /// Verify the server's certificate against `name`, rather than the TLS
+    /// server name.
+    pub fn certificate_name(mut self, name: &str) -> Self {
+        self.certificate_name = Some(name.to_string());
+        self
+    }
+
+    /// Only offer HTTP/1.1 using ALPN, for servers which misbehave when
+    /// HTTP/2 is offered.
+    pub fn http1_only(mut self) -> Self {
+        self.alpn_protocols = vec![b"http/1.1".to_vec()];
+        self
+    }
+
+    /// Only offer HTTP/2 using ALPN.
+    pub fn http2_only(mut self) -> Self {
+        self.alpn_protocols = vec![b"h2".to_vec()];
+        self
+    }
+
+    /// Set the protocols offered using ALPN, in order of preference.
+    ///
+    /// Fails if a protocol name is empty or longer than 255 bytes.
+    pub fn alpn_protocols<I, P>(mut self, protocols: I) -> Result<Self, InvalidAlpnProtocol>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<[u8]>,
+    {
+        self.alpn_protocols = super::alpn_protocols(protocols)?;
+        Ok(self)
+    }
+
+    /// Reject plain HTTP URIs, rather than sending requests unencrypted.
+    pub fn https_only(mut self, https_only: bool) -> Self {
+        self.https_only = https_only;
+        self
+    }
+
+    /// Build the rustls client configuration. Will fail if the provided
+    /// certificates can't be parsed, or no root certificates are available.
+    pub fn build_config(self) -> Result<ClientConfig, rustls::Error> {
+        let provider = Arc::new(ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+
+        let verifier = if self.pins.is_empty() && self.certificate_name.is_none() {
+            None
+        } else {
+            let mut verifier: Arc<dyn ServerCertVerifier> = if self.accept_invalid_certs {
+                Arc::new(NoVerification(provider.clone()))
+            } else {
+                WebPkiServerVerifier::builder_with_provider(
+                    Arc::new(self.root_store()?),
+                    provider.clone(),
+                )
+                .build()
+                .map_err(|e| rustls::Error::General(e.to_string()))?
+            };
+            if !self.pins.is_empty() {
+                verifier = Arc::new(PinningVerifier {
+                    inner: verifier,
+                    pins: self.pins.clone(),
+                });
+            }
+            if let Some(name) = &self.certificate_name {
+                verifier = Arc::new(CertificateNameVerifier {
+                    inner: verifier,
+                    name: server_name(name)?,
+                });
+            }
+            Some(verifier)
+        };
+
+        let builder = match verifier {
+            Some(verifier) => builder
+                .dangerous()
+                .with_custom_certificate_verifier(verifier),
+            None if self.accept_invalid_certs => builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(NoVerification(provider))),
+            None => builder.with_root_certificates(self.root_store()?),
+        };
+
+        let mut config = match &self.client_cert {
+            Some((cert_pem, key_pem)) => {
+                let certificates = CertificateDer::pem_slice_iter(cert_pem)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| {
+                        rustls::Error::General(format!("Invalid client certificate: {}", e))
+                    })?;
+                let key = PrivateKeyDer::from_pem_slice(key_pem).map_err(|e| {
+                    rustls::Error::General(format!("Invalid client private key: {}", e))
+                })?;
+                builder.with_client_auth_cert(certificates, key)?
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        config.alpn_protocols = self.alpn_protocols;
+        Ok(config)
+    }
+
+    /// Build the HTTPS connector. Will fail if the provided certificates
+    /// can't be parsed, or no root certificates are available.
+    pub fn build(self) -> Result<HttpsConnector<HttpConnector<R>>, rustls::Error>
+    where
+        R: Clone,
+    {
+        let http = self.http.clone();
+        self.build_with_connector(http)
+    }
+
+    /// Build the HTTPS connector, wrapping a different underlying connector,
+    /// e.g. a `ProxyConnector`. Will fail if the provided certificates can't
+    /// be parsed, or no root certificates are available.
+    pub fn build_with_connector<C>(self, connector: C) -> Result<HttpsConnector<C>, rustls::Error> {
+        let https_only = self.https_only;
+        let server_name = self.server_name.as_deref().map(server_name).transpose()?;
+        let config = self.build_config()?;
+
+        Ok(match server_name {
+            Some(name) => HttpsConnector::new(
+                connector,
+                config,
+                https_only,
+                Arc::new(FixedServerNameResolver::new(name)),
+            ),
+            None => {
+                let mut connector = HttpsConnector::from((connector, config));
+                if https_only {
+                    connector.enforce_https();
+                }
+                connector
+            }
+        })
+    }
+
+    fn root_store(&self) -> Result<RootCertStore, rustls::Error> {
+        let mut roots = RootCertStore::empty();
+
+        if self.native_roots {
+            let native = rustls_native_certs::load_native_certs();
+            if native.certs.is_empty() {
+                if let Some(error) = native.errors.first() {
+                    return Err(rustls::Error::General(format!(
+                        "Failed to load native root certificates: {}",
+                        error
+                    )));
+                }
+            }
+            roots.add_parsable_certificates(native.certs);
+        }
+
+        if self.webpki_roots {
+            roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        }
+
+        for pem in &self.root_certificates {
+            for certificate in CertificateDer::pem_slice_iter(pem) {
+                let certificate = certificate.map_err(|e| {
+                    rustls::Error::General(format!("Invalid root certificate: {}", e))
+                })?;
+                roots.add(certificate)?;
+            }
+        }
+
+        if roots.is_empty() {
+            return Err(rustls::Error::General(
+                "No root certificates configured".to_string(),
+            ));
+        }
+
+        Ok(roots)
+    }
+}
+
+/// Parse a DNS name or IP address for use with TLS.
+fn server_name(name: &str) -> Result<ServerName<'static>, rustls::Error> {
+    let name = name.trim_start_matches('[').trim_end_matches(']');
+    ServerName::try_from(name.to_string())
+        .map_err(|_| rustls::Error::General(format!("Invalid server name: {}", name)))
+}
+
+/// Certificate verifier which accepts any server certificate.
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Certificate verifier which checks the server's certificate chain against
+/// pinned public keys, after verifying it with another verifier.
+#[derive(Debug)]
+struct PinningVerifier {
+    inner: Arc<dyn ServerCertVerifier>,
+    pins: Pins,
+}
+
+/// Certificate verifier which verifies the server's certificate against a
+/// fixed name, rather than the TLS server name.
+#[derive(Debug)]
+struct CertificateNameVerifier {
+    inner: Arc<dyn ServerCertVerifier>,
+    name: ServerName<'static>,
+}
+
+impl ServerCertVerifier for CertificateNameVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.inner
+            .verify_server_cert(end_entity, intermediates, &self.name, ocsp_response, now)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Hash the SubjectPublicKeyInfo of a certificate.
+fn spki_sha256(certificate: &CertificateDer<'_>) -> Option<[u8; 32]> {
+    let certificate = webpki::EndEntityCert::try_from(certificate).ok()?;
+    let digest = ::ring::digest::digest(
+        &::ring::digest::SHA256,
+        certificate.subject_public_key_info().as_ref(),
+    );
+    digest.as_ref().try_into().ok()
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+
+        let host = match server_name {
+            ServerName::DnsName(name) => name.as_ref().to_string(),
+            other => other.to_str().into_owned(),
+        };
+        let hashes = std::iter::once(end_entity)
+            .chain(intermediates)
+            .filter_map(spki_sha256);
+        if self.pins.check(&host, hashes) {
+            Ok(verified)
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_config() {
+        let config = RustlsBuilder::new(HttpConnector::new())
+            .native_roots(false)
+            .webpki_roots(true)
+            .alpn_protocols(["http/1.1"])
+            .unwrap()
+            .build_config()
+            .unwrap();
+        assert_eq!(config.alpn_protocols, vec![b"http/1.1".to_vec()]);
+        assert!(RustlsBuilder::new(HttpConnector::new())
+            .alpn_protocols(["h2", ""])
+            .is_err());
+
+        let config = RustlsBuilder::new(HttpConnector::new())
+            .webpki_roots(true)
+            .native_roots(false)
+            .http2_only()
+            .build_config()
+            .unwrap();
+        assert_eq!(config.alpn_protocols, vec![b"h2".to_vec()]);
+
+        let error = RustlsBuilder::new(HttpConnector::new())
+            .native_roots(false)
+            .build_config()
+            .unwrap_err();
+        assert!(error.to_string().contains("No root certificates"));
+
+        let error = RustlsBuilder::new(HttpConnector::new())
+            .native_roots(false)
+            .add_root_certificate_pem(
+                b"-----BEGIN CERTIFICATE-----\nnot base64!\n-----END CERTIFICATE-----\n",
+            )
+            .build_config()
+            .unwrap_err();
+        assert!(error.to_string().contains("Invalid root certificate"));
+
+        assert!(RustlsBuilder::new(HttpConnector::new())
+            .native_roots(false)
+            .danger_accept_invalid_certs(true)
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn client_certificate() {
+        let client = rcgen::generate_simple_self_signed(vec!["client".to_string()]).unwrap();
+        let config = RustlsBuilder::new(HttpConnector::new())
+            .native_roots(false)
+            .webpki_roots(true)
+            .pinned_client_cert(
+                client.cert.pem().as_bytes(),
+                client.signing_key.serialize_pem().as_bytes(),
+            )
+            .build_config()
+            .unwrap();
+        assert!(config.client_auth_cert_resolver.has_certs());
+
+        let error = RustlsBuilder::new(HttpConnector::new())
+            .native_roots(false)
+            .webpki_roots(true)
+            .pinned_client_cert(client.cert.pem().as_bytes(), b"")
+            .build_config()
+            .unwrap_err();
+        assert!(error.to_string().contains("Invalid client private key"));
+    }
+
+    #[test]
+    fn checks_pins() {
+        let server = rcgen::generate_simple_self_signed(vec!["server.test".to_string()]).unwrap();
+        let certificate = server.cert.der().clone();
+        let hash = spki_sha256(&certificate).unwrap();
+        let name = ServerName::try_from("server.test").unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots.add(certificate.clone()).unwrap();
+        let inner = WebPkiServerVerifier::builder_with_provider(
+            Arc::new(roots),
+            Arc::new(ring::default_provider()),
+        )
+        .build()
+        .unwrap();
+
+        let verify = |pin| {
+            let mut pins = Pins::default();
+            pins.add("server.test", [SpkiPin::from_sha256(pin)]);
+            let verifier = PinningVerifier {
+                inner: inner.clone(),
+                pins,
+            };
+            verifier.verify_server_cert(&certificate, &[], &name, &[], UnixTime::now())
+        };
+
+        assert!(verify(hash).is_ok());
+        assert_eq!(
+            verify([0; 32]).unwrap_err(),
+            rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure)
+        );
+    }
+
+    #[test]
+    fn checks_certificate_name() {
+        let server = rcgen::generate_simple_self_signed(vec!["server.test".to_string()]).unwrap();
+        let certificate = server.cert.der().clone();
+        let address = ServerName::try_from("192.0.2.1").unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots.add(certificate.clone()).unwrap();
+        let inner = WebPkiServerVerifier::builder_with_provider(
+            Arc::new(roots),
+            Arc::new(ring::default_provider()),
+        )
+        .build()
+        .unwrap();
+
+        let verify = |name| {
+            let verifier = CertificateNameVerifier {
+                inner: inner.clone(),
+                name: server_name(name).unwrap(),
+            };
+            verifier.verify_server_cert(&certificate, &[], &address, &[], UnixTime::now())
+        };
+
+        assert!(verify("server.test").is_ok());
+        assert!(verify("other.test").is_err());
+
+        assert!(RustlsBuilder::new(HttpConnector::new())
+            .native_roots(false)
+            .add_root_certificate_pem(server.cert.pem().as_bytes())
+            .server_name("192.0.2.1")
+            .certificate_name("server.test")
+            .build()
+            .is_ok());
+        let error = RustlsBuilder::new(HttpConnector::new())
+            .server_name("not a name")
+            .build()
+            .unwrap_err();
+        assert!(error.to_string().contains("Invalid server name"));
+    }
+}
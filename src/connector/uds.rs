@@ -0,0 +1,151 @@
+//! Connector for HTTP over Unix Domain Sockets.
+
+use futures::future::BoxFuture;
+use hyper::Uri;
+use hyper_util::rt::TokioIo;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::task::{Context, Poll};
+use tokio::net::UnixStream;
+
+/// URI scheme used for requests sent over Unix Domain Sockets
+pub const UNIX_SCHEME: &str = "unix";
+
+/// Connector for HTTP over Unix Domain Sockets, for use with the hyper-util
+/// legacy `Client`.
+///
+/// The socket path is carried in the authority of a `unix://` URI, hex encoded
+/// so that it is a valid host name. `UnixConnector::base_path` can be used to
+/// build the base path given to a generated client.
+///
+/// ```rust
+/// # use swagger::connector::UnixConnector;
+/// let base_path = UnixConnector::base_path("/run/petstore.sock");
+/// assert!(base_path.starts_with("unix://"));
+///
+/// // Requests to e.g. `{base_path}/pets` are sent over the socket.
+/// let connector = UnixConnector::new();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct UnixConnector {
+    _private: (),
+}
+
+impl UnixConnector {
+    /// Create a new `UnixConnector`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the base path, e.g. for a generated client, for requests sent over
+    /// the socket at `socket_path`.
+    pub fn base_path<P: AsRef<Path>>(socket_path: P) -> String {
+        let path = socket_path.as_ref().as_os_str().as_encoded_bytes();
+        let mut base_path = format!("{}://", UNIX_SCHEME);
+        for byte in path {
+            base_path.push_str(&format!("{:02x}", byte));
+        }
+        base_path
+    }
+
+    /// Get the socket path from a `unix://` URI.
+    pub fn socket_path(uri: &Uri) -> io::Result<PathBuf> {
+        if uri.scheme_str() != Some(UNIX_SCHEME) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("URI scheme is not {}://", UNIX_SCHEME),
+            ));
+        }
+
+        let host = uri
+            .host()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "URI missing host"))?;
+
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "Invalid socket path in URI");
+        if host.len() % 2 != 0 {
+            return Err(invalid());
+        }
+        let bytes = (0..host.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&host[i..i + 2], 16).map_err(|_| invalid()))
+            .collect::<io::Result<Vec<u8>>>()?;
+
+        Ok(PathBuf::from(
+            String::from_utf8(bytes).map_err(|_| invalid())?,
+        ))
+    }
+}
+
+impl tower_service::Service<Uri> for UnixConnector {
+    type Response = TokioIo<UnixStream>;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        Box::pin(async move {
+            let path = Self::socket_path(&uri)?;
+            Ok(TokioIo::new(UnixStream::connect(path).await?))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::Bytes;
+    use hyper::{Request, Response};
+    use hyper_util::client::legacy::Client;
+    use hyper_util::rt::TokioExecutor;
+    use std::convert::Infallible;
+    use tokio::net::UnixListener;
+
+    #[test]
+    fn socket_path_round_trip() {
+        let base_path = UnixConnector::base_path("/tmp/api.sock");
+        let uri: Uri = format!("{}/pets?limit=1", base_path).parse().unwrap();
+        assert_eq!(
+            UnixConnector::socket_path(&uri).unwrap(),
+            PathBuf::from("/tmp/api.sock")
+        );
+
+        let uri: Uri = "http://localhost/pets".parse().unwrap();
+        assert!(UnixConnector::socket_path(&uri).is_err());
+    }
+
+    #[tokio::test]
+    async fn sends_request() {
+        let socket_path = std::env::temp_dir().join(format!("swagger-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&socket_path);
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service =
+                hyper::service::service_fn(|req: Request<hyper::body::Incoming>| async move {
+                    Ok::<_, Infallible>(Response::new(Full::new(Bytes::from(
+                        req.uri().to_string(),
+                    ))))
+                });
+            hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+                .unwrap();
+        });
+
+        let client =
+            Client::builder(TokioExecutor::new()).build::<_, Full<Bytes>>(UnixConnector::new());
+        let uri: Uri = format!("{}/pets?limit=1", UnixConnector::base_path(&socket_path))
+            .parse()
+            .unwrap();
+        let response = client.get(uri).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "/pets?limit=1");
+
+        std::fs::remove_file(&socket_path).unwrap();
+    }
+}
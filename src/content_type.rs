@@ -0,0 +1,318 @@
+//! Hyper service checking the `Content-Type` of request bodies.
+//!
+//! `ContentTypeService` rejects requests whose body has a media type the
+//! operation doesn't accept with `415 Unsupported Media Type`, listing the
+//! accepted types in the `Accept` header - rather than letting body
+//! deserialization fail with an obscure error.
+//!
+//! ```rust
+//! # use swagger::content_type::ContentTypes;
+//! # use swagger::MediaType;
+//! let content_types = ContentTypes::new([MediaType::from_static("application/json")])
+//!     .operation("uploadFile", [MediaType::from_static("image/*")]);
+//! ```
+
+use crate::response::json_error;
+use crate::{Has, MediaType, RequestParser, XSpanIdString};
+use futures::future::{BoxFuture, FutureExt};
+use hyper::body::Body;
+use hyper::header::{HeaderValue, ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, TRANSFER_ENCODING};
+use hyper::service::Service;
+use hyper::{Request, Response, StatusCode};
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// The media types accepted in the request body of each operation of an API.
+///
+/// Types may be ranges, such as `image/*`. Parameters of an accepted type
+/// must be present on the request's `Content-Type`, but other parameters,
+/// such as `charset`, are allowed.
+#[derive(Debug, Clone, Default)]
+pub struct ContentTypes {
+    default: Vec<MediaType>,
+    operations: HashMap<&'static str, Vec<MediaType>>,
+}
+
+impl ContentTypes {
+    /// Create a configuration where every operation accepts the given types.
+    pub fn new<I: IntoIterator<Item = MediaType>>(default: I) -> Self {
+        ContentTypes {
+            default: default.into_iter().collect(),
+            operations: HashMap::new(),
+        }
+    }
+
+    /// Set the types accepted by an operation, identified by the ID returned
+    /// by the API's `RequestParser`. Operations given no types aren't checked.
+    pub fn operation<I>(mut self, operation_id: &'static str, types: I) -> Self
+    where
+        I: IntoIterator<Item = MediaType>,
+    {
+        self.operations
+            .insert(operation_id, types.into_iter().collect());
+        self
+    }
+
+    fn accepted(&self, operation_id: Option<&'static str>) -> &[MediaType] {
+        operation_id
+            .and_then(|id| self.operations.get(id))
+            .unwrap_or(&self.default)
+    }
+
+    /// Check a request, returning the accepted types if its body's type
+    /// isn't one of them. Requests without a body are allowed.
+    fn check<RP, B>(&self, req: &Request<B>) -> Result<(), &[MediaType]>
+    where
+        RP: RequestParser<B>,
+        B: Body,
+    {
+        let accepted = self.accepted(RP::parse_operation_id(req));
+        if accepted.is_empty() || !has_body(req) {
+            return Ok(());
+        }
+
+        let content_type = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<MediaType>().ok());
+        match content_type {
+            Some(content_type) if accepted.iter().any(|range| range.includes(&content_type)) => {
+                Ok(())
+            }
+            _ => Err(accepted),
+        }
+    }
+}
+
+/// Whether a request has a non-empty body.
+fn has_body<B: Body>(req: &Request<B>) -> bool {
+    let headers = req.headers();
+    if headers.contains_key(TRANSFER_ENCODING) {
+        return true;
+    }
+    match headers.get(CONTENT_LENGTH) {
+        Some(length) => length.as_bytes() != b"0",
+        None => req.body().size_hint().exact() != Some(0),
+    }
+}
+
+/// Middleware wrapper service which checks the `Content-Type` of request
+/// bodies.
+pub struct ContentTypeMakeService<T, RP> {
+    inner: T,
+    content_types: Arc<ContentTypes>,
+    marker: PhantomData<fn(RP)>,
+}
+
+impl<T, RP> ContentTypeMakeService<T, RP> {
+    /// Create a new ContentTypeMakeService, using `RP` to identify the
+    /// operation of each request.
+    pub fn new(inner: T, content_types: ContentTypes) -> Self {
+        ContentTypeMakeService {
+            inner,
+            content_types: Arc::new(content_types),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, RP> fmt::Debug for ContentTypeMakeService<T, RP>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ContentTypeMakeService")
+            .field("inner", &self.inner)
+            .field("content_types", &self.content_types)
+            .finish()
+    }
+}
+
+impl<Inner, RP, Target> Service<Target> for ContentTypeMakeService<Inner, RP>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = ContentTypeService<Inner::Response, RP>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let content_types = self.content_types.clone();
+        Box::pin(self.inner.call(target).map(|s| {
+            Ok(ContentTypeService {
+                inner: s?,
+                content_types,
+                marker: PhantomData,
+            })
+        }))
+    }
+}
+
+/// Middleware wrapper service which checks the `Content-Type` of request
+/// bodies. Servers will normally want to use `ContentTypeMakeService`, which
+/// will create a `ContentTypeService` for each connection.
+pub struct ContentTypeService<T, RP> {
+    inner: T,
+    content_types: Arc<ContentTypes>,
+    marker: PhantomData<fn(RP)>,
+}
+
+impl<T, RP> ContentTypeService<T, RP> {
+    /// Create a new ContentTypeService, using `RP` to identify the operation
+    /// of each request.
+    pub fn new(inner: T, content_types: ContentTypes) -> Self {
+        ContentTypeService {
+            inner,
+            content_types: Arc::new(content_types),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, RP> Clone for ContentTypeService<T, RP>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        ContentTypeService {
+            inner: self.inner.clone(),
+            content_types: self.content_types.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, RP> fmt::Debug for ContentTypeService<T, RP>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ContentTypeService")
+            .field("inner", &self.inner)
+            .field("content_types", &self.content_types)
+            .finish()
+    }
+}
+
+impl<T, RP, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for ContentTypeService<T, RP>
+where
+    RP: RequestParser<ReqBody>,
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    T::Error: Send + 'static,
+    ReqBody: Body,
+    ResBody: From<String> + Send + 'static,
+    C: Has<XSpanIdString>,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let accepted = match self.content_types.check::<RP, _>(&req) {
+            Ok(()) => return Box::pin(self.inner.call((req, context))),
+            Err(accepted) => accepted,
+        };
+
+        let accepted: Vec<_> = accepted.iter().map(ToString::to_string).collect();
+        let accepted = accepted.join(", ");
+        let message = match req.headers().get(CONTENT_TYPE) {
+            Some(content_type) => format!(
+                "Unsupported Media Type {} - expected one of: {}",
+                String::from_utf8_lossy(content_type.as_bytes()),
+                accepted
+            ),
+            None => format!("Missing Content-Type - expected one of: {}", accepted),
+        };
+
+        let x_span_id = Has::<XSpanIdString>::get(&context);
+        let mut response: Response<ResBody> = json_error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            &message,
+            Some(&x_span_id.0),
+        );
+        if let Ok(accepted) = HeaderValue::from_str(&accepted) {
+            response.headers_mut().insert(ACCEPT, accepted);
+        }
+        Box::pin(futures::future::ok(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContextBuilder, EmptyContext, Push};
+
+    type TestContext = ContextBuilder<XSpanIdString, EmptyContext>;
+
+    struct TestParser;
+
+    impl<B> RequestParser<B> for TestParser {
+        fn parse_operation_id(req: &Request<B>) -> Option<&'static str> {
+            match req.uri().path() {
+                "/upload" => Some("upload"),
+                _ => None,
+            }
+        }
+    }
+
+    struct OkService;
+
+    impl Service<(Request<String>, TestContext)> for OkService {
+        type Response = Response<String>;
+        type Error = ();
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _: (Request<String>, TestContext)) -> Self::Future {
+            Box::pin(futures::future::ok(Response::new("ok".to_string())))
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_unsupported_media_types() {
+        let content_types = ContentTypes::new([MediaType::from_static("application/json")])
+            .operation("upload", [MediaType::from_static("image/*")]);
+        let service = ContentTypeService::<_, TestParser>::new(OkService, content_types);
+        let request = |path, content_type: Option<&str>, body: &str| {
+            let mut request = Request::post(path);
+            if let Some(content_type) = content_type {
+                request = request.header(CONTENT_TYPE, content_type);
+            }
+            (
+                request.body(body.to_string()).unwrap(),
+                EmptyContext.push(XSpanIdString("span".to_string())),
+            )
+        };
+
+        let ok = [
+            ("/pets", Some("application/json; charset=utf-8"), "{}"),
+            ("/pets", None, ""),
+            ("/upload", Some("image/png"), "png"),
+        ];
+        for (path, content_type, body) in ok {
+            let response = service
+                .call(request(path, content_type, body))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", path);
+        }
+
+        let response = service
+            .call(request("/upload", Some("application/json"), "{}"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(response.headers()[ACCEPT], "image/*");
+        assert_eq!(
+            response.body(),
+            r#"{"code":415,"message":"Unsupported Media Type application/json - expected one of: image/*","x-span-id":"span"}"#
+        );
+
+        let response = service.call(request("/pets", None, "{}")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+}
@@ -0,0 +1,417 @@
+//! Cookies - parsing the `Cookie` header of requests, and building the
+//! `Set-Cookie` headers of responses.
+//!
+//! `CookiesService` parses the `Cookie` header of each request into a
+//! `Cookies` entry in the context, for middleware and handlers to read.
+//! `SetCookie` builds `Set-Cookie` headers, as described in RFC 6265.
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! # use hyper::header::HeaderMap;
+//! # use swagger::cookies::{Cookies, SameSite, SetCookie};
+//! let cookies = Cookies::parse("session=abc123; theme=\"dark\"");
+//! assert_eq!(cookies.get("theme"), Some("dark"));
+//!
+//! let mut headers = HeaderMap::new();
+//! SetCookie::new("session", "def456")
+//!     .path("/")
+//!     .max_age(Duration::from_secs(3600))
+//!     .http_only(true)
+//!     .same_site(SameSite::Lax)
+//!     .append_to(&mut headers)
+//!     .unwrap();
+//! assert_eq!(
+//!     headers["set-cookie"],
+//!     "session=def456; Path=/; Max-Age=3600; Secure; HttpOnly; SameSite=Lax"
+//! );
+//! ```
+
+use crate::Push;
+use headers::{Expires, Header};
+use hyper::header::{HeaderMap, HeaderValue, COOKIE, SET_COOKIE};
+use hyper::service::Service;
+use hyper::Request;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::time::{Duration, SystemTime};
+
+/// The cookies sent with a request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cookies(Vec<(String, String)>);
+
+impl Cookies {
+    /// Parse a `Cookie` header. Invalid cookies are skipped.
+    pub fn parse(header: &str) -> Self {
+        let cookies = header
+            .split(';')
+            .filter_map(|cookie| {
+                let (name, value) = cookie.split_once('=')?;
+                let name = name.trim();
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(value);
+                (!name.is_empty()).then(|| (name.to_string(), value.to_string()))
+            })
+            .collect();
+        Cookies(cookies)
+    }
+
+    /// Parse the `Cookie` headers of a request. HTTP/2 clients may send
+    /// several.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let header = headers
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join("; ");
+        Self::parse(&header)
+    }
+
+    /// The value of the first cookie with a name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The cookies, as name and value pairs, in the order sent.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    /// The number of cookies.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether there are no cookies.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// The `SameSite` attribute of a cookie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    /// Only sent with requests from the same site.
+    Strict,
+    /// Also sent when navigating to the site from other sites.
+    Lax,
+    /// Sent with all requests. Such cookies must be `Secure`.
+    None,
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        })
+    }
+}
+
+/// Error returned when a cookie has an invalid name, value or attribute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidCookie(String);
+
+impl fmt::Display for InvalidCookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid cookie: {}", self.0)
+    }
+}
+
+impl Error for InvalidCookie {}
+
+/// Builder for a `Set-Cookie` header.
+///
+/// Cookies are `Secure` unless disabled, as browsers only accept some
+/// attributes, such as `SameSite=None`, on secure cookies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetCookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    expires: Option<SystemTime>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl SetCookie {
+    /// Set a cookie.
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        SetCookie {
+            name: name.into(),
+            value: value.into(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: true,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    /// Remove a cookie, by setting it to expire immediately. The path and
+    /// domain must match those it was set with.
+    pub fn removal(name: impl Into<String>) -> Self {
+        Self::new(name, "")
+            .max_age(Duration::ZERO)
+            .expires(SystemTime::UNIX_EPOCH)
+    }
+
+    /// Only send the cookie with requests for paths under `path`.
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Send the cookie with requests to `domain` and its subdomains.
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// Expire the cookie after `max_age`.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Expire the cookie at a time. `max_age` takes precedence, if set.
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// Only send the cookie over HTTPS. Defaults to `true`.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Hide the cookie from scripts.
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// Set the `SameSite` attribute.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// Build the `Set-Cookie` header value.
+    pub fn to_header_value(&self) -> Result<HeaderValue, InvalidCookie> {
+        let token = |s: &str| {
+            !s.is_empty()
+                && s.bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+        };
+        // cookie-octet from RFC 6265
+        let cookie_value = |s: &str| {
+            s.bytes()
+                .all(|b| b.is_ascii_graphic() && !b"\",;\\".contains(&b))
+        };
+        let attribute = |s: &str| s.bytes().all(|b| b.is_ascii_graphic() && b != b';');
+
+        if !token(&self.name) {
+            return Err(InvalidCookie(format!("invalid name {:?}", self.name)));
+        }
+        if !cookie_value(&self.value) {
+            return Err(InvalidCookie(format!("invalid value for {}", self.name)));
+        }
+        if self.same_site == Some(SameSite::None) && !self.secure {
+            return Err(InvalidCookie(format!(
+                "{} has SameSite=None but isn't Secure",
+                self.name
+            )));
+        }
+
+        let mut cookie = format!("{}={}", self.name, self.value);
+        for (name, value) in [("Path", &self.path), ("Domain", &self.domain)] {
+            if let Some(value) = value {
+                if !attribute(value) {
+                    return Err(InvalidCookie(format!("invalid {} {:?}", name, value)));
+                }
+                cookie.push_str(&format!("; {}={}", name, value));
+            }
+        }
+        if let Some(max_age) = self.max_age {
+            cookie.push_str(&format!("; Max-Age={}", max_age.as_secs()));
+        }
+        if let Some(expires) = self.expires {
+            let mut values = Vec::new();
+            Expires::from(expires).encode(&mut values);
+            if let Some(Ok(expires)) = values.first().map(HeaderValue::to_str) {
+                cookie.push_str(&format!("; Expires={}", expires));
+            }
+        }
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        if self.http_only {
+            cookie.push_str("; HttpOnly");
+        }
+        if let Some(same_site) = self.same_site {
+            cookie.push_str(&format!("; SameSite={}", same_site));
+        }
+
+        HeaderValue::from_str(&cookie).map_err(|e| InvalidCookie(e.to_string()))
+    }
+
+    /// Append the `Set-Cookie` header to response headers.
+    pub fn append_to(&self, headers: &mut HeaderMap) -> Result<(), InvalidCookie> {
+        headers.append(SET_COOKIE, self.to_header_value()?);
+        Ok(())
+    }
+}
+
+/// Middleware wrapper service which parses the `Cookie` header of each
+/// request into `Cookies` in the context.
+#[derive(Debug)]
+pub struct CookiesMakeService<T, C> {
+    inner: T,
+    marker: PhantomData<fn(C)>,
+}
+
+impl<T, C> CookiesMakeService<T, C> {
+    /// Create a new CookiesMakeService.
+    pub fn new(inner: T) -> Self {
+        CookiesMakeService {
+            inner,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<Inner, C, Target> Service<Target> for CookiesMakeService<Inner, C>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = CookiesService<Inner::Response, C>;
+    type Error = Inner::Error;
+    type Future = futures::future::BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        use futures::FutureExt;
+        Box::pin(self.inner.call(target).map(|s| Ok(CookiesService::new(s?))))
+    }
+}
+
+/// Middleware wrapper service which parses the `Cookie` header of each
+/// request into `Cookies` in the context. Servers will normally want to use
+/// `CookiesMakeService`, which will create a `CookiesService` for each
+/// connection.
+#[derive(Debug)]
+pub struct CookiesService<T, C> {
+    inner: T,
+    marker: PhantomData<fn(C)>,
+}
+
+impl<T, C> CookiesService<T, C> {
+    /// Create a new CookiesService.
+    pub fn new(inner: T) -> Self {
+        CookiesService {
+            inner,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Clone, C> Clone for CookiesService<T, C> {
+    fn clone(&self) -> Self {
+        Self::new(self.inner.clone())
+    }
+}
+
+impl<T, C, ReqBody> Service<(Request<ReqBody>, C)> for CookiesService<T, C>
+where
+    C: Push<Cookies>,
+    T: Service<(Request<ReqBody>, C::Result)>,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = T::Future;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let cookies = Cookies::from_headers(req.headers());
+        self.inner.call((req, context.push(cookies)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Has;
+
+    #[test]
+    fn parses_and_sets_cookies() {
+        let cookies = Cookies::parse("a=1; b=\"two\";; =x; c; a=3");
+        assert_eq!(cookies.len(), 3);
+        assert_eq!(cookies.get("a"), Some("1"));
+        assert_eq!(cookies.get("b"), Some("two"));
+        assert_eq!(cookies.get("c"), None);
+
+        let cookie = SetCookie::removal("session")
+            .path("/api")
+            .domain("example.com")
+            .secure(false)
+            .to_header_value()
+            .unwrap();
+        assert_eq!(
+            cookie,
+            "session=; Path=/api; Domain=example.com; Max-Age=0; \
+             Expires=Thu, 01 Jan 1970 00:00:00 GMT"
+        );
+
+        assert!(SetCookie::new("a b", "1").to_header_value().is_err());
+        assert!(SetCookie::new("a", "x;y").to_header_value().is_err());
+        assert!(SetCookie::new("a", "1")
+            .path("/;x")
+            .to_header_value()
+            .is_err());
+        assert!(SetCookie::new("a", "1")
+            .secure(false)
+            .same_site(SameSite::None)
+            .to_header_value()
+            .is_err());
+    }
+
+    struct TestApi;
+
+    impl<C: Has<Cookies>> Service<(Request<()>, C)> for TestApi {
+        type Response = Option<String>;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (_, context): (Request<()>, C)) -> Self::Future {
+            futures::future::ok(context.get().get("session").map(str::to_string))
+        }
+    }
+
+    #[tokio::test]
+    async fn adds_cookies_to_context() {
+        crate::new_context_type!(TestContext, TestEmptyContext, Cookies);
+
+        let service = CookiesService::<_, TestEmptyContext>::new(TestApi);
+        let request = Request::get("/")
+            .header(COOKIE, "theme=dark")
+            .header(COOKIE, "session=abc")
+            .body(())
+            .unwrap();
+        let session = service.call((request, TestEmptyContext)).await.unwrap();
+        assert_eq!(session.as_deref(), Some("abc"));
+    }
+}
@@ -0,0 +1,634 @@
+//! Hyper service implementing Cross-Origin Resource Sharing (CORS), so that
+//! APIs can be called from browsers on other origins.
+//!
+//! `CorsService` answers preflight requests itself, and adds the CORS
+//! headers to the responses to requests from allowed origins. Requests from
+//! other origins are passed on without CORS headers, so browsers won't let
+//! scripts read their responses, and are logged with their X-Span-ID.
+//!
+//! ```rust
+//! # use hyper::header::{HeaderName, CONTENT_TYPE};
+//! # use hyper::Method;
+//! # use std::time::Duration;
+//! # use swagger::cors::Cors;
+//! let cors = Cors::new()
+//!     .allow_origin("https://app.example.com")
+//!     .allow_origin("https://*.preview.example.com")
+//!     .allow_methods([Method::GET, Method::POST, Method::DELETE])
+//!     .allow_headers([CONTENT_TYPE, HeaderName::from_static("x-api-key")])
+//!     .allow_credentials(true)
+//!     .max_age(Duration::from_secs(600));
+//! ```
+
+use crate::response::coded_json_error;
+use crate::routes::Routes;
+use crate::{Has, XSpanIdString};
+use futures::future::{BoxFuture, FutureExt};
+use hyper::header::{
+    HeaderName, HeaderValue, ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+    ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS,
+    ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN,
+    VARY,
+};
+use hyper::service::Service;
+use hyper::{HeaderMap, Method, Request, Response, StatusCode};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+const LOG_TARGET: &str = "swagger::cors";
+
+/// An allowed origin.
+#[derive(Clone)]
+enum Origin {
+    /// An exact origin, such as `https://example.com`.
+    Exact(String),
+    /// A pattern with a single `*`, matching one or more characters.
+    Wildcard(String, String),
+    /// Origins accepted by a function.
+    Predicate(Arc<dyn Fn(&str) -> bool + Send + Sync>),
+}
+
+impl Origin {
+    fn matches(&self, origin: &str) -> bool {
+        match self {
+            Origin::Exact(allowed) => allowed.eq_ignore_ascii_case(origin),
+            Origin::Wildcard(prefix, suffix) => {
+                let origin = origin.to_ascii_lowercase();
+                origin.len() > prefix.len() + suffix.len()
+                    && origin.starts_with(prefix.as_str())
+                    && origin.ends_with(suffix.as_str())
+            }
+            Origin::Predicate(predicate) => predicate(origin),
+        }
+    }
+}
+
+impl fmt::Debug for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Exact(origin) => origin.fmt(f),
+            Origin::Wildcard(prefix, suffix) => write!(f, "\"{}*{}\"", prefix, suffix),
+            Origin::Predicate(_) => f.write_str("<predicate>"),
+        }
+    }
+}
+
+/// CORS configuration.
+///
+/// By default no origins are allowed; `GET`, `HEAD` and `POST` are the
+/// allowed methods, and no headers beyond the CORS-safelisted ones are
+/// allowed or exposed.
+#[derive(Debug, Clone)]
+pub struct Cors {
+    any_origin: bool,
+    origins: Vec<Origin>,
+    methods: Vec<Method>,
+    any_header: bool,
+    headers: Vec<HeaderName>,
+    expose_headers: Vec<HeaderName>,
+    credentials: bool,
+    max_age: Option<Duration>,
+    routes: Option<Routes>,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Cors {
+            any_origin: false,
+            origins: Vec::new(),
+            methods: vec![Method::GET, Method::HEAD, Method::POST],
+            any_header: false,
+            headers: Vec::new(),
+            expose_headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+            routes: None,
+        }
+    }
+}
+
+impl Cors {
+    /// Create a configuration which allows no origins.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow an origin, such as `https://example.com`. The origin may
+    /// contain a `*`, matching one or more characters - for example
+    /// `https://*.example.com` allows any subdomain of `example.com`.
+    pub fn allow_origin(mut self, origin: &str) -> Self {
+        let origin = origin.trim_end_matches('/');
+        self.origins.push(match origin.split_once('*') {
+            Some((prefix, suffix)) => {
+                Origin::Wildcard(prefix.to_ascii_lowercase(), suffix.to_ascii_lowercase())
+            }
+            None => Origin::Exact(origin.to_string()),
+        });
+        self
+    }
+
+    /// Allow the origins for which `predicate` returns `true`.
+    pub fn allow_origin_fn<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.origins.push(Origin::Predicate(Arc::new(predicate)));
+        self
+    }
+
+    /// Allow every origin, using `Access-Control-Allow-Origin: *`. This can't
+    /// be combined with allowing credentials.
+    pub fn allow_any_origin(mut self) -> Self {
+        self.any_origin = true;
+        self
+    }
+
+    /// Set the methods which may be used.
+    pub fn allow_methods<I: IntoIterator<Item = Method>>(mut self, methods: I) -> Self {
+        self.methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Set the request headers which may be sent.
+    pub fn allow_headers<I: IntoIterator<Item = HeaderName>>(mut self, headers: I) -> Self {
+        self.headers = headers.into_iter().collect();
+        self
+    }
+
+    /// Allow any request headers to be sent.
+    pub fn allow_any_header(mut self) -> Self {
+        self.any_header = true;
+        self
+    }
+
+    /// Set the response headers which scripts may read.
+    pub fn expose_headers<I: IntoIterator<Item = HeaderName>>(mut self, headers: I) -> Self {
+        self.expose_headers = headers.into_iter().collect();
+        self
+    }
+
+    /// Set whether requests may include credentials, such as cookies. This
+    /// can't be combined with allowing every origin.
+    pub fn allow_credentials(mut self, credentials: bool) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Set how long browsers may cache the results of preflight requests.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Use a route table to only allow the methods of the requested path
+    /// in preflight requests. Preflight requests for unknown paths are
+    /// passed on to the wrapped service.
+    pub fn routes(mut self, routes: Routes) -> Self {
+        self.routes = Some(routes);
+        self
+    }
+
+    /// The methods allowed for a path, or `None` if it's not a known route.
+    fn methods(&self, path: &str) -> Option<Vec<&Method>> {
+        let routes = match &self.routes {
+            Some(routes) => routes.allowed(path)?,
+            None => return Some(self.methods.iter().collect()),
+        };
+        Some(
+            self.methods
+                .iter()
+                .filter(|method| routes.contains(method))
+                .collect(),
+        )
+    }
+
+    /// Check the configuration is one which can be used safely.
+    fn validate(self) -> Self {
+        // Reflecting any origin with credentials allowed would let every
+        // website make credentialed requests, turning CORS off entirely
+        assert!(
+            !(self.any_origin && self.credentials),
+            "Invalid CORS configuration: credentials can't be allowed for any origin"
+        );
+        self
+    }
+
+    fn allows_origin(&self, origin: &str) -> bool {
+        self.any_origin || self.origins.iter().any(|allowed| allowed.matches(origin))
+    }
+
+    /// Check the method and headers requested by a preflight request,
+    /// returning what's not allowed.
+    fn check_preflight(&self, methods: &[&Method], headers: &HeaderMap) -> Result<(), String> {
+        let method = headers
+            .get(ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|method| Method::from_bytes(method.as_bytes()).ok());
+        match method {
+            Some(method) if methods.contains(&&method) => {}
+            Some(method) => return Err(format!("method {}", method)),
+            None => return Err("invalid method".to_string()),
+        }
+
+        if self.any_header {
+            return Ok(());
+        }
+        for name in requested_headers(headers) {
+            if !self.headers.iter().any(|allowed| allowed == name) {
+                return Err(format!("header {}", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Add `Vary: Origin` to a response, unless responses are the same for
+    /// every origin. This is needed whether or not the origin is allowed, so
+    /// that caches don't serve one origin's response to another.
+    fn add_vary(&self, headers: &mut HeaderMap) {
+        if !self.any_origin {
+            headers.append(VARY, HeaderValue::from_static("Origin"));
+        }
+    }
+
+    /// Add the headers common to preflight and actual responses.
+    fn add_origin_headers(&self, headers: &mut HeaderMap, origin: &HeaderValue) {
+        if self.any_origin {
+            headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+        } else {
+            headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+        }
+        if self.credentials {
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    }
+
+    fn preflight_headers(&self, methods: &[&Method], headers: &mut HeaderMap, request: &HeaderMap) {
+        if let Some(value) = join(methods.iter().map(|method| method.as_str())) {
+            headers.insert(ACCESS_CONTROL_ALLOW_METHODS, value);
+        }
+        let allowed = if self.any_header {
+            join(requested_headers(request))
+        } else {
+            join(self.headers.iter().map(HeaderName::as_str))
+        };
+        if let Some(value) = allowed {
+            headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, value);
+        }
+        if let Some(max_age) = self.max_age {
+            headers.insert(ACCESS_CONTROL_MAX_AGE, max_age.as_secs().into());
+        }
+        headers.append(
+            VARY,
+            HeaderValue::from_static(
+                "Access-Control-Request-Method, Access-Control-Request-Headers",
+            ),
+        );
+    }
+}
+
+/// The names in a request's `Access-Control-Request-Headers`.
+fn requested_headers(headers: &HeaderMap) -> impl Iterator<Item = &str> {
+    headers
+        .get_all(ACCESS_CONTROL_REQUEST_HEADERS)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+}
+
+/// Join values into a comma separated header, if there are any.
+fn join<'a>(values: impl Iterator<Item = &'a str>) -> Option<HeaderValue> {
+    let values: Vec<_> = values.collect();
+    if values.is_empty() {
+        return None;
+    }
+    HeaderValue::from_str(&values.join(", ")).ok()
+}
+
+/// Middleware wrapper service which implements CORS.
+#[derive(Debug)]
+pub struct CorsMakeService<T> {
+    inner: T,
+    cors: Arc<Cors>,
+}
+
+impl<T> CorsMakeService<T> {
+    /// Create a new CorsMakeService.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cors` allows credentials for any origin.
+    pub fn new(inner: T, cors: Cors) -> Self {
+        CorsMakeService {
+            inner,
+            cors: Arc::new(cors.validate()),
+        }
+    }
+}
+
+impl<Inner, Target> Service<Target> for CorsMakeService<Inner>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = CorsService<Inner::Response>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let cors = self.cors.clone();
+        Box::pin(
+            self.inner
+                .call(target)
+                .map(|s| Ok(CorsService { inner: s?, cors })),
+        )
+    }
+}
+
+/// Middleware wrapper service which implements CORS. Servers will normally
+/// want to use `CorsMakeService`, which will create a `CorsService` for each
+/// connection.
+#[derive(Debug, Clone)]
+pub struct CorsService<T> {
+    inner: T,
+    cors: Arc<Cors>,
+}
+
+impl<T> CorsService<T> {
+    /// Create a new CorsService.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cors` allows credentials for any origin.
+    pub fn new(inner: T, cors: Cors) -> Self {
+        CorsService {
+            inner,
+            cors: Arc::new(cors.validate()),
+        }
+    }
+}
+
+impl<T> CorsService<T> {
+    /// Pass a request without CORS headers to the wrapped service.
+    fn call_inner<ReqBody, ResBody, C>(
+        &self,
+        req: Request<ReqBody>,
+        context: C,
+    ) -> BoxFuture<'static, Result<Response<ResBody>, T::Error>>
+    where
+        T: Service<(Request<ReqBody>, C), Response = Response<ResBody>>,
+        T::Future: Send + 'static,
+    {
+        let cors = self.cors.clone();
+        Box::pin(self.inner.call((req, context)).map(move |response| {
+            let mut response = response?;
+            cors.add_vary(response.headers_mut());
+            Ok(response)
+        }))
+    }
+}
+
+impl<T, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for CorsService<T>
+where
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    T::Error: Send + 'static,
+    ResBody: From<String> + Send + 'static,
+    C: Has<XSpanIdString>,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let Some(origin) = req.headers().get(ORIGIN).cloned() else {
+            return self.call_inner(req, context);
+        };
+        let preflight = req.method() == Method::OPTIONS
+            && req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD);
+        let x_span_id = Has::<XSpanIdString>::get(&context).0.clone();
+        let origin_text = String::from_utf8_lossy(origin.as_bytes());
+
+        if !self.cors.allows_origin(&origin_text) {
+            log::info!(
+                target: LOG_TARGET,
+                "Denied CORS request from origin {} - X-Span-ID: {}",
+                origin_text,
+                x_span_id
+            );
+            if preflight {
+                let message = format!("Origin {} is not allowed", origin_text);
+                let mut response = coded_json_error(
+                    StatusCode::FORBIDDEN,
+                    "origin_not_allowed",
+                    &message,
+                    Some(&x_span_id),
+                );
+                self.cors.add_vary(response.headers_mut());
+                return Box::pin(futures::future::ok(response));
+            }
+            return self.call_inner(req, context);
+        }
+
+        if preflight {
+            let Some(methods) = self.cors.methods(req.uri().path()) else {
+                return self.call_inner(req, context);
+            };
+            if let Err(denied) = self.cors.check_preflight(&methods, req.headers()) {
+                log::info!(
+                    target: LOG_TARGET,
+                    "Denied CORS preflight from origin {} for {} - X-Span-ID: {}",
+                    origin_text,
+                    denied,
+                    x_span_id
+                );
+                let message = format!("CORS request for {} is not allowed", denied);
+                let mut response = coded_json_error(
+                    StatusCode::FORBIDDEN,
+                    "cors_request_not_allowed",
+                    &message,
+                    Some(&x_span_id),
+                );
+                self.cors.add_vary(response.headers_mut());
+                return Box::pin(futures::future::ok(response));
+            }
+
+            let mut response = Response::new(ResBody::from(String::new()));
+            *response.status_mut() = StatusCode::NO_CONTENT;
+            self.cors.add_vary(response.headers_mut());
+            self.cors
+                .add_origin_headers(response.headers_mut(), &origin);
+            self.cors
+                .preflight_headers(&methods, response.headers_mut(), req.headers());
+            return Box::pin(futures::future::ok(response));
+        }
+
+        let cors = self.cors.clone();
+        Box::pin(self.inner.call((req, context)).map(move |response| {
+            let mut response = response?;
+            let headers = response.headers_mut();
+            cors.add_vary(headers);
+            cors.add_origin_headers(headers, &origin);
+            if let Some(value) = join(cors.expose_headers.iter().map(HeaderName::as_str)) {
+                headers.insert(ACCESS_CONTROL_EXPOSE_HEADERS, value);
+            }
+            Ok(response)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContextBuilder, EmptyContext, Push};
+    use hyper::header::CONTENT_TYPE;
+
+    type TestContext = ContextBuilder<XSpanIdString, EmptyContext>;
+
+    struct OkService;
+
+    impl Service<(Request<String>, TestContext)> for OkService {
+        type Response = Response<String>;
+        type Error = ();
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _: (Request<String>, TestContext)) -> Self::Future {
+            Box::pin(futures::future::ok(Response::new("ok".to_string())))
+        }
+    }
+
+    fn request(
+        method: Method,
+        origin: &str,
+        headers: &[(&str, &str)],
+    ) -> (Request<String>, TestContext) {
+        let mut request = Request::builder()
+            .method(method)
+            .uri("/pets")
+            .header(ORIGIN, origin);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        (
+            request.body(String::new()).unwrap(),
+            EmptyContext.push(XSpanIdString("span".to_string())),
+        )
+    }
+
+    #[tokio::test]
+    async fn handles_cors_requests() {
+        let cors = Cors::new()
+            .allow_origin("https://app.example.com")
+            .allow_origin("https://*.example.org")
+            .allow_methods([Method::GET, Method::PUT])
+            .allow_headers([CONTENT_TYPE])
+            .expose_headers([HeaderName::from_static("x-total")])
+            .allow_credentials(true)
+            .max_age(Duration::from_secs(60));
+        let service = CorsService::new(OkService, cors);
+
+        let response = service
+            .call(request(
+                Method::OPTIONS,
+                "https://a.example.org",
+                &[
+                    ("access-control-request-method", "PUT"),
+                    ("access-control-request-headers", "Content-Type"),
+                ],
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(
+            headers[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://a.example.org"
+        );
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_METHODS], "GET, PUT");
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_HEADERS], "content-type");
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[ACCESS_CONTROL_MAX_AGE], "60");
+
+        let response = service
+            .call(request(
+                Method::OPTIONS,
+                "https://app.example.com",
+                &[
+                    ("access-control-request-method", "PUT"),
+                    ("access-control-request-headers", "x-other"),
+                ],
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = service
+            .call(request(Method::GET, "https://app.example.com", &[]))
+            .await
+            .unwrap();
+        assert_eq!(response.body(), "ok");
+        let headers = response.headers();
+        assert_eq!(
+            headers[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert_eq!(headers[ACCESS_CONTROL_EXPOSE_HEADERS], "x-total");
+        assert_eq!(headers[VARY], "Origin");
+
+        // Other origins get a response without CORS headers
+        for origin in ["https://evil.com", "https://example.org"] {
+            let response = service
+                .call(request(Method::GET, origin, &[]))
+                .await
+                .unwrap();
+            assert_eq!(response.body(), "ok");
+            assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+            assert_eq!(response.headers()[VARY], "Origin");
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "credentials can't be allowed for any origin")]
+    fn rejects_credentials_for_any_origin() {
+        let cors = Cors::new().allow_any_origin().allow_credentials(true);
+        CorsService::new(OkService, cors);
+    }
+
+    #[tokio::test]
+    async fn limits_preflights_to_routes() {
+        let cors = Cors::new()
+            .allow_any_origin()
+            .allow_methods([Method::GET, Method::PUT])
+            .routes(Routes::new().route("/pets", [Method::GET, Method::POST]));
+        let service = CorsService::new(OkService, cors);
+        let preflight = |method| {
+            request(
+                Method::OPTIONS,
+                "https://any.example.com",
+                &[("access-control-request-method", method)],
+            )
+        };
+
+        let response = service.call(preflight("GET")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_METHODS], "GET");
+        assert!(!response
+            .headers()
+            .get_all(VARY)
+            .iter()
+            .any(|value| value == "Origin"));
+
+        let response = service.call(preflight("PUT")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let (mut req, context) = preflight("GET");
+        *req.uri_mut() = "/owners".parse().unwrap();
+        let response = service.call((req, context)).await.unwrap();
+        assert_eq!(response.body(), "ok");
+    }
+}
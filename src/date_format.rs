@@ -0,0 +1,499 @@
+//! Dates and date-times, as described by `format: date` and
+//! `format: date-time`, using either `chrono` or `time`.
+//!
+//! `DateFormat` and `DateTimeFormat` convert to and from RFC 3339 strings,
+//! and are implemented for the types of whichever of the **chrono** and
+//! **time** features are enabled. The serde helpers in this module work with
+//! any of them, so generated models can use either crate:
+//!
+//! ```
+//! # use serde::{Deserialize, Serialize};
+//! #[derive(Deserialize, Serialize)]
+//! struct Pet {
+//!     #[serde(with = "swagger::date_format::date")]
+//!     born: chrono::NaiveDate,
+//!     #[serde(with = "swagger::date_format::lenient_date_time")]
+//!     updated: chrono::DateTime<chrono::Utc>,
+//! }
+//!
+//! let pet: Pet = serde_json::from_str(r#"{"born":"2020-02-29","updated":"2024-01-02 03:04:05.5"}"#).unwrap();
+//! assert_eq!(
+//!     serde_json::to_string(&pet).unwrap(),
+//!     r#"{"born":"2020-02-29","updated":"2024-01-02T03:04:05.500Z"}"#
+//! );
+//! ```
+//!
+//! Date-times are written with the offset they hold - `Z` for UTC - and a
+//! fraction of a second only if they have one.
+use std::fmt;
+
+/// Options for parsing date-times which aren't valid RFC 3339.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    assume_utc: bool,
+    truncate_fraction: bool,
+}
+
+impl ParseOptions {
+    /// Accept only RFC 3339 date-times, with at most nanosecond precision.
+    pub fn strict() -> Self {
+        ParseOptions::default()
+    }
+
+    /// Also accept date-times without an offset, assumed to be UTC,
+    /// separated from the date by a space rather than `T`, and with more
+    /// precision than nanoseconds, which is truncated.
+    pub fn lenient() -> Self {
+        ParseOptions {
+            assume_utc: true,
+            truncate_fraction: true,
+        }
+    }
+
+    /// Accept date-times without an offset, or separated by a space,
+    /// assuming they are UTC.
+    pub fn assume_utc(mut self, assume_utc: bool) -> Self {
+        self.assume_utc = assume_utc;
+        self
+    }
+
+    /// Accept fractions of a second more precise than nanoseconds,
+    /// truncating them.
+    pub fn truncate_fraction(mut self, truncate_fraction: bool) -> Self {
+        self.truncate_fraction = truncate_fraction;
+        self
+    }
+}
+
+/// Error returned when a date or date-time can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidDate(String);
+
+impl fmt::Display for InvalidDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid date: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidDate {}
+
+/// Types representing a `format: date` value - a calendar date.
+pub trait DateFormat: Sized {
+    /// Parse a date of the form `2024-01-31`.
+    fn parse_date(s: &str) -> Result<Self, InvalidDate>;
+
+    /// Format as a date of the form `2024-01-31`.
+    fn format_date(&self) -> String;
+}
+
+/// Types representing a `format: date-time` value - an instant, with an
+/// offset from UTC.
+pub trait DateTimeFormat: Sized {
+    /// Parse an RFC 3339 date-time, such as `2024-01-31T12:00:00+01:00`.
+    fn parse_date_time(s: &str, options: ParseOptions) -> Result<Self, InvalidDate>;
+
+    /// Format as an RFC 3339 date-time.
+    fn format_date_time(&self) -> String;
+}
+
+/// The components of a date-time.
+#[derive(Debug, Default, PartialEq)]
+struct Parts {
+    year: i32,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+    nanosecond: u32,
+    /// Offset from UTC in seconds.
+    offset: i32,
+}
+
+/// Parse a fixed number of ASCII digits from the start of `s`.
+fn digits(s: &mut &[u8], n: usize) -> Option<u32> {
+    if s.len() < n || !s[..n].iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let value = s[..n]
+        .iter()
+        .fold(0, |value, digit| value * 10 + u32::from(digit - b'0'));
+    *s = &s[n..];
+    Some(value)
+}
+
+/// Consume `c` from the start of `s`.
+fn expect(s: &mut &[u8], c: &[u8]) -> Option<()> {
+    let (first, rest) = s.split_first()?;
+    c.contains(first).then(|| *s = rest)
+}
+
+fn parse_ymd(s: &mut &[u8]) -> Option<(i32, u8, u8)> {
+    let year = digits(s, 4)?;
+    expect(s, b"-")?;
+    let month = digits(s, 2)?;
+    expect(s, b"-")?;
+    let day = digits(s, 2)?;
+    Some((year as i32, month as u8, day as u8))
+}
+
+fn parse_parts(s: &str, options: ParseOptions) -> Option<Parts> {
+    let mut s = s.as_bytes();
+    let (year, month, day) = parse_ymd(&mut s)?;
+    let separators: &[u8] = if options.assume_utc { b"Tt " } else { b"Tt" };
+    expect(&mut s, separators)?;
+    let hour = digits(&mut s, 2)? as u8;
+    expect(&mut s, b":")?;
+    let minute = digits(&mut s, 2)? as u8;
+    expect(&mut s, b":")?;
+    let second = digits(&mut s, 2)? as u8;
+
+    let mut nanosecond = 0;
+    if expect(&mut s, b".").is_some() {
+        let len = s.iter().take_while(|c| c.is_ascii_digit()).count();
+        if len == 0 || (len > 9 && !options.truncate_fraction) {
+            return None;
+        }
+        let (fraction, rest) = s.split_at(len);
+        for i in 0..9 {
+            let digit = fraction.get(i).map_or(0, |digit| u32::from(digit - b'0'));
+            nanosecond = nanosecond * 10 + digit;
+        }
+        s = rest;
+    }
+
+    let offset = match s.first() {
+        Some(b'Z' | b'z') => {
+            s = &s[1..];
+            0
+        }
+        Some(sign @ (b'+' | b'-')) => {
+            let sign = if *sign == b'+' { 1 } else { -1 };
+            s = &s[1..];
+            let hours = digits(&mut s, 2)? as i32;
+            expect(&mut s, b":")?;
+            let minutes = digits(&mut s, 2)? as i32;
+            sign * (hours * 3600 + minutes * 60)
+        }
+        None if options.assume_utc => 0,
+        _ => return None,
+    };
+
+    s.is_empty().then_some(Parts {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+        nanosecond,
+        offset,
+    })
+}
+
+fn format_parts(parts: Parts) -> String {
+    let mut s = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        parts.year, parts.month, parts.day, parts.hour, parts.minute, parts.second
+    );
+    match parts.nanosecond {
+        0 => {}
+        n if n % 1_000_000 == 0 => s += &format!(".{:03}", n / 1_000_000),
+        n if n % 1_000 == 0 => s += &format!(".{:06}", n / 1_000),
+        n => s += &format!(".{:09}", n),
+    }
+    match parts.offset {
+        0 => s.push('Z'),
+        offset => {
+            let sign = if offset < 0 { '-' } else { '+' };
+            let offset = offset.unsigned_abs() / 60;
+            s += &format!("{}{:02}:{:02}", sign, offset / 60, offset % 60);
+        }
+    }
+    s
+}
+
+fn invalid(s: &str) -> InvalidDate {
+    InvalidDate(s.to_string())
+}
+
+#[cfg(feature = "chrono")]
+mod chrono_impl {
+    use super::*;
+    use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, Offset, TimeZone, Timelike, Utc};
+
+    impl DateFormat for NaiveDate {
+        fn parse_date(s: &str) -> Result<Self, InvalidDate> {
+            let mut bytes = s.as_bytes();
+            parse_ymd(&mut bytes)
+                .filter(|_| bytes.is_empty())
+                .and_then(|(year, month, day)| {
+                    NaiveDate::from_ymd_opt(year, month.into(), day.into())
+                })
+                .ok_or_else(|| invalid(s))
+        }
+
+        fn format_date(&self) -> String {
+            format!("{:04}-{:02}-{:02}", self.year(), self.month(), self.day())
+        }
+    }
+
+    fn parse(s: &str, options: ParseOptions) -> Result<DateTime<FixedOffset>, InvalidDate> {
+        let parts = parse_parts(s, options).ok_or_else(|| invalid(s))?;
+        let date = NaiveDate::from_ymd_opt(parts.year, parts.month.into(), parts.day.into());
+        let date_time = date.and_then(|date| {
+            date.and_hms_nano_opt(
+                parts.hour.into(),
+                parts.minute.into(),
+                parts.second.into(),
+                parts.nanosecond,
+            )
+        });
+        FixedOffset::east_opt(parts.offset)
+            .zip(date_time)
+            .and_then(|(offset, date_time)| offset.from_local_datetime(&date_time).single())
+            .ok_or_else(|| invalid(s))
+    }
+
+    fn format<Tz: TimeZone>(date_time: &DateTime<Tz>) -> String {
+        let local = date_time.naive_local();
+        format_parts(Parts {
+            year: local.year(),
+            month: local.month() as u8,
+            day: local.day() as u8,
+            hour: local.hour() as u8,
+            minute: local.minute() as u8,
+            second: local.second() as u8,
+            nanosecond: local.nanosecond(),
+            offset: date_time.offset().fix().local_minus_utc(),
+        })
+    }
+
+    impl DateTimeFormat for DateTime<FixedOffset> {
+        fn parse_date_time(s: &str, options: ParseOptions) -> Result<Self, InvalidDate> {
+            parse(s, options)
+        }
+
+        fn format_date_time(&self) -> String {
+            format(self)
+        }
+    }
+
+    impl DateTimeFormat for DateTime<Utc> {
+        fn parse_date_time(s: &str, options: ParseOptions) -> Result<Self, InvalidDate> {
+            parse(s, options).map(|date_time| date_time.with_timezone(&Utc))
+        }
+
+        fn format_date_time(&self) -> String {
+            format(self)
+        }
+    }
+}
+
+#[cfg(feature = "time")]
+mod time_impl {
+    use super::*;
+    use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+
+    fn date(year: i32, month: u8, day: u8) -> Option<Date> {
+        let month = Month::try_from(month).ok()?;
+        Date::from_calendar_date(year, month, day).ok()
+    }
+
+    impl DateFormat for Date {
+        fn parse_date(s: &str) -> Result<Self, InvalidDate> {
+            let mut bytes = s.as_bytes();
+            parse_ymd(&mut bytes)
+                .filter(|_| bytes.is_empty())
+                .and_then(|(year, month, day)| date(year, month, day))
+                .ok_or_else(|| invalid(s))
+        }
+
+        fn format_date(&self) -> String {
+            format!(
+                "{:04}-{:02}-{:02}",
+                self.year(),
+                u8::from(self.month()),
+                self.day()
+            )
+        }
+    }
+
+    impl DateTimeFormat for OffsetDateTime {
+        fn parse_date_time(s: &str, options: ParseOptions) -> Result<Self, InvalidDate> {
+            let parts = parse_parts(s, options).ok_or_else(|| invalid(s))?;
+            let date = date(parts.year, parts.month, parts.day);
+            let time =
+                Time::from_hms_nano(parts.hour, parts.minute, parts.second, parts.nanosecond).ok();
+            let offset = UtcOffset::from_whole_seconds(parts.offset).ok();
+            match (date, time, offset) {
+                (Some(date), Some(time), Some(offset)) => {
+                    Ok(PrimitiveDateTime::new(date, time).assume_offset(offset))
+                }
+                _ => Err(invalid(s)),
+            }
+        }
+
+        fn format_date_time(&self) -> String {
+            format_parts(Parts {
+                year: self.year(),
+                month: self.month().into(),
+                day: self.day(),
+                hour: self.hour(),
+                minute: self.minute(),
+                second: self.second(),
+                nanosecond: self.nanosecond(),
+                offset: self.offset().whole_seconds(),
+            })
+        }
+    }
+}
+
+/// Define serde helpers for a type and its `Option`, using the given
+/// functions to parse and format values.
+#[cfg(feature = "serdejson")]
+macro_rules! serde_helpers {
+    ($name:ident, $option:ident, $what:literal, $trait:ident, $parse:expr, $format:expr) => {
+        #[doc = concat!("Serde helpers for ", $what, ", for use with `#[serde(with = \"swagger::date_format::", stringify!($name), "\")]`.")]
+        pub mod $name {
+            use super::*;
+            use serde::{Deserialize, Deserializer, Serializer};
+
+            #[doc = concat!("Serialize ", $what, ".")]
+            pub fn serialize<T: $trait, S: Serializer>(
+                value: &T,
+                serializer: S,
+            ) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&$format(value))
+            }
+
+            #[doc = concat!("Deserialize ", $what, ".")]
+            pub fn deserialize<'de, T: $trait, D: Deserializer<'de>>(
+                deserializer: D,
+            ) -> Result<T, D::Error> {
+                let s = std::borrow::Cow::<str>::deserialize(deserializer)?;
+                $parse(&s).map_err(serde::de::Error::custom)
+            }
+        }
+
+        #[doc = concat!("Serde helpers for optional ", $what, ", for use with `#[serde(with = \"swagger::date_format::", stringify!($option), "\", default)]`.")]
+        pub mod $option {
+            use super::*;
+            use serde::{Deserialize, Deserializer, Serializer};
+
+            #[doc = concat!("Serialize optional ", $what, ".")]
+            pub fn serialize<T: $trait, S: Serializer>(
+                value: &Option<T>,
+                serializer: S,
+            ) -> Result<S::Ok, S::Error> {
+                match value {
+                    Some(value) => serializer.serialize_str(&$format(value)),
+                    None => serializer.serialize_none(),
+                }
+            }
+
+            #[doc = concat!("Deserialize optional ", $what, ".")]
+            pub fn deserialize<'de, T: $trait, D: Deserializer<'de>>(
+                deserializer: D,
+            ) -> Result<Option<T>, D::Error> {
+                Option::<std::borrow::Cow<str>>::deserialize(deserializer)?
+                    .map(|s| $parse(&s).map_err(serde::de::Error::custom))
+                    .transpose()
+            }
+        }
+    };
+}
+
+#[cfg(feature = "serdejson")]
+serde_helpers!(
+    date,
+    option_date,
+    "dates",
+    DateFormat,
+    T::parse_date,
+    T::format_date
+);
+#[cfg(feature = "serdejson")]
+serde_helpers!(
+    date_time,
+    option_date_time,
+    "RFC 3339 date-times",
+    DateTimeFormat,
+    |s| T::parse_date_time(s, ParseOptions::strict()),
+    T::format_date_time
+);
+#[cfg(feature = "serdejson")]
+serde_helpers!(
+    lenient_date_time,
+    option_lenient_date_time,
+    "date-times, parsed with `ParseOptions::lenient`",
+    DateTimeFormat,
+    |s| T::parse_date_time(s, ParseOptions::lenient()),
+    T::format_date_time
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_date_times() {
+        let parts = parse_parts(
+            "2024-01-02T03:04:05.123456789123-01:30",
+            ParseOptions::lenient(),
+        );
+        assert_eq!(
+            parts,
+            Some(Parts {
+                year: 2024,
+                month: 1,
+                day: 2,
+                hour: 3,
+                minute: 4,
+                second: 5,
+                nanosecond: 123_456_789,
+                offset: -5400,
+            })
+        );
+        assert_eq!(
+            format_parts(parts.unwrap()),
+            "2024-01-02T03:04:05.123456789-01:30"
+        );
+
+        let strict = ParseOptions::strict();
+        assert!(parse_parts("2024-01-02T03:04:05.1234567891Z", strict).is_none());
+        assert!(parse_parts("2024-01-02 03:04:05Z", strict).is_none());
+        assert!(parse_parts("2024-01-02T03:04:05", strict).is_none());
+        assert!(parse_parts("2024-01-02T03:04:05", strict.assume_utc(true)).is_some());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn converts_chrono() {
+        use chrono::{DateTime, FixedOffset, NaiveDate};
+        let date_time = DateTime::<FixedOffset>::parse_date_time(
+            "2024-02-29T23:00:00.5+05:45",
+            ParseOptions::strict(),
+        )
+        .unwrap();
+        assert_eq!(
+            date_time.format_date_time(),
+            "2024-02-29T23:00:00.500+05:45"
+        );
+        assert!(NaiveDate::parse_date("2023-02-29").is_err());
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn converts_time() {
+        use time::{Date, OffsetDateTime};
+        let date_time =
+            OffsetDateTime::parse_date_time("2024-02-29T23:00:00.000001Z", ParseOptions::strict())
+                .unwrap();
+        assert_eq!(date_time.format_date_time(), "2024-02-29T23:00:00.000001Z");
+        assert_eq!(
+            Date::parse_date("0999-12-31").unwrap().format_date(),
+            "0999-12-31"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Client middleware decompressing response bodies.
+//!
+//! `DecompressionService` advertises the content codings supported by this
+//! build in the `Accept-Encoding` header, and decompresses response bodies as
+//! they are streamed, so generated deserialization code only ever sees the
+//! uncompressed body.
+//!
+//! Requests which already have an `Accept-Encoding` header are left alone,
+//! though their responses are still decompressed if a supported coding is
+//! used.
+
+use crate::compression::{Coding, CodingBody};
+use futures::future::{BoxFuture, TryFutureExt};
+use hyper::header::{HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH};
+use hyper::service::Service;
+use hyper::{Request, Response};
+
+/// Client middleware decompressing response bodies.
+///
+/// The services it wraps see the compressed response body, so put it outside
+/// `client_logging::LoggingService` if logged bodies should be readable.
+#[derive(Debug, Clone)]
+pub struct DecompressionService<T> {
+    inner: T,
+    accept_encoding: HeaderValue,
+}
+
+impl<T> DecompressionService<T> {
+    /// Create a new DecompressionService, accepting all codings supported by
+    /// this build.
+    pub fn new(inner: T) -> Self {
+        DecompressionService {
+            inner,
+            accept_encoding: Coding::accept_encoding(Coding::SUPPORTED),
+        }
+    }
+
+    /// Only accept the given codings, in order of preference.
+    pub fn accept(mut self, codings: &[Coding]) -> Self {
+        self.accept_encoding = Coding::accept_encoding(codings);
+        self
+    }
+}
+
+/// Decompress a response, if it uses a supported coding.
+fn decompress<B>(response: Response<B>) -> Response<CodingBody<B>> {
+    let (mut parts, body) = response.into_parts();
+
+    // Only a single coding is supported - anything else is passed through
+    // for the caller to deal with.
+    let coding = parts
+        .headers
+        .get(CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .and_then(Coding::from_name);
+
+    let body = match coding {
+        Some(coding) => {
+            parts.headers.remove(CONTENT_ENCODING);
+            parts.headers.remove(CONTENT_LENGTH);
+            CodingBody::decode(body, coding)
+        }
+        None => CodingBody::identity(body),
+    };
+
+    Response::from_parts(parts, body)
+}
+
+impl<T, B, C, ResBody> Service<(Request<B>, C)> for DecompressionService<T>
+where
+    T: Service<(Request<B>, C), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    ResBody: 'static,
+{
+    type Response = Response<CodingBody<ResBody>>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (mut req, context): (Request<B>, C)) -> Self::Future {
+        if !req.headers().contains_key(ACCEPT_ENCODING) && !self.accept_encoding.is_empty() {
+            req.headers_mut()
+                .insert(ACCEPT_ENCODING, self.accept_encoding.clone());
+        }
+
+        Box::pin(self.inner.call((req, context)).map_ok(decompress))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::Bytes;
+    use std::convert::Infallible;
+
+    #[derive(Clone)]
+    struct CompressingService;
+
+    impl Service<(Request<()>, ())> for CompressingService {
+        type Response = Response<Full<Bytes>>;
+        type Error = Infallible;
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, _): (Request<()>, ())) -> Self::Future {
+            let accepted = req.headers()[ACCEPT_ENCODING].to_str().unwrap().to_string();
+            let coding = Coding::from_name(accepted.split(',').next().unwrap()).unwrap();
+            Box::pin(async move {
+                let body = CodingBody::encode(Full::new(Bytes::from("Hello, world")), coding)
+                    .collect()
+                    .await
+                    .unwrap()
+                    .to_bytes();
+                Ok(Response::builder()
+                    .header(CONTENT_ENCODING, coding.as_str())
+                    .header(CONTENT_LENGTH, body.len())
+                    .body(Full::new(body))
+                    .unwrap())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn decompresses_responses() {
+        for coding in Coding::SUPPORTED {
+            let service = DecompressionService::new(CompressingService).accept(&[*coding]);
+            let response = service.call((Request::new(()), ())).await.unwrap();
+            assert!(!response.headers().contains_key(CONTENT_ENCODING));
+            assert!(!response.headers().contains_key(CONTENT_LENGTH));
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, "Hello, world");
+        }
+    }
+}
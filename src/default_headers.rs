@@ -0,0 +1,168 @@
+//! Client middleware adding default headers to requests.
+//!
+//! `DefaultHeadersService` sets a `User-Agent` identifying the application
+//! and this crate, and any other default headers, on outgoing requests which
+//! don't already have them.
+//!
+//! Headers can be set for a single request by adding `RequestHeaders` to its
+//! context - these take precedence over the defaults.
+//!
+//! ```rust
+//! # use hyper::header::{HeaderName, HeaderValue, InvalidHeaderValue};
+//! # use swagger::default_headers::DefaultHeadersService;
+//! # fn wrap<T>(client: T) -> Result<(), InvalidHeaderValue> {
+//! let client = DefaultHeadersService::new(client)
+//!     .user_agent("pet-shop", "1.2.0")?
+//!     .header(
+//!         HeaderName::from_static("x-tenant"),
+//!         HeaderValue::from_static("acme"),
+//!     );
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::Has;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, InvalidHeaderValue, USER_AGENT};
+use hyper::service::Service;
+use hyper::Request;
+
+/// Context entry holding headers to set on a single request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestHeaders(pub HeaderMap);
+
+/// The `User-Agent` product token for this crate.
+fn crate_product() -> String {
+    format!("swagger-rs/{}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Client middleware adding default headers to requests.
+///
+/// Put it outside `RedirectService`, so that credentials set as defaults
+/// aren't added back to requests redirected to a different origin, and
+/// outside `RetryService` and `HedgeService`, which resend the headers of the
+/// original request.
+#[derive(Debug, Clone)]
+pub struct DefaultHeadersService<T> {
+    inner: T,
+    headers: HeaderMap,
+}
+
+impl<T> DefaultHeadersService<T> {
+    /// Create a new DefaultHeadersService, with a `User-Agent` identifying
+    /// this crate.
+    pub fn new(inner: T) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            USER_AGENT,
+            HeaderValue::from_str(&crate_product()).expect("Crate version is a valid header"),
+        );
+        DefaultHeadersService { inner, headers }
+    }
+
+    /// Set the `User-Agent` to identify the application, as well as this
+    /// crate - e.g. `pet-shop/1.2.0 swagger-rs/7.0.0`.
+    ///
+    /// Fails if the application name or version isn't a valid header value.
+    pub fn user_agent(mut self, app: &str, version: &str) -> Result<Self, InvalidHeaderValue> {
+        let user_agent = format!("{}/{} {}", app, version, crate_product());
+        self.headers
+            .insert(USER_AGENT, HeaderValue::try_from(user_agent)?);
+        Ok(self)
+    }
+
+    /// Set a default header. This replaces any existing default for the
+    /// header, including the `User-Agent`.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+}
+
+impl<T, B, C> Service<(Request<B>, C)> for DefaultHeadersService<T>
+where
+    C: Has<Option<RequestHeaders>>,
+    T: Service<(Request<B>, C)>,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = T::Future;
+
+    fn call(&self, (mut req, context): (Request<B>, C)) -> Self::Future {
+        if let Some(RequestHeaders(headers)) = Has::<Option<RequestHeaders>>::get(&context) {
+            for name in headers.keys() {
+                req.headers_mut().remove(name);
+            }
+            for (name, value) in headers {
+                req.headers_mut().append(name, value.clone());
+            }
+        }
+
+        for (name, value) in &self.headers {
+            if !req.headers().contains_key(name) {
+                req.headers_mut().insert(name, value.clone());
+            }
+        }
+
+        self.inner.call((req, context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Push;
+    use futures::future;
+    use std::convert::Infallible;
+
+    crate::new_context_type!(TestContext, TestEmptyContext, Option<RequestHeaders>);
+
+    type TestContextType =
+        crate::make_context_ty!(TestContext, TestEmptyContext, Option<RequestHeaders>);
+
+    /// Service returning the headers of the request.
+    struct EchoHeaders;
+
+    impl Service<(Request<()>, TestContextType)> for EchoHeaders {
+        type Response = HeaderMap;
+        type Error = Infallible;
+        type Future = future::Ready<Result<HeaderMap, Infallible>>;
+
+        fn call(&self, (req, _): (Request<()>, TestContextType)) -> Self::Future {
+            future::ok(req.headers().clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn sets_default_headers() {
+        let tenant = HeaderName::from_static("x-tenant");
+        let service = DefaultHeadersService::new(EchoHeaders)
+            .user_agent("pet-shop", "1.2.0")
+            .unwrap()
+            .header(tenant.clone(), HeaderValue::from_static("acme"));
+
+        let headers = service
+            .call((Request::new(()), TestEmptyContext.push(None)))
+            .await
+            .unwrap();
+        assert_eq!(
+            headers[USER_AGENT],
+            format!("pet-shop/1.2.0 swagger-rs/{}", env!("CARGO_PKG_VERSION"))
+        );
+        assert_eq!(headers[&tenant], "acme");
+
+        let mut overrides = HeaderMap::new();
+        overrides.insert(tenant.clone(), HeaderValue::from_static("globex"));
+        let headers = service
+            .call((
+                Request::new(()),
+                TestEmptyContext.push(Some(RequestHeaders(overrides))),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(headers[&tenant], "globex");
+
+        assert!(DefaultHeadersService::new(EchoHeaders)
+            .user_agent("pet-shop\n", "1.2.0")
+            .is_err());
+    }
+}
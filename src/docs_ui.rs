@@ -0,0 +1,379 @@
+//! Hyper service serving interactive API documentation - Swagger UI or ReDoc.
+//!
+//! `DocsUiService` responds to `GET` requests with a page rendering the
+//! OpenAPI document at a given URL, such as one served by the API itself. It
+//! takes plain `hyper::Request`s, so it can be mounted alongside the API in a
+//! `CompositeMakeService`, using `IntoMakeService`.
+//!
+//! The Swagger UI or ReDoc scripts and stylesheets can be embedded in the
+//! application with `asset`, and are then served by the service itself, so
+//! the page works without access to anything outside the deployment. The page
+//! loads them relative to its own path, so if the service is mounted with
+//! `CompositeMakeService::strip_prefix`, link to the page with a trailing
+//! slash, such as `/docs/`, or set `assets_url` to the mount path.
+//!
+//! Otherwise, the page loads a pinned version of the files from a CDN, with
+//! `crossorigin="anonymous"`. Use `integrity` to give their Subresource
+//! Integrity hashes, so browsers refuse files which have been tampered with.
+//!
+//! ```rust
+//! # use swagger::docs_ui::DocsUiService;
+//! # use swagger::IntoMakeService;
+//! # const SWAGGER_UI_CSS: &str = "";
+//! # const SWAGGER_UI_BUNDLE_JS: &str = "";
+//! let docs = DocsUiService::<String, std::convert::Infallible>::swagger_ui("/openapi.json")
+//!     .title("Pet Store API")
+//!     // e.g. include_str!("../assets/swagger-ui.css")
+//!     .asset("swagger-ui.css", SWAGGER_UI_CSS)
+//!     .asset("swagger-ui-bundle.js", SWAGGER_UI_BUNDLE_JS);
+//! let make_service = IntoMakeService::new(docs);
+//! // composite.push(("/docs", Box::new(make_service)));
+//! ```
+
+use hyper::header::{HeaderValue, ALLOW, CACHE_CONTROL, CONTENT_TYPE};
+use hyper::service::Service;
+use hyper::{Method, Request, Response, StatusCode};
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// The default location of the Swagger UI files, from `swagger-ui-dist`.
+const SWAGGER_UI_ASSETS_URL: &str = "https://cdn.jsdelivr.net/npm/swagger-ui-dist@5.17.14";
+
+/// The default location of the ReDoc standalone bundle.
+const REDOC_ASSETS_URL: &str = "https://cdn.jsdelivr.net/npm/redoc@2.1.5/bundles";
+
+/// Which documentation UI to serve.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ui {
+    SwaggerUi,
+    Redoc,
+}
+
+/// Escape text for use in HTML content or a quoted attribute.
+fn html_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Service responding with a Swagger UI or ReDoc page for an OpenAPI document.
+pub struct DocsUiService<B, E> {
+    ui: Ui,
+    spec_url: String,
+    title: String,
+    cdn_url: &'static str,
+    assets_url: Option<String>,
+    assets: HashMap<String, &'static str>,
+    integrity: HashMap<String, String>,
+    marker: PhantomData<fn() -> (B, E)>,
+}
+
+impl<B, E> DocsUiService<B, E> {
+    fn new(ui: Ui, spec_url: &str, cdn_url: &'static str) -> Self {
+        DocsUiService {
+            ui,
+            spec_url: spec_url.to_string(),
+            title: "API Documentation".to_string(),
+            cdn_url,
+            assets_url: None,
+            assets: HashMap::new(),
+            integrity: HashMap::new(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Serve Swagger UI for the OpenAPI document at the given URL.
+    pub fn swagger_ui(spec_url: &str) -> Self {
+        Self::new(Ui::SwaggerUi, spec_url, SWAGGER_UI_ASSETS_URL)
+    }
+
+    /// Serve ReDoc for the OpenAPI document at the given URL.
+    pub fn redoc(spec_url: &str) -> Self {
+        Self::new(Ui::Redoc, spec_url, REDOC_ASSETS_URL)
+    }
+
+    /// Set the title of the page.
+    ///
+    /// Defaults to "API Documentation".
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    /// Load the UI's files from the given URL. For Swagger UI, this holds
+    /// `swagger-ui.css` and `swagger-ui-bundle.js` from `swagger-ui-dist`, and
+    /// for ReDoc, `redoc.standalone.js`.
+    ///
+    /// Defaults to the service itself for files embedded with `asset`, and
+    /// to a CDN, at a pinned version, for the others.
+    pub fn assets_url(mut self, assets_url: &str) -> Self {
+        self.assets_url = Some(assets_url.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Serve one of the UI's files, such as `swagger-ui-bundle.js`, from the
+    /// service, in response to requests for a path ending in its name. Unless
+    /// `assets_url` is set, the page loads the file from the service.
+    pub fn asset(mut self, name: &str, contents: &'static str) -> Self {
+        self.assets.insert(name.to_string(), contents);
+        self
+    }
+
+    /// Set the Subresource Integrity hash of one of the UI's files, such as
+    /// `sha384-...` for `swagger-ui-bundle.js`.
+    pub fn integrity(mut self, name: &str, hash: &str) -> Self {
+        self.integrity.insert(name.to_string(), hash.to_string());
+        self
+    }
+
+    /// The attributes loading one of the UI's files, from a page at `path`.
+    fn asset_attributes(&self, path: &str, attribute: &str, name: &str) -> String {
+        let url = match &self.assets_url {
+            Some(assets_url) => format!("{}/{}", assets_url, name),
+            // Relative to the page, so that it works wherever the service is
+            // mounted
+            None if self.assets.contains_key(name) => match path.rsplit('/').next() {
+                Some(page) if !page.is_empty() => format!("{}/{}", page, name),
+                _ => name.to_string(),
+            },
+            None => format!("{}/{}", self.cdn_url, name),
+        };
+        let mut attributes = format!(
+            r#"{}="{}" crossorigin="anonymous""#,
+            attribute,
+            html_escape(&url)
+        );
+        if let Some(hash) = self.integrity.get(name) {
+            attributes.push_str(&format!(r#" integrity="{}""#, html_escape(hash)));
+        }
+        attributes
+    }
+
+    /// The HTML page, served at `path`.
+    fn html(&self, path: &str) -> String {
+        let title = html_escape(&self.title);
+        let spec_url = html_escape(&self.spec_url);
+        match self.ui {
+            Ui::SwaggerUi => format!(
+                r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<link rel="stylesheet" {css}>
+</head>
+<body>
+<div id="swagger-ui" data-url="{spec_url}"></div>
+<script {js}></script>
+<script>
+const element = document.getElementById("swagger-ui");
+window.ui = SwaggerUIBundle({{ url: element.dataset.url, domNode: element }});
+</script>
+</body>
+</html>
+"#,
+                css = self.asset_attributes(path, "href", "swagger-ui.css"),
+                js = self.asset_attributes(path, "src", "swagger-ui-bundle.js"),
+            ),
+            Ui::Redoc => format!(
+                r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+</head>
+<body>
+<redoc spec-url="{spec_url}"></redoc>
+<script {js}></script>
+</body>
+</html>
+"#,
+                js = self.asset_attributes(path, "src", "redoc.standalone.js"),
+            ),
+        }
+    }
+}
+
+impl<B, E> Clone for DocsUiService<B, E> {
+    fn clone(&self) -> Self {
+        DocsUiService {
+            ui: self.ui,
+            spec_url: self.spec_url.clone(),
+            title: self.title.clone(),
+            cdn_url: self.cdn_url,
+            assets_url: self.assets_url.clone(),
+            assets: self.assets.clone(),
+            integrity: self.integrity.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<B, E> fmt::Debug for DocsUiService<B, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DocsUiService")
+            .field("ui", &self.ui)
+            .field("spec_url", &self.spec_url)
+            .field("title", &self.title)
+            .field("cdn_url", &self.cdn_url)
+            .field("assets_url", &self.assets_url)
+            .field("assets", &self.assets.keys())
+            .field("integrity", &self.integrity)
+            .finish()
+    }
+}
+
+impl<B, E, ReqBody> Service<Request<ReqBody>> for DocsUiService<B, E>
+where
+    B: From<String>,
+{
+    type Response = Response<B>;
+    type Error = E;
+    type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn call(&self, req: Request<ReqBody>) -> Self::Future {
+        let asset = req
+            .uri()
+            .path()
+            .rsplit('/')
+            .next()
+            .and_then(|name| self.assets.get_key_value(name));
+        let mut response = if req.method() != Method::GET && req.method() != Method::HEAD {
+            let mut response = Response::new(B::from(String::new()));
+            *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+            response
+                .headers_mut()
+                .insert(ALLOW, HeaderValue::from_static("GET, HEAD"));
+            response
+        } else if let Some((name, contents)) = asset {
+            let content_type = if name.ends_with(".js") {
+                "text/javascript; charset=utf-8"
+            } else if name.ends_with(".css") {
+                "text/css; charset=utf-8"
+            } else {
+                "application/octet-stream"
+            };
+            let mut response = Response::new(B::from(contents.to_string()));
+            response
+                .headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+            response
+        } else {
+            let mut response = Response::new(B::from(self.html(req.uri().path())));
+            response.headers_mut().insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static("text/html; charset=utf-8"),
+            );
+            response
+        };
+        // The page is tiny, the spec it points at may change, and embedded
+        // files change when the application is upgraded
+        response
+            .headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        futures::future::ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+
+    #[tokio::test]
+    async fn serves_docs_pages() {
+        let swagger_ui = DocsUiService::<String, Infallible>::swagger_ui("/openapi.json?v=\"1\"")
+            .title("Pets & <Owners>")
+            .assets_url("/assets/");
+        let response = swagger_ui
+            .call(Request::get("/docs").body(()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "text/html; charset=utf-8");
+        let body = response.into_body();
+        assert!(body.contains("<title>Pets &amp; &lt;Owners&gt;</title>"));
+        assert!(body.contains(r#"data-url="/openapi.json?v=&quot;1&quot;""#));
+        assert!(body.contains(
+            r#"<script src="/assets/swagger-ui-bundle.js" crossorigin="anonymous"></script>"#
+        ));
+
+        let redoc = DocsUiService::<String, Infallible>::redoc("/openapi.json")
+            .integrity("redoc.standalone.js", "sha384-abc");
+        let response = redoc
+            .call(Request::get("/docs/").body(()).unwrap())
+            .await
+            .unwrap();
+        let body = response.into_body();
+        assert!(body.contains(r#"<redoc spec-url="/openapi.json"></redoc>"#));
+        assert!(body.contains(
+            r#"<script src="https://cdn.jsdelivr.net/npm/redoc@2.1.5/bundles/redoc.standalone.js" crossorigin="anonymous" integrity="sha384-abc"></script>"#
+        ));
+
+        let response = redoc
+            .call(Request::post("/docs").body(()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[ALLOW], "GET, HEAD");
+    }
+
+    #[tokio::test]
+    async fn serves_embedded_assets() {
+        let docs = DocsUiService::<String, Infallible>::swagger_ui("/openapi.json")
+            .asset("swagger-ui-bundle.js", "window.SwaggerUIBundle = () => {};");
+        let response = docs
+            .call(Request::get("/swagger-ui-bundle.js").body(()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[CONTENT_TYPE],
+            "text/javascript; charset=utf-8"
+        );
+        assert_eq!(response.into_body(), "window.SwaggerUIBundle = () => {};");
+
+        // Embedded files are loaded relative to the page, and others from the
+        // CDN
+        let response = docs
+            .call(Request::get("/docs").body(()).unwrap())
+            .await
+            .unwrap();
+        let body = response.into_body();
+        assert!(
+            body.contains(r#"<script src="docs/swagger-ui-bundle.js" crossorigin="anonymous">"#)
+        );
+        assert!(body.contains(
+            r#"href="https://cdn.jsdelivr.net/npm/swagger-ui-dist@5.17.14/swagger-ui.css""#
+        ));
+
+        let response = docs
+            .call(Request::get("/docs/").body(()).unwrap())
+            .await
+            .unwrap();
+        assert!(response
+            .into_body()
+            .contains(r#"<script src="swagger-ui-bundle.js" crossorigin="anonymous">"#));
+
+        let response = docs
+            .assets_url("/static/")
+            .call(Request::get("/docs").body(()).unwrap())
+            .await
+            .unwrap();
+        assert!(response
+            .into_body()
+            .contains(r#"<script src="/static/swagger-ui-bundle.js" crossorigin="anonymous">"#));
+    }
+}
@@ -1,6 +1,10 @@
 //! Hyper service that drops a context to an incoming request and passes it on
 //! to a wrapped service.
 
+use crate::auth::AuthData;
+use crate::{Has, XSpanIdString, X_SPAN_ID};
+use headers::{Authorization, HeaderMapExt};
+use hyper::header::{HeaderName, HeaderValue};
 use hyper::Request;
 use std::marker::PhantomData;
 
@@ -129,3 +133,226 @@ where
         self.inner.call(req)
     }
 }
+
+/// Middleware wrapper service that behaves like `DropContextMakeService`, but
+/// copies the X-Span-ID and authentication data from the context onto the
+/// request before dropping the context. Servers will normally want to use
+/// this, which will create a `DropContextWithHeadersService` to handle each
+/// connection.
+#[derive(Debug)]
+pub struct DropContextWithHeadersMakeService<T, C>
+where
+    C: Send + 'static,
+{
+    inner: T,
+    api_key_header: Option<HeaderName>,
+    marker: PhantomData<C>,
+}
+
+impl<T, C> DropContextWithHeadersMakeService<T, C>
+where
+    C: Send + 'static,
+{
+    /// Create a new DropContextWithHeadersMakeService struct wrapping a value
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            api_key_header: None,
+            marker: PhantomData,
+        }
+    }
+
+    /// Copy API keys from the context into the given header.
+    pub fn api_key_header(mut self, header: HeaderName) -> Self {
+        self.api_key_header = Some(header);
+        self
+    }
+}
+
+impl<Inner, Context, Target> hyper::service::Service<Target>
+    for DropContextWithHeadersMakeService<Inner, Context>
+where
+    Context: Send + 'static,
+    Inner: hyper::service::Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = DropContextWithHeadersService<Inner::Response, Context>;
+    type Error = Inner::Error;
+    type Future = futures::future::BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let api_key_header = self.api_key_header.clone();
+        Box::pin(self.inner.call(target).map(|s| {
+            let mut service = DropContextWithHeadersService::new(s?);
+            service.api_key_header = api_key_header;
+            Ok(service)
+        }))
+    }
+}
+
+/// Swagger Middleware that wraps a `hyper::service::Service`, copies the
+/// X-Span-ID and authentication data from the context onto the request as
+/// headers, then drops the context. Headers already present on the request
+/// are left alone.
+///
+/// This lets a `(Request, Context)` client stack sit on top of a plain hyper
+/// client, while still propagating the X-Span-ID and credentials.
+///
+/// ```
+/// # use swagger::DropContextWithHeadersService;
+/// # use hyper_util::client::legacy::Client;
+/// # use hyper_util::rt::TokioExecutor;
+/// # use hyper_util::service::TowerToHyperService;
+/// # use http_body_util::Empty;
+/// # use hyper::body::Bytes;
+/// # use hyper::header::HeaderName;
+/// # use swagger::{AuthData, ContextBuilder, EmptyContext, XSpanIdString};
+/// type ClientContext = swagger::make_context_ty!(
+///     ContextBuilder, EmptyContext, Option<AuthData>, XSpanIdString
+/// );
+///
+/// let client = Client::builder(TokioExecutor::new()).build_http::<Empty<Bytes>>();
+/// let client: DropContextWithHeadersService<_, ClientContext> =
+///     DropContextWithHeadersService::new(TowerToHyperService::new(client))
+///         .api_key_header(HeaderName::from_static("x-api-key"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct DropContextWithHeadersService<T, C>
+where
+    C: Send + 'static,
+{
+    inner: T,
+    api_key_header: Option<HeaderName>,
+    marker: PhantomData<C>,
+}
+
+impl<T, C> DropContextWithHeadersService<T, C>
+where
+    C: Send + 'static,
+{
+    /// Create a new DropContextWithHeadersService struct wrapping a value
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            api_key_header: None,
+            marker: PhantomData,
+        }
+    }
+
+    /// Copy API keys from the context into the given header.
+    pub fn api_key_header(mut self, header: HeaderName) -> Self {
+        self.api_key_header = Some(header);
+        self
+    }
+}
+
+impl<Inner, Body, Context> hyper::service::Service<(Request<Body>, Context)>
+    for DropContextWithHeadersService<Inner, Context>
+where
+    Context: Has<XSpanIdString> + Has<Option<AuthData>> + Send + 'static,
+    Inner: hyper::service::Service<Request<Body>>,
+{
+    type Response = Inner::Response;
+    type Error = Inner::Error;
+    type Future = Inner::Future;
+
+    fn call(&self, (mut req, context): (Request<Body>, Context)) -> Self::Future {
+        let headers = req.headers_mut();
+
+        if !headers.contains_key(X_SPAN_ID) {
+            let x_span_id = Has::<XSpanIdString>::get(&context);
+            if let Ok(value) = HeaderValue::from_str(&x_span_id.0) {
+                headers.insert(X_SPAN_ID, value);
+            }
+        }
+
+        match Has::<Option<AuthData>>::get(&context) {
+            Some(AuthData::Basic(username, password))
+                if !headers.contains_key(hyper::header::AUTHORIZATION) =>
+            {
+                headers.typed_insert(Authorization::basic(username, password));
+            }
+            Some(AuthData::Bearer(token))
+                if !headers.contains_key(hyper::header::AUTHORIZATION) =>
+            {
+                if let Ok(bearer) = Authorization::bearer(token) {
+                    headers.typed_insert(bearer);
+                }
+            }
+            Some(AuthData::ApiKey(key)) => {
+                if let Some(header) = &self.api_key_header {
+                    if !headers.contains_key(header) {
+                        if let Ok(mut value) = HeaderValue::from_str(key) {
+                            value.set_sensitive(true);
+                            headers.insert(header.clone(), value);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContextBuilder, EmptyContext, Push};
+
+    struct EchoHeaders;
+
+    impl hyper::service::Service<Request<()>> for EchoHeaders {
+        type Response = hyper::HeaderMap;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, req: Request<()>) -> Self::Future {
+            futures::future::ok(req.headers().clone())
+        }
+    }
+
+    type TestContext =
+        ContextBuilder<Option<AuthData>, ContextBuilder<XSpanIdString, EmptyContext>>;
+
+    fn context(auth: AuthData) -> TestContext {
+        EmptyContext
+            .push(XSpanIdString("span".to_string()))
+            .push(Some(auth))
+    }
+
+    #[tokio::test]
+    async fn copies_headers() {
+        use hyper::service::Service as _;
+
+        let service = DropContextWithHeadersService::new(EchoHeaders)
+            .api_key_header(HeaderName::from_static("x-api-key"));
+
+        let headers = service
+            .call((Request::new(()), context(AuthData::basic("user", "pass"))))
+            .await
+            .unwrap();
+        assert_eq!(headers[X_SPAN_ID], "span");
+        assert_eq!(headers[hyper::header::AUTHORIZATION], "Basic dXNlcjpwYXNz");
+
+        let headers = service
+            .call((Request::new(()), context(AuthData::apikey("key"))))
+            .await
+            .unwrap();
+        assert_eq!(headers["x-api-key"], "key");
+
+        // Existing headers are left alone
+        let request = Request::builder()
+            .header(X_SPAN_ID, "original")
+            .header(hyper::header::AUTHORIZATION, "Bearer original")
+            .body(())
+            .unwrap();
+        let headers = service
+            .call((request, context(AuthData::basic("user", "pass"))))
+            .await
+            .unwrap();
+        assert_eq!(headers[X_SPAN_ID], "original");
+        assert_eq!(headers[hyper::header::AUTHORIZATION], "Bearer original");
+    }
+}
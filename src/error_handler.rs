@@ -0,0 +1,362 @@
+//! Hyper service turning the errors of an API into responses.
+//!
+//! An error returned by a hyper service drops the connection without any
+//! response, leaving the client to guess what went wrong. `ErrorHandlerService`
+//! converts every error of the service it wraps into a `ServiceError`, and
+//! passes it to a hook, along with the request's context, to build the
+//! response. If the service panics, the hook is passed `ServiceError::Panic`,
+//! and if the hook itself panics, a `500 Internal Server Error` is returned,
+//! so that the client always gets a response.
+//!
+//! The hook `ServiceError::into_response` gives the same JSON errors as the
+//! crate's middleware; custom hooks can return problems, add headers or
+//! translate errors of their own:
+//!
+//! ```rust
+//! # use swagger::error_handler::{ErrorHandlerService, ServiceError};
+//! # use swagger::{Has, XSpanIdString};
+//! # use hyper::Response;
+//! # type Context = swagger::ContextBuilder<XSpanIdString, swagger::EmptyContext>;
+//! # struct Api;
+//! let service = ErrorHandlerService::<_, Context, _>::new(Api, |error: ServiceError, context: &Context| {
+//!     let x_span_id = &Has::<XSpanIdString>::get(context).0;
+//!     error.into_response::<String>(Some(x_span_id))
+//! });
+//! ```
+
+use crate::body_limit::BodyTooLarge;
+use crate::response::json_error;
+use crate::{ApiError, Has, XSpanIdString};
+use futures::future::{BoxFuture, FutureExt};
+use hyper::service::Service;
+use hyper::{Request, Response, StatusCode};
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+const LOG_TARGET: &str = "swagger::error_handler";
+
+/// The errors of a service, each corresponding to an error response.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ServiceError {
+    /// The request was malformed.
+    BadRequest(String),
+    /// The request wasn't authenticated.
+    Unauthorized,
+    /// The client isn't allowed to make the request.
+    Forbidden,
+    /// The requested resource doesn't exist.
+    NotFound,
+    /// The resource doesn't support the request's method.
+    MethodNotAllowed,
+    /// The request body was too large.
+    PayloadTooLarge,
+    /// The request took too long to handle.
+    Timeout,
+    /// The service is temporarily unable to handle requests.
+    Unavailable,
+    /// The service panicked.
+    Panic,
+    /// Any other error.
+    Internal(Box<dyn Error + Send + Sync>),
+}
+
+impl ServiceError {
+    /// The status code of the response for the error.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ServiceError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ServiceError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ServiceError::Forbidden => StatusCode::FORBIDDEN,
+            ServiceError::NotFound => StatusCode::NOT_FOUND,
+            ServiceError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ServiceError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ServiceError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ServiceError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ServiceError::Panic | ServiceError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Build a JSON error response for the error, of the form
+    /// `{"code":<status>,"message":"<message>","x-span-id":"<X-Span-ID>"}`.
+    ///
+    /// The details of internal errors and panics aren't exposed to the
+    /// client.
+    pub fn into_response<B>(self, x_span_id: Option<&str>) -> Response<B>
+    where
+        B: From<String>,
+    {
+        let status = self.status();
+        match self {
+            ServiceError::BadRequest(message) => json_error(status, &message, x_span_id),
+            _ => json_error(
+                status,
+                status.canonical_reason().unwrap_or("Error"),
+                x_span_id,
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServiceError::BadRequest(message) => write!(f, "Bad request: {}", message),
+            ServiceError::Panic => f.write_str("Service panicked"),
+            ServiceError::Internal(error) => write!(f, "Internal error: {}", error),
+            error => f.write_str(error.status().canonical_reason().unwrap_or("Error")),
+        }
+    }
+}
+
+impl Error for ServiceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ServiceError::Internal(error) => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<BodyTooLarge> for ServiceError {
+    fn from(_: BodyTooLarge) -> Self {
+        ServiceError::PayloadTooLarge
+    }
+}
+
+impl From<ApiError> for ServiceError {
+    fn from(error: ApiError) -> Self {
+        ServiceError::Internal(Box::new(error))
+    }
+}
+
+impl From<hyper::Error> for ServiceError {
+    fn from(error: hyper::Error) -> Self {
+        ServiceError::Internal(Box::new(error))
+    }
+}
+
+impl From<Box<dyn Error + Send + Sync>> for ServiceError {
+    fn from(error: Box<dyn Error + Send + Sync>) -> Self {
+        match error.downcast::<ServiceError>() {
+            Ok(error) => *error,
+            Err(error) if error.is::<BodyTooLarge>() => ServiceError::PayloadTooLarge,
+            Err(error) => ServiceError::Internal(error),
+        }
+    }
+}
+
+impl From<Infallible> for ServiceError {
+    fn from(error: Infallible) -> Self {
+        match error {}
+    }
+}
+
+/// Middleware wrapper service which turns the errors of the wrapped service
+/// into responses.
+pub struct ErrorHandlerMakeService<T, C, F> {
+    inner: T,
+    hook: Arc<F>,
+    marker: PhantomData<fn(C)>,
+}
+
+impl<T, C, F> ErrorHandlerMakeService<T, C, F> {
+    /// Create a new ErrorHandlerMakeService, building the responses for
+    /// errors with `hook`.
+    pub fn new(inner: T, hook: F) -> Self {
+        ErrorHandlerMakeService {
+            inner,
+            hook: Arc::new(hook),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: fmt::Debug, C, F> fmt::Debug for ErrorHandlerMakeService<T, C, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorHandlerMakeService")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<Inner, C, F, Target> Service<Target> for ErrorHandlerMakeService<Inner, C, F>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+    F: Send + Sync + 'static,
+{
+    type Response = ErrorHandlerService<Inner::Response, C, F>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let hook = self.hook.clone();
+        Box::pin(self.inner.call(target).map(|s| {
+            Ok(ErrorHandlerService {
+                inner: s?,
+                hook,
+                marker: PhantomData,
+            })
+        }))
+    }
+}
+
+/// Middleware wrapper service which turns the errors of the wrapped service
+/// into responses. Servers will normally want to use
+/// `ErrorHandlerMakeService`, which will create an `ErrorHandlerService` for
+/// each connection.
+pub struct ErrorHandlerService<T, C, F> {
+    inner: T,
+    hook: Arc<F>,
+    marker: PhantomData<fn(C)>,
+}
+
+impl<T, C, F> ErrorHandlerService<T, C, F> {
+    /// Create a new ErrorHandlerService, building the responses for errors
+    /// with `hook`.
+    pub fn new(inner: T, hook: F) -> Self {
+        ErrorHandlerService {
+            inner,
+            hook: Arc::new(hook),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Clone, C, F> Clone for ErrorHandlerService<T, C, F> {
+    fn clone(&self) -> Self {
+        ErrorHandlerService {
+            inner: self.inner.clone(),
+            hook: self.hook.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: fmt::Debug, C, F> fmt::Debug for ErrorHandlerService<T, C, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorHandlerService")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<T, C, F, ReqBody, ResBody> Service<(Request<ReqBody>, C)> for ErrorHandlerService<T, C, F>
+where
+    C: Has<XSpanIdString> + Clone + Send + 'static,
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>>,
+    T::Error: Into<ServiceError>,
+    T::Future: Send + 'static,
+    F: Fn(ServiceError, &C) -> Response<ResBody> + Send + Sync + 'static,
+    ResBody: From<String> + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let hook = self.hook.clone();
+        let hook_context = context.clone();
+        let future =
+            std::panic::catch_unwind(AssertUnwindSafe(|| self.inner.call((req, context)))).ok();
+
+        Box::pin(async move {
+            let result = match future {
+                Some(future) => match AssertUnwindSafe(future).catch_unwind().await {
+                    Ok(result) => result.map_err(Into::into),
+                    Err(_) => Err(ServiceError::Panic),
+                },
+                None => Err(ServiceError::Panic),
+            };
+            let error = match result {
+                Ok(response) => return Ok(response),
+                Err(error) => error,
+            };
+
+            let x_span_id = Has::<XSpanIdString>::get(&hook_context).0.clone();
+            if error.status().is_server_error() {
+                log::error!(target: LOG_TARGET, "{} - X-Span-ID: {}", error, x_span_id);
+            } else {
+                log::debug!(target: LOG_TARGET, "{} - X-Span-ID: {}", error, x_span_id);
+            }
+
+            let response =
+                std::panic::catch_unwind(AssertUnwindSafe(|| hook(error, &hook_context)));
+            Ok(response.unwrap_or_else(|_| {
+                log::error!(
+                    target: LOG_TARGET,
+                    "Error hook panicked - X-Span-ID: {}",
+                    x_span_id
+                );
+                ServiceError::Panic.into_response(Some(&x_span_id))
+            }))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContextBuilder, EmptyContext, Push};
+
+    type Context = ContextBuilder<XSpanIdString, EmptyContext>;
+
+    struct TestApi;
+
+    impl Service<(Request<()>, Context)> for TestApi {
+        type Response = Response<String>;
+        type Error = Box<dyn Error + Send + Sync>;
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, _): (Request<()>, Context)) -> Self::Future {
+            match req.uri().path() {
+                "/ok" => futures::future::ok(Response::new("ok".to_string())),
+                "/missing" => futures::future::err(Box::new(ServiceError::NotFound)),
+                "/large" => futures::future::err(Box::new(BodyTooLarge(10))),
+                "/panic" => panic!("handler panicked"),
+                _ => futures::future::err("database unavailable".into()),
+            }
+        }
+    }
+
+    fn hook(error: ServiceError, context: &Context) -> Response<String> {
+        let x_span_id = &Has::<XSpanIdString>::get(context).0;
+        match error {
+            ServiceError::NotFound => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body("custom".to_string())
+                .unwrap(),
+            error => error.into_response(Some(x_span_id)),
+        }
+    }
+
+    async fn send(path: &str) -> Response<String> {
+        let service = ErrorHandlerService::new(TestApi, hook);
+        let context = EmptyContext.push(XSpanIdString("span".to_string()));
+        let req = Request::get(path).body(()).unwrap();
+        service.call((req, context)).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn handles_errors() {
+        assert_eq!(send("/ok").await.body(), "ok");
+        assert_eq!(send("/missing").await.body(), "custom");
+        assert_eq!(send("/large").await.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let response = send("/panic").await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            response.body(),
+            r#"{"code":500,"message":"Internal Server Error","x-span-id":"span"}"#
+        );
+
+        let response = send("/db").await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(!response.body().contains("database"));
+    }
+}
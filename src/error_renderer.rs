@@ -0,0 +1,267 @@
+//! Hyper service rendering the error responses of the crate's middleware in
+//! a deployment's own format.
+//!
+//! The crate's middleware - such as `CorsService`, `ValidationService`,
+//! `StrictQueryService`, `BodyLimitService`, `ConcurrencyLimitService` and
+//! the fallbacks - report errors as JSON of the form
+//! `{"code":<status>,"message":"<message>","x-span-id":"<X-Span-ID>"}`.
+//! `ErrorRendererService` passes each of these errors to an `ErrorRenderer`,
+//! with the request's context, and returns its response instead, so that
+//! every error has the same envelope as the API's own. Headers of the
+//! original response, such as `Allow` or `Retry-After`, are kept.
+//!
+//! Renderers are given the `ErrorDetails` of an error: its status, a
+//! machine-readable code such as `not_found` or `origin_not_allowed`, its
+//! message and the request's X-Span-ID.
+//!
+//! ```rust
+//! # use swagger::error_renderer::{ErrorDetails, ErrorRendererService};
+//! # use swagger::XSpanIdString;
+//! # use hyper::Response;
+//! # type Context = swagger::ContextBuilder<XSpanIdString, swagger::EmptyContext>;
+//! # struct Api;
+//! let service = ErrorRendererService::<_, Context, _>::new(Api, |error: &ErrorDetails<'_>, _: &Context| {
+//!     Response::builder()
+//!         .status(error.status)
+//!         .header("content-type", "application/json")
+//!         .body(format!(
+//!             r#"{{"error":{{"code":"{}","trace":"{}"}}}}"#,
+//!             error.code, error.x_span_id
+//!         ))
+//!         .unwrap()
+//! });
+//! ```
+
+use crate::response::ErrorMessage;
+use crate::{Has, XSpanIdString};
+use futures::future::{BoxFuture, FutureExt};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::http::Extensions;
+use hyper::service::Service;
+use hyper::{Request, Response, StatusCode};
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// An error reported by the crate's middleware.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ErrorDetails<'a> {
+    /// The status code of the response.
+    pub status: StatusCode,
+    /// A machine-readable code for the error, such as `not_found`.
+    pub code: &'a str,
+    /// A description of the error.
+    pub message: &'a str,
+    /// The X-Span-ID of the request.
+    pub x_span_id: &'a str,
+    /// The extensions of the original response. Requests rejected by
+    /// `ValidationService` have their `ValidationErrors` here.
+    pub extensions: &'a Extensions,
+}
+
+/// Renders the errors of the crate's middleware as responses.
+///
+/// Implemented for functions and closures taking the error and the
+/// request's context.
+pub trait ErrorRenderer<C, B> {
+    /// Render an error as a response.
+    fn render(&self, error: &ErrorDetails<'_>, context: &C) -> Response<B>;
+}
+
+impl<F, C, B> ErrorRenderer<C, B> for F
+where
+    F: Fn(&ErrorDetails<'_>, &C) -> Response<B>,
+{
+    fn render(&self, error: &ErrorDetails<'_>, context: &C) -> Response<B> {
+        self(error, context)
+    }
+}
+
+/// Re-render an error response of the crate's middleware.
+fn render<B, C, R>(response: Response<B>, renderer: &R, context: &C) -> Response<B>
+where
+    C: Has<XSpanIdString>,
+    R: ErrorRenderer<C, B>,
+{
+    let (mut parts, body) = response.into_parts();
+    let Some(error) = parts.extensions.remove::<ErrorMessage>() else {
+        return Response::from_parts(parts, body);
+    };
+
+    let details = ErrorDetails {
+        status: parts.status,
+        code: &error.code,
+        message: &error.message,
+        x_span_id: &Has::<XSpanIdString>::get(context).0,
+        extensions: &parts.extensions,
+    };
+    let (rendered, body) = renderer.render(&details, context).into_parts();
+
+    parts.status = rendered.status;
+    parts.headers.remove(CONTENT_TYPE);
+    parts.headers.remove(CONTENT_LENGTH);
+    parts.headers.extend(rendered.headers);
+    parts.extensions.extend(rendered.extensions);
+    Response::from_parts(parts, body)
+}
+
+/// Middleware wrapper service which renders the error responses of the
+/// crate's middleware with an `ErrorRenderer`.
+pub struct ErrorRendererMakeService<T, C, R> {
+    inner: T,
+    renderer: Arc<R>,
+    marker: PhantomData<fn(C)>,
+}
+
+impl<T, C, R> ErrorRendererMakeService<T, C, R> {
+    /// Create a new ErrorRendererMakeService.
+    pub fn new(inner: T, renderer: R) -> Self {
+        ErrorRendererMakeService {
+            inner,
+            renderer: Arc::new(renderer),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: fmt::Debug, C, R> fmt::Debug for ErrorRendererMakeService<T, C, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorRendererMakeService")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<Inner, C, R, Target> Service<Target> for ErrorRendererMakeService<Inner, C, R>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+    R: Send + Sync + 'static,
+{
+    type Response = ErrorRendererService<Inner::Response, C, R>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let renderer = self.renderer.clone();
+        Box::pin(self.inner.call(target).map(|s| {
+            Ok(ErrorRendererService {
+                inner: s?,
+                renderer,
+                marker: PhantomData,
+            })
+        }))
+    }
+}
+
+/// Middleware wrapper service which renders the error responses of the
+/// crate's middleware with an `ErrorRenderer`. Servers will normally want
+/// to use `ErrorRendererMakeService`, which will create an
+/// `ErrorRendererService` for each connection.
+pub struct ErrorRendererService<T, C, R> {
+    inner: T,
+    renderer: Arc<R>,
+    marker: PhantomData<fn(C)>,
+}
+
+impl<T, C, R> ErrorRendererService<T, C, R> {
+    /// Create a new ErrorRendererService.
+    pub fn new(inner: T, renderer: R) -> Self {
+        ErrorRendererService {
+            inner,
+            renderer: Arc::new(renderer),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Clone, C, R> Clone for ErrorRendererService<T, C, R> {
+    fn clone(&self) -> Self {
+        ErrorRendererService {
+            inner: self.inner.clone(),
+            renderer: self.renderer.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: fmt::Debug, C, R> fmt::Debug for ErrorRendererService<T, C, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorRendererService")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<T, C, R, ReqBody, ResBody> Service<(Request<ReqBody>, C)> for ErrorRendererService<T, C, R>
+where
+    C: Has<XSpanIdString> + Clone + Send + 'static,
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    R: ErrorRenderer<C, ResBody> + Send + Sync + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let renderer = self.renderer.clone();
+        let render_context = context.clone();
+        Box::pin(
+            self.inner
+                .call((req, context))
+                .map(move |response| Ok(render(response?, &*renderer, &render_context))),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fallback::{DefaultFallback, MethodNotAllowed};
+    use crate::routes::Routes;
+    use crate::{ContextBuilder, EmptyContext, Push};
+    use hyper::header::ALLOW;
+    use hyper::Method;
+
+    type Context = ContextBuilder<XSpanIdString, EmptyContext>;
+
+    fn renderer(error: &ErrorDetails<'_>, _: &Context) -> Response<String> {
+        Response::builder()
+            .status(error.status)
+            .header(CONTENT_TYPE, "text/plain")
+            .body(format!(
+                "{} {} {}",
+                error.code, error.message, error.x_span_id
+            ))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn renders_errors() {
+        let routes = Routes::new().route("/pets", [Method::GET]);
+        let fallback = MethodNotAllowed::new(routes, DefaultFallback::<String, ()>::new());
+        let service = ErrorRendererService::new(fallback, renderer);
+        let call = |req: Request<()>| {
+            let context: Context = EmptyContext.push(XSpanIdString("span".to_string()));
+            service.call((req, context))
+        };
+
+        let response = call(Request::post("/pets").body(()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[ALLOW], "GET");
+        assert_eq!(response.headers()[CONTENT_TYPE], "text/plain");
+        assert_eq!(
+            response.body(),
+            "method_not_allowed Method Not Allowed span"
+        );
+
+        let response = call(Request::get("/owners").body(()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.body(), "not_found Not Found span");
+    }
+}
@@ -0,0 +1,404 @@
+//! Hyper service setting `ETag` validators on responses, and answering
+//! conditional `GET` and `HEAD` requests with `304 Not Modified`.
+//!
+//! The entity tag of a `200 OK` response is, in order of preference:
+//!
+//! - the `ETag` header set by the handler,
+//! - a version token set by the handler using the `VersionToken` added to
+//!   its context, e.g. a database row version,
+//! - a hash of the response body, if its length is known and no larger
+//!   than `max_body_size`.
+//!
+//! Entity tags are strong unless `weak` is set - weak tags should be used if
+//! the same representation may be encoded differently, e.g. compressed.
+//!
+//! If the request's `If-None-Match` header matches the entity tag, or it has
+//! no `If-None-Match` but an `If-Modified-Since` header not older than the
+//! response's `Last-Modified` header, the response is replaced by
+//! `304 Not Modified`, keeping its other headers.
+//!
+//! ```rust
+//! # use swagger::etag::VersionToken;
+//! # use swagger::Has;
+//! # fn get_pet<C: Has<VersionToken>>(context: &C) {
+//! // In the API implementation
+//! context.get().set("42");
+//! # }
+//! ```
+
+use crate::body_ext::collect_limited;
+use crate::response::json_error;
+use crate::{Has, Push, XSpanIdString};
+use futures::future::{BoxFuture, FutureExt};
+use headers::{ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch, LastModified};
+use hyper::body::{Body, Bytes};
+use hyper::header::{HeaderMap, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE, ETAG};
+use hyper::service::Service;
+use hyper::{Method, Request, Response, StatusCode};
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+/// Default maximum size of response bodies hashed to make entity tags.
+const DEFAULT_MAX_BODY_SIZE: u64 = 1024 * 1024;
+
+/// A slot in the request context in which the API implementation can set a
+/// version token identifying the representation it returns, to be used as
+/// its entity tag instead of a hash of the body.
+#[derive(Clone, Default)]
+pub struct VersionToken(Arc<Mutex<Option<String>>>);
+
+impl VersionToken {
+    /// Set the version token. Tokens are used as the opaque part of the
+    /// entity tag, so shouldn't contain `"`.
+    pub fn set(&self, token: impl Into<String>) {
+        *self.0.lock().expect("version token lock poisoned") = Some(token.into());
+    }
+
+    /// The version token, if set.
+    pub fn get(&self) -> Option<String> {
+        self.0.lock().expect("version token lock poisoned").clone()
+    }
+}
+
+impl fmt::Debug for VersionToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("VersionToken").field(&self.get()).finish()
+    }
+}
+
+/// How entity tags are made.
+#[derive(Debug, Clone, Copy)]
+struct Config {
+    weak: bool,
+    max_body_size: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            weak: false,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+}
+
+impl Config {
+    /// Make an entity tag header from an opaque tag.
+    fn etag(&self, tag: &str) -> Option<HeaderValue> {
+        let prefix = if self.weak { "W/" } else { "" };
+        HeaderValue::from_str(&format!("{}\"{}\"", prefix, tag)).ok()
+    }
+}
+
+/// 64-bit FNV-1a hash, which is stable between runs and versions, unlike
+/// the standard library's hashers.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The validators of a conditional request.
+#[derive(Debug)]
+struct Conditions {
+    if_none_match: Option<IfNoneMatch>,
+    if_modified_since: Option<IfModifiedSince>,
+}
+
+impl Conditions {
+    fn new<B>(req: &Request<B>) -> Option<Self> {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return None;
+        }
+        Some(Conditions {
+            if_none_match: req.headers().typed_get(),
+            if_modified_since: req.headers().typed_get(),
+        })
+    }
+
+    /// Whether the client's copy of a representation with these headers is
+    /// up to date.
+    fn not_modified(&self, headers: &HeaderMap) -> bool {
+        match &self.if_none_match {
+            Some(if_none_match) => headers
+                .typed_get::<ETag>()
+                .is_some_and(|etag| !if_none_match.precondition_passes(&etag)),
+            None => match (&self.if_modified_since, headers.typed_get::<LastModified>()) {
+                (Some(since), Some(modified)) => !since.is_modified(modified.into()),
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Set the entity tag of a response, and replace it by `304 Not Modified` if
+/// the client's copy is up to date.
+async fn validate<B>(
+    response: Response<B>,
+    conditions: Conditions,
+    token: VersionToken,
+    config: Config,
+    x_span_id: String,
+) -> Response<B>
+where
+    B: Body<Data = Bytes> + From<Bytes> + From<String>,
+{
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+    let (mut parts, mut body) = response.into_parts();
+
+    if !parts.headers.contains_key(ETAG) {
+        let etag = if let Some(token) = token.get() {
+            config.etag(&token)
+        } else {
+            match body.size_hint().exact() {
+                Some(length) if length <= config.max_body_size => {
+                    let data = match collect_limited(body, config.max_body_size).await {
+                        Ok(data) => data,
+                        Err(_) => {
+                            log::warn!(
+                                target: "swagger::etag",
+                                "Failed to read response body to compute ETag (X-Span-ID {})",
+                                x_span_id
+                            );
+                            return json_error(
+                                StatusCode::INTERNAL_SERVER_ERROR,
+                                "Internal Server Error",
+                                Some(&x_span_id),
+                            );
+                        }
+                    };
+                    let etag = config.etag(&format!("{:x}-{:016x}", data.len(), fnv1a(&data)));
+                    body = B::from(data);
+                    etag
+                }
+                _ => None,
+            }
+        };
+        if let Some(etag) = etag {
+            parts.headers.insert(ETAG, etag);
+        }
+    }
+
+    if conditions.not_modified(&parts.headers) {
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(CONTENT_LENGTH);
+        parts.headers.remove(CONTENT_TYPE);
+        return Response::from_parts(parts, B::from(String::new()));
+    }
+    Response::from_parts(parts, body)
+}
+
+/// Middleware wrapper service which sets entity tags on responses and
+/// answers conditional requests, adding a `VersionToken` to the context of
+/// every request.
+#[derive(Debug)]
+pub struct ETagMakeService<T, C> {
+    inner: T,
+    config: Config,
+    marker: PhantomData<fn(C)>,
+}
+
+impl<T, C> ETagMakeService<T, C> {
+    /// Create a new ETagMakeService, making strong entity tags and hashing
+    /// bodies of up to 1MiB.
+    pub fn new(inner: T) -> Self {
+        ETagMakeService {
+            inner,
+            config: Config::default(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Make weak entity tags.
+    pub fn weak(mut self, weak: bool) -> Self {
+        self.config.weak = weak;
+        self
+    }
+
+    /// Set the maximum size of response bodies hashed to make entity tags.
+    pub fn max_body_size(mut self, max_body_size: u64) -> Self {
+        self.config.max_body_size = max_body_size;
+        self
+    }
+}
+
+impl<Inner, C, Target> Service<Target> for ETagMakeService<Inner, C>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = ETagService<Inner::Response, C>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let config = self.config;
+        Box::pin(self.inner.call(target).map(move |s| {
+            Ok(ETagService {
+                inner: s?,
+                config,
+                marker: PhantomData,
+            })
+        }))
+    }
+}
+
+/// Middleware wrapper service which sets entity tags on responses and
+/// answers conditional requests. Servers will normally want to use
+/// `ETagMakeService`, which will create an `ETagService` for each
+/// connection.
+#[derive(Debug)]
+pub struct ETagService<T, C> {
+    inner: T,
+    config: Config,
+    marker: PhantomData<fn(C)>,
+}
+
+impl<T, C> ETagService<T, C> {
+    /// Create a new ETagService, making strong entity tags and hashing
+    /// bodies of up to 1MiB.
+    pub fn new(inner: T) -> Self {
+        ETagService {
+            inner,
+            config: Config::default(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Make weak entity tags.
+    pub fn weak(mut self, weak: bool) -> Self {
+        self.config.weak = weak;
+        self
+    }
+
+    /// Set the maximum size of response bodies hashed to make entity tags.
+    pub fn max_body_size(mut self, max_body_size: u64) -> Self {
+        self.config.max_body_size = max_body_size;
+        self
+    }
+}
+
+impl<T: Clone, C> Clone for ETagService<T, C> {
+    fn clone(&self) -> Self {
+        ETagService {
+            inner: self.inner.clone(),
+            config: self.config,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, C, ReqBody, ResBody> Service<(Request<ReqBody>, C)> for ETagService<T, C>
+where
+    C: Has<XSpanIdString> + Push<VersionToken>,
+    T: Service<(Request<ReqBody>, C::Result), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    T::Error: Send + 'static,
+    ResBody: Body<Data = Bytes> + From<Bytes> + From<String> + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let token = VersionToken::default();
+        let Some(conditions) = Conditions::new(&req) else {
+            return Box::pin(self.inner.call((req, context.push(token))));
+        };
+        let x_span_id = Has::<XSpanIdString>::get(&context).to_string();
+        let config = self.config;
+        let response = self.inner.call((req, context.push(token.clone())));
+        Box::pin(async move {
+            Ok(validate(response.await?, conditions, token, config, x_span_id).await)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+    use hyper::header::{IF_NONE_MATCH, LAST_MODIFIED};
+
+    crate::new_context_type!(TestContext, TestEmptyContext, VersionToken, XSpanIdString);
+
+    type Context = TestContext<XSpanIdString, TestEmptyContext>;
+
+    struct TestApi;
+
+    impl<C: Has<VersionToken>> Service<(Request<()>, C)> for TestApi {
+        type Response = Response<Full<Bytes>>;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, context): (Request<()>, C)) -> Self::Future {
+            let mut response = Response::new(Full::from("Rex"));
+            match req.uri().path() {
+                "/token" => Has::<VersionToken>::get(&context).set("v7"),
+                "/header" => {
+                    let headers = response.headers_mut();
+                    headers.insert(ETAG, HeaderValue::from_static("W/\"h\""));
+                    headers.insert(
+                        LAST_MODIFIED,
+                        HeaderValue::from_static("Tue, 15 Nov 1994 12:45:26 GMT"),
+                    );
+                }
+                _ => {}
+            }
+            futures::future::ok(response)
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_conditional_requests() {
+        let service = ETagService::<_, Context>::new(TestApi);
+        let call = |path: &str, headers: &[(&str, &str)]| {
+            let mut request = Request::builder().uri(path);
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+            let context = TestEmptyContext.push(XSpanIdString("span".to_string()));
+            service.call((request.body(()).unwrap(), context))
+        };
+
+        let response = call("/hash", &[]).await.unwrap();
+        let etag = response.headers()[ETAG].to_str().unwrap().to_string();
+        assert_eq!(etag, format!("\"3-{:016x}\"", fnv1a(b"Rex")));
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "Rex");
+
+        let response = call("/hash", &[(IF_NONE_MATCH.as_str(), &etag)])
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[ETAG], etag.as_str());
+
+        let response = call("/token", &[(IF_NONE_MATCH.as_str(), "\"v6\"")])
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ETAG], "\"v7\"");
+
+        // If-None-Match uses weak comparison
+        let response = call("/header", &[(IF_NONE_MATCH.as_str(), "\"h\"")])
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        let response = call(
+            "/header",
+            &[("if-modified-since", "Tue, 15 Nov 1994 12:45:26 GMT")],
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let service = ETagService::<_, Context>::new(TestApi).weak(true);
+        let context = TestEmptyContext.push(XSpanIdString("span".to_string()));
+        let request = Request::builder().uri("/token").body(()).unwrap();
+        let response = service.call((request, context)).await.unwrap();
+        assert_eq!(response.headers()[ETAG], "W/\"v7\"");
+    }
+}
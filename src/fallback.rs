@@ -0,0 +1,373 @@
+//! Hyper services for handling requests which don't match any API operation.
+//!
+//! `FallbackService` wraps an API service, passing requests which the API's
+//! `RequestParser` doesn't recognise to a fallback service instead. Both take
+//! the same `(Request, Context)` pair, so the fallback can log or inspect
+//! authentication data just like the API itself.
+//!
+//! `DefaultFallback` is a fallback responding with a JSON "not found" error
+//! including the request's X-Span-ID. `MethodNotAllowed` wraps another
+//! fallback, responding with `405 Method Not Allowed` and an `Allow` header
+//! to requests for paths in a route table which don't support the request's
+//! method, so that these aren't reported as unknown paths.
+
+use crate::response::json_error;
+use crate::routes::{allow_header, Routes};
+use crate::{Has, RequestParser, XSpanIdString};
+use futures::future::{Either, FutureExt};
+use hyper::header::ALLOW;
+use hyper::service::Service;
+use hyper::{Request, Response, StatusCode};
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// Fallback service returning a JSON `404 Not Found` response.
+///
+/// The response body has the form
+/// `{"code":404,"message":"Not Found","x-span-id":"<X-Span-ID>"}`, and the
+/// X-Span-ID is also returned in the response headers.
+pub struct DefaultFallback<B, E> {
+    marker: PhantomData<fn() -> (B, E)>,
+}
+
+impl<B, E> DefaultFallback<B, E> {
+    /// Create a new DefaultFallback
+    pub fn new() -> Self {
+        DefaultFallback {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<B, E> Default for DefaultFallback<B, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B, E> Clone for DefaultFallback<B, E> {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+impl<B, E> fmt::Debug for DefaultFallback<B, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DefaultFallback")
+    }
+}
+
+impl<B, E, ReqBody, C> Service<(Request<ReqBody>, C)> for DefaultFallback<B, E>
+where
+    B: From<String>,
+    C: Has<XSpanIdString>,
+{
+    type Response = Response<B>;
+    type Error = E;
+    type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (_, context): (Request<ReqBody>, C)) -> Self::Future {
+        let x_span_id = Has::<XSpanIdString>::get(&context);
+
+        futures::future::ok(json_error(
+            StatusCode::NOT_FOUND,
+            "Not Found",
+            Some(&x_span_id.0),
+        ))
+    }
+}
+
+/// Fallback service returning a JSON `405 Method Not Allowed` response, with
+/// an `Allow` header, for requests whose path is in the route table, and
+/// passing other requests to the wrapped fallback.
+///
+/// ```rust
+/// # use hyper::Method;
+/// # use swagger::fallback::{DefaultFallback, MethodNotAllowed};
+/// # use swagger::Routes;
+/// let routes = Routes::new().route("/pets", [Method::GET, Method::POST]);
+/// let fallback = MethodNotAllowed::new(routes, DefaultFallback::<String, ()>::new());
+/// ```
+#[derive(Debug, Clone)]
+pub struct MethodNotAllowed<F> {
+    routes: Arc<Routes>,
+    fallback: F,
+}
+
+impl<F> MethodNotAllowed<F> {
+    /// Create a new MethodNotAllowed, using `fallback` for unknown paths.
+    pub fn new(routes: Routes, fallback: F) -> Self {
+        MethodNotAllowed {
+            routes: Arc::new(routes),
+            fallback,
+        }
+    }
+}
+
+impl<F, B, ReqBody, C> Service<(Request<ReqBody>, C)> for MethodNotAllowed<F>
+where
+    F: Service<(Request<ReqBody>, C), Response = Response<B>>,
+    B: From<String>,
+    C: Has<XSpanIdString>,
+{
+    type Response = Response<B>;
+    type Error = F::Error;
+    type Future = Either<futures::future::Ready<Result<Response<B>, F::Error>>, F::Future>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let methods = match self.routes.allowed(req.uri().path()) {
+            Some(methods) if !methods.contains(req.method()) => methods,
+            _ => return Either::Right(self.fallback.call((req, context))),
+        };
+
+        let x_span_id = Has::<XSpanIdString>::get(&context);
+        let mut response = json_error(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "Method Not Allowed",
+            Some(&x_span_id.0),
+        );
+        response.headers_mut().insert(ALLOW, allow_header(methods));
+        Either::Left(futures::future::ok(response))
+    }
+}
+
+/// Make service creating a clone of the wrapped fallback service for each
+/// connection. Useful for passing a fallback to `CompositeMakeService::set_fallback`
+/// (wrapped in an `AddContextMakeService`), e.g.
+///
+/// ```ignore
+/// composite_make_service.set_fallback(AddContextMakeService::<_, EmptyContext>::new(
+///     MakeFallback::new(DefaultFallback::new()),
+/// ));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MakeFallback<F> {
+    fallback: F,
+}
+
+impl<F> MakeFallback<F> {
+    /// Create a new MakeFallback, cloning `fallback` for each connection.
+    pub fn new(fallback: F) -> Self {
+        MakeFallback { fallback }
+    }
+}
+
+impl<F, Target> Service<Target> for MakeFallback<F>
+where
+    F: Clone,
+{
+    type Response = F;
+    type Error = std::convert::Infallible;
+    type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn call(&self, _target: Target) -> Self::Future {
+        futures::future::ok(self.fallback.clone())
+    }
+}
+
+/// Middleware wrapper service which passes requests that the `RequestParser`
+/// `RP` can't match to an operation to the fallback service, rather than to
+/// the API service. This should usually be used directly around the generated
+/// API service.
+pub struct FallbackMakeService<T, F, RP> {
+    inner: T,
+    fallback: F,
+    marker: PhantomData<fn(RP)>,
+}
+
+impl<T, F, RP> FallbackMakeService<T, F, RP> {
+    /// Create a new FallbackMakeService, cloning `fallback` for each connection.
+    pub fn new(inner: T, fallback: F) -> Self {
+        FallbackMakeService {
+            inner,
+            fallback,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, F, RP> fmt::Debug for FallbackMakeService<T, F, RP>
+where
+    T: fmt::Debug,
+    F: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FallbackMakeService")
+            .field("inner", &self.inner)
+            .field("fallback", &self.fallback)
+            .finish()
+    }
+}
+
+impl<Inner, F, RP, Target> Service<Target> for FallbackMakeService<Inner, F, RP>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+    F: Clone + Send + 'static,
+{
+    type Response = FallbackService<Inner::Response, F, RP>;
+    type Error = Inner::Error;
+    type Future = futures::future::BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let fallback = self.fallback.clone();
+        Box::pin(
+            self.inner
+                .call(target)
+                .map(|s| Ok(FallbackService::new(s?, fallback))),
+        )
+    }
+}
+
+/// Middleware wrapper service which passes requests that the `RequestParser`
+/// `RP` can't match to an operation to the fallback service, rather than to
+/// the API service. Servers will normally want to use `FallbackMakeService`,
+/// which will create a `FallbackService` for each connection.
+pub struct FallbackService<T, F, RP> {
+    inner: T,
+    fallback: F,
+    marker: PhantomData<fn(RP)>,
+}
+
+impl<T, F, RP> FallbackService<T, F, RP> {
+    /// Create a new FallbackService
+    pub fn new(inner: T, fallback: F) -> Self {
+        FallbackService {
+            inner,
+            fallback,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, F, RP> Clone for FallbackService<T, F, RP>
+where
+    T: Clone,
+    F: Clone,
+{
+    fn clone(&self) -> Self {
+        Self::new(self.inner.clone(), self.fallback.clone())
+    }
+}
+
+impl<T, F, RP> fmt::Debug for FallbackService<T, F, RP>
+where
+    T: fmt::Debug,
+    F: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FallbackService")
+            .field("inner", &self.inner)
+            .field("fallback", &self.fallback)
+            .finish()
+    }
+}
+
+impl<T, F, RP, B, C> Service<(Request<B>, C)> for FallbackService<T, F, RP>
+where
+    RP: RequestParser<B>,
+    T: Service<(Request<B>, C)>,
+    F: Service<(Request<B>, C), Response = T::Response, Error = T::Error>,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = Either<T::Future, F::Future>;
+
+    fn call(&self, req: (Request<B>, C)) -> Self::Future {
+        if RP::parse_operation_id(&req.0).is_some() {
+            Either::Left(self.inner.call(req))
+        } else {
+            Either::Right(self.fallback.call(req))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContextBuilder, EmptyContext, Push, X_SPAN_ID};
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::Bytes;
+    use hyper::header::CONTENT_TYPE;
+    use hyper::Method;
+
+    struct TestParser;
+
+    impl<B> RequestParser<B> for TestParser {
+        fn parse_operation_id(req: &Request<B>) -> Option<&'static str> {
+            match req.uri().path() {
+                "/pets" => Some("listPets"),
+                _ => None,
+            }
+        }
+    }
+
+    type TestContext = ContextBuilder<XSpanIdString, EmptyContext>;
+
+    #[derive(Clone)]
+    struct TestApi;
+
+    impl Service<(Request<()>, TestContext)> for TestApi {
+        type Response = Response<Full<Bytes>>;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _req: (Request<()>, TestContext)) -> Self::Future {
+            futures::future::ok(Response::new(Full::from("pets")))
+        }
+    }
+
+    #[tokio::test]
+    async fn unmatched_requests_use_fallback() {
+        let service: FallbackService<_, _, TestParser> =
+            FallbackService::new(TestApi, DefaultFallback::new());
+        let context = EmptyContext.push(XSpanIdString("span".to_string()));
+
+        let response = service
+            .call((Request::get("/pets").body(()).unwrap(), context.clone()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = service
+            .call((Request::get("/cats").body(()).unwrap(), context))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[X_SPAN_ID], "span");
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(
+            body,
+            r#"{"code":404,"message":"Not Found","x-span-id":"span"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn known_paths_are_method_not_allowed() {
+        let routes = Routes::new().route("/pets/{id}", [Method::GET, Method::POST]);
+        let fallback = MethodNotAllowed::new(routes, DefaultFallback::<Full<Bytes>, ()>::new());
+        let service: FallbackService<_, _, TestParser> = FallbackService::new(TestApi, fallback);
+        let request = |method, path| {
+            let request = Request::builder().method(method).uri(path);
+            let context = EmptyContext.push(XSpanIdString("span".to_string()));
+            (request.body(()).unwrap(), context)
+        };
+
+        let response = service
+            .call(request(Method::DELETE, "/pets/1"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[ALLOW], "GET, POST");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(
+            body,
+            r#"{"code":405,"message":"Method Not Allowed","x-span-id":"span"}"#
+        );
+
+        let response = service.call(request(Method::GET, "/cats")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
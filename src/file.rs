@@ -0,0 +1,280 @@
+//! Streaming file bodies.
+//!
+//! `File` is a request or response body for `type: string, format: binary`
+//! operations, which streams its contents rather than holding the whole file
+//! in memory, along with the file's content type, name and length.
+//!
+//! ```rust
+//! # use hyper::body::Incoming;
+//! # use hyper::{Request, Response};
+//! # use swagger::File;
+//! # fn handle(request: Request<Incoming>) -> Response<File> {
+//! // Receive an upload...
+//! let (parts, body) = request.into_parts();
+//! let upload = File::from_parts(&parts.headers, body);
+//!
+//! // ... or send a download
+//! File::from(b"%PDF-1.7".to_vec())
+//!     .with_content_type("application/pdf")
+//!     .with_filename("report.pdf")
+//!     .into_response()
+//! # }
+//! ```
+
+use crate::body_ext::{collect_limited, CollectError};
+use crate::header::content_disposition;
+use futures::stream::{Stream, TryStreamExt};
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyExt, Full, StreamBody};
+use hyper::body::{Body, Bytes, Frame, Incoming, SizeHint};
+use hyper::header::{HeaderMap, HeaderValue, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::Response;
+use std::error::Error;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Content type used for files without a content type.
+const OCTET_STREAM: &str = "application/octet-stream";
+
+/// A file, streamed as a request or response body.
+pub struct File {
+    body: UnsyncBoxBody<Bytes, Box<dyn Error + Send + Sync>>,
+    content_type: Option<String>,
+    filename: Option<String>,
+    length: Option<u64>,
+}
+
+impl fmt::Debug for File {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("File")
+            .field("content_type", &self.content_type)
+            .field("filename", &self.filename)
+            .field("length", &self.length)
+            .finish_non_exhaustive()
+    }
+}
+
+impl File {
+    /// Create a file streaming the contents of `body`. The length is taken
+    /// from the body's size hint, if it's exact.
+    pub fn new<B>(body: B) -> Self
+    where
+        B: Body<Data = Bytes> + Send + 'static,
+        B::Error: Into<Box<dyn Error + Send + Sync>>,
+    {
+        let length = body.size_hint().exact();
+        File {
+            body: body.map_err(Into::into).boxed_unsync(),
+            content_type: None,
+            filename: None,
+            length,
+        }
+    }
+
+    /// Create a file streaming the chunks of `stream`.
+    pub fn from_stream<S, E>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+        E: Into<Box<dyn Error + Send + Sync>> + 'static,
+    {
+        Self::new(StreamBody::new(
+            stream
+                .map_ok(Frame::data)
+                .map_err(Into::<Box<dyn Error + Send + Sync>>::into),
+        ))
+    }
+
+    /// Create a file from a request or response body, taking the content
+    /// type, name and length from the `Content-Type`, `Content-Disposition`
+    /// and `Content-Length` headers.
+    pub fn from_parts<B>(headers: &HeaderMap, body: B) -> Self
+    where
+        B: Body<Data = Bytes> + Send + 'static,
+        B::Error: Into<Box<dyn Error + Send + Sync>>,
+    {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value: &HeaderValue| value.to_str().ok())
+        };
+
+        let mut file = Self::new(body);
+        file.content_type = header(CONTENT_TYPE).map(str::to_string);
+        file.filename = header(CONTENT_DISPOSITION).and_then(|value| content_disposition(value).1);
+        if let Some(length) = header(CONTENT_LENGTH).and_then(|value| value.parse().ok()) {
+            file.length = Some(length);
+        }
+        file
+    }
+
+    /// Set the content type of the file.
+    pub fn with_content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    /// Set the name of the file.
+    pub fn with_filename(mut self, filename: impl Into<String>) -> Self {
+        self.filename = Some(filename.into());
+        self
+    }
+
+    /// Set the length of the file, if it's known but the body doesn't
+    /// report it.
+    pub fn with_length(mut self, length: u64) -> Self {
+        self.length = Some(length);
+        self
+    }
+
+    /// The content type of the file, if known.
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// The name of the file, if known.
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    /// The length of the file in bytes, if known.
+    pub fn length(&self) -> Option<u64> {
+        self.length
+    }
+
+    /// Headers describing the file: `Content-Type` (defaulting to
+    /// `application/octet-stream`), `Content-Length` if the length is known,
+    /// and `Content-Disposition` if the name is known.
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        let content_type = self.content_type.as_deref().unwrap_or(OCTET_STREAM);
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_str(content_type)
+                .unwrap_or_else(|_| HeaderValue::from_static(OCTET_STREAM)),
+        );
+        if let Some(length) = self.length {
+            headers.insert(CONTENT_LENGTH, HeaderValue::from(length));
+        }
+        if let Some(filename) = &self.filename {
+            let filename = filename.replace('\\', "\\\\").replace('"', "\\\"");
+            if let Ok(value) =
+                HeaderValue::from_str(&format!("attachment; filename=\"{}\"", filename))
+            {
+                headers.insert(CONTENT_DISPOSITION, value);
+            }
+        }
+
+        headers
+    }
+
+    /// Create a `200 OK` response streaming the file, with headers describing
+    /// it.
+    pub fn into_response(self) -> Response<File> {
+        let headers = self.headers();
+        let mut response = Response::new(self);
+        *response.headers_mut() = headers;
+        response
+    }
+
+    /// Read the whole file into memory, however large it is. Use
+    /// `bytes_limited` for files from untrusted sources, such as requests.
+    pub async fn bytes(self) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        Ok(self.body.collect().await?.to_bytes())
+    }
+
+    /// Read the whole file into memory, failing with
+    /// `CollectError::TooLarge` if it is larger than `limit` bytes.
+    pub async fn bytes_limited(
+        self,
+        limit: u64,
+    ) -> Result<Bytes, CollectError<Box<dyn Error + Send + Sync>>> {
+        collect_limited(self.body, limit).await
+    }
+}
+
+impl From<Incoming> for File {
+    fn from(body: Incoming) -> Self {
+        Self::new(body)
+    }
+}
+
+impl From<Full<Bytes>> for File {
+    fn from(body: Full<Bytes>) -> Self {
+        Self::new(body)
+    }
+}
+
+impl From<Bytes> for File {
+    fn from(data: Bytes) -> Self {
+        Self::new(Full::new(data))
+    }
+}
+
+impl From<Vec<u8>> for File {
+    fn from(data: Vec<u8>) -> Self {
+        Self::from(Bytes::from(data))
+    }
+}
+
+impl Body for File {
+    type Data = Bytes;
+    type Error = Box<dyn Error + Send + Sync>;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(&mut self.body).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self.length {
+            Some(length) => SizeHint::with_exact(length),
+            None => self.body.size_hint(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use std::convert::Infallible;
+
+    #[tokio::test]
+    async fn streams_files() {
+        let chunks = [
+            Bytes::from_static(b"Hello, "),
+            Bytes::from_static(b"World!"),
+        ];
+        let file = File::from_stream(stream::iter(chunks.map(Ok::<_, Infallible>)))
+            .with_filename("hello \"world\".txt");
+        assert_eq!(file.length(), None);
+
+        let response = file.into_response();
+        assert_eq!(response.headers()[CONTENT_TYPE], OCTET_STREAM);
+        assert_eq!(
+            response.headers()[CONTENT_DISPOSITION],
+            "attachment; filename=\"hello \\\"world\\\".txt\""
+        );
+        assert!(response.headers().get(CONTENT_LENGTH).is_none());
+
+        let (parts, body) = response.into_parts();
+        let file = File::from_parts(&parts.headers, body);
+        assert_eq!(file.filename(), Some("hello \"world\".txt"));
+        assert_eq!(file.content_type(), Some(OCTET_STREAM));
+        assert_eq!(file.bytes().await.unwrap(), "Hello, World!");
+
+        let file = File::from(b"%PDF".to_vec()).with_content_type("application/pdf");
+        assert_eq!(file.size_hint().exact(), Some(4));
+        let headers = file.headers();
+        assert_eq!(headers[CONTENT_TYPE], "application/pdf");
+        assert_eq!(headers[CONTENT_LENGTH], "4");
+    }
+}
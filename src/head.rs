@@ -0,0 +1,213 @@
+//! Hyper service answering `HEAD` requests using the API's `GET` handlers.
+//!
+//! Generated servers usually only implement `GET`, so `HEAD` requests get a
+//! `404` or `405`. `HeadService` passes `HEAD` requests on as `GET`
+//! requests, and returns the response's status and headers without its
+//! body. If the response has no `Content-Length` but its body's length is
+//! known, it's set, as it would have been for the `GET`.
+//!
+//! If given a route table, `HEAD` requests for paths whose routes include
+//! `HEAD` are passed on unchanged.
+
+use crate::routes::Routes;
+use futures::future::{BoxFuture, FutureExt};
+use hyper::body::Body;
+use hyper::header::{HeaderValue, CONTENT_LENGTH};
+use hyper::service::Service;
+use hyper::{Method, Request, Response};
+use std::future::Future;
+use std::sync::Arc;
+
+/// Convert a `HEAD` request to a `GET` request, returning whether it was
+/// converted.
+fn to_get<B>(req: &mut Request<B>, routes: Option<&Routes>) -> bool {
+    if req.method() != Method::HEAD {
+        return false;
+    }
+    let implemented = routes
+        .and_then(|routes| routes.allowed(req.uri().path()))
+        .is_some_and(|methods| methods.contains(&Method::HEAD));
+    if implemented {
+        return false;
+    }
+    *req.method_mut() = Method::GET;
+    true
+}
+
+/// Strip the body from the response to a `HEAD` request.
+fn strip_body<B, E, F>(response: F) -> BoxFuture<'static, Result<Response<B>, E>>
+where
+    F: Future<Output = Result<Response<B>, E>> + Send + 'static,
+    B: Body + From<String>,
+{
+    Box::pin(response.map(|response| {
+        let (mut parts, body) = response?.into_parts();
+        if !parts.headers.contains_key(CONTENT_LENGTH) {
+            if let Some(length) = body.size_hint().exact() {
+                parts
+                    .headers
+                    .insert(CONTENT_LENGTH, HeaderValue::from(length));
+            }
+        }
+        Ok(Response::from_parts(parts, B::from(String::new())))
+    }))
+}
+
+/// Middleware wrapper service which answers `HEAD` requests using `GET`
+/// handlers.
+#[derive(Debug)]
+pub struct HeadMakeService<T> {
+    inner: T,
+    routes: Option<Arc<Routes>>,
+}
+
+impl<T> HeadMakeService<T> {
+    /// Create a new HeadMakeService, answering all `HEAD` requests using
+    /// `GET` handlers.
+    pub fn new(inner: T) -> Self {
+        HeadMakeService {
+            inner,
+            routes: None,
+        }
+    }
+
+    /// Pass on `HEAD` requests for paths whose routes include `HEAD`.
+    pub fn routes(mut self, routes: Routes) -> Self {
+        self.routes = Some(Arc::new(routes));
+        self
+    }
+}
+
+impl<Inner, Target> Service<Target> for HeadMakeService<Inner>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = HeadService<Inner::Response>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let routes = self.routes.clone();
+        Box::pin(
+            self.inner
+                .call(target)
+                .map(|s| Ok(HeadService { inner: s?, routes })),
+        )
+    }
+}
+
+/// Middleware wrapper service which answers `HEAD` requests using `GET`
+/// handlers. Handles both plain requests, and requests carrying a context.
+#[derive(Debug, Clone)]
+pub struct HeadService<T> {
+    inner: T,
+    routes: Option<Arc<Routes>>,
+}
+
+impl<T> HeadService<T> {
+    /// Create a new HeadService, answering all `HEAD` requests using `GET`
+    /// handlers.
+    pub fn new(inner: T) -> Self {
+        HeadService {
+            inner,
+            routes: None,
+        }
+    }
+
+    /// Pass on `HEAD` requests for paths whose routes include `HEAD`.
+    pub fn routes(mut self, routes: Routes) -> Self {
+        self.routes = Some(Arc::new(routes));
+        self
+    }
+}
+
+impl<T, ReqBody, ResBody> Service<Request<ReqBody>> for HeadService<T>
+where
+    T: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    ResBody: Body + From<String>,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, mut req: Request<ReqBody>) -> Self::Future {
+        if to_get(&mut req, self.routes.as_deref()) {
+            strip_body(self.inner.call(req))
+        } else {
+            Box::pin(self.inner.call(req))
+        }
+    }
+}
+
+impl<T, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for HeadService<T>
+where
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    ResBody: Body + From<String>,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (mut req, context): (Request<ReqBody>, C)) -> Self::Future {
+        if to_get(&mut req, self.routes.as_deref()) {
+            strip_body(self.inner.call((req, context)))
+        } else {
+            Box::pin(self.inner.call((req, context)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::Bytes;
+
+    struct TestApi;
+
+    impl Service<Request<()>> for TestApi {
+        type Response = Response<Full<Bytes>>;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, req: Request<()>) -> Self::Future {
+            let body = format!("{} {}", req.method(), req.uri().path());
+            let response = Response::builder()
+                .header("x-method", req.method().as_str())
+                .body(Full::from(body))
+                .unwrap();
+            futures::future::ok(response)
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_head_requests() {
+        let service =
+            HeadService::new(TestApi).routes(Routes::new().route("/custom", [Method::HEAD]));
+        let request = |method, path| {
+            Request::builder()
+                .method(method)
+                .uri(path)
+                .body(())
+                .unwrap()
+        };
+
+        let response = service.call(request(Method::HEAD, "/pets")).await.unwrap();
+        assert_eq!(response.headers()["x-method"], "GET");
+        assert_eq!(response.headers()[CONTENT_LENGTH], "9");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(body.is_empty());
+
+        let response = service
+            .call(request(Method::HEAD, "/custom"))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["x-method"], "HEAD");
+        let response = service.call(request(Method::GET, "/pets")).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "GET /pets");
+    }
+}
@@ -32,3 +32,57 @@ impl fmt::Display for XSpanIdString {
         write!(f, "{}", self.0)
     }
 }
+
+/// Get the `name` and `filename` parameters of a `Content-Disposition`
+/// header.
+pub(crate) fn content_disposition(value: &str) -> (Option<String>, Option<String>) {
+    let mut name = None;
+    let mut filename = None;
+
+    // Skip the disposition type
+    let mut rest = match value.find(';') {
+        Some(i) => &value[i + 1..],
+        None => return (None, None),
+    };
+
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].trim().to_ascii_lowercase();
+        rest = rest[eq + 1..].trim_start();
+
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            let mut end = quoted.len();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next().map(|(_, c)| c)),
+                    '"' => {
+                        end = i + 1;
+                        break;
+                    }
+                    c => value.push(c),
+                }
+            }
+            rest = &quoted[end..];
+            value
+        } else {
+            let end = rest.find(';').unwrap_or(rest.len());
+            let value = rest[..end].trim().to_string();
+            rest = &rest[end..];
+            value
+        };
+
+        match key.as_str() {
+            "name" => name = Some(value),
+            "filename" => filename = Some(value),
+            _ => {}
+        }
+
+        rest = match rest.find(';') {
+            Some(i) => &rest[i + 1..],
+            None => "",
+        };
+    }
+
+    (name, filename)
+}
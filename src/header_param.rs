@@ -0,0 +1,162 @@
+//! Header parameters with `style: simple`, the only style OpenAPI allows
+//! for headers.
+//!
+//! Arrays are comma separated whether or not they're exploded. Objects are
+//! written as `key,value,key,value`, or as `key=value,key=value` when
+//! exploded. Names and values are percent-encoded as in RFC 6570, so that
+//! commas, `=` and characters which aren't allowed in headers survive the
+//! round trip.
+//!
+//! ```rust
+//! # use serde::{Deserialize, Serialize};
+//! # use swagger::header_param;
+//! #[derive(Debug, PartialEq, Deserialize, Serialize)]
+//! struct Point {
+//!     x: i32,
+//!     label: String,
+//! }
+//!
+//! let point = Point { x: -1, label: "a,b".to_string() };
+//! let value = header_param::to_header_value(&point, true).unwrap();
+//! assert_eq!(value, "label=a%2Cb,x=-1");
+//! assert_eq!(header_param::from_header_value::<Point>(&value, true).unwrap(), point);
+//!
+//! let ids: Vec<u64> = header_param::from_str("1,2,3", false).unwrap();
+//! assert_eq!(ids, [1, 2, 3]);
+//! ```
+//!
+//! Arrays and objects nested inside other values are encoded as JSON text.
+
+use crate::query::encode;
+use crate::urlencoded::{self, percent_decode};
+use hyper::header::HeaderValue;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+
+/// Error returned when a header parameter can't be serialized or
+/// deserialized.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidHeaderParam(String);
+
+impl fmt::Display for InvalidHeaderParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid header parameter: {}", self.0)
+    }
+}
+
+impl Error for InvalidHeaderParam {}
+
+/// Serialize a header parameter.
+pub fn to_string<T: Serialize>(value: &T, explode: bool) -> Result<String, InvalidHeaderParam> {
+    let value = serde_json::to_value(value).map_err(|e| InvalidHeaderParam(e.to_string()))?;
+    Ok(match value {
+        Value::Array(values) => {
+            let values: Vec<_> = values.iter().map(|value| encode(&text(value))).collect();
+            values.join(",")
+        }
+        Value::Object(fields) => {
+            let separator = if explode { "=" } else { "," };
+            let fields: Vec<_> = fields
+                .iter()
+                .map(|(key, value)| [encode(key), encode(&text(value))].join(separator))
+                .collect();
+            fields.join(",")
+        }
+        value => encode(&text(&value)),
+    })
+}
+
+/// Serialize a header parameter as a header value.
+pub fn to_header_value<T: Serialize>(
+    value: &T,
+    explode: bool,
+) -> Result<HeaderValue, InvalidHeaderParam> {
+    let value = to_string(value, explode)?;
+    // Everything but unreserved characters is percent-encoded, so this can't
+    // fail
+    HeaderValue::from_str(&value).map_err(|e| InvalidHeaderParam(e.to_string()))
+}
+
+/// Deserialize a header parameter.
+pub fn from_str<T: DeserializeOwned>(value: &str, explode: bool) -> Result<T, InvalidHeaderParam> {
+    let value = value.trim();
+    let decode = |value: &str| percent_decode(value.trim(), false).into_owned();
+    let parts = if value.is_empty() {
+        Vec::new()
+    } else {
+        value.split(',').collect()
+    };
+    let fields = if explode {
+        parts
+            .iter()
+            .filter_map(|part| part.split_once('='))
+            .map(|(key, value)| (decode(key), decode(value)))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let parts = parts.into_iter().map(decode).collect();
+    urlencoded::from_delimited(decode(value), parts, fields).map_err(|e| InvalidHeaderParam(e.0))
+}
+
+/// Deserialize a header parameter from a header value.
+pub fn from_header_value<T: DeserializeOwned>(
+    value: &HeaderValue,
+    explode: bool,
+) -> Result<T, InvalidHeaderParam> {
+    let value = value
+        .to_str()
+        .map_err(|_| InvalidHeaderParam("header isn't visible ASCII".to_string()))?;
+    from_str(value, explode)
+}
+
+/// The text of a value.
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Color {
+        #[serde(rename = "R")]
+        r: u8,
+        #[serde(rename = "G")]
+        g: u8,
+    }
+
+    #[test]
+    fn encodes_simple_style() {
+        let color = Color { r: 100, g: 200 };
+        assert_eq!(to_string(&color, false).unwrap(), "G,200,R,100");
+        assert_eq!(to_string(&color, true).unwrap(), "G=200,R=100");
+        assert_eq!(from_str::<Color>("R,100,G,200", false).unwrap(), color);
+        assert_eq!(from_str::<Color>("R=100, G=200", true).unwrap(), color);
+
+        let words = vec!["a b".to_string(), "c,d".to_string(), "e=f+g".to_string()];
+        let value = to_header_value(&words, true).unwrap();
+        assert_eq!(value, "a%20b,c%2Cd,e%3Df%2Bg");
+        assert_eq!(
+            from_header_value::<Vec<String>>(&value, true).unwrap(),
+            words
+        );
+
+        let map: BTreeMap<String, String> = from_str("a=1,b=x%3Dy", true).unwrap();
+        assert_eq!(map["b"], "x=y");
+        assert_eq!(to_string(&"caf\u{e9}", false).unwrap(), "caf%C3%A9");
+        assert_eq!(from_str::<String>("a+b%2C", false).unwrap(), "a+b,");
+        assert_eq!(from_str::<Vec<u32>>("", false).unwrap(), Vec::<u32>::new());
+        assert!(from_str::<Vec<u32>>("1,x", false).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+//! Conversions between header values and the types used for header
+//! parameters and response headers.
+//!
+//! `IntoHeaderValue` wraps any type implementing `HeaderFormat`, and can be
+//! converted to and from a `HeaderValue` with `TryFrom`, returning an error
+//! rather than panicking or losing data when a header isn't valid:
+//!
+//! ```rust
+//! # use hyper::header::HeaderValue;
+//! # use swagger::IntoHeaderValue;
+//! let value = HeaderValue::try_from(IntoHeaderValue(vec![1u32, 2, 3])).unwrap();
+//! assert_eq!(value, "1,2,3");
+//!
+//! let IntoHeaderValue(ids) = IntoHeaderValue::<Vec<u32>>::try_from(&value).unwrap();
+//! assert_eq!(ids, [1, 2, 3]);
+//!
+//! assert!(IntoHeaderValue::<bool>::try_from(HeaderValue::from_static("yes")).is_err());
+//! ```
+//!
+//! Strings may contain any UTF-8 characters other than control characters.
+//! Lists are comma separated, so the items of a list mustn't contain commas -
+//! see the `header_param` module for lists and objects which need to be
+//! escaped. Dates and date-times are written as in RFC 3339.
+
+use hyper::header::HeaderValue;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use uuid::Uuid;
+
+/// Error returned when a value can't be converted to or from a header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidHeader(String);
+
+impl fmt::Display for InvalidHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid header: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidHeader {}
+
+/// Types which can be written to, and read from, a header.
+pub trait HeaderFormat: Sized {
+    /// Format as the text of a header.
+    fn format_header(&self) -> String;
+
+    /// Parse the text of a header.
+    fn parse_header(s: &str) -> Result<Self, InvalidHeader>;
+}
+
+/// Implement `HeaderFormat` using `Display` and `FromStr`.
+macro_rules! header_format_from_str {
+    ($($ty:ty),*) => {
+        $(
+            impl HeaderFormat for $ty {
+                fn format_header(&self) -> String {
+                    self.to_string()
+                }
+
+                fn parse_header(s: &str) -> Result<Self, InvalidHeader> {
+                    s.trim()
+                        .parse()
+                        .map_err(|e| InvalidHeader(format!("{:?}: {}", s, e)))
+                }
+            }
+        )*
+    };
+}
+
+header_format_from_str!(bool, i8, i16, i32, i64, u8, u16, u32, u64, f32, f64, Uuid);
+
+impl HeaderFormat for String {
+    fn format_header(&self) -> String {
+        self.clone()
+    }
+
+    fn parse_header(s: &str) -> Result<Self, InvalidHeader> {
+        Ok(s.to_string())
+    }
+}
+
+/// A comma separated list.
+impl<T: HeaderFormat> HeaderFormat for Vec<T> {
+    fn format_header(&self) -> String {
+        let items: Vec<_> = self.iter().map(HeaderFormat::format_header).collect();
+        items.join(",")
+    }
+
+    fn parse_header(s: &str) -> Result<Self, InvalidHeader> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(T::parse_header)
+            .collect()
+    }
+}
+
+/// Implement `HeaderFormat` for dates and date-times.
+#[cfg(any(feature = "chrono", feature = "time"))]
+macro_rules! header_format_date {
+    ($trait:ident, $format:ident, $parse:expr, $($ty:ty),*) => {
+        $(
+            impl HeaderFormat for $ty {
+                fn format_header(&self) -> String {
+                    crate::date_format::$trait::$format(self)
+                }
+
+                fn parse_header(s: &str) -> Result<Self, InvalidHeader> {
+                    $parse(s.trim()).map_err(|e| InvalidHeader(e.to_string()))
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "chrono")]
+header_format_date!(
+    DateFormat,
+    format_date,
+    <chrono::NaiveDate as crate::date_format::DateFormat>::parse_date,
+    chrono::NaiveDate
+);
+
+#[cfg(feature = "chrono")]
+header_format_date!(
+    DateTimeFormat,
+    format_date_time,
+    |s| crate::date_format::DateTimeFormat::parse_date_time(
+        s,
+        crate::date_format::ParseOptions::strict()
+    ),
+    chrono::DateTime<chrono::Utc>,
+    chrono::DateTime<chrono::FixedOffset>
+);
+
+#[cfg(feature = "time")]
+header_format_date!(
+    DateFormat,
+    format_date,
+    <time::Date as crate::date_format::DateFormat>::parse_date,
+    time::Date
+);
+
+#[cfg(feature = "time")]
+header_format_date!(
+    DateTimeFormat,
+    format_date_time,
+    |s| crate::date_format::DateTimeFormat::parse_date_time(
+        s,
+        crate::date_format::ParseOptions::strict()
+    ),
+    time::OffsetDateTime
+);
+
+/// Wrapper for converting a value to and from a `HeaderValue`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct IntoHeaderValue<T>(pub T);
+
+impl<T> Deref for IntoHeaderValue<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for IntoHeaderValue<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: HeaderFormat> TryFrom<&IntoHeaderValue<T>> for HeaderValue {
+    type Error = InvalidHeader;
+
+    fn try_from(value: &IntoHeaderValue<T>) -> Result<Self, InvalidHeader> {
+        let text = value.0.format_header();
+        HeaderValue::from_str(&text).map_err(|_| InvalidHeader(format!("{:?}", text)))
+    }
+}
+
+impl<T: HeaderFormat> TryFrom<IntoHeaderValue<T>> for HeaderValue {
+    type Error = InvalidHeader;
+
+    fn try_from(value: IntoHeaderValue<T>) -> Result<Self, InvalidHeader> {
+        HeaderValue::try_from(&value)
+    }
+}
+
+impl<T: HeaderFormat> TryFrom<&HeaderValue> for IntoHeaderValue<T> {
+    type Error = InvalidHeader;
+
+    fn try_from(value: &HeaderValue) -> Result<Self, InvalidHeader> {
+        let text = std::str::from_utf8(value.as_bytes())
+            .map_err(|_| InvalidHeader(format!("{:?} isn't UTF-8", value)))?;
+        T::parse_header(text).map(IntoHeaderValue)
+    }
+}
+
+impl<T: HeaderFormat> TryFrom<HeaderValue> for IntoHeaderValue<T> {
+    type Error = InvalidHeader;
+
+    fn try_from(value: HeaderValue) -> Result<Self, InvalidHeader> {
+        IntoHeaderValue::try_from(&value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: HeaderFormat>(value: T) -> (HeaderValue, T) {
+        let header = HeaderValue::try_from(IntoHeaderValue(value)).unwrap();
+        let IntoHeaderValue(value) = IntoHeaderValue::try_from(&header).unwrap();
+        (header, value)
+    }
+
+    #[test]
+    fn converts_headers() {
+        assert_eq!(round_trip(true), (HeaderValue::from_static("true"), true));
+        assert_eq!(round_trip(-1.5f64).1, -1.5);
+        let (header, value) = round_trip("caf\u{e9}".to_string());
+        assert_eq!(header.as_bytes(), "caf\u{e9}".as_bytes());
+        assert_eq!(value, "caf\u{e9}");
+        let (header, value) = round_trip(vec!["a".to_string(), "b".to_string()]);
+        assert_eq!((header.to_str().unwrap(), value.len()), ("a,b", 2));
+
+        let uuid = Uuid::new_v4();
+        assert_eq!(round_trip(uuid).1, uuid);
+
+        let list: IntoHeaderValue<Vec<i64>> =
+            HeaderValue::from_static(" 1, -2 ,3,").try_into().unwrap();
+        assert_eq!(*list, [1, -2, 3]);
+        assert!(IntoHeaderValue::<Vec<u8>>::try_from(HeaderValue::from_static("1,300")).is_err());
+        assert!(HeaderValue::try_from(IntoHeaderValue("a\nb".to_string())).is_err());
+        let latin1 = HeaderValue::from_bytes(b"caf\xe9").unwrap();
+        assert!(IntoHeaderValue::<String>::try_from(latin1).is_err());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn converts_date_times() {
+        use chrono::{DateTime, Utc};
+        let header = HeaderValue::from_static("2024-01-02T03:04:05+01:00");
+        let IntoHeaderValue(date_time) =
+            IntoHeaderValue::<DateTime<Utc>>::try_from(&header).unwrap();
+        assert_eq!(
+            HeaderValue::try_from(IntoHeaderValue(date_time)).unwrap(),
+            "2024-01-02T02:04:05Z"
+        );
+    }
+}
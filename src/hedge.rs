@@ -0,0 +1,364 @@
+//! Client middleware hedging slow requests.
+//!
+//! `HedgeService` sends a second copy of a request if the first hasn't
+//! completed after a delay - either fixed, or a percentile of recent request
+//! latencies - and uses whichever response arrives first, cancelling the
+//! other request. This trades extra load on the server for lower tail
+//! latency.
+//!
+//! Only idempotent requests are hedged, as the server may process both
+//! copies. By default, only `GET`, `HEAD` and `OPTIONS` requests are hedged.
+//! Request bodies are buffered so that they can be sent twice, and requests
+//! whose bodies may be longer than the policy's `max_body_size` aren't
+//! hedged.
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! # use swagger::hedge::HedgePolicy;
+//! # use swagger::retry::RetryBudget;
+//! let policy = HedgePolicy::new(Duration::from_millis(100))
+//!     .latency_percentile(0.95)
+//!     .budget(RetryBudget::new(0.1, 10));
+//! ```
+
+use crate::body_ext::{collect_limited, CollectError};
+use crate::retry::{rebuild, RetryBudget};
+use futures::future::{self, BoxFuture, Either, TryFutureExt};
+use hyper::body::{Body, Bytes};
+use hyper::service::Service;
+use hyper::{Method, Request, Response, Uri};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Number of recent latencies used to calculate the hedging delay.
+const LATENCY_SAMPLES: usize = 1000;
+
+/// Number of latencies needed before the percentile is used, rather than the
+/// fixed delay.
+const MIN_LATENCY_SAMPLES: usize = 20;
+
+/// Policy deciding which requests are hedged, when, and where to.
+#[derive(Debug, Clone)]
+pub struct HedgePolicy {
+    delay: Duration,
+    percentile: Option<f64>,
+    methods: Vec<Method>,
+    alternate: Option<Uri>,
+    max_body_size: u64,
+    budget: Option<RetryBudget>,
+}
+
+impl HedgePolicy {
+    /// Create a new hedge policy, hedging `GET`, `HEAD` and `OPTIONS`
+    /// requests which haven't completed after `delay`, with request bodies of
+    /// up to 10MiB.
+    pub fn new(delay: Duration) -> Self {
+        HedgePolicy {
+            delay,
+            percentile: None,
+            methods: vec![Method::GET, Method::HEAD, Method::OPTIONS],
+            alternate: None,
+            max_body_size: 10 * 1024 * 1024,
+            budget: None,
+        }
+    }
+
+    /// Hedge requests which take longer than the given percentile (between 0
+    /// and 1) of recent request latencies. The fixed delay is used until
+    /// enough requests have completed.
+    pub fn latency_percentile(mut self, percentile: f64) -> Self {
+        self.percentile = Some(percentile.clamp(0.0, 1.0));
+        self
+    }
+
+    /// Set the request methods which may be hedged. Methods which aren't
+    /// idempotent are ignored.
+    pub fn methods<I: IntoIterator<Item = Method>>(mut self, methods: I) -> Self {
+        self.methods = methods.into_iter().filter(Method::is_idempotent).collect();
+        self
+    }
+
+    /// Send hedged requests to an alternate endpoint. The scheme and
+    /// authority of `alternate` replace those of the original request.
+    pub fn alternate(mut self, alternate: Uri) -> Self {
+        self.alternate = Some(alternate);
+        self
+    }
+
+    /// Set the maximum size of request body, in bytes, which will be buffered
+    /// for hedging. Requests with bodies which may be longer than this are
+    /// sent once, and not hedged.
+    pub fn max_body_size(mut self, max_body_size: u64) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Limit hedged requests using a budget, which may be shared with other
+    /// services.
+    pub fn budget(mut self, budget: RetryBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+}
+
+/// Recent request latencies.
+#[derive(Debug, Default)]
+struct Latencies(VecDeque<Duration>);
+
+impl Latencies {
+    fn record(&mut self, latency: Duration) {
+        if self.0.len() == LATENCY_SAMPLES {
+            self.0.pop_front();
+        }
+        self.0.push_back(latency);
+    }
+
+    fn percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.0.len() < MIN_LATENCY_SAMPLES {
+            return None;
+        }
+        let mut sorted: Vec<_> = self.0.iter().copied().collect();
+        sorted.sort_unstable();
+        let index = ((sorted.len() - 1) as f64 * percentile).round() as usize;
+        Some(sorted[index])
+    }
+}
+
+/// Point a request at the alternate endpoint.
+fn redirect<B>(mut request: Request<B>, alternate: &Uri) -> Request<B> {
+    let mut parts = request.uri().clone().into_parts();
+    parts.scheme = alternate.scheme().cloned();
+    parts.authority = alternate.authority().cloned();
+    if let Ok(uri) = Uri::from_parts(parts) {
+        *request.uri_mut() = uri;
+    }
+    request
+}
+
+/// Client middleware hedging slow requests according to a `HedgePolicy`.
+///
+/// Put it inside `RetryService`, so that each attempt can be hedged, and share
+/// a `RetryBudget` between their policies to limit the extra load they add
+/// together.
+#[derive(Clone)]
+pub struct HedgeService<T> {
+    inner: T,
+    policy: Arc<HedgePolicy>,
+    latencies: Arc<Mutex<Latencies>>,
+}
+
+impl<T> HedgeService<T> {
+    /// Create a new HedgeService, hedging requests according to `policy`.
+    pub fn new(inner: T, policy: HedgePolicy) -> Self {
+        HedgeService {
+            inner,
+            policy: Arc::new(policy),
+            latencies: Arc::default(),
+        }
+    }
+
+    /// The delay before a request is hedged.
+    pub fn delay(&self) -> Duration {
+        self.policy
+            .percentile
+            .and_then(|percentile| self.latencies.lock().unwrap().percentile(percentile))
+            .unwrap_or(self.policy.delay)
+    }
+}
+
+impl<T> fmt::Debug for HedgeService<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HedgeService")
+            .field("inner", &self.inner)
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for HedgeService<T>
+where
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>> + Clone + Send + 'static,
+    T::Future: Send + 'static,
+    T::Error: Into<Box<dyn Error + Send + Sync>>,
+    ReqBody: Body + From<Bytes> + Send + 'static,
+    ReqBody::Data: Send,
+    ReqBody::Error: Into<Box<dyn Error + Send + Sync>>,
+    C: Clone + Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let policy = self.policy.clone();
+        if let Some(budget) = &policy.budget {
+            budget.deposit();
+        }
+
+        // Only bodies known to fit within the limit are buffered, as a longer
+        // body can't be sent once part of it has been read
+        let body_fits = req
+            .body()
+            .size_hint()
+            .upper()
+            .is_some_and(|upper| upper <= policy.max_body_size);
+        if !policy.methods.contains(req.method()) || !body_fits {
+            return Box::pin(self.inner.call((req, context)).map_err(Into::into));
+        }
+
+        let inner = self.inner.clone();
+        let latencies = self.latencies.clone();
+        let delay = self.delay();
+        Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            let body = collect_limited(body, policy.max_body_size)
+                .await
+                .map_err(CollectError::into_boxed)?;
+
+            let start = Instant::now();
+            let primary = inner
+                .call((rebuild(&parts, &body), context.clone()))
+                .map_err(Into::into);
+            let timer = tokio::time::sleep(delay);
+            futures::pin_mut!(primary, timer);
+
+            let primary = match future::select(primary, timer).await {
+                Either::Left((result, _)) => {
+                    latencies.lock().unwrap().record(start.elapsed());
+                    return result;
+                }
+                Either::Right((_, primary)) => primary,
+            };
+
+            if !policy.budget.as_ref().is_none_or(RetryBudget::withdraw) {
+                let result = primary.await;
+                latencies.lock().unwrap().record(start.elapsed());
+                return result;
+            }
+
+            let mut request = rebuild(&parts, &body);
+            if let Some(alternate) = &policy.alternate {
+                request = redirect(request, alternate);
+            }
+            let hedge = inner.call((request, context)).map_err(Into::into);
+            futures::pin_mut!(hedge);
+
+            // Use the first successful response, dropping - and so
+            // cancelling - the other request.
+            let (first, other) = match future::select(primary, hedge).await {
+                Either::Left((result, hedge)) => (result, Either::Left(hedge)),
+                Either::Right((result, primary)) => (result, Either::Right(primary)),
+            };
+            latencies.lock().unwrap().record(start.elapsed());
+
+            let succeeded = |result: &Result<Response<ResBody>, Self::Error>| match result {
+                Ok(response) => !response.status().is_server_error(),
+                Err(_) => false,
+            };
+
+            if succeeded(&first) {
+                return first;
+            }
+
+            let second = other.await;
+            if succeeded(&second) {
+                second
+            } else {
+                first
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::Full;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Service which is slow to respond to its first request, and responds
+    /// with the authority each request was sent to.
+    #[derive(Clone)]
+    struct SlowFirst {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Service<(Request<Full<Bytes>>, ())> for SlowFirst {
+        type Response = Response<String>;
+        type Error = std::io::Error;
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, _): (Request<Full<Bytes>>, ())) -> Self::Future {
+            let first = self.calls.fetch_add(1, Ordering::SeqCst) == 0;
+            let authority = req.uri().authority().unwrap().to_string();
+            Box::pin(async move {
+                if first {
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                }
+                Ok(Response::new(authority))
+            })
+        }
+    }
+
+    fn request(method: Method) -> Request<Full<Bytes>> {
+        let mut request = Request::new(Full::new(Bytes::new()));
+        *request.method_mut() = method;
+        *request.uri_mut() = "http://primary/pets".parse().unwrap();
+        request
+    }
+
+    #[tokio::test]
+    async fn hedges_slow_requests() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let service = HedgeService::new(
+            SlowFirst {
+                calls: calls.clone(),
+            },
+            HedgePolicy::new(Duration::from_millis(50))
+                .alternate("http://secondary".parse().unwrap()),
+        );
+
+        let response = service.call((request(Method::GET), ())).await.unwrap();
+        assert_eq!(response.body(), "secondary");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_hedge_unsafe_requests() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let service = HedgeService::new(
+            SlowFirst {
+                calls: calls.clone(),
+            },
+            HedgePolicy::new(Duration::from_millis(50)).methods([Method::GET, Method::POST]),
+        );
+
+        let response = service.call((request(Method::POST), ())).await.unwrap();
+        assert_eq!(response.body(), "primary");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn does_not_hedge_large_requests() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let service = HedgeService::new(
+            SlowFirst {
+                calls: calls.clone(),
+            },
+            HedgePolicy::new(Duration::from_millis(50)).max_body_size(3),
+        );
+
+        let mut request = request(Method::GET);
+        *request.body_mut() = Full::new(Bytes::from("body"));
+        let response = service.call((request, ())).await.unwrap();
+        assert_eq!(response.body(), "primary");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,327 @@
+//! Module for routing requests between services by host, so that several APIs
+//! or tenants can share one listener.
+//!
+//! Use by passing `hyper::server::MakeService` instances for each host to a
+//! `HostRouterMakeService`. Each request is dispatched on the host in its URI
+//! or, if that is absent, its `Host` header, ignoring case, any port and any
+//! trailing dot.
+//!
+//! Hosts are matched by patterns, which are either exact host names, such as
+//! `api.example.com`, or wildcards, such as `*.example.com`, matching any
+//! subdomain of `example.com` but not `example.com` itself. Exact patterns
+//! take precedence over wildcards, and longer wildcards over shorter ones.
+//!
+//! Example Usage
+//! =============
+//!
+//! ```ignore
+//! let mut make_service = HostRouterMakeService::new();
+//! make_service.push("api.example.com", Box::new(api_make_service));
+//! make_service.push("*.tenants.example.com", Box::new(tenant_make_service));
+//!
+//! // use as you would any `MakeService` instance
+//! ```
+use crate::composites::{CompositedMakeService, CompositedService};
+use crate::response::json_error;
+use crate::XSpanIdString;
+use futures::future::{BoxFuture, FutureExt, TryFutureExt};
+use hyper::header::HOST;
+use hyper::service::Service;
+use hyper::{Request, Response, StatusCode};
+use std::fmt;
+
+/// A pattern matching request hosts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HostPattern {
+    /// Matches a single host.
+    Exact(String),
+    /// Matches subdomains of a domain, stored with a leading `.`.
+    Wildcard(String),
+}
+
+impl HostPattern {
+    fn parse(pattern: &str) -> Self {
+        let pattern = normalize_host(pattern);
+        match pattern.strip_prefix('*') {
+            Some(suffix) if suffix.starts_with('.') => HostPattern::Wildcard(suffix.to_string()),
+            _ => HostPattern::Exact(pattern),
+        }
+    }
+
+    /// How well the pattern matches a host, if at all. Higher is better.
+    fn rank(&self, host: &str) -> Option<usize> {
+        match self {
+            HostPattern::Exact(exact) => (exact == host).then_some(usize::MAX),
+            HostPattern::Wildcard(suffix) => {
+                (host.len() > suffix.len() && host.ends_with(suffix)).then_some(suffix.len())
+            }
+        }
+    }
+}
+
+impl fmt::Display for HostPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostPattern::Exact(host) => f.write_str(host),
+            HostPattern::Wildcard(suffix) => write!(f, "*{}", suffix),
+        }
+    }
+}
+
+/// Lower-case a host, removing any port and trailing dot.
+fn normalize_host(host: &str) -> String {
+    let host = host.trim();
+    let host = if host.starts_with('[') {
+        // IPv6 literal
+        host.split_inclusive(']').next().unwrap_or(host)
+    } else {
+        host.rsplit_once(':').map_or(host, |(host, _)| host)
+    };
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// The host a request was sent to.
+fn request_host<B>(req: &Request<B>) -> Option<String> {
+    let host = match req.uri().host() {
+        Some(host) => host,
+        None => req.headers().get(HOST)?.to_str().ok()?,
+    };
+    Some(normalize_host(host))
+}
+
+/// Find the best pattern matching a host.
+fn select(patterns: &[HostPattern], host: &str) -> Option<usize> {
+    patterns
+        .iter()
+        .enumerate()
+        .filter_map(|(index, pattern)| Some((index, pattern.rank(host)?)))
+        // Prefer the first pattern pushed among equally good matches
+        .min_by_key(|(index, rank)| (std::cmp::Reverse(*rank), *index))
+        .map(|(index, _)| index)
+}
+
+/// `MakeService` for the requests to a host.
+type HostEntry<Target, ReqBody, ResBody, Error, MakeError> = (
+    HostPattern,
+    Box<dyn CompositedMakeService<Target, ReqBody, ResBody, Error, MakeError> + Send>,
+);
+
+/// Routes requests between `MakeService`s by host.
+///
+/// Requests for hosts matching no pattern are passed to the fallback
+/// `MakeService` if one has been set, and otherwise rejected with
+/// `421 Misdirected Request`.
+pub struct HostRouterMakeService<Target, ReqBody, ResBody, Error, MakeError> {
+    hosts: Vec<HostEntry<Target, ReqBody, ResBody, Error, MakeError>>,
+    fallback:
+        Option<Box<dyn CompositedMakeService<Target, ReqBody, ResBody, Error, MakeError> + Send>>,
+}
+
+impl<Target, ReqBody, ResBody, Error, MakeError>
+    HostRouterMakeService<Target, ReqBody, ResBody, Error, MakeError>
+{
+    /// Create an empty `HostRouterMakeService`.
+    pub fn new() -> Self {
+        HostRouterMakeService {
+            hosts: Vec::new(),
+            fallback: None,
+        }
+    }
+
+    /// Add the `MakeService` for the hosts matching a pattern, such as
+    /// `api.example.com` or `*.example.com`.
+    pub fn push(
+        &mut self,
+        pattern: &str,
+        make_service: Box<
+            dyn CompositedMakeService<Target, ReqBody, ResBody, Error, MakeError> + Send,
+        >,
+    ) {
+        self.hosts.push((HostPattern::parse(pattern), make_service));
+    }
+
+    /// Set the `MakeService` used to handle requests for hosts matching no
+    /// pattern.
+    pub fn set_fallback<M>(&mut self, fallback: M)
+    where
+        M: CompositedMakeService<Target, ReqBody, ResBody, Error, MakeError> + Send + 'static,
+    {
+        self.fallback = Some(Box::new(fallback));
+    }
+}
+
+impl<Target, ReqBody, ResBody, Error, MakeError> Default
+    for HostRouterMakeService<Target, ReqBody, ResBody, Error, MakeError>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Target, ReqBody, ResBody, Error, MakeError> fmt::Debug
+    for HostRouterMakeService<Target, ReqBody, ResBody, Error, MakeError>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hosts: Vec<String> = self.hosts.iter().map(|(p, _)| p.to_string()).collect();
+        f.debug_struct("HostRouterMakeService")
+            .field("hosts", &hosts)
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+impl<Target, ReqBody, ResBody, Error, MakeError> Service<Target>
+    for HostRouterMakeService<Target, ReqBody, ResBody, Error, MakeError>
+where
+    Target: Clone,
+    ReqBody: 'static,
+    ResBody: 'static,
+    MakeError: Send + 'static,
+    Error: 'static,
+{
+    type Response = HostRouter<ReqBody, ResBody, Error>;
+    type Error = MakeError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let services: Vec<_> = self
+            .hosts
+            .iter()
+            .map(|(pattern, make_service)| {
+                let pattern = pattern.clone();
+                make_service
+                    .call(target.clone())
+                    .map_ok(move |service| (pattern, service))
+            })
+            .collect();
+        let fallback = self.fallback.as_ref().map(|fallback| fallback.call(target));
+
+        Box::pin(
+            futures::future::join(
+                futures::future::try_join_all(services),
+                futures::future::OptionFuture::from(fallback),
+            )
+            .map(move |(services, fallback)| {
+                let (patterns, services) = services?.into_iter().unzip();
+                Ok(HostRouter {
+                    patterns,
+                    services,
+                    fallback: fallback.transpose()?,
+                })
+            }),
+        )
+    }
+}
+
+/// Routes requests between services by host. Servers will normally want to
+/// use `HostRouterMakeService`, which will create a `HostRouter` for each
+/// connection.
+pub struct HostRouter<ReqBody, ResBody, Error> {
+    patterns: Vec<HostPattern>,
+    services: Vec<Box<dyn CompositedService<ReqBody, ResBody, Error> + Send>>,
+    fallback: Option<Box<dyn CompositedService<ReqBody, ResBody, Error> + Send>>,
+}
+
+impl<ReqBody, ResBody, Error> fmt::Debug for HostRouter<ReqBody, ResBody, Error> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hosts: Vec<String> = self.patterns.iter().map(HostPattern::to_string).collect();
+        f.debug_struct("HostRouter")
+            .field("hosts", &hosts)
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+impl<ReqBody, ResBody, Error> Service<Request<ReqBody>> for HostRouter<ReqBody, ResBody, Error>
+where
+    ResBody: From<String> + Send + 'static,
+    Error: Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, req: Request<ReqBody>) -> Self::Future {
+        let index = request_host(&req).and_then(|host| select(&self.patterns, &host));
+
+        match (index, &self.fallback) {
+            (Some(index), _) => self.services[index].call(req),
+            (None, Some(fallback)) => fallback.call(req),
+            (None, None) => {
+                let x_span_id = XSpanIdString::get_or_generate(&req);
+                Box::pin(futures::future::ok(json_error(
+                    StatusCode::MISDIRECTED_REQUEST,
+                    "Requested host is not served",
+                    Some(&x_span_id.0),
+                )))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type TestRouter = HostRouterMakeService<(), (), String, (), ()>;
+
+    struct MakeHostService(&'static str);
+
+    impl<Target> Service<Target> for MakeHostService {
+        type Response = HostService;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _target: Target) -> Self::Future {
+            futures::future::ok(HostService(self.0))
+        }
+    }
+
+    struct HostService(&'static str);
+
+    impl Service<Request<()>> for HostService {
+        type Response = Response<String>;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _req: Request<()>) -> Self::Future {
+            futures::future::ok(Response::new(self.0.to_string()))
+        }
+    }
+
+    async fn send(router: &HostRouter<(), String, ()>, uri: &str, host: &str) -> Response<String> {
+        let req = Request::get(uri).header(HOST, host).body(()).unwrap();
+        Service::call(router, req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn routes_by_host() {
+        let mut make_service = TestRouter::new();
+        make_service.push("*.example.com", Box::new(MakeHostService("wildcard")));
+        make_service.push("*.eu.example.com", Box::new(MakeHostService("eu")));
+        make_service.push("API.example.com", Box::new(MakeHostService("api")));
+        let router = Service::call(&make_service, ()).await.unwrap();
+
+        assert_eq!(
+            send(&router, "/", "api.example.com:8080").await.body(),
+            "api"
+        );
+        assert_eq!(send(&router, "/", "Api.Example.Com.").await.body(), "api");
+        assert_eq!(send(&router, "/", "a.example.com").await.body(), "wildcard");
+        assert_eq!(send(&router, "/", "a.eu.example.com").await.body(), "eu");
+        assert_eq!(
+            send(&router, "https://api.example.com/", "other")
+                .await
+                .body(),
+            "api"
+        );
+        assert_eq!(
+            send(&router, "/", "example.com").await.status(),
+            StatusCode::MISDIRECTED_REQUEST
+        );
+
+        make_service.set_fallback(MakeHostService("fallback"));
+        let router = Service::call(&make_service, ()).await.unwrap();
+        assert_eq!(send(&router, "/", "[::1]:80").await.body(), "fallback");
+    }
+}
@@ -0,0 +1,377 @@
+//! Hyper service enforcing optimistic concurrency control with `If-Match`.
+//!
+//! `PUT`, `PATCH` and `DELETE` requests for the required operations must have
+//! an `If-Match` header, or are rejected with `428 Precondition Required`.
+//! The header is parsed and added to the context as a `Precondition`, which
+//! the API implementation checks against the current version of the
+//! resource. If the check fails, the response is replaced by
+//! `412 Precondition Failed`.
+//!
+//! Entity tags are compared using the strong comparison, so weak tags never
+//! match.
+//!
+//! ```rust
+//! # use swagger::if_match::Precondition;
+//! # use swagger::Has;
+//! # fn update_pet<C: Has<Precondition>>(context: &C, current_version: &str) {
+//! // In the API implementation
+//! if !context.get().matches(Some(current_version)) {
+//!     return; // The response will be replaced by `412 Precondition Failed`
+//! }
+//! # }
+//! ```
+
+use crate::response::json_error;
+use crate::{Has, Push, RequestParser, XSpanIdString};
+use futures::future::{BoxFuture, FutureExt};
+use hyper::header::IF_MATCH;
+use hyper::service::Service;
+use hyper::{Method, Request, Response, StatusCode};
+use std::collections::HashSet;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// The versions of a resource a request may be applied to, from its
+/// `If-Match` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpectedVersion {
+    /// `If-Match: *` - any current version.
+    Any,
+    /// The opaque parts of the strong entity tags listed. Weak and invalid
+    /// tags are skipped, as they never match.
+    Tags(Vec<String>),
+}
+
+impl ExpectedVersion {
+    /// Parse an `If-Match` header.
+    pub fn parse(value: &str) -> Self {
+        if value.trim() == "*" {
+            return ExpectedVersion::Any;
+        }
+        let tags = value
+            .split(',')
+            .map(str::trim)
+            .filter_map(|tag| tag.strip_prefix('"')?.strip_suffix('"'))
+            .filter(|tag| !tag.contains('"'))
+            .map(str::to_string)
+            .collect();
+        ExpectedVersion::Tags(tags)
+    }
+
+    /// Whether the current version of the resource, or `None` if it doesn't
+    /// exist, is expected.
+    pub fn matches(&self, current: Option<&str>) -> bool {
+        match (self, current) {
+            (ExpectedVersion::Any, current) => current.is_some(),
+            (ExpectedVersion::Tags(tags), Some(current)) => tags.iter().any(|tag| tag == current),
+            (ExpectedVersion::Tags(_), None) => false,
+        }
+    }
+}
+
+/// The `If-Match` precondition of a request, added to its context.
+#[derive(Clone, Default)]
+pub struct Precondition {
+    expected: Option<ExpectedVersion>,
+    failed: Arc<AtomicBool>,
+}
+
+impl Precondition {
+    fn new(expected: Option<ExpectedVersion>) -> Self {
+        Precondition {
+            expected,
+            failed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// The expected version, or `None` if the request has no `If-Match`
+    /// header.
+    pub fn expected(&self) -> Option<&ExpectedVersion> {
+        self.expected.as_ref()
+    }
+
+    /// Check the current version of the resource, or `None` if it doesn't
+    /// exist, against the expected version. Requests without `If-Match`
+    /// always pass.
+    pub fn matches(&self, current: Option<&str>) -> bool {
+        self.check_with(|expected| expected.matches(current))
+    }
+
+    /// Check the expected version using a comparator, e.g. for versions
+    /// which aren't strings. Requests without `If-Match` always pass.
+    pub fn check_with<F: FnOnce(&ExpectedVersion) -> bool>(&self, comparator: F) -> bool {
+        let passed = self.expected.as_ref().is_none_or(comparator);
+        if !passed {
+            self.failed.store(true, Ordering::Release);
+        }
+        passed
+    }
+
+    fn failed(&self) -> bool {
+        self.failed.load(Ordering::Acquire)
+    }
+}
+
+impl fmt::Debug for Precondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Precondition")
+            .field("expected", &self.expected)
+            .field("failed", &self.failed())
+            .finish()
+    }
+}
+
+/// The requests which must have an `If-Match` header.
+#[derive(Debug, Clone, Default)]
+struct Required {
+    all: bool,
+    operations: HashSet<&'static str>,
+}
+
+impl Required {
+    fn contains<B, RP: RequestParser<B>>(&self, req: &Request<B>) -> bool {
+        let mutating = [Method::PUT, Method::PATCH, Method::DELETE].contains(req.method());
+        mutating
+            && (self.all
+                || RP::parse_operation_id(req).is_some_and(|id| self.operations.contains(id)))
+    }
+}
+
+/// Middleware wrapper service which requires `If-Match` on mutating
+/// requests, and adds a `Precondition` to the context of every request.
+pub struct IfMatchMakeService<T, RP, C> {
+    inner: T,
+    required: Arc<Required>,
+    marker: PhantomData<fn(RP, C)>,
+}
+
+impl<T, RP, C> IfMatchMakeService<T, RP, C> {
+    /// Create a new IfMatchMakeService. No operations require `If-Match`
+    /// until added with `require` or `require_all`.
+    pub fn new(inner: T) -> Self {
+        IfMatchMakeService {
+            inner,
+            required: Arc::default(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Require `If-Match` on `PUT`, `PATCH` and `DELETE` requests for an
+    /// operation ID, as returned by the API's `RequestParser`.
+    pub fn require(mut self, operation_id: &'static str) -> Self {
+        Arc::make_mut(&mut self.required)
+            .operations
+            .insert(operation_id);
+        self
+    }
+
+    /// Require `If-Match` on all `PUT`, `PATCH` and `DELETE` requests.
+    pub fn require_all(mut self) -> Self {
+        Arc::make_mut(&mut self.required).all = true;
+        self
+    }
+}
+
+impl<T: fmt::Debug, RP, C> fmt::Debug for IfMatchMakeService<T, RP, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IfMatchMakeService")
+            .field("inner", &self.inner)
+            .field("required", &self.required)
+            .finish()
+    }
+}
+
+impl<Inner, RP, C, Target> Service<Target> for IfMatchMakeService<Inner, RP, C>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = IfMatchService<Inner::Response, RP, C>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let required = self.required.clone();
+        Box::pin(self.inner.call(target).map(|s| {
+            Ok(IfMatchService {
+                inner: s?,
+                required,
+                marker: PhantomData,
+            })
+        }))
+    }
+}
+
+/// Middleware wrapper service which requires `If-Match` on mutating
+/// requests. Servers will normally want to use `IfMatchMakeService`, which
+/// will create an `IfMatchService` for each connection.
+pub struct IfMatchService<T, RP, C> {
+    inner: T,
+    required: Arc<Required>,
+    marker: PhantomData<fn(RP, C)>,
+}
+
+impl<T, RP, C> IfMatchService<T, RP, C> {
+    /// Create a new IfMatchService. No operations require `If-Match` until
+    /// added with `require` or `require_all`.
+    pub fn new(inner: T) -> Self {
+        IfMatchService {
+            inner,
+            required: Arc::default(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Require `If-Match` on `PUT`, `PATCH` and `DELETE` requests for an
+    /// operation ID, as returned by the API's `RequestParser`.
+    pub fn require(mut self, operation_id: &'static str) -> Self {
+        Arc::make_mut(&mut self.required)
+            .operations
+            .insert(operation_id);
+        self
+    }
+
+    /// Require `If-Match` on all `PUT`, `PATCH` and `DELETE` requests.
+    pub fn require_all(mut self) -> Self {
+        Arc::make_mut(&mut self.required).all = true;
+        self
+    }
+}
+
+impl<T: Clone, RP, C> Clone for IfMatchService<T, RP, C> {
+    fn clone(&self) -> Self {
+        IfMatchService {
+            inner: self.inner.clone(),
+            required: self.required.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: fmt::Debug, RP, C> fmt::Debug for IfMatchService<T, RP, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IfMatchService")
+            .field("inner", &self.inner)
+            .field("required", &self.required)
+            .finish()
+    }
+}
+
+impl<T, RP, C, ReqBody, ResBody> Service<(Request<ReqBody>, C)> for IfMatchService<T, RP, C>
+where
+    RP: RequestParser<ReqBody>,
+    C: Has<XSpanIdString> + Push<Precondition>,
+    T: Service<(Request<ReqBody>, C::Result), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    T::Error: Send + 'static,
+    ResBody: From<String> + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let x_span_id = Has::<XSpanIdString>::get(&context).to_string();
+        let values: Vec<&str> = req
+            .headers()
+            .get_all(IF_MATCH)
+            .iter()
+            .map(|value| value.to_str().unwrap_or_default())
+            .collect();
+        let expected = (!values.is_empty()).then(|| ExpectedVersion::parse(&values.join(",")));
+
+        if expected.is_none() && self.required.contains::<_, RP>(&req) {
+            return Box::pin(futures::future::ok(json_error(
+                StatusCode::PRECONDITION_REQUIRED,
+                "Precondition Required",
+                Some(&x_span_id),
+            )));
+        }
+
+        let precondition = Precondition::new(expected);
+        let response = self.inner.call((req, context.push(precondition.clone())));
+        Box::pin(response.map(move |response| {
+            if precondition.failed() {
+                Ok(json_error(
+                    StatusCode::PRECONDITION_FAILED,
+                    "Precondition Failed",
+                    Some(&x_span_id),
+                ))
+            } else {
+                response
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::new_context_type!(TestContext, TestEmptyContext, Precondition, XSpanIdString);
+
+    type Context = TestContext<XSpanIdString, TestEmptyContext>;
+
+    struct TestParser;
+
+    impl<B> RequestParser<B> for TestParser {
+        fn parse_operation_id(req: &Request<B>) -> Option<&'static str> {
+            (req.uri().path() == "/pets/1").then_some("updatePet")
+        }
+    }
+
+    struct TestApi;
+
+    impl<C: Has<Precondition>> Service<(Request<()>, C)> for TestApi {
+        type Response = Response<String>;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (_, context): (Request<()>, C)) -> Self::Future {
+            context.get().matches(Some("v2"));
+            futures::future::ok(Response::new("updated".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn enforces_if_match() {
+        let service = IfMatchService::<_, TestParser, Context>::new(TestApi).require("updatePet");
+        let call = |method, path, if_match: Option<&str>| {
+            let mut request = Request::builder().method(method).uri(path);
+            if let Some(if_match) = if_match {
+                request = request.header(IF_MATCH, if_match);
+            }
+            let context = TestEmptyContext.push(XSpanIdString("span".to_string()));
+            service.call((request.body(()).unwrap(), context))
+        };
+
+        let response = call(Method::PUT, "/pets/1", None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+        assert_eq!(
+            response.body(),
+            r#"{"code":428,"message":"Precondition Required","x-span-id":"span"}"#
+        );
+
+        let response = call(Method::PUT, "/pets/1", Some("\"v1\", W/\"v2\""))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+        for if_match in ["\"v1\", \"v2\"", "*"] {
+            let response = call(Method::PUT, "/pets/1", Some(if_match)).await.unwrap();
+            assert_eq!(response.body(), "updated");
+        }
+        for (method, path) in [(Method::GET, "/pets/1"), (Method::DELETE, "/pets/2")] {
+            let response = call(method, path, None).await.unwrap();
+            assert_eq!(response.body(), "updated");
+        }
+
+        assert_eq!(
+            ExpectedVersion::parse("\"a\",W/\"b\", c, \"d\""),
+            ExpectedVersion::Tags(vec!["a".to_string(), "d".to_string()])
+        );
+        assert!(!ExpectedVersion::Any.matches(None));
+    }
+}
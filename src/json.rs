@@ -0,0 +1,511 @@
+//! Deserializing JSON request bodies with detailed errors.
+//!
+//! serde_json's errors describe what went wrong, but not where in the
+//! document - `from_slice` also tracks the path to the value which failed,
+//! returning a `JsonError` which can be turned into a structured
+//! `400 Bad Request` response for the client.
+//!
+//! ```rust
+//! # use serde::Deserialize;
+//! #[derive(Debug, Deserialize)]
+//! struct Pet {
+//!     id: u32,
+//! }
+//!
+//! let error = swagger::json::from_str::<Vec<Pet>>(r#"[{"id": 1}, {"id": "two"}]"#).unwrap_err();
+//! assert_eq!(error.pointer(), "/1/id");
+//! assert_eq!(error.expected(), Some("u32"));
+//! assert_eq!((error.line(), error.column()), (1, 24));
+//! ```
+//!
+//! `JsonArrayStream` reads large arrays an item at a time, with the same
+//! error detail.
+
+use crate::X_SPAN_ID;
+use futures::stream::Stream;
+use hyper::body::{Body, Bytes};
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::{Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_path_to_error::Segment;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Error deserializing a JSON document, locating the value which failed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct JsonError {
+    message: String,
+    pointer: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expected: Option<String>,
+    line: usize,
+    column: usize,
+}
+
+impl JsonError {
+    fn new(error: serde_path_to_error::Error<serde_json::Error>) -> Self {
+        let pointer = error
+            .path()
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Seq { index } => Some(index.to_string()),
+                Segment::Map { key } => Some(key.replace('~', "~0").replace('/', "~1")),
+                Segment::Enum { variant } => Some(variant.replace('~', "~0").replace('/', "~1")),
+                Segment::Unknown => None,
+            })
+            .fold(String::new(), |pointer, token| pointer + "/" + &token);
+        Self::at(pointer, error.into_inner())
+    }
+
+    fn at(pointer: String, error: serde_json::Error) -> Self {
+        let (line, column) = (error.line(), error.column());
+        let message = error.to_string();
+        // serde_json appends the position, which is reported separately
+        let message = message
+            .strip_suffix(&format!(" at line {} column {}", line, column))
+            .unwrap_or(&message)
+            .to_string();
+        let expected = message
+            .split_once(", expected ")
+            .map(|(_, expected)| expected.to_string());
+
+        JsonError {
+            message,
+            pointer,
+            expected,
+            line,
+            column,
+        }
+    }
+
+    /// serde's description of the error, without its position.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// JSON pointer, as described in RFC 6901, to the value which failed to
+    /// deserialize. Empty if the document as a whole was invalid.
+    pub fn pointer(&self) -> &str {
+        &self.pointer
+    }
+
+    /// What was expected in place of the invalid value, if known, such as
+    /// `u32` or `a string`.
+    pub fn expected(&self) -> Option<&str> {
+        self.expected.as_deref()
+    }
+
+    /// The line of the document on which the error was found, starting at 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The column of the line at which the error was found, starting at 1.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Build a `400 Bad Request` response describing the error, of the form
+    /// `{"code":400,"message":"...","pointer":"...","expected":"...","line":1,"column":1,"x-span-id":"..."}`.
+    pub fn into_response<B: From<String>>(self, x_span_id: Option<&str>) -> Response<B> {
+        #[derive(Serialize)]
+        struct Body<'a> {
+            code: u16,
+            #[serde(flatten)]
+            error: &'a JsonError,
+            #[serde(rename = "x-span-id", skip_serializing_if = "Option::is_none")]
+            x_span_id: Option<&'a str>,
+        }
+
+        let body = Body {
+            code: StatusCode::BAD_REQUEST.as_u16(),
+            error: &self,
+            x_span_id,
+        };
+        let body = serde_json::to_string(&body).expect("error should serialize");
+
+        let mut response = Response::new(B::from(body));
+        *response.status_mut() = StatusCode::BAD_REQUEST;
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        if let Some(Ok(x_span_id)) = x_span_id.map(HeaderValue::from_str) {
+            response.headers_mut().insert(X_SPAN_ID, x_span_id);
+        }
+        response
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.pointer.is_empty() {
+            write!(f, "{}", self.message)?;
+        } else {
+            write!(f, "{}: {}", self.pointer, self.message)?;
+        }
+        write!(f, " at line {} column {}", self.line, self.column)
+    }
+}
+
+impl Error for JsonError {}
+
+/// Deserialize an instance of `T` from bytes of JSON.
+pub fn from_slice<T: DeserializeOwned>(v: &[u8]) -> Result<T, JsonError> {
+    let deserializer = &mut serde_json::Deserializer::from_slice(v);
+    let value = serde_path_to_error::deserialize(&mut *deserializer).map_err(JsonError::new)?;
+    deserializer
+        .end()
+        .map_err(|e| JsonError::at(String::new(), e))?;
+    Ok(value)
+}
+
+/// Deserialize an instance of `T` from a string of JSON.
+pub fn from_str<T: DeserializeOwned>(s: &str) -> Result<T, JsonError> {
+    from_slice(s.as_bytes())
+}
+
+/// Error reading a stream of JSON values from a body.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum JsonStreamError {
+    /// The body couldn't be read.
+    Body(Box<dyn Error + Send + Sync>),
+    /// The body wasn't valid JSON, or a value wasn't valid for the expected
+    /// type.
+    Json(JsonError),
+}
+
+impl fmt::Display for JsonStreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonStreamError::Body(e) => write!(f, "Failed to read body: {}", e),
+            JsonStreamError::Json(e) => write!(f, "Invalid JSON: {}", e),
+        }
+    }
+}
+
+impl Error for JsonStreamError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            JsonStreamError::Body(e) => Some(e.as_ref()),
+            JsonStreamError::Json(e) => Some(e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArrayState {
+    Start,
+    First,
+    Item,
+    AfterItem,
+    End,
+    Failed,
+}
+
+/// A stream of the items of a JSON array read from a body, such as the
+/// response of a bulk list operation, deserializing each item as soon as it
+/// has been received rather than buffering the whole array.
+///
+/// An item which isn't valid for `T` produces an error, and the stream
+/// continues with the next item. Errors in the structure of the array end
+/// the stream. Errors locate the failing value in the whole document.
+///
+/// ```rust
+/// # use futures::stream::TryStreamExt;
+/// # use http_body_util::Full;
+/// # use hyper::body::Bytes;
+/// # use swagger::json::JsonArrayStream;
+/// # async fn example() -> Result<(), swagger::json::JsonStreamError> {
+/// let body = Full::new(Bytes::from(r#"[{"id": 1}, {"id": 2}]"#));
+/// let pets: Vec<serde_json::Value> = JsonArrayStream::new(body).try_collect().await?;
+/// assert_eq!(pets.len(), 2);
+/// # Ok(())
+/// # }
+/// ```
+pub struct JsonArrayStream<B, T> {
+    body: B,
+    buffer: Vec<u8>,
+    done: bool,
+    state: ArrayState,
+    index: usize,
+    /// Position of the start of the buffer in the document.
+    line: usize,
+    column: usize,
+    /// Progress scanning for the end of the current item.
+    scanned: usize,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<B, T> fmt::Debug for JsonArrayStream<B, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonArrayStream")
+            .field("index", &self.index)
+            .field("buffered", &self.buffer.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B, T> JsonArrayStream<B, T> {
+    /// Read the items of the array in `body`.
+    pub fn new(body: B) -> Self {
+        JsonArrayStream {
+            body,
+            buffer: Vec::new(),
+            done: false,
+            state: ArrayState::Start,
+            index: 0,
+            line: 1,
+            column: 1,
+            scanned: 0,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+            marker: PhantomData,
+        }
+    }
+
+    /// Remove `n` bytes from the start of the buffer.
+    fn consume(&mut self, n: usize) {
+        for &b in &self.buffer[..n] {
+            if b == b'\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+        self.buffer.drain(..n);
+    }
+
+    fn skip_whitespace(&mut self) {
+        let n = self
+            .buffer
+            .iter()
+            .take_while(|b| b.is_ascii_whitespace())
+            .count();
+        self.consume(n);
+    }
+
+    fn syntax_error(&mut self, message: &str) -> JsonStreamError {
+        self.state = ArrayState::Failed;
+        JsonStreamError::Json(JsonError {
+            message: message.to_string(),
+            pointer: String::new(),
+            expected: None,
+            line: self.line,
+            column: self.column,
+        })
+    }
+
+    /// Find the end of the item at the start of the buffer - the `,` or `]`
+    /// following it - continuing from where the last call left off.
+    fn scan_item(&mut self) -> Option<usize> {
+        while self.scanned < self.buffer.len() {
+            let b = self.buffer[self.scanned];
+            if self.in_string {
+                match b {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => self.in_string = false,
+                    _ => {}
+                }
+            } else {
+                match b {
+                    b'"' => self.in_string = true,
+                    b'{' | b'[' => self.depth += 1,
+                    b',' | b']' if self.depth == 0 => {
+                        let end = self.scanned;
+                        self.scanned = 0;
+                        return Some(end);
+                    }
+                    b'}' | b']' => self.depth = self.depth.saturating_sub(1),
+                    _ => {}
+                }
+            }
+            self.scanned += 1;
+        }
+        None
+    }
+
+    /// Take the next item, or structural error, from the buffer. Returns
+    /// `None` if more of the body is needed, or the array has ended.
+    fn next_item(&mut self) -> Option<Result<T, JsonStreamError>>
+    where
+        T: DeserializeOwned,
+    {
+        loop {
+            if self.scanned == 0 {
+                self.skip_whitespace();
+            }
+            let next = self.buffer.first().copied();
+            match (self.state, next) {
+                (ArrayState::Failed, _) | (_, None) => return None,
+                (ArrayState::Start, Some(b'[')) => {
+                    self.consume(1);
+                    self.state = ArrayState::First;
+                }
+                (ArrayState::Start, Some(_)) => {
+                    return Some(Err(self.syntax_error("expected `[`")))
+                }
+                (ArrayState::First, Some(b']')) | (ArrayState::AfterItem, Some(b']')) => {
+                    self.consume(1);
+                    self.state = ArrayState::End;
+                }
+                (ArrayState::First, Some(_)) => self.state = ArrayState::Item,
+                (ArrayState::AfterItem, Some(b',')) => {
+                    self.consume(1);
+                    self.state = ArrayState::Item;
+                }
+                (ArrayState::AfterItem, Some(_)) => {
+                    return Some(Err(self.syntax_error("expected `,` or `]`")))
+                }
+                (ArrayState::End, Some(_)) => {
+                    return Some(Err(self.syntax_error("trailing characters")))
+                }
+                (ArrayState::Item, Some(_)) => {
+                    let end = self.scan_item()?;
+                    let (line, column) = (self.line, self.column);
+                    let result = from_slice(&self.buffer[..end]).map_err(|mut e| {
+                        e.pointer = format!("/{}{}", self.index, e.pointer);
+                        if e.line == 1 {
+                            e.column += column - 1;
+                        }
+                        e.line += line - 1;
+                        JsonStreamError::Json(e)
+                    });
+                    self.consume(end);
+                    self.index += 1;
+                    self.state = ArrayState::AfterItem;
+                    return Some(result);
+                }
+            }
+        }
+    }
+}
+
+impl<B, T> Stream for JsonArrayStream<B, T>
+where
+    B: Body<Data = Bytes> + Unpin,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+    T: DeserializeOwned,
+{
+    type Item = Result<T, JsonStreamError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(item) = self.next_item() {
+                return Poll::Ready(Some(item));
+            }
+            match self.state {
+                ArrayState::Failed => return Poll::Ready(None),
+                ArrayState::End if self.done => return Poll::Ready(None),
+                _ if self.done => {
+                    return Poll::Ready(Some(Err(self.syntax_error("unexpected end of body"))))
+                }
+                _ => {}
+            }
+
+            match Pin::new(&mut self.body).poll_frame(cx) {
+                Poll::Ready(Some(Ok(frame))) => {
+                    if let Ok(data) = frame.into_data() {
+                        self.buffer.extend_from_slice(&data);
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => {
+                    self.state = ArrayState::Failed;
+                    return Poll::Ready(Some(Err(JsonStreamError::Body(e.into()))));
+                }
+                Poll::Ready(None) => self.done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Pet {
+        name: String,
+        tags: HashMap<String, u32>,
+    }
+
+    #[test]
+    fn locates_errors() {
+        let error = from_str::<Pet>("{\"name\": \"Rex\",\n \"tags\": {\"a/b\": -1}}").unwrap_err();
+        assert_eq!(error.pointer(), "/tags/a~1b");
+        assert_eq!(error.expected(), Some("u32"));
+        assert_eq!(
+            error.to_string(),
+            "/tags/a~1b: invalid value: integer `-1`, expected u32 at line 2 column 19"
+        );
+
+        let response: Response<String> = error.into_response(Some("span"));
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.body(),
+            r#"{"code":400,"message":"invalid value: integer `-1`, expected u32","pointer":"/tags/a~1b","expected":"u32","line":2,"column":19,"x-span-id":"span"}"#
+        );
+
+        let error = from_str::<Pet>(r#"{"name": "Rex", "tags": {}} x"#).unwrap_err();
+        assert_eq!(error.pointer(), "");
+        assert_eq!(error.message(), "trailing characters");
+    }
+
+    #[tokio::test]
+    async fn streams_array_items() {
+        use futures::stream::{self, StreamExt};
+        use http_body_util::StreamBody;
+        use hyper::body::Frame;
+
+        let chunks = [
+            " [ {\"a\": [1, \"],\"]}",
+            ",\n",
+            "{}, ",
+            "{\"a\":",
+            " true}]",
+        ];
+        let body =
+            StreamBody::new(stream::iter(chunks.map(|chunk| {
+                Ok::<_, std::convert::Infallible>(Frame::data(Bytes::from(chunk)))
+            })));
+        let items: Vec<_> =
+            JsonArrayStream::<_, HashMap<String, Vec<serde_json::Value>>>::new(body)
+                .collect()
+                .await;
+        assert_eq!(items.len(), 3);
+        assert_eq!(
+            items[0].as_ref().unwrap()["a"],
+            [serde_json::json!(1), "],".into()]
+        );
+        assert!(items[1].as_ref().unwrap().is_empty());
+        match &items[2] {
+            Err(JsonStreamError::Json(e)) => {
+                assert_eq!(e.pointer(), "/2/a");
+                assert_eq!((e.line(), e.column()), (2, 14));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let body = http_body_util::Full::new(Bytes::from("[1, 2"));
+        let items: Vec<_> = JsonArrayStream::<_, u32>::new(body).collect().await;
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[1].as_ref().unwrap_err().to_string(),
+            "Invalid JSON: unexpected end of body at line 1 column 5"
+        );
+    }
+}
@@ -5,27 +5,45 @@
 //! Crate features exist to reduce the dependencies on the crate. Most features
 //! should be enabled by the generator when relevant.
 //!
-//! By default, the **serdejson** feature is enabled.
+//! By default, the **serdejson** and **zeroize** features are enabled.
 //!
 //! ## Format support
 //!
 //! - **multipart_form** - Enable support for `multipart/form-data` as described in RFC 7578
 //! - **multipart_related** - Enable support for `multipart/related` as described in RFC 2387
-//! - **serdejson** - Enable JSON serialization/deserialization support using serde.
+//! - **xml** - Enable XML serialization/deserialization support using serde.
+//! - **msgpack** - Enable MessagePack serialization/deserialization support using serde.
+//! - **cbor** - Enable CBOR serialization/deserialization support using serde.
+//! - **serdejson** - Enable JSON serialization/deserialization support using serde, and
+//!   serde support for `application/x-www-form-urlencoded` bodies.
 //!
 //! ## Feature support
 //!
 //! - **serdevalid** - Enable support for JSON schema based validation
+//! - **validation** - Enable validation of requests against an OpenAPI document at runtime
+//! - **decimal** - Enable the `Decimal` type for decimal numbers, using `rust_decimal`
+//! - **chrono** - Enable support for dates and date-times using `chrono`
+//! - **time** - Enable support for dates and date-times using `time`
 //! - **conversion** - Enable support for Frunk-based conversion - in particular,
 //!   [transmogrification](https://docs.rs/frunk/latest/frunk/#transmogrifying)
+//! - **gzip** - Enable support for the `gzip` and `deflate` content codings
+//! - **brotli** - Enable support for the `br` content coding
+//! - **zstd** - Enable support for the `zstd` content coding
+//! - **zeroize** - Enable zeroing the memory of `AuthData` and `SecretString` when they're dropped
 //!
 //! ## Use case support
 //! - **client** - Enable support for providing an OpenAPI client
 //! - **server** - Enable support for providing an OpenAPI server
 //! - **http1** - Enable support for HTTP/1 based APIs - RFC 9112
 //! - **http2** - Enable support for HTTP/2 based APIs - RFC 9113
-//! - **tls** - Enable support for HTTP over TLS (HTTPS)
+//! - **tls** - Enable support for HTTP over TLS (HTTPS) for clients and, with **server**
+//!   except on macOS, iOS and Windows, servers using OpenSSL
+//! - **rustls** - Enable support for HTTP over TLS (HTTPS) using rustls, for clients and,
+//!   with **server**, servers
 //! - **uds** - Enable support for HTTP over UDS (Unix Domain Sockets)
+//! - **proxy** - Enable support for sending client requests through proxies
+//! - **websocket** - Enable support for serving WebSocket connections alongside a server API
+//! - **docs_ui** - Enable support for serving Swagger UI or ReDoc documentation pages
 
 #![deny(
     missing_docs,
@@ -48,9 +66,30 @@ pub use base64_format::ByteArray;
 pub mod nullable_format;
 pub use nullable_format::Nullable;
 
+pub mod number_format;
+
+#[cfg(any(feature = "chrono", feature = "time"))]
+pub mod date_format;
+
+pub mod additional_properties;
+pub use additional_properties::AdditionalProperties;
+
 mod body;
 pub use body::BodyExt;
 
+pub mod body_ext;
+
+pub mod file;
+pub use file::File;
+
+pub mod range;
+
+pub mod redaction;
+pub use redaction::Redactor;
+
+pub mod secret;
+pub use secret::SecretString;
+
 pub mod auth;
 pub use auth::{AuthData, Authorization};
 
@@ -63,25 +102,291 @@ pub mod connector;
 #[cfg(feature = "client")]
 pub use connector::Connector;
 
+#[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+pub mod client;
+
+#[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
+pub mod compression;
+
+#[cfg(all(
+    feature = "client",
+    any(feature = "gzip", feature = "brotli", feature = "zstd")
+))]
+pub mod decompression;
+#[cfg(all(
+    feature = "client",
+    any(feature = "gzip", feature = "brotli", feature = "zstd")
+))]
+pub use decompression::DecompressionService;
+
+#[cfg(all(
+    feature = "client",
+    any(feature = "gzip", feature = "brotli", feature = "zstd")
+))]
+pub mod request_compression;
+#[cfg(all(
+    feature = "client",
+    any(feature = "gzip", feature = "brotli", feature = "zstd")
+))]
+pub use request_compression::RequestCompressionService;
+
+#[cfg(all(
+    feature = "server",
+    any(feature = "gzip", feature = "brotli", feature = "zstd")
+))]
+pub mod response_compression;
+#[cfg(all(
+    feature = "server",
+    any(feature = "gzip", feature = "brotli", feature = "zstd")
+))]
+pub use response_compression::{CompressionMakeService, CompressionService};
+
+#[cfg(feature = "client")]
+pub mod client_logging;
+#[cfg(feature = "client")]
+pub use client_logging::LoggingService;
+
+#[cfg(feature = "client")]
+pub mod cache;
+#[cfg(feature = "client")]
+pub use cache::CacheService;
+
+#[cfg(feature = "client")]
+pub mod retry;
+#[cfg(feature = "client")]
+pub use retry::RetryService;
+
+#[cfg(feature = "client")]
+pub mod hedge;
+#[cfg(feature = "client")]
+pub use hedge::HedgeService;
+
+#[cfg(feature = "client")]
+pub mod rate_limit;
+#[cfg(feature = "client")]
+pub use rate_limit::RateLimitService;
+
+#[cfg(feature = "client")]
+pub mod rewrite;
+#[cfg(feature = "client")]
+pub use rewrite::RewriteService;
+
+#[cfg(feature = "client")]
+pub mod default_headers;
+#[cfg(feature = "client")]
+pub use default_headers::DefaultHeadersService;
+
+#[cfg(feature = "client")]
+pub mod redirect;
+#[cfg(feature = "client")]
+pub use redirect::RedirectService;
+
+#[cfg(feature = "client")]
+pub mod circuit_breaker;
+#[cfg(feature = "client")]
+pub use circuit_breaker::CircuitBreakerService;
+
+#[cfg(feature = "client")]
+pub mod timeout;
+#[cfg(feature = "client")]
+pub use timeout::TimeoutService;
+
 #[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
 pub mod composites;
 #[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
 pub use composites::{CompositeMakeService, CompositeMakeServiceEntry, CompositeService, NotFound};
 
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+pub mod base_path;
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+pub use base_path::{BasePathMakeService, BasePathService};
+
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+pub mod version_router;
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+pub use version_router::{VersionRouter, VersionRouterMakeService};
+
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+pub mod host_router;
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+pub use host_router::{HostRouter, HostRouterMakeService};
+
+#[cfg(feature = "server")]
+pub mod server;
+
 pub mod add_context;
-pub use add_context::{AddContextMakeService, AddContextService};
+pub use add_context::{
+    AddContextMakeService, AddContextService, AddContextWithTargetMakeService,
+    AddContextWithTargetService,
+};
+
+pub mod add_state;
+pub use add_state::{AddStateMakeService, AddStateService};
+
+pub mod make;
+pub use make::{IntoMakeService, IntoMakeServiceWithConnectInfo};
+
+pub mod client_info;
+pub use client_info::{ClientInfo, ClientInfoMakeService, ClientInfoService};
 
 pub mod drop_context;
-pub use drop_context::{DropContextMakeService, DropContextService};
+pub use drop_context::{
+    DropContextMakeService, DropContextService, DropContextWithHeadersMakeService,
+    DropContextWithHeadersService,
+};
+
+pub mod concurrency_limit;
+pub use concurrency_limit::{ConcurrencyLimitMakeService, ConcurrencyLimitService};
+
+#[cfg(feature = "server")]
+pub mod request_queue;
+#[cfg(feature = "server")]
+pub use request_queue::{RequestQueueMakeService, RequestQueueService};
+
+#[cfg(feature = "server")]
+pub mod error_handler;
+#[cfg(feature = "server")]
+pub use error_handler::{ErrorHandlerMakeService, ErrorHandlerService, ServiceError};
+
+pub mod fallback;
+pub use fallback::{
+    DefaultFallback, FallbackMakeService, FallbackService, MakeFallback, MethodNotAllowed,
+};
+
+pub mod error_renderer;
+pub use error_renderer::{ErrorRenderer, ErrorRendererMakeService, ErrorRendererService};
+
+pub mod stack;
+pub use stack::StackBuilder;
+
+pub mod swappable;
+pub use swappable::SwappableService;
+
+pub mod method_override;
+pub use method_override::{MethodOverrideMakeService, MethodOverrideService};
+
+pub mod normalize_path;
+pub use normalize_path::{NormalizePathMakeService, NormalizePathService};
+
+pub mod route_middleware;
+pub use route_middleware::{RouteMiddlewareMakeService, RouteMiddlewareService};
 
 pub mod request_parser;
 pub use request_parser::RequestParser;
 
+pub mod routes;
+pub use routes::Routes;
+
+pub mod options;
+pub use options::{OptionsMakeService, OptionsService};
+
+pub mod head;
+pub use head::{HeadMakeService, HeadService};
+
+pub mod media_type;
+pub use media_type::MediaType;
+
+pub mod negotiation;
+pub use negotiation::{NegotiationMakeService, NegotiationService};
+
+pub mod content_type;
+pub use content_type::{ContentTypeMakeService, ContentTypeService};
+
+pub mod body_limit;
+pub use body_limit::{BodyLimitMakeService, BodyLimitService};
+
+#[cfg(feature = "server")]
+pub mod cors;
+#[cfg(feature = "server")]
+pub use cors::{CorsMakeService, CorsService};
+
+#[cfg(feature = "server")]
+pub mod etag;
+#[cfg(feature = "server")]
+pub use etag::{ETagMakeService, ETagService};
+
+pub mod cache_policy;
+pub use cache_policy::{CachePolicyMakeService, CachePolicyService};
+
+pub mod vary;
+pub use vary::{VaryMakeService, VaryService};
+
+#[cfg(feature = "websocket")]
+pub mod websocket;
+#[cfg(feature = "websocket")]
+pub use websocket::{WebSocketMakeService, WebSocketService};
+
+#[cfg(feature = "docs_ui")]
+pub mod docs_ui;
+#[cfg(feature = "docs_ui")]
+pub use docs_ui::DocsUiService;
+
+pub mod cookies;
+pub use cookies::{CookiesMakeService, CookiesService};
+
+#[cfg(feature = "serdejson")]
+pub mod problem;
+#[cfg(feature = "serdejson")]
+pub use problem::{Problem, ProblemMakeService, ProblemService};
+
+#[cfg(feature = "serdejson")]
+pub mod strict_query;
+#[cfg(feature = "serdejson")]
+pub use strict_query::{StrictQueryMakeService, StrictQueryService};
+
+#[cfg(feature = "validation")]
+pub mod validation;
+#[cfg(feature = "validation")]
+pub use validation::{
+    ResponseValidationMakeService, ResponseValidationService, ValidationMakeService,
+    ValidationService,
+};
+
+pub mod if_match;
+pub use if_match::{IfMatchMakeService, IfMatchService};
+
+mod response;
+
 mod header;
 pub use header::{XSpanIdString, X_SPAN_ID};
 
+pub mod header_value;
+pub use header_value::IntoHeaderValue;
+
 pub mod multipart;
 
+pub mod urlencoded;
+
+#[cfg(feature = "serdejson")]
+pub mod query;
+
+#[cfg(feature = "serdejson")]
+pub mod header_param;
+
+#[cfg(feature = "serdejson")]
+pub mod path_param;
+
+#[cfg(feature = "serdejson")]
+pub mod json;
+
+#[cfg(feature = "serdejson")]
+pub mod patch;
+
+#[cfg(feature = "serdejson")]
+pub mod ndjson;
+
+#[cfg(any(feature = "client", feature = "server"))]
+pub mod sse;
+
+#[cfg(feature = "xml")]
+pub mod xml;
+
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+
+#[cfg(feature = "cbor")]
+pub mod cbor;
+
 mod one_any_of;
 pub use one_any_of::*;
 
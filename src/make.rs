@@ -0,0 +1,213 @@
+//! Helpers for turning a service into a `MakeService`, for use with servers
+//! which create one service per connection.
+
+use crate::Push;
+use futures::future::{ready, Ready};
+use hyper::service::Service;
+use hyper::Request;
+use std::convert::Infallible;
+use std::fmt;
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+
+/// `MakeService` which creates a clone of the wrapped service for each
+/// connection.
+#[derive(Debug, Clone)]
+pub struct IntoMakeService<S> {
+    inner: S,
+}
+
+impl<S> IntoMakeService<S> {
+    /// Create a new IntoMakeService struct wrapping a service
+    pub fn new(inner: S) -> Self {
+        IntoMakeService { inner }
+    }
+}
+
+impl<S, Target> Service<Target> for IntoMakeService<S>
+where
+    S: Clone,
+{
+    type Response = S;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    fn call(&self, _target: Target) -> Self::Future {
+        ready(Ok(self.inner.clone()))
+    }
+}
+
+/// Typed connection information, extracted from the accept target of a
+/// connection.
+pub trait Connected<Target>: Clone + Send + Sync + 'static {
+    /// Extract the connection information from the target.
+    fn connect_info(target: &Target) -> Self;
+}
+
+impl Connected<SocketAddr> for SocketAddr {
+    fn connect_info(target: &SocketAddr) -> Self {
+        *target
+    }
+}
+
+/// Connection information, as added to the request extensions or context by
+/// `IntoMakeServiceWithConnectInfo`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectInfo<T>(pub T);
+
+/// `MakeService` which creates a clone of the wrapped service for each
+/// connection, and makes connection information of type `C` available to it.
+///
+/// When the service accepts plain `hyper::Request`s, a `ConnectInfo<C>` is
+/// inserted into the request extensions. When it accepts a request and
+/// context, `ConnectInfo<C>` is pushed onto the context.
+///
+/// ```rust
+/// # use std::net::SocketAddr;
+/// # use swagger::make::IntoMakeServiceWithConnectInfo;
+/// # fn wrap<S>(service: S) {
+/// let make_service = IntoMakeServiceWithConnectInfo::<_, SocketAddr>::new(service);
+/// # }
+/// ```
+pub struct IntoMakeServiceWithConnectInfo<S, C> {
+    inner: S,
+    marker: PhantomData<fn() -> C>,
+}
+
+impl<S, C> IntoMakeServiceWithConnectInfo<S, C> {
+    /// Create a new IntoMakeServiceWithConnectInfo struct wrapping a service
+    pub fn new(inner: S) -> Self {
+        IntoMakeServiceWithConnectInfo {
+            inner,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<S, C> Clone for IntoMakeServiceWithConnectInfo<S, C>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Self::new(self.inner.clone())
+    }
+}
+
+impl<S, C> fmt::Debug for IntoMakeServiceWithConnectInfo<S, C>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IntoMakeServiceWithConnectInfo")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<S, C, Target> Service<Target> for IntoMakeServiceWithConnectInfo<S, C>
+where
+    S: Clone,
+    C: Connected<Target>,
+{
+    type Response = AddConnectInfo<S, C>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        ready(Ok(AddConnectInfo {
+            inner: self.inner.clone(),
+            connect_info: ConnectInfo(C::connect_info(&target)),
+        }))
+    }
+}
+
+/// Service which makes connection information available to the wrapped
+/// service. This should not usually be used directly - use
+/// `IntoMakeServiceWithConnectInfo`, which will create `AddConnectInfo`
+/// instances as needed.
+#[derive(Debug, Clone)]
+pub struct AddConnectInfo<S, C> {
+    inner: S,
+    connect_info: ConnectInfo<C>,
+}
+
+impl<S, C, B> Service<Request<B>> for AddConnectInfo<S, C>
+where
+    S: Service<Request<B>>,
+    C: Clone + Send + Sync + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&self, mut req: Request<B>) -> Self::Future {
+        req.extensions_mut().insert(self.connect_info.clone());
+        self.inner.call(req)
+    }
+}
+
+impl<S, C, B, Context> Service<(Request<B>, Context)> for AddConnectInfo<S, C>
+where
+    Context: Push<ConnectInfo<C>>,
+    S: Service<(Request<B>, Context::Result)>,
+    C: Clone,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&self, (req, context): (Request<B>, Context)) -> Self::Future {
+        self.inner
+            .call((req, context.push(self.connect_info.clone())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Has;
+
+    crate::new_context_type!(TestContext, TestEmptyContext, ConnectInfo<SocketAddr>);
+
+    #[derive(Clone)]
+    struct TestService;
+
+    impl Service<Request<()>> for TestService {
+        type Response = Option<ConnectInfo<SocketAddr>>;
+        type Error = ();
+        type Future = Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, req: Request<()>) -> Self::Future {
+            ready(Ok(req.extensions().get().cloned()))
+        }
+    }
+
+    impl<C> Service<(Request<()>, C)> for TestService
+    where
+        C: Has<ConnectInfo<SocketAddr>>,
+    {
+        type Response = Option<ConnectInfo<SocketAddr>>;
+        type Error = ();
+        type Future = Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (_req, context): (Request<()>, C)) -> Self::Future {
+            ready(Ok(Some(*context.get())))
+        }
+    }
+
+    #[tokio::test]
+    async fn connect_info() {
+        let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let make_service = IntoMakeServiceWithConnectInfo::<_, SocketAddr>::new(TestService);
+        let service = make_service.call(addr).await.unwrap();
+
+        let response = service.call(Request::new(())).await.unwrap();
+        assert_eq!(response, Some(ConnectInfo(addr)));
+
+        let response = service
+            .call((Request::new(()), TestEmptyContext))
+            .await
+            .unwrap();
+        assert_eq!(response, Some(ConnectInfo(addr)));
+    }
+}
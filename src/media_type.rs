@@ -0,0 +1,188 @@
+//! Media types, as used in the `Content-Type` and `Accept` headers.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// A media type, such as `application/json` or `text/plain; charset=utf-8`.
+///
+/// The type, subtype and parameter names are case-insensitive, so are
+/// stored in lowercase.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MediaType {
+    type_: String,
+    subtype: String,
+    params: Vec<(String, String)>,
+}
+
+impl MediaType {
+    /// Parse a media type from a static string.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the string isn't a valid media type.
+    pub fn from_static(s: &'static str) -> Self {
+        s.parse().expect("invalid media type")
+    }
+
+    /// The type, e.g. `application`.
+    pub fn type_(&self) -> &str {
+        &self.type_
+    }
+
+    /// The subtype, e.g. `json`.
+    pub fn subtype(&self) -> &str {
+        &self.subtype
+    }
+
+    /// The type and subtype, without parameters.
+    pub fn essence(&self) -> String {
+        format!("{}/{}", self.type_, self.subtype)
+    }
+
+    /// The value of a parameter.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The parameters, in order.
+    pub fn params(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.params.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    /// Whether this media type, which may be a range such as `text/*` or
+    /// `*/*`, includes `other`. Parameters of the range must also be present
+    /// on `other`.
+    pub fn includes(&self, other: &MediaType) -> bool {
+        (self.type_ == "*" || self.type_ == other.type_)
+            && (self.subtype == "*" || self.subtype == other.subtype)
+            && self.params.iter().all(|(name, value)| {
+                other
+                    .param(name)
+                    .is_some_and(|v| v.eq_ignore_ascii_case(value))
+            })
+    }
+
+    /// Parse a media range from an `Accept` header, returning its `q` weight
+    /// separately. Extension parameters after the weight are ignored.
+    pub(crate) fn parse_range(s: &str) -> Result<(Self, Option<f32>), InvalidMediaType> {
+        let invalid = || InvalidMediaType(s.to_string());
+        let mut parts = split_params(s);
+        let essence = parts.next().ok_or_else(invalid)?;
+        let (type_, subtype) = essence.trim().split_once('/').ok_or_else(invalid)?;
+        let valid = |s: &str| !s.is_empty() && s.bytes().all(is_token);
+        if !valid(type_) || !valid(subtype) {
+            return Err(invalid());
+        }
+
+        let mut media_type = MediaType {
+            type_: type_.to_ascii_lowercase(),
+            subtype: subtype.to_ascii_lowercase(),
+            params: Vec::new(),
+        };
+        let mut quality = None;
+        for param in parts {
+            let param = param.trim();
+            if param.is_empty() {
+                continue;
+            }
+            let (name, value) = param.split_once('=').ok_or_else(invalid)?;
+            let name = name.trim().to_ascii_lowercase();
+            let value = value.trim();
+            let value = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+                Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+                None => value.to_string(),
+            };
+            if name == "q" {
+                // Anything after the weight is an extension parameter
+                quality = Some(value.parse().map_err(|_| invalid())?);
+                break;
+            }
+            media_type.params.push((name, value));
+        }
+        Ok((media_type, quality))
+    }
+}
+
+/// Split at semicolons which aren't inside quoted strings.
+fn split_params(s: &str) -> impl Iterator<Item = &str> {
+    let mut quoted = false;
+    let mut escaped = false;
+    s.split(move |c| {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => return true,
+            _ => {}
+        }
+        false
+    })
+}
+
+fn is_token(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+impl FromStr for MediaType {
+    type Err = InvalidMediaType;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_range(s).map(|(media_type, _)| media_type)
+    }
+}
+
+impl fmt::Display for MediaType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.type_, self.subtype)?;
+        for (name, value) in &self.params {
+            if !value.is_empty() && value.bytes().all(is_token) {
+                write!(f, "; {}={}", name, value)?;
+            } else {
+                let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+                write!(f, "; {}=\"{}\"", name, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Error returned when a media type can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidMediaType(String);
+
+impl fmt::Display for InvalidMediaType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid media type: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidMediaType {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_media_types() {
+        let media_type: MediaType = "Text/HTML; Charset=\"utf-8\"; foo=\"a;b\"".parse().unwrap();
+        assert_eq!(media_type.essence(), "text/html");
+        assert_eq!(media_type.param("charset"), Some("utf-8"));
+        assert_eq!(media_type.param("foo"), Some("a;b"));
+        assert_eq!(
+            media_type.to_string(),
+            "text/html; charset=utf-8; foo=\"a;b\""
+        );
+
+        let (range, quality) = MediaType::parse_range("text/*;level=1;q=0.5;ext=1").unwrap();
+        assert_eq!(range.to_string(), "text/*; level=1");
+        assert_eq!(quality, Some(0.5));
+        assert!(!range.includes(&media_type));
+        assert!(MediaType::from_static("*/*").includes(&media_type));
+
+        assert!("json".parse::<MediaType>().is_err());
+        assert!("text/ html".parse::<MediaType>().is_err());
+    }
+}
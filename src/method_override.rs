@@ -0,0 +1,183 @@
+//! Hyper service supporting the `X-HTTP-Method-Override` header.
+//!
+//! Some clients sit behind proxies which only allow `GET` and `POST`. Such
+//! clients can send a `POST` request with an `X-HTTP-Method-Override` header
+//! naming the intended method, which this middleware applies before the
+//! request reaches the API.
+//!
+//! Only `POST` requests are overridden, and only to methods in an allowed set
+//! (by default `PUT`, `PATCH` and `DELETE`). This middleware is opt-in, as it
+//! allows clients to bypass method restrictions applied by proxies.
+
+use futures::FutureExt;
+use hyper::service::Service;
+use hyper::{Method, Request};
+use std::sync::Arc;
+
+/// Header - `X-HTTP-Method-Override` - used to override the method of a `POST` request.
+pub const X_HTTP_METHOD_OVERRIDE: &str = "X-HTTP-Method-Override";
+
+/// Apply the method override header, if present, to a request.
+fn apply_override<B>(req: &mut Request<B>, allowed: &[Method]) {
+    if req.method() != Method::POST {
+        return;
+    }
+
+    let method = req
+        .headers_mut()
+        .remove(X_HTTP_METHOD_OVERRIDE)
+        .and_then(|value| Method::from_bytes(value.as_bytes()).ok());
+
+    if let Some(method) = method.filter(|method| allowed.contains(method)) {
+        *req.method_mut() = method;
+    }
+}
+
+fn default_allowed() -> Arc<[Method]> {
+    Arc::from([Method::PUT, Method::PATCH, Method::DELETE])
+}
+
+/// Middleware wrapper service which applies `X-HTTP-Method-Override` headers
+/// on `POST` requests.
+#[derive(Debug)]
+pub struct MethodOverrideMakeService<T> {
+    inner: T,
+    allowed: Arc<[Method]>,
+}
+
+impl<T> MethodOverrideMakeService<T> {
+    /// Create a new MethodOverrideMakeService struct wrapping a value, allowing
+    /// overrides to `PUT`, `PATCH` and `DELETE`.
+    pub fn new(inner: T) -> Self {
+        MethodOverrideMakeService {
+            inner,
+            allowed: default_allowed(),
+        }
+    }
+
+    /// Set the methods which requests may be overridden to.
+    pub fn allowed_methods<I: IntoIterator<Item = Method>>(mut self, methods: I) -> Self {
+        self.allowed = methods.into_iter().collect();
+        self
+    }
+}
+
+impl<Inner, Target> Service<Target> for MethodOverrideMakeService<Inner>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = MethodOverrideService<Inner::Response>;
+    type Error = Inner::Error;
+    type Future = futures::future::BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let allowed = self.allowed.clone();
+        Box::pin(
+            self.inner
+                .call(target)
+                .map(|s| Ok(MethodOverrideService { inner: s?, allowed })),
+        )
+    }
+}
+
+/// Middleware wrapper service which applies `X-HTTP-Method-Override` headers
+/// on `POST` requests. Handles both plain requests, and requests carrying a
+/// context.
+#[derive(Debug, Clone)]
+pub struct MethodOverrideService<T> {
+    inner: T,
+    allowed: Arc<[Method]>,
+}
+
+impl<T> MethodOverrideService<T> {
+    /// Create a new MethodOverrideService struct wrapping a value, allowing
+    /// overrides to `PUT`, `PATCH` and `DELETE`.
+    pub fn new(inner: T) -> Self {
+        MethodOverrideService {
+            inner,
+            allowed: default_allowed(),
+        }
+    }
+
+    /// Set the methods which requests may be overridden to.
+    pub fn allowed_methods<I: IntoIterator<Item = Method>>(mut self, methods: I) -> Self {
+        self.allowed = methods.into_iter().collect();
+        self
+    }
+}
+
+impl<Inner, Body> Service<Request<Body>> for MethodOverrideService<Inner>
+where
+    Inner: Service<Request<Body>>,
+{
+    type Response = Inner::Response;
+    type Error = Inner::Error;
+    type Future = Inner::Future;
+
+    fn call(&self, mut req: Request<Body>) -> Self::Future {
+        apply_override(&mut req, &self.allowed);
+        self.inner.call(req)
+    }
+}
+
+impl<Inner, Body, Context> Service<(Request<Body>, Context)> for MethodOverrideService<Inner>
+where
+    Inner: Service<(Request<Body>, Context)>,
+{
+    type Response = Inner::Response;
+    type Error = Inner::Error;
+    type Future = Inner::Future;
+
+    fn call(&self, (mut req, context): (Request<Body>, Context)) -> Self::Future {
+        apply_override(&mut req, &self.allowed);
+        self.inner.call((req, context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MethodService;
+
+    impl Service<Request<()>> for MethodService {
+        type Response = (Method, bool);
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, req: Request<()>) -> Self::Future {
+            let has_header = req.headers().contains_key(X_HTTP_METHOD_OVERRIDE);
+            futures::future::ok((req.method().clone(), has_header))
+        }
+    }
+
+    async fn send(
+        service: &MethodOverrideService<MethodService>,
+        method: Method,
+        over: &str,
+    ) -> Method {
+        let req = Request::builder()
+            .method(method.clone())
+            .header(X_HTTP_METHOD_OVERRIDE, over)
+            .body(())
+            .unwrap();
+        let (new_method, has_header) = service.call(req).await.unwrap();
+        // The header is consumed from POST requests only.
+        assert_eq!(has_header, method != Method::POST);
+        new_method
+    }
+
+    #[tokio::test]
+    async fn overrides_post() {
+        let service = MethodOverrideService::new(MethodService);
+
+        assert_eq!(send(&service, Method::POST, "PATCH").await, Method::PATCH);
+        assert_eq!(send(&service, Method::POST, "delete").await, Method::POST);
+        assert_eq!(send(&service, Method::POST, "GET").await, Method::POST);
+        assert_eq!(send(&service, Method::GET, "DELETE").await, Method::GET);
+
+        let service = service.allowed_methods([Method::GET]);
+        assert_eq!(send(&service, Method::POST, "GET").await, Method::GET);
+    }
+}
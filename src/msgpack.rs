@@ -0,0 +1,77 @@
+//! MessagePack request and response bodies.
+//!
+//! Structs are encoded as maps keyed by field name, rather than as arrays,
+//! so that they have the same shape as the JSON described by the schema and
+//! can be read by clients without the Rust definitions.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+
+/// The `application/msgpack` media type.
+pub const APPLICATION_MSGPACK: &str = "application/msgpack";
+
+/// Whether a `Content-Type` is MessagePack: `application/msgpack`, or the
+/// `application/x-msgpack` and `application/vnd.msgpack` aliases.
+pub fn is_msgpack(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    matches!(
+        essence.as_str(),
+        APPLICATION_MSGPACK | "application/x-msgpack" | "application/vnd.msgpack"
+    )
+}
+
+/// Error returned when a MessagePack body can't be written or read.
+#[derive(Debug)]
+pub struct MsgpackError(String);
+
+impl fmt::Display for MsgpackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid MessagePack: {}", self.0)
+    }
+}
+
+impl std::error::Error for MsgpackError {}
+
+/// Serialize `value` as MessagePack.
+pub fn to_vec<T: Serialize>(value: &T) -> Result<Vec<u8>, MsgpackError> {
+    rmp_serde::to_vec_named(value).map_err(|e| MsgpackError(e.to_string()))
+}
+
+/// Deserialize a MessagePack body.
+pub fn from_slice<T: DeserializeOwned>(body: &[u8]) -> Result<T, MsgpackError> {
+    rmp_serde::from_slice(body).map_err(|e| MsgpackError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Pet {
+        id: u64,
+        name: String,
+    }
+
+    #[test]
+    fn round_trips() {
+        let pet = Pet {
+            id: 1,
+            name: "Rex".to_string(),
+        };
+        let body = to_vec(&pet).unwrap();
+        // A two entry map, keyed by field name
+        assert_eq!(&body[..4], b"\x82\xa2id");
+        assert_eq!(from_slice::<Pet>(&body).unwrap(), pet);
+        assert!(from_slice::<Pet>(b"\xc1").is_err());
+
+        assert!(is_msgpack("application/x-msgpack"));
+        assert!(!is_msgpack("application/json"));
+    }
+}
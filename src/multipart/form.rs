@@ -1,6 +1,38 @@
 //! Helper functions for multipart/form-data support
+//!
+//! `Multipart` parses a `multipart/form-data` request body, as described in
+//! RFC 7578, as a sequence of parts, each of which has a streaming body - so
+//! that file uploads don't need to be held in memory.
+//!
+//! ```rust
+//! # use hyper::body::Body;
+//! # use hyper::Request;
+//! # use swagger::multipart::form::{boundary, Limits, Multipart, MultipartError};
+//! # async fn upload<B>(request: Request<B>) -> Result<(), MultipartError>
+//! # where
+//! #     B: Body<Data = hyper::body::Bytes>,
+//! #     B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! # {
+//! let boundary = boundary(request.headers()).ok_or(MultipartError::MissingBoundary)?;
+//! let mut multipart = Multipart::new(request.into_body(), &boundary)
+//!     .limits(Limits::new().max_part_size(10 * 1024 * 1024));
+//!
+//! while let Some(mut part) = multipart.next_part().await? {
+//!     if part.filename().is_some() {
+//!         while let Some(chunk) = part.chunk().await? {
+//!             // Write the chunk to a file...
+//!         }
+//!     } else {
+//!         let value = part.text().await?;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
 use hyper::header::{HeaderMap, CONTENT_TYPE};
 
+pub use super::reader::{Field, Limits, Multipart, MultipartError, Part};
+
 /// Utility function to get the multipart boundary marker (if any) from the Headers.
 pub fn boundary(headers: &HeaderMap) -> Option<String> {
     headers.get(CONTENT_TYPE).and_then(|content_type| {
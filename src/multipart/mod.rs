@@ -4,3 +4,6 @@
 pub mod form;
 #[cfg(feature = "multipart_related")]
 pub mod related;
+
+#[cfg(any(feature = "multipart_form", feature = "multipart_related"))]
+mod reader;
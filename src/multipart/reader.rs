@@ -0,0 +1,674 @@
+//! Streaming parser for multipart bodies, as described in RFC 2046.
+use crate::header::content_disposition;
+use futures::future::poll_fn;
+use futures::ready;
+use futures::stream::Stream;
+use futures::task::{Context, Poll};
+use hyper::body::{Body, Buf, Bytes};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_DISPOSITION, CONTENT_TYPE};
+use std::error::Error;
+use std::fmt;
+use std::pin::Pin;
+
+const CONTENT_ID: HeaderName = HeaderName::from_static("content-id");
+
+/// Error parsing a multipart body.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum MultipartError {
+    /// The request has no multipart boundary in its `Content-Type`.
+    MissingBoundary,
+    /// Reading the underlying body failed.
+    Body(Box<dyn Error + Send + Sync>),
+    /// The body isn't a valid multipart body.
+    Malformed(&'static str),
+    /// The body has more parts than allowed.
+    TooManyParts(usize),
+    /// A part's body is larger than allowed.
+    PartTooLarge(u64),
+    /// The body is larger than allowed.
+    BodyTooLarge(u64),
+    /// A field collected as text isn't valid UTF-8.
+    InvalidUtf8,
+}
+
+impl fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MultipartError::MissingBoundary => f.write_str("Missing multipart boundary"),
+            MultipartError::Body(e) => write!(f, "Failed to read multipart body: {}", e),
+            MultipartError::Malformed(reason) => write!(f, "Malformed multipart body: {}", reason),
+            MultipartError::TooManyParts(limit) => {
+                write!(f, "Multipart body has more than {} parts", limit)
+            }
+            MultipartError::PartTooLarge(limit) => {
+                write!(f, "Multipart part is larger than {} bytes", limit)
+            }
+            MultipartError::BodyTooLarge(limit) => {
+                write!(f, "Multipart body is larger than {} bytes", limit)
+            }
+            MultipartError::InvalidUtf8 => f.write_str("Multipart field is not valid UTF-8"),
+        }
+    }
+}
+
+impl Error for MultipartError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MultipartError::Body(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Limits on the size of a multipart body, to protect servers from
+/// resource exhaustion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    parts: usize,
+    part_size: Option<u64>,
+    total_size: Option<u64>,
+    field_size: usize,
+    header_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            parts: 100,
+            part_size: None,
+            total_size: None,
+            field_size: 64 * 1024,
+            header_size: 8 * 1024,
+        }
+    }
+}
+
+impl Limits {
+    /// Create the default limits: at most 100 parts, with at most 8KiB of
+    /// headers each, and fields collected into memory of at most 64KiB.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of parts.
+    pub fn max_parts(mut self, parts: usize) -> Self {
+        self.parts = parts;
+        self
+    }
+
+    /// Set the maximum size of any part's body. By default, parts streamed
+    /// with `Part::chunk` are unlimited.
+    pub fn max_part_size(mut self, bytes: u64) -> Self {
+        self.part_size = Some(bytes);
+        self
+    }
+
+    /// Set the maximum size of the whole body. By default, this is
+    /// unlimited.
+    pub fn max_total_size(mut self, bytes: u64) -> Self {
+        self.total_size = Some(bytes);
+        self
+    }
+
+    /// Set the maximum size of a part collected into memory, using
+    /// `Part::bytes` or `Part::text`.
+    pub fn max_field_size(mut self, bytes: usize) -> Self {
+        self.field_size = bytes;
+        self
+    }
+
+    /// Set the maximum size of each part's headers.
+    pub fn max_header_size(mut self, bytes: usize) -> Self {
+        self.header_size = bytes;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Before the first delimiter.
+    Preamble,
+    /// After a delimiter, before the part's headers or the close delimiter.
+    Delimiter,
+    /// Reading a part's headers.
+    Headers,
+    /// Reading a part's body.
+    Body,
+    /// After the close delimiter.
+    Done,
+}
+
+/// Streaming parser for a multipart body - e.g. `multipart/form-data` or
+/// `multipart/related`.
+pub struct Multipart<B> {
+    body: Pin<Box<B>>,
+    /// Delimiter between parts - CRLF, "--" and the boundary.
+    delimiter: Vec<u8>,
+    buf: Vec<u8>,
+    eof: bool,
+    state: State,
+    limits: Limits,
+    total_size: u64,
+    parts: usize,
+    part_size: u64,
+    root: Option<String>,
+}
+
+impl<B> fmt::Debug for Multipart<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Multipart")
+            .field("state", &self.state)
+            .field("limits", &self.limits)
+            .field("parts", &self.parts)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A part of a multipart body, as a field in a form, collected into memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    /// Name of the field.
+    pub name: Option<String>,
+    /// Filename of the field, if it's a file upload.
+    pub filename: Option<String>,
+    /// Content type of the field.
+    pub content_type: Option<mime::Mime>,
+    /// Content ID of the field, without the surrounding angle brackets.
+    pub content_id: Option<String>,
+    /// Whether this is the root part of a `multipart/related` body.
+    pub is_root: bool,
+    /// Contents of the field.
+    pub data: Bytes,
+}
+
+impl<B> Multipart<B>
+where
+    B: Body,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    /// Parse `body` using the multipart boundary from the request's
+    /// `Content-Type` - see `boundary`.
+    pub fn new(body: B, boundary: &str) -> Self {
+        let mut delimiter = b"\r\n--".to_vec();
+        delimiter.extend_from_slice(boundary.as_bytes());
+        Multipart {
+            body: Box::pin(body),
+            delimiter,
+            // Allow the first delimiter to be matched at the very start of
+            // the body
+            buf: b"\r\n".to_vec(),
+            eof: false,
+            state: State::Preamble,
+            limits: Limits::default(),
+            total_size: 0,
+            parts: 0,
+            part_size: 0,
+            root: None,
+        }
+    }
+
+    /// Set the limits used when parsing the body.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Set the Content ID of the root part of a `multipart/related` body,
+    /// from the `start` parameter of its `Content-Type`. By default, the
+    /// first part is the root.
+    pub fn root(mut self, content_id: &str) -> Self {
+        self.root = Some(strip_angle_brackets(content_id).to_string());
+        self
+    }
+
+    /// Get the next part of the body, skipping the rest of the current part.
+    /// Returns `None` once all parts have been read.
+    pub async fn next_part(&mut self) -> Result<Option<Part<'_, B>>, MultipartError> {
+        let headers = match poll_fn(|cx| self.poll_next_part(cx)).await? {
+            Some(headers) => headers,
+            None => return Ok(None),
+        };
+
+        let (name, filename) = headers
+            .get(CONTENT_DISPOSITION)
+            .and_then(|value| value.to_str().ok())
+            .map(content_disposition)
+            .unwrap_or_default();
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok());
+        let content_id = headers
+            .get(CONTENT_ID)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| strip_angle_brackets(value).to_string());
+        let is_root = match &self.root {
+            Some(root) => content_id.as_ref() == Some(root),
+            None => self.parts == 1,
+        };
+
+        Ok(Some(Part {
+            multipart: self,
+            headers,
+            name,
+            filename,
+            content_type,
+            content_id,
+            is_root,
+        }))
+    }
+
+    /// Read the rest of the body, collecting each part into memory. Each part
+    /// is limited to the maximum field size.
+    pub async fn collect_fields(mut self) -> Result<Vec<Field>, MultipartError> {
+        let mut fields = Vec::new();
+        while let Some(mut part) = self.next_part().await? {
+            let name = part.name.take();
+            let filename = part.filename.take();
+            let content_type = part.content_type.take();
+            let content_id = part.content_id.take();
+            let is_root = part.is_root;
+            let data = part.bytes().await?;
+            fields.push(Field {
+                name,
+                filename,
+                content_type,
+                content_id,
+                is_root,
+                data,
+            });
+        }
+        Ok(fields)
+    }
+
+    /// Read more of the underlying body into the buffer. Returns `false` at
+    /// the end of the body.
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<Result<bool, MultipartError>> {
+        while !self.eof {
+            match ready!(self.body.as_mut().poll_frame(cx)) {
+                Some(Ok(frame)) => {
+                    if let Ok(mut data) = frame.into_data() {
+                        let len = data.remaining();
+                        self.total_size += len as u64;
+                        if let Some(limit) = self.limits.total_size {
+                            if self.total_size > limit {
+                                return Poll::Ready(Err(MultipartError::BodyTooLarge(limit)));
+                            }
+                        }
+                        while data.has_remaining() {
+                            let chunk = data.chunk();
+                            self.buf.extend_from_slice(chunk);
+                            let advance = chunk.len();
+                            data.advance(advance);
+                        }
+                        if len > 0 {
+                            return Poll::Ready(Ok(true));
+                        }
+                    }
+                }
+                Some(Err(e)) => return Poll::Ready(Err(MultipartError::Body(e.into()))),
+                None => self.eof = true,
+            }
+        }
+        Poll::Ready(Ok(false))
+    }
+
+    /// Read more of the underlying body, failing at the end of the body.
+    fn poll_require(
+        &mut self,
+        cx: &mut Context<'_>,
+        reason: &'static str,
+    ) -> Poll<Result<(), MultipartError>> {
+        if ready!(self.poll_fill(cx))? {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Ready(Err(MultipartError::Malformed(reason)))
+        }
+    }
+
+    fn poll_next_part(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, MultipartError>> {
+        loop {
+            match self.state {
+                State::Preamble => match find(&self.buf, &self.delimiter) {
+                    Some(i) => {
+                        self.buf.drain(..i + self.delimiter.len());
+                        self.state = State::Delimiter;
+                    }
+                    None => {
+                        // Keep anything which may be the start of a delimiter
+                        let keep = self.delimiter.len() - 1;
+                        let discard = self.buf.len().saturating_sub(keep);
+                        self.buf.drain(..discard);
+                        ready!(self.poll_require(cx, "missing boundary"))?;
+                    }
+                },
+                State::Delimiter => {
+                    if self.buf.starts_with(b"--") {
+                        // Close delimiter - ignore any epilogue
+                        self.state = State::Done;
+                        continue;
+                    }
+                    match find(&self.buf, b"\r\n") {
+                        Some(i) => {
+                            // Transport padding may follow the delimiter
+                            if !self.buf[..i].iter().all(|b| *b == b' ' || *b == b'\t') {
+                                return Poll::Ready(Err(MultipartError::Malformed(
+                                    "invalid boundary",
+                                )));
+                            }
+                            self.buf.drain(..i + 2);
+                            self.state = State::Headers;
+                        }
+                        None if self.buf.len() > self.limits.header_size => {
+                            return Poll::Ready(Err(MultipartError::Malformed("invalid boundary")));
+                        }
+                        None => ready!(self.poll_require(cx, "unexpected end of body"))?,
+                    }
+                }
+                State::Headers => {
+                    let end = if self.buf.starts_with(b"\r\n") {
+                        Some((0, 2))
+                    } else {
+                        find(&self.buf, b"\r\n\r\n").map(|i| (i, i + 4))
+                    };
+                    match end {
+                        Some((end, consumed)) => {
+                            if end > self.limits.header_size {
+                                return Poll::Ready(Err(MultipartError::Malformed(
+                                    "part headers too large",
+                                )));
+                            }
+                            let headers = parse_headers(&self.buf[..end])?;
+                            self.buf.drain(..consumed);
+
+                            self.parts += 1;
+                            if self.parts > self.limits.parts {
+                                return Poll::Ready(Err(MultipartError::TooManyParts(
+                                    self.limits.parts,
+                                )));
+                            }
+                            self.part_size = 0;
+                            self.state = State::Body;
+                            return Poll::Ready(Ok(Some(headers)));
+                        }
+                        None if self.buf.len() > self.limits.header_size => {
+                            return Poll::Ready(Err(MultipartError::Malformed(
+                                "part headers too large",
+                            )));
+                        }
+                        None => ready!(self.poll_require(cx, "unexpected end of body"))?,
+                    }
+                }
+                State::Body => {
+                    // Skip the rest of the current part
+                    while ready!(self.poll_chunk(cx))?.is_some() {}
+                }
+                State::Done => return Poll::Ready(Ok(None)),
+            }
+        }
+    }
+
+    /// Read the next chunk of the current part's body.
+    fn poll_chunk(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Bytes>, MultipartError>> {
+        if self.state != State::Body {
+            return Poll::Ready(Ok(None));
+        }
+
+        loop {
+            let len = match find(&self.buf, &self.delimiter) {
+                Some(0) => {
+                    self.buf.drain(..self.delimiter.len());
+                    self.state = State::Delimiter;
+                    return Poll::Ready(Ok(None));
+                }
+                Some(i) => i,
+                // Keep anything which may be the start of a delimiter
+                None => self.buf.len().saturating_sub(self.delimiter.len() - 1),
+            };
+
+            if len > 0 {
+                self.part_size += len as u64;
+                if let Some(limit) = self.limits.part_size {
+                    if self.part_size > limit {
+                        return Poll::Ready(Err(MultipartError::PartTooLarge(limit)));
+                    }
+                }
+                let chunk = Bytes::copy_from_slice(&self.buf[..len]);
+                self.buf.drain(..len);
+                return Poll::Ready(Ok(Some(chunk)));
+            }
+
+            ready!(self.poll_require(cx, "unexpected end of body"))?;
+        }
+    }
+}
+
+/// A part of a multipart body. The part's body can be read in chunks, using
+/// `chunk` or as a `Stream`, or collected into memory.
+pub struct Part<'a, B> {
+    multipart: &'a mut Multipart<B>,
+    headers: HeaderMap,
+    name: Option<String>,
+    filename: Option<String>,
+    content_type: Option<mime::Mime>,
+    content_id: Option<String>,
+    is_root: bool,
+}
+
+impl<B> fmt::Debug for Part<'_, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Part")
+            .field("headers", &self.headers)
+            .field("name", &self.name)
+            .field("filename", &self.filename)
+            .field("content_type", &self.content_type)
+            .field("content_id", &self.content_id)
+            .field("is_root", &self.is_root)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B> Part<'_, B>
+where
+    B: Body,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    /// The name of the form field, from the `Content-Disposition` header.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The filename of an uploaded file, from the `Content-Disposition`
+    /// header.
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    /// The content type of the part, if specified.
+    pub fn content_type(&self) -> Option<&mime::Mime> {
+        self.content_type.as_ref()
+    }
+
+    /// The Content ID of the part, without the surrounding angle brackets.
+    pub fn content_id(&self) -> Option<&str> {
+        self.content_id.as_deref()
+    }
+
+    /// Whether this is the root part of a `multipart/related` body - see
+    /// `Multipart::root`.
+    pub fn is_root(&self) -> bool {
+        self.is_root
+    }
+
+    /// All headers of the part.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Read the next chunk of the part's body. Returns `None` at the end of
+    /// the part.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, MultipartError> {
+        poll_fn(|cx| self.multipart.poll_chunk(cx)).await
+    }
+
+    /// Collect the part's body into memory, limited to the maximum field
+    /// size.
+    pub async fn bytes(mut self) -> Result<Bytes, MultipartError> {
+        let limit = self.multipart.limits.field_size;
+        let mut data = Vec::new();
+        while let Some(chunk) = self.chunk().await? {
+            if data.len() + chunk.len() > limit {
+                return Err(MultipartError::PartTooLarge(limit as u64));
+            }
+            data.extend_from_slice(&chunk);
+        }
+        Ok(data.into())
+    }
+
+    /// Collect the part's body into a string, limited to the maximum field
+    /// size.
+    pub async fn text(self) -> Result<String, MultipartError> {
+        let data = self.bytes().await?;
+        String::from_utf8(data.to_vec()).map_err(|_| MultipartError::InvalidUtf8)
+    }
+}
+
+impl<B> Stream for Part<'_, B>
+where
+    B: Body,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    type Item = Result<Bytes, MultipartError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut()
+            .multipart
+            .poll_chunk(cx)
+            .map(Result::transpose)
+    }
+}
+
+/// Remove the angle brackets around a Content ID.
+fn strip_angle_brackets(content_id: &str) -> &str {
+    let content_id = content_id.trim();
+    content_id
+        .strip_prefix('<')
+        .and_then(|id| id.strip_suffix('>'))
+        .unwrap_or(content_id)
+}
+
+/// Find the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Parse the CRLF separated headers of a part.
+fn parse_headers(raw: &[u8]) -> Result<HeaderMap, MultipartError> {
+    let mut headers = HeaderMap::new();
+    for line in raw.split(|b| *b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+        let colon = line
+            .iter()
+            .position(|b| *b == b':')
+            .ok_or(MultipartError::Malformed("invalid part header"))?;
+        let name = HeaderName::from_bytes(&line[..colon])
+            .map_err(|_| MultipartError::Malformed("invalid part header"))?;
+        let value = HeaderValue::from_bytes(line[colon + 1..].trim_ascii())
+            .map_err(|_| MultipartError::Malformed("invalid part header"))?;
+        headers.append(name, value);
+    }
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use http_body_util::StreamBody;
+    use hyper::body::Frame;
+    use std::convert::Infallible;
+
+    const BODY: &[u8] = b"preamble\r\n--XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+        Holiday\r\n--XyZ  \r\n\
+        Content-Disposition: form-data; name=\"photo\"; filename=\"a \\\"b\\\".png\"\r\n\
+        Content-Type: image/png\r\n\r\n\
+        \x89PNG\r\n--X\r\n--XyZ--\r\nepilogue";
+
+    /// Body which returns the data in chunks of `size` bytes.
+    fn chunked(
+        data: &'static [u8],
+        size: usize,
+    ) -> StreamBody<impl Stream<Item = Result<Frame<Bytes>, Infallible>>> {
+        StreamBody::new(stream::iter(
+            data.chunks(size)
+                .map(|chunk| Ok(Frame::data(Bytes::from_static(chunk)))),
+        ))
+    }
+
+    #[tokio::test]
+    async fn parses_parts() {
+        for size in [1, 3, 7, BODY.len()] {
+            let mut multipart = Multipart::new(chunked(BODY, size), "XyZ");
+
+            let part = multipart.next_part().await.unwrap().unwrap();
+            assert_eq!(part.name(), Some("title"));
+            assert_eq!(part.filename(), None);
+            assert_eq!(part.text().await.unwrap(), "Holiday");
+
+            let mut part = multipart.next_part().await.unwrap().unwrap();
+            assert_eq!(part.name(), Some("photo"));
+            assert_eq!(part.filename(), Some("a \"b\".png"));
+            assert_eq!(part.content_type(), Some(&mime::IMAGE_PNG));
+            let mut data = Vec::new();
+            while let Some(chunk) = part.chunk().await.unwrap() {
+                data.extend_from_slice(&chunk);
+            }
+            assert_eq!(data, b"\x89PNG\r\n--X");
+
+            assert!(multipart.next_part().await.unwrap().is_none());
+        }
+
+        let fields = Multipart::new(chunked(BODY, 5), "XyZ")
+            .collect_fields()
+            .await
+            .unwrap();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[1].data, Bytes::from_static(b"\x89PNG\r\n--X"));
+    }
+
+    #[tokio::test]
+    async fn enforces_limits() {
+        let error = Multipart::new(chunked(BODY, 4), "XyZ")
+            .limits(Limits::new().max_parts(1))
+            .collect_fields()
+            .await
+            .unwrap_err();
+        assert!(matches!(error, MultipartError::TooManyParts(1)));
+
+        let error = Multipart::new(chunked(BODY, 4), "XyZ")
+            .limits(Limits::new().max_field_size(4))
+            .collect_fields()
+            .await
+            .unwrap_err();
+        assert!(matches!(error, MultipartError::PartTooLarge(4)));
+
+        let error = Multipart::new(chunked(&BODY[..60], 4), "XyZ")
+            .collect_fields()
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            MultipartError::Malformed("unexpected end of body")
+        ));
+    }
+}
@@ -1,9 +1,47 @@
 //! Helper functions for multipart/related support
+//!
+//! `RelatedBuilder` encodes a `multipart/related` body, as described in
+//! RFC 2387, and `Multipart` parses one as a sequence of streaming parts.
+//!
+//! ```rust
+//! # use hyper::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+//! # use swagger::multipart::related::{boundary, start, Multipart, MultipartError, RelatedBuilder};
+//! # async fn example() -> Result<(), MultipartError> {
+//! let mut metadata = HeaderMap::new();
+//! metadata.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+//! let mut document = HeaderMap::new();
+//! document.insert(CONTENT_TYPE, HeaderValue::from_static("application/pdf"));
+//!
+//! let related = RelatedBuilder::new()
+//!     .part(metadata, r#"{"title":"Report"}"#)
+//!     .part(document, &b"%PDF-1.7"[..]);
+//! let mut headers = HeaderMap::new();
+//! headers.insert(CONTENT_TYPE, related.content_type());
+//! let body = http_body_util::Full::new(related.into_body());
+//!
+//! let boundary = boundary(&headers).ok_or(MultipartError::MissingBoundary)?;
+//! let mut multipart = Multipart::new(body, &boundary);
+//! if let Some(start) = start(&headers) {
+//!     multipart = multipart.root(&start);
+//! }
+//! while let Some(part) = multipart.next_part().await? {
+//!     if part.is_root() {
+//!         let metadata = part.text().await?;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
 
-use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::body::Bytes;
+use hyper::header::{HeaderName, HeaderValue, InvalidHeaderValue, CONTENT_TYPE};
 use hyper::HeaderMap;
 use mime::Mime;
 
+pub use super::reader::{Field, Limits, Multipart, MultipartError, Part};
+
+const CONTENT_ID: HeaderName = HeaderName::from_static("content-id");
+
 /// Construct the Body for a multipart/related request. The mime 0.2.6 library
 /// does not parse quoted-string parameters correctly. The boundary doesn't
 /// need to be a quoted string if it does not contain a '/', hence ensure
@@ -39,6 +77,128 @@ pub fn create_multipart_headers(content_type: Option<&HeaderValue>) -> Result<He
     Ok(multipart_headers)
 }
 
+/// Utility function to get the multipart boundary marker (if any) of a
+/// `multipart/related` body from the Headers.
+pub fn boundary(headers: &HeaderMap) -> Option<String> {
+    related_content_type(headers)
+        .and_then(|mime| mime.get_param(mime::BOUNDARY).map(|x| x.to_string()))
+}
+
+/// Utility function to get the Content ID of the root part (if specified) of
+/// a `multipart/related` body from the Headers.
+pub fn start(headers: &HeaderMap) -> Option<String> {
+    related_content_type(headers).and_then(|mime| mime.get_param("start").map(|x| x.to_string()))
+}
+
+fn related_content_type(headers: &HeaderMap) -> Option<Mime> {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(|content_type| content_type.parse::<Mime>().ok())
+        .filter(|mime| mime.type_() == mime::MULTIPART && mime.subtype() == "related")
+}
+
+/// Builder for `multipart/related` bodies.
+///
+/// The first part is the root part, unless another is chosen using `root`.
+#[derive(Debug, Clone)]
+pub struct RelatedBuilder {
+    boundary: String,
+    start: Option<String>,
+    parts: Vec<(HeaderMap, Bytes)>,
+}
+
+impl Default for RelatedBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RelatedBuilder {
+    /// Create a new builder, with a randomly generated boundary.
+    pub fn new() -> Self {
+        RelatedBuilder {
+            boundary: String::from_utf8_lossy(&generate_boundary()).into_owned(),
+            start: None,
+            parts: Vec::new(),
+        }
+    }
+
+    /// Add a part, with the given headers - typically `Content-Type`, and
+    /// `Content-ID` for parts referenced by other parts.
+    pub fn part(mut self, headers: HeaderMap, body: impl Into<Bytes>) -> Self {
+        self.parts.push((headers, body.into()));
+        self
+    }
+
+    /// Use the part with the given Content ID as the root part.
+    ///
+    /// Fails if the Content ID isn't a valid header value.
+    pub fn root(mut self, content_id: &str) -> Result<Self, InvalidHeaderValue> {
+        let content_id = content_id.trim_start_matches('<').trim_end_matches('>');
+        HeaderValue::from_str(content_id)?;
+        self.start = Some(content_id.to_string());
+        Ok(self)
+    }
+
+    /// The boundary between parts.
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// The `Content-Type` of the body, including the boundary and the type of
+    /// the root part.
+    pub fn content_type(&self) -> HeaderValue {
+        let mut content_type = format!("multipart/related; boundary={}", self.boundary);
+
+        let root = match &self.start {
+            Some(start) => self.parts.iter().find(|(headers, _)| {
+                headers
+                    .get(CONTENT_ID)
+                    .and_then(|id| id.to_str().ok())
+                    .map(|id| id.trim_start_matches('<').trim_end_matches('>'))
+                    == Some(start.as_str())
+            }),
+            None => self.parts.first(),
+        };
+        if let Some(root_type) = root
+            .and_then(|(headers, _)| headers.get(CONTENT_TYPE))
+            .and_then(|root_type| root_type.to_str().ok())
+            .and_then(|root_type| root_type.parse::<Mime>().ok())
+        {
+            content_type.push_str(&format!("; type=\"{}\"", root_type.essence_str()));
+        }
+        if let Some(start) = &self.start {
+            content_type.push_str(&format!("; start=\"<{}>\"", start));
+        }
+
+        HeaderValue::from_str(&content_type).expect("Content-Type is a valid header")
+    }
+
+    /// Encode the body.
+    pub fn into_body(self) -> Bytes {
+        let mut body = Vec::new();
+        for (headers, data) in self.parts {
+            body.extend_from_slice(b"--");
+            body.extend_from_slice(self.boundary.as_bytes());
+            body.extend_from_slice(b"\r\n");
+            for (name, value) in &headers {
+                body.extend_from_slice(name.as_str().as_bytes());
+                body.extend_from_slice(b": ");
+                body.extend_from_slice(value.as_bytes());
+                body.extend_from_slice(b"\r\n");
+            }
+            body.extend_from_slice(b"\r\n");
+            body.extend_from_slice(&data);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(b"--");
+        body.extend_from_slice(self.boundary.as_bytes());
+        body.extend_from_slice(b"--\r\n");
+        body.into()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -110,4 +270,42 @@ mod tests {
             .unwrap()
             .contains("Couldn't read Content-Type header value"));
     }
+
+    #[tokio::test]
+    async fn encodes_and_parses_related() {
+        let mut document = HeaderMap::new();
+        document.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        document.insert(CONTENT_ID, HeaderValue::from_static("<doc>"));
+        let mut metadata = HeaderMap::new();
+        metadata.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        metadata.insert(CONTENT_ID, HeaderValue::from_static("<meta>"));
+
+        let related = RelatedBuilder::new()
+            .part(document, "Hello, World!")
+            .part(metadata, "{}")
+            .root("meta")
+            .unwrap();
+        assert!(RelatedBuilder::new().root("<meta\n>").is_err());
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, related.content_type());
+        assert!(headers[CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .ends_with("; type=\"application/json\"; start=\"<meta>\""));
+        assert_eq!(boundary(&headers).as_deref(), Some(related.boundary()));
+        assert_eq!(start(&headers).as_deref(), Some("<meta>"));
+
+        let body = http_body_util::Full::new(related.into_body());
+        let fields = Multipart::new(body, &boundary(&headers).unwrap())
+            .root(&start(&headers).unwrap())
+            .collect_fields()
+            .await
+            .unwrap();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0].content_id.as_deref(), Some("doc"));
+        assert!(!fields[0].is_root);
+        assert_eq!(fields[0].data, Bytes::from_static(b"Hello, World!"));
+        assert!(fields[1].is_root);
+        assert_eq!(fields[1].content_type, Some(mime::APPLICATION_JSON));
+    }
 }
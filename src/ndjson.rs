@@ -0,0 +1,252 @@
+//! Newline delimited JSON (NDJSON, or JSON Lines) bodies.
+//!
+//! `NdjsonBody` streams a sequence of values as a response body, writing each
+//! one as it's produced - so long-running list or export operations don't
+//! need to hold the whole result in memory. `NdjsonStream` is the inverse,
+//! for clients reading such a response.
+//!
+//! ```rust
+//! # use futures::stream::{self, TryStreamExt};
+//! # use swagger::ndjson::{NdjsonBody, NdjsonStream};
+//! # async fn example() -> Result<(), swagger::ndjson::NdjsonError> {
+//! let response = NdjsonBody::new(stream::iter(1..=3)).into_response();
+//!
+//! let stream = NdjsonStream::<_, u32>::new(response.into_body());
+//! let values: Vec<u32> = stream.try_collect().await?;
+//! assert_eq!(values, [1, 2, 3]);
+//! # Ok(())
+//! # }
+//! ```
+
+use futures::stream::{BoxStream, Stream, StreamExt};
+use hyper::body::{Body, Bytes, Frame};
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::Response;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// The `application/x-ndjson` media type.
+pub const APPLICATION_NDJSON: &str = "application/x-ndjson";
+
+/// A body streaming values as newline delimited JSON.
+pub struct NdjsonBody {
+    stream: BoxStream<'static, Result<Bytes, Box<dyn Error + Send + Sync>>>,
+}
+
+impl fmt::Debug for NdjsonBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NdjsonBody").finish_non_exhaustive()
+    }
+}
+
+impl NdjsonBody {
+    /// Create a body writing each value of `stream` as a line of JSON.
+    pub fn new<S, T>(stream: S) -> Self
+    where
+        S: Stream<Item = T> + Send + 'static,
+        T: Serialize + 'static,
+    {
+        Self::from_try_stream(stream.map(Ok::<_, std::convert::Infallible>))
+    }
+
+    /// Create a body writing each value of `stream` as a line of JSON. An
+    /// error ends the body with an error, so the client sees that the
+    /// response is incomplete.
+    pub fn from_try_stream<S, T, E>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<T, E>> + Send + 'static,
+        T: Serialize + 'static,
+        E: Into<Box<dyn Error + Send + Sync>> + 'static,
+    {
+        let stream = stream.map(|value| {
+            let mut line = serde_json::to_vec(&value.map_err(Into::into)?)?;
+            line.push(b'\n');
+            Ok(Bytes::from(line))
+        });
+        NdjsonBody {
+            stream: stream.boxed(),
+        }
+    }
+
+    /// Create a `200 OK` response streaming the body, with an
+    /// `application/x-ndjson` content type.
+    pub fn into_response(self) -> Response<NdjsonBody> {
+        let mut response = Response::new(self);
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(APPLICATION_NDJSON));
+        response
+    }
+}
+
+impl Body for NdjsonBody {
+    type Data = Bytes;
+    type Error = Box<dyn Error + Send + Sync>;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        self.stream.poll_next_unpin(cx).map_ok(Frame::data)
+    }
+}
+
+/// Error reading a newline delimited JSON body.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum NdjsonError {
+    /// The body couldn't be read.
+    Body(Box<dyn Error + Send + Sync>),
+    /// A line wasn't valid JSON for the expected type.
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for NdjsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NdjsonError::Body(e) => write!(f, "Failed to read body: {}", e),
+            NdjsonError::Json(e) => write!(f, "Invalid JSON line: {}", e),
+        }
+    }
+}
+
+impl Error for NdjsonError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NdjsonError::Body(e) => Some(e.as_ref()),
+            NdjsonError::Json(e) => Some(e),
+        }
+    }
+}
+
+/// A stream of values read from a newline delimited JSON body. Blank lines
+/// are skipped, and the last line needn't end with a newline.
+pub struct NdjsonStream<B, T> {
+    body: B,
+    buffer: Vec<u8>,
+    done: bool,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<B, T> fmt::Debug for NdjsonStream<B, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NdjsonStream")
+            .field("buffered", &self.buffer.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B, T> NdjsonStream<B, T> {
+    /// Read values from `body`.
+    pub fn new(body: B) -> Self {
+        NdjsonStream {
+            body,
+            buffer: Vec::new(),
+            done: false,
+            marker: PhantomData,
+        }
+    }
+
+    /// Take the next non-blank line from the buffer - or the remainder of
+    /// the buffer, once the body has ended.
+    fn next_line(&mut self) -> Option<Vec<u8>> {
+        loop {
+            let line = match self.buffer.iter().position(|&b| b == b'\n') {
+                Some(end) => {
+                    let mut line: Vec<u8> = self.buffer.drain(..=end).collect();
+                    line.pop();
+                    line
+                }
+                None if self.done && !self.buffer.is_empty() => std::mem::take(&mut self.buffer),
+                None => return None,
+            };
+            if !line.trim_ascii().is_empty() {
+                return Some(line);
+            }
+        }
+    }
+}
+
+impl<B, T> Stream for NdjsonStream<B, T>
+where
+    B: Body<Data = Bytes> + Unpin,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+    T: DeserializeOwned,
+{
+    type Item = Result<T, NdjsonError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(line) = self.next_line() {
+                return Poll::Ready(Some(
+                    serde_json::from_slice(&line).map_err(NdjsonError::Json),
+                ));
+            }
+            if self.done {
+                return Poll::Ready(None);
+            }
+
+            match Pin::new(&mut self.body).poll_frame(cx) {
+                Poll::Ready(Some(Ok(frame))) => {
+                    if let Ok(data) = frame.into_data() {
+                        self.buffer.extend_from_slice(&data);
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => {
+                    self.done = true;
+                    self.buffer.clear();
+                    return Poll::Ready(Some(Err(NdjsonError::Body(e.into()))));
+                }
+                Poll::Ready(None) => self.done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream::{self, TryStreamExt};
+    use http_body_util::{BodyExt, StreamBody};
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Pet {
+        name: String,
+    }
+
+    #[tokio::test]
+    async fn streams_lines() {
+        let pets = ["Rex", "Tiddles"].map(|name| Pet {
+            name: name.to_string(),
+        });
+        let response = NdjsonBody::new(stream::iter(pets)).into_response();
+        assert_eq!(response.headers()[CONTENT_TYPE], APPLICATION_NDJSON);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "{\"name\":\"Rex\"}\n{\"name\":\"Tiddles\"}\n");
+
+        // Lines split across chunks, blank lines, and no final newline
+        let chunks = ["{\"name\":", "\"Rex\"}\n\r\n{\"na", "me\":\"Tiddles\"}"];
+        let body =
+            StreamBody::new(stream::iter(chunks.map(|chunk| {
+                Ok::<_, std::convert::Infallible>(Frame::data(Bytes::from(chunk)))
+            })));
+        let pets: Vec<Pet> = NdjsonStream::new(body).try_collect().await.unwrap();
+        assert_eq!(pets[0].name, "Rex");
+        assert_eq!(pets[1].name, "Tiddles");
+
+        let body = NdjsonBody::new(stream::iter(["not a number"]));
+        let mut values = NdjsonStream::<_, u32>::new(body);
+        assert!(matches!(
+            values.next().await,
+            Some(Err(NdjsonError::Json(_)))
+        ));
+        assert!(values.next().await.is_none());
+    }
+}
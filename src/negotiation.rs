@@ -0,0 +1,376 @@
+//! Hyper service choosing response media types from the `Accept` header.
+//!
+//! `negotiate` picks the best of the media types an operation can produce,
+//! honouring wildcards and `q` weights as described in RFC 9110. The
+//! `NegotiationService` middleware does this for each request, rejecting
+//! requests which accept none of them with `406 Not Acceptable`, and storing
+//! the chosen type in the context for the API to use.
+//!
+//! ```rust
+//! # use swagger::negotiation::{negotiate, Negotiation};
+//! # use swagger::MediaType;
+//! let json = MediaType::from_static("application/json");
+//! let xml = MediaType::from_static("application/xml");
+//!
+//! let accept = "application/xml;q=0.9, application/*;q=0.5, */*;q=0";
+//! assert_eq!(negotiate(Some(accept), &[json.clone(), xml.clone()]), Some(xml.clone()));
+//!
+//! let negotiation = Negotiation::new([json.clone()])
+//!     .operation("getPet", [json, xml]);
+//! ```
+
+use crate::response::json_error;
+use crate::{Has, MediaType, RequestParser, XSpanIdString};
+use futures::future::{BoxFuture, FutureExt};
+use hyper::header::ACCEPT;
+use hyper::service::Service;
+use hyper::{HeaderMap, Request, Response, StatusCode};
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// Choose the best media type in `supported` for an `Accept` header.
+///
+/// Each supported type is weighted by the most specific range in the header
+/// which includes it, and the type with the highest weight is chosen - or
+/// the earliest in `supported`, if several are equally acceptable. Without
+/// an `Accept` header, the first supported type is chosen. Returns `None` if
+/// no type is acceptable.
+pub fn negotiate(accept: Option<&str>, supported: &[MediaType]) -> Option<MediaType> {
+    let accept = match accept {
+        Some(accept) if !accept.trim().is_empty() => accept,
+        _ => return supported.first().cloned(),
+    };
+
+    // Invalid ranges are ignored
+    let ranges: Vec<_> = accept
+        .split(',')
+        .filter_map(|range| MediaType::parse_range(range).ok())
+        .collect();
+
+    let mut best: Option<(&MediaType, f32)> = None;
+    for media_type in supported {
+        let quality = ranges
+            .iter()
+            .filter(|(range, _)| range.includes(media_type))
+            .max_by_key(|(range, _)| specificity(range))
+            .map(|(_, quality)| quality.unwrap_or(1.0).clamp(0.0, 1.0))
+            .unwrap_or(0.0);
+        if quality > 0.0 && best.is_none_or(|(_, best)| quality > best) {
+            best = Some((media_type, quality));
+        }
+    }
+    best.map(|(media_type, _)| media_type.clone())
+}
+
+/// How specific a media range is - more specific ranges take precedence.
+fn specificity(range: &MediaType) -> usize {
+    match (range.type_(), range.subtype()) {
+        ("*", _) => 0,
+        (_, "*") => 1,
+        _ => 2 + range.params().count(),
+    }
+}
+
+/// The media types produced by each operation of an API.
+#[derive(Debug, Clone, Default)]
+pub struct Negotiation {
+    default: Vec<MediaType>,
+    operations: HashMap<&'static str, Vec<MediaType>>,
+}
+
+impl Negotiation {
+    /// Create a configuration where every operation produces the given types,
+    /// in order of preference.
+    pub fn new<I: IntoIterator<Item = MediaType>>(default: I) -> Self {
+        Negotiation {
+            default: default.into_iter().collect(),
+            operations: HashMap::new(),
+        }
+    }
+
+    /// Set the types produced by an operation, identified by the ID returned
+    /// by the API's `RequestParser`. Operations which produce no body should
+    /// be given no types, so that any `Accept` header is allowed.
+    pub fn operation<I>(mut self, operation_id: &'static str, types: I) -> Self
+    where
+        I: IntoIterator<Item = MediaType>,
+    {
+        self.operations
+            .insert(operation_id, types.into_iter().collect());
+        self
+    }
+
+    fn supported(&self, operation_id: Option<&'static str>) -> &[MediaType] {
+        operation_id
+            .and_then(|id| self.operations.get(id))
+            .unwrap_or(&self.default)
+    }
+}
+
+/// Join all `Accept` headers into a single list.
+fn accept_header(headers: &HeaderMap) -> Option<String> {
+    let values: Vec<_> = headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+    (!values.is_empty()).then(|| values.join(","))
+}
+
+/// Middleware wrapper service which negotiates response media types.
+pub struct NegotiationMakeService<T, RP> {
+    inner: T,
+    negotiation: Arc<Negotiation>,
+    marker: PhantomData<fn(RP)>,
+}
+
+impl<T, RP> NegotiationMakeService<T, RP> {
+    /// Create a new NegotiationMakeService, using `RP` to identify the
+    /// operation of each request.
+    pub fn new(inner: T, negotiation: Negotiation) -> Self {
+        NegotiationMakeService {
+            inner,
+            negotiation: Arc::new(negotiation),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, RP> fmt::Debug for NegotiationMakeService<T, RP>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NegotiationMakeService")
+            .field("inner", &self.inner)
+            .field("negotiation", &self.negotiation)
+            .finish()
+    }
+}
+
+impl<Inner, RP, Target> Service<Target> for NegotiationMakeService<Inner, RP>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = NegotiationService<Inner::Response, RP>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let negotiation = self.negotiation.clone();
+        Box::pin(self.inner.call(target).map(|s| {
+            Ok(NegotiationService {
+                inner: s?,
+                negotiation,
+                marker: PhantomData,
+            })
+        }))
+    }
+}
+
+/// Middleware wrapper service which negotiates response media types,
+/// storing the chosen type in the context as `Option<MediaType>`. Servers
+/// will normally want to use `NegotiationMakeService`, which will create a
+/// `NegotiationService` for each connection.
+pub struct NegotiationService<T, RP> {
+    inner: T,
+    negotiation: Arc<Negotiation>,
+    marker: PhantomData<fn(RP)>,
+}
+
+impl<T, RP> NegotiationService<T, RP> {
+    /// Create a new NegotiationService, using `RP` to identify the operation
+    /// of each request.
+    pub fn new(inner: T, negotiation: Negotiation) -> Self {
+        NegotiationService {
+            inner,
+            negotiation: Arc::new(negotiation),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, RP> Clone for NegotiationService<T, RP>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        NegotiationService {
+            inner: self.inner.clone(),
+            negotiation: self.negotiation.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, RP> fmt::Debug for NegotiationService<T, RP>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NegotiationService")
+            .field("inner", &self.inner)
+            .field("negotiation", &self.negotiation)
+            .finish()
+    }
+}
+
+impl<T, RP, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for NegotiationService<T, RP>
+where
+    RP: RequestParser<ReqBody>,
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    T::Error: Send + 'static,
+    ResBody: From<String> + Send + 'static,
+    C: Has<Option<MediaType>> + Has<XSpanIdString>,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, mut context): (Request<ReqBody>, C)) -> Self::Future {
+        let supported = self.negotiation.supported(RP::parse_operation_id(&req));
+        if supported.is_empty() {
+            return Box::pin(self.inner.call((req, context)));
+        }
+
+        let accept = accept_header(req.headers());
+        match negotiate(accept.as_deref(), supported) {
+            Some(media_type) => {
+                context.set(Some(media_type));
+                Box::pin(self.inner.call((req, context)))
+            }
+            None => {
+                let supported: Vec<_> = supported.iter().map(ToString::to_string).collect();
+                let message = format!(
+                    "Not Acceptable - supported media types are: {}",
+                    supported.join(", ")
+                );
+                let x_span_id = Has::<XSpanIdString>::get(&context);
+                let response = json_error(StatusCode::NOT_ACCEPTABLE, &message, Some(&x_span_id.0));
+                Box::pin(futures::future::ok(response))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Push;
+
+    crate::new_context_type!(
+        TestContext,
+        TestEmptyContext,
+        XSpanIdString,
+        Option<MediaType>
+    );
+
+    type Context = crate::make_context_ty!(
+        TestContext,
+        TestEmptyContext,
+        Option<MediaType>,
+        XSpanIdString
+    );
+
+    struct TestParser;
+
+    impl<B> RequestParser<B> for TestParser {
+        fn parse_operation_id(req: &Request<B>) -> Option<&'static str> {
+            match req.uri().path() {
+                "/pets/1" => Some("getPet"),
+                "/pets/1/delete" => Some("deletePet"),
+                _ => None,
+            }
+        }
+    }
+
+    /// Service responding with the negotiated type.
+    struct EchoService;
+
+    impl Service<(Request<()>, Context)> for EchoService {
+        type Response = Response<String>;
+        type Error = ();
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (_, context): (Request<()>, Context)) -> Self::Future {
+            let media_type = Has::<Option<MediaType>>::get(&context);
+            let body = media_type
+                .as_ref()
+                .map_or("none".to_string(), ToString::to_string);
+            Box::pin(futures::future::ok(Response::new(body)))
+        }
+    }
+
+    #[test]
+    fn negotiates_media_types() {
+        let json = MediaType::from_static("application/json");
+        let xml = MediaType::from_static("application/xml");
+        let html = MediaType::from_static("text/html; level=1");
+        let supported = [json.clone(), xml.clone(), html.clone()];
+
+        assert_eq!(negotiate(None, &supported), Some(json.clone()));
+        assert_eq!(negotiate(Some("*/*"), &supported), Some(json.clone()));
+        assert_eq!(
+            negotiate(Some("application/json;q=0.5, application/*"), &supported),
+            Some(xml.clone())
+        );
+        assert_eq!(
+            negotiate(
+                Some("*/*;q=0.1, text/html;level=1;q=0.5, text/*"),
+                &supported
+            ),
+            Some(html.clone())
+        );
+        assert_eq!(
+            negotiate(Some("application/json;q=0, */*;q=0.2"), &supported[..1]),
+            None
+        );
+        assert_eq!(negotiate(Some("image/png, bogus"), &supported), None);
+    }
+
+    #[tokio::test]
+    async fn rejects_unacceptable_requests() {
+        let json = MediaType::from_static("application/json");
+        let xml = MediaType::from_static("application/xml");
+        let negotiation = Negotiation::new([json.clone()])
+            .operation("getPet", [json, xml])
+            .operation("deletePet", []);
+        let service = NegotiationService::<_, TestParser>::new(EchoService, negotiation);
+        let context = || {
+            TestEmptyContext
+                .push(XSpanIdString("span".to_string()))
+                .push(None::<MediaType>)
+        };
+
+        let request = |path, accept| {
+            let request = Request::get(path).header(ACCEPT, accept);
+            (request.body(()).unwrap(), context())
+        };
+
+        let response = service
+            .call(request("/pets/1", "application/xml"))
+            .await
+            .unwrap();
+        assert_eq!(response.body(), "application/xml");
+
+        let response = service
+            .call(request("/pets", "application/xml"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(
+            response.body(),
+            r#"{"code":406,"message":"Not Acceptable - supported media types are: application/json","x-span-id":"span"}"#
+        );
+
+        let response = service
+            .call(request("/pets/1/delete", "image/png"))
+            .await
+            .unwrap();
+        assert_eq!(response.body(), "none");
+    }
+}
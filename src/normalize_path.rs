@@ -0,0 +1,327 @@
+//! Hyper service normalizing request paths before they are routed.
+//!
+//! `NormalizePathService` collapses duplicate slashes, and resolves `.` and
+//! `..` segments, so that `//pets/./1/../2` is routed as `/pets/2`. Trailing
+//! slashes can be kept, stripped or required. Requests whose paths change are
+//! either rewritten before being passed on, or redirected to the normalized
+//! path with `308 Permanent Redirect`.
+//!
+//! Requests which try to escape the root with `..`, or which contain
+//! percent-encoded slashes, backslashes or dot segments - which the router
+//! might decode after normalization - are rejected with `400 Bad Request`.
+//!
+//! ```rust
+//! # use swagger::normalize_path::{normalize, TrailingSlash};
+//! assert_eq!(normalize("//pets/./1/../2/", TrailingSlash::Strip).unwrap(), "/pets/2");
+//! assert!(normalize("/pets/%2e%2e/secret", TrailingSlash::Keep).is_err());
+//! ```
+
+use crate::response::json_error;
+use crate::{Has, XSpanIdString};
+use futures::future::{BoxFuture, FutureExt};
+use hyper::header::{HeaderValue, LOCATION};
+use hyper::http::uri::PathAndQuery;
+use hyper::service::Service;
+use hyper::{Request, Response, StatusCode, Uri};
+use std::error::Error;
+use std::fmt;
+
+/// What to do with trailing slashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrailingSlash {
+    /// Keep trailing slashes, so `/pets/` and `/pets` are different paths.
+    #[default]
+    Keep,
+    /// Remove trailing slashes.
+    Strip,
+    /// Add a trailing slash to paths without one.
+    Require,
+}
+
+/// Error returned when a path can't be safely normalized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidPath(String);
+
+impl fmt::Display for InvalidPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid path: {}", self.0)
+    }
+}
+
+impl Error for InvalidPath {}
+
+/// Whether a segment contains a percent-encoded slash or backslash, or is a
+/// percent-encoded dot segment.
+fn encoded_traversal(segment: &str) -> bool {
+    let lower = segment.to_ascii_lowercase();
+    if lower.contains("%2f") || lower.contains("%5c") {
+        return true;
+    }
+    let decoded = lower.replace("%2e", ".");
+    decoded != lower && (decoded == "." || decoded == "..")
+}
+
+/// Normalize a path, collapsing duplicate slashes and resolving `.` and `..`
+/// segments. The asterisk-form target of `OPTIONS *` is left unchanged.
+pub fn normalize(path: &str, trailing_slash: TrailingSlash) -> Result<String, InvalidPath> {
+    if path == "*" {
+        return Ok(path.to_string());
+    }
+
+    let mut segments: Vec<&str> = Vec::new();
+    let mut directory = false;
+    for segment in path.split('/') {
+        if encoded_traversal(segment) {
+            let message = format!("{:?} contains an encoded separator", path);
+            return Err(InvalidPath(message));
+        }
+        // Paths ending in a dot segment refer to a directory
+        directory = matches!(segment, "" | "." | "..");
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments
+                    .pop()
+                    .ok_or_else(|| InvalidPath(format!("{:?} is outside the root", path)))?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    let mut normalized = format!("/{}", segments.join("/"));
+    let slash = match trailing_slash {
+        TrailingSlash::Keep => directory,
+        TrailingSlash::Strip => false,
+        TrailingSlash::Require => true,
+    };
+    if slash && !segments.is_empty() {
+        normalized.push('/');
+    }
+    Ok(normalized)
+}
+
+/// Normalize the path of a request, returning the normalized path if it
+/// changed.
+fn normalize_request<B>(
+    req: &Request<B>,
+    trailing_slash: TrailingSlash,
+) -> Result<Option<PathAndQuery>, InvalidPath> {
+    let path = req.uri().path();
+    let normalized = normalize(path, trailing_slash)?;
+    if normalized == path {
+        return Ok(None);
+    }
+    let path_and_query = match req.uri().query() {
+        Some(query) => format!("{}?{}", normalized, query),
+        None => normalized,
+    };
+    path_and_query
+        .parse()
+        .map(Some)
+        .map_err(|e| InvalidPath(format!("{}", e)))
+}
+
+/// How paths are normalized.
+#[derive(Debug, Clone, Copy, Default)]
+struct Config {
+    trailing_slash: TrailingSlash,
+    redirect: bool,
+}
+
+/// Middleware wrapper service which normalizes request paths.
+#[derive(Debug)]
+pub struct NormalizePathMakeService<T> {
+    inner: T,
+    config: Config,
+}
+
+impl<T> NormalizePathMakeService<T> {
+    /// Create a new NormalizePathMakeService, keeping trailing slashes, and
+    /// rewriting requests.
+    pub fn new(inner: T) -> Self {
+        NormalizePathMakeService {
+            inner,
+            config: Config::default(),
+        }
+    }
+
+    /// Set what to do with trailing slashes.
+    pub fn trailing_slash(mut self, trailing_slash: TrailingSlash) -> Self {
+        self.config.trailing_slash = trailing_slash;
+        self
+    }
+
+    /// Redirect requests to the normalized path, rather than rewriting them.
+    pub fn redirect(mut self, redirect: bool) -> Self {
+        self.config.redirect = redirect;
+        self
+    }
+}
+
+impl<Inner, Target> Service<Target> for NormalizePathMakeService<Inner>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = NormalizePathService<Inner::Response>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let config = self.config;
+        Box::pin(
+            self.inner
+                .call(target)
+                .map(move |s| Ok(NormalizePathService { inner: s?, config })),
+        )
+    }
+}
+
+/// Middleware wrapper service which normalizes request paths. Servers will
+/// normally want to use `NormalizePathMakeService`, which will create a
+/// `NormalizePathService` for each connection.
+#[derive(Debug, Clone)]
+pub struct NormalizePathService<T> {
+    inner: T,
+    config: Config,
+}
+
+impl<T> NormalizePathService<T> {
+    /// Create a new NormalizePathService, keeping trailing slashes, and
+    /// rewriting requests.
+    pub fn new(inner: T) -> Self {
+        NormalizePathService {
+            inner,
+            config: Config::default(),
+        }
+    }
+
+    /// Set what to do with trailing slashes.
+    pub fn trailing_slash(mut self, trailing_slash: TrailingSlash) -> Self {
+        self.config.trailing_slash = trailing_slash;
+        self
+    }
+
+    /// Redirect requests to the normalized path, rather than rewriting them.
+    pub fn redirect(mut self, redirect: bool) -> Self {
+        self.config.redirect = redirect;
+        self
+    }
+}
+
+impl<T, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for NormalizePathService<T>
+where
+    C: Has<XSpanIdString>,
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    T::Error: Send + 'static,
+    ResBody: From<String> + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (mut req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let path_and_query = match normalize_request(&req, self.config.trailing_slash) {
+            Ok(Some(path_and_query)) => path_and_query,
+            Ok(None) => return Box::pin(self.inner.call((req, context))),
+            Err(e) => {
+                return Box::pin(futures::future::ok(json_error(
+                    StatusCode::BAD_REQUEST,
+                    &e.to_string(),
+                    Some(&context.get().0),
+                )))
+            }
+        };
+
+        if self.config.redirect {
+            let mut response = Response::new(ResBody::from(String::new()));
+            *response.status_mut() = StatusCode::PERMANENT_REDIRECT;
+            if let Ok(location) = HeaderValue::from_str(path_and_query.as_str()) {
+                response.headers_mut().insert(LOCATION, location);
+            }
+            return Box::pin(futures::future::ok(response));
+        }
+
+        let mut parts = req.uri().clone().into_parts();
+        parts.path_and_query = Some(path_and_query);
+        if let Ok(uri) = Uri::from_parts(parts) {
+            *req.uri_mut() = uri;
+        }
+        Box::pin(self.inner.call((req, context)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContextBuilder, EmptyContext, Push};
+
+    #[test]
+    fn normalizes_paths() {
+        let cases = [
+            ("/", "/", "/", "/"),
+            ("//pets//1", "/pets/1", "/pets/1", "/pets/1/"),
+            ("/pets/./1/../2/", "/pets/2/", "/pets/2", "/pets/2/"),
+            ("/pets/1/..", "/pets/", "/pets", "/pets/"),
+            ("/pets/%2E/..%2f", "", "", ""),
+            ("/pets/../..", "", "", ""),
+        ];
+        for (path, keep, strip, require) in cases {
+            for (trailing_slash, expected) in [
+                (TrailingSlash::Keep, keep),
+                (TrailingSlash::Strip, strip),
+                (TrailingSlash::Require, require),
+            ] {
+                let normalized = normalize(path, trailing_slash).unwrap_or_default();
+                assert_eq!(normalized, expected, "{} {:?}", path, trailing_slash);
+            }
+        }
+        assert_eq!(
+            normalize("/a%2e/b..", TrailingSlash::Keep).unwrap(),
+            "/a%2e/b.."
+        );
+        assert_eq!(normalize("*", TrailingSlash::Require).unwrap(), "*");
+    }
+
+    struct TestApi;
+
+    impl<C> Service<(Request<()>, C)> for TestApi {
+        type Response = Response<String>;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, _): (Request<()>, C)) -> Self::Future {
+            futures::future::ok(Response::new(req.uri().to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn rewrites_and_redirects() {
+        let service = NormalizePathService::new(TestApi).trailing_slash(TrailingSlash::Strip);
+        let call = |service: &NormalizePathService<TestApi>, uri: &str| {
+            let context: ContextBuilder<XSpanIdString, EmptyContext> =
+                EmptyContext.push(XSpanIdString("span".to_string()));
+            service.call((Request::get(uri).body(()).unwrap(), context))
+        };
+
+        let response = call(&service, "//pets/./1/?q=a/../b").await.unwrap();
+        assert_eq!(response.body(), "/pets/1?q=a/../b");
+        let response = call(&service, "/../etc/passwd").await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let service = service.redirect(true);
+        let response = call(&service, "/pets/1/").await.unwrap();
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(response.headers()[LOCATION], "/pets/1");
+        let response = call(&service, "/pets/1").await.unwrap();
+        assert_eq!(response.body(), "/pets/1");
+
+        // Requests for the server as a whole aren't redirected
+        let context: ContextBuilder<XSpanIdString, EmptyContext> =
+            EmptyContext.push(XSpanIdString("span".to_string()));
+        let request = Request::options("*").body(()).unwrap();
+        let response = service.call((request, context)).await.unwrap();
+        assert_eq!(response.body(), "*");
+    }
+}
@@ -20,6 +20,29 @@ use std::mem;
 /// Note that this is distinct from a value that is optional and not present!
 ///
 /// Nullable implements many of the same methods as the Option type (map, unwrap, etc).
+///
+/// To distinguish between a value which is absent, present but null, and
+/// present - e.g. for the fields of a PATCH body - use `Option<Nullable<T>>`,
+/// with the serde helpers in this module:
+///
+/// ```
+/// # use serde::{Deserialize, Serialize};
+/// # use swagger::nullable_format::{default_optional_nullable, deserialize_optional_nullable};
+/// # use swagger::Nullable;
+/// #[derive(Deserialize, Serialize)]
+/// struct PetPatch {
+///     #[serde(deserialize_with = "deserialize_optional_nullable")]
+///     #[serde(default = "default_optional_nullable")]
+///     #[serde(skip_serializing_if = "Option::is_none")]
+///     tag: Option<Nullable<String>>,
+/// }
+///
+/// let patch: PetPatch = serde_json::from_str(r#"{"tag":null}"#).unwrap();
+/// assert_eq!(patch.tag, Some(Nullable::Null));
+///
+/// let patch: PetPatch = serde_json::from_str("{}").unwrap();
+/// assert_eq!(patch.tag, None);
+/// ```
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub enum Nullable<T> {
     /// Null value
@@ -503,9 +526,83 @@ impl<T> Nullable<T> {
     pub fn take(&mut self) -> Nullable<T> {
         mem::replace(self, Nullable::Null)
     }
+
+    /////////////////////////////////////////////////////////////////////////
+    // Converting to and from Option
+    /////////////////////////////////////////////////////////////////////////
+
+    /// Converts from `Option<T>` to `Nullable<T>`, mapping `None` to `Null`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use ::swagger::Nullable;
+    ///
+    /// assert_eq!(Nullable::from_option(Some(2)), Nullable::Present(2));
+    /// assert_eq!(Nullable::<u32>::from_option(None), Nullable::Null);
+    /// ```
+    #[inline]
+    pub fn from_option(value: Option<T>) -> Nullable<T> {
+        match value {
+            Some(x) => Nullable::Present(x),
+            None => Nullable::Null,
+        }
+    }
+
+    /// Converts from `Nullable<T>` to `Option<T>`, mapping `Null` to `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use ::swagger::Nullable;
+    ///
+    /// assert_eq!(Nullable::Present(2).into_option(), Some(2));
+    /// assert_eq!(Nullable::<u32>::Null.into_option(), None);
+    /// ```
+    #[inline]
+    pub fn into_option(self) -> Option<T> {
+        match self {
+            Nullable::Present(x) => Some(x),
+            Nullable::Null => None,
+        }
+    }
+
+    /// Converts a tri-state `Option<Option<T>>` - where the outer `None` is
+    /// absent, and the inner `None` is null - to an `Option<Nullable<T>>`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use ::swagger::Nullable;
+    ///
+    /// assert_eq!(Nullable::from_nested_option(Some(Some(2))), Some(Nullable::Present(2)));
+    /// assert_eq!(Nullable::<u32>::from_nested_option(Some(None)), Some(Nullable::Null));
+    /// assert_eq!(Nullable::<u32>::from_nested_option(None), None);
+    /// ```
+    #[inline]
+    pub fn from_nested_option(value: Option<Option<T>>) -> Option<Nullable<T>> {
+        value.map(Nullable::from_option)
+    }
+
+    /// Converts a tri-state `Option<Nullable<T>>` to an `Option<Option<T>>`,
+    /// where the outer `None` is absent, and the inner `None` is null.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use ::swagger::Nullable;
+    ///
+    /// assert_eq!(Nullable::into_nested_option(Some(Nullable::Present(2))), Some(Some(2)));
+    /// assert_eq!(Nullable::<u32>::into_nested_option(Some(Nullable::Null)), Some(None));
+    /// assert_eq!(Nullable::<u32>::into_nested_option(None), None);
+    /// ```
+    #[inline]
+    pub fn into_nested_option(value: Option<Nullable<T>>) -> Option<Option<T>> {
+        value.map(Nullable::into_option)
+    }
 }
 
-impl<'a, T: Clone> Nullable<&'a T> {
+impl<T: Clone> Nullable<&T> {
     /// Maps an `Nullable<&T>` to an `Nullable<T>` by cloning the contents of the
     /// Nullable.
     ///
@@ -577,6 +674,12 @@ impl<T> From<T> for Nullable<T> {
     }
 }
 
+impl<T> From<Nullable<T>> for Option<T> {
+    fn from(val: Nullable<T>) -> Option<T> {
+        val.into_option()
+    }
+}
+
 #[cfg(feature = "serdejson")]
 impl<T> Serialize for Nullable<T>
 where
@@ -802,6 +905,23 @@ mod serde_tests {
     }
 
     // The tests:
+    #[test]
+    fn optionalnullable_from_nested_option() {
+        for (value, json) in [
+            (None, "{}"),
+            (Some(None), "{\"item\":null}"),
+            (Some(Some("x".to_string())), "{\"item\":\"x\"}"),
+        ] {
+            let thing = OptionalNullableStringStruct {
+                item: Nullable::from_nested_option(value.clone()),
+            };
+            assert_eq!(::serde_json::to_string(&thing).unwrap(), json);
+
+            let thing: OptionalNullableStringStruct = ::serde_json::from_str(json).unwrap();
+            assert_eq!(Nullable::into_nested_option(thing.item), value);
+        }
+    }
+
     #[test]
     fn missing_optionalnullable_value() {
         let string = "{}";
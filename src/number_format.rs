@@ -0,0 +1,271 @@
+//! Numbers encoded as JSON strings.
+//!
+//! JSON parsers in other languages often read every number as a double,
+//! losing precision above 2^53, so APIs commonly send `format: int64` and
+//! money-like values as strings. These serde helpers write numbers as
+//! strings, and read them from either strings or numbers.
+//!
+//! ```
+//! # use serde::{Deserialize, Serialize};
+//! #[derive(Debug, Deserialize, Serialize)]
+//! struct Account {
+//!     #[serde(with = "swagger::number_format::string")]
+//!     id: i64,
+//!     #[serde(with = "swagger::number_format::option_string", default)]
+//!     parent: Option<u64>,
+//! }
+//!
+//! let account: Account = serde_json::from_str(r#"{"id": 9007199254740993}"#).unwrap();
+//! assert_eq!(
+//!     serde_json::to_string(&account).unwrap(),
+//!     r#"{"id":"9007199254740993","parent":null}"#
+//! );
+//! ```
+#[cfg(feature = "serdejson")]
+use serde::de::{Deserializer, Error, Visitor};
+#[cfg(feature = "serdejson")]
+use serde::ser::Serializer;
+#[cfg(any(feature = "serdejson", feature = "decimal"))]
+use std::fmt;
+#[cfg(feature = "serdejson")]
+use std::marker::PhantomData;
+#[cfg(feature = "decimal")]
+use std::ops::{Deref, DerefMut};
+#[cfg(any(feature = "serdejson", feature = "decimal"))]
+use std::str::FromStr;
+
+/// Serde helpers for numbers written as strings, for use with
+/// `#[serde(with = "swagger::number_format::string")]`.
+#[cfg(feature = "serdejson")]
+pub mod string {
+    use super::*;
+
+    /// Serialize a number as a string.
+    pub fn serialize<T: fmt::Display, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    /// Deserialize a number from a string or a number.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(NumberVisitor(PhantomData))
+    }
+}
+
+/// Serde helpers for optional numbers written as strings, for use with
+/// `#[serde(with = "swagger::number_format::option_string", default)]`.
+#[cfg(feature = "serdejson")]
+pub mod option_string {
+    use super::*;
+
+    /// Serialize an optional number as a string, or `null`.
+    pub fn serialize<T: fmt::Display, S: Serializer>(
+        value: &Option<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => serializer.collect_str(value),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    /// Deserialize an optional number from a string, a number or `null`.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_option(OptionVisitor(PhantomData))
+    }
+}
+
+#[cfg(feature = "serdejson")]
+struct NumberVisitor<T>(PhantomData<T>);
+
+#[cfg(feature = "serdejson")]
+impl<T> Visitor<'_> for NumberVisitor<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a number, or a string containing a number")
+    }
+
+    fn visit_str<E: Error>(self, value: &str) -> Result<T, E> {
+        value
+            .trim()
+            .parse()
+            .map_err(|e| E::custom(format!("invalid number {:?}: {}", value, e)))
+    }
+
+    fn visit_i64<E: Error>(self, value: i64) -> Result<T, E> {
+        self.visit_str(&value.to_string())
+    }
+
+    fn visit_u64<E: Error>(self, value: u64) -> Result<T, E> {
+        self.visit_str(&value.to_string())
+    }
+
+    fn visit_f64<E: Error>(self, value: f64) -> Result<T, E> {
+        self.visit_str(&value.to_string())
+    }
+}
+
+#[cfg(feature = "serdejson")]
+struct OptionVisitor<T>(PhantomData<T>);
+
+#[cfg(feature = "serdejson")]
+impl<'de, T> Visitor<'de> for OptionVisitor<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    type Value = Option<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a number, a string containing a number, or null")
+    }
+
+    fn visit_none<E: Error>(self) -> Result<Option<T>, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: Error>(self) -> Result<Option<T>, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Option<T>, D::Error> {
+        string::deserialize(deserializer).map(Some)
+    }
+}
+
+/// A decimal number, for money-like values which mustn't suffer from
+/// floating point rounding. Written as a JSON string, and read from either a
+/// string or a number.
+///
+/// ```
+/// # use swagger::number_format::Decimal;
+/// let price: Decimal = serde_json::from_str(r#""19.99""#).unwrap();
+/// assert_eq!(serde_json::to_string(&price).unwrap(), r#""19.99""#);
+/// ```
+#[cfg(feature = "decimal")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Decimal(pub rust_decimal::Decimal);
+
+#[cfg(feature = "decimal")]
+impl Deref for Decimal {
+    type Target = rust_decimal::Decimal;
+
+    fn deref(&self) -> &rust_decimal::Decimal {
+        &self.0
+    }
+}
+
+#[cfg(feature = "decimal")]
+impl DerefMut for Decimal {
+    fn deref_mut(&mut self) -> &mut rust_decimal::Decimal {
+        &mut self.0
+    }
+}
+
+#[cfg(feature = "decimal")]
+impl From<rust_decimal::Decimal> for Decimal {
+    fn from(value: rust_decimal::Decimal) -> Self {
+        Decimal(value)
+    }
+}
+
+#[cfg(feature = "decimal")]
+impl From<Decimal> for rust_decimal::Decimal {
+    fn from(value: Decimal) -> Self {
+        value.0
+    }
+}
+
+#[cfg(feature = "decimal")]
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(feature = "decimal")]
+impl FromStr for Decimal {
+    type Err = rust_decimal::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Accept exponents, as numbers from JSON may use them
+        rust_decimal::Decimal::from_str_exact(s)
+            .or_else(|_| rust_decimal::Decimal::from_scientific(s))
+            .map(Decimal)
+    }
+}
+
+#[cfg(all(feature = "decimal", feature = "serdejson"))]
+impl serde::Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        string::serialize(self, serializer)
+    }
+}
+
+#[cfg(all(feature = "decimal", feature = "serdejson"))]
+impl<'de> serde::Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        string::deserialize(deserializer)
+    }
+}
+
+#[cfg(all(test, feature = "serdejson"))]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Ids {
+        #[serde(with = "super::string")]
+        id: i64,
+        #[serde(with = "super::option_string", default)]
+        parent: Option<u64>,
+    }
+
+    #[test]
+    fn reads_strings_and_numbers() {
+        let ids: Ids =
+            serde_json::from_str(r#"{"id":"-12","parent":18446744073709551615}"#).unwrap();
+        assert_eq!(
+            ids,
+            Ids {
+                id: -12,
+                parent: Some(u64::MAX)
+            }
+        );
+        assert_eq!(
+            serde_json::to_string(&ids).unwrap(),
+            r#"{"id":"-12","parent":"18446744073709551615"}"#
+        );
+
+        let ids: Ids = serde_json::from_str(r#"{"id":1}"#).unwrap();
+        assert_eq!(ids.parent, None);
+        assert!(serde_json::from_str::<Ids>(r#"{"id":"1.5"}"#).is_err());
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn reads_decimals() {
+        use super::Decimal;
+        let price: Decimal = serde_json::from_str("1.1e2").unwrap();
+        assert_eq!(price.to_string(), "110");
+        let price: Decimal = serde_json::from_str(r#""0.10""#).unwrap();
+        assert_eq!(serde_json::to_string(&price).unwrap(), r#""0.10""#);
+    }
+}
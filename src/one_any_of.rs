@@ -1,17 +1,177 @@
 //! Implementations of OpenAPI `oneOf` and `anyOf` types, assuming rules are just types
 #[cfg(feature = "conversion")]
 use frunk_enum_derive::LabelledGenericEnum;
-use serde::{
-    de::Error,
-    Deserialize, Deserializer, Serialize, Serializer,
-    __private::de::{Content, ContentRefDeserializer},
-};
+#[cfg(feature = "serdejson")]
+use serde::de::{DeserializeOwned, Error};
+#[cfg(feature = "serdejson")]
+use serde::{Deserialize, Deserializer};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+#[cfg(feature = "serdejson")]
+use serde_json::Value;
 #[cfg(feature = "serdevalid")]
 use serde_valid::Validate;
 use std::fmt;
+#[cfg(feature = "serdejson")]
+use std::marker::PhantomData;
+#[cfg(feature = "serdejson")]
+use std::ops::{Deref, DerefMut};
 use std::str::FromStr;
 use std::string::ToString;
 
+/// A `oneOf` or `anyOf` type whose variants can be deserialized individually,
+/// as chosen by a discriminator.
+#[cfg(feature = "serdejson")]
+pub trait DeserializeVariant: Sized {
+    /// Deserialize the variant with the given index - 0 for `A`, 1 for `B`,
+    /// and so on. Returns `None` if there is no such variant.
+    fn deserialize_variant(index: usize, content: Value)
+        -> Option<Result<Self, serde_json::Error>>;
+}
+
+/// The discriminator of a polymorphic schema - the property whose value
+/// says which of the schemas an object matches.
+#[cfg(feature = "serdejson")]
+pub trait Discriminator {
+    /// The name of the discriminator property.
+    const PROPERTY: &'static str;
+
+    /// The index of the variant with the given discriminator value - 0 for
+    /// `A`, 1 for `B`, and so on.
+    fn variant(value: &str) -> Option<usize>;
+}
+
+/// A `oneOf` or `anyOf` type deserialized according to a discriminator `D`,
+/// rather than by trying each variant in turn.
+///
+/// ```rust
+/// # use serde::Deserialize;
+/// # use swagger::{Discriminated, Discriminator, OneOf2};
+/// #[derive(Debug, PartialEq, Deserialize)]
+/// struct Cat { name: String }
+/// #[derive(Debug, PartialEq, Deserialize)]
+/// struct Dog { name: String }
+///
+/// struct PetType;
+///
+/// impl Discriminator for PetType {
+///     const PROPERTY: &'static str = "petType";
+///
+///     fn variant(value: &str) -> Option<usize> {
+///         match value {
+///             "Cat" => Some(0),
+///             "Dog" => Some(1),
+///             _ => None,
+///         }
+///     }
+/// }
+///
+/// type Pet = Discriminated<PetType, OneOf2<Cat, Dog>>;
+///
+/// let pet: Pet = serde_json::from_str(r#"{"petType": "Dog", "name": "Rex"}"#).unwrap();
+/// assert_eq!(*pet, OneOf2::B(Dog { name: "Rex".to_string() }));
+/// ```
+#[cfg(feature = "serdejson")]
+pub struct Discriminated<D, T> {
+    inner: T,
+    marker: PhantomData<fn(D)>,
+}
+
+#[cfg(feature = "serdejson")]
+impl<D, T> Discriminated<D, T> {
+    /// Wrap a value.
+    pub fn new(inner: T) -> Self {
+        Discriminated {
+            inner,
+            marker: PhantomData,
+        }
+    }
+
+    /// Unwrap the value.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+#[cfg(feature = "serdejson")]
+impl<D, T> Deref for Discriminated<D, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+#[cfg(feature = "serdejson")]
+impl<D, T> DerefMut for Discriminated<D, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+#[cfg(feature = "serdejson")]
+impl<D, T: fmt::Debug> fmt::Debug for Discriminated<D, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+#[cfg(feature = "serdejson")]
+impl<D, T: Clone> Clone for Discriminated<D, T> {
+    fn clone(&self) -> Self {
+        Self::new(self.inner.clone())
+    }
+}
+
+#[cfg(feature = "serdejson")]
+impl<D, T: PartialEq> PartialEq for Discriminated<D, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+#[cfg(feature = "serdejson")]
+impl<D, T: Serialize> Serialize for Discriminated<D, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.inner.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serdejson")]
+impl<'de, D, T> Deserialize<'de> for Discriminated<D, T>
+where
+    D: Discriminator,
+    T: DeserializeVariant,
+{
+    fn deserialize<De: Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+        let content = Value::deserialize(deserializer)?;
+        let value = match content.get(D::PROPERTY) {
+            Some(Value::String(value)) => value,
+            _ => {
+                return Err(De::Error::custom(format!(
+                    "missing discriminator property `{}`",
+                    D::PROPERTY
+                )))
+            }
+        };
+        let variant = D::variant(value).ok_or_else(|| {
+            De::Error::custom(format!(
+                "unknown value `{}` of discriminator property `{}`",
+                value,
+                D::PROPERTY
+            ))
+        })?;
+        match T::deserialize_variant(variant, content) {
+            Some(result) => result.map(Self::new).map_err(De::Error::custom),
+            None => Err(De::Error::custom(format!(
+                "no variant {} for discriminator property `{}`",
+                variant,
+                D::PROPERTY
+            ))),
+        }
+    }
+}
+
 // Define a macro to define the common parts between `OneOf` and `AnyOf` enums for a specific
 // number of inner types.
 macro_rules! common_one_any_of {
@@ -33,6 +193,7 @@ macro_rules! common_one_any_of {
             ),*
         }
 
+        #[cfg(feature = "serde")]
         impl<$($i),*> Serialize for $t<$($i),*> where
             $($i: PartialEq + Serialize,)*
         {
@@ -52,6 +213,26 @@ macro_rules! common_one_any_of {
                 }
             }
         }
+
+        #[cfg(feature = "serdejson")]
+        impl<$($i),*> DeserializeVariant for $t<$($i),*> where
+            $($i: PartialEq + DeserializeOwned,)*
+        {
+            #[allow(unused_assignments)]
+            fn deserialize_variant(
+                index: usize,
+                content: Value,
+            ) -> Option<Result<Self, serde_json::Error>> {
+                let mut i = 0;
+                $(
+                    if i == index {
+                        return Some($i::deserialize(content).map(Self::$i));
+                    }
+                    i += 1;
+                )*
+                None
+            }
+        }
     }
 }
 
@@ -63,14 +244,15 @@ macro_rules! one_of {
     ) => {
         common_one_any_of!(oneOf, $t, $($i),*);
 
+        #[cfg(feature = "serdejson")]
         impl<'b, $($i),*> Deserialize<'b> for $t<$($i),*> where
             $($i: PartialEq + for<'a> Deserialize<'a>,)*
         {
             fn deserialize<De: Deserializer<'b>>(deserializer: De) -> Result<Self, De::Error> {
-                let content = Content::deserialize(deserializer)?;
+                let content = Value::deserialize(deserializer)?;
                 let mut result = Err(De::Error::custom("data did not match any within oneOf"));
                 $(
-                    if let Ok(inner) = $i::deserialize(ContentRefDeserializer::<De::Error>::new(&content)) {
+                    if let Ok(inner) = $i::deserialize(&content) {
                         if result.is_err() {
                             result = Ok(Self::$i(inner));
                         } else {
@@ -129,13 +311,14 @@ macro_rules! any_of {
     ) => {
         common_one_any_of!(anyOf, $t, $($i),*);
 
+        #[cfg(feature = "serdejson")]
         impl<'b, $($i),*> Deserialize<'b> for $t<$($i),*> where
             $($i: PartialEq + for<'a> Deserialize<'a>,)*
         {
             fn deserialize<De: Deserializer<'b>>(deserializer: De) -> Result<Self, De::Error> {
-                let content = Content::deserialize(deserializer)?;
+                let content = Value::deserialize(deserializer)?;
                 $(
-                    if let Ok(inner) = $i::deserialize(ContentRefDeserializer::<De::Error>::new(&content)) {
+                    if let Ok(inner) = $i::deserialize(&content) {
                         return Ok(Self::$i(inner));
                     }
                 )*
@@ -176,3 +359,55 @@ any_of!(AnyOf13, A, B, C, D, E, F, G, H, I, J, K, L, M);
 any_of!(AnyOf14, A, B, C, D, E, F, G, H, I, J, K, L, M, N);
 any_of!(AnyOf15, A, B, C, D, E, F, G, H, I, J, K, L, M, N, O);
 any_of!(AnyOf16, A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P);
+
+#[cfg(all(test, feature = "serdejson"))]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Cat {
+        name: String,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Dog {
+        name: String,
+    }
+
+    struct PetType;
+
+    impl Discriminator for PetType {
+        const PROPERTY: &'static str = "petType";
+
+        fn variant(value: &str) -> Option<usize> {
+            match value {
+                "Cat" => Some(0),
+                "Dog" => Some(1),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn dispatches_on_discriminator() {
+        // Both variants match, so only the discriminator can decide
+        let pet: Discriminated<PetType, OneOf2<Cat, Dog>> =
+            serde_json::from_str(r#"{"petType": "Dog", "name": "Rex"}"#).unwrap();
+        assert_eq!(
+            pet.into_inner(),
+            OneOf2::B(Dog {
+                name: "Rex".to_string()
+            })
+        );
+        assert!(serde_json::from_str::<OneOf2<Cat, Dog>>(r#"{"name": "Rex"}"#).is_err());
+
+        let error = serde_json::from_str::<Discriminated<PetType, AnyOf2<Cat, Dog>>>(
+            r#"{"petType": "Fish", "name": "Nemo"}"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown value `Fish` of discriminator property `petType`"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Hyper service answering `OPTIONS` requests from a route table.
+//!
+//! Generated servers don't implement `OPTIONS`, so return `404` or `405`
+//! for it. `OptionsService` instead answers `OPTIONS` requests for known
+//! paths with `204 No Content` and an `Allow` header listing the path's
+//! methods. Requests for unknown paths, and for paths whose routes include
+//! `OPTIONS`, are passed on to the wrapped service.
+//!
+//! CORS preflight requests are also `OPTIONS` requests, so `CorsService`
+//! should wrap this service, so that it answers them first - and can be
+//! given the same routes, to allow only the methods of each path.
+//!
+//! ```rust
+//! # use hyper::Method;
+//! # use swagger::options::OptionsService;
+//! # use swagger::routes::Routes;
+//! # struct Api;
+//! let routes = Routes::new().route("/pets", [Method::GET, Method::POST]);
+//! let service = OptionsService::new(Api, routes);
+//! ```
+
+use crate::routes::{allow_header, Routes};
+use futures::future::{BoxFuture, FutureExt};
+use hyper::header::ALLOW;
+use hyper::service::Service;
+use hyper::{Method, Request, Response, StatusCode};
+use std::sync::Arc;
+
+/// Middleware wrapper service which answers `OPTIONS` requests.
+#[derive(Debug)]
+pub struct OptionsMakeService<T> {
+    inner: T,
+    routes: Arc<Routes>,
+}
+
+impl<T> OptionsMakeService<T> {
+    /// Create a new OptionsMakeService.
+    pub fn new(inner: T, routes: Routes) -> Self {
+        OptionsMakeService {
+            inner,
+            routes: Arc::new(routes),
+        }
+    }
+}
+
+impl<Inner, Target> Service<Target> for OptionsMakeService<Inner>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = OptionsService<Inner::Response>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let routes = self.routes.clone();
+        Box::pin(
+            self.inner
+                .call(target)
+                .map(|s| Ok(OptionsService { inner: s?, routes })),
+        )
+    }
+}
+
+/// Middleware wrapper service which answers `OPTIONS` requests. Servers will
+/// normally want to use `OptionsMakeService`, which will create an
+/// `OptionsService` for each connection.
+#[derive(Debug, Clone)]
+pub struct OptionsService<T> {
+    inner: T,
+    routes: Arc<Routes>,
+}
+
+impl<T> OptionsService<T> {
+    /// Create a new OptionsService.
+    pub fn new(inner: T, routes: Routes) -> Self {
+        OptionsService {
+            inner,
+            routes: Arc::new(routes),
+        }
+    }
+}
+
+impl<T, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for OptionsService<T>
+where
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    T::Error: Send + 'static,
+    ResBody: From<String> + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        if req.method() == Method::OPTIONS {
+            match self.routes.allowed(req.uri().path()) {
+                Some(methods) if !methods.contains(&Method::OPTIONS) => {
+                    let mut response = Response::new(ResBody::from(String::new()));
+                    *response.status_mut() = StatusCode::NO_CONTENT;
+                    let methods = methods.iter().chain([&Method::OPTIONS]);
+                    response.headers_mut().insert(ALLOW, allow_header(methods));
+                    return Box::pin(futures::future::ok(response));
+                }
+                _ => {}
+            }
+        }
+        Box::pin(self.inner.call((req, context)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestApi;
+
+    impl Service<(Request<String>, ())> for TestApi {
+        type Response = Response<String>;
+        type Error = ();
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, _): (Request<String>, ())) -> Self::Future {
+            let mut response = Response::new(req.uri().path().to_string());
+            *response.status_mut() = StatusCode::NOT_FOUND;
+            Box::pin(futures::future::ok(response))
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_options() {
+        let routes = Routes::new()
+            .route("/pets/{id}", [Method::GET, Method::DELETE])
+            .route("/custom", [Method::OPTIONS]);
+        let service = OptionsService::new(TestApi, routes);
+        let request = |method, path| {
+            let request = Request::builder().method(method).uri(path);
+            (request.body(String::new()).unwrap(), ())
+        };
+
+        let response = service
+            .call(request(Method::OPTIONS, "/pets/1"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()[ALLOW], "GET, DELETE, OPTIONS");
+
+        for (method, path) in [
+            (Method::OPTIONS, "/owners"),
+            (Method::OPTIONS, "/custom"),
+            (Method::GET, "/pets/1"),
+        ] {
+            let response = service.call(request(method, path)).await.unwrap();
+            assert_eq!(response.body(), path);
+        }
+    }
+}
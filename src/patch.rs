@@ -0,0 +1,381 @@
+//! JSON Merge Patch (RFC 7386) and JSON Patch (RFC 6902) documents, as
+//! used by `PATCH` operations.
+//!
+//! Both can be applied to a `serde_json::Value`, or to any model which can be
+//! converted to and from one.
+//!
+//! ```rust
+//! # use serde::{Deserialize, Serialize};
+//! # use swagger::patch::{MergePatch, Patch};
+//! #[derive(Debug, PartialEq, Deserialize, Serialize)]
+//! struct Pet {
+//!     name: String,
+//!     tags: Vec<String>,
+//! }
+//!
+//! let pet = Pet { name: "Rex".to_string(), tags: vec![] };
+//!
+//! let merge: MergePatch = serde_json::from_str(r#"{"name": "Max"}"#).unwrap();
+//! let pet = merge.apply_to(&pet).unwrap();
+//! assert_eq!(pet.name, "Max");
+//!
+//! let patch: Patch = serde_json::from_str(r#"[{"op": "add", "path": "/tags/-", "value": "good"}]"#).unwrap();
+//! let pet = patch.apply_to(&pet).unwrap();
+//! assert_eq!(pet.tags, ["good"]);
+//! ```
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+
+/// The `application/merge-patch+json` media type.
+pub const APPLICATION_MERGE_PATCH_JSON: &str = "application/merge-patch+json";
+
+/// The `application/json-patch+json` media type.
+pub const APPLICATION_JSON_PATCH_JSON: &str = "application/json-patch+json";
+
+/// Error applying a patch.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PatchError {
+    /// A path wasn't a valid JSON pointer.
+    InvalidPointer(String),
+    /// A path didn't refer to a value in the document, or to a place where
+    /// a value can be added.
+    PathNotFound(String),
+    /// A `test` operation failed.
+    TestFailed(String),
+    /// A value can't be moved into itself.
+    MoveIntoSelf(String),
+    /// The patched document wasn't valid for the model.
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::InvalidPointer(path) => write!(f, "Invalid JSON pointer {:?}", path),
+            PatchError::PathNotFound(path) => write!(f, "Path {:?} not found", path),
+            PatchError::TestFailed(path) => write!(f, "Test of {:?} failed", path),
+            PatchError::MoveIntoSelf(path) => {
+                write!(f, "Can't move {:?} into one of its children", path)
+            }
+            PatchError::Json(e) => write!(f, "Patched document is invalid: {}", e),
+        }
+    }
+}
+
+impl Error for PatchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PatchError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Apply `patch` to a model by converting it to and from a
+/// `serde_json::Value`.
+fn apply_to<T, F>(value: &T, patch: F) -> Result<T, PatchError>
+where
+    T: Serialize + DeserializeOwned,
+    F: FnOnce(&mut Value) -> Result<(), PatchError>,
+{
+    let mut document = serde_json::to_value(value).map_err(PatchError::Json)?;
+    patch(&mut document)?;
+    serde_json::from_value(document).map_err(PatchError::Json)
+}
+
+/// A JSON Merge Patch document - RFC 7386.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct MergePatch(pub Value);
+
+impl MergePatch {
+    /// Apply the patch to a document.
+    pub fn apply(&self, target: &mut Value) {
+        merge(target, &self.0);
+    }
+
+    /// Apply the patch to a model.
+    pub fn apply_to<T: Serialize + DeserializeOwned>(&self, value: &T) -> Result<T, PatchError> {
+        apply_to(value, |document| {
+            self.apply(document);
+            Ok(())
+        })
+    }
+}
+
+fn merge(target: &mut Value, patch: &Value) {
+    let patch = match patch {
+        Value::Object(patch) => patch,
+        patch => {
+            *target = patch.clone();
+            return;
+        }
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    let target = target.as_object_mut().expect("target is an object");
+    for (name, value) in patch {
+        if value.is_null() {
+            target.remove(name);
+        } else {
+            merge(target.entry(name.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// An operation of a JSON Patch document.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    /// Add a value to an object, or insert it into an array.
+    Add {
+        /// Where to add the value.
+        path: String,
+        /// The value to add.
+        value: Value,
+    },
+    /// Remove a value.
+    Remove {
+        /// The value to remove.
+        path: String,
+    },
+    /// Replace a value.
+    Replace {
+        /// The value to replace.
+        path: String,
+        /// The new value.
+        value: Value,
+    },
+    /// Move a value.
+    Move {
+        /// The value to move.
+        from: String,
+        /// Where to move it to.
+        path: String,
+    },
+    /// Copy a value.
+    Copy {
+        /// The value to copy.
+        from: String,
+        /// Where to copy it to.
+        path: String,
+    },
+    /// Check that a value is equal to the given one.
+    Test {
+        /// The value to check.
+        path: String,
+        /// The expected value.
+        value: Value,
+    },
+}
+
+/// A JSON Patch document - RFC 6902.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Patch(pub Vec<PatchOperation>);
+
+impl Patch {
+    /// Apply the patch to a document. If any operation fails, the document
+    /// is left unchanged.
+    pub fn apply(&self, target: &mut Value) -> Result<(), PatchError> {
+        let mut document = target.clone();
+        for operation in &self.0 {
+            apply_operation(&mut document, operation)?;
+        }
+        *target = document;
+        Ok(())
+    }
+
+    /// Apply the patch to a model.
+    pub fn apply_to<T: Serialize + DeserializeOwned>(&self, value: &T) -> Result<T, PatchError> {
+        apply_to(value, |document| self.apply(document))
+    }
+}
+
+fn apply_operation(document: &mut Value, operation: &PatchOperation) -> Result<(), PatchError> {
+    match operation {
+        PatchOperation::Add { path, value } => add(document, path, value.clone()),
+        PatchOperation::Remove { path } => remove(document, path).map(drop),
+        PatchOperation::Replace { path, value } => {
+            parse_pointer(path)?;
+            let target = document
+                .pointer_mut(path)
+                .ok_or_else(|| PatchError::PathNotFound(path.clone()))?;
+            *target = value.clone();
+            Ok(())
+        }
+        PatchOperation::Move { from, path } => {
+            if path.starts_with(&format!("{}/", from)) {
+                return Err(PatchError::MoveIntoSelf(from.clone()));
+            }
+            let value = remove(document, from)?;
+            add(document, path, value)
+        }
+        PatchOperation::Copy { from, path } => {
+            parse_pointer(from)?;
+            let value = document
+                .pointer(from)
+                .ok_or_else(|| PatchError::PathNotFound(from.clone()))?
+                .clone();
+            add(document, path, value)
+        }
+        PatchOperation::Test { path, value } => {
+            parse_pointer(path)?;
+            match document.pointer(path) {
+                Some(actual) if actual == value => Ok(()),
+                _ => Err(PatchError::TestFailed(path.clone())),
+            }
+        }
+    }
+}
+
+/// Split a JSON pointer into its unescaped reference tokens.
+fn parse_pointer(path: &str) -> Result<Vec<String>, PatchError> {
+    if path.is_empty() {
+        return Ok(Vec::new());
+    }
+    let tokens = path
+        .strip_prefix('/')
+        .ok_or_else(|| PatchError::InvalidPointer(path.to_string()))?;
+    Ok(tokens
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+/// Find the parent of the value a pointer refers to, and the last token.
+fn parent<'a>(document: &'a mut Value, path: &str) -> Result<(&'a mut Value, String), PatchError> {
+    let mut tokens = parse_pointer(path)?;
+    let last = tokens
+        .pop()
+        .ok_or_else(|| PatchError::PathNotFound(path.to_string()))?;
+    let mut parent = document;
+    for token in tokens {
+        parent = match parent {
+            Value::Object(map) => map.get_mut(&token),
+            Value::Array(array) => array_index(&token).and_then(|index| array.get_mut(index)),
+            _ => None,
+        }
+        .ok_or_else(|| PatchError::PathNotFound(path.to_string()))?;
+    }
+    Ok((parent, last))
+}
+
+/// Parse an array index - digits without leading zeros.
+fn array_index(token: &str) -> Option<usize> {
+    let valid =
+        token == "0" || (!token.starts_with('0') && token.bytes().all(|b| b.is_ascii_digit()));
+    valid.then(|| token.parse().ok()).flatten()
+}
+
+fn add(document: &mut Value, path: &str, value: Value) -> Result<(), PatchError> {
+    if path.is_empty() {
+        *document = value;
+        return Ok(());
+    }
+    let not_found = || PatchError::PathNotFound(path.to_string());
+    match parent(document, path)? {
+        (Value::Object(map), name) => {
+            map.insert(name, value);
+        }
+        (Value::Array(array), index) if index == "-" => array.push(value),
+        (Value::Array(array), index) => {
+            let index = array_index(&index)
+                .filter(|index| *index <= array.len())
+                .ok_or_else(not_found)?;
+            array.insert(index, value);
+        }
+        _ => return Err(not_found()),
+    }
+    Ok(())
+}
+
+fn remove(document: &mut Value, path: &str) -> Result<Value, PatchError> {
+    let not_found = || PatchError::PathNotFound(path.to_string());
+    match parent(document, path)? {
+        (Value::Object(map), name) => map.remove(&name).ok_or_else(not_found),
+        (Value::Array(array), index) => array_index(&index)
+            .filter(|index| *index < array.len())
+            .map(|index| array.remove(index))
+            .ok_or_else(not_found),
+        _ => Err(not_found()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn merges_patches() {
+        // Example from RFC 7386 section 3
+        let mut document = json!({
+            "title": "Goodbye!",
+            "author": {"givenName": "John", "familyName": "Doe"},
+            "tags": ["example", "sample"],
+            "content": "This will be unchanged"
+        });
+        MergePatch(json!({
+            "title": "Hello!",
+            "phoneNumber": "+01-123-456-7890",
+            "author": {"familyName": null},
+            "tags": ["example"]
+        }))
+        .apply(&mut document);
+        assert_eq!(
+            document,
+            json!({
+                "title": "Hello!",
+                "author": {"givenName": "John"},
+                "tags": ["example"],
+                "content": "This will be unchanged",
+                "phoneNumber": "+01-123-456-7890"
+            })
+        );
+    }
+
+    #[test]
+    fn applies_patches() {
+        let mut document = json!({"foo": ["bar", "baz"], "a/b": {"c": 1}});
+        let patch: Patch = serde_json::from_value(json!([
+            {"op": "test", "path": "/a~1b/c", "value": 1},
+            {"op": "add", "path": "/foo/1", "value": "qux"},
+            {"op": "remove", "path": "/foo/0"},
+            {"op": "move", "from": "/a~1b/c", "path": "/foo/-"},
+            {"op": "copy", "from": "/foo", "path": "/copy"},
+            {"op": "replace", "path": "/a~1b", "value": null}
+        ]))
+        .unwrap();
+        patch.apply(&mut document).unwrap();
+        assert_eq!(
+            document,
+            json!({"foo": ["qux", "baz", 1], "a/b": null, "copy": ["qux", "baz", 1]})
+        );
+
+        // Failed patches leave the document unchanged
+        let patch: Patch = serde_json::from_value(json!([
+            {"op": "remove", "path": "/foo"},
+            {"op": "test", "path": "/copy/0", "value": "bar"}
+        ]))
+        .unwrap();
+        let error = patch.apply(&mut document).unwrap_err();
+        assert!(matches!(error, PatchError::TestFailed(_)));
+        assert!(document.get("foo").is_some());
+
+        let patch = Patch(vec![PatchOperation::Add {
+            path: "/foo/01".to_string(),
+            value: json!(0),
+        }]);
+        assert!(matches!(
+            patch.apply(&mut document),
+            Err(PatchError::PathNotFound(_))
+        ));
+    }
+}
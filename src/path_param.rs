@@ -0,0 +1,270 @@
+//! Path parameters, with the `simple`, `label` and `matrix` styles.
+//!
+//! | Style    | `explode` | Primitive | Array        | Object            |
+//! |----------|-----------|-----------|--------------|-------------------|
+//! | `simple` | false     | `5`       | `3,4`        | `R,100,G,200`     |
+//! | `simple` | true      | `5`       | `3,4`        | `R=100,G=200`     |
+//! | `label`  | false     | `.5`      | `.3,4`       | `.R,100,G,200`    |
+//! | `label`  | true      | `.5`      | `.3.4`       | `.R=100.G=200`    |
+//! | `matrix` | false     | `;id=5`   | `;id=3,4`    | `;id=R,100,G,200` |
+//! | `matrix` | true      | `;id=5`   | `;id=3;id=4` | `;R=100;G=200`    |
+//!
+//! Values are percent-encoded as in RFC 6570, and decoded when parsed.
+//!
+//! ```rust
+//! # use swagger::path_param::{self, Style};
+//! let segment = path_param::to_string(&vec![3, 4], "id", Style::Matrix, true).unwrap();
+//! assert_eq!(segment, ";id=3;id=4");
+//!
+//! let ids: Vec<u32> = path_param::from_str(&segment, "id", Style::Matrix, true).unwrap();
+//! assert_eq!(ids, [3, 4]);
+//!
+//! assert_eq!(path_param::decode_segment("caf%C3%A9%2Bt"), "caf\u{e9}+t");
+//! ```
+
+use crate::query::encode;
+use crate::urlencoded::{self, percent_decode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+
+/// Percent-decode a path segment. Unlike query strings, `+` isn't a space.
+pub fn decode_segment(segment: &str) -> Cow<'_, str> {
+    percent_decode(segment, false)
+}
+
+/// OpenAPI serialization style of a path parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Style {
+    /// `style: simple` - comma separated values.
+    #[default]
+    Simple,
+    /// `style: label` - values prefixed by `.`.
+    Label,
+    /// `style: matrix` - values prefixed by `;name=`.
+    Matrix,
+}
+
+/// Error returned when a path parameter can't be serialized or deserialized.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidPathParam(String);
+
+impl fmt::Display for InvalidPathParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid path parameter: {}", self.0)
+    }
+}
+
+impl Error for InvalidPathParam {}
+
+/// The text of a value. Arrays and objects nested inside other values are
+/// encoded as JSON.
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Serialize a path parameter called `name`.
+pub fn to_string<T: Serialize>(
+    value: &T,
+    name: &str,
+    style: Style,
+    explode: bool,
+) -> Result<String, InvalidPathParam> {
+    let value = serde_json::to_value(value).map_err(|e| InvalidPathParam(e.to_string()))?;
+    let name = encode(name);
+    let separator = match (style, explode) {
+        (Style::Simple, _) | (_, false) => ",",
+        (Style::Label, true) => ".",
+        (Style::Matrix, true) => ";",
+    };
+
+    let items: Vec<String> = match &value {
+        Value::Array(values) => values
+            .iter()
+            .map(|value| match (style, explode) {
+                (Style::Matrix, true) => format!("{}={}", name, encode(&text(value))),
+                _ => encode(&text(value)),
+            })
+            .collect(),
+        Value::Object(fields) if explode => fields
+            .iter()
+            .map(|(key, value)| format!("{}={}", encode(key), encode(&text(value))))
+            .collect(),
+        Value::Object(fields) => fields
+            .iter()
+            .flat_map(|(key, value)| [encode(key), encode(&text(value))])
+            .collect(),
+        value => vec![encode(&text(value))],
+    };
+    let joined = items.join(separator);
+
+    Ok(match style {
+        Style::Simple => joined,
+        Style::Label => format!(".{}", joined),
+        Style::Matrix => match &value {
+            Value::Array(_) | Value::Object(_) if explode => format!(";{}", joined),
+            _ if joined.is_empty() => format!(";{}", name),
+            _ => format!(";{}={}", name, joined),
+        },
+    })
+}
+
+/// Deserialize a path parameter called `name` from a path segment, or the
+/// part of a segment holding the parameter.
+pub fn from_str<T: DeserializeOwned>(
+    segment: &str,
+    name: &str,
+    style: Style,
+    explode: bool,
+) -> Result<T, InvalidPathParam> {
+    let missing =
+        |prefix| InvalidPathParam(format!("{:?} doesn't start with {:?}", segment, prefix));
+    let decode = |value: &str| decode_segment(value).into_owned();
+
+    let (whole, parts, fields): (&str, Vec<&str>, Vec<(&str, &str)>) = match style {
+        Style::Simple => (segment, split(segment, ','), pairs(segment, ',', explode)),
+        Style::Label => {
+            let rest = segment.strip_prefix('.').ok_or_else(|| missing("."))?;
+            let separator = if explode { '.' } else { ',' };
+            (
+                rest,
+                split(rest, separator),
+                pairs(rest, separator, explode),
+            )
+        }
+        Style::Matrix => {
+            let rest = segment.strip_prefix(';').ok_or_else(|| missing(";"))?;
+            let fields = pairs(rest, ';', true);
+            let values: Vec<&str> = fields
+                .iter()
+                .filter(|(key, _)| decode(key) == name)
+                .map(|(_, value)| *value)
+                .collect();
+            let whole = match values.first() {
+                Some(value) => value,
+                None if decode(rest) == name => "",
+                None if explode => rest,
+                None => return Err(missing(&format!(";{}=", name))),
+            };
+            if explode {
+                (whole, values, fields)
+            } else {
+                (whole, split(whole, ','), Vec::new())
+            }
+        }
+    };
+
+    let parts = parts.into_iter().map(decode).collect();
+    let fields = fields
+        .into_iter()
+        .map(|(key, value)| (decode(key), decode(value)))
+        .collect();
+    urlencoded::from_delimited(decode(whole), parts, fields).map_err(|e| InvalidPathParam(e.0))
+}
+
+/// Split a list, which may be empty.
+fn split(list: &str, separator: char) -> Vec<&str> {
+    if list.is_empty() {
+        Vec::new()
+    } else {
+        list.split(separator).collect()
+    }
+}
+
+/// The `key=value` pairs of an exploded object.
+fn pairs(list: &str, separator: char, explode: bool) -> Vec<(&str, &str)> {
+    if !explode {
+        return Vec::new();
+    }
+    split(list, separator)
+        .into_iter()
+        .filter_map(|pair| pair.split_once('='))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Color {
+        #[serde(rename = "R")]
+        r: u8,
+        #[serde(rename = "G")]
+        g: u8,
+    }
+
+    #[test]
+    fn encodes_styles() {
+        let color = Color { r: 100, g: 200 };
+        let ids = vec!["3".to_string(), "a.b,c".to_string()];
+        let cases = [
+            (Style::Simple, false, "5", "3,a.b%2Cc", "G,200,R,100"),
+            (Style::Simple, true, "5", "3,a.b%2Cc", "G=200,R=100"),
+            (Style::Label, false, ".5", ".3,a.b%2Cc", ".G,200,R,100"),
+            (Style::Label, true, ".5", ".3.a.b%2Cc", ".G=200.R=100"),
+            (
+                Style::Matrix,
+                false,
+                ";id=5",
+                ";id=3,a.b%2Cc",
+                ";id=G,200,R,100",
+            ),
+            (
+                Style::Matrix,
+                true,
+                ";id=5",
+                ";id=3;id=a.b%2Cc",
+                ";G=200;R=100",
+            ),
+        ];
+        for (style, explode, primitive, array, object) in cases {
+            let case = format!("{:?} {}", style, explode);
+            assert_eq!(
+                to_string(&5, "id", style, explode).unwrap(),
+                primitive,
+                "{}",
+                case
+            );
+            assert_eq!(
+                to_string(&ids, "id", style, explode).unwrap(),
+                array,
+                "{}",
+                case
+            );
+            assert_eq!(
+                to_string(&color, "id", style, explode).unwrap(),
+                object,
+                "{}",
+                case
+            );
+
+            let parsed: u32 = from_str(primitive, "id", style, explode).unwrap();
+            assert_eq!(parsed, 5, "{}", case);
+            let parsed: Color = from_str(object, "id", style, explode).unwrap();
+            assert_eq!(parsed, color, "{}", case);
+        }
+
+        // Unlike the others, exploded labels can't contain `.`
+        let parsed: Vec<String> = from_str(";id=3;id=a.b%2Cc", "id", Style::Matrix, true).unwrap();
+        assert_eq!(parsed, ids);
+        let parsed: Vec<String> = from_str(".3,a.b%2Cc", "id", Style::Label, false).unwrap();
+        assert_eq!(parsed, ids);
+
+        assert_eq!(to_string(&"", "id", Style::Matrix, false).unwrap(), ";id");
+        assert_eq!(
+            from_str::<String>(";id", "id", Style::Matrix, false).unwrap(),
+            ""
+        );
+        assert!(from_str::<u32>("5", "id", Style::Label, false).is_err());
+        assert!(from_str::<u32>(";other=5", "id", Style::Matrix, false).is_err());
+    }
+}
@@ -0,0 +1,387 @@
+//! Problem details for HTTP APIs, as defined in RFC 7807, and a hyper service
+//! reporting errors as `application/problem+json` responses.
+//!
+//! `Problem` can be built by handlers to describe their own errors, and
+//! deserialized by clients from the error responses of other services.
+//!
+//! `ProblemService` converts the error responses of the crate's middleware -
+//! such as `404 Not Found` from `DefaultFallback`, `405 Method Not Allowed`
+//! from `MethodNotAllowed` and `400 Bad Request` from `ValidationService` -
+//! into problems, along with error responses without a body, such as the
+//! `401 Unauthorized` and `403 Forbidden` responses of authentication layers.
+//! If the API panics, it responds with a `500 Internal Server Error` problem
+//! instead of dropping the connection. Every problem includes the request's
+//! X-Span-ID as the `x-span-id` extension member, and problems for requests
+//! rejected by `ValidationService` list each violation in the `errors`
+//! member.
+//!
+//! ```rust
+//! # use swagger::problem::Problem;
+//! # use hyper::StatusCode;
+//! let problem = Problem::new(StatusCode::FORBIDDEN)
+//!     .with_type("https://example.com/probs/out-of-credit")
+//!     .with_detail("Your current balance is 30, but that costs 50.")
+//!     .with_extension("balance", 30);
+//! let response: hyper::Response<String> = problem.into_response(Some("span"));
+//! ```
+
+use crate::response::ErrorMessage;
+#[cfg(feature = "validation")]
+use crate::validation::ValidationErrors;
+use crate::{Has, XSpanIdString, X_SPAN_ID};
+use futures::future::{BoxFuture, FutureExt};
+use hyper::body::Body;
+use hyper::header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::service::Service;
+use hyper::{Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::panic::AssertUnwindSafe;
+
+/// The media type of problems.
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+fn about_blank() -> String {
+    "about:blank".to_string()
+}
+
+/// The details of an error, as defined in RFC 7807.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Problem {
+    /// A URI reference identifying the type of problem. `about:blank` means
+    /// the problem has no semantics beyond its status code.
+    #[serde(rename = "type", default = "about_blank")]
+    pub problem_type: String,
+    /// A short summary of the type of problem.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The status code of the response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// An explanation specific to this occurrence of the problem.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// A URI reference identifying this occurrence of the problem.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// Additional members of the problem.
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+impl Problem {
+    /// Create an `about:blank` problem with a status code, titled with the
+    /// status code's reason phrase.
+    pub fn new(status: StatusCode) -> Self {
+        Problem {
+            problem_type: about_blank(),
+            title: status.canonical_reason().map(str::to_string),
+            status: Some(status.as_u16()),
+            detail: None,
+            instance: None,
+            extensions: Map::new(),
+        }
+    }
+
+    /// Set the type of the problem.
+    pub fn with_type(mut self, problem_type: impl Into<String>) -> Self {
+        self.problem_type = problem_type.into();
+        self
+    }
+
+    /// Set the title of the problem.
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Set the detail of the problem.
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Set the instance of the problem.
+    pub fn with_instance(mut self, instance: impl Into<String>) -> Self {
+        self.instance = Some(instance.into());
+        self
+    }
+
+    /// Add an extension member to the problem.
+    pub fn with_extension(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.extensions.insert(name.into(), value.into());
+        self
+    }
+
+    /// The status code of the problem, or `500 Internal Server Error` if it
+    /// has none or it isn't valid.
+    pub fn status_code(&self) -> StatusCode {
+        self.status
+            .and_then(|status| StatusCode::from_u16(status).ok())
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    /// Build an `application/problem+json` response for the problem.
+    ///
+    /// The X-Span-ID, if known, is added as the `x-span-id` extension member
+    /// and also returned in the headers.
+    pub fn into_response<B>(mut self, x_span_id: Option<&str>) -> Response<B>
+    where
+        B: From<String>,
+    {
+        if let Some(x_span_id) = x_span_id {
+            self.extensions
+                .insert("x-span-id".to_string(), x_span_id.into());
+        }
+
+        let mut response = Response::new(B::from(self.to_string()));
+        *response.status_mut() = self.status_code();
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        if let Some(Ok(x_span_id)) = x_span_id.map(HeaderValue::from_str) {
+            response.headers_mut().insert(X_SPAN_ID, x_span_id);
+        }
+
+        response
+    }
+}
+
+impl fmt::Display for Problem {
+    /// Formats the problem as JSON.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+        f.write_str(&json)
+    }
+}
+
+impl Error for Problem {}
+
+/// Replace an error response of the crate's middleware, or one without a
+/// body, with a problem.
+fn convert<B>(response: Response<B>, x_span_id: &str) -> Response<B>
+where
+    B: Body + From<String>,
+{
+    let status = response.status();
+    let problem = match response.extensions().get::<ErrorMessage>() {
+        Some(ErrorMessage { message, .. }) => {
+            let problem = Problem::new(status);
+            let problem = if problem.title.as_deref() == Some(message) {
+                problem
+            } else {
+                problem.with_detail(message.clone())
+            };
+            #[cfg(feature = "validation")]
+            let problem = match response.extensions().get::<ValidationErrors>() {
+                Some(errors) => problem
+                    .with_extension("errors", serde_json::to_value(errors).unwrap_or_default()),
+                None => problem,
+            };
+            problem
+        }
+        None if (status.is_client_error() || status.is_server_error())
+            && response.body().size_hint().exact() == Some(0) =>
+        {
+            Problem::new(status)
+        }
+        None => return response,
+    };
+
+    // Keep headers such as `Allow` and `Retry-After`
+    let (mut parts, _) = response.into_parts();
+    let problem = problem.into_response::<B>(Some(x_span_id));
+    parts.headers.remove(CONTENT_LENGTH);
+    parts.headers.extend(problem.headers().clone());
+    parts.extensions.remove::<ErrorMessage>();
+    #[cfg(feature = "validation")]
+    parts.extensions.remove::<ValidationErrors>();
+    Response::from_parts(parts, problem.into_body())
+}
+
+/// Middleware wrapper service which reports errors as problems.
+#[derive(Debug)]
+pub struct ProblemMakeService<T, C> {
+    inner: T,
+    marker: PhantomData<fn(C)>,
+}
+
+impl<T, C> ProblemMakeService<T, C> {
+    /// Create a new ProblemMakeService.
+    pub fn new(inner: T) -> Self {
+        ProblemMakeService {
+            inner,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<Inner, C, Target> Service<Target> for ProblemMakeService<Inner, C>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = ProblemService<Inner::Response, C>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        Box::pin(self.inner.call(target).map(|s| Ok(ProblemService::new(s?))))
+    }
+}
+
+/// Middleware wrapper service which reports errors as problems. Servers will
+/// normally want to use `ProblemMakeService`, which will create a
+/// `ProblemService` for each connection.
+#[derive(Debug)]
+pub struct ProblemService<T, C> {
+    inner: T,
+    marker: PhantomData<fn(C)>,
+}
+
+impl<T, C> ProblemService<T, C> {
+    /// Create a new ProblemService.
+    pub fn new(inner: T) -> Self {
+        ProblemService {
+            inner,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Clone, C> Clone for ProblemService<T, C> {
+    fn clone(&self) -> Self {
+        Self::new(self.inner.clone())
+    }
+}
+
+impl<T, C, ReqBody, ResBody> Service<(Request<ReqBody>, C)> for ProblemService<T, C>
+where
+    C: Has<XSpanIdString>,
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    T::Error: Send + 'static,
+    ResBody: Body + From<String> + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let x_span_id = Has::<XSpanIdString>::get(&context).0.clone();
+        let internal_error = |x_span_id: &str| {
+            Ok(Problem::new(StatusCode::INTERNAL_SERVER_ERROR).into_response(Some(x_span_id)))
+        };
+
+        let future =
+            match std::panic::catch_unwind(AssertUnwindSafe(|| self.inner.call((req, context)))) {
+                Ok(future) => future,
+                Err(_) => return Box::pin(futures::future::ready(internal_error(&x_span_id))),
+            };
+
+        Box::pin(
+            AssertUnwindSafe(future)
+                .catch_unwind()
+                .map(move |result| match result {
+                    Ok(result) => Ok(convert(result?, &x_span_id)),
+                    Err(_) => internal_error(&x_span_id),
+                }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fallback::DefaultFallback;
+    use crate::{ContextBuilder, EmptyContext, Push};
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::Bytes;
+    use hyper::header::RETRY_AFTER;
+
+    #[test]
+    fn serializes_problems() {
+        let problem = Problem::new(StatusCode::FORBIDDEN)
+            .with_type("https://example.com/probs/out-of-credit")
+            .with_detail("Your current balance is 30, but that costs 50.")
+            .with_instance("/account/12345/msgs/abc")
+            .with_extension("balance", 30);
+        let json = problem.to_string();
+        assert_eq!(
+            json,
+            r#"{"type":"https://example.com/probs/out-of-credit","title":"Forbidden","status":403,"detail":"Your current balance is 30, but that costs 50.","instance":"/account/12345/msgs/abc","balance":30}"#
+        );
+        assert_eq!(serde_json::from_str::<Problem>(&json).unwrap(), problem);
+
+        let problem: Problem = serde_json::from_str(r#"{"title":"Oops"}"#).unwrap();
+        assert_eq!(problem.problem_type, "about:blank");
+        assert_eq!(problem.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    type Context = ContextBuilder<XSpanIdString, EmptyContext>;
+
+    #[derive(Clone)]
+    struct TestApi;
+
+    impl Service<(Request<()>, Context)> for TestApi {
+        type Response = Response<Full<Bytes>>;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, context): (Request<()>, Context)) -> Self::Future {
+            match req.uri().path() {
+                "/panic" => panic!("handler panicked"),
+                "/busy" => futures::future::ok(
+                    Response::builder()
+                        .status(StatusCode::SERVICE_UNAVAILABLE)
+                        .header(RETRY_AFTER, "10")
+                        .body(Full::default())
+                        .unwrap(),
+                ),
+                "/ok" => futures::future::ok(Response::new(Full::from("ok"))),
+                _ => DefaultFallback::new().call((req, context)),
+            }
+        }
+    }
+
+    async fn send(path: &str) -> (Response<()>, Value) {
+        let service = ProblemService::new(TestApi);
+        let context = EmptyContext.push(XSpanIdString("span".to_string()));
+        let req = Request::get(path).body(()).unwrap();
+        let (parts, body) = service.call((req, context)).await.unwrap().into_parts();
+        let body = body.collect().await.unwrap().to_bytes();
+        let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        (Response::from_parts(parts, ()), body)
+    }
+
+    #[tokio::test]
+    async fn converts_errors() {
+        let (response, body) = send("/missing").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[CONTENT_TYPE], PROBLEM_JSON);
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Not Found",
+                "status": 404,
+                "x-span-id": "span"
+            })
+        );
+
+        let (response, body) = send("/busy").await;
+        assert_eq!(response.headers()[RETRY_AFTER], "10");
+        assert_eq!(body["title"], "Service Unavailable");
+
+        let (response, body) = send("/panic").await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["x-span-id"], "span");
+
+        let (response, _) = send("/ok").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(CONTENT_TYPE).is_none());
+    }
+}
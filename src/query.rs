@@ -0,0 +1,363 @@
+//! Query strings, encoded by clients and parsed by servers.
+//!
+//! `to_string` serializes a struct of query parameters, encoding each one
+//! using its OpenAPI `style` and `explode` settings, in the same way as
+//! [`urlencoded::to_string`](crate::urlencoded::to_string) does for form
+//! bodies. Names and values are percent-encoded as URI components (RFC 3986),
+//! so spaces become `%20` and reserved characters such as `&`, `=`, `+` and
+//! `#` can't change the meaning of the query.
+//!
+//! ```rust
+//! # use serde::Serialize;
+//! # use std::collections::BTreeMap;
+//! # use swagger::query;
+//! # use swagger::urlencoded::{Encodings, Style};
+//! #[derive(Serialize)]
+//! struct FindPets {
+//!     name: String,
+//!     tags: Vec<String>,
+//!     status: Vec<String>,
+//!     filter: BTreeMap<String, u32>,
+//!     limit: Option<u32>,
+//! }
+//!
+//! let encodings = Encodings::new()
+//!     .property("status", Style::PipeDelimited, false)
+//!     .property("filter", Style::DeepObject, true);
+//!
+//! let params = FindPets {
+//!     name: "Rex & co".to_string(),
+//!     tags: vec!["good".to_string(), "dog+cat".to_string()],
+//!     status: vec!["available".to_string(), "sold".to_string()],
+//!     filter: BTreeMap::from([("age".to_string(), 3)]),
+//!     limit: None,
+//! };
+//!
+//! assert_eq!(
+//!     query::with_query("/pets", &params, &encodings).unwrap(),
+//!     "/pets?filter[age]=3&name=Rex%20%26%20co&status=available|sold&tags=good&tags=dog%2Bcat"
+//! );
+//! ```
+//!
+//! Parameters are encoded in name order, and parameters which are `None` are
+//! left out. Arrays and objects nested inside other values, such as the
+//! elements of an array of objects with `style: form`, are encoded as JSON
+//! text. `deepObject` parameters are instead encoded with one field per
+//! leaf value, using indices as the keys of arrays.
+//!
+//! On the server side, `QueryParams` holds the parameters of a request's
+//! query string, and deserializes them using the same settings - so that
+//! `deepObject`, delimited and non-exploded arrays, and repeated parameters
+//! are all understood. Errors name the parameter which couldn't be
+//! deserialized, and where it is in the query string:
+//!
+//! ```rust
+//! # use serde::Deserialize;
+//! # use swagger::query::QueryParams;
+//! # use swagger::urlencoded::{Encodings, Style};
+//! #[derive(Debug, Deserialize)]
+//! struct FindPets {
+//!     status: Vec<String>,
+//!     limit: Option<u32>,
+//! }
+//!
+//! let encodings = Encodings::new().property("status", Style::PipeDelimited, false);
+//!
+//! let params = QueryParams::parse("status=available|sold&limit=10");
+//! let find: FindPets = params.deserialize(&encodings).unwrap();
+//! assert_eq!(find.status, ["available", "sold"]);
+//!
+//! let params = QueryParams::parse("status=sold&limit=ten");
+//! let error = params.deserialize::<FindPets>(&encodings).unwrap_err();
+//! assert_eq!(error.parameter(), Some("limit"));
+//! assert_eq!(error.offset(), Some(12));
+//! ```
+
+use crate::urlencoded::{self, decode, Encodings, FormError};
+use hyper::Uri;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+
+/// Percent-encode a string as a URI query component, leaving only the
+/// unreserved characters of RFC 3986 as they are.
+pub fn encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            byte => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Serialize `value`, which must serialize as a map or struct, as a query
+/// string, without the leading `?`.
+pub fn to_string<T: Serialize>(value: &T, encodings: &Encodings) -> Result<String, FormError> {
+    let pairs: Vec<_> = urlencoded::to_pairs(value, encodings, encode)?
+        .into_iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    Ok(pairs.join("&"))
+}
+
+/// Append the query string for `value` to a path, which may already have a
+/// query. Nothing is added if there are no parameters.
+pub fn with_query<T: Serialize>(
+    path: &str,
+    value: &T,
+    encodings: &Encodings,
+) -> Result<String, FormError> {
+    let query = to_string(value, encodings)?;
+    Ok(match (query.is_empty(), path.contains('?')) {
+        (true, _) => path.to_string(),
+        (false, true) => format!("{}&{}", path, query),
+        (false, false) => format!("{}?{}", path, query),
+    })
+}
+
+/// A parameter of a query string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct QueryParam {
+    name: String,
+    value: String,
+    offset: usize,
+}
+
+/// The parameters of a query string.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryParams {
+    query: String,
+    params: Vec<QueryParam>,
+}
+
+impl QueryParams {
+    /// Parse a query string, without the leading `?`. Names and values are
+    /// percent-decoded, with `+` decoded as a space.
+    pub fn parse(query: &str) -> Self {
+        let mut params = Vec::new();
+        let mut offset = 0;
+        for pair in query.split('&') {
+            if !pair.is_empty() {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                params.push(QueryParam {
+                    name: decode(name).into_owned(),
+                    value: decode(value).into_owned(),
+                    offset,
+                });
+            }
+            offset += pair.len() + 1;
+        }
+        QueryParams {
+            query: query.to_string(),
+            params,
+        }
+    }
+
+    /// Parse the query string of a URI.
+    pub fn from_uri(uri: &Uri) -> Self {
+        Self::parse(uri.query().unwrap_or_default())
+    }
+
+    /// The first value of a parameter.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|param| param.name == name)
+            .map(|param| param.value.as_str())
+    }
+
+    /// All the values of a repeated parameter.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.params
+            .iter()
+            .filter(move |param| param.name == name)
+            .map(|param| param.value.as_str())
+    }
+
+    /// Whether the query string has a parameter. For `deepObject`
+    /// parameters, this is the name without the `[key]` suffixes.
+    pub fn contains(&self, name: &str) -> bool {
+        self.names().any(|n| n == name)
+    }
+
+    /// The names of the parameters, without the `[key]` suffixes of
+    /// `deepObject` parameters. Repeated parameters are repeated.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.params.iter().map(|param| base_name(&param.name))
+    }
+
+    /// The byte offset of the first occurrence of a parameter in the query
+    /// string.
+    pub fn offset(&self, name: &str) -> Option<usize> {
+        self.params
+            .iter()
+            .find(|param| base_name(&param.name) == name)
+            .map(|param| param.offset)
+    }
+
+    /// Whether there are no parameters.
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+
+    /// Deserialize the parameters, decoding each one using its `style` and
+    /// `explode` settings.
+    pub fn deserialize<T: DeserializeOwned>(&self, encodings: &Encodings) -> Result<T, QueryError> {
+        urlencoded::from_str_with_path(&self.query, encodings).map_err(|e| {
+            let path = e.path().to_string();
+            let parameter = path
+                .split(['.', '['])
+                .next()
+                .filter(|name| !name.is_empty() && self.contains(name))
+                .map(str::to_string);
+            QueryError {
+                offset: parameter.as_deref().and_then(|name| self.offset(name)),
+                parameter,
+                path,
+                message: e.into_inner().0,
+            }
+        })
+    }
+}
+
+/// The name of a parameter without the `[key]` suffixes of a `deepObject`.
+fn base_name(name: &str) -> &str {
+    name.split('[').next().unwrap_or_default()
+}
+
+/// Error returned when query parameters can't be deserialized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError {
+    parameter: Option<String>,
+    offset: Option<usize>,
+    path: String,
+    message: String,
+}
+
+impl QueryError {
+    /// The parameter which couldn't be deserialized, if known. Missing
+    /// parameters aren't known, but are named in the message.
+    pub fn parameter(&self) -> Option<&str> {
+        self.parameter.as_deref()
+    }
+
+    /// The byte offset of the parameter in the query string.
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    /// The path of the value which couldn't be deserialized, such as
+    /// `filter.status`, or `.` for the query as a whole.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// A description of the error.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.parameter, self.offset) {
+            (Some(parameter), Some(offset)) => write!(
+                f,
+                "Invalid query parameter {} at offset {}: {}",
+                parameter, offset, self.message
+            ),
+            _ => write!(f, "Invalid query string: {}", self.message),
+        }
+    }
+}
+
+impl Error for QueryError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::urlencoded::Style;
+    use serde_json::json;
+
+    #[test]
+    fn encodes_styles() {
+        let params = json!({
+            "q": "a b/c?d=e#f",
+            "points": [{"x": 1, "y": 2}, {"x": 3, "y": 4}],
+            "where": {"point": {"x": 1}, "ids": [5, 6]},
+            "ids": [1, 2],
+            "words": ["big", "red dog"],
+            "coords": {"lat": 1.5, "long": -2},
+            "unset": null,
+        });
+        let encodings = Encodings::new()
+            .property("where", Style::DeepObject, true)
+            .property("ids", Style::Form, false)
+            .property("words", Style::SpaceDelimited, false)
+            .property("coords", Style::Form, false);
+
+        assert_eq!(
+            to_string(&params, &encodings).unwrap(),
+            "coords=lat,1.5,long,-2&ids=1,2\
+             &points=%7B%22x%22%3A1%2C%22y%22%3A2%7D&points=%7B%22x%22%3A3%2C%22y%22%3A4%7D\
+             &q=a%20b%2Fc%3Fd%3De%23f&where[ids][0]=5&where[ids][1]=6&where[point][x]=1\
+             &words=big%20red%20dog"
+        );
+        assert_eq!(
+            with_query("/a?b=c", &json!({"d": "~"}), &encodings).unwrap(),
+            "/a?b=c&d=~"
+        );
+        assert_eq!(
+            with_query("/a", &json!({"d": null}), &encodings).unwrap(),
+            "/a"
+        );
+        assert!(to_string(&json!([1]), &encodings).is_err());
+    }
+
+    #[test]
+    fn parses_query_params() {
+        #[derive(Debug, serde::Deserialize)]
+        struct Params {
+            ids: Vec<u32>,
+            tag: Vec<String>,
+            words: Vec<String>,
+            filter: std::collections::BTreeMap<String, Vec<u32>>,
+        }
+
+        let encodings = Encodings::new()
+            .property("ids", Style::Form, false)
+            .property("words", Style::SpaceDelimited, false)
+            .property("filter", Style::DeepObject, true);
+        let query = "ids=1,2&tag=a+b&tag=%26&words=x+y%20z&filter[age][0]=3&filter[age][1]=4";
+        let params = QueryParams::parse(query);
+        assert_eq!(params.get_all("tag").collect::<Vec<_>>(), ["a b", "&"]);
+        assert!(params.contains("filter"));
+        assert_eq!(params.offset("words"), Some(24));
+
+        let parsed: Params = params.deserialize(&encodings).unwrap();
+        assert_eq!(parsed.ids, [1, 2]);
+        assert_eq!(parsed.tag, ["a b", "&"]);
+        assert_eq!(parsed.words, ["x y", "z"]);
+        assert_eq!(parsed.filter["age"], [3, 4]);
+
+        let params = QueryParams::parse(&query.replace("[1]=4", "[1]=x"));
+        let error = params.deserialize::<Params>(&encodings).unwrap_err();
+        assert_eq!(error.parameter(), Some("filter"));
+        assert_eq!(error.path(), "filter.age[1]");
+        assert_eq!(error.offset(), Some(38));
+
+        let error = QueryParams::parse("ids=1")
+            .deserialize::<Params>(&encodings)
+            .unwrap_err();
+        assert_eq!(error.parameter(), None);
+        assert_eq!(
+            error.to_string(),
+            "Invalid query string: missing field `tag`"
+        );
+    }
+}
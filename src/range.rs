@@ -0,0 +1,539 @@
+//! Range requests, for resumable downloads.
+//!
+//! `range_response` turns the `200 OK` response to a `GET` request into a
+//! `206 Partial Content` response holding the byte ranges asked for by the
+//! request's `Range` header - a single range, or several as
+//! `multipart/byteranges`. Unsatisfiable ranges get
+//! `416 Range Not Satisfiable`. If the request has an `If-Range` header not
+//! matching the response's `ETag` or `Last-Modified` header, the whole file
+//! is sent, as the client's partial copy is out of date.
+//!
+//! Files are streamed, so ranges are read by skipping the bytes before them.
+//! Overlapping and adjacent ranges are merged, and sent in order.
+//!
+//! ```rust
+//! # use hyper::header::{HeaderMap, HeaderValue, CONTENT_RANGE, RANGE};
+//! # use hyper::StatusCode;
+//! # use swagger::range::{range_response, ContentRange};
+//! # use swagger::File;
+//! let mut request = HeaderMap::new();
+//! request.insert(RANGE, HeaderValue::from_static("bytes=5-"));
+//!
+//! let response = range_response(&request, File::from(b"Hello, World!".to_vec()).into_response());
+//! assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+//!
+//! let range: ContentRange = response.headers()[CONTENT_RANGE].to_str().unwrap().parse().unwrap();
+//! assert_eq!(range.range, Some((5, 12)));
+//! assert_eq!(range.complete_length, Some(13));
+//! ```
+
+use crate::File;
+use headers::{ETag, HeaderMapExt, IfRange, LastModified};
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use hyper::header::{
+    HeaderMap, HeaderValue, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE,
+};
+use hyper::{Response, StatusCode};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{ready, Context, Poll};
+
+/// Maximum number of ranges served from a single request, after merging.
+/// Requests for more get the whole file.
+const MAX_RANGES: usize = 32;
+
+/// A range of bytes, with both ends inclusive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ByteRange {
+    /// Offset of the first byte.
+    pub start: u64,
+    /// Offset of the last byte.
+    pub end: u64,
+}
+
+impl ByteRange {
+    /// The number of bytes in the range.
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// Whether the range is empty, which it never is.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+}
+
+/// Error returned when a `Range` header can't be used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RangeError {
+    /// The header isn't a valid `bytes` range, so should be ignored.
+    Invalid,
+    /// None of the ranges overlap the file.
+    Unsatisfiable,
+}
+
+impl fmt::Display for RangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RangeError::Invalid => write!(f, "Invalid range"),
+            RangeError::Unsatisfiable => write!(f, "Range not satisfiable"),
+        }
+    }
+}
+
+impl Error for RangeError {}
+
+/// Parse a `Range` header, such as `bytes=0-99,200-,-50`, returning the
+/// ranges of a file of `length` bytes it asks for, in the order given.
+/// Ranges starting beyond the end of the file are skipped, and ranges ending
+/// beyond it are shortened.
+pub fn parse_range(value: &str, length: u64) -> Result<Vec<ByteRange>, RangeError> {
+    let (unit, specs) = value.split_once('=').ok_or(RangeError::Invalid)?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return Err(RangeError::Invalid);
+    }
+
+    let number = |s: &str| {
+        (!s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()))
+            .then(|| s.parse::<u64>().ok())
+            .flatten()
+            .ok_or(RangeError::Invalid)
+    };
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (first, last) = spec.split_once('-').ok_or(RangeError::Invalid)?;
+        let range = if first.is_empty() {
+            // Suffix range: the last N bytes
+            let suffix = number(last)?;
+            (suffix > 0 && length > 0).then(|| ByteRange {
+                start: length.saturating_sub(suffix),
+                end: length - 1,
+            })
+        } else {
+            let start = number(first)?;
+            let end = if last.is_empty() {
+                u64::MAX
+            } else {
+                number(last)?
+            };
+            if end < start {
+                return Err(RangeError::Invalid);
+            }
+            (start < length).then(|| ByteRange {
+                start,
+                end: end.min(length - 1),
+            })
+        };
+        ranges.extend(range);
+    }
+
+    if ranges.is_empty() {
+        Err(RangeError::Unsatisfiable)
+    } else {
+        Ok(ranges)
+    }
+}
+
+/// A `Content-Range` header, such as `bytes 0-99/1000`, or `bytes */1000`
+/// for an unsatisfiable range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContentRange {
+    /// The first and last offsets of the range, or `None` if unsatisfiable.
+    pub range: Option<(u64, u64)>,
+    /// The length of the whole file, if known.
+    pub complete_length: Option<u64>,
+}
+
+impl ContentRange {
+    /// The `Content-Range` of a range of a file of `length` bytes.
+    pub fn new(range: ByteRange, length: u64) -> Self {
+        ContentRange {
+            range: Some((range.start, range.end)),
+            complete_length: Some(length),
+        }
+    }
+
+    /// The `Content-Range` of an unsatisfiable range of a file of `length`
+    /// bytes.
+    pub fn unsatisfied(length: u64) -> Self {
+        ContentRange {
+            range: None,
+            complete_length: Some(length),
+        }
+    }
+
+    fn to_header_value(self) -> HeaderValue {
+        HeaderValue::from_str(&self.to_string()).expect("content ranges are valid header values")
+    }
+}
+
+impl fmt::Display for ContentRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.range {
+            Some((start, end)) => write!(f, "bytes {}-{}/", start, end)?,
+            None => write!(f, "bytes */")?,
+        }
+        match self.complete_length {
+            Some(length) => write!(f, "{}", length),
+            None => write!(f, "*"),
+        }
+    }
+}
+
+impl FromStr for ContentRange {
+    type Err = RangeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (unit, rest) = s.trim().split_once(' ').ok_or(RangeError::Invalid)?;
+        if !unit.eq_ignore_ascii_case("bytes") {
+            return Err(RangeError::Invalid);
+        }
+        let (range, length) = rest.trim().split_once('/').ok_or(RangeError::Invalid)?;
+        let number = |s: &str| s.parse::<u64>().map_err(|_| RangeError::Invalid);
+
+        let complete_length = match length {
+            "*" => None,
+            length => Some(number(length)?),
+        };
+        let range = match range {
+            "*" if complete_length.is_some() => None,
+            range => {
+                let (start, end) = range.split_once('-').ok_or(RangeError::Invalid)?;
+                let (start, end) = (number(start)?, number(end)?);
+                if end < start || complete_length.is_some_and(|length| end >= length) {
+                    return Err(RangeError::Invalid);
+                }
+                Some((start, end))
+            }
+        };
+        Ok(ContentRange {
+            range,
+            complete_length,
+        })
+    }
+}
+
+/// Whether the ranges of a request should be served, given its `If-Range`
+/// header and the response's `ETag` and `Last-Modified` headers. Requests
+/// without `If-Range` always match, and weak entity tags never do.
+pub fn if_range_matches(request: &HeaderMap, response: &HeaderMap) -> bool {
+    match request.typed_get::<IfRange>() {
+        Some(if_range) => !if_range.is_modified(
+            response.typed_get::<ETag>().as_ref(),
+            response.typed_get::<LastModified>().as_ref(),
+        ),
+        None => true,
+    }
+}
+
+/// Sort and merge overlapping or adjacent ranges.
+fn coalesce(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => {
+                last.end = last.end.max(range.end)
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Serve the ranges asked for by a request from the response to it.
+///
+/// Responses other than `200 OK`, and files whose length isn't known, are
+/// returned unchanged, except that `200 OK` responses for files of known
+/// length get `Accept-Ranges: bytes`. This should only be used for
+/// responses to `GET` requests.
+pub fn range_response(request: &HeaderMap, response: Response<File>) -> Response<File> {
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+    let (mut parts, file) = response.into_parts();
+    let length = file.length().or_else(|| {
+        parts
+            .headers
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse().ok())
+    });
+    let Some(length) = length else {
+        return Response::from_parts(parts, file);
+    };
+    parts
+        .headers
+        .insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    let ranges = request
+        .get(RANGE)
+        .filter(|_| if_range_matches(request, &parts.headers))
+        .and_then(|value| value.to_str().ok())
+        .map(|value| parse_range(value, length));
+    let ranges = match ranges {
+        Some(Ok(ranges)) => coalesce(ranges),
+        Some(Err(RangeError::Unsatisfiable)) => {
+            parts.status = StatusCode::RANGE_NOT_SATISFIABLE;
+            parts.headers.insert(
+                CONTENT_RANGE,
+                ContentRange::unsatisfied(length).to_header_value(),
+            );
+            parts.headers.insert(CONTENT_LENGTH, HeaderValue::from(0));
+            return Response::from_parts(parts, File::from(Bytes::new()));
+        }
+        Some(Err(RangeError::Invalid)) | None => return Response::from_parts(parts, file),
+    };
+    if ranges.len() > MAX_RANGES {
+        return Response::from_parts(parts, file);
+    }
+
+    parts.status = StatusCode::PARTIAL_CONTENT;
+    let content_type = file.content_type().map(str::to_string).or_else(|| {
+        parts
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    });
+
+    let body = if let [range] = ranges[..] {
+        parts.headers.insert(
+            CONTENT_RANGE,
+            ContentRange::new(range, length).to_header_value(),
+        );
+        RangeBody::new(file, vec![(Bytes::new(), range)], Bytes::new())
+    } else {
+        let boundary = format!("swagger-byteranges-{}", uuid::Uuid::new_v4().simple());
+        let content_type_line = content_type
+            .map(|content_type| format!("Content-Type: {}\r\n", content_type))
+            .unwrap_or_default();
+        let ranges = ranges
+            .into_iter()
+            .enumerate()
+            .map(|(i, range)| {
+                let header = format!(
+                    "{}--{}\r\n{}Content-Range: {}\r\n\r\n",
+                    if i == 0 { "" } else { "\r\n" },
+                    boundary,
+                    content_type_line,
+                    ContentRange::new(range, length)
+                );
+                (Bytes::from(header), range)
+            })
+            .collect();
+        parts.headers.remove(CONTENT_RANGE);
+        parts.headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_str(&format!("multipart/byteranges; boundary={}", boundary))
+                .expect("boundaries are valid header values"),
+        );
+        RangeBody::new(
+            file,
+            ranges,
+            Bytes::from(format!("\r\n--{}--\r\n", boundary)),
+        )
+    };
+
+    let mut file = File::new(body);
+    if let Some(content_type) = parts.headers.get(CONTENT_TYPE) {
+        if let Ok(content_type) = content_type.to_str() {
+            file = file.with_content_type(content_type);
+        }
+    }
+    if let Some(length) = file.length() {
+        parts
+            .headers
+            .insert(CONTENT_LENGTH, HeaderValue::from(length));
+    }
+    Response::from_parts(parts, file)
+}
+
+/// Body streaming ranges of a file, each preceded by a header, and followed
+/// by a trailer. The ranges must be sorted and not overlap.
+struct RangeBody {
+    file: File,
+    /// Offset in the file of the start of `buffer`.
+    offset: u64,
+    /// Data read from the file but not yet used.
+    buffer: Bytes,
+    ranges: VecDeque<(Bytes, ByteRange)>,
+    trailer: Bytes,
+    length: u64,
+}
+
+impl RangeBody {
+    fn new(file: File, ranges: Vec<(Bytes, ByteRange)>, trailer: Bytes) -> Self {
+        let length = ranges
+            .iter()
+            .map(|(header, range)| header.len() as u64 + range.len())
+            .sum::<u64>()
+            + trailer.len() as u64;
+        RangeBody {
+            file,
+            offset: 0,
+            buffer: Bytes::new(),
+            ranges: ranges.into(),
+            trailer,
+            length,
+        }
+    }
+}
+
+impl Body for RangeBody {
+    type Data = Bytes;
+    type Error = Box<dyn Error + Send + Sync>;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = &mut *self;
+        loop {
+            let Some((header, range)) = this.ranges.front_mut() else {
+                let trailer = std::mem::take(&mut this.trailer);
+                return Poll::Ready((!trailer.is_empty()).then(|| Ok(Frame::data(trailer))));
+            };
+            if !header.is_empty() {
+                let header = std::mem::take(header);
+                this.length -= header.len() as u64;
+                return Poll::Ready(Some(Ok(Frame::data(header))));
+            }
+            let range = *range;
+
+            if this.buffer.is_empty() {
+                match ready!(Pin::new(&mut this.file).poll_frame(cx)) {
+                    Some(Ok(frame)) => {
+                        if let Ok(data) = frame.into_data() {
+                            this.buffer = data;
+                        }
+                        continue;
+                    }
+                    Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                    None => return Poll::Ready(Some(Err("file ended before range".into()))),
+                }
+            }
+
+            let len = this.buffer.len() as u64;
+            let from = range.start.saturating_sub(this.offset).min(len);
+            let to = (range.end + 1 - this.offset).min(len);
+            let data = this.buffer.slice(from as usize..to as usize);
+            this.buffer = this.buffer.slice(to as usize..);
+            this.offset += to;
+            if this.offset > range.end {
+                this.ranges.pop_front();
+            }
+            if !data.is_empty() {
+                this.length -= data.len() as u64;
+                return Poll::Ready(Some(Ok(Frame::data(data))));
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.ranges.is_empty() && self.trailer.is_empty()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use hyper::header::{ETAG, IF_RANGE};
+    use std::convert::Infallible;
+
+    #[test]
+    fn parses_ranges() {
+        let range = |start, end| ByteRange { start, end };
+        assert_eq!(
+            parse_range("bytes=0-4, 8-, -3", 10),
+            Ok(vec![range(0, 4), range(8, 9), range(7, 9)])
+        );
+        assert_eq!(parse_range("bytes=5-100,20-", 10), Ok(vec![range(5, 9)]));
+        assert_eq!(parse_range("bytes=-20", 10), Ok(vec![range(0, 9)]));
+        assert_eq!(parse_range("bytes=10-", 10), Err(RangeError::Unsatisfiable));
+        assert_eq!(parse_range("bytes=-0", 10), Err(RangeError::Unsatisfiable));
+        assert_eq!(parse_range("bytes=4-2", 10), Err(RangeError::Invalid));
+        assert_eq!(parse_range("items=0-1", 10), Err(RangeError::Invalid));
+        assert_eq!(
+            coalesce(vec![range(8, 9), range(0, 2), range(3, 4), range(9, 9)]),
+            [range(0, 4), range(8, 9)]
+        );
+
+        for value in ["bytes 0-99/1000", "bytes */1000", "bytes 0-99/*"] {
+            let content_range: ContentRange = value.parse().unwrap();
+            assert_eq!(content_range.to_string(), value);
+        }
+        assert!("bytes 0-1000/1000".parse::<ContentRange>().is_err());
+        assert!("bytes */*".parse::<ContentRange>().is_err());
+    }
+
+    #[tokio::test]
+    async fn serves_ranges() {
+        let file = || {
+            let chunks = ["0123", "4567", "89"].map(|s| Ok::<_, Infallible>(Bytes::from(s)));
+            let mut response = File::from_stream(stream::iter(chunks))
+                .with_length(10)
+                .with_content_type("text/plain")
+                .into_response();
+            response
+                .headers_mut()
+                .insert(ETAG, HeaderValue::from_static("\"v1\""));
+            response
+        };
+        let request = |range: &'static str, if_range: Option<&'static str>| {
+            let mut headers = HeaderMap::new();
+            headers.insert(RANGE, HeaderValue::from_static(range));
+            if let Some(if_range) = if_range {
+                headers.insert(IF_RANGE, HeaderValue::from_static(if_range));
+            }
+            headers
+        };
+
+        let response = range_response(&request("bytes=3-5", Some("\"v1\"")), file());
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[CONTENT_RANGE], "bytes 3-5/10");
+        assert_eq!(response.headers()[CONTENT_LENGTH], "3");
+        assert_eq!(response.headers()[ACCEPT_RANGES], "bytes");
+        assert_eq!(response.into_body().bytes().await.unwrap(), "345");
+
+        let response = range_response(&request("bytes=0-1,-2", None), file());
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        let content_type = response.headers()[CONTENT_TYPE].to_str().unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap()
+            .to_string();
+        let length: usize = response.headers()[CONTENT_LENGTH]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let body = response.into_body().bytes().await.unwrap();
+        assert_eq!(body.len(), length);
+        assert_eq!(
+            body,
+            format!(
+                "--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\r\n\
+                 --{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n\
+                 --{b}--\r\n",
+                b = boundary
+            )
+        );
+
+        let response = range_response(&request("bytes=3-5", Some("\"v2\"")), file());
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.into_body().bytes().await.unwrap(), "0123456789");
+
+        let response = range_response(&request("bytes=20-", None), file());
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[CONTENT_RANGE], "bytes */10");
+    }
+}
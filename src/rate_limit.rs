@@ -0,0 +1,228 @@
+//! Client middleware limiting the rate of outgoing requests.
+//!
+//! `RateLimitService` delays requests so that they don't exceed a
+//! `RateLimiter`'s limits, rather than failing them, so that generated
+//! clients stay within upstream API quotas. Limits can apply to all requests,
+//! and to each host separately.
+//!
+//! Limits are token buckets: a limit of `requests` per `period` with a
+//! `burst` allows up to `burst` requests at once, with the bucket refilling
+//! at a steady rate of `requests` per `period`.
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! # use swagger::rate_limit::{RateLimit, RateLimiter};
+//! let limiter = RateLimiter::new()
+//!     .global(RateLimit::new(100, Duration::from_secs(1)))
+//!     .per_host(RateLimit::new(10, Duration::from_secs(1)).burst(20))
+//!     .host("slow.example.com", RateLimit::new(1, Duration::from_secs(1)));
+//! ```
+
+use futures::future::{BoxFuture, TryFutureExt};
+use hyper::service::Service;
+use hyper::Request;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// A rate limit of `requests` per `period`, allowing bursts of up to `burst`
+/// requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    interval: Duration,
+    burst: u32,
+}
+
+impl RateLimit {
+    /// Create a new rate limit of `requests` per `period`, with a burst of
+    /// `requests`.
+    pub fn new(requests: u32, period: Duration) -> Self {
+        let requests = requests.max(1);
+        RateLimit {
+            interval: period / requests,
+            burst: requests,
+        }
+    }
+
+    /// Set the number of requests which may be made at once.
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+}
+
+/// Token bucket, tracked as the time at which it will next be full.
+#[derive(Debug)]
+struct Bucket {
+    limit: RateLimit,
+    full_at: Mutex<Instant>,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit) -> Self {
+        Bucket {
+            limit,
+            full_at: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Take a token, returning when the request may be made.
+    fn reserve(&self) -> Instant {
+        let now = Instant::now();
+        let mut full_at = self.full_at.lock().unwrap();
+        let start = (*full_at).max(now);
+        *full_at = start + self.limit.interval;
+
+        // The bucket may be drawn down by `burst` tokens before waiting
+        let tolerance = self.limit.interval * self.limit.burst;
+        (*full_at).checked_sub(tolerance).unwrap_or(now).max(now)
+    }
+}
+
+/// Rate limits for outgoing requests. Clones share the same limits.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    global: Option<Arc<Bucket>>,
+    per_host: Option<RateLimit>,
+    host_limits: Arc<HashMap<String, RateLimit>>,
+    hosts: Arc<Mutex<HashMap<String, Arc<Bucket>>>>,
+}
+
+impl RateLimiter {
+    /// Create a new rate limiter, without any limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the rate of all requests.
+    pub fn global(mut self, limit: RateLimit) -> Self {
+        self.global = Some(Arc::new(Bucket::new(limit)));
+        self
+    }
+
+    /// Limit the rate of requests to each host.
+    pub fn per_host(mut self, limit: RateLimit) -> Self {
+        self.per_host = Some(limit);
+        self
+    }
+
+    /// Limit the rate of requests to a particular host, overriding the
+    /// per-host limit.
+    pub fn host(mut self, host: &str, limit: RateLimit) -> Self {
+        Arc::make_mut(&mut self.host_limits).insert(host.to_ascii_lowercase(), limit);
+        self
+    }
+
+    /// Reserve capacity for a request to `host`, returning when it may be
+    /// made.
+    fn reserve(&self, host: Option<&str>) -> Option<Instant> {
+        let global = self.global.as_ref().map(|bucket| bucket.reserve());
+
+        let host = host.map(str::to_ascii_lowercase).and_then(|host| {
+            let limit = self.host_limits.get(&host).copied().or(self.per_host)?;
+            let bucket = self
+                .hosts
+                .lock()
+                .unwrap()
+                .entry(host)
+                .or_insert_with(|| Arc::new(Bucket::new(limit)))
+                .clone();
+            Some(bucket.reserve())
+        });
+
+        global.max(host)
+    }
+}
+
+/// Client middleware delaying requests to stay within a `RateLimiter`'s
+/// limits.
+///
+/// Put it inside `RetryService`, `HedgeService` and `RedirectService`, so
+/// that the requests they send count towards the limits too, and inside
+/// `RewriteService`, so that per-host limits apply to the hosts requests are
+/// actually sent to.
+#[derive(Debug, Clone)]
+pub struct RateLimitService<T> {
+    inner: T,
+    limiter: RateLimiter,
+}
+
+impl<T> RateLimitService<T> {
+    /// Create a new RateLimitService, limiting requests using `limiter`.
+    pub fn new(inner: T, limiter: RateLimiter) -> Self {
+        RateLimitService { inner, limiter }
+    }
+}
+
+impl<T, B, C> Service<(Request<B>, C)> for RateLimitService<T>
+where
+    T: Service<(Request<B>, C)> + Clone + Send + 'static,
+    T::Future: Send + 'static,
+    T::Error: Into<Box<dyn Error + Send + Sync>>,
+    B: Send + 'static,
+    C: Send + 'static,
+{
+    type Response = T::Response;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<B>, C)) -> Self::Future {
+        let ready_at = self.limiter.reserve(req.uri().host());
+        let ready_at = match ready_at {
+            Some(ready_at) if ready_at > Instant::now() => ready_at,
+            _ => return Box::pin(self.inner.call((req, context)).map_err(Into::into)),
+        };
+
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            tokio::time::sleep_until(ready_at).await;
+            inner.call((req, context)).await.map_err(Into::into)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use std::convert::Infallible;
+
+    #[derive(Clone)]
+    struct Succeed;
+
+    impl Service<(Request<()>, ())> for Succeed {
+        type Response = ();
+        type Error = Infallible;
+        type Future = future::Ready<Result<(), Infallible>>;
+
+        fn call(&self, _: (Request<()>, ())) -> Self::Future {
+            future::ok(())
+        }
+    }
+
+    fn request(uri: &str) -> (Request<()>, ()) {
+        let mut request = Request::new(());
+        *request.uri_mut() = uri.parse().unwrap();
+        (request, ())
+    }
+
+    #[tokio::test]
+    async fn delays_requests_per_host() {
+        let limit = RateLimit::new(1, Duration::from_millis(50));
+        let service = RateLimitService::new(Succeed, RateLimiter::new().per_host(limit));
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            service.call(request("http://a.example/")).await.unwrap();
+        }
+        // The first request is made immediately, then one per 50ms
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        // Other hosts have their own limit
+        let start = Instant::now();
+        service.call(request("http://b.example/")).await.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,413 @@
+//! Client middleware following redirects.
+//!
+//! hyper doesn't follow redirects, so `RedirectService` does, according to a
+//! `RedirectPolicy`:
+//!
+//! - `307` and `308` redirects are followed with the same method and body.
+//! - `303` redirects are followed with a `GET` request without a body,
+//!   unless the original request was a `HEAD` request.
+//! - `301` and `302` redirects of `POST` requests are followed with a `GET`
+//!   request without a body, as browsers do, unless the policy preserves the
+//!   method. Other methods are preserved.
+//!
+//! Request bodies are buffered so that they can be resent, failing with
+//! `CollectError::TooLarge` if larger than the policy's `max_body_size`.
+//!
+//! Credentials - the `Authorization`, `Proxy-Authorization` and `Cookie`
+//! headers - are removed when redirected to a different origin.
+//!
+//! ```rust
+//! # use swagger::redirect::RedirectPolicy;
+//! let policy = RedirectPolicy::new()
+//!     .max_redirects(5)
+//!     .same_origin_only(true);
+//! ```
+
+use crate::body_ext::{collect_limited, CollectError};
+use crate::retry::rebuild;
+use futures::future::BoxFuture;
+use hyper::body::{Body, Bytes};
+use hyper::header::{
+    HeaderName, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, HOST,
+    LOCATION, PROXY_AUTHORIZATION,
+};
+use hyper::service::Service;
+use hyper::{Method, Request, Response, StatusCode, Uri};
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+/// Error returned when a request is redirected too many times.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TooManyRedirects {
+    /// The maximum number of redirects which may be followed.
+    pub max_redirects: usize,
+}
+
+impl fmt::Display for TooManyRedirects {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "More than {} redirects", self.max_redirects)
+    }
+}
+
+impl Error for TooManyRedirects {}
+
+/// Policy deciding which redirects are followed, and how.
+#[derive(Debug, Clone)]
+pub struct RedirectPolicy {
+    max_redirects: usize,
+    max_body_size: u64,
+    same_origin_only: bool,
+    preserve_post: bool,
+    sensitive_headers: Vec<HeaderName>,
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RedirectPolicy {
+    /// Create a new redirect policy, following up to 10 redirects to any
+    /// origin, with request bodies of up to 10MiB.
+    pub fn new() -> Self {
+        RedirectPolicy {
+            max_redirects: 10,
+            max_body_size: 10 * 1024 * 1024,
+            same_origin_only: false,
+            preserve_post: false,
+            sensitive_headers: vec![AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE],
+        }
+    }
+
+    /// Set the maximum number of redirects to follow. Requests redirected
+    /// more times than this fail with `TooManyRedirects`.
+    pub fn max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    /// Set the maximum size of request body, in bytes, which will be buffered
+    /// for resending.
+    pub fn max_body_size(mut self, max_body_size: u64) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Only follow redirects to the same origin - scheme, host and port - as
+    /// the original request. Other redirect responses are returned to the
+    /// caller.
+    pub fn same_origin_only(mut self, same_origin_only: bool) -> Self {
+        self.same_origin_only = same_origin_only;
+        self
+    }
+
+    /// Follow `301` and `302` redirects of `POST` requests with a `POST`
+    /// request, rather than changing to `GET`.
+    pub fn preserve_post(mut self, preserve_post: bool) -> Self {
+        self.preserve_post = preserve_post;
+        self
+    }
+
+    /// Also remove the given header when redirected to a different origin.
+    pub fn sensitive_header(mut self, name: HeaderName) -> Self {
+        self.sensitive_headers.push(name);
+        self
+    }
+
+    /// The method to use when following a redirect, or `None` if the status
+    /// isn't a redirect which can be followed.
+    fn redirect_method(&self, status: StatusCode, method: &Method) -> Option<Method> {
+        match status {
+            StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => Some(method.clone()),
+            StatusCode::SEE_OTHER if method == Method::HEAD => Some(Method::HEAD),
+            StatusCode::SEE_OTHER => Some(Method::GET),
+            StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND
+                if method == Method::POST && !self.preserve_post =>
+            {
+                Some(Method::GET)
+            }
+            StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => Some(method.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Resolve a `Location` header against the URI of the request - RFC 3986
+/// section 5.2.
+fn resolve(base: &Uri, location: &str) -> Option<Uri> {
+    // Fragments aren't sent to the server
+    let location = location.split('#').next().unwrap_or_default();
+
+    if location.contains("://") {
+        return location.parse().ok();
+    }
+    if location.starts_with("//") {
+        return format!("{}:{}", base.scheme_str()?, location).parse().ok();
+    }
+
+    let path_and_query = if location.starts_with('/') {
+        location.to_string()
+    } else {
+        // Relative path - replace the last segment of the base path
+        let path = base.path();
+        let directory = &path[..=path.rfind('/').unwrap_or_default()];
+        format!("{}{}", directory, location)
+    };
+
+    let mut parts = base.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse().ok()?);
+    Uri::from_parts(parts).ok()
+}
+
+fn same_origin(a: &Uri, b: &Uri) -> bool {
+    a.scheme() == b.scheme()
+        && a.host().map(str::to_ascii_lowercase) == b.host().map(str::to_ascii_lowercase)
+        && a.port_u16() == b.port_u16()
+}
+
+/// Client middleware following redirects according to a `RedirectPolicy`.
+///
+/// Put it outside `RetryService`, so that each request in the chain of
+/// redirects is retried, and inside `DefaultHeadersService`, so that
+/// credentials removed on cross-origin redirects aren't added back as
+/// defaults.
+#[derive(Clone)]
+pub struct RedirectService<T> {
+    inner: T,
+    policy: Arc<RedirectPolicy>,
+}
+
+impl<T> RedirectService<T> {
+    /// Create a new RedirectService, following redirects according to
+    /// `policy`.
+    pub fn new(inner: T, policy: RedirectPolicy) -> Self {
+        RedirectService {
+            inner,
+            policy: Arc::new(policy),
+        }
+    }
+}
+
+impl<T> fmt::Debug for RedirectService<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedirectService")
+            .field("inner", &self.inner)
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+impl<T, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for RedirectService<T>
+where
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>> + Clone + Send + 'static,
+    T::Future: Send + 'static,
+    T::Error: Into<Box<dyn Error + Send + Sync>>,
+    ReqBody: Body + From<Bytes> + Send + 'static,
+    ReqBody::Data: Send,
+    ReqBody::Error: Into<Box<dyn Error + Send + Sync>>,
+    C: Clone + Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let inner = self.inner.clone();
+        let policy = self.policy.clone();
+        Box::pin(async move {
+            let (mut parts, body) = req.into_parts();
+            let mut body = collect_limited(body, policy.max_body_size)
+                .await
+                .map_err(CollectError::into_boxed)?;
+            let origin = parts.uri.clone();
+
+            let mut redirects = 0;
+            loop {
+                let response = inner
+                    .call((rebuild(&parts, &body), context.clone()))
+                    .await
+                    .map_err(Into::into)?;
+
+                let method = match policy.redirect_method(response.status(), &parts.method) {
+                    Some(method) => method,
+                    None => return Ok(response),
+                };
+                let location = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|location| location.to_str().ok())
+                    .and_then(|location| resolve(&parts.uri, location));
+                let location = match location {
+                    Some(location) => location,
+                    None => return Ok(response),
+                };
+
+                if policy.same_origin_only && !same_origin(&origin, &location) {
+                    return Ok(response);
+                }
+                if redirects == policy.max_redirects {
+                    return Err(TooManyRedirects {
+                        max_redirects: policy.max_redirects,
+                    }
+                    .into());
+                }
+                redirects += 1;
+
+                if !same_origin(&parts.uri, &location) {
+                    parts.headers.remove(HOST);
+                    for name in &policy.sensitive_headers {
+                        parts.headers.remove(name);
+                    }
+                }
+
+                if method != parts.method && method == Method::GET {
+                    body = Bytes::new();
+                    for name in [CONTENT_TYPE, CONTENT_LENGTH, CONTENT_ENCODING] {
+                        parts.headers.remove(name);
+                    }
+                }
+
+                parts.method = method;
+                parts.uri = location;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+    use std::sync::Mutex;
+
+    /// Method, URI, whether the Authorization header was sent, and body of
+    /// a request.
+    type Sent = (Method, Uri, bool, Bytes);
+
+    /// Service redirecting `/start` to `location` with the given status, and
+    /// recording the requests it receives.
+    #[derive(Clone)]
+    struct Redirector {
+        status: StatusCode,
+        location: &'static str,
+        requests: Arc<Mutex<Vec<Sent>>>,
+    }
+
+    impl Service<(Request<Full<Bytes>>, ())> for Redirector {
+        type Response = Response<()>;
+        type Error = Box<dyn Error + Send + Sync>;
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, _): (Request<Full<Bytes>>, ())) -> Self::Future {
+            let this = self.clone();
+            Box::pin(async move {
+                let (parts, body) = req.into_parts();
+                let body = body.collect().await?.to_bytes();
+                let redirect = parts.uri.path() == "/start";
+                this.requests.lock().unwrap().push((
+                    parts.method,
+                    parts.uri,
+                    parts.headers.contains_key(AUTHORIZATION),
+                    body,
+                ));
+
+                let mut response = Response::new(());
+                if redirect {
+                    *response.status_mut() = this.status;
+                    response
+                        .headers_mut()
+                        .insert(LOCATION, this.location.parse().unwrap());
+                }
+                Ok(response)
+            })
+        }
+    }
+
+    fn post() -> Request<Full<Bytes>> {
+        let mut request = Request::new(Full::new(Bytes::from("body")));
+        *request.method_mut() = Method::POST;
+        *request.uri_mut() = "http://a.example/start".parse().unwrap();
+        request
+            .headers_mut()
+            .insert(AUTHORIZATION, "Bearer secret".parse().unwrap());
+        request
+    }
+
+    #[tokio::test]
+    async fn follows_redirects() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let service = RedirectService::new(
+            Redirector {
+                status: StatusCode::TEMPORARY_REDIRECT,
+                location: "end",
+                requests: requests.clone(),
+            },
+            RedirectPolicy::new(),
+        );
+        service.call((post(), ())).await.unwrap();
+        let last = requests.lock().unwrap().pop().unwrap();
+        assert_eq!(
+            last,
+            (
+                Method::POST,
+                "http://a.example/end".parse().unwrap(),
+                true,
+                Bytes::from("body")
+            )
+        );
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let service = RedirectService::new(
+            Redirector {
+                status: StatusCode::FOUND,
+                location: "http://b.example/end",
+                requests: requests.clone(),
+            },
+            RedirectPolicy::new(),
+        );
+        service.call((post(), ())).await.unwrap();
+        let last = requests.lock().unwrap().pop().unwrap();
+        assert_eq!(
+            last,
+            (
+                Method::GET,
+                "http://b.example/end".parse().unwrap(),
+                false,
+                Bytes::new()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn limits_redirects() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let service = RedirectService::new(
+            Redirector {
+                status: StatusCode::FOUND,
+                location: "/start",
+                requests: requests.clone(),
+            },
+            RedirectPolicy::new().max_redirects(2),
+        );
+        let error = service.call((post(), ())).await.unwrap_err();
+        assert!(error.downcast_ref::<TooManyRedirects>().is_some());
+        assert_eq!(requests.lock().unwrap().len(), 3);
+
+        let service = RedirectService::new(
+            Redirector {
+                status: StatusCode::FOUND,
+                location: "http://b.example/end",
+                requests: requests.clone(),
+            },
+            RedirectPolicy::new().same_origin_only(true),
+        );
+        let response = service.call((post(), ())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FOUND);
+    }
+}
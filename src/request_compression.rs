@@ -0,0 +1,145 @@
+//! Client middleware compressing request bodies.
+//!
+//! `RequestCompressionService` compresses request bodies which are at least
+//! a minimum size, setting the `Content-Encoding` header to match. This is
+//! opt-in, as servers aren't required to accept compressed requests - it
+//! should only be used for APIs which are known to support it, e.g. for bulk
+//! upload operations.
+//!
+//! ```rust
+//! # #[cfg(feature = "gzip")]
+//! # fn wrap<T>(client: T) {
+//! # use swagger::compression::Coding;
+//! # use swagger::request_compression::RequestCompressionService;
+//! let client = RequestCompressionService::new(client, Coding::Gzip).min_size(64 * 1024);
+//! # }
+//! ```
+
+use crate::compression::{Coding, CodingBody};
+use hyper::body::Body;
+use hyper::header::{HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH};
+use hyper::service::Service;
+use hyper::Request;
+
+/// Default minimum size of request bodies which are compressed.
+const DEFAULT_MIN_SIZE: u64 = 1024;
+
+/// Client middleware compressing request bodies.
+///
+/// Put it inside `RetryService`, `HedgeService` and `RedirectService`, which
+/// buffer the uncompressed body to resend it, so that each request sent is
+/// compressed separately.
+#[derive(Debug, Clone)]
+pub struct RequestCompressionService<T> {
+    inner: T,
+    coding: Coding,
+    min_size: u64,
+}
+
+impl<T> RequestCompressionService<T> {
+    /// Create a new RequestCompressionService, compressing request bodies of
+    /// at least 1KiB with the given coding.
+    pub fn new(inner: T, coding: Coding) -> Self {
+        RequestCompressionService {
+            inner,
+            coding,
+            min_size: DEFAULT_MIN_SIZE,
+        }
+    }
+
+    /// Only compress request bodies of at least `min_size` bytes. Bodies of
+    /// unknown length are compressed unless they are known to be smaller.
+    pub fn min_size(mut self, min_size: u64) -> Self {
+        self.min_size = min_size;
+        self
+    }
+}
+
+impl<T, B, C> Service<(Request<B>, C)> for RequestCompressionService<T>
+where
+    T: Service<(Request<CodingBody<B>>, C)>,
+    B: Body,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = T::Future;
+
+    fn call(&self, (req, context): (Request<B>, C)) -> Self::Future {
+        let (mut parts, body) = req.into_parts();
+
+        let large_enough = !body.is_end_stream()
+            && body
+                .size_hint()
+                .upper()
+                .is_none_or(|size| size >= self.min_size);
+
+        let body = if large_enough && !parts.headers.contains_key(CONTENT_ENCODING) {
+            parts.headers.remove(CONTENT_LENGTH);
+            parts.headers.insert(
+                CONTENT_ENCODING,
+                HeaderValue::from_static(self.coding.as_str()),
+            );
+            CodingBody::encode(body, self.coding)
+        } else {
+            CodingBody::identity(body)
+        };
+
+        self.inner.call((Request::from_parts(parts, body), context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::BoxFuture;
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::Bytes;
+    use std::convert::Infallible;
+
+    /// Service echoing the request's Content-Encoding and decompressed body
+    #[derive(Clone)]
+    struct DecompressingService;
+
+    impl Service<(Request<CodingBody<Full<Bytes>>>, ())> for DecompressingService {
+        type Response = (Option<String>, Bytes);
+        type Error = Infallible;
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, _): (Request<CodingBody<Full<Bytes>>>, ())) -> Self::Future {
+            Box::pin(async move {
+                let (parts, body) = req.into_parts();
+                let encoding = parts
+                    .headers
+                    .get(CONTENT_ENCODING)
+                    .map(|v| v.to_str().unwrap().to_string());
+                let body = match encoding.as_deref().and_then(Coding::from_name) {
+                    Some(coding) => CodingBody::decode(body, coding),
+                    None => CodingBody::identity(body),
+                };
+                Ok((encoding, body.collect().await.unwrap().to_bytes()))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn compresses_large_bodies() {
+        let coding = Coding::SUPPORTED[0];
+        let service = RequestCompressionService::new(DecompressingService, coding).min_size(100);
+
+        let large = Bytes::from("a".repeat(100));
+        let (encoding, body) = service
+            .call((Request::new(Full::new(large.clone())), ()))
+            .await
+            .unwrap();
+        assert_eq!(encoding.as_deref(), Some(coding.as_str()));
+        assert_eq!(body, large);
+
+        let small = Bytes::from("a".repeat(99));
+        let (encoding, body) = service
+            .call((Request::new(Full::new(small.clone())), ()))
+            .await
+            .unwrap();
+        assert_eq!(encoding, None);
+        assert_eq!(body, small);
+    }
+}
@@ -0,0 +1,318 @@
+//! Hyper service queueing requests when too many are in flight.
+//!
+//! Unlike `ConcurrencyLimitService`, which rejects excess requests
+//! immediately, `RequestQueueService` holds them in a bounded FIFO queue until
+//! a slot is free. Requests are only rejected, with `503 Service Unavailable`,
+//! if the queue is full or they wait longer than the queue timeout.
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! # use swagger::request_queue::RequestQueue;
+//! let queue = RequestQueue::new(100)
+//!     .max_queue_depth(1000)
+//!     .queue_timeout(Duration::from_secs(5));
+//!
+//! // `queue` can be cloned and kept to report the queue depth.
+//! assert_eq!(queue.queued(), 0);
+//! ```
+
+use crate::response::json_error;
+use crate::{Has, XSpanIdString};
+use futures::future::{BoxFuture, FutureExt};
+use hyper::header::{HeaderValue, RETRY_AFTER};
+use hyper::service::Service;
+use hyper::{Request, Response, StatusCode};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Decrements the queue depth when dropped, including when the waiting
+/// request is cancelled.
+struct QueuedGuard(Arc<AtomicUsize>);
+
+impl Drop for QueuedGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Request queue, shared by all connections. Clones share the same queue, so
+/// can be used to report the number of requests in flight and queued, e.g. to
+/// a metrics system.
+#[derive(Debug, Clone)]
+pub struct RequestQueue {
+    max_in_flight: usize,
+    max_queue_depth: usize,
+    queue_timeout: Duration,
+    permits: Arc<Semaphore>,
+    queued: Arc<AtomicUsize>,
+}
+
+impl RequestQueue {
+    /// Create a new request queue, allowing `max_in_flight` requests to be
+    /// handled concurrently, with a queue depth of `max_in_flight` and a queue
+    /// timeout of 30 seconds.
+    pub fn new(max_in_flight: usize) -> Self {
+        RequestQueue {
+            max_in_flight,
+            max_queue_depth: max_in_flight,
+            queue_timeout: Duration::from_secs(30),
+            permits: Arc::new(Semaphore::new(max_in_flight)),
+            queued: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Set the maximum number of requests waiting in the queue.
+    pub fn max_queue_depth(mut self, max_queue_depth: usize) -> Self {
+        self.max_queue_depth = max_queue_depth;
+        self
+    }
+
+    /// Set the maximum time a request may wait in the queue.
+    pub fn queue_timeout(mut self, queue_timeout: Duration) -> Self {
+        self.queue_timeout = queue_timeout;
+        self
+    }
+
+    /// Number of requests currently being handled.
+    pub fn in_flight(&self) -> usize {
+        self.max_in_flight - self.permits.available_permits()
+    }
+
+    /// Number of requests currently waiting in the queue.
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Acquire)
+    }
+
+    /// Try to join the queue, returning a guard which leaves it when dropped.
+    fn enqueue(&self) -> Option<QueuedGuard> {
+        let max = self.max_queue_depth;
+        self.queued
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |queued| {
+                (queued < max).then_some(queued + 1)
+            })
+            .ok()
+            .map(|_| QueuedGuard(self.queued.clone()))
+    }
+
+    /// Wait for a slot, failing if the queue is full or the request times out.
+    async fn acquire(self) -> Result<OwnedSemaphorePermit, Rejection> {
+        if let Ok(permit) = self.permits.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        let _guard = self.enqueue().ok_or(Rejection::QueueFull)?;
+        let permit = tokio::time::timeout(self.queue_timeout, self.permits.acquire_owned())
+            .await
+            .map_err(|_| Rejection::TimedOut)?;
+        Ok(permit.expect("Request queue semaphore is never closed"))
+    }
+}
+
+/// Why a request was rejected without being served.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rejection {
+    /// The queue was already full when the request arrived.
+    QueueFull,
+    /// The request waited in the queue for longer than the queue timeout.
+    TimedOut,
+}
+
+impl Rejection {
+    fn message(self) -> &'static str {
+        match self {
+            Rejection::QueueFull => "Request queue full",
+            Rejection::TimedOut => "Request timed out waiting in queue",
+        }
+    }
+}
+
+/// Middleware wrapper service which queues requests when too many are in
+/// flight.
+#[derive(Debug)]
+pub struct RequestQueueMakeService<T> {
+    inner: T,
+    queue: RequestQueue,
+}
+
+impl<T> RequestQueueMakeService<T> {
+    /// Create a new RequestQueueMakeService, sharing `queue` across all
+    /// connections.
+    pub fn new(inner: T, queue: RequestQueue) -> Self {
+        RequestQueueMakeService { inner, queue }
+    }
+}
+
+impl<Inner, Target> Service<Target> for RequestQueueMakeService<Inner>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = RequestQueueService<Inner::Response>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let queue = self.queue.clone();
+        Box::pin(
+            self.inner
+                .call(target)
+                .map(|s| Ok(RequestQueueService::new(s?, queue))),
+        )
+    }
+}
+
+/// Middleware wrapper service which queues requests when too many are in
+/// flight. Servers will normally want to use `RequestQueueMakeService`, which
+/// will create a `RequestQueueService` for each connection.
+///
+/// A request counts as in flight until the wrapped service has returned its
+/// response, not including the time taken to stream the response body.
+#[derive(Clone)]
+pub struct RequestQueueService<T> {
+    inner: T,
+    queue: RequestQueue,
+}
+
+impl<T> RequestQueueService<T> {
+    /// Create a new RequestQueueService
+    pub fn new(inner: T, queue: RequestQueue) -> Self {
+        RequestQueueService { inner, queue }
+    }
+}
+
+impl<T> fmt::Debug for RequestQueueService<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestQueueService")
+            .field("inner", &self.inner)
+            .field("queue", &self.queue)
+            .finish()
+    }
+}
+
+impl<T, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for RequestQueueService<T>
+where
+    C: Has<XSpanIdString>,
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>> + Clone + Send + 'static,
+    T::Future: Send + 'static,
+    T::Error: Send + 'static,
+    ReqBody: Send + 'static,
+    ResBody: From<String> + Send + 'static,
+    C: Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let inner = self.inner.clone();
+        let queue = self.queue.clone();
+        Box::pin(async move {
+            match queue.acquire().await {
+                Ok(permit) => {
+                    let response = inner.call((req, context)).await;
+                    drop(permit);
+                    response
+                }
+                Err(rejection) => {
+                    let x_span_id = Has::<XSpanIdString>::get(&context);
+                    let mut response = json_error(
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        rejection.message(),
+                        Some(&x_span_id.0),
+                    );
+                    response
+                        .headers_mut()
+                        .insert(RETRY_AFTER, HeaderValue::from_static("1"));
+                    Ok(response)
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContextBuilder, EmptyContext, Push};
+    use std::sync::Mutex;
+    use tokio::sync::oneshot;
+
+    type TestContext = ContextBuilder<XSpanIdString, EmptyContext>;
+
+    /// Service which doesn't respond until told to.
+    #[derive(Clone, Default)]
+    struct BlockingService(Arc<Mutex<Vec<oneshot::Sender<()>>>>);
+
+    impl BlockingService {
+        fn release(&self) {
+            self.0.lock().unwrap().clear();
+        }
+    }
+
+    impl Service<(Request<()>, TestContext)> for BlockingService {
+        type Response = Response<String>;
+        type Error = ();
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _req: (Request<()>, TestContext)) -> Self::Future {
+            let (tx, rx) = oneshot::channel();
+            self.0.lock().unwrap().push(tx);
+            Box::pin(async move {
+                let _ = rx.await;
+                Ok(Response::new("done".to_string()))
+            })
+        }
+    }
+
+    fn request() -> (Request<()>, TestContext) {
+        (
+            Request::get("/").body(()).unwrap(),
+            EmptyContext.push(XSpanIdString("span".to_string())),
+        )
+    }
+
+    #[tokio::test]
+    async fn queues_requests() {
+        let inner = BlockingService::default();
+        let queue = RequestQueue::new(1)
+            .max_queue_depth(1)
+            .queue_timeout(Duration::from_millis(50));
+        let service = RequestQueueService::new(inner.clone(), queue.clone());
+
+        let first = tokio::spawn(service.call(request()));
+        let second = tokio::spawn(service.call(request()));
+        tokio::task::yield_now().await;
+        assert_eq!(queue.in_flight(), 1);
+        assert_eq!(queue.queued(), 1);
+
+        let rejected = service.call(request()).await.unwrap();
+        assert_eq!(rejected.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(rejected.body().contains("Request queue full"));
+
+        inner.release();
+        assert_eq!(first.await.unwrap().unwrap().status(), StatusCode::OK);
+        tokio::task::yield_now().await;
+        assert_eq!(queue.queued(), 0);
+        inner.release();
+        assert_eq!(second.await.unwrap().unwrap().status(), StatusCode::OK);
+        assert_eq!(queue.in_flight(), 0);
+
+        // A request which waits too long is rejected.
+        let blocked = tokio::spawn(service.call(request()));
+        tokio::task::yield_now().await;
+        let timed_out = service.call(request()).await.unwrap();
+        assert_eq!(timed_out.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(timed_out
+            .body()
+            .contains("Request timed out waiting in queue"));
+        assert_eq!(queue.queued(), 0);
+        inner.release();
+        assert_eq!(blocked.await.unwrap().unwrap().status(), StatusCode::OK);
+    }
+}
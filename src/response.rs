@@ -0,0 +1,130 @@
+//! Helpers for building the error responses returned by the crate's middleware.
+
+use crate::X_SPAN_ID;
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::{Response, StatusCode};
+use std::borrow::Cow;
+
+/// Escape a string for inclusion in a JSON string literal.
+pub(crate) fn json_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Marks a response as an error produced by the crate's middleware, holding
+/// its code and message, so that `ProblemService` and
+/// `ErrorRendererService` can render it differently.
+#[derive(Debug, Clone)]
+pub(crate) struct ErrorMessage {
+    pub(crate) code: Cow<'static, str>,
+    pub(crate) message: String,
+}
+
+/// The default machine-readable code for an error status, such as
+/// `not_found`.
+pub(crate) fn error_code(status: StatusCode) -> Cow<'static, str> {
+    match status.canonical_reason() {
+        Some(reason) => reason
+            .chars()
+            .map(|c| match c {
+                c if c.is_ascii_alphanumeric() => c.to_ascii_lowercase(),
+                _ => '_',
+            })
+            .collect::<String>()
+            .into(),
+        None => Cow::Borrowed("error"),
+    }
+}
+
+/// Build a JSON error response of the form
+/// `{"code":<status>,"message":"<message>","x-span-id":"<X-Span-ID>"}`.
+///
+/// The X-Span-ID is omitted if not known, and is also returned in the headers.
+pub(crate) fn json_error<B>(
+    status: StatusCode,
+    message: &str,
+    x_span_id: Option<&str>,
+) -> Response<B>
+where
+    B: From<String>,
+{
+    coded_json_error(status, error_code(status), message, x_span_id)
+}
+
+/// Build a JSON error response, as `json_error`, with a more specific
+/// machine-readable code than the status's for custom renderers, such as
+/// `origin_not_allowed`.
+pub(crate) fn coded_json_error<B>(
+    status: StatusCode,
+    code: impl Into<Cow<'static, str>>,
+    message: &str,
+    x_span_id: Option<&str>,
+) -> Response<B>
+where
+    B: From<String>,
+{
+    let body = match x_span_id {
+        Some(x_span_id) => format!(
+            r#"{{"code":{},"message":"{}","x-span-id":"{}"}}"#,
+            status.as_u16(),
+            json_escape(message),
+            json_escape(x_span_id)
+        ),
+        None => format!(
+            r#"{{"code":{},"message":"{}"}}"#,
+            status.as_u16(),
+            json_escape(message)
+        ),
+    };
+
+    let mut response = Response::new(B::from(body));
+    *response.status_mut() = status;
+    response.extensions_mut().insert(ErrorMessage {
+        code: code.into(),
+        message: message.to_string(),
+    });
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    if let Some(Ok(x_span_id)) = x_span_id.map(HeaderValue::from_str) {
+        response.headers_mut().insert(X_SPAN_ID, x_span_id);
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_json() {
+        assert_eq!(json_escape("a\"b\\c\n"), "a\\\"b\\\\c\\u000a");
+    }
+
+    #[test]
+    fn builds_error() {
+        let response: Response<String> =
+            json_error(StatusCode::NOT_FOUND, "Not Found", Some("span"));
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[X_SPAN_ID], "span");
+        assert_eq!(
+            response.body(),
+            r#"{"code":404,"message":"Not Found","x-span-id":"span"}"#
+        );
+
+        let response: Response<String> = json_error(StatusCode::SERVICE_UNAVAILABLE, "Busy", None);
+        assert_eq!(response.body(), r#"{"code":503,"message":"Busy"}"#);
+        let error = response.extensions().get::<ErrorMessage>().unwrap();
+        assert_eq!(error.code, "service_unavailable");
+        assert_eq!(error.message, "Busy");
+    }
+}
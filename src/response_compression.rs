@@ -0,0 +1,423 @@
+//! Server middleware compressing response bodies.
+//!
+//! `CompressionService` compresses response bodies using the client's
+//! preferred coding from the request's `Accept-Encoding` header, streaming
+//! them through the compressor as they are produced. Only responses with an
+//! allowed content type, and which aren't known to be smaller than a minimum
+//! size, are compressed. These responses get `Vary: Accept-Encoding`,
+//! whether compressed or not, so that caches keep the versions apart.
+//!
+//! Responses which already have a `Content-Encoding`, partial content,
+//! responses with `Cache-Control: no-transform`, and responses to `HEAD`
+//! requests are never compressed. Strong `ETag`s of compressed responses are
+//! made weak, as the compressed bytes may differ between responses.
+//!
+//! ```rust
+//! # #[cfg(feature = "gzip")]
+//! # fn wrap<T>(api: T) {
+//! # use swagger::compression::Coding;
+//! # use swagger::response_compression::CompressionService;
+//! # use swagger::MediaType;
+//! let service = CompressionService::new(api)
+//!     .codings([Coding::Gzip])
+//!     .min_size(4096)
+//!     .content_types([
+//!         MediaType::from_static("application/json"),
+//!         MediaType::from_static("text/*"),
+//!     ]);
+//! # }
+//! ```
+
+use crate::compression::{Coding, CodingBody};
+use crate::MediaType;
+use futures::future::{BoxFuture, FutureExt};
+use hyper::body::Body;
+use hyper::header::{
+    HeaderMap, HeaderValue, ACCEPT_ENCODING, ACCEPT_RANGES, CACHE_CONTROL, CONTENT_ENCODING,
+    CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, VARY,
+};
+use hyper::service::Service;
+use hyper::{Method, Request, Response, StatusCode};
+use std::sync::Arc;
+
+/// Default minimum size of response bodies which are compressed.
+const DEFAULT_MIN_SIZE: u64 = 1024;
+
+/// Content types compressed by default.
+const DEFAULT_CONTENT_TYPES: &[&str] = &[
+    "application/json",
+    "application/*+json",
+    "application/xml",
+    "application/*+xml",
+    "application/javascript",
+    "image/svg+xml",
+    "text/css",
+    "text/csv",
+    "text/html",
+    "text/javascript",
+    "text/plain",
+    "text/xml",
+];
+
+/// Choose the coding in `supported` for an `Accept-Encoding` header.
+///
+/// The coding with the highest `q` weight is chosen, or the earliest in
+/// `supported` if several are equally acceptable. `*` gives the weight of
+/// codings not listed. Returns `None` if there's no header, or no supported
+/// coding is acceptable.
+pub fn negotiate_encoding(accept_encoding: Option<&str>, supported: &[Coding]) -> Option<Coding> {
+    let accept_encoding = accept_encoding?;
+    let mut weights = Vec::new();
+    let mut wildcard = None;
+    for item in accept_encoding.split(',') {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or_default().trim();
+        // Invalid weights make the coding unacceptable
+        let quality = parts
+            .find_map(|param| {
+                let (name, value) = param.split_once('=')?;
+                name.trim()
+                    .eq_ignore_ascii_case("q")
+                    .then(|| value.trim().parse::<f32>().unwrap_or(0.0))
+            })
+            .unwrap_or(1.0);
+        if name == "*" {
+            wildcard = Some(quality);
+        } else if let Some(coding) = Coding::from_name(name) {
+            weights.push((coding, quality));
+        }
+    }
+
+    let mut best: Option<(Coding, f32)> = None;
+    for coding in supported {
+        let quality = weights
+            .iter()
+            .find(|(c, _)| c == coding)
+            .map(|(_, quality)| *quality)
+            .or(wildcard)
+            .unwrap_or(0.0);
+        if quality > 0.0 && best.is_none_or(|(_, best)| quality > best) {
+            best = Some((*coding, quality));
+        }
+    }
+    best.map(|(coding, _)| coding)
+}
+
+/// Which responses are compressed, and how.
+#[derive(Debug, Clone)]
+struct Config {
+    codings: Vec<Coding>,
+    min_size: u64,
+    content_types: Vec<MediaType>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            codings: Coding::SUPPORTED.to_vec(),
+            min_size: DEFAULT_MIN_SIZE,
+            content_types: DEFAULT_CONTENT_TYPES
+                .iter()
+                .map(|content_type| MediaType::from_static(content_type))
+                .collect(),
+        }
+    }
+}
+
+impl Config {
+    /// Whether a response with these headers can be compressed, if the
+    /// client accepts it.
+    fn compressible(&self, headers: &HeaderMap) -> bool {
+        let Some(content_type) = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok()?.parse::<MediaType>().ok())
+        else {
+            return false;
+        };
+        self.content_types
+            .iter()
+            .any(|range| match range.subtype().strip_prefix('*') {
+                // Structured syntax suffix, e.g. `application/*+json`
+                Some(suffix) if !suffix.is_empty() => {
+                    range.type_() == content_type.type_()
+                        && content_type.subtype().ends_with(suffix)
+                }
+                _ => range.includes(&content_type),
+            })
+    }
+}
+
+/// Whether a header lists a token, case-insensitively.
+fn lists(headers: &HeaderMap, name: impl hyper::header::AsHeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|item| {
+            let item = item.trim();
+            item.eq_ignore_ascii_case(token) || item == "*"
+        })
+}
+
+/// Compress a response with the coding negotiated for the request, if it's
+/// compressible.
+fn compress<B: Body>(
+    response: Response<B>,
+    coding: Option<Coding>,
+    config: &Config,
+) -> Response<CodingBody<B>> {
+    let (mut parts, body) = response.into_parts();
+
+    let compressible = config.compressible(&parts.headers)
+        && !parts.headers.contains_key(CONTENT_ENCODING)
+        && !parts.headers.contains_key(CONTENT_RANGE)
+        && !lists(&parts.headers, CACHE_CONTROL, "no-transform")
+        && !matches!(
+            parts.status,
+            StatusCode::NO_CONTENT | StatusCode::PARTIAL_CONTENT | StatusCode::NOT_MODIFIED
+        );
+    if !compressible {
+        return Response::from_parts(parts, CodingBody::identity(body));
+    }
+
+    if !lists(&parts.headers, VARY, "accept-encoding") {
+        parts
+            .headers
+            .append(VARY, HeaderValue::from_static("Accept-Encoding"));
+    }
+
+    let large_enough = !body.is_end_stream()
+        && body
+            .size_hint()
+            .upper()
+            .is_none_or(|size| size >= config.min_size);
+    let Some(coding) = coding.filter(|_| large_enough) else {
+        return Response::from_parts(parts, CodingBody::identity(body));
+    };
+
+    parts.headers.remove(CONTENT_LENGTH);
+    parts.headers.remove(ACCEPT_RANGES);
+    parts
+        .headers
+        .insert(CONTENT_ENCODING, HeaderValue::from_static(coding.as_str()));
+    if let Some(etag) = parts.headers.get(ETAG) {
+        if etag.as_bytes().starts_with(b"\"") {
+            let mut weak = b"W/".to_vec();
+            weak.extend_from_slice(etag.as_bytes());
+            if let Ok(weak) = HeaderValue::from_bytes(&weak) {
+                parts.headers.insert(ETAG, weak);
+            }
+        }
+    }
+    Response::from_parts(parts, CodingBody::encode(body, coding))
+}
+
+/// Middleware wrapper service which compresses response bodies.
+#[derive(Debug)]
+pub struct CompressionMakeService<T> {
+    inner: T,
+    config: Arc<Config>,
+}
+
+impl<T> CompressionMakeService<T> {
+    /// Create a new CompressionMakeService, compressing responses of at
+    /// least 1KiB with common text-based content types, using any coding
+    /// supported by this build.
+    pub fn new(inner: T) -> Self {
+        CompressionMakeService {
+            inner,
+            config: Arc::default(),
+        }
+    }
+
+    /// Set the codings which may be used, in order of preference.
+    pub fn codings<I: IntoIterator<Item = Coding>>(mut self, codings: I) -> Self {
+        Arc::make_mut(&mut self.config).codings = codings.into_iter().collect();
+        self
+    }
+
+    /// Only compress response bodies of at least `min_size` bytes. Bodies of
+    /// unknown length are compressed unless they are known to be smaller.
+    pub fn min_size(mut self, min_size: u64) -> Self {
+        Arc::make_mut(&mut self.config).min_size = min_size;
+        self
+    }
+
+    /// Set the content types which are compressed. These may be ranges such
+    /// as `text/*`, or have a structured syntax suffix such as
+    /// `application/*+json`.
+    pub fn content_types<I: IntoIterator<Item = MediaType>>(mut self, content_types: I) -> Self {
+        Arc::make_mut(&mut self.config).content_types = content_types.into_iter().collect();
+        self
+    }
+}
+
+impl<Inner, Target> Service<Target> for CompressionMakeService<Inner>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = CompressionService<Inner::Response>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let config = self.config.clone();
+        Box::pin(
+            self.inner
+                .call(target)
+                .map(|s| Ok(CompressionService { inner: s?, config })),
+        )
+    }
+}
+
+/// Middleware wrapper service which compresses response bodies. Servers will
+/// normally want to use `CompressionMakeService`, which will create a
+/// `CompressionService` for each connection.
+#[derive(Debug, Clone)]
+pub struct CompressionService<T> {
+    inner: T,
+    config: Arc<Config>,
+}
+
+impl<T> CompressionService<T> {
+    /// Create a new CompressionService, compressing responses of at least
+    /// 1KiB with common text-based content types, using any coding
+    /// supported by this build.
+    pub fn new(inner: T) -> Self {
+        CompressionService {
+            inner,
+            config: Arc::default(),
+        }
+    }
+
+    /// Set the codings which may be used, in order of preference.
+    pub fn codings<I: IntoIterator<Item = Coding>>(mut self, codings: I) -> Self {
+        Arc::make_mut(&mut self.config).codings = codings.into_iter().collect();
+        self
+    }
+
+    /// Only compress response bodies of at least `min_size` bytes. Bodies of
+    /// unknown length are compressed unless they are known to be smaller.
+    pub fn min_size(mut self, min_size: u64) -> Self {
+        Arc::make_mut(&mut self.config).min_size = min_size;
+        self
+    }
+
+    /// Set the content types which are compressed. These may be ranges such
+    /// as `text/*`, or have a structured syntax suffix such as
+    /// `application/*+json`.
+    pub fn content_types<I: IntoIterator<Item = MediaType>>(mut self, content_types: I) -> Self {
+        Arc::make_mut(&mut self.config).content_types = content_types.into_iter().collect();
+        self
+    }
+}
+
+impl<T, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for CompressionService<T>
+where
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    ResBody: Body,
+{
+    type Response = Response<CodingBody<ResBody>>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let coding = if req.method() == Method::HEAD {
+            None
+        } else {
+            let accept_encoding = req
+                .headers()
+                .get_all(ACCEPT_ENCODING)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .collect::<Vec<_>>()
+                .join(",");
+            negotiate_encoding(
+                (!accept_encoding.is_empty()).then_some(accept_encoding.as_str()),
+                &self.config.codings,
+            )
+        };
+        let config = self.config.clone();
+        Box::pin(
+            self.inner
+                .call((req, context))
+                .map(move |response| Ok(compress(response?, coding, &config))),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::Bytes;
+
+    struct TestApi;
+
+    impl Service<(Request<()>, ())> for TestApi {
+        type Response = Response<Full<Bytes>>;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, _): (Request<()>, ())) -> Self::Future {
+            let (content_type, length) = match req.uri().path() {
+                "/small" => ("application/json", 10),
+                "/image" => ("image/png", 2000),
+                _ => ("application/problem+json; charset=utf-8", 2000),
+            };
+            let response = Response::builder()
+                .header(CONTENT_TYPE, content_type)
+                .header(ETAG, "\"v1\"")
+                .body(Full::from("a".repeat(length)))
+                .unwrap();
+            futures::future::ok(response)
+        }
+    }
+
+    #[tokio::test]
+    async fn compresses_responses() {
+        let coding = Coding::SUPPORTED[0];
+        let service = CompressionService::new(TestApi);
+        let call = |path: &str, accept_encoding: &str| {
+            let request = Request::builder()
+                .uri(path)
+                .header(ACCEPT_ENCODING, accept_encoding)
+                .body(())
+                .unwrap();
+            service.call((request, ()))
+        };
+
+        let accept = format!("identity;q=0.5, {};q=0.8, unknown", coding);
+        let response = call("/large", &accept).await.unwrap();
+        assert_eq!(response.headers()[CONTENT_ENCODING], coding.as_str());
+        assert_eq!(response.headers()[VARY], "Accept-Encoding");
+        assert_eq!(response.headers()[ETAG], "W/\"v1\"");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = CodingBody::decode(Full::new(body), coding)
+            .collect()
+            .await
+            .unwrap()
+            .to_bytes();
+        assert_eq!(body, "a".repeat(2000));
+
+        let response = call("/small", "*").await.unwrap();
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+        assert_eq!(response.headers()[VARY], "Accept-Encoding");
+        let response = call("/image", "*").await.unwrap();
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+        assert!(response.headers().get(VARY).is_none());
+
+        let supported = Coding::SUPPORTED;
+        assert_eq!(negotiate_encoding(None, supported), None);
+        assert_eq!(negotiate_encoding(Some("*;q=0"), supported), None);
+        assert_eq!(negotiate_encoding(Some("*"), supported), Some(supported[0]));
+        let last = supported[supported.len() - 1];
+        assert_eq!(
+            negotiate_encoding(Some(&format!("{};q=1, *;q=0.1", last)), supported),
+            Some(last)
+        );
+    }
+}
@@ -0,0 +1,476 @@
+//! Client middleware retrying failed requests.
+//!
+//! `RetryService` retries requests which fail with a connection error, or
+//! with a response status indicating a transient failure, waiting with
+//! exponential backoff between attempts, or as long as the server asks in a
+//! `Retry-After` header.
+//!
+//! Request bodies are buffered so that they can be resent, so only requests
+//! which may be retried pay this cost. By default, only idempotent methods
+//! are retried. Requests whose bodies may be longer than the policy's
+//! `max_body_size` are sent once, without being buffered or retried.
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! # use swagger::retry::{RetryBudget, RetryPolicy};
+//! let policy = RetryPolicy::new()
+//!     .max_attempts(5)
+//!     .initial_backoff(Duration::from_millis(50))
+//!     .budget(RetryBudget::new(0.2, 10));
+//! ```
+
+use crate::body_ext::{collect_limited, CollectError};
+use futures::future::{BoxFuture, TryFutureExt};
+use headers::Header;
+use hyper::body::{Body, Bytes};
+use hyper::header::RETRY_AFTER;
+use hyper::service::Service;
+use hyper::{Method, Request, Response, StatusCode};
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Tokens are stored in thousandths, so that fractional deposits can be made.
+const TOKEN_SCALE: f64 = 1000.0;
+
+/// Budget limiting the number of retries to a fraction of the number of
+/// requests, so that retries can't multiply the load on a failing server.
+///
+/// The budget starts with `min_retries` tokens, and each request deposits
+/// `ratio` tokens, up to a maximum of `min_retries` plus the deposits of 100
+/// requests. Each retry withdraws one token. Clones share the same budget.
+#[derive(Debug, Clone)]
+pub struct RetryBudget {
+    ratio: f64,
+    max: i64,
+    balance: Arc<AtomicI64>,
+}
+
+impl RetryBudget {
+    /// Create a new retry budget, allowing `ratio` retries per request, and
+    /// `min_retries` retries regardless of the number of requests.
+    pub fn new(ratio: f64, min_retries: usize) -> Self {
+        let min = (min_retries as f64 * TOKEN_SCALE) as i64;
+        RetryBudget {
+            ratio,
+            max: min + (ratio * 100.0 * TOKEN_SCALE) as i64,
+            balance: Arc::new(AtomicI64::new(min)),
+        }
+    }
+
+    pub(crate) fn deposit(&self) {
+        let deposit = (self.ratio * TOKEN_SCALE) as i64;
+        let max = self.max;
+        let _ = self
+            .balance
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |balance| {
+                Some((balance + deposit).min(max))
+            });
+    }
+
+    pub(crate) fn withdraw(&self) -> bool {
+        let withdrawal = TOKEN_SCALE as i64;
+        self.balance
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |balance| {
+                (balance >= withdrawal).then_some(balance - withdrawal)
+            })
+            .is_ok()
+    }
+}
+
+/// Policy deciding which requests are retried, and how long to wait between
+/// attempts.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    methods: Vec<Method>,
+    statuses: Vec<StatusCode>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    honor_retry_after: bool,
+    max_retry_after: Duration,
+    max_body_size: u64,
+    budget: Option<RetryBudget>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetryPolicy {
+    /// Create a new retry policy, making up to 3 attempts at idempotent
+    /// requests which fail with a connection error or a `429`, `502`, `503` or
+    /// `504` response. The backoff starts at 100ms, doubling up to 10s, with
+    /// jitter, and a `Retry-After` of up to 60s is honored. Request bodies of
+    /// up to 10MiB are buffered for resending.
+    pub fn new() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            methods: vec![
+                Method::GET,
+                Method::HEAD,
+                Method::OPTIONS,
+                Method::PUT,
+                Method::DELETE,
+                Method::TRACE,
+            ],
+            statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            jitter: true,
+            honor_retry_after: true,
+            max_retry_after: Duration::from_secs(60),
+            max_body_size: 10 * 1024 * 1024,
+            budget: None,
+        }
+    }
+
+    /// Set the maximum number of attempts, including the first.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Set the request methods which may be retried.
+    pub fn methods<I: IntoIterator<Item = Method>>(mut self, methods: I) -> Self {
+        self.methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Set the response statuses which cause a retry.
+    pub fn statuses<I: IntoIterator<Item = StatusCode>>(mut self, statuses: I) -> Self {
+        self.statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Set the backoff before the first retry. This doubles for each
+    /// subsequent retry.
+    pub fn initial_backoff(mut self, initial_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// Set the maximum backoff between attempts.
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Enable or disable jitter. With jitter, each backoff is chosen at random
+    /// between half and all of the exponential backoff.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Enable or disable honoring the `Retry-After` header. Responses asking
+    /// for a longer delay than `max_retry_after` aren't retried.
+    pub fn honor_retry_after(mut self, honor: bool, max_retry_after: Duration) -> Self {
+        self.honor_retry_after = honor;
+        self.max_retry_after = max_retry_after;
+        self
+    }
+
+    /// Set the maximum size of request body, in bytes, which will be buffered
+    /// for resending. Requests with bodies which may be longer than this are
+    /// sent once, and not retried.
+    pub fn max_body_size(mut self, max_body_size: u64) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Limit retries using a retry budget, which may be shared with other
+    /// services.
+    pub fn budget(mut self, budget: RetryBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_backoff);
+
+        if self.jitter {
+            let random = RandomState::new().build_hasher().finish();
+            let half = backoff / 2;
+            half + half.mul_f64(random as f64 / u64::MAX as f64)
+        } else {
+            backoff
+        }
+    }
+
+    /// Delay before retrying after a response, or `None` if it shouldn't be
+    /// retried.
+    fn retry_delay<B>(&self, response: &Response<B>, attempt: u32) -> Option<Duration> {
+        if !self.statuses.contains(&response.status()) {
+            return None;
+        }
+
+        match response.headers().get(RETRY_AFTER) {
+            Some(value) if self.honor_retry_after => {
+                let delay = retry_after(value)?;
+                (delay <= self.max_retry_after).then_some(delay)
+            }
+            _ => Some(self.backoff(attempt)),
+        }
+    }
+}
+
+/// Parse a `Retry-After` header, which is either a number of seconds or a
+/// date.
+fn retry_after(value: &hyper::header::HeaderValue) -> Option<Duration> {
+    if let Some(secs) = value.to_str().ok().and_then(|s| s.trim().parse().ok()) {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let date = headers::Date::decode(&mut std::iter::once(value)).ok()?;
+    Some(
+        SystemTime::from(date)
+            .duration_since(SystemTime::now())
+            .unwrap_or_default(),
+    )
+}
+
+/// Rebuild a request from its parts and buffered body.
+pub(crate) fn rebuild<B: From<Bytes>>(
+    parts: &hyper::http::request::Parts,
+    body: &Bytes,
+) -> Request<B> {
+    let mut request = Request::new(B::from(body.clone()));
+    *request.method_mut() = parts.method.clone();
+    *request.uri_mut() = parts.uri.clone();
+    *request.version_mut() = parts.version;
+    *request.headers_mut() = parts.headers.clone();
+    *request.extensions_mut() = parts.extensions.clone();
+    request
+}
+
+/// Client middleware retrying failed requests according to a `RetryPolicy`.
+///
+/// Wrap it with `TimeoutService` to bound the time taken by all attempts
+/// together, or wrap `TimeoutService` with it to give each attempt its own
+/// timeout.
+#[derive(Clone)]
+pub struct RetryService<T> {
+    inner: T,
+    policy: Arc<RetryPolicy>,
+}
+
+impl<T> RetryService<T> {
+    /// Create a new RetryService, retrying requests according to `policy`.
+    pub fn new(inner: T, policy: RetryPolicy) -> Self {
+        RetryService {
+            inner,
+            policy: Arc::new(policy),
+        }
+    }
+}
+
+impl<T> fmt::Debug for RetryService<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryService")
+            .field("inner", &self.inner)
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+impl<T, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for RetryService<T>
+where
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>> + Clone + Send + 'static,
+    T::Future: Send + 'static,
+    T::Error: Into<Box<dyn Error + Send + Sync>>,
+    ReqBody: Body + From<Bytes> + Send + 'static,
+    ReqBody::Data: Send,
+    ReqBody::Error: Into<Box<dyn Error + Send + Sync>>,
+    C: Clone + Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let policy = self.policy.clone();
+        if let Some(budget) = &policy.budget {
+            budget.deposit();
+        }
+
+        // Only bodies known to fit within the limit are buffered, as a longer
+        // body can't be sent once part of it has been read
+        let body_fits = req
+            .body()
+            .size_hint()
+            .upper()
+            .is_some_and(|upper| upper <= policy.max_body_size);
+        if policy.max_attempts <= 1 || !policy.methods.contains(req.method()) || !body_fits {
+            return Box::pin(self.inner.call((req, context)).map_err(Into::into));
+        }
+
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            let body = collect_limited(body, policy.max_body_size)
+                .await
+                .map_err(CollectError::into_boxed)?;
+
+            let mut attempt = 1;
+            loop {
+                let result = inner
+                    .call((rebuild(&parts, &body), context.clone()))
+                    .await
+                    .map_err(Into::into);
+
+                let delay = match &result {
+                    Ok(response) => policy.retry_delay(response, attempt),
+                    Err(_) => Some(policy.backoff(attempt)),
+                };
+
+                let retry = delay.filter(|_| {
+                    attempt < policy.max_attempts
+                        && policy.budget.as_ref().is_none_or(RetryBudget::withdraw)
+                });
+
+                match retry {
+                    Some(delay) => {
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                    None => return result,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+    use std::sync::Mutex;
+
+    /// Service returning the given statuses in turn, and recording the
+    /// request bodies it receives.
+    #[derive(Clone)]
+    struct TestService {
+        statuses: Arc<Mutex<Vec<StatusCode>>>,
+        bodies: Arc<Mutex<Vec<Bytes>>>,
+    }
+
+    impl TestService {
+        fn new(mut statuses: Vec<StatusCode>) -> Self {
+            statuses.reverse();
+            TestService {
+                statuses: Arc::new(Mutex::new(statuses)),
+                bodies: Arc::default(),
+            }
+        }
+    }
+
+    impl Service<(Request<Full<Bytes>>, ())> for TestService {
+        type Response = Response<()>;
+        type Error = std::io::Error;
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, _): (Request<Full<Bytes>>, ())) -> Self::Future {
+            let status = self.statuses.lock().unwrap().pop();
+            let bodies = self.bodies.clone();
+            Box::pin(async move {
+                let body = req.into_body().collect().await.unwrap().to_bytes();
+                bodies.lock().unwrap().push(body);
+                match status {
+                    Some(status) => {
+                        let mut response = Response::new(());
+                        *response.status_mut() = status;
+                        response
+                            .headers_mut()
+                            .insert(RETRY_AFTER, "0".parse().unwrap());
+                        Ok(response)
+                    }
+                    None => Err(std::io::ErrorKind::ConnectionRefused.into()),
+                }
+            })
+        }
+    }
+
+    fn request(method: Method) -> (Request<Full<Bytes>>, ()) {
+        let mut request = Request::new(Full::new(Bytes::from("body")));
+        *request.method_mut() = method;
+        (request, ())
+    }
+
+    #[tokio::test]
+    async fn retries_requests() {
+        let policy = RetryPolicy::new().initial_backoff(Duration::from_millis(1));
+
+        let inner = TestService::new(vec![StatusCode::SERVICE_UNAVAILABLE, StatusCode::OK]);
+        let service = RetryService::new(inner.clone(), policy.clone());
+        let response = service.call(request(Method::PUT)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(*inner.bodies.lock().unwrap(), vec!["body", "body"]);
+
+        // Connection errors are retried, up to the maximum number of attempts
+        let inner = TestService::new(vec![]);
+        let service = RetryService::new(inner.clone(), policy.clone());
+        assert!(service.call(request(Method::GET)).await.is_err());
+        assert_eq!(inner.bodies.lock().unwrap().len(), 3);
+
+        // Non-idempotent requests aren't retried
+        let inner = TestService::new(vec![StatusCode::SERVICE_UNAVAILABLE, StatusCode::OK]);
+        let service = RetryService::new(inner.clone(), policy.clone());
+        let response = service.call(request(Method::POST)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // Nor are requests with bodies too long to buffer
+        let inner = TestService::new(vec![StatusCode::SERVICE_UNAVAILABLE, StatusCode::OK]);
+        let service = RetryService::new(inner.clone(), policy.max_body_size(3));
+        let response = service.call(request(Method::PUT)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(*inner.bodies.lock().unwrap(), vec!["body"]);
+    }
+
+    #[tokio::test]
+    async fn respects_budget() {
+        let budget = RetryBudget::new(0.0, 1);
+        let policy = RetryPolicy::new()
+            .initial_backoff(Duration::from_millis(1))
+            .budget(budget);
+
+        let inner = TestService::new(vec![]);
+        let service = RetryService::new(inner.clone(), policy);
+        assert!(service.call(request(Method::GET)).await.is_err());
+        assert_eq!(inner.bodies.lock().unwrap().len(), 2);
+        assert!(service.call(request(Method::GET)).await.is_err());
+        assert_eq!(inner.bodies.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn parses_retry_after() {
+        assert_eq!(
+            retry_after(&"120".parse().unwrap()),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            retry_after(&"Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap()),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after(&"soon".parse().unwrap()), None);
+    }
+}
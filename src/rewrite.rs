@@ -0,0 +1,168 @@
+//! Client middleware rewriting request URIs.
+//!
+//! `RewriteService` points requests at a different base URI - replacing the
+//! scheme, host and port, and the base path - so that a single generated
+//! client can target several environments, or per-tenant hosts, chosen at
+//! runtime.
+//!
+//! The base URI can be overridden for a single request by adding a `BaseUri`
+//! to its context.
+//!
+//! ```rust
+//! # use swagger::rewrite::RewriteService;
+//! # fn wrap<T>(client: T) {
+//! // The generated client was created with a base path of
+//! // "http://localhost:8080/v2"
+//! let client = RewriteService::new(client, "https://staging.example.com/api/v2".parse().unwrap())
+//!     .strip_prefix("/v2");
+//! # }
+//! ```
+
+use crate::Has;
+use hyper::service::Service;
+use hyper::{Request, Uri};
+
+/// Context entry overriding the base URI for a single request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BaseUri(pub Uri);
+
+/// Client middleware rewriting request URIs to use a different base URI.
+///
+/// Put it outside the middleware keyed on the host, such as
+/// `CircuitBreakerService`, `RateLimitService` and `CacheService`, so that
+/// they see the rewritten URI, and outside `RedirectService`, so that
+/// redirect locations aren't rewritten.
+#[derive(Debug, Clone)]
+pub struct RewriteService<T> {
+    inner: T,
+    base: Uri,
+    strip_prefix: String,
+}
+
+impl<T> RewriteService<T> {
+    /// Create a new RewriteService, sending requests to `base` by default.
+    ///
+    /// The scheme and authority of requests are replaced with those of
+    /// `base`, and the path of `base` is prepended to the request's path.
+    pub fn new(inner: T, base: Uri) -> Self {
+        RewriteService {
+            inner,
+            base,
+            strip_prefix: String::new(),
+        }
+    }
+
+    /// Remove a prefix - usually the base path the client was created with -
+    /// from request paths before prepending the new base path.
+    pub fn strip_prefix(mut self, prefix: &str) -> Self {
+        self.strip_prefix = prefix.trim_end_matches('/').to_string();
+        self
+    }
+}
+
+/// Rewrite a URI to be relative to `base`.
+fn rewrite(uri: &Uri, base: &Uri, strip_prefix: &str) -> Option<Uri> {
+    let path = uri.path();
+    let path = match path.strip_prefix(strip_prefix) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+        _ => path,
+    };
+
+    let mut path_and_query = base.path().trim_end_matches('/').to_string();
+    path_and_query.push_str(path);
+    if path_and_query.is_empty() {
+        path_and_query.push('/');
+    }
+    if let Some(query) = uri.query() {
+        path_and_query.push('?');
+        path_and_query.push_str(query);
+    }
+
+    let mut builder = Uri::builder().path_and_query(path_and_query);
+    if let Some(scheme) = base.scheme().or(uri.scheme()) {
+        builder = builder.scheme(scheme.clone());
+    }
+    if let Some(authority) = base.authority().or(uri.authority()) {
+        builder = builder.authority(authority.clone());
+    }
+    builder.build().ok()
+}
+
+impl<T, B, C> Service<(Request<B>, C)> for RewriteService<T>
+where
+    C: Has<Option<BaseUri>>,
+    T: Service<(Request<B>, C)>,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = T::Future;
+
+    fn call(&self, (mut req, context): (Request<B>, C)) -> Self::Future {
+        let base = match Has::<Option<BaseUri>>::get(&context) {
+            Some(BaseUri(base)) => base,
+            None => &self.base,
+        };
+
+        if let Some(uri) = rewrite(req.uri(), base, &self.strip_prefix) {
+            *req.uri_mut() = uri;
+        }
+
+        self.inner.call((req, context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Push;
+    use futures::future;
+    use std::convert::Infallible;
+
+    crate::new_context_type!(TestContext, TestEmptyContext, Option<BaseUri>);
+
+    type TestContextType = crate::make_context_ty!(TestContext, TestEmptyContext, Option<BaseUri>);
+
+    /// Service returning the URI of the request.
+    struct EchoUri;
+
+    impl Service<(Request<()>, TestContextType)> for EchoUri {
+        type Response = Uri;
+        type Error = Infallible;
+        type Future = future::Ready<Result<Uri, Infallible>>;
+
+        fn call(&self, (req, _): (Request<()>, TestContextType)) -> Self::Future {
+            future::ok(req.uri().clone())
+        }
+    }
+
+    fn request(uri: &str) -> Request<()> {
+        let mut request = Request::new(());
+        *request.uri_mut() = uri.parse().unwrap();
+        request
+    }
+
+    #[tokio::test]
+    async fn rewrites_uris() {
+        let service = RewriteService::new(EchoUri, "https://staging:8443/api/v2/".parse().unwrap())
+            .strip_prefix("/v2");
+
+        let uri = service
+            .call((
+                request("http://localhost/v2/pets?limit=10"),
+                TestEmptyContext.push(None),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(uri, "https://staging:8443/api/v2/pets?limit=10");
+
+        let tenant = BaseUri("http://tenant.example".parse().unwrap());
+        let uri = service
+            .call((
+                request("http://localhost/v2/pets"),
+                TestEmptyContext.push(Some(tenant)),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(uri, "http://tenant.example/pets");
+    }
+}
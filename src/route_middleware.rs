@@ -0,0 +1,339 @@
+//! Hyper service applying middleware to some routes of an API only.
+//!
+//! `RouteMiddlewareService` holds two copies of the API service: one wrapped
+//! in extra middleware (e.g. a stricter concurrency limit, or a different
+//! authenticator), and one without. Requests matching a `RouteSet` are passed
+//! to the wrapped copy, and all other requests bypass the extra middleware.
+//!
+//! ```rust
+//! # use hyper::Method;
+//! # use swagger::route_middleware::RouteSet;
+//! let routes = RouteSet::new()
+//!     .operation("uploadFile")
+//!     .path(Some(Method::POST), "/pets/{petId}/photos")
+//!     .path(None, "/admin/*");
+//!
+//! # struct Parser;
+//! # impl<B> swagger::RequestParser<B> for Parser {
+//! #     fn parse_operation_id(_: &hyper::Request<B>) -> Option<&'static str> { None }
+//! # }
+//! let req = hyper::Request::post("/pets/42/photos").body(()).unwrap();
+//! assert!(routes.matches::<Parser, _>(&req));
+//! ```
+
+use crate::RequestParser;
+use futures::future::{BoxFuture, Either, FutureExt};
+use hyper::service::Service;
+use hyper::{Method, Request};
+use std::collections::HashSet;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// A path pattern, optionally restricted to a single method.
+#[derive(Debug, Clone)]
+struct PathPattern {
+    method: Option<Method>,
+    segments: Vec<String>,
+}
+
+impl PathPattern {
+    fn matches(&self, method: &Method, path: &str) -> bool {
+        if self.method.as_ref().is_some_and(|m| m != method) {
+            return false;
+        }
+
+        let mut path_segments = path.trim_start_matches('/').split('/');
+        for segment in &self.segments {
+            if segment == "*" {
+                return true;
+            }
+            match path_segments.next() {
+                Some(s) if s == segment => {}
+                Some(s) if segment.starts_with('{') && segment.ends_with('}') && !s.is_empty() => {}
+                _ => return false,
+            }
+        }
+        path_segments.next().is_none()
+    }
+}
+
+/// Set of routes, identified by operation ID or path pattern.
+///
+/// Path patterns are matched segment by segment. A segment of the form
+/// `{name}` matches any non-empty segment, and a final `*` segment matches
+/// any remaining path.
+#[derive(Debug, Clone, Default)]
+pub struct RouteSet {
+    operations: HashSet<&'static str>,
+    paths: Vec<PathPattern>,
+}
+
+impl RouteSet {
+    /// Create an empty `RouteSet`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an operation ID, as returned by the API's `RequestParser`.
+    pub fn operation(mut self, operation_id: &'static str) -> Self {
+        self.operations.insert(operation_id);
+        self
+    }
+
+    /// Add a path pattern, matching only the given method if one is specified.
+    pub fn path(mut self, method: Option<Method>, pattern: &str) -> Self {
+        self.paths.push(PathPattern {
+            method,
+            segments: pattern
+                .trim_start_matches('/')
+                .split('/')
+                .map(ToString::to_string)
+                .collect(),
+        });
+        self
+    }
+
+    /// Check whether a request matches any route in this set.
+    pub fn matches<RP, B>(&self, req: &Request<B>) -> bool
+    where
+        RP: RequestParser<B>,
+    {
+        self.paths
+            .iter()
+            .any(|pattern| pattern.matches(req.method(), req.uri().path()))
+            || (!self.operations.is_empty()
+                && RP::parse_operation_id(req).is_some_and(|id| self.operations.contains(id)))
+    }
+}
+
+/// Middleware wrapper service which applies extra middleware to some routes.
+///
+/// For each connection, the service created by the inner `MakeService` is
+/// cloned and passed to `layer` to create the copy with extra middleware.
+pub struct RouteMiddlewareMakeService<T, F, RP> {
+    inner: T,
+    layer: Arc<F>,
+    routes: Arc<RouteSet>,
+    marker: PhantomData<fn(RP)>,
+}
+
+impl<T, F, RP> RouteMiddlewareMakeService<T, F, RP> {
+    /// Create a new RouteMiddlewareMakeService, using `layer` to add middleware
+    /// for requests matching `routes`.
+    pub fn new(inner: T, routes: RouteSet, layer: F) -> Self {
+        RouteMiddlewareMakeService {
+            inner,
+            layer: Arc::new(layer),
+            routes: Arc::new(routes),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, F, RP> fmt::Debug for RouteMiddlewareMakeService<T, F, RP>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RouteMiddlewareMakeService")
+            .field("inner", &self.inner)
+            .field("routes", &self.routes)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Inner, F, RP, Target, Wrapped> Service<Target> for RouteMiddlewareMakeService<Inner, F, RP>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+    Inner::Response: Clone,
+    F: Fn(Inner::Response) -> Wrapped + Send + Sync + 'static,
+{
+    type Response = RouteMiddlewareService<Inner::Response, Wrapped, RP>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let layer = self.layer.clone();
+        let routes = self.routes.clone();
+        Box::pin(self.inner.call(target).map(move |s| {
+            let bypass = s?;
+            let wrapped = layer(bypass.clone());
+            Ok(RouteMiddlewareService {
+                bypass,
+                wrapped,
+                routes,
+                marker: PhantomData,
+            })
+        }))
+    }
+}
+
+/// Middleware wrapper service which passes requests matching a `RouteSet` to
+/// a copy of the API wrapped in extra middleware, and all other requests
+/// directly to the API. Servers will normally want to use
+/// `RouteMiddlewareMakeService`, which will create a `RouteMiddlewareService`
+/// for each connection.
+pub struct RouteMiddlewareService<T, W, RP> {
+    bypass: T,
+    wrapped: W,
+    routes: Arc<RouteSet>,
+    marker: PhantomData<fn(RP)>,
+}
+
+impl<T, W, RP> RouteMiddlewareService<T, W, RP> {
+    /// Create a new RouteMiddlewareService, passing requests matching `routes`
+    /// to `wrapped`, and all others to `bypass`.
+    pub fn new(bypass: T, wrapped: W, routes: RouteSet) -> Self {
+        RouteMiddlewareService {
+            bypass,
+            wrapped,
+            routes: Arc::new(routes),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, W, RP> Clone for RouteMiddlewareService<T, W, RP>
+where
+    T: Clone,
+    W: Clone,
+{
+    fn clone(&self) -> Self {
+        RouteMiddlewareService {
+            bypass: self.bypass.clone(),
+            wrapped: self.wrapped.clone(),
+            routes: self.routes.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, W, RP> fmt::Debug for RouteMiddlewareService<T, W, RP>
+where
+    T: fmt::Debug,
+    W: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RouteMiddlewareService")
+            .field("bypass", &self.bypass)
+            .field("wrapped", &self.wrapped)
+            .field("routes", &self.routes)
+            .finish()
+    }
+}
+
+impl<T, W, RP, B, C> Service<(Request<B>, C)> for RouteMiddlewareService<T, W, RP>
+where
+    RP: RequestParser<B>,
+    T: Service<(Request<B>, C)>,
+    W: Service<(Request<B>, C), Response = T::Response, Error = T::Error>,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = Either<W::Future, T::Future>;
+
+    fn call(&self, req: (Request<B>, C)) -> Self::Future {
+        if self.routes.matches::<RP, B>(&req.0) {
+            Either::Left(self.wrapped.call(req))
+        } else {
+            Either::Right(self.bypass.call(req))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestParser;
+
+    impl<B> RequestParser<B> for TestParser {
+        fn parse_operation_id(req: &Request<B>) -> Option<&'static str> {
+            match req.uri().path() {
+                "/upload" => Some("upload"),
+                _ => None,
+            }
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestApi;
+
+    impl Service<(Request<()>, ())> for TestApi {
+        type Response = &'static str;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _req: (Request<()>, ())) -> Self::Future {
+            futures::future::ok("api")
+        }
+    }
+
+    #[derive(Clone)]
+    struct Tagged<T>(T);
+
+    impl<T> Service<(Request<()>, ())> for Tagged<T>
+    where
+        T: Service<(Request<()>, ()), Response = &'static str>,
+    {
+        type Response = &'static str;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _req: (Request<()>, ())) -> Self::Future {
+            futures::future::ok("middleware")
+        }
+    }
+
+    struct MakeTestApi;
+
+    impl<Target> Service<Target> for MakeTestApi {
+        type Response = TestApi;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _target: Target) -> Self::Future {
+            futures::future::ok(TestApi)
+        }
+    }
+
+    #[test]
+    fn matches_paths() {
+        let routes = RouteSet::new()
+            .path(Some(Method::POST), "/pets/{id}")
+            .path(None, "/admin/*");
+        let matches = |method: Method, path: &str| {
+            let req = Request::builder()
+                .method(method)
+                .uri(path)
+                .body(())
+                .unwrap();
+            routes.matches::<TestParser, _>(&req)
+        };
+
+        assert!(matches(Method::POST, "/pets/1"));
+        assert!(!matches(Method::GET, "/pets/1"));
+        assert!(!matches(Method::POST, "/pets/1/photos"));
+        assert!(!matches(Method::POST, "/pets/"));
+        assert!(matches(Method::GET, "/admin/users/1"));
+        assert!(!matches(Method::GET, "/administrator"));
+    }
+
+    #[tokio::test]
+    async fn applies_middleware_to_routes() {
+        let make_service: RouteMiddlewareMakeService<_, _, TestParser> =
+            RouteMiddlewareMakeService::new(
+                MakeTestApi,
+                RouteSet::new().operation("upload").path(None, "/admin/*"),
+                Tagged,
+            );
+        let service = make_service.call(()).await.unwrap();
+        let send = |path: &str| service.call((Request::get(path).body(()).unwrap(), ()));
+
+        assert_eq!(send("/upload").await, Ok("middleware"));
+        assert_eq!(send("/admin/users").await, Ok("middleware"));
+        assert_eq!(send("/pets").await, Ok("api"));
+    }
+}
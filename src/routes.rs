@@ -0,0 +1,176 @@
+//! Route tables, describing the paths of an API and the methods each one
+//! supports, for middleware which needs to know about routes the API
+//! doesn't handle itself - such as answering `OPTIONS` requests.
+//!
+//! Paths are OpenAPI path templates, in which `{name}` matches a single
+//! path segment. Where several templates match a path, the one with
+//! literal segments furthest to the left is used, so `/pets/mine` is
+//! preferred to `/pets/{id}`.
+//!
+//! ```rust
+//! # use hyper::Method;
+//! # use swagger::routes::Routes;
+//! let pets = Routes::new()
+//!     .route("/pets", [Method::GET, Method::POST])
+//!     .route("/pets/{petId}", [Method::GET, Method::DELETE]);
+//! let routes = Routes::new().mount("/v2", pets);
+//!
+//! assert_eq!(routes.allowed("/v2/pets/3"), Some(&[Method::GET, Method::DELETE][..]));
+//! assert_eq!(routes.allowed("/v2/owners"), None);
+//! ```
+
+use hyper::header::HeaderValue;
+use hyper::Method;
+
+/// An `Allow` header listing methods.
+pub(crate) fn allow_header<'a, I: IntoIterator<Item = &'a Method>>(methods: I) -> HeaderValue {
+    let methods: Vec<_> = methods.into_iter().map(Method::as_str).collect();
+    HeaderValue::from_str(&methods.join(", ")).expect("method names are valid header values")
+}
+
+/// A segment of a path template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Parameter(String),
+}
+
+/// A path template, and the methods it supports.
+#[derive(Debug, Clone)]
+struct Route {
+    template: String,
+    segments: Vec<Segment>,
+    methods: Vec<Method>,
+}
+
+impl Route {
+    fn new(template: String, methods: Vec<Method>) -> Self {
+        let segments = split(&template)
+            .map(
+                |segment| match segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                    Some(name) => Segment::Parameter(name.to_string()),
+                    None => Segment::Literal(segment.to_string()),
+                },
+            )
+            .collect();
+        Route {
+            template,
+            segments,
+            methods,
+        }
+    }
+
+    /// Whether the route matches a path, and if so which of its segments
+    /// are literals.
+    fn matches(&self, path: &str) -> Option<Vec<bool>> {
+        let mut segments = self.segments.iter();
+        let mut literals = Vec::with_capacity(self.segments.len());
+        for part in split(path) {
+            match segments.next()? {
+                Segment::Literal(literal) if literal == part => literals.push(true),
+                Segment::Parameter(_) if !part.is_empty() => literals.push(false),
+                _ => return None,
+            }
+        }
+        segments.next().is_none().then_some(literals)
+    }
+}
+
+/// Split a path into its segments, ignoring a trailing slash.
+fn split(path: &str) -> impl Iterator<Item = &str> {
+    let path = path.strip_prefix('/').unwrap_or(path);
+    let path = path.strip_suffix('/').unwrap_or(path);
+    (!path.is_empty())
+        .then(|| path.split('/'))
+        .into_iter()
+        .flatten()
+}
+
+/// A table of the paths of an API, and the methods each supports.
+#[derive(Debug, Clone, Default)]
+pub struct Routes {
+    routes: Vec<Route>,
+}
+
+impl Routes {
+    /// Create an empty route table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a path template and the methods it supports. Methods are added to
+    /// those of the template if it's already in the table.
+    pub fn route<I: IntoIterator<Item = Method>>(mut self, template: &str, methods: I) -> Self {
+        let template = format!("/{}", template.trim_matches('/'));
+        let route = match self.routes.iter_mut().find(|r| r.template == template) {
+            Some(route) => route,
+            None => {
+                self.routes.push(Route::new(template, Vec::new()));
+                self.routes.last_mut().expect("route was just added")
+            }
+        };
+        for method in methods {
+            if !route.methods.contains(&method) {
+                route.methods.push(method);
+            }
+        }
+        self
+    }
+
+    /// Add the routes of an API mounted at a base path, as with a
+    /// `CompositeMakeService`.
+    pub fn mount(self, base_path: &str, routes: Routes) -> Self {
+        let base_path = base_path.trim_end_matches('/');
+        routes.routes.into_iter().fold(self, |table, route| {
+            let template = format!("{}{}", base_path, route.template);
+            table.route(&template, route.methods)
+        })
+    }
+
+    /// Find the template matching a path, preferring literal segments.
+    fn find(&self, path: &str) -> Option<&Route> {
+        self.routes
+            .iter()
+            .filter_map(|route| route.matches(path).map(|literals| (literals, route)))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, route)| route)
+    }
+
+    /// The methods supported by a path, or `None` if it doesn't match any
+    /// route.
+    pub fn allowed(&self, path: &str) -> Option<&[Method]> {
+        self.find(path).map(|route| route.methods.as_slice())
+    }
+
+    /// The template matching a path, such as `/pets/{petId}`.
+    pub fn template(&self, path: &str) -> Option<&str> {
+        self.find(path).map(|route| route.template.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_templates() {
+        let routes = Routes::new()
+            .route("/pets/{id}", [Method::GET])
+            .route("pets/mine/", [Method::GET, Method::PUT])
+            .route("/pets/{id}", [Method::DELETE, Method::GET])
+            .route("/", [Method::GET])
+            .mount("/v1/", Routes::new().route("/{a}/{b}", [Method::POST]));
+
+        assert_eq!(
+            routes.allowed("/pets/3"),
+            Some(&[Method::GET, Method::DELETE][..])
+        );
+        assert_eq!(routes.template("/pets/mine"), Some("/pets/mine"));
+        assert_eq!(routes.template("/pets/mine/"), Some("/pets/mine"));
+        assert_eq!(routes.template("/"), Some("/"));
+        assert_eq!(routes.template("/v1/x/y"), Some("/v1/{a}/{b}"));
+        assert_eq!(routes.allowed("/pets"), None);
+        assert_eq!(routes.allowed("/pets//"), None);
+        assert_eq!(routes.allowed("/pets/3/4"), None);
+    }
+}
@@ -0,0 +1,128 @@
+//! Strings which must not be leaked, such as `format: password` values.
+use crate::redaction::REDACTED;
+use std::fmt;
+
+/// A secret string, such as a password.
+///
+/// Its `Debug` and `Display` implementations print `[REDACTED]`, and with the
+/// `zeroize` feature, its memory is zeroed when it's dropped. With the `serde`
+/// dependency, it can be deserialized, but doesn't implement `Serialize`, so
+/// that it can't end up in logged models by accident - models which send
+/// secrets must opt in with the `expose` helper:
+///
+/// ```
+/// # use serde::{Deserialize, Serialize};
+/// # use swagger::SecretString;
+/// #[derive(Debug, Deserialize, Serialize)]
+/// struct Login {
+///     username: String,
+///     #[serde(serialize_with = "swagger::secret::expose")]
+///     password: SecretString,
+/// }
+///
+/// let login: Login = serde_json::from_str(r#"{"username":"alice","password":"hunter2"}"#).unwrap();
+/// assert_eq!(login.password.expose(), "hunter2");
+/// assert_eq!(
+///     format!("{:?}", login),
+///     r#"Login { username: "alice", password: SecretString("[REDACTED]") }"#
+/// );
+/// ```
+#[derive(Clone, Default)]
+pub struct SecretString(String);
+
+impl SecretString {
+    /// Wrap a secret.
+    pub fn new(secret: String) -> Self {
+        SecretString(secret)
+    }
+
+    /// The secret value. Take care not to log it.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(secret: String) -> Self {
+        SecretString(secret)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(secret: &str) -> Self {
+        SecretString(secret.to_string())
+    }
+}
+
+impl std::str::FromStr for SecretString {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.into())
+    }
+}
+
+impl PartialEq for SecretString {
+    /// Compare in constant time for secrets of the same length, so that
+    /// comparisons don't reveal how much of a guess was right.
+    fn eq(&self, other: &Self) -> bool {
+        let (a, b) = (self.0.as_bytes(), other.0.as_bytes());
+        a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+}
+
+impl Eq for SecretString {}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SecretString").field(&REDACTED).finish()
+    }
+}
+
+impl fmt::Display for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.0);
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for SecretString {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(SecretString)
+    }
+}
+
+/// Serialize the value of a secret, for use with
+/// `#[serde(serialize_with = "swagger::secret::expose")]`.
+#[cfg(feature = "serde")]
+pub fn expose<S: serde::Serializer>(
+    secret: &SecretString,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(secret.expose())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_secrets() {
+        let secret = SecretString::from("hunter2");
+        assert_eq!(
+            format!("{} {:?}", secret, secret),
+            "[REDACTED] SecretString(\"[REDACTED]\")"
+        );
+        assert_eq!(secret.expose(), "hunter2");
+        assert_eq!(secret, SecretString::new("hunter2".to_string()));
+        assert_ne!(secret, SecretString::from("hunter3"));
+        assert_ne!(secret, SecretString::from("hunter"));
+    }
+}
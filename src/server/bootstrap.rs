@@ -0,0 +1,378 @@
+//! Running an API with a standard middleware stack in one call.
+//!
+//! [`serve`] binds a server to an address and serves an API `MakeService`
+//! wrapped in the middleware a generated server needs:
+//!
+//! - `AddContextMakeService`, adding the request's `X-Span-ID` to the context,
+//! - request logging, recording each request and its outcome with its span ID,
+//! - an [`Authenticator`], adding the `Option<Authorization>` for the request,
+//!
+//! so the API receives a [`ServeContext`]. APIs which take plain
+//! `hyper::Request`s can be wrapped in `DropContextMakeService`, or
+//! `DropContextWithHeadersMakeService` to keep the span ID.
+//!
+//! The server shuts down gracefully on Ctrl-C. [`ServeConfig`] holds the
+//! options, with defaults suitable for most services.
+//!
+//! ```no_run
+//! # async fn run<M, S, B>(api: M) -> std::io::Result<()>
+//! # where
+//! #     M: hyper::service::Service<std::net::SocketAddr, Response = S>,
+//! #     M::Future: Send + 'static,
+//! #     M::Future: Send + 'static,
+//! #     M::Error: Into<Box<dyn std::error::Error + Send + Sync>> + Send + 'static,
+//! #     S: hyper::service::Service<
+//! #         (hyper::Request<hyper::body::Incoming>, swagger::server::bootstrap::ServeContext),
+//! #         Response = hyper::Response<B>,
+//! #     > + Send + 'static,
+//! #     S::Future: Send + 'static,
+//! #     S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! #     B: hyper::body::Body + Send + 'static,
+//! #     B::Data: Send,
+//! #     B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! # {
+//! use swagger::server::bootstrap::{Authenticator, ServeConfig};
+//!
+//! let config = ServeConfig::new().authenticator(Authenticator::AllowAll("cosmo".into()));
+//! swagger::server::serve("127.0.0.1:8080".parse().unwrap(), api, config).await
+//! # }
+//! ```
+
+use super::Server;
+use crate::auth::Authorization;
+use crate::{AddContextMakeService, ContextBuilder, EmptyContext, Has, Push, XSpanIdString};
+use futures::future::{BoxFuture, FutureExt};
+use hyper::body::{Body, Incoming};
+use hyper::service::Service;
+use hyper::{HeaderMap, Request, Response};
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+
+/// The context passed to APIs served by [`serve`].
+pub type ServeContext =
+    ContextBuilder<Option<Authorization>, ContextBuilder<XSpanIdString, EmptyContext>>;
+
+/// Function authorizing a request from its headers.
+pub type AuthenticateFn = dyn Fn(&HeaderMap) -> Option<Authorization> + Send + Sync;
+
+/// How requests are authenticated.
+#[derive(Clone, Default)]
+pub enum Authenticator {
+    /// Requests aren't authenticated, so the context holds `None`.
+    #[default]
+    Anonymous,
+    /// All requests are authorized for all scopes with the given subject. Only
+    /// for use in development, or behind a proxy which has already checked
+    /// the client's credentials.
+    AllowAll(String),
+    /// Requests are authorized using a function of their headers, such as one
+    /// verifying the credentials from `auth::from_headers`.
+    Custom(Arc<AuthenticateFn>),
+}
+
+impl Authenticator {
+    /// Authorize requests using a function of their headers.
+    pub fn custom<F>(f: F) -> Self
+    where
+        F: Fn(&HeaderMap) -> Option<Authorization> + Send + Sync + 'static,
+    {
+        Authenticator::Custom(Arc::new(f))
+    }
+
+    fn authenticate(&self, headers: &HeaderMap) -> Option<Authorization> {
+        match self {
+            Authenticator::Anonymous => None,
+            Authenticator::AllowAll(subject) => Some(Authorization {
+                subject: subject.clone(),
+                scopes: crate::auth::Scopes::All,
+                issuer: None,
+            }),
+            Authenticator::Custom(f) => f(headers),
+        }
+    }
+}
+
+impl fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Authenticator::Anonymous => f.write_str("Anonymous"),
+            Authenticator::AllowAll(subject) => f.debug_tuple("AllowAll").field(subject).finish(),
+            Authenticator::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+/// Options for [`serve`].
+#[derive(Clone, Debug)]
+pub struct ServeConfig {
+    authenticator: Authenticator,
+    log_requests: bool,
+    header_read_timeout: Duration,
+    idle_timeout: Option<Duration>,
+    drain_timeout: Duration,
+}
+
+impl Default for ServeConfig {
+    fn default() -> Self {
+        ServeConfig {
+            authenticator: Authenticator::default(),
+            log_requests: true,
+            header_read_timeout: Duration::from_secs(30),
+            idle_timeout: None,
+            drain_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ServeConfig {
+    /// The default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how requests are authenticated.
+    ///
+    /// Defaults to [`Authenticator::Anonymous`].
+    pub fn authenticator(mut self, authenticator: Authenticator) -> Self {
+        self.authenticator = authenticator;
+        self
+    }
+
+    /// Set whether to log each request at info level.
+    ///
+    /// Defaults to `true`.
+    pub fn log_requests(mut self, log_requests: bool) -> Self {
+        self.log_requests = log_requests;
+        self
+    }
+
+    /// Set the maximum time a client may take to send the headers of an
+    /// HTTP/1 request. See `Server::header_read_timeout`.
+    ///
+    /// Defaults to 30 seconds.
+    pub fn header_read_timeout(mut self, timeout: Duration) -> Self {
+        self.header_read_timeout = timeout;
+        self
+    }
+
+    /// Close connections which have been idle for the given time. See
+    /// `Server::idle_timeout`.
+    ///
+    /// By default, idle connections are kept open.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the maximum time to wait for in-flight requests on shutdown. See
+    /// `Server::drain_timeout`.
+    ///
+    /// Defaults to 30 seconds.
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    fn configure(&self, server: Server<TcpListener>) -> Server<TcpListener> {
+        let server = server
+            .header_read_timeout(self.header_read_timeout)
+            .drain_timeout(self.drain_timeout);
+        match self.idle_timeout {
+            Some(idle_timeout) => server.idle_timeout(idle_timeout),
+            None => server,
+        }
+    }
+}
+
+/// Serve an API on the given address with the standard middleware stack until
+/// Ctrl-C is received.
+///
+/// See the [module documentation](self) for details.
+pub async fn serve<M, S, B>(addr: SocketAddr, api: M, config: ServeConfig) -> io::Result<()>
+where
+    M: Service<SocketAddr, Response = S>,
+    M::Future: Send + 'static,
+    M::Error: Into<Box<dyn StdError + Send + Sync>> + Send + 'static,
+    S: Service<(Request<Incoming>, ServeContext), Response = Response<B>> + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    let server = config.configure(Server::bind(addr).await?);
+    server.serve(make_service(api, config)).await
+}
+
+/// Wrap an API in the middleware stack.
+fn make_service<M>(
+    api: M,
+    config: ServeConfig,
+) -> AddContextMakeService<StackMakeService<M>, EmptyContext> {
+    AddContextMakeService::new(StackMakeService {
+        inner: api,
+        authenticator: config.authenticator,
+        log_requests: config.log_requests,
+    })
+}
+
+/// Middleware logging and authenticating requests. There is no need to use
+/// these as separate services, as [`serve`] always uses both.
+struct StackMakeService<T> {
+    inner: T,
+    authenticator: Authenticator,
+    log_requests: bool,
+}
+
+impl<Inner, Target> Service<Target> for StackMakeService<Inner>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = StackService<Inner::Response>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let authenticator = self.authenticator.clone();
+        let log_requests = self.log_requests;
+        Box::pin(self.inner.call(target).map(move |s| {
+            Ok(StackService {
+                inner: s?,
+                authenticator,
+                log_requests,
+            })
+        }))
+    }
+}
+
+struct StackService<T> {
+    inner: T,
+    authenticator: Authenticator,
+    log_requests: bool,
+}
+
+impl<T, B, C, ResBody> Service<(Request<B>, C)> for StackService<T>
+where
+    C: Has<XSpanIdString> + Push<Option<Authorization>>,
+    T: Service<(Request<B>, C::Result), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<B>, C)) -> Self::Future {
+        let authorization = self.authenticator.authenticate(req.headers());
+        if !self.log_requests {
+            return self.inner.call((req, context.push(authorization))).boxed();
+        }
+
+        let span_id = Has::<XSpanIdString>::get(&context).0.clone();
+        let method = req.method().clone();
+        let path = req.uri().path().to_string();
+        let start = Instant::now();
+        Box::pin(
+            self.inner
+                .call((req, context.push(authorization)))
+                .map(move |response| {
+                    let elapsed = start.elapsed();
+                    match &response {
+                        Ok(response) => log::info!(
+                            "{} {} {} - {} in {:?}",
+                            span_id,
+                            method,
+                            path,
+                            response.status(),
+                            elapsed
+                        ),
+                        Err(_) => {
+                            log::warn!("{} {} {} - failed in {:?}", span_id, method, path, elapsed)
+                        }
+                    }
+                    response
+                }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Empty, Full};
+    use hyper::body::Bytes;
+    use hyper_util::rt::TokioIo;
+    use std::convert::Infallible;
+    use tokio::net::TcpStream;
+
+    struct MakeTestApi;
+
+    impl Service<SocketAddr> for MakeTestApi {
+        type Response = TestApi;
+        type Error = Infallible;
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _target: SocketAddr) -> Self::Future {
+            futures::future::ok(TestApi)
+        }
+    }
+
+    struct TestApi;
+
+    impl Service<(Request<Incoming>, ServeContext)> for TestApi {
+        type Response = Response<Full<Bytes>>;
+        type Error = Infallible;
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (_, context): (Request<Incoming>, ServeContext)) -> Self::Future {
+            let span_id: &XSpanIdString = context.get();
+            let authorization: &Option<Authorization> = context.get();
+            let subject = authorization.as_ref().map_or("anonymous", |a| &a.subject);
+            futures::future::ok(Response::new(Full::from(format!(
+                "{} {}",
+                span_id.0, subject
+            ))))
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_api_with_context() {
+        let config = ServeConfig::new().authenticator(Authenticator::custom(|headers| {
+            headers.get("x-user").map(|user| Authorization {
+                subject: user.to_str().unwrap().to_string(),
+                scopes: crate::auth::Scopes::All,
+                issuer: None,
+            })
+        }));
+        let server = config.configure(Server::bind("127.0.0.1:0".parse().unwrap()).await.unwrap());
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.serve_with_shutdown(
+            make_service(MakeTestApi, config),
+            futures::future::pending(),
+        ));
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .unwrap();
+        tokio::spawn(connection);
+
+        for (user, expected) in [(None, "span anonymous"), (Some("cosmo"), "span cosmo")] {
+            let mut request = Request::get("/").header(crate::X_SPAN_ID, "span");
+            if let Some(user) = user {
+                request = request.header("x-user", user);
+            }
+            let response = sender
+                .send_request(request.body(Empty::<Bytes>::new()).unwrap())
+                .await
+                .unwrap();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, expected);
+        }
+    }
+}
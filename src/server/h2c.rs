@@ -0,0 +1,318 @@
+//! Upgrading HTTP/1.1 connections to HTTP/2 over cleartext, using the
+//! `Upgrade: h2c` mechanism from RFC 7540 section 3.2.
+//!
+//! The request carrying the upgrade is answered with `101 Switching
+//! Protocols`, and then served as stream 1 of the HTTP/2 connection which
+//! follows. hyper's HTTP/2 server has no way to start a connection with a
+//! stream already open, so the request is passed to it as a HEADERS frame,
+//! written into the connection just after the client's preface and SETTINGS
+//! frame.
+
+use base64::alphabet::URL_SAFE;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+use futures::future::{BoxFuture, FutureExt};
+use http_body_util::{Either, Empty};
+use hyper::body::{Body, Incoming};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, CONNECTION, CONTENT_LENGTH, UPGRADE};
+use hyper::http::request::Parts;
+use hyper::service::Service;
+use hyper::upgrade::OnUpgrade;
+use hyper::{Request, Response, StatusCode, Version};
+use std::error::Error as StdError;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The HTTP/2 connection preface sent by clients.
+const PREFACE_LEN: usize = 24;
+
+/// The length of an HTTP/2 frame header.
+const FRAME_HEADER_LEN: usize = 9;
+
+/// The largest frame payload every HTTP/2 server accepts.
+const MAX_FRAME_SIZE: usize = 16_384;
+
+const FRAME_HEADERS: u8 = 0x1;
+const FRAME_CONTINUATION: u8 = 0x9;
+const FLAG_END_STREAM: u8 = 0x1;
+const FLAG_END_HEADERS: u8 = 0x4;
+
+/// Decoder for the `HTTP2-Settings` header, which is base64url encoded with
+/// padding optional.
+const SETTINGS_ENGINE: GeneralPurpose = GeneralPurpose::new(
+    &URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Headers which are specific to an HTTP/1 connection, so aren't carried over
+/// to HTTP/2.
+const CONNECTION_HEADERS: [&str; 7] = [
+    "connection",
+    "host",
+    "http2-settings",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// An accepted upgrade, waiting for the HTTP/1 connection to hand over its I/O.
+pub(super) struct H2cUpgrade {
+    pub(super) on_upgrade: OnUpgrade,
+    /// The frames opening stream 1 with the upgraded request.
+    pub(super) frames: Vec<u8>,
+}
+
+/// Shared between the service accepting an upgrade and the task serving the
+/// connection.
+pub(super) type PendingUpgrade = Arc<Mutex<Option<H2cUpgrade>>>;
+
+/// Whether a header contains a token, case-insensitively, in any of its
+/// comma-separated values.
+fn has_token(headers: &HeaderMap, name: HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(token))
+}
+
+/// Whether a request asks to be upgraded to h2c, and can be. Requests with a
+/// body aren't upgraded, and are answered over HTTP/1.1 instead, as RFC 7540
+/// allows.
+fn is_upgrade(req: &Request<Incoming>) -> bool {
+    let headers = req.headers();
+    let mut settings = headers.get_all("http2-settings").iter();
+    let settings_valid = match (settings.next(), settings.next()) {
+        // Each setting is a 16-bit identifier and a 32-bit value
+        (Some(value), None) => SETTINGS_ENGINE
+            .decode(value.as_bytes())
+            .is_ok_and(|settings| settings.len() % 6 == 0),
+        _ => false,
+    };
+    req.version() == Version::HTTP_11
+        && has_token(headers, UPGRADE, "h2c")
+        && has_token(headers, CONNECTION, "upgrade")
+        && has_token(headers, CONNECTION, "http2-settings")
+        && settings_valid
+        && !headers.contains_key(hyper::header::TRANSFER_ENCODING)
+        && headers
+            .get(CONTENT_LENGTH)
+            .is_none_or(|length| length.as_bytes() == b"0")
+}
+
+/// Append an HPACK integer with an `n` bit prefix.
+fn encode_integer(out: &mut Vec<u8>, flags: u8, n: u32, mut value: usize) {
+    let max = (1 << n) - 1;
+    if value < max {
+        out.push(flags | value as u8);
+        return;
+    }
+    out.push(flags | max as u8);
+    value -= max;
+    while value >= 128 {
+        out.push((value % 128) as u8 | 0x80);
+        value /= 128;
+    }
+    out.push(value as u8);
+}
+
+/// Append a header field as an HPACK literal which isn't indexed, so leaves
+/// the decoder's dynamic table untouched for the client's own header blocks.
+fn encode_header(out: &mut Vec<u8>, name: &[u8], value: &[u8]) {
+    out.push(0x00);
+    encode_integer(out, 0x00, 7, name.len());
+    out.extend_from_slice(name);
+    encode_integer(out, 0x00, 7, value.len());
+    out.extend_from_slice(value);
+}
+
+/// The HEADERS and CONTINUATION frames opening stream 1 with a request.
+fn request_frames(parts: &Parts) -> Vec<u8> {
+    let path = parts
+        .uri
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+    let mut block = Vec::new();
+    encode_header(&mut block, b":method", parts.method.as_str().as_bytes());
+    encode_header(&mut block, b":scheme", b"http");
+    encode_header(&mut block, b":path", path.as_bytes());
+    if let Some(host) = parts.headers.get(hyper::header::HOST) {
+        encode_header(&mut block, b":authority", host.as_bytes());
+    }
+    for (name, value) in &parts.headers {
+        if CONNECTION_HEADERS.contains(&name.as_str())
+            || (name == hyper::header::TE && value != "trailers")
+        {
+            continue;
+        }
+        encode_header(&mut block, name.as_str().as_bytes(), value.as_bytes());
+    }
+
+    let mut frames = Vec::with_capacity(block.len() + FRAME_HEADER_LEN);
+    let mut chunks = block.chunks(MAX_FRAME_SIZE).peekable();
+    let mut frame_type = FRAME_HEADERS;
+    let mut flags = FLAG_END_STREAM;
+    while let Some(chunk) = chunks.next() {
+        if chunks.peek().is_none() {
+            flags |= FLAG_END_HEADERS;
+        }
+        frames.extend_from_slice(&(chunk.len() as u32).to_be_bytes()[1..]);
+        frames.push(frame_type);
+        frames.push(flags);
+        frames.extend_from_slice(&1u32.to_be_bytes());
+        frames.extend_from_slice(chunk);
+        frame_type = FRAME_CONTINUATION;
+        flags = 0;
+    }
+    frames
+}
+
+/// Service accepting `Upgrade: h2c` requests, passing all others on to the
+/// inner service.
+pub(super) struct H2cService<S> {
+    pub(super) inner: S,
+    /// Where to leave accepted upgrades, if upgrades are enabled.
+    pub(super) pending: Option<PendingUpgrade>,
+}
+
+impl<S, B> Service<Request<Incoming>> for H2cService<S>
+where
+    S: Service<Request<Incoming>, Response = Response<B>>,
+    S::Future: Send + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    B: Body + Send + 'static,
+    B::Data: Send,
+{
+    type Response = Response<Either<B, Empty<B::Data>>>;
+    type Error = Box<dyn StdError + Send + Sync>;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, mut req: Request<Incoming>) -> Self::Future {
+        let Some(pending) = self.pending.as_ref().filter(|_| is_upgrade(&req)) else {
+            return Box::pin(
+                self.inner
+                    .call(req)
+                    .map(|response| Ok(response.map_err(Into::into)?.map(Either::Left))),
+            );
+        };
+
+        let on_upgrade = hyper::upgrade::on(&mut req);
+        let (parts, _) = req.into_parts();
+        *pending.lock().unwrap() = Some(H2cUpgrade {
+            on_upgrade,
+            frames: request_frames(&parts),
+        });
+
+        let mut response = Response::new(Either::Right(Empty::new()));
+        *response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+        let headers = response.headers_mut();
+        headers.insert(CONNECTION, HeaderValue::from_static("Upgrade"));
+        headers.insert(UPGRADE, HeaderValue::from_static("h2c"));
+        Box::pin(futures::future::ok(response))
+    }
+}
+
+/// Upgraded I/O, which inserts the frames opening stream 1 after the client's
+/// connection preface and first frame, which must be SETTINGS.
+pub(super) struct H2cIo<T> {
+    inner: T,
+    /// The preface and first frame header read so far.
+    prelude: Vec<u8>,
+    /// Bytes left to read before the frames are inserted.
+    remaining: usize,
+    frames: Vec<u8>,
+    written: usize,
+}
+
+impl<T> H2cIo<T> {
+    pub(super) fn new(inner: T, frames: Vec<u8>) -> Self {
+        H2cIo {
+            inner,
+            prelude: Vec::with_capacity(PREFACE_LEN + FRAME_HEADER_LEN),
+            remaining: PREFACE_LEN + FRAME_HEADER_LEN,
+            frames,
+            written: 0,
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for H2cIo<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if this.remaining == 0 && this.written < this.frames.len() {
+            let frames = &this.frames[this.written..];
+            let len = frames.len().min(buf.remaining());
+            buf.put_slice(&frames[..len]);
+            this.written += len;
+            return Poll::Ready(Ok(()));
+        }
+        if this.remaining == 0 {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+
+        // Read no further than the end of the first frame, to know where to
+        // insert the frames
+        let limit = this.remaining.min(buf.remaining());
+        let mut prelude = ReadBuf::new(buf.initialize_unfilled_to(limit));
+        futures::ready!(Pin::new(&mut this.inner).poll_read(cx, &mut prelude))?;
+        let read = prelude.filled().len();
+        if read == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        let header_len = PREFACE_LEN + FRAME_HEADER_LEN;
+        if this.prelude.len() < header_len {
+            let take = read.min(header_len - this.prelude.len());
+            this.prelude.extend_from_slice(&prelude.filled()[..take]);
+            if this.prelude.len() == header_len {
+                let length = &this.prelude[PREFACE_LEN..PREFACE_LEN + 3];
+                let length = u32::from_be_bytes([0, length[0], length[1], length[2]]);
+                this.remaining += length as usize;
+            }
+        }
+        this.remaining -= read;
+        buf.advance(read);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for H2cIo<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
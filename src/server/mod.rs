@@ -0,0 +1,1018 @@
+//! Helpers for running a hyper server.
+//!
+//! The [`Server`] wrapper accepts connections from a [`Listener`], serves each
+//! connection with the service produced by a `MakeService`, and on shutdown
+//! stops accepting new connections before draining the in-flight ones.
+//!
+//! Each connection is served using HTTP/1 or HTTP/2, detected from what the
+//! client sends, so HTTP/2 over cleartext (h2c) is supported for clients with
+//! prior knowledge, such as gRPC clients and service mesh sidecars. Older
+//! clients using the HTTP/1.1 `Upgrade: h2c` mechanism, deprecated by RFC
+//! 9113, can be upgraded to HTTP/2 with [`Server::h2c_upgrade`]. HTTP/2
+//! connections can be tuned with the `http2_*` methods.
+//!
+//! The number of connections, and how long each is kept open, can be limited
+//! with [`Server::max_connections`], [`Server::header_read_timeout`],
+//! [`Server::idle_timeout`] and [`Server::max_requests_per_connection`].
+//!
+//! With the `rustls` or `tls` features, connections can be served over TLS
+//! using the `tls` module.
+//!
+//! With the `uds` feature, connections can be served on Unix domain sockets
+//! using the `unix` module.
+//!
+//! Behind TCP load balancers, the clients' addresses can be read from PROXY
+//! protocol headers using the `proxy_protocol` module.
+//!
+//! On Unix, connections can be served on sockets passed by systemd socket
+//! activation using the `systemd` module.
+//!
+//! To serve a generated API with the standard middleware stack in one call,
+//! see [`serve`].
+//!
+//! ```no_run
+//! # async fn run<M, S, B>(make_service: M) -> std::io::Result<()>
+//! # where
+//! #     M: hyper::service::Service<std::net::SocketAddr, Response = S>,
+//! #     M::Future: Send + 'static,
+//! #     M::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! #     S: hyper::service::Service<hyper::Request<hyper::body::Incoming>, Response = hyper::Response<B>> + Send + 'static,
+//! #     S::Future: Send + 'static,
+//! #     S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! #     B: hyper::body::Body + Send + 'static,
+//! #     B::Data: Send,
+//! #     B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! # {
+//! use std::time::Duration;
+//! use swagger::server::Server;
+//!
+//! let server = Server::bind("127.0.0.1:8080".parse().unwrap())
+//!     .await?
+//!     .drain_timeout(Duration::from_secs(30));
+//!
+//! // Cancelled once the server starts shutting down.
+//! let shutdown = server.shutdown_token();
+//!
+//! // Serve until Ctrl-C is received.
+//! server.serve(make_service).await
+//! # }
+//! ```
+pub mod bootstrap;
+mod h2c;
+pub mod proxy_protocol;
+#[cfg(unix)]
+pub mod systemd;
+#[cfg(any(
+    feature = "rustls",
+    all(
+        feature = "tls",
+        not(any(target_os = "macos", target_os = "windows", target_os = "ios"))
+    )
+))]
+pub mod tls;
+#[cfg(all(feature = "uds", unix))]
+pub mod unix;
+
+pub use bootstrap::serve;
+
+use h2c::{H2cIo, H2cService, PendingUpgrade};
+
+use futures::future::BoxFuture;
+use http_body_util::Empty;
+use hyper::body::{Body, Bytes, Incoming};
+use hyper::header::{HeaderValue, CONNECTION};
+use hyper::service::Service;
+use hyper::{Request, Response, StatusCode, Version};
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use std::convert::Infallible;
+use std::error::Error as StdError;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+const LOG_TARGET: &str = "swagger::server";
+
+/// Time to wait before accepting again after a failure to accept a connection,
+/// to avoid spinning when out of file descriptors.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Number of connections over the maximum which are answered with `503
+/// Service Unavailable` at once. Any more are closed as soon as they're
+/// accepted.
+const OVERLOADED_CONNECTIONS: usize = 16;
+
+/// Time after which connections over the maximum are closed, whether or not
+/// they've been answered.
+const OVERLOADED_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Source of incoming connections for a [`Server`].
+pub trait Listener: Send {
+    /// Connection I/O type.
+    type Io: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    /// Information about the connection, passed as the target to the `MakeService`.
+    type Target: Send;
+
+    /// Accept the next connection.
+    fn accept(&mut self) -> BoxFuture<'_, io::Result<(Self::Io, Self::Target)>>;
+
+    /// The address of the other end of a connection, if it has one, for
+    /// logging.
+    fn peer_addr(_target: &Self::Target) -> Option<SocketAddr> {
+        None
+    }
+}
+
+impl Listener for TcpListener {
+    type Io = TcpStream;
+    type Target = SocketAddr;
+
+    fn accept(&mut self) -> BoxFuture<'_, io::Result<(Self::Io, Self::Target)>> {
+        Box::pin(TcpListener::accept(self))
+    }
+
+    fn peer_addr(target: &SocketAddr) -> Option<SocketAddr> {
+        Some(*target)
+    }
+}
+
+/// Server serving connections from a [`Listener`] with graceful shutdown.
+#[derive(Debug)]
+pub struct Server<L> {
+    listener: L,
+    builder: auto::Builder<TokioExecutor>,
+    drain_timeout: Option<Duration>,
+    max_connections: Option<usize>,
+    limits: ConnectionLimits,
+    h2c_upgrade: bool,
+    shutdown: CancellationToken,
+}
+
+impl Server<TcpListener> {
+    /// Bind a TCP listener to the given address and create a server for it.
+    pub async fn bind(addr: SocketAddr) -> io::Result<Self> {
+        Ok(Self::new(TcpListener::bind(addr).await?))
+    }
+
+    /// The local address that this server is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+impl<L> Server<L>
+where
+    L: Listener,
+{
+    /// Create a server accepting connections from the given listener.
+    pub fn new(listener: L) -> Self {
+        Server {
+            listener,
+            builder: auto::Builder::new(TokioExecutor::new()),
+            drain_timeout: None,
+            max_connections: None,
+            limits: ConnectionLimits::default(),
+            h2c_upgrade: false,
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    /// Set the maximum time to wait for in-flight connections to complete once
+    /// shutdown has started. Connections still open after this time are dropped.
+    ///
+    /// By default, the server waits indefinitely.
+    pub fn drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.drain_timeout = Some(drain_timeout);
+        self
+    }
+
+    /// Set the maximum number of connections to serve at once. Connections
+    /// stop counting towards the maximum once they start closing.
+    ///
+    /// A few further connections at a time are still accepted, and their
+    /// first request answered with `503 Service Unavailable` before the
+    /// connection is closed. Beyond those, connections are closed as soon as
+    /// they're accepted. By default, the number of connections isn't limited.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Set the maximum time a client may take to send the headers of an
+    /// HTTP/1 request, after which the connection is closed.
+    ///
+    /// By default, clients may take any amount of time.
+    pub fn header_read_timeout(mut self, timeout: Duration) -> Self {
+        self.builder
+            .http1()
+            .timer(TokioTimer::new())
+            .header_read_timeout(timeout);
+        self
+    }
+
+    /// Close connections which have had no requests in flight for the given
+    /// time.
+    ///
+    /// By default, idle connections are kept open until the client closes
+    /// them.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.limits.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Close connections once they have served the given number of requests.
+    ///
+    /// HTTP/1 connections are closed after the last response, which is sent
+    /// with `Connection: close`. HTTP/2 clients are sent a GOAWAY frame, so
+    /// that they open a new connection for further requests. By default, the
+    /// number of requests isn't limited.
+    pub fn max_requests_per_connection(mut self, max: usize) -> Self {
+        self.limits.max_requests = Some(max);
+        self
+    }
+
+    /// Only serve HTTP/1 connections.
+    pub fn http1_only(mut self) -> Self {
+        self.builder = self.builder.http1_only();
+        self
+    }
+
+    /// Only serve HTTP/2 connections, including HTTP/2 over cleartext with
+    /// prior knowledge.
+    pub fn http2_only(mut self) -> Self {
+        self.builder = self.builder.http2_only();
+        self
+    }
+
+    /// Upgrade HTTP/1.1 connections to HTTP/2 when the client asks to with
+    /// `Upgrade: h2c` and `HTTP2-Settings` headers, as described by RFC 7540.
+    ///
+    /// The request carrying the upgrade is answered over HTTP/2, unless it has
+    /// a body, in which case it's answered over HTTP/1.1 and the connection
+    /// isn't upgraded. This has no effect if the server only serves HTTP/1.
+    /// By default, connections aren't upgraded.
+    pub fn h2c_upgrade(mut self, enabled: bool) -> Self {
+        self.h2c_upgrade = enabled;
+        self
+    }
+
+    /// Set the maximum number of concurrent streams a client may open on an
+    /// HTTP/2 connection.
+    ///
+    /// Defaults to 200.
+    pub fn http2_max_concurrent_streams(mut self, max: u32) -> Self {
+        self.builder.http2().max_concurrent_streams(max);
+        self
+    }
+
+    /// Set the initial flow control window size of HTTP/2 streams, in bytes.
+    ///
+    /// Defaults to 1MiB.
+    pub fn http2_initial_stream_window_size(mut self, size: u32) -> Self {
+        self.builder.http2().initial_stream_window_size(size);
+        self
+    }
+
+    /// Set the initial flow control window size of HTTP/2 connections, in
+    /// bytes.
+    ///
+    /// Defaults to 1MiB.
+    pub fn http2_initial_connection_window_size(mut self, size: u32) -> Self {
+        self.builder.http2().initial_connection_window_size(size);
+        self
+    }
+
+    /// Size HTTP/2 flow control windows using the connection's estimated
+    /// bandwidth-delay product, overriding the initial window sizes.
+    pub fn http2_adaptive_window(mut self, enabled: bool) -> Self {
+        self.builder.http2().adaptive_window(enabled);
+        self
+    }
+
+    /// Send HTTP/2 pings at the given interval on connections which are
+    /// otherwise idle, and close connections which don't acknowledge a ping
+    /// within the timeout.
+    ///
+    /// By default, pings aren't sent.
+    pub fn http2_keep_alive(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.builder
+            .http2()
+            .timer(TokioTimer::new())
+            .keep_alive_interval(interval)
+            .keep_alive_timeout(timeout);
+        self
+    }
+
+    /// Replace the server's listener, keeping its configuration.
+    fn map_listener<M: Listener>(self, f: impl FnOnce(L) -> M) -> Server<M> {
+        Server {
+            listener: f(self.listener),
+            builder: self.builder,
+            drain_timeout: self.drain_timeout,
+            max_connections: self.max_connections,
+            limits: self.limits,
+            h2c_upgrade: self.h2c_upgrade,
+            shutdown: self.shutdown,
+        }
+    }
+
+    /// Token which is cancelled when the server starts shutting down.
+    ///
+    /// This can be stored in request contexts, allowing long-running operations
+    /// to finish early when the server is stopping.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Serve connections until Ctrl-C is received, then shut down gracefully.
+    pub async fn serve<M, S, B>(self, make_service: M) -> io::Result<()>
+    where
+        M: Service<L::Target, Response = S>,
+        M::Future: Send + 'static,
+        M::Error: Into<Box<dyn StdError + Send + Sync>>,
+        S: Service<Request<Incoming>, Response = Response<B>> + Send + 'static,
+        S::Future: Send + 'static,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        B: Body + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    {
+        self.serve_with_shutdown(make_service, async {
+            // If we can't listen for Ctrl-C, run until the process is killed.
+            if tokio::signal::ctrl_c().await.is_err() {
+                futures::future::pending::<()>().await;
+            }
+        })
+        .await
+    }
+
+    /// Serve connections until the `signal` future completes, then shut down
+    /// gracefully.
+    ///
+    /// On shutdown, the server stops accepting connections, cancels the
+    /// [shutdown token](Self::shutdown_token), and asks each open connection to
+    /// close once its in-flight requests have completed.
+    pub async fn serve_with_shutdown<M, S, B, F>(self, make_service: M, signal: F) -> io::Result<()>
+    where
+        M: Service<L::Target, Response = S>,
+        M::Future: Send + 'static,
+        M::Error: Into<Box<dyn StdError + Send + Sync>>,
+        S: Service<Request<Incoming>, Response = Response<B>> + Send + 'static,
+        S::Future: Send + 'static,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        B: Body + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn StdError + Send + Sync>>,
+        F: Future<Output = ()>,
+    {
+        let Server {
+            mut listener,
+            builder,
+            drain_timeout,
+            max_connections,
+            limits,
+            h2c_upgrade,
+            shutdown,
+        } = self;
+        let h2c_upgrade = h2c_upgrade && builder.is_http2_available();
+        let builder = Arc::new(builder);
+        let slots = max_connections.map(|max| Arc::new(Semaphore::new(max)));
+        let overloaded = Arc::new(Semaphore::new(OVERLOADED_CONNECTIONS));
+        let mut connections = JoinSet::new();
+        tokio::pin!(signal);
+
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let (io, target) = match accepted {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            log::warn!(target: LOG_TARGET, "Failed to accept connection: {}", e);
+                            tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                            continue;
+                        }
+                    };
+
+                    let slot = match &slots {
+                        Some(slots) => match slots.clone().try_acquire_owned() {
+                            Ok(slot) => Some(slot),
+                            Err(_) => {
+                                // Over the maximum - answer with 503 if there's
+                                // room, and otherwise just close the connection.
+                                let Ok(slot) = overloaded.clone().try_acquire_owned() else {
+                                    continue;
+                                };
+                                let limits = ConnectionLimits {
+                                    max_requests: Some(1),
+                                    ..limits
+                                };
+                                let connection = serve_connection(
+                                    builder.clone(),
+                                    io,
+                                    Overloaded,
+                                    limits,
+                                    Some(slot),
+                                    false,
+                                    shutdown.clone(),
+                                );
+                                connections.spawn(async move {
+                                    let _ = tokio::time::timeout(OVERLOADED_TIMEOUT, connection).await;
+                                });
+                                continue;
+                            }
+                        },
+                        None => None,
+                    };
+
+                    // The service is created in the connection's task, so that a
+                    // slow MakeService doesn't hold up accepting connections.
+                    let peer_addr = L::peer_addr(&target);
+                    let service = make_service.call(target);
+                    let builder = builder.clone();
+                    let shutdown = shutdown.clone();
+                    connections.spawn(async move {
+                        // If we can't create a service for this connection, drop it.
+                        let service = match service.await {
+                            Ok(service) => service,
+                            Err(e) => {
+                                let e = e.into();
+                                match peer_addr {
+                                    Some(peer_addr) => log::warn!(
+                                        target: LOG_TARGET,
+                                        "Failed to create service for connection from {}: {}",
+                                        peer_addr,
+                                        e
+                                    ),
+                                    None => log::warn!(
+                                        target: LOG_TARGET,
+                                        "Failed to create service for connection: {}",
+                                        e
+                                    ),
+                                }
+                                return;
+                            }
+                        };
+                        serve_connection(builder, io, service, limits, slot, h2c_upgrade, shutdown)
+                            .await;
+                    });
+                }
+                Some(_) = connections.join_next(), if !connections.is_empty() => {}
+                _ = &mut signal => break,
+            }
+        }
+
+        drop(listener);
+        shutdown.cancel();
+
+        // Connections close gracefully once the shutdown token is cancelled.
+        if let Some(drain_timeout) = drain_timeout {
+            let drain = async { while connections.join_next().await.is_some() {} };
+            if tokio::time::timeout(drain_timeout, drain).await.is_err() {
+                connections.abort_all();
+            }
+        }
+
+        while connections.join_next().await.is_some() {}
+
+        Ok(())
+    }
+}
+
+/// Limits applied to each connection.
+#[derive(Debug, Clone, Copy, Default)]
+struct ConnectionLimits {
+    idle_timeout: Option<Duration>,
+    max_requests: Option<usize>,
+}
+
+/// Serve a connection until it closes, closing it gracefully once it has been
+/// idle for too long, has served its maximum number of requests, or the server
+/// is shutting down.
+///
+/// If `h2c_upgrade` is set, HTTP/1.1 connections upgraded to HTTP/2 go on to
+/// be served in the same task, with the same service and limits. The
+/// connection's `slot`, if any, is released as soon as it starts closing.
+fn serve_connection<I, S, B>(
+    builder: Arc<auto::Builder<TokioExecutor>>,
+    io: I,
+    service: S,
+    limits: ConnectionLimits,
+    slot: Option<OwnedSemaphorePermit>,
+    h2c_upgrade: bool,
+    shutdown: CancellationToken,
+) -> impl Future<Output = ()> + Send + 'static
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Service<Request<Incoming>, Response = Response<B>> + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    let activity = Arc::new(Activity::new(limits.max_requests, slot));
+    let service = SharedService(Arc::new(Mutex::new(service)));
+    let pending = h2c_upgrade.then(PendingUpgrade::default);
+
+    async move {
+        let connection = builder.serve_connection_with_upgrades(
+            TokioIo::new(io),
+            H2cService {
+                inner: TrackedService {
+                    inner: service.clone(),
+                    activity: activity.clone(),
+                },
+                pending: pending.clone(),
+            },
+        );
+        tokio::pin!(connection);
+        let closing = drive(
+            connection.as_mut(),
+            |connection| connection.graceful_shutdown(),
+            &activity,
+            limits,
+            &shutdown,
+            false,
+        )
+        .await;
+
+        let Some(upgrade) = pending.and_then(|pending| pending.lock().unwrap().take()) else {
+            return;
+        };
+        let Ok(upgraded) = upgrade.on_upgrade.await else {
+            return;
+        };
+        let connection = builder.serve_connection(
+            TokioIo::new(H2cIo::new(TokioIo::new(upgraded), upgrade.frames)),
+            TrackedService {
+                inner: service,
+                activity: activity.clone(),
+            },
+        );
+        tokio::pin!(connection);
+        drive(
+            connection.as_mut(),
+            |connection| connection.graceful_shutdown(),
+            &activity,
+            limits,
+            &shutdown,
+            closing,
+        )
+        .await;
+    }
+}
+
+/// Poll a connection until it completes, closing it gracefully as described
+/// for [`serve_connection`]. Returns whether the connection was closing.
+async fn drive<C: Future>(
+    mut connection: Pin<&mut C>,
+    graceful_shutdown: impl Fn(Pin<&mut C>),
+    activity: &Activity,
+    limits: ConnectionLimits,
+    shutdown: &CancellationToken,
+    mut closing: bool,
+) -> bool {
+    if closing {
+        graceful_shutdown(connection.as_mut());
+    }
+    let idle = tokio::time::sleep(limits.idle_timeout.unwrap_or_default());
+    tokio::pin!(idle);
+
+    loop {
+        tokio::select! {
+            _ = connection.as_mut() => return closing,
+            _ = shutdown.cancelled(), if !closing => closing = true,
+            _ = activity.exhausted.notified(), if !closing => closing = true,
+            _ = idle.as_mut(), if !closing && limits.idle_timeout.is_some() => {
+                let timeout = limits.idle_timeout.unwrap_or_default();
+                match activity.idle_since() {
+                    Some(since) if since + timeout <= Instant::now() => closing = true,
+                    Some(since) => idle.as_mut().reset(since + timeout),
+                    None => idle.as_mut().reset(Instant::now() + timeout),
+                }
+            }
+        }
+        if closing {
+            activity.release_slot();
+            graceful_shutdown(connection.as_mut());
+        }
+    }
+}
+
+/// Service shared by the HTTP/1 and HTTP/2 sides of an upgraded connection.
+struct SharedService<S>(Arc<Mutex<S>>);
+
+impl<S> Clone for SharedService<S> {
+    fn clone(&self) -> Self {
+        SharedService(self.0.clone())
+    }
+}
+
+impl<S, R> Service<R> for SharedService<S>
+where
+    S: Service<R>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&self, req: R) -> Self::Future {
+        self.0.lock().unwrap().call(req)
+    }
+}
+
+/// Requests made on a connection, shared between the connection's service and
+/// the task serving it.
+#[derive(Debug)]
+struct Activity {
+    max_requests: Option<usize>,
+    requests: AtomicUsize,
+    in_flight: AtomicUsize,
+    last_active: Mutex<Instant>,
+    /// Notified when the connection has received its maximum number of
+    /// requests.
+    exhausted: Notify,
+    /// The connection's place in the maximum number of connections.
+    slot: Mutex<Option<OwnedSemaphorePermit>>,
+}
+
+impl Activity {
+    fn new(max_requests: Option<usize>, slot: Option<OwnedSemaphorePermit>) -> Self {
+        Activity {
+            max_requests,
+            requests: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+            last_active: Mutex::new(Instant::now()),
+            exhausted: Notify::new(),
+            slot: Mutex::new(slot),
+        }
+    }
+
+    /// Stop counting the connection towards the maximum, as it's closing.
+    fn release_slot(&self) {
+        self.slot.lock().unwrap().take();
+    }
+
+    /// When the last request completed, if none are in flight.
+    fn idle_since(&self) -> Option<Instant> {
+        let last_active = self.last_active.lock().unwrap();
+        if self.in_flight.load(Ordering::SeqCst) > 0 {
+            return None;
+        }
+        Some(*last_active)
+    }
+}
+
+/// Service recording the [`Activity`] on a connection.
+struct TrackedService<S> {
+    inner: S,
+    activity: Arc<Activity>,
+}
+
+impl<S, B> Service<Request<Incoming>> for TrackedService<S>
+where
+    S: Service<Request<Incoming>, Response = Response<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = TrackedFuture<S::Future>;
+
+    fn call(&self, req: Request<Incoming>) -> Self::Future {
+        let activity = &self.activity;
+        activity.in_flight.fetch_add(1, Ordering::SeqCst);
+        let requests = activity.requests.fetch_add(1, Ordering::SeqCst) + 1;
+        let last = activity.max_requests == Some(requests);
+        if last {
+            // Released before the response is sent, so that clients can open
+            // a new connection as soon as they see this one close.
+            activity.release_slot();
+            activity.exhausted.notify_one();
+        }
+
+        TrackedFuture {
+            // HTTP/2 connections are closed with GOAWAY instead.
+            close: last && req.version() <= Version::HTTP_11,
+            inner: Box::pin(self.inner.call(req)),
+            activity: activity.clone(),
+        }
+    }
+}
+
+/// Response future of a [`TrackedService`], which counts as in flight until
+/// it is dropped.
+struct TrackedFuture<F> {
+    inner: Pin<Box<F>>,
+    activity: Arc<Activity>,
+    /// Whether to close the HTTP/1 connection after the response.
+    close: bool,
+}
+
+impl<F, B, E> Future for TrackedFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let close = self.close;
+        self.inner.as_mut().poll(cx).map_ok(|mut response| {
+            if close {
+                response
+                    .headers_mut()
+                    .insert(CONNECTION, HeaderValue::from_static("close"));
+            }
+            response
+        })
+    }
+}
+
+impl<F> Drop for TrackedFuture<F> {
+    fn drop(&mut self) {
+        let mut last_active = self.activity.last_active.lock().unwrap();
+        *last_active = Instant::now();
+        self.activity.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Service answering requests on connections over the maximum.
+struct Overloaded;
+
+impl Service<Request<Incoming>> for Overloaded {
+    type Response = Response<Empty<Bytes>>;
+    type Error = Infallible;
+    type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn call(&self, _req: Request<Incoming>) -> Self::Future {
+        let mut response = Response::new(Empty::new());
+        *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        response
+            .headers_mut()
+            .insert(CONNECTION, HeaderValue::from_static("close"));
+        futures::future::ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Empty, Full};
+    use hyper::body::Bytes;
+    use std::convert::Infallible;
+
+    /// Make service for services answering "hello". Requests for `/slow`
+    /// notify `started`, and are only answered once `release` is notified.
+    #[derive(Clone, Default)]
+    struct MakeTestService {
+        started: Arc<Notify>,
+        release: Arc<Notify>,
+    }
+
+    impl Service<SocketAddr> for MakeTestService {
+        type Response = MakeTestService;
+        type Error = Infallible;
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _target: SocketAddr) -> Self::Future {
+            futures::future::ok(self.clone())
+        }
+    }
+
+    impl Service<Request<Incoming>> for MakeTestService {
+        type Response = Response<Full<Bytes>>;
+        type Error = Infallible;
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn call(&self, req: Request<Incoming>) -> Self::Future {
+            let service = self.clone();
+            Box::pin(async move {
+                if req.uri().path() == "/slow" {
+                    service.started.notify_one();
+                    service.release.notified().await;
+                }
+                Ok(Response::new(Full::from("hello")))
+            })
+        }
+    }
+
+    async fn get(addr: SocketAddr, path: &str) -> String {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .unwrap();
+        tokio::spawn(connection);
+
+        let request = Request::get(path)
+            .header("host", "localhost")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let response = sender.send_request(request).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn serves_http2_with_prior_knowledge() {
+        let server = Server::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap()
+            .http2_only()
+            .http2_max_concurrent_streams(10)
+            .http2_initial_stream_window_size(128 * 1024)
+            .http2_keep_alive(Duration::from_secs(10), Duration::from_secs(5));
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(
+            server.serve_with_shutdown(MakeTestService::default(), futures::future::pending()),
+        );
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (mut sender, connection) =
+            hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+                .await
+                .unwrap();
+        tokio::spawn(connection);
+
+        let request = Request::get(format!("http://{}/", addr))
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let response = sender.send_request(request).await.unwrap();
+        assert_eq!(response.version(), Version::HTTP_2);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello");
+    }
+
+    #[tokio::test]
+    async fn upgrades_to_h2c() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let server = Server::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap()
+            .h2c_upgrade(true);
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(
+            server.serve_with_shutdown(MakeTestService::default(), futures::future::pending()),
+        );
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(
+                b"GET / HTTP/1.1\r\n\
+                  Host: localhost\r\n\
+                  Connection: Upgrade, HTTP2-Settings\r\n\
+                  Upgrade: h2c\r\n\
+                  HTTP2-Settings: AAMAAABkAAQCAAAAAAIAAAAA\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            response.push(stream.read_u8().await.unwrap());
+        }
+        assert!(response.starts_with(b"HTTP/1.1 101 Switching Protocols\r\n"));
+
+        // The client's preface and an empty SETTINGS frame
+        stream
+            .write_all(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n\0\0\0\x04\0\0\0\0\0")
+            .await
+            .unwrap();
+
+        // The upgraded request is answered on stream 1
+        let mut status = None;
+        let body = loop {
+            let mut header = [0; 9];
+            stream.read_exact(&mut header).await.unwrap();
+            let length = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+            let mut payload = vec![0; length];
+            stream.read_exact(&mut payload).await.unwrap();
+            let stream_id = u32::from_be_bytes([header[5], header[6], header[7], header[8]]);
+            match (header[3], stream_id) {
+                (0x1, 1) => status = payload.first().copied(),
+                (0x0, 1) => break payload,
+                _ => {}
+            }
+        };
+        // Indexed `:status: 200`
+        assert_eq!(status, Some(0x88));
+        assert_eq!(body, b"hello");
+    }
+
+    #[tokio::test]
+    async fn limits_connections() {
+        let server = Server::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap()
+            .max_connections(1)
+            .max_requests_per_connection(2)
+            .idle_timeout(Duration::from_millis(100));
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(
+            server.serve_with_shutdown(MakeTestService::default(), futures::future::pending()),
+        );
+
+        let connect = || async {
+            let stream = TcpStream::connect(addr).await.unwrap();
+            let (sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+                .await
+                .unwrap();
+            (sender, tokio::spawn(connection))
+        };
+        let request = || Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+
+        let (mut sender, connection) = connect().await;
+        let response = sender.send_request(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(CONNECTION));
+
+        // Connections over the maximum are answered with 503
+        let (mut overloaded, overloaded_connection) = connect().await;
+        let response = overloaded.send_request(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[CONNECTION], "close");
+        overloaded_connection.await.unwrap().unwrap();
+
+        // Connections are closed after their last request
+        let response = sender.send_request(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONNECTION], "close");
+        connection.await.unwrap().unwrap();
+
+        // ...making room for another, which is closed once idle
+        let (mut sender, connection) = connect().await;
+        let response = sender.send_request(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        drop(response);
+        tokio::time::timeout(Duration::from_secs(5), connection)
+            .await
+            .expect("idle connection should be closed")
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn creates_services_in_connection_tasks() {
+        /// Make service whose first service is never created.
+        struct StalledMakeService(AtomicUsize);
+
+        impl Service<SocketAddr> for StalledMakeService {
+            type Response = MakeTestService;
+            type Error = Infallible;
+            type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+            fn call(&self, _target: SocketAddr) -> Self::Future {
+                if self.0.fetch_add(1, Ordering::SeqCst) == 0 {
+                    return Box::pin(futures::future::pending());
+                }
+                Box::pin(futures::future::ok(MakeTestService::default()))
+            }
+        }
+
+        let server = Server::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.serve_with_shutdown(
+            StalledMakeService(AtomicUsize::new(0)),
+            futures::future::pending(),
+        ));
+
+        let _stalled = TcpStream::connect(addr).await.unwrap();
+        assert_eq!(get(addr, "/").await, "hello");
+    }
+
+    #[tokio::test]
+    async fn drains_in_flight_requests() {
+        let server = Server::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let token = server.shutdown_token();
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let make_service = MakeTestService::default();
+
+        let server = tokio::spawn(server.serve_with_shutdown(make_service.clone(), async {
+            let _ = rx.await;
+        }));
+
+        assert_eq!(get(addr, "/").await, "hello");
+
+        let slow = tokio::spawn(get(addr, "/slow"));
+        make_service.started.notified().await;
+        tx.send(()).unwrap();
+        token.cancelled().await;
+
+        // The in-flight request completes after shutdown has started
+        make_service.release.notify_one();
+        assert_eq!(slow.await.unwrap(), "hello");
+        server.await.unwrap().unwrap();
+        assert!(token.is_cancelled());
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+}
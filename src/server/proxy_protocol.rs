@@ -0,0 +1,348 @@
+//! Accepting connections through L4 load balancers using the PROXY protocol.
+//!
+//! A TCP load balancer forwarding connections to the server hides the
+//! client's address, which is only known to the server if the load balancer
+//! sends it first, in a HAProxy PROXY protocol header (version 1 or 2).
+//! [`ProxyProtocolListener`] reads the header from connections from trusted
+//! load balancers, and passes the client's address to the `MakeService` in
+//! place of the load balancer's. `IntoMakeServiceWithConnectInfo` then adds
+//! it to each request's context as `ConnectInfo<SocketAddr>`, from which
+//! `ClientInfoService` takes the client IP.
+//!
+//! Connections from addresses which aren't trusted are served as they are,
+//! without reading a header, so that clients can't claim any address they
+//! like. Connections from trusted addresses must start with a header, or they
+//! are closed. With TLS, the PROXY protocol listener must accept connections
+//! before the TLS listener, as load balancers send the header before the TLS
+//! handshake.
+//!
+//! ```no_run
+//! # async fn run<M, S, B>(make_service: M) -> std::io::Result<()>
+//! # where
+//! #     M: hyper::service::Service<std::net::SocketAddr, Response = S>,
+//! #     M::Future: Send + 'static,
+//! #     M::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! #     S: hyper::service::Service<hyper::Request<hyper::body::Incoming>, Response = hyper::Response<B>> + Send + 'static,
+//! #     S::Future: Send + 'static,
+//! #     S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! #     B: hyper::body::Body + Send + 'static,
+//! #     B::Data: Send,
+//! #     B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! # {
+//! use swagger::server::Server;
+//!
+//! Server::bind("0.0.0.0:8080".parse().unwrap())
+//!     .await?
+//!     .proxy_protocol(["10.0.0.0/8".parse().unwrap()])
+//!     .serve(make_service)
+//!     .await
+//! # }
+//! ```
+
+use super::{Listener, Server};
+use crate::client_info::IpNetwork;
+use futures::future::BoxFuture;
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// The default maximum time to wait for the PROXY protocol header.
+const DEFAULT_HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The signature starting a version 1 header.
+const V1_SIGNATURE: &[u8] = b"PROXY ";
+
+/// The maximum length of a version 1 header, including the CRLF.
+const V1_MAX_LEN: usize = 107;
+
+/// The signature starting a version 2 header.
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid PROXY protocol header: {}", message),
+    )
+}
+
+/// Read a PROXY protocol header, returning the client's address, or `None`
+/// if the load balancer didn't forward the connection from a client, such as
+/// for health checks.
+async fn read_header<IO: AsyncRead + Unpin>(io: &mut IO) -> io::Result<Option<SocketAddr>> {
+    // Both versions of header are at least this long, so this never reads
+    // beyond the header.
+    let mut start = [0; 8];
+    io.read_exact(&mut start).await?;
+
+    if start.starts_with(V1_SIGNATURE) {
+        let mut line = start.to_vec();
+        while !line.ends_with(b"\r\n") {
+            if line.len() == V1_MAX_LEN {
+                return Err(invalid("too long"));
+            }
+            line.push(io.read_u8().await?);
+        }
+        let line = std::str::from_utf8(&line).map_err(|_| invalid("not ASCII"))?;
+        parse_v1(line)
+    } else if V2_SIGNATURE.starts_with(&start) {
+        let mut header = [0; 16];
+        header[..8].copy_from_slice(&start);
+        io.read_exact(&mut header[8..]).await?;
+        let mut addresses = vec![0; usize::from(u16::from_be_bytes([header[14], header[15]]))];
+        io.read_exact(&mut addresses).await?;
+        parse_v2(&header, &addresses)
+    } else {
+        Err(invalid("missing"))
+    }
+}
+
+/// Parse a version 1 header, such as
+/// `PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n`.
+fn parse_v1(line: &str) -> io::Result<Option<SocketAddr>> {
+    let mut fields = line.trim_end_matches("\r\n").split(' ').skip(1);
+    match fields.next() {
+        Some("TCP4") | Some("TCP6") => {}
+        Some("UNKNOWN") => return Ok(None),
+        _ => return Err(invalid("unknown protocol")),
+    }
+    let ip = fields
+        .next()
+        .and_then(|ip| ip.parse::<IpAddr>().ok())
+        .ok_or_else(|| invalid("invalid source address"))?;
+    let port = fields
+        .nth(1)
+        .and_then(|port| port.parse::<u16>().ok())
+        .ok_or_else(|| invalid("invalid source port"))?;
+    Ok(Some(SocketAddr::new(ip, port)))
+}
+
+/// Parse a version 2 header, given its fixed 16 bytes and the addresses
+/// which follow.
+fn parse_v2(header: &[u8; 16], addresses: &[u8]) -> io::Result<Option<SocketAddr>> {
+    if header[..12] != *V2_SIGNATURE || header[12] >> 4 != 2 {
+        return Err(invalid("unsupported version"));
+    }
+    match header[12] & 0x0f {
+        // LOCAL - the connection was made by the load balancer itself
+        0 => return Ok(None),
+        // PROXY
+        1 => {}
+        _ => return Err(invalid("unknown command")),
+    }
+
+    let source = match header[13] >> 4 {
+        // AF_INET
+        1 if addresses.len() >= 12 => {
+            let ip = <[u8; 4]>::try_from(&addresses[..4]).expect("slice of length 4");
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            SocketAddr::new(Ipv4Addr::from(ip).into(), port)
+        }
+        // AF_INET6
+        2 if addresses.len() >= 36 => {
+            let ip = <[u8; 16]>::try_from(&addresses[..16]).expect("slice of length 16");
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            SocketAddr::new(Ipv6Addr::from(ip).into(), port)
+        }
+        1 | 2 => return Err(invalid("addresses too short")),
+        // AF_UNSPEC or AF_UNIX
+        _ => return Ok(None),
+    };
+    Ok(Some(source))
+}
+
+/// A PROXY protocol header being read.
+type Header<IO> = BoxFuture<'static, io::Result<(IO, SocketAddr)>>;
+
+/// [`Listener`] reading PROXY protocol headers from the connections of
+/// another listener which come from trusted load balancers.
+///
+/// The other listener's `accept` must be cancel safe, as it is for
+/// `TcpListener`.
+pub struct ProxyProtocolListener<L: Listener> {
+    inner: L,
+    trusted: Vec<IpNetwork>,
+    header_timeout: Duration,
+    headers: FuturesUnordered<Header<L::Io>>,
+}
+
+impl<L: Listener<Target = SocketAddr>> ProxyProtocolListener<L> {
+    /// Read PROXY protocol headers from the connections accepted by a
+    /// listener. No addresses are trusted by default.
+    pub fn new(listener: L) -> Self {
+        ProxyProtocolListener {
+            inner: listener,
+            trusted: Vec::new(),
+            header_timeout: DEFAULT_HEADER_TIMEOUT,
+            headers: FuturesUnordered::new(),
+        }
+    }
+
+    /// Trust connections from a network of load balancers to send a PROXY
+    /// protocol header.
+    pub fn trust(mut self, network: IpNetwork) -> Self {
+        self.trusted.push(network);
+        self
+    }
+
+    /// Set the maximum time to wait for the PROXY protocol header on a
+    /// connection, after which the connection is closed.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn header_timeout(mut self, timeout: Duration) -> Self {
+        self.header_timeout = timeout;
+        self
+    }
+}
+
+impl<L> fmt::Debug for ProxyProtocolListener<L>
+where
+    L: Listener + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyProtocolListener")
+            .field("inner", &self.inner)
+            .field("trusted", &self.trusted)
+            .field("header_timeout", &self.header_timeout)
+            .field("headers", &self.headers.len())
+            .finish()
+    }
+}
+
+impl<L> Listener for ProxyProtocolListener<L>
+where
+    L: Listener<Target = SocketAddr>,
+{
+    type Io = L::Io;
+    type Target = SocketAddr;
+
+    fn peer_addr(target: &SocketAddr) -> Option<SocketAddr> {
+        Some(*target)
+    }
+
+    fn accept(&mut self) -> BoxFuture<'_, io::Result<(Self::Io, Self::Target)>> {
+        Box::pin(async move {
+            loop {
+                tokio::select! {
+                    accepted = self.inner.accept() => {
+                        let (mut io, addr) = accepted?;
+                        if !self.trusted.iter().any(|network| network.contains(addr.ip())) {
+                            return Ok((io, addr));
+                        }
+
+                        let timeout = self.header_timeout;
+                        self.headers.push(Box::pin(async move {
+                            let source = tokio::time::timeout(timeout, read_header(&mut io))
+                                .await
+                                .map_err(|_| {
+                                    io::Error::new(
+                                        io::ErrorKind::TimedOut,
+                                        "PROXY protocol header timed out",
+                                    )
+                                })??;
+                            Ok((io, source.unwrap_or(addr)))
+                        }));
+                    }
+                    Some(header) = self.headers.next(), if !self.headers.is_empty() => {
+                        // The load balancer will see the connection close
+                        if let Ok(connection) = header {
+                            return Ok(connection);
+                        }
+                    }
+                }
+            }
+        })
+    }
+}
+
+impl<L> Server<L>
+where
+    L: Listener<Target = SocketAddr>,
+{
+    /// Read PROXY protocol headers from connections from the trusted networks
+    /// of load balancers, using the default header timeout.
+    pub fn proxy_protocol(
+        self,
+        trusted: impl IntoIterator<Item = IpNetwork>,
+    ) -> Server<ProxyProtocolListener<L>> {
+        self.map_listener(|listener| {
+            trusted
+                .into_iter()
+                .fold(ProxyProtocolListener::new(listener), |listener, network| {
+                    listener.trust(network)
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn parses_headers() {
+        assert_eq!(
+            parse_v1("PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n").unwrap(),
+            Some("192.0.2.1:56324".parse().unwrap())
+        );
+        assert_eq!(
+            parse_v1("PROXY TCP6 2001:db8::1 2001:db8::2 56324 443\r\n").unwrap(),
+            Some("[2001:db8::1]:56324".parse().unwrap())
+        );
+        assert_eq!(parse_v1("PROXY UNKNOWN\r\n").unwrap(), None);
+        assert!(parse_v1("PROXY TCP4 192.0.2.1\r\n").is_err());
+
+        let mut header = [0; 16];
+        header[..12].copy_from_slice(V2_SIGNATURE);
+        header[12] = 0x21;
+        header[13] = 0x11;
+        let addresses = [192, 0, 2, 1, 198, 51, 100, 1, 0xdc, 0x04, 0x01, 0xbb];
+        assert_eq!(
+            parse_v2(&header, &addresses).unwrap(),
+            Some("192.0.2.1:56324".parse().unwrap())
+        );
+        assert!(parse_v2(&header, &addresses[..8]).is_err());
+        header[12] = 0x20;
+        assert_eq!(parse_v2(&header, &[]).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn reads_headers_from_trusted_addresses() {
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let mut listener = ProxyProtocolListener::new(tcp).trust("127.0.0.0/8".parse().unwrap());
+
+        // A slow load balancer doesn't hold up other connections
+        let _slow = TcpStream::connect(addr).await.unwrap();
+        let mut v2 = TcpStream::connect(addr).await.unwrap();
+        v2.write_all(V2_SIGNATURE).await.unwrap();
+        v2.write_all(&[0x21, 0x21, 0, 36]).await.unwrap();
+        let mut addresses = [0; 36];
+        addresses[..16].copy_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        addresses[32..34].copy_from_slice(&443u16.to_be_bytes());
+        v2.write_all(&addresses).await.unwrap();
+        v2.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+
+        let (mut io, source) = listener.accept().await.unwrap();
+        assert_eq!(source, "[2001:db8::1]:443".parse().unwrap());
+        let mut request = [0; 16];
+        io.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request, b"GET / HTTP/1.1\r\n");
+
+        // Connections without a header are closed
+        let mut missing = TcpStream::connect(addr).await.unwrap();
+        missing.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+        let mut v1 = TcpStream::connect(addr).await.unwrap();
+        v1.write_all(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n")
+            .await
+            .unwrap();
+        let (_, source) = listener.accept().await.unwrap();
+        assert_eq!(source, "192.0.2.1:56324".parse().unwrap());
+        // ...or reset, as the request wasn't read
+        assert!(!matches!(missing.read(&mut request).await, Ok(n) if n > 0));
+    }
+}
@@ -0,0 +1,360 @@
+//! Serving connections on sockets passed by systemd socket activation.
+//!
+//! With socket activation, systemd binds the sockets listed in a `.socket`
+//! unit and passes them to the service when it starts, using the
+//! `LISTEN_PID`, `LISTEN_FDS` and `LISTEN_FDNAMES` environment variables. A
+//! [`SystemdListener`] accepts connections on all of the TCP and Unix domain
+//! stream sockets passed, or on those with a given name, as set by
+//! `FileDescriptorName=` in the socket unit. Sockets must be configured with
+//! `Accept=no`, which is systemd's default.
+//!
+//! ```no_run
+//! # async fn run<M, S, B>(make_service: M) -> std::io::Result<()>
+//! # where
+//! #     M: hyper::service::Service<swagger::server::systemd::SystemdTarget, Response = S>,
+//! #     M::Future: Send + 'static,
+//! #     M::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! #     S: hyper::service::Service<hyper::Request<hyper::body::Incoming>, Response = hyper::Response<B>> + Send + 'static,
+//! #     S::Future: Send + 'static,
+//! #     S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! #     B: hyper::body::Body + Send + 'static,
+//! #     B::Data: Send,
+//! #     B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! # {
+//! use swagger::server::systemd::SystemdListener;
+//! use swagger::server::Server;
+//!
+//! let listener = SystemdListener::from_env()?.named("api")?;
+//!
+//! Server::new(listener).serve(make_service).await
+//! # }
+//! ```
+
+use super::{Listener, Server};
+use crate::make::Connected;
+use futures::future::BoxFuture;
+use std::net::SocketAddr;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::{env, io};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+
+/// The first file descriptor passed by systemd.
+const LISTEN_FDS_START: RawFd = 3;
+
+/// A socket passed by systemd.
+#[derive(Debug)]
+enum Socket {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Socket {
+    /// Take ownership of a listening socket, detecting its address family.
+    fn new(fd: OwnedFd) -> io::Result<Self> {
+        let tcp = std::net::TcpListener::from(fd);
+        if tcp.local_addr().is_ok() {
+            tcp.set_nonblocking(true)?;
+            return Ok(Socket::Tcp(TcpListener::from_std(tcp)?));
+        }
+
+        let unix = std::os::unix::net::UnixListener::from(OwnedFd::from(tcp));
+        unix.local_addr()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "unsupported socket type"))?;
+        unix.set_nonblocking(true)?;
+        Ok(Socket::Unix(UnixListener::from_std(unix)?))
+    }
+
+    fn poll_accept(
+        &self,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<(SystemdStream, SystemdTarget)>> {
+        match self {
+            Socket::Tcp(listener) => listener.poll_accept(cx).map_ok(|(stream, addr)| {
+                (SystemdStream::Tcp(stream), SystemdTarget::new(Some(addr)))
+            }),
+            Socket::Unix(listener) => listener
+                .poll_accept(cx)
+                .map_ok(|(stream, _)| (SystemdStream::Unix(stream), SystemdTarget::new(None))),
+        }
+    }
+}
+
+/// [`Listener`] accepting connections on sockets passed by systemd.
+#[derive(Debug)]
+pub struct SystemdListener {
+    sockets: Vec<(Option<Arc<str>>, Socket)>,
+    /// The socket to poll first, so that no socket is starved.
+    next: usize,
+}
+
+impl SystemdListener {
+    /// Take the sockets passed to this process by systemd.
+    ///
+    /// The environment variables are removed, so that the sockets aren't also
+    /// taken by child processes. Returns an error if no sockets were passed.
+    /// This must be called from within a Tokio runtime.
+    pub fn from_env() -> io::Result<Self> {
+        let listen_pid = env::var("LISTEN_PID").ok();
+        let listen_fds = env::var("LISTEN_FDS").ok();
+        let listen_fdnames = env::var("LISTEN_FDNAMES").ok();
+        for var in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+            env::remove_var(var);
+        }
+
+        let fds = listen_fds
+            .filter(|_| listen_pid.and_then(|pid| pid.parse().ok()) == Some(std::process::id()))
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "no sockets passed by systemd")
+            })?;
+        let count: RawFd = fds
+            .parse()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid LISTEN_FDS"))?;
+        let names: Vec<_> = match &listen_fdnames {
+            Some(names) => names.split(':').map(Some).collect(),
+            None => Vec::new(),
+        };
+
+        let mut fds = Vec::new();
+        for (i, fd) in (LISTEN_FDS_START..LISTEN_FDS_START.saturating_add(count)).enumerate() {
+            // SAFETY: systemd passes ownership of these file descriptors to
+            // this process, and removing the environment variables above
+            // ensures that they can only be taken once.
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            // systemd doesn't set close-on-exec, so swap for a duplicate which
+            // has it set.
+            let fd = fd.try_clone()?;
+            fds.push((names.get(i).copied().flatten(), fd));
+        }
+        Self::from_fds(fds)
+    }
+
+    fn from_fds<'a>(fds: impl IntoIterator<Item = (Option<&'a str>, OwnedFd)>) -> io::Result<Self> {
+        let sockets = fds
+            .into_iter()
+            .map(|(name, fd)| Ok((name.map(Arc::from), Socket::new(fd)?)))
+            .collect::<io::Result<Vec<_>>>()?;
+        if sockets.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no sockets passed by systemd",
+            ));
+        }
+        Ok(SystemdListener { sockets, next: 0 })
+    }
+
+    /// Keep only the sockets with the given name, closing the others.
+    ///
+    /// Returns an error if there are no sockets with the name.
+    pub fn named(mut self, name: &str) -> io::Result<Self> {
+        self.sockets.retain(|(n, _)| n.as_deref() == Some(name));
+        if self.sockets.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no socket named {} passed by systemd", name),
+            ));
+        }
+        Ok(self)
+    }
+
+    /// The names of the sockets, in the order they were passed.
+    pub fn names(&self) -> impl Iterator<Item = Option<&str>> {
+        self.sockets.iter().map(|(name, _)| name.as_deref())
+    }
+
+    fn poll_accept(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<(SystemdStream, SystemdTarget)>> {
+        let len = self.sockets.len();
+        for i in 0..len {
+            let index = (self.next + i) % len;
+            let (name, socket) = &self.sockets[index];
+            if let Poll::Ready(result) = socket.poll_accept(cx) {
+                self.next = (index + 1) % len;
+                return Poll::Ready(result.map(|(stream, mut target)| {
+                    target.name = name.clone();
+                    (stream, target)
+                }));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Listener for SystemdListener {
+    type Io = SystemdStream;
+    type Target = SystemdTarget;
+
+    fn accept(&mut self) -> BoxFuture<'_, io::Result<(Self::Io, Self::Target)>> {
+        Box::pin(futures::future::poll_fn(move |cx| self.poll_accept(cx)))
+    }
+
+    fn peer_addr(target: &SystemdTarget) -> Option<SocketAddr> {
+        target.remote_addr
+    }
+}
+
+impl Server<SystemdListener> {
+    /// Create a server for all of the sockets passed to this process by
+    /// systemd.
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub fn from_systemd() -> io::Result<Self> {
+        Ok(Self::new(SystemdListener::from_env()?))
+    }
+}
+
+/// Information about a connection accepted by a [`SystemdListener`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemdTarget {
+    name: Option<Arc<str>>,
+    remote_addr: Option<SocketAddr>,
+}
+
+impl SystemdTarget {
+    fn new(remote_addr: Option<SocketAddr>) -> Self {
+        SystemdTarget {
+            name: None,
+            remote_addr,
+        }
+    }
+
+    /// The name of the socket the connection was accepted on, if systemd
+    /// passed socket names.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The address of the client, for TCP connections.
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr
+    }
+}
+
+impl Connected<SystemdTarget> for SystemdTarget {
+    fn connect_info(target: &SystemdTarget) -> Self {
+        target.clone()
+    }
+}
+
+impl Connected<SystemdTarget> for Option<SocketAddr> {
+    fn connect_info(target: &SystemdTarget) -> Self {
+        target.remote_addr
+    }
+}
+
+/// A connection accepted by a [`SystemdListener`].
+#[derive(Debug)]
+pub enum SystemdStream {
+    /// A TCP connection.
+    Tcp(TcpStream),
+    /// A Unix domain socket connection.
+    Unix(UnixStream),
+}
+
+impl AsyncRead for SystemdStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            SystemdStream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            SystemdStream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for SystemdStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            SystemdStream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            SystemdStream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            SystemdStream::Tcp(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            SystemdStream::Unix(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            SystemdStream::Tcp(stream) => stream.is_write_vectored(),
+            SystemdStream::Unix(stream) => stream.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            SystemdStream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            SystemdStream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            SystemdStream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            SystemdStream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn accepts_connections_on_named_sockets() {
+        let path = env::temp_dir().join(format!("swagger-systemd-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let unix = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let other = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let listener = SystemdListener::from_fds([
+            (Some("api"), OwnedFd::from(tcp)),
+            (Some("api"), OwnedFd::from(unix)),
+            (Some("metrics"), OwnedFd::from(other)),
+        ])
+        .unwrap();
+        assert_eq!(
+            listener.names().collect::<Vec<_>>(),
+            [Some("api"), Some("api"), Some("metrics")]
+        );
+
+        let mut listener = listener.named("api").unwrap();
+        let (client, accepted) = tokio::join!(TcpStream::connect(addr), listener.accept());
+        let (stream, target) = accepted.unwrap();
+        assert!(matches!(stream, SystemdStream::Tcp(_)));
+        assert_eq!(target.name(), Some("api"));
+        assert_eq!(
+            target.remote_addr(),
+            Some(client.unwrap().local_addr().unwrap())
+        );
+
+        let (_client, accepted) = tokio::join!(UnixStream::connect(&path), listener.accept());
+        let (stream, target) = accepted.unwrap();
+        assert!(matches!(stream, SystemdStream::Unix(_)));
+        assert_eq!(target.name(), Some("api"));
+        assert_eq!(target.remote_addr(), None);
+
+        assert!(listener.named("metrics").is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
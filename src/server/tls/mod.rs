@@ -0,0 +1,647 @@
+//! Serving HTTPS.
+//!
+//! [`TlsConfig`] loads certificates and private keys from PEM files, and
+//! builds an acceptor which [`Server::tls`] uses to serve each connection over
+//! TLS. Clients using SNI can be served different certificates by host name,
+//! and the files can be watched, so that renewed certificates are picked up
+//! without a restart.
+//!
+//! Two TLS implementations are supported, with the same configuration:
+//! - With the `rustls` feature, `TlsConfig::build_rustls` builds a
+//!   `RustlsAcceptor`.
+//! - With the `tls` feature, `TlsConfig::build_openssl` builds an
+//!   `OpensslAcceptor`, using the platform's OpenSSL - for deployments which
+//!   need a FIPS validated TLS stack, or OpenSSL's configuration. As for
+//!   clients, OpenSSL isn't used on macOS, iOS or Windows, so servers there
+//!   should use rustls.
+//!
+//! TLS handshakes run concurrently, so slow or malicious clients can't hold
+//! up other connections, and handshakes which fail or don't complete within
+//! a timeout are dropped.
+//!
+//! For mutual TLS, `TlsConfig::client_auth` verifies client certificates
+//! against a CA bundle, optionally checking CRLs. Connections are passed to
+//! the `MakeService` with a [`TlsTarget`], holding the verified
+//! [`ClientCertificate`], which `IntoMakeServiceWithConnectInfo` can add to
+//! each request's context as `ConnectInfo<Option<ClientCertificate>>` for an
+//! authenticator to map to an `Authorization`.
+//!
+//! ```no_run
+//! # async fn run<M, S, B>(make_service: M) -> std::io::Result<()>
+//! # where
+//! #     M: hyper::service::Service<swagger::server::tls::TlsTarget<std::net::SocketAddr>, Response = S>,
+//! #     M::Future: Send + 'static,
+//! #     M::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! #     S: hyper::service::Service<hyper::Request<hyper::body::Incoming>, Response = hyper::Response<B>> + Send + 'static,
+//! #     S::Future: Send + 'static,
+//! #     S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! #     B: hyper::body::Body + Send + 'static,
+//! #     B::Data: Send,
+//! #     B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! # {
+//! use std::time::Duration;
+//! use swagger::server::tls::TlsConfig;
+//! use swagger::server::Server;
+//!
+//! let tls = TlsConfig::new("/etc/tls/api.pem", "/etc/tls/api.key")
+//!     .sni("admin.example.com", "/etc/tls/admin.pem", "/etc/tls/admin.key")
+//!     .reload_interval(Duration::from_secs(60));
+//! # #[cfg(feature = "rustls")]
+//! let acceptor = tls.build_rustls()?;
+//! # #[cfg(not(feature = "rustls"))]
+//! # let acceptor = tls.build_openssl()?;
+//!
+//! Server::bind("0.0.0.0:443".parse().unwrap())
+//!     .await?
+//!     .tls(acceptor)
+//!     .serve(make_service)
+//!     .await
+//! # }
+//! ```
+
+#[cfg(all(
+    feature = "tls",
+    not(any(target_os = "macos", target_os = "windows", target_os = "ios"))
+))]
+mod openssl;
+#[cfg(feature = "rustls")]
+mod rustls;
+
+#[cfg(all(
+    feature = "tls",
+    not(any(target_os = "macos", target_os = "windows", target_os = "ios"))
+))]
+pub use self::openssl::OpensslAcceptor;
+#[cfg(feature = "rustls")]
+pub use self::rustls::RustlsAcceptor;
+
+use super::{Listener, Server};
+use crate::make::Connected;
+use arc_swap::ArcSwap;
+use futures::future::{BoxFuture, Either};
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Default time allowed for a client to complete the TLS handshake.
+const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const LOG_TARGET: &str = "swagger::server::tls";
+
+/// The error for a PEM file which can't be used.
+fn invalid(path: &Path, error: impl fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid PEM file {}: {}", path.display(), error),
+    )
+}
+
+/// A certificate chain and private key, in PEM files, and the OCSP
+/// response to staple.
+#[derive(Debug, Clone)]
+struct PemFiles {
+    cert: PathBuf,
+    key: PathBuf,
+    ocsp: Option<PathBuf>,
+}
+
+impl PemFiles {
+    fn new(cert: PathBuf, key: PathBuf) -> Self {
+        PemFiles {
+            cert,
+            key,
+            ocsp: None,
+        }
+    }
+
+    /// The DER encoded OCSP response to staple, if any.
+    fn read_ocsp(&self) -> io::Result<Option<Vec<u8>>> {
+        self.ocsp.as_ref().map(std::fs::read).transpose()
+    }
+}
+
+/// The PEM files of a server, by host name pattern.
+#[derive(Debug, Clone)]
+struct CertificateFiles {
+    default: PemFiles,
+    names: Vec<(String, PemFiles)>,
+}
+
+impl CertificateFiles {
+    fn files(&self) -> impl Iterator<Item = &PemFiles> {
+        std::iter::once(&self.default).chain(self.names.iter().map(|(_, files)| files))
+    }
+
+    /// Load each certificate, using a TLS implementation's loader.
+    fn load<K>(&self, load: impl Fn(&PemFiles) -> io::Result<K>) -> io::Result<Certificates<K>> {
+        Ok(Certificates {
+            default: load(&self.default)?,
+            names: self
+                .names
+                .iter()
+                .map(|(name, files)| Ok((name.clone(), load(files)?)))
+                .collect::<io::Result<_>>()?,
+        })
+    }
+
+    /// When each file was last modified.
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        self.files()
+            .flat_map(|files| [Some(&files.cert), Some(&files.key), files.ocsp.as_ref()])
+            .flatten()
+            .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+}
+
+/// The certificates of a server, by host name pattern.
+#[derive(Debug)]
+struct Certificates<K> {
+    default: K,
+    names: Vec<(String, K)>,
+}
+
+impl<K> Certificates<K> {
+    /// The certificate for a host name. Exact names take precedence over
+    /// wildcards such as `*.example.com`, and longer wildcards over shorter
+    /// ones.
+    fn find(&self, server_name: Option<&str>) -> &K {
+        let Some(server_name) = server_name.map(str::to_ascii_lowercase) else {
+            return &self.default;
+        };
+        self.names
+            .iter()
+            .filter_map(|(pattern, key)| {
+                let rank = match pattern.strip_prefix('*') {
+                    Some(suffix) => (server_name.len() > suffix.len()
+                        && server_name.ends_with(suffix))
+                    .then_some(suffix.len())?,
+                    None => (*pattern == server_name).then_some(usize::MAX)?,
+                };
+                Some((rank, key))
+            })
+            .max_by_key(|(rank, _)| *rank)
+            .map_or(&self.default, |(_, key)| key)
+    }
+}
+
+/// Reload the certificates whenever their files change, until they are
+/// dropped.
+fn watch<K, F>(
+    certificates: Weak<ArcSwap<Certificates<K>>>,
+    files: CertificateFiles,
+    interval: Duration,
+    load: F,
+) where
+    K: Send + Sync + 'static,
+    F: Fn(&PemFiles) -> io::Result<K> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut modified = files.modified();
+        let mut ticks = tokio::time::interval(interval);
+        ticks.tick().await;
+        loop {
+            ticks.tick().await;
+            let Some(certificates) = certificates.upgrade() else {
+                return;
+            };
+            let now = files.modified();
+            if now == modified {
+                continue;
+            }
+            // If the files are only partly updated, try again next time
+            match files.load(&load) {
+                Ok(reloaded) => {
+                    certificates.store(Arc::new(reloaded));
+                    modified = now;
+                    log::info!(target: LOG_TARGET, "Reloaded TLS certificates");
+                }
+                Err(e) => {
+                    log::warn!(target: LOG_TARGET, "Failed to reload TLS certificates: {}", e)
+                }
+            }
+        }
+    });
+}
+
+/// Whether clients must present a certificate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientAuth {
+    /// Reject clients which don't present a valid certificate.
+    Required,
+    /// Accept clients which don't present a certificate, but reject those
+    /// presenting an invalid one.
+    Optional,
+}
+
+/// Builder for TLS acceptors, serving certificates loaded from PEM files.
+///
+/// By default, both HTTP/2 and HTTP/1.1 are offered using ALPN, if the
+/// corresponding crate features are enabled.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    files: CertificateFiles,
+    alpn_protocols: Vec<Vec<u8>>,
+    handshake_timeout: Duration,
+    reload_interval: Option<Duration>,
+    client_auth: Option<(ClientAuth, PathBuf)>,
+    crls: Vec<PathBuf>,
+}
+
+impl TlsConfig {
+    /// Serve the certificate chain and private key in the given PEM files,
+    /// unless another certificate matches the host name the client sends
+    /// using SNI.
+    pub fn new(cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Self {
+        let mut alpn_protocols = Vec::new();
+        if cfg!(feature = "http2") {
+            alpn_protocols.push(b"h2".to_vec());
+        }
+        if cfg!(feature = "http1") {
+            alpn_protocols.push(b"http/1.1".to_vec());
+        }
+
+        TlsConfig {
+            files: CertificateFiles {
+                default: PemFiles::new(cert_path.into(), key_path.into()),
+                names: Vec::new(),
+            },
+            alpn_protocols,
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+            reload_interval: None,
+            client_auth: None,
+            crls: Vec::new(),
+        }
+    }
+
+    /// Serve the certificate chain and private key in the given PEM files to
+    /// clients requesting a host name, such as `api.example.com`, or any
+    /// subdomain matching a wildcard, such as `*.example.com`.
+    pub fn sni(
+        mut self,
+        server_name: &str,
+        cert_path: impl Into<PathBuf>,
+        key_path: impl Into<PathBuf>,
+    ) -> Self {
+        self.files.names.push((
+            server_name.to_ascii_lowercase(),
+            PemFiles::new(cert_path.into(), key_path.into()),
+        ));
+        self
+    }
+
+    /// Staple the DER encoded OCSP response in the given file to the
+    /// certificate most recently added with `new` or `sni`. The response is
+    /// reloaded with the certificates, so it can be refreshed before it
+    /// expires.
+    pub fn ocsp_response(mut self, path: impl Into<PathBuf>) -> Self {
+        let files = match self.files.names.last_mut() {
+            Some((_, files)) => files,
+            None => &mut self.files.default,
+        };
+        files.ocsp = Some(path.into());
+        self
+    }
+
+    /// Ask clients for certificates, verifying them against the CA
+    /// certificates in the given PEM file.
+    pub fn client_auth(mut self, ca_path: impl Into<PathBuf>, mode: ClientAuth) -> Self {
+        self.client_auth = Some((mode, ca_path.into()));
+        self
+    }
+
+    /// Reject client certificates revoked by the CRLs in the given PEM file.
+    /// Each certificate in the chain, other than the CA's, must be covered by
+    /// a CRL.
+    ///
+    /// CRLs only apply if client certificates are verified using
+    /// `client_auth`, and are loaded when the acceptor is built.
+    pub fn crl(mut self, path: impl Into<PathBuf>) -> Self {
+        self.crls.push(path.into());
+        self
+    }
+
+    /// Set the protocols offered using ALPN, in order of preference.
+    pub fn alpn_protocols<I, P>(mut self, protocols: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<[u8]>,
+    {
+        self.alpn_protocols = protocols.into_iter().map(|p| p.as_ref().to_vec()).collect();
+        self
+    }
+
+    /// Set the time allowed for a client to complete the TLS handshake.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// Check the PEM files for changes at the given interval, and reload the
+    /// certificates when they change. Connections already established keep
+    /// their certificate.
+    ///
+    /// If the new files can't be loaded - for example, because the
+    /// certificate has been written but the key hasn't yet - the previous
+    /// certificates are served until the next check.
+    pub fn reload_interval(mut self, interval: Duration) -> Self {
+        self.reload_interval = Some(interval);
+        self
+    }
+}
+
+/// The certificate chain presented by a client and verified by the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    chain: Arc<[Vec<u8>]>,
+}
+
+impl ClientCertificate {
+    /// The client certificate, from a DER encoded chain starting with the
+    /// end-entity certificate.
+    fn from_chain(chain: Vec<Vec<u8>>) -> Option<Self> {
+        (!chain.is_empty()).then(|| ClientCertificate {
+            chain: chain.into(),
+        })
+    }
+
+    /// The DER encoded end-entity certificate.
+    pub fn der(&self) -> &[u8] {
+        &self.chain[0]
+    }
+
+    /// The DER encoded certificates of the chain the client presented,
+    /// starting with the end-entity certificate.
+    pub fn chain(&self) -> impl Iterator<Item = &[u8]> {
+        self.chain.iter().map(Vec::as_slice)
+    }
+}
+
+/// The target of a connection accepted by a [`TlsListener`]: the target of
+/// the underlying listener, and the client's certificate.
+#[derive(Debug, Clone)]
+pub struct TlsTarget<T> {
+    target: T,
+    client_certificate: Option<ClientCertificate>,
+}
+
+impl<T> TlsTarget<T> {
+    /// The target of the underlying listener, such as the client's address.
+    pub fn target(&self) -> &T {
+        &self.target
+    }
+
+    /// The certificate the client presented, if client certificates are
+    /// verified.
+    pub fn client_certificate(&self) -> Option<&ClientCertificate> {
+        self.client_certificate.as_ref()
+    }
+}
+
+impl Connected<TlsTarget<SocketAddr>> for SocketAddr {
+    fn connect_info(target: &TlsTarget<SocketAddr>) -> Self {
+        target.target
+    }
+}
+
+impl<T> Connected<TlsTarget<T>> for Option<ClientCertificate> {
+    fn connect_info(target: &TlsTarget<T>) -> Self {
+        target.client_certificate.clone()
+    }
+}
+
+impl<T> Connected<TlsTarget<T>> for TlsTarget<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    fn connect_info(target: &TlsTarget<T>) -> Self {
+        target.clone()
+    }
+}
+
+/// A TLS implementation, performing the server side of the TLS handshake on
+/// each connection accepted by a [`TlsListener`].
+pub trait Acceptor<IO>: Send + 'static {
+    /// The connection, once the handshake has completed.
+    type Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    /// Perform the TLS handshake.
+    fn accept(&self, io: IO) -> BoxFuture<'static, io::Result<Self::Stream>>;
+
+    /// The time allowed for a client to complete the handshake.
+    fn handshake_timeout(&self) -> Duration;
+
+    /// The verified certificate of the client of a connection.
+    ///
+    /// Defaults to `None`, for acceptors which don't verify client
+    /// certificates.
+    fn client_certificate(_stream: &Self::Stream) -> Option<ClientCertificate> {
+        None
+    }
+}
+
+/// A TLS handshake in progress.
+type Handshake<L, A> = BoxFuture<
+    'static,
+    io::Result<(
+        <A as Acceptor<<L as Listener>::Io>>::Stream,
+        TlsTarget<<L as Listener>::Target>,
+    )>,
+>;
+
+/// [`Listener`] accepting TLS connections on the connections of another
+/// listener.
+///
+/// The other listener's `accept` must be cancel safe, as it is for
+/// `TcpListener`.
+pub struct TlsListener<L: Listener, A: Acceptor<L::Io>> {
+    inner: L,
+    acceptor: A,
+    handshakes: FuturesUnordered<Handshake<L, A>>,
+}
+
+impl<L: Listener, A: Acceptor<L::Io>> TlsListener<L, A> {
+    /// Accept TLS connections on the connections accepted by a listener.
+    pub fn new(listener: L, acceptor: A) -> Self {
+        TlsListener {
+            inner: listener,
+            acceptor,
+            handshakes: FuturesUnordered::new(),
+        }
+    }
+}
+
+impl<L, A> fmt::Debug for TlsListener<L, A>
+where
+    L: Listener + fmt::Debug,
+    A: Acceptor<L::Io> + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsListener")
+            .field("inner", &self.inner)
+            .field("acceptor", &self.acceptor)
+            .field("handshakes", &self.handshakes.len())
+            .finish()
+    }
+}
+
+impl<L, A> Listener for TlsListener<L, A>
+where
+    L: Listener,
+    L::Target: 'static,
+    A: Acceptor<L::Io>,
+{
+    type Io = A::Stream;
+    type Target = TlsTarget<L::Target>;
+
+    fn peer_addr(target: &Self::Target) -> Option<SocketAddr> {
+        L::peer_addr(&target.target)
+    }
+
+    fn accept(&mut self) -> BoxFuture<'_, io::Result<(Self::Io, Self::Target)>> {
+        Box::pin(async move {
+            loop {
+                let accepted = tokio::select! {
+                    accepted = self.inner.accept() => Either::Left(accepted?),
+                    Some(handshake) = self.handshakes.next(), if !self.handshakes.is_empty() => {
+                        Either::Right(handshake)
+                    }
+                };
+
+                match accepted {
+                    Either::Left((io, target)) => {
+                        let handshake = self.acceptor.accept(io);
+                        let timeout = self.acceptor.handshake_timeout();
+                        self.handshakes.push(Box::pin(async move {
+                            let io = tokio::time::timeout(timeout, handshake).await.map_err(
+                                |_| {
+                                    io::Error::new(
+                                        io::ErrorKind::TimedOut,
+                                        "TLS handshake timed out",
+                                    )
+                                },
+                            )??;
+                            let client_certificate = A::client_certificate(&io);
+                            Ok((
+                                io,
+                                TlsTarget {
+                                    target,
+                                    client_certificate,
+                                },
+                            ))
+                        }));
+                    }
+                    Either::Right(Ok(connection)) => return Ok(connection),
+                    // The client will see the connection close
+                    Either::Right(Err(_)) => {}
+                }
+            }
+        })
+    }
+}
+
+impl<L> Server<L>
+where
+    L: Listener,
+    L::Target: 'static,
+{
+    /// Serve connections over TLS.
+    pub fn tls<A: Acceptor<L::Io>>(self, acceptor: A) -> Server<TlsListener<L, A>> {
+        self.map_listener(|listener| TlsListener::new(listener, acceptor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A self-signed certificate for a host name, or one issued by a
+    /// `TestCa`.
+    pub(super) struct TestCert {
+        pub(super) cert: rcgen::Certificate,
+        pub(super) key: rcgen::KeyPair,
+    }
+
+    impl TestCert {
+        pub(super) fn new(name: &str) -> Self {
+            let certified = rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap();
+            TestCert {
+                cert: certified.cert,
+                key: certified.signing_key,
+            }
+        }
+
+        pub(super) fn write(&self, dir: &Path, name: &str) -> (PathBuf, PathBuf) {
+            let cert = dir.join(format!("{}.pem", name));
+            let key = dir.join(format!("{}.key", name));
+            std::fs::write(&cert, self.cert.pem()).unwrap();
+            std::fs::write(&key, self.key.serialize_pem()).unwrap();
+            (cert, key)
+        }
+    }
+
+    /// A certificate authority issuing client certificates.
+    pub(super) struct TestCa {
+        cert: rcgen::Certificate,
+        issuer: rcgen::Issuer<'static, rcgen::KeyPair>,
+    }
+
+    impl TestCa {
+        pub(super) fn new() -> Self {
+            let mut params = rcgen::CertificateParams::new(Vec::new()).unwrap();
+            params
+                .distinguished_name
+                .push(rcgen::DnType::CommonName, "Test CA");
+            params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+            let key = rcgen::KeyPair::generate().unwrap();
+            TestCa {
+                cert: params.self_signed(&key).unwrap(),
+                issuer: rcgen::Issuer::new(params, key),
+            }
+        }
+
+        pub(super) fn issue(&self, name: &str) -> TestCert {
+            let key = rcgen::KeyPair::generate().unwrap();
+            let mut params = rcgen::CertificateParams::new(vec![name.to_string()]).unwrap();
+            params
+                .distinguished_name
+                .push(rcgen::DnType::CommonName, name);
+            let cert = params.signed_by(&key, &self.issuer).unwrap();
+            TestCert { cert, key }
+        }
+
+        pub(super) fn write(&self, dir: &Path) -> PathBuf {
+            let path = dir.join("ca.pem");
+            std::fs::write(&path, self.cert.pem()).unwrap();
+            path
+        }
+    }
+
+    #[test]
+    fn finds_certificates_by_name() {
+        let certificates = Certificates {
+            default: "default",
+            names: vec![
+                ("*.example.com".to_string(), "wildcard"),
+                ("*.api.example.com".to_string(), "api wildcard"),
+                ("admin.api.example.com".to_string(), "admin"),
+            ],
+        };
+
+        assert_eq!(*certificates.find(None), "default");
+        assert_eq!(*certificates.find(Some("example.com")), "default");
+        assert_eq!(*certificates.find(Some("www.Example.com")), "wildcard");
+        assert_eq!(
+            *certificates.find(Some("v1.api.example.com")),
+            "api wildcard"
+        );
+        assert_eq!(*certificates.find(Some("admin.api.example.com")), "admin");
+    }
+}
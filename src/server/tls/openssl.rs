@@ -0,0 +1,378 @@
+//! TLS acceptor using OpenSSL.
+
+use super::{
+    invalid, watch, Acceptor, ClientAuth, ClientCertificate, PemFiles, TlsConfig,
+    DEFAULT_HANDSHAKE_TIMEOUT,
+};
+use arc_swap::ArcSwap;
+use futures::future::BoxFuture;
+use hyper_openssl::SslStream;
+use hyper_util::rt::TokioIo;
+use openssl::pkey::PKey;
+use openssl::ssl::{
+    AlpnError, NameType, SniError, Ssl, SslAcceptor, SslAcceptorBuilder, SslContext, SslFiletype,
+    SslMethod, SslVerifyMode,
+};
+use openssl::x509::store::{X509Lookup, X509StoreBuilder};
+use openssl::x509::verify::X509VerifyFlags;
+use openssl::x509::X509;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// The protocol in a client's ALPN offer which the server prefers.
+fn select_protocol<'a>(server: &[Vec<u8>], client: &'a [u8]) -> Option<&'a [u8]> {
+    let mut offered = Vec::new();
+    let mut rest = client;
+    while let Some((&len, tail)) = rest.split_first() {
+        if tail.len() < len as usize {
+            break;
+        }
+        let (protocol, tail) = tail.split_at(len as usize);
+        offered.push(protocol);
+        rest = tail;
+    }
+    server
+        .iter()
+        .find_map(|protocol| offered.iter().find(|p| **p == protocol.as_slice()).copied())
+}
+
+/// The settings shared by the context of each certificate.
+#[derive(Debug)]
+struct Settings {
+    alpn_protocols: Vec<Vec<u8>>,
+    client_auth: Option<(ClientAuth, PathBuf)>,
+    crls: Vec<PathBuf>,
+}
+
+/// Verify client certificates against the CA certificates in a PEM file.
+fn client_auth(
+    builder: &mut SslAcceptorBuilder,
+    (mode, ca_path): &(ClientAuth, PathBuf),
+    crl_paths: &[PathBuf],
+) -> io::Result<()> {
+    let mut store = X509StoreBuilder::new().map_err(io::Error::other)?;
+    let cas = X509::stack_from_pem(&std::fs::read(ca_path)?).map_err(|e| invalid(ca_path, e))?;
+    for ca in cas {
+        builder
+            .add_client_ca(&ca)
+            .map_err(|e| invalid(ca_path, e))?;
+        store.add_cert(ca).map_err(|e| invalid(ca_path, e))?;
+    }
+    if !crl_paths.is_empty() {
+        let lookup = store
+            .add_lookup(X509Lookup::file())
+            .map_err(io::Error::other)?;
+        for path in crl_paths {
+            // The path is passed to OpenSSL as a C string
+            path.to_str()
+                .ok_or_else(|| invalid(path, "path isn't valid UTF-8"))?;
+            lookup
+                .load_crl_file(path, SslFiletype::PEM)
+                .map_err(|e| invalid(path, e))?;
+        }
+        store
+            .set_flags(X509VerifyFlags::CRL_CHECK | X509VerifyFlags::CRL_CHECK_ALL)
+            .map_err(io::Error::other)?;
+    }
+
+    builder
+        .set_verify_cert_store(store.build())
+        .map_err(io::Error::other)?;
+    builder.set_verify(match mode {
+        ClientAuth::Required => SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT,
+        ClientAuth::Optional => SslVerifyMode::PEER,
+    });
+    // Sessions can only be resumed with verified clients given a context
+    builder
+        .set_session_id_context(b"swagger")
+        .map_err(io::Error::other)
+}
+
+fn acceptor_builder(settings: &Arc<Settings>) -> io::Result<SslAcceptorBuilder> {
+    let mut builder =
+        SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server()).map_err(io::Error::other)?;
+    if !settings.alpn_protocols.is_empty() {
+        let settings = settings.clone();
+        builder.set_alpn_select_callback(move |_, client| {
+            select_protocol(&settings.alpn_protocols, client).ok_or(AlpnError::NOACK)
+        });
+    }
+    if let Some(auth) = &settings.client_auth {
+        client_auth(&mut builder, auth, &settings.crls)?;
+    }
+    Ok(builder)
+}
+
+fn load(files: &PemFiles, settings: &Arc<Settings>) -> io::Result<SslContext> {
+    let certs =
+        X509::stack_from_pem(&std::fs::read(&files.cert)?).map_err(|e| invalid(&files.cert, e))?;
+    let Some((cert, chain)) = certs.split_first() else {
+        return Err(invalid(&files.cert, "no certificates found"));
+    };
+    let key = PKey::private_key_from_pem(&std::fs::read(&files.key)?)
+        .map_err(|e| invalid(&files.key, e))?;
+
+    let mut builder = acceptor_builder(settings)?;
+    builder
+        .set_certificate(cert)
+        .map_err(|e| invalid(&files.cert, e))?;
+    for cert in chain {
+        builder
+            .add_extra_chain_cert(cert.clone())
+            .map_err(|e| invalid(&files.cert, e))?;
+    }
+    builder
+        .set_private_key(&key)
+        .map_err(|e| invalid(&files.key, e))?;
+    builder
+        .check_private_key()
+        .map_err(|e| invalid(&files.key, e))?;
+    if let Some(response) = files.read_ocsp()? {
+        builder
+            .set_status_callback(move |ssl| {
+                ssl.set_ocsp_status(&response)?;
+                Ok(true)
+            })
+            .map_err(io::Error::other)?;
+    }
+
+    Ok(builder.build().into_context())
+}
+
+impl TlsConfig {
+    /// Load the certificates and build an acceptor using OpenSSL.
+    ///
+    /// If the files are to be reloaded, this must be called from within a
+    /// Tokio runtime.
+    pub fn build_openssl(self) -> io::Result<OpensslAcceptor> {
+        let settings = Arc::new(Settings {
+            alpn_protocols: self.alpn_protocols,
+            client_auth: self.client_auth,
+            crls: self.crls,
+        });
+        let mut builder = acceptor_builder(&settings)?;
+        let load = move |files: &PemFiles| load(files, &settings);
+        let certificates = Arc::new(ArcSwap::from_pointee(self.files.load(&load)?));
+
+        // Each connection switches to the context holding the certificate for
+        // its host name - OpenSSL calls this even if the client doesn't use
+        // SNI.
+        let selector = certificates.clone();
+        builder.set_servername_callback(move |ssl, _| {
+            let certificates = selector.load();
+            let context = certificates.find(ssl.servername(NameType::HOST_NAME));
+            ssl.set_ssl_context(context)
+                .map_err(|_| SniError::ALERT_FATAL)
+        });
+
+        if let Some(interval) = self.reload_interval {
+            watch(Arc::downgrade(&certificates), self.files, interval, load);
+        }
+
+        Ok(OpensslAcceptor {
+            acceptor: builder.build(),
+            handshake_timeout: self.handshake_timeout,
+        })
+    }
+}
+
+/// Accepts TLS connections using OpenSSL.
+#[derive(Clone)]
+pub struct OpensslAcceptor {
+    acceptor: SslAcceptor,
+    handshake_timeout: Duration,
+}
+
+impl OpensslAcceptor {
+    /// Create an acceptor from an OpenSSL acceptor, for cases [`TlsConfig`]
+    /// doesn't cover.
+    pub fn from_acceptor(acceptor: SslAcceptor) -> Self {
+        OpensslAcceptor {
+            acceptor,
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+        }
+    }
+}
+
+impl fmt::Debug for OpensslAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OpensslAcceptor")
+            .field("handshake_timeout", &self.handshake_timeout)
+            .finish()
+    }
+}
+
+impl<IO> Acceptor<IO> for OpensslAcceptor
+where
+    IO: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Stream = TokioIo<SslStream<TokioIo<IO>>>;
+
+    fn accept(&self, io: IO) -> BoxFuture<'static, io::Result<Self::Stream>> {
+        let ssl = Ssl::new(self.acceptor.context());
+        Box::pin(async move {
+            let mut stream = SslStream::new(ssl.map_err(io::Error::other)?, TokioIo::new(io))
+                .map_err(io::Error::other)?;
+            Pin::new(&mut stream)
+                .accept()
+                .await
+                .map_err(|e| e.into_io_error().unwrap_or_else(io::Error::other))?;
+            Ok(TokioIo::new(stream))
+        })
+    }
+
+    fn handshake_timeout(&self) -> Duration {
+        self.handshake_timeout
+    }
+
+    fn client_certificate(stream: &Self::Stream) -> Option<ClientCertificate> {
+        let ssl = stream.inner().ssl();
+        let cert = ssl.peer_certificate()?;
+        // On the server, the peer's chain doesn't include its certificate
+        let chain = std::iter::once(cert.as_ref())
+            .chain(ssl.peer_cert_chain().into_iter().flatten())
+            .filter_map(|cert| cert.to_der().ok())
+            .collect();
+        ClientCertificate::from_chain(chain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{TestCa, TestCert};
+    use super::super::TlsListener;
+    use super::*;
+    use crate::server::Listener;
+    use openssl::ssl::{SslConnector, StatusType};
+    use std::net::SocketAddr;
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Connect, returning the server's certificate and the negotiated
+    /// protocol. The certificate is compared rather than verified.
+    async fn connect(addr: SocketAddr, name: &str) -> (Vec<u8>, Option<Vec<u8>>) {
+        let mut connector = SslConnector::builder(SslMethod::tls_client()).unwrap();
+        connector.set_verify(SslVerifyMode::NONE);
+        connector.set_alpn_protos(b"\x08http/1.1\x02h2").unwrap();
+        let ssl = connector
+            .build()
+            .configure()
+            .unwrap()
+            .into_ssl(name)
+            .unwrap();
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut stream = SslStream::new(ssl, TokioIo::new(stream)).unwrap();
+        Pin::new(&mut stream).connect().await.unwrap();
+        (
+            stream.ssl().peer_certificate().unwrap().to_der().unwrap(),
+            stream.ssl().selected_alpn_protocol().map(<[u8]>::to_vec),
+        )
+    }
+
+    #[tokio::test]
+    async fn serves_and_reloads_certificates() {
+        let dir = std::env::temp_dir().join(format!("swagger-openssl-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (first, other, renewed) = (
+            TestCert::new("localhost"),
+            TestCert::new("api.other.test"),
+            TestCert::new("localhost"),
+        );
+
+        let (cert, key) = first.write(&dir, "default");
+        let (other_cert, other_key) = other.write(&dir, "other");
+        let acceptor = TlsConfig::new(&cert, &key)
+            .sni("*.other.test", other_cert, other_key)
+            .alpn_protocols(["h2", "http/1.1"])
+            .reload_interval(Duration::from_millis(20))
+            .build_openssl()
+            .unwrap();
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let mut listener = TlsListener::new(tcp, acceptor);
+        tokio::spawn(async move {
+            // Keep the connections open until the test ends
+            let mut connections = Vec::new();
+            while let Ok((io, _)) = listener.accept().await {
+                connections.push(io);
+            }
+        });
+
+        let (cert, protocol) = connect(addr, "localhost").await;
+        assert_eq!(cert, first.cert.der().to_vec());
+        assert_eq!(protocol.as_deref(), Some(&b"h2"[..]));
+        let (cert, _) = connect(addr, "api.other.test").await;
+        assert_eq!(cert, other.cert.der().to_vec());
+        // Clients connecting by IP address don't use SNI
+        let (cert, _) = connect(addr, "127.0.0.1").await;
+        assert_eq!(cert, first.cert.der().to_vec());
+
+        renewed.write(&dir, "default");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let (cert, _) = connect(addr, "localhost").await;
+        assert_eq!(cert, renewed.cert.der().to_vec());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn staples_ocsp_and_accepts_optional_client_certificates() {
+        let dir = std::env::temp_dir().join(format!("swagger-openssl-mtls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let server = TestCert::new("localhost");
+        let ca = TestCa::new();
+        let client = ca.issue("client");
+
+        let (cert, key) = server.write(&dir, "server");
+        let ocsp = dir.join("server.ocsp");
+        std::fs::write(&ocsp, b"ocsp response").unwrap();
+        let acceptor = TlsConfig::new(cert, key)
+            .ocsp_response(ocsp)
+            .client_auth(ca.write(&dir), ClientAuth::Optional)
+            .build_openssl()
+            .unwrap();
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let mut listener = TlsListener::new(tcp, acceptor);
+
+        let connect = |client: Option<&TestCert>| {
+            let mut connector = SslConnector::builder(SslMethod::tls_client()).unwrap();
+            connector.set_verify(SslVerifyMode::NONE);
+            if let Some(client) = client {
+                let cert = X509::from_der(client.cert.der()).unwrap();
+                let key = PKey::private_key_from_der(&client.key.serialize_der()).unwrap();
+                connector.set_certificate(&cert).unwrap();
+                connector.set_private_key(&key).unwrap();
+            }
+            let mut ssl = connector
+                .build()
+                .configure()
+                .unwrap()
+                .into_ssl("localhost")
+                .unwrap();
+            ssl.set_status_type(StatusType::OCSP).unwrap();
+            async move {
+                let stream = TcpStream::connect(addr).await.unwrap();
+                let mut stream = SslStream::new(ssl, TokioIo::new(stream)).unwrap();
+                Pin::new(&mut stream).connect().await.unwrap();
+                stream
+            }
+        };
+
+        let (stream, accepted) = tokio::join!(connect(None), listener.accept());
+        assert_eq!(stream.ssl().ocsp_status(), Some(&b"ocsp response"[..]));
+        assert!(accepted.unwrap().1.client_certificate().is_none());
+
+        let (_stream, accepted) = tokio::join!(connect(Some(&client)), listener.accept());
+        let target = accepted.unwrap().1;
+        let chain: Vec<_> = target.client_certificate().unwrap().chain().collect();
+        assert_eq!(chain, [client.cert.der().as_ref()]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,315 @@
+//! TLS acceptor using rustls.
+
+use super::{
+    invalid, watch, Acceptor, Certificates, ClientAuth, ClientCertificate, PemFiles, TlsConfig,
+    DEFAULT_HANDSHAKE_TIMEOUT,
+};
+use arc_swap::ArcSwap;
+use futures::future::{BoxFuture, FutureExt};
+use rustls::crypto::ring;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, CertificateRevocationListDer, PrivateKeyDer};
+use rustls::server::danger::ClientCertVerifier;
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{RootCertStore, ServerConfig};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+fn load(files: &PemFiles) -> io::Result<Arc<CertifiedKey>> {
+    let certs = CertificateDer::pem_slice_iter(&std::fs::read(&files.cert)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(&files.cert, e))?;
+    if certs.is_empty() {
+        return Err(invalid(&files.cert, "no certificates found"));
+    }
+    let key = PrivateKeyDer::from_pem_slice(&std::fs::read(&files.key)?)
+        .map_err(|e| invalid(&files.key, e))?;
+    let key = ring::sign::any_supported_type(&key).map_err(|e| invalid(&files.key, e))?;
+
+    let mut certified = CertifiedKey::new(certs, key);
+    certified.ocsp = files.read_ocsp()?;
+    Ok(Arc::new(certified))
+}
+
+/// Verifies client certificates against the CA certificates in a PEM file.
+fn client_verifier(
+    mode: ClientAuth,
+    ca_path: &Path,
+    crl_paths: &[PathBuf],
+) -> io::Result<Arc<dyn ClientCertVerifier>> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_slice_iter(&std::fs::read(ca_path)?) {
+        roots
+            .add(cert.map_err(|e| invalid(ca_path, e))?)
+            .map_err(|e| invalid(ca_path, e))?;
+    }
+    let mut crls = Vec::new();
+    for path in crl_paths {
+        for crl in CertificateRevocationListDer::pem_slice_iter(&std::fs::read(path)?) {
+            crls.push(crl.map_err(|e| invalid(path, e))?);
+        }
+    }
+
+    let mut builder = WebPkiClientVerifier::builder_with_provider(
+        Arc::new(roots),
+        Arc::new(ring::default_provider()),
+    )
+    .with_crls(crls);
+    if mode == ClientAuth::Optional {
+        builder = builder.allow_unauthenticated();
+    }
+    builder.build().map_err(io::Error::other)
+}
+
+/// Selects the certificate for each connection by SNI.
+#[derive(Debug)]
+struct Resolver {
+    certificates: Arc<ArcSwap<Certificates<Arc<CertifiedKey>>>>,
+}
+
+impl ResolvesServerCert for Resolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(
+            self.certificates
+                .load()
+                .find(client_hello.server_name())
+                .clone(),
+        )
+    }
+}
+
+impl TlsConfig {
+    /// Load the certificates and build an acceptor using rustls.
+    ///
+    /// If the files are to be reloaded, this must be called from within a
+    /// Tokio runtime.
+    pub fn build_rustls(self) -> io::Result<RustlsAcceptor> {
+        let certificates = Arc::new(ArcSwap::from_pointee(self.files.load(load)?));
+
+        let builder = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?;
+        let builder = match &self.client_auth {
+            Some((mode, ca_path)) => {
+                builder.with_client_cert_verifier(client_verifier(*mode, ca_path, &self.crls)?)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let mut config = builder.with_cert_resolver(Arc::new(Resolver {
+            certificates: certificates.clone(),
+        }));
+        config.alpn_protocols = self.alpn_protocols;
+
+        if let Some(interval) = self.reload_interval {
+            watch(Arc::downgrade(&certificates), self.files, interval, load);
+        }
+
+        Ok(RustlsAcceptor {
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+            handshake_timeout: self.handshake_timeout,
+        })
+    }
+}
+
+/// Accepts TLS connections using rustls.
+#[derive(Clone)]
+pub struct RustlsAcceptor {
+    acceptor: TlsAcceptor,
+    handshake_timeout: Duration,
+}
+
+impl RustlsAcceptor {
+    /// Create an acceptor from a rustls configuration, for cases
+    /// [`TlsConfig`] doesn't cover.
+    pub fn from_config(config: Arc<ServerConfig>) -> Self {
+        RustlsAcceptor {
+            acceptor: TlsAcceptor::from(config),
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+        }
+    }
+}
+
+impl fmt::Debug for RustlsAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RustlsAcceptor")
+            .field("config", self.acceptor.config())
+            .field("handshake_timeout", &self.handshake_timeout)
+            .finish()
+    }
+}
+
+impl<IO> Acceptor<IO> for RustlsAcceptor
+where
+    IO: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Stream = TlsStream<IO>;
+
+    fn accept(&self, io: IO) -> BoxFuture<'static, io::Result<Self::Stream>> {
+        self.acceptor.accept(io).boxed()
+    }
+
+    fn handshake_timeout(&self) -> Duration {
+        self.handshake_timeout
+    }
+
+    fn client_certificate(stream: &Self::Stream) -> Option<ClientCertificate> {
+        let certs = stream.get_ref().1.peer_certificates()?;
+        ClientCertificate::from_chain(certs.iter().map(|cert| cert.to_vec()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{TestCa, TestCert};
+    use super::super::TlsListener;
+    use super::*;
+    use crate::server::Listener;
+    use rustls::pki_types::ServerName;
+    use rustls::ClientConfig;
+    use std::net::SocketAddr;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc;
+    use tokio_rustls::TlsConnector;
+
+    /// Connect, returning the server's certificate.
+    async fn connect(
+        roots: &RootCertStore,
+        addr: SocketAddr,
+        name: &'static str,
+    ) -> CertificateDer<'static> {
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots.clone())
+            .with_no_client_auth();
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let stream = TlsConnector::from(Arc::new(config))
+            .connect(ServerName::try_from(name).unwrap(), stream)
+            .await
+            .unwrap();
+        stream.get_ref().1.peer_certificates().unwrap()[0].clone()
+    }
+
+    #[tokio::test]
+    async fn serves_and_reloads_certificates() {
+        let dir = std::env::temp_dir().join(format!("swagger-rustls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (first, other, renewed) = (
+            TestCert::new("localhost"),
+            TestCert::new("api.other.test"),
+            TestCert::new("localhost"),
+        );
+        let mut roots = RootCertStore::empty();
+        for test_cert in [&first, &other, &renewed] {
+            roots.add(test_cert.cert.der().clone()).unwrap();
+        }
+
+        let (cert, key) = first.write(&dir, "default");
+        let (other_cert, other_key) = other.write(&dir, "other");
+        let acceptor = TlsConfig::new(&cert, &key)
+            .sni("*.other.test", other_cert, other_key)
+            .reload_interval(Duration::from_millis(20))
+            .build_rustls()
+            .unwrap();
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let mut listener = TlsListener::new(tcp, acceptor);
+        tokio::spawn(async move {
+            // Keep the connections open until the test ends
+            let mut connections = Vec::new();
+            while let Ok((io, _)) = listener.accept().await {
+                connections.push(io);
+            }
+        });
+
+        assert_eq!(&connect(&roots, addr, "localhost").await, first.cert.der());
+        assert_eq!(
+            &connect(&roots, addr, "api.other.test").await,
+            other.cert.der()
+        );
+
+        renewed.write(&dir, "default");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(
+            &connect(&roots, addr, "localhost").await,
+            renewed.cert.der()
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn verifies_client_certificates() {
+        let dir = std::env::temp_dir().join(format!("swagger-rustls-mtls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let server = TestCert::new("localhost");
+        let ca = TestCa::new();
+        let (client, untrusted) = (ca.issue("client"), TestCert::new("client"));
+
+        let (cert, key) = server.write(&dir, "server");
+        let acceptor = TlsConfig::new(cert, key)
+            .client_auth(ca.write(&dir), ClientAuth::Required)
+            .build_rustls()
+            .unwrap();
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let mut listener = TlsListener::new(tcp, acceptor);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((io, target)) = listener.accept().await {
+                connections.push(io);
+                tx.send(target).unwrap();
+            }
+        });
+
+        let mut roots = RootCertStore::empty();
+        roots.add(server.cert.der().clone()).unwrap();
+        let connect = |client: Option<&TestCert>| {
+            let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots.clone());
+            let config = match client {
+                Some(client) => builder
+                    .with_client_auth_cert(
+                        vec![client.cert.der().clone()],
+                        PrivateKeyDer::try_from(client.key.serialize_der()).unwrap(),
+                    )
+                    .unwrap(),
+                None => builder.with_no_client_auth(),
+            };
+            async move {
+                let stream = TcpStream::connect(addr).await.unwrap();
+                // With TLS 1.3, the server rejects the client's certificate
+                // after the client has completed the handshake.
+                let _ = TlsConnector::from(Arc::new(config))
+                    .connect(ServerName::try_from("localhost").unwrap(), stream)
+                    .await;
+            }
+        };
+
+        connect(None).await;
+        connect(Some(&untrusted)).await;
+        connect(Some(&client)).await;
+
+        let target = rx.recv().await.unwrap();
+        assert_eq!(target.target().ip(), addr.ip());
+        assert_eq!(
+            target.client_certificate().unwrap().der(),
+            client.cert.der().as_ref()
+        );
+        assert!(tokio::time::timeout(Duration::from_millis(100), rx.recv())
+            .await
+            .is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
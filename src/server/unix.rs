@@ -0,0 +1,232 @@
+//! Serving connections on Unix domain sockets.
+//!
+//! [`UnixSocket`] binds a [`UnixListener`] to a socket path, setting the
+//! socket file's permissions and owner so that only the intended clients -
+//! such as a sidecar proxy - can connect, and removing stale socket files
+//! left behind by a previous process. The socket file is removed when the
+//! listener is dropped.
+//!
+//! Connections are passed to the `MakeService` with the [`PeerCredentials`]
+//! of the connecting process, which `IntoMakeServiceWithConnectInfo` can add
+//! to each request's context as `ConnectInfo<PeerCredentials>`.
+//!
+//! ```no_run
+//! # async fn run<M, S, B>(make_service: M) -> std::io::Result<()>
+//! # where
+//! #     M: hyper::service::Service<swagger::server::unix::PeerCredentials, Response = S>,
+//! #     M::Future: Send + 'static,
+//! #     M::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! #     S: hyper::service::Service<hyper::Request<hyper::body::Incoming>, Response = hyper::Response<B>> + Send + 'static,
+//! #     S::Future: Send + 'static,
+//! #     S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! #     B: hyper::body::Body + Send + 'static,
+//! #     B::Data: Send,
+//! #     B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! # {
+//! use swagger::server::unix::UnixSocket;
+//! use swagger::server::Server;
+//!
+//! let listener = UnixSocket::new("/run/pet-store/api.sock")
+//!     .mode(0o660)
+//!     .owner(None, Some(1000))
+//!     .bind()?;
+//!
+//! Server::new(listener).serve(make_service).await
+//! # }
+//! ```
+
+use super::{Listener, Server};
+use crate::make::Connected;
+use futures::future::BoxFuture;
+use std::fs::Permissions;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use tokio::net::UnixStream;
+
+/// The credentials of the process at the other end of a Unix domain socket
+/// connection, as reported by the operating system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCredentials {
+    /// The user ID of the process.
+    pub uid: u32,
+    /// The group ID of the process.
+    pub gid: u32,
+    /// The ID of the process, on platforms which report it.
+    pub pid: Option<i32>,
+}
+
+impl Connected<PeerCredentials> for PeerCredentials {
+    fn connect_info(target: &PeerCredentials) -> Self {
+        *target
+    }
+}
+
+/// Builder for [`UnixListener`]s.
+#[derive(Debug, Clone)]
+pub struct UnixSocket {
+    path: PathBuf,
+    mode: Option<u32>,
+    owner: Option<(Option<u32>, Option<u32>)>,
+    remove_stale: bool,
+}
+
+impl UnixSocket {
+    /// Listen on a socket at the given path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        UnixSocket {
+            path: path.into(),
+            mode: None,
+            owner: None,
+            remove_stale: true,
+        }
+    }
+
+    /// Set the permissions of the socket file, such as `0o660`. Clients need
+    /// write permission to connect.
+    ///
+    /// By default, the permissions are set by the process's umask.
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Set the user and group which own the socket file. This normally
+    /// requires the process to be privileged, or, to change only the group,
+    /// to be a member of it.
+    pub fn owner(mut self, uid: Option<u32>, gid: Option<u32>) -> Self {
+        self.owner = Some((uid, gid));
+        self
+    }
+
+    /// Set whether to remove a socket file left at the path by a process
+    /// which is no longer listening on it.
+    ///
+    /// Defaults to `true`. Files which aren't sockets, or sockets which are
+    /// still being listened on, are never removed.
+    pub fn remove_stale(mut self, remove_stale: bool) -> Self {
+        self.remove_stale = remove_stale;
+        self
+    }
+
+    /// Bind the listener.
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub fn bind(self) -> io::Result<UnixListener> {
+        if self.remove_stale && is_stale(&self.path)? {
+            std::fs::remove_file(&self.path)?;
+        }
+
+        let inner = tokio::net::UnixListener::bind(&self.path)?;
+        // From here, the listener removes the socket file if setup fails
+        let listener = UnixListener {
+            inner,
+            path: self.path,
+        };
+        if let Some((uid, gid)) = self.owner {
+            std::os::unix::fs::chown(&listener.path, uid, gid)?;
+        }
+        if let Some(mode) = self.mode {
+            std::fs::set_permissions(&listener.path, Permissions::from_mode(mode))?;
+        }
+        Ok(listener)
+    }
+}
+
+/// Whether there is a socket file at the path which nothing is listening on.
+fn is_stale(path: &Path) -> io::Result<bool> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            match std::os::unix::net::UnixStream::connect(path) {
+                Ok(_) => Ok(false),
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => Ok(true),
+                Err(e) => Err(e),
+            }
+        }
+        Ok(_) => Ok(false),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// [`Listener`] accepting connections on a Unix domain socket, which removes
+/// the socket file when dropped.
+#[derive(Debug)]
+pub struct UnixListener {
+    inner: tokio::net::UnixListener,
+    path: PathBuf,
+}
+
+impl UnixListener {
+    /// The path of the socket file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for UnixListener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl Listener for UnixListener {
+    type Io = UnixStream;
+    type Target = PeerCredentials;
+
+    fn accept(&mut self) -> BoxFuture<'_, io::Result<(Self::Io, Self::Target)>> {
+        Box::pin(async move {
+            let (stream, _) = self.inner.accept().await?;
+            let credentials = stream.peer_cred()?;
+            let credentials = PeerCredentials {
+                uid: credentials.uid(),
+                gid: credentials.gid(),
+                pid: credentials.pid(),
+            };
+            Ok((stream, credentials))
+        })
+    }
+}
+
+impl Server<UnixListener> {
+    /// Bind a Unix domain socket listener to the given path, with the default
+    /// options of [`UnixSocket`], and create a server for it.
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub fn bind_unix(path: impl Into<PathBuf>) -> io::Result<Self> {
+        Ok(Self::new(UnixSocket::new(path).bind()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+
+    #[tokio::test]
+    async fn accepts_connections_with_credentials() {
+        let dir = std::env::temp_dir().join(format!("swagger-unix-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("api.sock");
+
+        // A socket file left behind by a previous process is replaced
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let mut listener = UnixSocket::new(&path).mode(0o600).bind().unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(metadata.mode() & 0o777, 0o600);
+
+        // A socket which is being listened on isn't
+        let error = UnixSocket::new(&path).bind().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+
+        let (_client, accepted) = tokio::join!(UnixStream::connect(&path), listener.accept());
+        let (_, credentials) = accepted.unwrap();
+        assert_eq!(credentials.uid, metadata.uid());
+        assert_eq!(credentials.gid, metadata.gid());
+        assert_eq!(credentials.pid, Some(std::process::id() as i32));
+
+        drop(listener);
+        assert!(!path.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
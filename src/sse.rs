@@ -0,0 +1,449 @@
+//! Server-Sent Events, as described in the HTML Living Standard.
+//!
+//! `SseBody` streams a sequence of `Event`s as a `text/event-stream`
+//! response, optionally sending keep-alive comments so that idle connections
+//! aren't closed by proxies. `SseStream` is the inverse, for clients reading
+//! such a response.
+//!
+//! ```rust
+//! # use futures::stream::{self, TryStreamExt};
+//! # use std::time::Duration;
+//! # use swagger::sse::{Event, SseBody, SseStream};
+//! # async fn example() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! let events = stream::iter([
+//!     Event::new().event("greeting").data("Hello,\nWorld!"),
+//!     Event::new().id("2").data("Bye"),
+//! ]);
+//! let response = SseBody::new(events)
+//!     .keep_alive(Duration::from_secs(15))
+//!     .into_response();
+//!
+//! let events: Vec<Event> = SseStream::new(response.into_body()).try_collect().await?;
+//! assert_eq!(events[0].event_type(), Some("greeting"));
+//! assert_eq!(events[0].data_str(), Some("Hello,\nWorld!"));
+//! assert_eq!(events[1].id_str(), Some("2"));
+//! # Ok(())
+//! # }
+//! ```
+
+use futures::stream::{BoxStream, Stream, StreamExt};
+use hyper::body::{Body, Bytes, Frame};
+use hyper::header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE};
+use hyper::Response;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{sleep, Instant, Sleep};
+
+/// The `text/event-stream` media type.
+pub const TEXT_EVENT_STREAM: &str = "text/event-stream";
+
+/// A Server-Sent Event.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Event {
+    data: Option<String>,
+    event: Option<String>,
+    id: Option<String>,
+    retry: Option<Duration>,
+    comment: Option<String>,
+}
+
+impl Event {
+    /// Create an empty event.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the data of the event. Data containing newlines is sent as
+    /// multiple `data` lines.
+    pub fn data(mut self, data: impl Into<String>) -> Self {
+        self.data = Some(data.into());
+        self
+    }
+
+    /// Set the data of the event to `value` serialized as JSON.
+    #[cfg(feature = "serdejson")]
+    pub fn json_data<T: serde::Serialize>(self, value: &T) -> Result<Self, serde_json::Error> {
+        Ok(self.data(serde_json::to_string(value)?))
+    }
+
+    /// Set the type of the event, used by browsers to pick the event
+    /// listener.
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// Set the ID of the event, which clients send in the `Last-Event-ID`
+    /// header when reconnecting.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Set how long clients should wait before reconnecting.
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Add a comment, which clients ignore.
+    pub fn comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// The data of the event.
+    pub fn data_str(&self) -> Option<&str> {
+        self.data.as_deref()
+    }
+
+    /// The data of the event, deserialized from JSON.
+    #[cfg(feature = "serdejson")]
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_str(self.data.as_deref().unwrap_or_default())
+    }
+
+    /// The type of the event.
+    pub fn event_type(&self) -> Option<&str> {
+        self.event.as_deref()
+    }
+
+    /// The ID of the event - for received events, the last ID sent.
+    pub fn id_str(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// How long clients should wait before reconnecting.
+    pub fn retry_duration(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    /// Encode the event in the `text/event-stream` format.
+    pub fn to_bytes(&self) -> Bytes {
+        // Fields can't contain line breaks, other than by using multiple
+        // data or comment lines.
+        fn field(out: &mut String, name: &str, value: &str) {
+            for line in value.split(['\n', '\r']) {
+                out.push_str(name);
+                out.push_str(": ");
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+
+        let mut out = String::new();
+        if let Some(comment) = &self.comment {
+            for line in comment.split(['\n', '\r']) {
+                out.push_str(": ");
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+        if let Some(event) = &self.event {
+            field(&mut out, "event", &event.replace(['\n', '\r'], " "));
+        }
+        if let Some(id) = &self.id {
+            field(&mut out, "id", &id.replace(['\n', '\r', '\0'], " "));
+        }
+        if let Some(retry) = self.retry {
+            field(&mut out, "retry", &retry.as_millis().to_string());
+        }
+        if let Some(data) = &self.data {
+            field(&mut out, "data", data);
+        }
+        out.push('\n');
+        Bytes::from(out)
+    }
+}
+
+/// Comment sent to keep idle connections open.
+const KEEP_ALIVE: &[u8] = b":\n\n";
+
+/// A body streaming Server-Sent Events.
+pub struct SseBody {
+    events: BoxStream<'static, Result<Event, Box<dyn Error + Send + Sync>>>,
+    keep_alive: Option<Duration>,
+    // Created on first poll, so the body can be created outside a runtime
+    timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl fmt::Debug for SseBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SseBody")
+            .field("keep_alive", &self.keep_alive)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SseBody {
+    /// Create a body sending each event of `events`.
+    pub fn new<S>(events: S) -> Self
+    where
+        S: Stream<Item = Event> + Send + 'static,
+    {
+        Self::from_try_stream(events.map(Ok::<_, std::convert::Infallible>))
+    }
+
+    /// Create a body sending each event of `events`. An error ends the body
+    /// with an error.
+    pub fn from_try_stream<S, E>(events: S) -> Self
+    where
+        S: Stream<Item = Result<Event, E>> + Send + 'static,
+        E: Into<Box<dyn Error + Send + Sync>> + 'static,
+    {
+        SseBody {
+            events: events.map(|event| event.map_err(Into::into)).boxed(),
+            keep_alive: None,
+            timer: None,
+        }
+    }
+
+    /// Send a keep-alive comment whenever no event has been sent for the
+    /// given interval.
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = Some(interval);
+        self
+    }
+
+    /// Create a `200 OK` response streaming the events, with a
+    /// `text/event-stream` content type, and caching disabled.
+    pub fn into_response(self) -> Response<SseBody> {
+        let mut response = Response::new(self);
+        let headers = response.headers_mut();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(TEXT_EVENT_STREAM));
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        response
+    }
+}
+
+impl Body for SseBody {
+    type Data = Bytes;
+    type Error = Box<dyn Error + Send + Sync>;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = &mut *self;
+        match this.events.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok(event))) => {
+                if let (Some(interval), Some(timer)) = (this.keep_alive, &mut this.timer) {
+                    timer.as_mut().reset(Instant::now() + interval);
+                }
+                Poll::Ready(Some(Ok(Frame::data(event.to_bytes()))))
+            }
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => {
+                let Some(interval) = this.keep_alive else {
+                    return Poll::Pending;
+                };
+                let timer = this.timer.get_or_insert_with(|| Box::pin(sleep(interval)));
+                match timer.as_mut().poll(cx) {
+                    Poll::Ready(()) => {
+                        timer.as_mut().reset(Instant::now() + interval);
+                        Poll::Ready(Some(Ok(Frame::data(Bytes::from_static(KEEP_ALIVE)))))
+                    }
+                    Poll::Pending => Poll::Pending,
+                }
+            }
+        }
+    }
+}
+
+/// A stream of events read from a `text/event-stream` body.
+///
+/// Comments are skipped, and the ID of each event is the last ID sent, as in
+/// browsers.
+pub struct SseStream<B> {
+    body: B,
+    buffer: Vec<u8>,
+    done: bool,
+    started: bool,
+    last_event_id: Option<String>,
+    pending: Event,
+}
+
+impl<B> fmt::Debug for SseStream<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SseStream")
+            .field("last_event_id", &self.last_event_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B> SseStream<B> {
+    /// Read events from `body`.
+    pub fn new(body: B) -> Self {
+        SseStream {
+            body,
+            buffer: Vec::new(),
+            done: false,
+            started: false,
+            last_event_id: None,
+            pending: Event::default(),
+        }
+    }
+
+    /// The last event ID received, to send in the `Last-Event-ID` header when
+    /// reconnecting.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// Take the next line from the buffer. Lines end with CR, LF or CRLF.
+    fn next_line(&mut self) -> Option<String> {
+        let end = self.buffer.iter().position(|&b| b == b'\r' || b == b'\n');
+        let (end, len) = match end {
+            // Wait to see whether a CR is followed by LF
+            Some(end)
+                if end + 1 == self.buffer.len() && self.buffer[end] == b'\r' && !self.done =>
+            {
+                return None
+            }
+            Some(end) if self.buffer[end..].starts_with(b"\r\n") => (end, 2),
+            Some(end) => (end, 1),
+            None if self.done && !self.buffer.is_empty() => (self.buffer.len(), 0),
+            None => return None,
+        };
+        let line: Vec<u8> = self.buffer.drain(..end + len).take(end).collect();
+        Some(String::from_utf8_lossy(&line).into_owned())
+    }
+
+    /// Process a line, returning an event if it ends one.
+    fn process_line(&mut self, line: &str) -> Option<Event> {
+        if line.is_empty() {
+            let mut event = std::mem::take(&mut self.pending);
+            event.id = self.last_event_id.clone();
+            let data = event.data.as_mut()?;
+            if data.ends_with('\n') {
+                data.pop();
+            }
+            return Some(event);
+        }
+
+        let (name, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match name {
+            // A comment
+            "" => {}
+            "data" => {
+                let data = self.pending.data.get_or_insert_with(String::new);
+                data.push_str(value);
+                data.push('\n');
+            }
+            "event" => self.pending.event = Some(value.to_string()),
+            "id" if !value.contains('\0') => self.last_event_id = Some(value.to_string()),
+            "retry" => {
+                if let Ok(millis) = value.parse() {
+                    self.pending.retry = Some(Duration::from_millis(millis));
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+impl<B> Stream for SseStream<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    type Item = Result<Event, Box<dyn Error + Send + Sync>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            while let Some(line) = self.next_line() {
+                if let Some(event) = self.process_line(&line) {
+                    return Poll::Ready(Some(Ok(event)));
+                }
+            }
+            if self.done {
+                // An incomplete event at the end of the stream is discarded
+                return Poll::Ready(None);
+            }
+
+            match Pin::new(&mut self.body).poll_frame(cx) {
+                Poll::Ready(Some(Ok(frame))) => {
+                    if let Ok(mut data) = frame.into_data() {
+                        if !self.started && !data.is_empty() {
+                            self.started = true;
+                            if data.starts_with("\u{feff}".as_bytes()) {
+                                data = data.slice(3..);
+                            }
+                        }
+                        self.buffer.extend_from_slice(&data);
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => {
+                    self.done = true;
+                    self.buffer.clear();
+                    return Poll::Ready(Some(Err(e.into())));
+                }
+                Poll::Ready(None) => self.done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream::{self, TryStreamExt};
+    use http_body_util::{BodyExt, StreamBody};
+
+    #[tokio::test]
+    async fn encodes_and_decodes_events() {
+        let event = Event::new()
+            .comment("hi")
+            .event("update")
+            .id("1")
+            .retry(Duration::from_secs(3))
+            .data("a\nb");
+        assert_eq!(
+            event.to_bytes(),
+            ": hi\nevent: update\nid: 1\nretry: 3000\ndata: a\ndata: b\n\n"
+        );
+
+        // Events split across chunks, with all line endings, and events
+        // without data, which are ignored
+        let chunks = [
+            "\u{feff}: comment\r\nid: 7\r",
+            "\ndata:x\rdata:  y\n\nevent: ping\n\ndata",
+            ": z\n\nretry: 10\ndata: incomplete",
+        ];
+        let body =
+            StreamBody::new(stream::iter(chunks.map(|chunk| {
+                Ok::<_, std::convert::Infallible>(Frame::data(Bytes::from(chunk)))
+            })));
+        let mut events = SseStream::new(body);
+        let first = events.try_next().await.unwrap().unwrap();
+        assert_eq!(first.data_str(), Some("x\n y"));
+        assert_eq!(first.id_str(), Some("7"));
+        let second = events.try_next().await.unwrap().unwrap();
+        assert_eq!(second, Event::new().id("7").data("z"));
+        assert!(events.try_next().await.unwrap().is_none());
+        assert_eq!(events.last_event_id(), Some("7"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sends_keep_alives() {
+        let events = stream::once(async {
+            sleep(Duration::from_secs(25)).await;
+            Event::new().data("done")
+        });
+        let mut body = SseBody::new(events).keep_alive(Duration::from_secs(10));
+
+        let mut frames = Vec::new();
+        while let Some(frame) = body.frame().await {
+            frames.push(frame.unwrap().into_data().unwrap());
+        }
+        assert_eq!(frames, [":\n\n", ":\n\n", "data: done\n\n"]);
+    }
+}
@@ -0,0 +1,298 @@
+//! Builder for assembling a stack of middleware around an API.
+//!
+//! Writing out the types for a stack of context-carrying middleware by hand is
+//! error prone, as each layer's context type depends on the layers outside it.
+//! `StackBuilder` works these types out when the stack is built.
+//!
+//! Layers are added from the inside out, starting with the one closest to the
+//! API, and the context added by `AddContextMakeService` is always outermost.
+//!
+//! ```rust
+//! # use swagger::{EmptyContext, StackBuilder};
+//! # fn stack<T>(api_make_service: T) {
+//! let make_service = StackBuilder::new(api_make_service)
+//!     .with_allow_all_auth("cosmo")
+//!     .with_context::<EmptyContext>()
+//!     .build();
+//! # }
+//! ```
+
+use crate::auth::{MakeAllowAllAuthenticator, RcBound};
+use crate::{AddContextMakeService, AddStateMakeService, EmptyContext, Push, XSpanIdString};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// Describes how a layer changes the context passed through it.
+///
+/// `Context` is the type of context the layer receives, and `InnerContext` is
+/// the type of context it passes on to the service it wraps.
+pub trait ContextLayer<Context> {
+    /// The context type passed to the wrapped service.
+    type InnerContext;
+}
+
+/// A layer which can be added to a `StackBuilder`. Layers must also implement
+/// `ContextLayer` to describe the context passed to the service they wrap.
+pub trait StackLayer<Inner, Context> {
+    /// The `MakeService` resulting from applying this layer.
+    type Output;
+
+    /// Wrap the inner `MakeService` in this layer.
+    fn layer(self, inner: Inner) -> Self::Output;
+}
+
+/// Layer which doesn't change the context, created from a function wrapping
+/// the inner `MakeService`. See `StackBuilder::with_layer_fn`.
+#[derive(Clone)]
+pub struct LayerFn<F>(F);
+
+impl<F> fmt::Debug for LayerFn<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LayerFn")
+    }
+}
+
+impl<F, Context> ContextLayer<Context> for LayerFn<F> {
+    type InnerContext = Context;
+}
+
+impl<F, Inner, Context, Output> StackLayer<Inner, Context> for LayerFn<F>
+where
+    F: FnOnce(Inner) -> Output,
+{
+    type Output = Output;
+
+    fn layer(self, inner: Inner) -> Self::Output {
+        (self.0)(inner)
+    }
+}
+
+/// Layer adding a `MakeAllowAllAuthenticator`.
+#[derive(Clone, Debug)]
+pub struct AllowAllAuthLayer {
+    subject: String,
+}
+
+impl<Context> ContextLayer<Context> for AllowAllAuthLayer
+where
+    Context: RcBound,
+{
+    type InnerContext = Context::Result;
+}
+
+impl<Inner, Context> StackLayer<Inner, Context> for AllowAllAuthLayer
+where
+    Context: RcBound,
+    Context::Result: Send + 'static,
+{
+    type Output = MakeAllowAllAuthenticator<Inner, Context>;
+
+    fn layer(self, inner: Inner) -> Self::Output {
+        MakeAllowAllAuthenticator::new(inner, self.subject)
+    }
+}
+
+/// Layer adding an `AddStateMakeService`.
+#[derive(Clone, Debug)]
+pub struct AddStateLayer<S> {
+    state: S,
+}
+
+impl<S, Context> ContextLayer<Context> for AddStateLayer<S>
+where
+    Context: Push<S>,
+{
+    type InnerContext = Context::Result;
+}
+
+impl<S, Inner, Context> StackLayer<Inner, Context> for AddStateLayer<S>
+where
+    Context: Push<S> + Send + 'static,
+    Context::Result: Send + 'static,
+{
+    type Output = AddStateMakeService<Inner, S, Context>;
+
+    fn layer(self, inner: Inner) -> Self::Output {
+        AddStateMakeService::new(inner, self.state)
+    }
+}
+
+/// Applies a list of layers, outermost first, to an API `MakeService`.
+///
+/// Implemented for `()` (no layers) and `(Layer, Rest)`.
+pub trait BuildStack<Api, Context> {
+    /// The resulting `MakeService`.
+    type Output;
+
+    /// Apply the layers to the API.
+    fn build(self, api: Api) -> Self::Output;
+}
+
+impl<Api, Context> BuildStack<Api, Context> for () {
+    type Output = Api;
+
+    fn build(self, api: Api) -> Self::Output {
+        api
+    }
+}
+
+type InnerOutput<Api, Context, Layer, Rest> =
+    <Rest as BuildStack<Api, <Layer as ContextLayer<Context>>::InnerContext>>::Output;
+
+impl<Api, Context, Layer, Rest> BuildStack<Api, Context> for (Layer, Rest)
+where
+    Layer: ContextLayer<Context>,
+    Rest: BuildStack<Api, <Layer as ContextLayer<Context>>::InnerContext>,
+    Layer: StackLayer<InnerOutput<Api, Context, Layer, Rest>, Context>,
+{
+    type Output = <Layer as StackLayer<InnerOutput<Api, Context, Layer, Rest>, Context>>::Output;
+
+    fn build(self, api: Api) -> Self::Output {
+        let (layer, rest) = self;
+        layer.layer(rest.build(api))
+    }
+}
+
+/// Fluent builder for a stack of middleware around an API `MakeService`.
+///
+/// See the [module documentation](self) for details.
+pub struct StackBuilder<Api, Layers, Context> {
+    api: Api,
+    layers: Layers,
+    marker: PhantomData<fn(Context)>,
+}
+
+impl<Api> StackBuilder<Api, (), EmptyContext> {
+    /// Start building a stack around the API `MakeService`.
+    ///
+    /// The stack uses `EmptyContext` unless `with_context` is called.
+    pub fn new(api: Api) -> Self {
+        StackBuilder {
+            api,
+            layers: (),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<Api, Layers, Context> StackBuilder<Api, Layers, Context> {
+    /// Add a layer outside the layers added so far.
+    pub fn with_layer<Layer>(self, layer: Layer) -> StackBuilder<Api, (Layer, Layers), Context> {
+        StackBuilder {
+            api: self.api,
+            layers: (layer, self.layers),
+            marker: PhantomData,
+        }
+    }
+
+    /// Add a layer which doesn't change the context, using a function which
+    /// wraps the inner `MakeService`.
+    pub fn with_layer_fn<F>(self, f: F) -> StackBuilder<Api, (LayerFn<F>, Layers), Context> {
+        self.with_layer(LayerFn(f))
+    }
+
+    /// Add a `MakeAllowAllAuthenticator` layer, authorizing all requests with
+    /// the given subject.
+    pub fn with_allow_all_auth<S: Into<String>>(
+        self,
+        subject: S,
+    ) -> StackBuilder<Api, (AllowAllAuthLayer, Layers), Context> {
+        self.with_layer(AllowAllAuthLayer {
+            subject: subject.into(),
+        })
+    }
+
+    /// Add an `AddStateMakeService` layer, adding a clone of `state` to the
+    /// context of every request.
+    pub fn with_state<S>(self, state: S) -> StackBuilder<Api, (AddStateLayer<S>, Layers), Context> {
+        self.with_layer(AddStateLayer { state })
+    }
+
+    /// Set the empty context type used by the outermost `AddContextMakeService`.
+    pub fn with_context<NewContext>(self) -> StackBuilder<Api, Layers, NewContext> {
+        StackBuilder {
+            api: self.api,
+            layers: self.layers,
+            marker: PhantomData,
+        }
+    }
+
+    /// Build the stack, wrapped in an `AddContextMakeService`.
+    pub fn build(self) -> AddContextMakeService<Layers::Output, Context>
+    where
+        Context: Default + Push<XSpanIdString> + Send + 'static,
+        Context::Result: Send + 'static,
+        Layers: BuildStack<Api, Context::Result>,
+    {
+        AddContextMakeService::new(self.layers.build(self.api))
+    }
+}
+
+impl<Api, Layers, Context> fmt::Debug for StackBuilder<Api, Layers, Context>
+where
+    Api: fmt::Debug,
+    Layers: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StackBuilder")
+            .field("api", &self.api)
+            .field("layers", &self.layers)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{Authorization, Scopes};
+    use crate::{ContextBuilder, Has};
+    use hyper::service::Service;
+    use hyper::Request;
+
+    type TestContext =
+        ContextBuilder<Option<Authorization>, ContextBuilder<XSpanIdString, EmptyContext>>;
+
+    struct MakeTestService;
+
+    impl<Target> Service<Target> for MakeTestService {
+        type Response = TestService;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _target: Target) -> Self::Future {
+            futures::future::ok(TestService)
+        }
+    }
+
+    struct TestService;
+
+    impl Service<(Request<()>, TestContext)> for TestService {
+        type Response = String;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (_, context): (Request<()>, TestContext)) -> Self::Future {
+            let authorization: &Option<Authorization> = context.get();
+            let authorization = authorization.as_ref().unwrap();
+            assert_eq!(authorization.scopes, Scopes::All);
+            futures::future::ok(authorization.subject.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn builds_stack() {
+        let make_service = StackBuilder::new(MakeTestService)
+            .with_allow_all_auth("cosmo")
+            .with_layer_fn(|inner| inner)
+            .with_context::<EmptyContext>()
+            .build();
+
+        let service = make_service.call(()).await.unwrap();
+        let subject = service
+            .call(Request::get("/").body(()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(subject, "cosmo");
+    }
+}
@@ -0,0 +1,274 @@
+//! Hyper service rejecting requests with query parameters their operation
+//! doesn't declare.
+//!
+//! Query parameters an operation doesn't know are normally ignored, so a
+//! client's typo - `?fliter=active` - silently returns unfiltered results.
+//! `StrictQueryService` rejects such requests with `400 Bad Request`, naming
+//! the unknown parameters, so typos are caught as soon as they're made.
+//!
+//! The parameters of each operation, identified by the API's
+//! `RequestParser`, are listed in a `QueryAllowlist`. Requests for
+//! operations which aren't listed are passed on unchecked. With the
+//! `validation` feature, the allowlist can be built from the OpenAPI
+//! document with `OpenApi::query_allowlist`.
+//!
+//! ```rust
+//! # use swagger::strict_query::QueryAllowlist;
+//! let allowlist = QueryAllowlist::new()
+//!     .operation("findPets", ["filter", "limit"])
+//!     // Cache busters are allowed on every operation
+//!     .always("_");
+//! ```
+
+use crate::query::QueryParams;
+use crate::response::coded_json_error;
+use crate::{Has, RequestParser, XSpanIdString};
+use futures::future::{BoxFuture, FutureExt};
+use hyper::service::Service;
+use hyper::{Request, Response, StatusCode};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// The query parameters declared by each operation of an API.
+#[derive(Debug, Clone, Default)]
+pub struct QueryAllowlist {
+    operations: HashMap<String, HashSet<String>>,
+    always: HashSet<String>,
+}
+
+impl QueryAllowlist {
+    /// Create an empty allowlist, checking no operations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the query parameters of an operation, identified by the ID
+    /// returned by the API's `RequestParser`.
+    pub fn operation<I, S>(mut self, operation_id: &str, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.operations.insert(
+            operation_id.to_string(),
+            names.into_iter().map(Into::into).collect(),
+        );
+        self
+    }
+
+    /// Allow a query parameter on every operation, such as an API key.
+    pub fn always(mut self, name: impl Into<String>) -> Self {
+        self.always.insert(name.into());
+        self
+    }
+
+    /// The query parameters of a request which an operation doesn't
+    /// declare, or `None` if the operation isn't checked. Parameters using
+    /// the `deepObject` style, such as `filter[name]`, are named without the
+    /// brackets.
+    pub fn unknown<'a>(&self, operation_id: &str, query: &'a QueryParams) -> Option<Vec<&'a str>> {
+        let allowed = self.operations.get(operation_id)?;
+        let mut unknown: Vec<&str> = Vec::new();
+        for name in query.names() {
+            if !allowed.contains(name) && !self.always.contains(name) && !unknown.contains(&name) {
+                unknown.push(name);
+            }
+        }
+        Some(unknown)
+    }
+}
+
+/// Middleware wrapper service which rejects requests with query parameters
+/// their operation doesn't declare.
+pub struct StrictQueryMakeService<T, RP> {
+    inner: T,
+    allowlist: Arc<QueryAllowlist>,
+    marker: PhantomData<fn(RP)>,
+}
+
+impl<T, RP> StrictQueryMakeService<T, RP> {
+    /// Create a new StrictQueryMakeService, using `RP` to identify the
+    /// operation of each request.
+    pub fn new(inner: T, allowlist: QueryAllowlist) -> Self {
+        StrictQueryMakeService {
+            inner,
+            allowlist: Arc::new(allowlist),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, RP> fmt::Debug for StrictQueryMakeService<T, RP>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StrictQueryMakeService")
+            .field("inner", &self.inner)
+            .field("allowlist", &self.allowlist)
+            .finish()
+    }
+}
+
+impl<Inner, RP, Target> Service<Target> for StrictQueryMakeService<Inner, RP>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = StrictQueryService<Inner::Response, RP>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let allowlist = self.allowlist.clone();
+        Box::pin(self.inner.call(target).map(|s| {
+            Ok(StrictQueryService {
+                inner: s?,
+                allowlist,
+                marker: PhantomData,
+            })
+        }))
+    }
+}
+
+/// Middleware wrapper service which rejects requests with query parameters
+/// their operation doesn't declare. Servers will normally want to use
+/// `StrictQueryMakeService`, which will create a `StrictQueryService` for
+/// each connection.
+pub struct StrictQueryService<T, RP> {
+    inner: T,
+    allowlist: Arc<QueryAllowlist>,
+    marker: PhantomData<fn(RP)>,
+}
+
+impl<T, RP> StrictQueryService<T, RP> {
+    /// Create a new StrictQueryService, using `RP` to identify the operation
+    /// of each request.
+    pub fn new(inner: T, allowlist: QueryAllowlist) -> Self {
+        StrictQueryService {
+            inner,
+            allowlist: Arc::new(allowlist),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, RP> Clone for StrictQueryService<T, RP>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        StrictQueryService {
+            inner: self.inner.clone(),
+            allowlist: self.allowlist.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, RP> fmt::Debug for StrictQueryService<T, RP>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StrictQueryService")
+            .field("inner", &self.inner)
+            .field("allowlist", &self.allowlist)
+            .finish()
+    }
+}
+
+impl<T, RP, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for StrictQueryService<T, RP>
+where
+    RP: RequestParser<ReqBody>,
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>>,
+    ResBody: From<String>,
+    C: Has<XSpanIdString>,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = futures::future::Either<
+        futures::future::Ready<Result<Self::Response, Self::Error>>,
+        T::Future,
+    >;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        if let Some(operation_id) = RP::parse_operation_id(&req) {
+            let query = QueryParams::from_uri(req.uri());
+            match self.allowlist.unknown(operation_id, &query) {
+                Some(unknown) if !unknown.is_empty() => {
+                    let x_span_id = Has::<XSpanIdString>::get(&context);
+                    let message = format!("Unknown query parameters: {}", unknown.join(", "));
+                    return futures::future::Either::Left(futures::future::ok(coded_json_error(
+                        StatusCode::BAD_REQUEST,
+                        "unknown_query_parameter",
+                        &message,
+                        Some(&x_span_id.0),
+                    )));
+                }
+                _ => {}
+            }
+        }
+        futures::future::Either::Right(self.inner.call((req, context)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContextBuilder, EmptyContext, Push};
+
+    struct TestParser;
+
+    impl RequestParser<()> for TestParser {
+        fn parse_operation_id(req: &Request<()>) -> Option<&'static str> {
+            match req.uri().path() {
+                "/pets" => Some("findPets"),
+                _ => None,
+            }
+        }
+    }
+
+    struct TestApi;
+
+    impl<C> Service<(Request<()>, C)> for TestApi {
+        type Response = Response<String>;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _: (Request<()>, C)) -> Self::Future {
+            futures::future::ok(Response::new("ok".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_parameters() {
+        let allowlist = QueryAllowlist::new()
+            .operation("findPets", ["filter", "limit"])
+            .always("_");
+        let service = StrictQueryService::<_, TestParser>::new(TestApi, allowlist);
+        let call = |uri: &str| {
+            let context: ContextBuilder<XSpanIdString, EmptyContext> =
+                EmptyContext.push(XSpanIdString("span".to_string()));
+            service.call((Request::get(uri).body(()).unwrap(), context))
+        };
+
+        let response = call("/pets?filter[name]=Rex&limit=1&_=123").await.unwrap();
+        assert_eq!(response.body(), "ok");
+
+        let response = call("/pets?fliter=a&limit=1&fliter=b&sort=name")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.body(),
+            r#"{"code":400,"message":"Unknown query parameters: fliter, sort","x-span-id":"span"}"#
+        );
+
+        // Operations which aren't listed aren't checked
+        let response = call("/other?fliter=a").await.unwrap();
+        assert_eq!(response.body(), "ok");
+    }
+}
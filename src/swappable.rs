@@ -0,0 +1,111 @@
+//! Hyper service whose inner service can be replaced at runtime.
+//!
+//! This allows reconfiguration without downtime, e.g. after a configuration
+//! reload or when credentials are renewed: requests already in progress complete
+//! using the old service, while new requests use the new one.
+//!
+//! ```rust
+//! # use swagger::SwappableService;
+//! # fn swap<T>(old_make_service: T, new_make_service: T) {
+//! let make_service = SwappableService::new(old_make_service);
+//!
+//! // Keep a clone to swap the service later - clones share the same inner service.
+//! let handle = make_service.clone();
+//! handle.swap(new_make_service);
+//! # }
+//! ```
+
+use arc_swap::ArcSwap;
+use hyper::service::Service;
+use std::fmt;
+use std::sync::Arc;
+
+/// Service wrapper whose inner service can be atomically replaced.
+///
+/// This can wrap services or `MakeService`s anywhere in a stack. Clones of a
+/// `SwappableService` share the same inner service, so swapping it through one
+/// clone affects them all.
+pub struct SwappableService<T> {
+    inner: Arc<ArcSwap<T>>,
+}
+
+impl<T> SwappableService<T> {
+    /// Create a new SwappableService wrapping a value
+    pub fn new(inner: T) -> Self {
+        SwappableService {
+            inner: Arc::new(ArcSwap::from_pointee(inner)),
+        }
+    }
+
+    /// Replace the inner service, returning the previous one.
+    pub fn swap(&self, inner: T) -> Arc<T> {
+        self.inner.swap(Arc::new(inner))
+    }
+
+    /// Get the current inner service.
+    pub fn load(&self) -> Arc<T> {
+        self.inner.load_full()
+    }
+}
+
+impl<T> Clone for SwappableService<T> {
+    fn clone(&self) -> Self {
+        SwappableService {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for SwappableService<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SwappableService")
+            .field("inner", &self.inner.load())
+            .finish()
+    }
+}
+
+impl<T, Request> Service<Request> for SwappableService<T>
+where
+    T: Service<Request>,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = T::Future;
+
+    fn call(&self, req: Request) -> Self::Future {
+        self.inner.load().call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NameService(&'static str);
+
+    impl Service<()> for NameService {
+        type Response = &'static str;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _req: ()) -> Self::Future {
+            futures::future::ok(self.0)
+        }
+    }
+
+    #[tokio::test]
+    async fn swaps_inner_service() {
+        let service = SwappableService::new(NameService("old"));
+        let handle = service.clone();
+        let in_progress = service.call(());
+
+        let old = handle.swap(NameService("new"));
+        assert_eq!(old.0, "old");
+
+        assert_eq!(in_progress.await, Ok("old"));
+        assert_eq!(service.call(()).await, Ok("new"));
+    }
+}
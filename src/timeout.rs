@@ -0,0 +1,153 @@
+//! Client middleware bounding the time taken by requests.
+//!
+//! `TimeoutService` fails requests which don't receive a response within a
+//! timeout with a `TimeoutError`. The timeout covers establishing the
+//! connection as well as waiting for the response headers, but not
+//! streaming the response body.
+//!
+//! The default timeout can be overridden for a single request by adding a
+//! `RequestTimeout` to its context.
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! # use swagger::timeout::RequestTimeout;
+//! # use swagger::Push;
+//! swagger::new_context_type!(ClientContext, EmptyClientContext, Option<RequestTimeout>);
+//!
+//! let context = EmptyClientContext.push(Some(RequestTimeout(Duration::from_secs(120))));
+//! ```
+
+use crate::Has;
+use futures::future::BoxFuture;
+use hyper::service::Service;
+use hyper::Request;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// Context entry overriding the timeout for a single request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestTimeout(pub Duration);
+
+/// Error returned when a request times out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeoutError {
+    /// The timeout which expired.
+    pub timeout: Duration,
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Request timed out after {:?}", self.timeout)
+    }
+}
+
+impl Error for TimeoutError {}
+
+/// Client middleware failing requests which take longer than a timeout.
+///
+/// Only the time taken by the services it wraps counts towards the timeout -
+/// wrap `RateLimitService` and `RedirectService` with it to include their
+/// delays and redirects, or put it inside them to time each request sent.
+#[derive(Debug, Clone)]
+pub struct TimeoutService<T> {
+    inner: T,
+    timeout: Duration,
+}
+
+impl<T> TimeoutService<T> {
+    /// Create a new TimeoutService, with a default timeout of `timeout`.
+    pub fn new(inner: T, timeout: Duration) -> Self {
+        TimeoutService { inner, timeout }
+    }
+}
+
+impl<T, B, C> Service<(Request<B>, C)> for TimeoutService<T>
+where
+    C: Has<Option<RequestTimeout>>,
+    T: Service<(Request<B>, C)>,
+    T::Future: Send + 'static,
+    T::Response: Send + 'static,
+    T::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    type Response = T::Response;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<B>, C)) -> Self::Future {
+        let timeout = match Has::<Option<RequestTimeout>>::get(&context) {
+            Some(RequestTimeout(timeout)) => *timeout,
+            None => self.timeout,
+        };
+
+        let future = self.inner.call((req, context));
+        Box::pin(async move {
+            match tokio::time::timeout(timeout, future).await {
+                Ok(result) => result.map_err(Into::into),
+                Err(_) => Err(TimeoutError { timeout }.into()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Push;
+
+    crate::new_context_type!(TestContext, TestEmptyContext, Option<RequestTimeout>);
+
+    type TestContextType =
+        crate::make_context_ty!(TestContext, TestEmptyContext, Option<RequestTimeout>);
+
+    /// Service which responds after the number of milliseconds in the URI
+    /// path.
+    struct SlowService;
+
+    impl Service<(Request<()>, TestContextType)> for SlowService {
+        type Response = ();
+        type Error = std::io::Error;
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, _): (Request<()>, TestContextType)) -> Self::Future {
+            let delay = req.uri().path()[1..].parse().unwrap();
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                Ok(())
+            })
+        }
+    }
+
+    fn request(path: &str, timeout: Option<Duration>) -> (Request<()>, TestContextType) {
+        (
+            Request::get(path).body(()).unwrap(),
+            TestEmptyContext.push(timeout.map(RequestTimeout)),
+        )
+    }
+
+    #[tokio::test]
+    async fn times_out() {
+        let service = TimeoutService::new(SlowService, Duration::from_millis(50));
+
+        assert!(service.call(request("/0", None)).await.is_ok());
+
+        let error = service.call(request("/1000", None)).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<TimeoutError>(),
+            Some(&TimeoutError {
+                timeout: Duration::from_millis(50)
+            })
+        );
+
+        let error = service
+            .call(request("/1000", Some(Duration::from_millis(1))))
+            .await
+            .unwrap_err();
+        assert!(error.is::<TimeoutError>());
+
+        assert!(service
+            .call(request("/100", Some(Duration::from_secs(10))))
+            .await
+            .is_ok());
+    }
+}
@@ -0,0 +1,638 @@
+//! `application/x-www-form-urlencoded` bodies.
+//!
+//! `to_string` and `from_str` convert between serde types and form bodies,
+//! encoding each property using its OpenAPI `style` and `explode` settings
+//! from the operation's `encoding` object, so that clients and servers agree
+//! on how arrays and objects are represented.
+//!
+//! ```rust
+//! # use serde::{Deserialize, Serialize};
+//! # use std::collections::HashMap;
+//! # use swagger::urlencoded::{self, Encodings, Style};
+//! #[derive(Debug, PartialEq, Deserialize, Serialize)]
+//! struct Search {
+//!     query: String,
+//!     tags: Vec<String>,
+//!     ids: Vec<u32>,
+//!     filter: HashMap<String, String>,
+//! }
+//!
+//! let encodings = Encodings::new()
+//!     .property("ids", Style::Form, false)
+//!     .property("filter", Style::DeepObject, true);
+//!
+//! let search = Search {
+//!     query: "cats & dogs".to_string(),
+//!     tags: vec!["pets".to_string(), "cute".to_string()],
+//!     ids: vec![1, 2],
+//!     filter: HashMap::from([("status".to_string(), "sold".to_string())]),
+//! };
+//!
+//! let body = urlencoded::to_string(&search, &encodings).unwrap();
+//! assert_eq!(
+//!     body,
+//!     "filter[status]=sold&ids=1,2&query=cats+%26+dogs&tags=pets&tags=cute"
+//! );
+//! assert_eq!(urlencoded::from_str::<Search>(&body, &encodings).unwrap(), search);
+//! ```
+//!
+//! Fields are encoded in name order. Properties of an object with
+//! `style: form, explode: true` are encoded as top-level fields, so are
+//! deserialized with `#[serde(flatten)]` - which only supports string
+//! values, as form bodies are untyped.
+
+use std::borrow::Cow;
+#[cfg(feature = "serdejson")]
+use std::collections::HashMap;
+#[cfg(feature = "serdejson")]
+use std::fmt;
+
+/// Percent-encode a string as part of an `application/x-www-form-urlencoded`
+/// name or value.
+pub fn encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'*' | b'-' | b'.' | b'_' => {
+                encoded.push(byte as char)
+            }
+            b' ' => encoded.push('+'),
+            byte => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Percent-decode an `application/x-www-form-urlencoded` name or value.
+/// Invalid escapes are left as they are.
+pub fn decode(value: &str) -> Cow<'_, str> {
+    percent_decode(value, true)
+}
+
+/// Percent-decode a string, optionally decoding `+` as a space.
+pub(crate) fn percent_decode(value: &str, plus_as_space: bool) -> Cow<'_, str> {
+    if !(value.contains('%') || plus_as_space && value.contains('+')) {
+        return value.into();
+    }
+
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' if plus_as_space => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned().into()
+}
+
+/// OpenAPI serialization style of a property.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Style {
+    /// `style: form` - arrays are repeated fields when exploded, or comma
+    /// separated.
+    #[default]
+    Form,
+    /// `style: spaceDelimited` - arrays are space separated.
+    SpaceDelimited,
+    /// `style: pipeDelimited` - arrays are pipe separated.
+    PipeDelimited,
+    /// `style: deepObject` - objects are encoded as `name[key]=value`.
+    DeepObject,
+}
+
+#[cfg(feature = "serdejson")]
+impl Style {
+    /// Separator between the elements of an array which isn't exploded.
+    fn delimiter(self) -> &'static str {
+        match self {
+            Style::Form | Style::DeepObject => ",",
+            Style::SpaceDelimited => "%20",
+            Style::PipeDelimited => "|",
+        }
+    }
+}
+
+/// The `style` and `explode` settings for the properties of a form body.
+///
+/// Properties without settings use `style: form, explode: true`, as in
+/// OpenAPI.
+#[cfg(feature = "serdejson")]
+#[derive(Clone, Debug, Default)]
+pub struct Encodings {
+    properties: HashMap<String, (Style, bool)>,
+}
+
+#[cfg(feature = "serdejson")]
+impl Encodings {
+    /// Create settings with every property using `style: form, explode: true`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the style of a property, and whether it's exploded.
+    pub fn property(mut self, name: &str, style: Style, explode: bool) -> Self {
+        self.properties.insert(name.to_string(), (style, explode));
+        self
+    }
+
+    fn get(&self, name: &str) -> (Style, bool) {
+        self.properties
+            .get(name)
+            .copied()
+            .unwrap_or((Style::Form, true))
+    }
+}
+
+/// Error returned when a form body can't be serialized or deserialized.
+#[cfg(feature = "serdejson")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormError(pub(crate) String);
+
+#[cfg(feature = "serdejson")]
+impl fmt::Display for FormError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid form body: {}", self.0)
+    }
+}
+
+#[cfg(feature = "serdejson")]
+impl std::error::Error for FormError {}
+
+#[cfg(feature = "serdejson")]
+impl serde::de::Error for FormError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        FormError(msg.to_string())
+    }
+}
+
+#[cfg(feature = "serdejson")]
+pub use self::serde_impl::{from_bytes, from_str, to_string};
+#[cfg(feature = "serdejson")]
+pub(crate) use self::serde_impl::{from_delimited, from_str_with_path, to_pairs};
+
+#[cfg(feature = "serdejson")]
+mod serde_impl {
+    use super::{decode, encode, Encodings, FormError, Style};
+    use serde::de::value::{MapDeserializer, SeqDeserializer};
+    use serde::de::{DeserializeOwned, Deserializer, IntoDeserializer, Visitor};
+    use serde::{forward_to_deserialize_any, Serialize};
+    use serde_json::Value;
+
+    /// Serialize `value`, which must serialize as a map or struct, as a form
+    /// body.
+    pub fn to_string<T: Serialize>(value: &T, encodings: &Encodings) -> Result<String, FormError> {
+        let pairs: Vec<_> = to_pairs(value, encodings, encode)?
+            .into_iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        Ok(pairs.join("&"))
+    }
+
+    /// Serialize `value` as encoded name/value pairs, percent-encoding names
+    /// and values with `encode`.
+    pub(crate) fn to_pairs<T: Serialize>(
+        value: &T,
+        encodings: &Encodings,
+        encode: fn(&str) -> String,
+    ) -> Result<Vec<(String, String)>, FormError> {
+        let value = serde_json::to_value(value).map_err(|e| FormError(e.to_string()))?;
+        let Value::Object(properties) = value else {
+            return Err(FormError("only objects can be encoded".to_string()));
+        };
+
+        let mut pairs = Vec::new();
+        for (name, value) in &properties {
+            let (style, explode) = encodings.get(name);
+            match value {
+                Value::Null => {}
+                Value::Array(_) | Value::Object(_) if style == Style::DeepObject => {
+                    deep_object(&mut pairs, encode(name), value, encode)
+                }
+                Value::Array(values) if explode && style == Style::Form => {
+                    for value in values {
+                        pairs.push((encode(name), encode(&text(value))));
+                    }
+                }
+                Value::Object(fields) if explode => {
+                    for (key, value) in fields {
+                        pairs.push((encode(key), encode(&text(value))));
+                    }
+                }
+                Value::Array(values) => {
+                    let values: Vec<_> = values.iter().map(|value| encode(&text(value))).collect();
+                    pairs.push((encode(name), values.join(style.delimiter())));
+                }
+                Value::Object(fields) => {
+                    let values: Vec<_> = fields
+                        .iter()
+                        .flat_map(|(key, value)| [encode(key), encode(&text(value))])
+                        .collect();
+                    pairs.push((encode(name), values.join(style.delimiter())));
+                }
+                value => pairs.push((encode(name), encode(&text(value)))),
+            }
+        }
+        Ok(pairs)
+    }
+
+    /// The text of a value. Arrays and objects nested inside other values
+    /// are encoded as JSON.
+    fn text(value: &Value) -> String {
+        match value {
+            Value::Null => String::new(),
+            Value::String(s) => s.clone(),
+            value => value.to_string(),
+        }
+    }
+
+    /// Encode an array or object as `name[key]=value` pairs, using indices as
+    /// the keys of arrays.
+    fn deep_object(
+        pairs: &mut Vec<(String, String)>,
+        name: String,
+        value: &Value,
+        encode: fn(&str) -> String,
+    ) {
+        match value {
+            Value::Object(fields) => {
+                for (key, value) in fields {
+                    deep_object(pairs, format!("{}[{}]", name, encode(key)), value, encode);
+                }
+            }
+            Value::Array(values) => {
+                for (index, value) in values.iter().enumerate() {
+                    deep_object(pairs, format!("{}[{}]", name, index), value, encode);
+                }
+            }
+            Value::Null => {}
+            value => pairs.push((name, encode(&text(value)))),
+        }
+    }
+
+    /// Deserialize a form body.
+    pub fn from_str<T: DeserializeOwned>(
+        input: &str,
+        encodings: &Encodings,
+    ) -> Result<T, FormError> {
+        T::deserialize(NodeDeserializer(parse(input, encodings)))
+    }
+
+    /// Deserialize a form body or query string, returning the path of the
+    /// value which couldn't be deserialized on failure.
+    pub(crate) fn from_str_with_path<T: DeserializeOwned>(
+        input: &str,
+        encodings: &Encodings,
+    ) -> Result<T, serde_path_to_error::Error<FormError>> {
+        serde_path_to_error::deserialize(NodeDeserializer(parse(input, encodings)))
+    }
+
+    /// Parse a form body into its fields.
+    fn parse(input: &str, encodings: &Encodings) -> Node {
+        let mut fields = Vec::new();
+        for pair in input.split('&').filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let name = decode(name);
+
+            let base = name.split('[').next().unwrap_or_default();
+            let (style, explode) = encodings.get(base);
+            if style == Style::DeepObject {
+                let path: Vec<_> = name
+                    .split('[')
+                    .map(|segment| segment.trim_end_matches(']'))
+                    .collect();
+                insert(&mut fields, &path, Node::Value(decode(value).into_owned()));
+            } else if !explode {
+                let node = Node::Delimited {
+                    whole: decode(value).into_owned(),
+                    parts: value
+                        .split(style.delimiter())
+                        .map(|part| decode(part).into_owned())
+                        .collect(),
+                    fields: Vec::new(),
+                };
+                insert(&mut fields, &[&name], node);
+            } else {
+                insert(
+                    &mut fields,
+                    &[&name],
+                    Node::Value(decode(value).into_owned()),
+                );
+            }
+        }
+        Node::Map(fields)
+    }
+
+    /// Deserialize a form body, e.g. a collected request body.
+    pub fn from_bytes<T: DeserializeOwned>(
+        input: &[u8],
+        encodings: &Encodings,
+    ) -> Result<T, FormError> {
+        let input =
+            std::str::from_utf8(input).map_err(|_| FormError("invalid UTF-8".to_string()))?;
+        from_str(input, encodings)
+    }
+
+    /// Deserialize a single delimited value, such as a `style: simple`
+    /// parameter. `fields` are the `key=value` pairs of an exploded object.
+    pub(crate) fn from_delimited<T: DeserializeOwned>(
+        whole: String,
+        parts: Vec<String>,
+        fields: Vec<(String, String)>,
+    ) -> Result<T, FormError> {
+        T::deserialize(NodeDeserializer(Node::Delimited {
+            whole,
+            parts,
+            fields,
+        }))
+    }
+
+    /// A parsed form field.
+    #[derive(Debug)]
+    enum Node {
+        /// A single value.
+        Value(String),
+        /// A value which isn't exploded, and so is split into an array or
+        /// object if that's what's expected. Objects are read from
+        /// `fields` if there are any, or else from alternate parts.
+        Delimited {
+            whole: String,
+            parts: Vec<String>,
+            fields: Vec<(String, String)>,
+        },
+        /// A repeated field.
+        Seq(Vec<Node>),
+        /// The fields of a body, or of a `deepObject`.
+        Map(Vec<(String, Node)>),
+    }
+
+    /// Add a field at the given path, turning repeated fields into a `Seq`.
+    fn insert(fields: &mut Vec<(String, Node)>, path: &[&str], node: Node) {
+        let Some((name, rest)) = path.split_first() else {
+            return;
+        };
+        let index = match fields.iter().position(|(key, _)| key == name) {
+            Some(index) => index,
+            None if rest.is_empty() => {
+                fields.push((name.to_string(), node));
+                return;
+            }
+            None => {
+                fields.push((name.to_string(), Node::Map(Vec::new())));
+                fields.len() - 1
+            }
+        };
+
+        let existing = &mut fields[index].1;
+        match (existing, rest.is_empty()) {
+            (Node::Map(children), false) => insert(children, rest, node),
+            (Node::Seq(values), true) => values.push(node),
+            (existing, true) => {
+                let first = std::mem::replace(existing, Node::Seq(Vec::new()));
+                *existing = Node::Seq(vec![first, node]);
+            }
+            // A field which is both a value and an object - keep the value
+            (_, false) => {}
+        }
+    }
+
+    impl<'de> IntoDeserializer<'de, FormError> for Node {
+        type Deserializer = NodeDeserializer;
+
+        fn into_deserializer(self) -> Self::Deserializer {
+            NodeDeserializer(self)
+        }
+    }
+
+    #[derive(Debug)]
+    struct NodeDeserializer(Node);
+
+    impl NodeDeserializer {
+        /// The single value of this node, for deserializing primitives.
+        fn text(self) -> Result<String, FormError> {
+            match self.0 {
+                Node::Value(value) | Node::Delimited { whole: value, .. } => Ok(value),
+                Node::Seq(mut values) if values.len() == 1 => {
+                    NodeDeserializer(values.remove(0)).text()
+                }
+                Node::Seq(_) => Err(FormError("expected a single value".to_string())),
+                Node::Map(_) => Err(FormError("expected a value, not an object".to_string())),
+            }
+        }
+    }
+
+    macro_rules! deserialize_parsed {
+        ($($method:ident => $visit:ident,)*) => {
+            $(
+                fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+                    let text = self.text()?;
+                    let value = text
+                        .parse()
+                        .map_err(|_| FormError(format!("invalid value: {:?}", text)))?;
+                    visitor.$visit(value)
+                }
+            )*
+        };
+    }
+
+    impl<'de> Deserializer<'de> for NodeDeserializer {
+        type Error = FormError;
+
+        fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+            match self.0 {
+                Node::Value(value) | Node::Delimited { whole: value, .. } => {
+                    visitor.visit_string(value)
+                }
+                Node::Seq(values) => {
+                    let mut seq = SeqDeserializer::new(values.into_iter());
+                    let value = visitor.visit_seq(&mut seq)?;
+                    seq.end()?;
+                    Ok(value)
+                }
+                Node::Map(fields) => {
+                    let mut map = MapDeserializer::new(fields.into_iter());
+                    let value = visitor.visit_map(&mut map)?;
+                    map.end()?;
+                    Ok(value)
+                }
+            }
+        }
+
+        fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+            let values = match self.0 {
+                Node::Value(value) => vec![Node::Value(value)],
+                Node::Delimited { parts, .. } => parts.into_iter().map(Node::Value).collect(),
+                Node::Seq(values) => values,
+                // deepObject arrays are objects with indices as keys
+                Node::Map(mut fields) => {
+                    fields.sort_by_key(|(key, _)| key.parse::<usize>().unwrap_or(usize::MAX));
+                    fields.into_iter().map(|(_, value)| value).collect()
+                }
+            };
+            NodeDeserializer(Node::Seq(values)).deserialize_any(visitor)
+        }
+
+        fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+            match self.0 {
+                Node::Delimited { fields, .. } if !fields.is_empty() => {
+                    let fields = fields
+                        .into_iter()
+                        .map(|(key, value)| (key, Node::Value(value)))
+                        .collect();
+                    NodeDeserializer(Node::Map(fields)).deserialize_any(visitor)
+                }
+                Node::Delimited { parts, .. } => {
+                    let mut parts = parts.into_iter();
+                    let mut fields = Vec::new();
+                    while let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+                        fields.push((key, Node::Value(value)));
+                    }
+                    NodeDeserializer(Node::Map(fields)).deserialize_any(visitor)
+                }
+                node => NodeDeserializer(node).deserialize_any(visitor),
+            }
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            _fields: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, FormError> {
+            self.deserialize_map(visitor)
+        }
+
+        fn deserialize_tuple<V: Visitor<'de>>(
+            self,
+            _len: usize,
+            visitor: V,
+        ) -> Result<V::Value, FormError> {
+            self.deserialize_seq(visitor)
+        }
+
+        fn deserialize_tuple_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            _len: usize,
+            visitor: V,
+        ) -> Result<V::Value, FormError> {
+            self.deserialize_seq(visitor)
+        }
+
+        fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+            visitor.visit_some(self)
+        }
+
+        fn deserialize_newtype_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            visitor: V,
+        ) -> Result<V::Value, FormError> {
+            visitor.visit_newtype_struct(self)
+        }
+
+        fn deserialize_enum<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            _variants: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, FormError> {
+            visitor.visit_enum(self.text()?.into_deserializer())
+        }
+
+        deserialize_parsed! {
+            deserialize_bool => visit_bool,
+            deserialize_i8 => visit_i8,
+            deserialize_i16 => visit_i16,
+            deserialize_i32 => visit_i32,
+            deserialize_i64 => visit_i64,
+            deserialize_u8 => visit_u8,
+            deserialize_u16 => visit_u16,
+            deserialize_u32 => visit_u32,
+            deserialize_u64 => visit_u64,
+            deserialize_f32 => visit_f32,
+            deserialize_f64 => visit_f64,
+            deserialize_char => visit_char,
+        }
+
+        forward_to_deserialize_any! {
+            i128 u128 str string bytes byte_buf unit unit_struct identifier ignored_any
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use serde::Deserialize;
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, PartialEq, Deserialize, Serialize)]
+        struct Color {
+            #[serde(rename = "R")]
+            r: u8,
+            #[serde(rename = "G")]
+            g: u8,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize, Serialize)]
+        struct Params {
+            name: String,
+            colors: Vec<String>,
+            pipes: Vec<u32>,
+            spaces: Vec<String>,
+            point: Color,
+            nested: BTreeMap<String, Vec<Color>>,
+            missing: Option<bool>,
+            #[serde(flatten)]
+            exploded: BTreeMap<String, String>,
+        }
+
+        #[test]
+        fn encodes_styles() {
+            let encodings = Encodings::new()
+                .property("colors", Style::Form, false)
+                .property("pipes", Style::PipeDelimited, false)
+                .property("spaces", Style::SpaceDelimited, false)
+                .property("point", Style::Form, false)
+                .property("nested", Style::DeepObject, true);
+            let params = Params {
+                name: "a=b&c".to_string(),
+                colors: vec!["blue".to_string(), "black,brown".to_string()],
+                pipes: vec![1, 2, 3],
+                spaces: vec!["x y".to_string(), "z".to_string()],
+                point: Color { r: 100, g: 200 },
+                nested: BTreeMap::from([(
+                    "list".to_string(),
+                    vec![Color { r: 1, g: 2 }, Color { r: 3, g: 4 }],
+                )]),
+                missing: None,
+                exploded: BTreeMap::from([("extra".to_string(), "5".to_string())]),
+            };
+
+            let body = to_string(&params, &encodings).unwrap();
+            assert_eq!(
+                body,
+                "colors=blue,black%2Cbrown&extra=5&name=a%3Db%26c&nested[list][0][G]=2\
+                 &nested[list][0][R]=1&nested[list][1][G]=4&nested[list][1][R]=3\
+                 &pipes=1|2|3&point=G,200,R,100&spaces=x+y%20z"
+            );
+            assert_eq!(from_str::<Params>(&body, &encodings).unwrap(), params);
+
+            let value: Value = from_str("a=1&a=2&b=%E2%9C%93", &Encodings::new()).unwrap();
+            assert_eq!(value, serde_json::json!({"a": ["1", "2"], "b": "\u{2713}"}));
+            assert!(from_str::<Color>("R=300&G=1", &Encodings::new()).is_err());
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! Checks implementing the constraint keywords of OpenAPI schemas, for
+//! generated code and handlers to validate values which have already been
+//! deserialized.
+//!
+//! Each check takes the JSON pointer of the value, and returns a
+//! `ValidationError` for the body at that pointer if the value doesn't
+//! conform, with the same code and message `ValidationService` would report.
+//! Errors for parameters can be moved to their location with
+//! `ValidationError::at`, and several errors can be collected with
+//! `ValidationErrors::check`.
+//!
+//! ```rust
+//! use swagger::validation::constraints;
+//! use swagger::validation::{Location, ValidationError};
+//!
+//! fn check_limit(limit: i32) -> Result<(), ValidationError> {
+//!     constraints::minimum("/limit", limit, 1, false)
+//!         .and_then(|_| constraints::maximum("/limit", limit, 100, false))
+//!         .map_err(|e| e.at(Location::Query))
+//! }
+//!
+//! assert!(check_limit(10).is_ok());
+//! assert_eq!(
+//!     check_limit(0).unwrap_err().to_string(),
+//!     "query /limit: must be at least 1"
+//! );
+//! ```
+
+use super::ValidationError;
+use regex::Regex;
+use std::fmt::Display;
+
+/// Check `minimum`, or `minimum` with `exclusiveMinimum`.
+pub fn minimum<T>(
+    pointer: &str,
+    value: T,
+    minimum: T,
+    exclusive: bool,
+) -> Result<(), ValidationError>
+where
+    T: PartialOrd + Display,
+{
+    if exclusive && value <= minimum {
+        Err(ValidationError::new(
+            pointer,
+            "exclusiveMinimum",
+            format!("must be greater than {}", minimum),
+        ))
+    } else if value < minimum {
+        Err(ValidationError::new(
+            pointer,
+            "minimum",
+            format!("must be at least {}", minimum),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check `maximum`, or `maximum` with `exclusiveMaximum`.
+pub fn maximum<T>(
+    pointer: &str,
+    value: T,
+    maximum: T,
+    exclusive: bool,
+) -> Result<(), ValidationError>
+where
+    T: PartialOrd + Display,
+{
+    if exclusive && value >= maximum {
+        Err(ValidationError::new(
+            pointer,
+            "exclusiveMaximum",
+            format!("must be less than {}", maximum),
+        ))
+    } else if value > maximum {
+        Err(ValidationError::new(
+            pointer,
+            "maximum",
+            format!("must be at most {}", maximum),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check `multipleOf`. Non-positive divisors, which OpenAPI doesn't allow,
+/// are ignored.
+pub fn multiple_of(pointer: &str, value: f64, multiple: f64) -> Result<(), ValidationError> {
+    let quotient = value / multiple;
+    if multiple > 0.0 && (quotient - quotient.round()).abs() > 1e-9 {
+        Err(ValidationError::new(
+            pointer,
+            "multipleOf",
+            format!("must be a multiple of {}", multiple),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check `minLength`, counting characters rather than bytes.
+pub fn min_length(pointer: &str, value: &str, min: usize) -> Result<(), ValidationError> {
+    if value.chars().count() < min {
+        Err(ValidationError::new(
+            pointer,
+            "minLength",
+            format!("must be at least {} characters long", min),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check `maxLength`, counting characters rather than bytes.
+pub fn max_length(pointer: &str, value: &str, max: usize) -> Result<(), ValidationError> {
+    if value.chars().count() > max {
+        Err(ValidationError::new(
+            pointer,
+            "maxLength",
+            format!("must be at most {} characters long", max),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check `pattern`. As in OpenAPI, the pattern isn't anchored, so it only
+/// has to match part of the value.
+pub fn pattern(pointer: &str, value: &str, pattern: &Regex) -> Result<(), ValidationError> {
+    if pattern.is_match(value) {
+        Ok(())
+    } else {
+        Err(ValidationError::new(
+            pointer,
+            "pattern",
+            format!("must match the pattern {}", pattern.as_str()),
+        ))
+    }
+}
+
+/// Check `enum`.
+pub fn enumeration<T: PartialEq>(
+    pointer: &str,
+    value: &T,
+    allowed: &[T],
+) -> Result<(), ValidationError> {
+    if allowed.contains(value) {
+        Ok(())
+    } else {
+        Err(ValidationError::new(
+            pointer,
+            "enum",
+            "must be one of the allowed values".to_string(),
+        ))
+    }
+}
+
+/// Check `minItems`.
+pub fn min_items<T>(pointer: &str, items: &[T], min: usize) -> Result<(), ValidationError> {
+    if items.len() < min {
+        Err(ValidationError::new(
+            pointer,
+            "minItems",
+            format!("must have at least {} items", min),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check `maxItems`.
+pub fn max_items<T>(pointer: &str, items: &[T], max: usize) -> Result<(), ValidationError> {
+    if items.len() > max {
+        Err(ValidationError::new(
+            pointer,
+            "maxItems",
+            format!("must have at most {} items", max),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check `uniqueItems`.
+pub fn unique_items<T: PartialEq>(pointer: &str, items: &[T]) -> Result<(), ValidationError> {
+    let duplicate = items
+        .iter()
+        .enumerate()
+        .any(|(i, a)| items[..i].contains(a));
+    if duplicate {
+        Err(ValidationError::new(
+            pointer,
+            "uniqueItems",
+            "must not contain duplicate items".to_string(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::Location;
+
+    #[test]
+    fn checks_constraints() {
+        assert!(minimum("/a", 1, 1, false).is_ok());
+        assert_eq!(
+            minimum("/a", 1, 1, true).unwrap_err().to_string(),
+            "body /a: must be greater than 1"
+        );
+        assert_eq!(
+            maximum("/a", 2.5, 2.0, false).unwrap_err().message(),
+            "must be at most 2"
+        );
+        assert!(multiple_of("/a", 0.3, 0.1).is_ok());
+        assert!(multiple_of("/a", 0.35, 0.1).is_err());
+        assert!(min_length("/a", "ééé", 3).is_ok());
+        assert!(max_length("/a", "ééé", 2).is_err());
+
+        let regex = Regex::new("^[a-z]+$").unwrap();
+        assert!(pattern("/a", "abc", &regex).is_ok());
+        assert_eq!(
+            pattern("/a", "ABC", &regex)
+                .unwrap_err()
+                .at(Location::Header)
+                .to_string(),
+            "header /a: must match the pattern ^[a-z]+$"
+        );
+
+        assert!(enumeration("/a", &"cat", &["cat", "dog"]).is_ok());
+        assert!(enumeration("/a", &"cow", &["cat", "dog"]).is_err());
+        assert!(min_items("/a", &[1, 2], 3).is_err());
+        assert!(max_items("/a", &[1, 2], 2).is_ok());
+        assert!(unique_items("/a", &[1, 2, 3]).is_ok());
+        assert!(unique_items("/a", &[1, 2, 1]).is_err());
+    }
+}
@@ -0,0 +1,1062 @@
+//! Runtime validation of requests against the OpenAPI document of a service.
+//!
+//! Generated models are often lenient - with `#[serde(default)]` fields, and
+//! constraints such as `minimum` or `pattern` which aren't checked when
+//! deserializing - so requests which don't conform to the API can reach the
+//! handlers. `ValidationService` checks each request against the operation it
+//! matches in the OpenAPI document, and rejects non-conforming requests with
+//! `400 Bad Request`, listing every violation, before they reach the API:
+//!
+//! - Path, query, header and cookie parameters must be present if required,
+//!   and must match their schemas.
+//! - Request bodies must be present if required, and must have a declared
+//!   content type, or are rejected with `415 Unsupported Media Type`. JSON
+//!   bodies must match their schemas.
+//!
+//! The document is loaded from JSON or YAML at startup, and requests are
+//! matched to operations using its paths, under the base path of its first
+//! server. Requests matching no operation are passed on unchecked, for the
+//! API to reject.
+//!
+//! The bodies of requests for operations with a `requestBody` are buffered
+//! before being checked, up to the service's `max_body_size` - longer bodies
+//! are rejected with `413 Content Too Large`. Other requests are passed on
+//! with their bodies streaming, so the API receives an `Either` of the
+//! buffered and original body.
+//!
+//! In CI and staging, `ResponseValidationService` can also check the
+//! responses of the API against the same document - their status codes,
+//! required headers and JSON bodies - and log violations or replace the
+//! responses with `500 Internal Server Error`, depending on the
+//! `ResponseMode`. To share the document, pass both services an
+//! `Arc<OpenApi>`.
+//!
+//! Handlers and generated code can apply the same constraints to values
+//! they have already deserialized with the checks in `constraints`.
+//!
+//! ```rust
+//! # use swagger::validation::{OpenApi, ValidationService};
+//! # struct Api;
+//! let spec = OpenApi::from_json(r#"{
+//!     "openapi": "3.0.3",
+//!     "paths": {"/pets/{petId}": {"get": {
+//!         "operationId": "getPet",
+//!         "parameters": [{
+//!             "name": "petId", "in": "path", "required": true,
+//!             "schema": {"type": "integer", "minimum": 1}
+//!         }]
+//!     }}}
+//! }"#).unwrap();
+//! let service = ValidationService::new(Api, spec);
+//! ```
+
+pub mod constraints;
+mod response;
+mod schema;
+
+pub use response::{ResponseMode, ResponseValidationMakeService, ResponseValidationService};
+pub use schema::Schema;
+
+use self::schema::{escape, Direction, Document};
+use crate::body_ext::{collect_limited, CollectError};
+use crate::cookies::Cookies;
+use crate::media_type::MediaType;
+use crate::path_param::decode_segment;
+use crate::problem::Problem;
+use crate::query::QueryParams;
+use crate::response::{coded_json_error, json_error};
+use crate::routes::Routes;
+use crate::strict_query::QueryAllowlist;
+use crate::{Has, XSpanIdString};
+use futures::future::BoxFuture;
+use http_body_util::{Either, Full};
+use hyper::body::{Body, Bytes};
+use hyper::header::CONTENT_TYPE;
+use hyper::service::Service;
+use hyper::{Method, Request, Response, StatusCode};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+/// The default maximum size of request body buffered for validation.
+const DEFAULT_MAX_BODY_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Where in a request a value was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Location {
+    /// A path parameter.
+    Path,
+    /// A query parameter.
+    Query,
+    /// A header.
+    Header,
+    /// A cookie.
+    Cookie,
+    /// The body.
+    Body,
+}
+
+impl Location {
+    fn parse(location: &str) -> Option<Self> {
+        match location {
+            "path" => Some(Location::Path),
+            "query" => Some(Location::Query),
+            "header" => Some(Location::Header),
+            "cookie" => Some(Location::Cookie),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Location::Path => "path",
+            Location::Query => "query",
+            Location::Header => "header",
+            Location::Cookie => "cookie",
+            Location::Body => "body",
+        })
+    }
+}
+
+/// A value which doesn't conform to the API.
+///
+/// Serializes as
+/// `{"location":"query","pointer":"/limit","code":"minimum","message":"must be at least 1"}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationError {
+    location: Location,
+    pointer: String,
+    code: &'static str,
+    message: String,
+}
+
+impl ValidationError {
+    /// An error in the body, at a JSON pointer such as `/pets/0/name`.
+    pub(crate) fn new(pointer: &str, code: &'static str, message: String) -> Self {
+        ValidationError {
+            location: Location::Body,
+            pointer: pointer.to_string(),
+            code,
+            message,
+        }
+    }
+
+    /// Move the error to another part of the request, such as a parameter
+    /// checked with the functions in `constraints`.
+    pub fn at(mut self, location: Location) -> Self {
+        self.location = location;
+        self
+    }
+
+    /// Where in the request the value was found.
+    pub fn location(&self) -> Location {
+        self.location
+    }
+
+    /// The JSON pointer to the value. For parameters, the first token is the
+    /// parameter name, so the second item of a `tags` query parameter is at
+    /// `/tags/1`.
+    pub fn pointer(&self) -> &str {
+        &self.pointer
+    }
+
+    /// The schema keyword the value violates, such as `minimum` or
+    /// `required`, or `json` if a body isn't valid JSON.
+    pub fn code(&self) -> &str {
+        self.code
+    }
+
+    /// What is wrong with the value.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.pointer.is_empty() {
+            write!(f, "{}: {}", self.location, self.message)
+        } else {
+            write!(f, "{} {}: {}", self.location, self.pointer, self.message)
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+/// Every violation found in a request or value.
+///
+/// Serializes as an array of `ValidationError`s, and can be reported as a
+/// `400 Bad Request` problem listing them in its `errors` member.
+///
+/// ```rust
+/// # use swagger::validation::{constraints, Location, ValidationErrors};
+/// let mut errors = ValidationErrors::new();
+/// errors.check(constraints::min_length("/name", "", 1));
+/// errors.check(constraints::maximum("/limit", 500, 100, false).map_err(|e| e.at(Location::Query)));
+/// assert_eq!(errors.len(), 2);
+/// let problem = errors.into_problem();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct ValidationErrors(Vec<ValidationError>);
+
+impl ValidationErrors {
+    /// Create an empty collection.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an error.
+    pub fn push(&mut self, error: ValidationError) {
+        self.0.push(error);
+    }
+
+    /// Add the error of a check, if it failed.
+    pub fn check(&mut self, result: Result<(), ValidationError>) {
+        self.0.extend(result.err());
+    }
+
+    /// The number of errors.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether there are no errors.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterate over the errors.
+    pub fn iter(&self) -> std::slice::Iter<'_, ValidationError> {
+        self.0.iter()
+    }
+
+    /// `Ok` if there are no errors, and otherwise `Err` with the errors.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+
+    /// A `400 Bad Request` problem listing the errors in its `errors`
+    /// member.
+    pub fn into_problem(self) -> Problem {
+        Problem::new(StatusCode::BAD_REQUEST)
+            .with_detail(format!("Invalid request: {}", self))
+            .with_extension("errors", serde_json::to_value(self).unwrap_or_default())
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    /// Formats the errors separated by `; `.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, error) in self.0.iter().enumerate() {
+            if index > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ValidationErrors {}
+
+impl From<ValidationError> for ValidationErrors {
+    fn from(error: ValidationError) -> Self {
+        ValidationErrors(vec![error])
+    }
+}
+
+impl FromIterator<ValidationError> for ValidationErrors {
+    fn from_iter<I: IntoIterator<Item = ValidationError>>(iter: I) -> Self {
+        ValidationErrors(iter.into_iter().collect())
+    }
+}
+
+impl Extend<ValidationError> for ValidationErrors {
+    fn extend<I: IntoIterator<Item = ValidationError>>(&mut self, iter: I) {
+        self.0.extend(iter);
+    }
+}
+
+impl IntoIterator for ValidationErrors {
+    type Item = ValidationError;
+    type IntoIter = std::vec::IntoIter<ValidationError>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ValidationErrors {
+    type Item = &'a ValidationError;
+    type IntoIter = std::slice::Iter<'a, ValidationError>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// Error returned when an OpenAPI document can't be loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpecError(String);
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid OpenAPI document: {}", self.0)
+    }
+}
+
+impl Error for SpecError {}
+
+/// A parameter of an operation.
+#[derive(Debug)]
+struct Parameter {
+    name: String,
+    location: Location,
+    required: bool,
+    explode: bool,
+    schema: Option<Schema>,
+}
+
+impl Parameter {
+    /// Check the values of the parameter.
+    fn validate(&self, values: &[String], direction: Direction) -> Vec<ValidationError> {
+        let pointer = format!("/{}", escape(&self.name));
+        if values.is_empty() {
+            return if self.required {
+                let error = ValidationError::new(&pointer, "required", "is required".to_string());
+                vec![error.at(self.location)]
+            } else {
+                Vec::new()
+            };
+        }
+        let Some(schema) = &self.schema else {
+            return Vec::new();
+        };
+        let Some(value) = parameter_value(values, schema, self.explode) else {
+            return Vec::new();
+        };
+        match schema.validate_as(&value, &pointer, direction) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.into_iter().map(|e| e.at(self.location)).collect(),
+        }
+    }
+}
+
+/// The request body of an operation.
+#[derive(Debug)]
+struct RequestBody {
+    required: bool,
+    content: Vec<(MediaType, Option<Schema>)>,
+}
+
+/// An operation of the API.
+#[derive(Debug)]
+struct Operation {
+    id: Option<String>,
+    template: String,
+    parameters: Vec<Parameter>,
+    body: Option<RequestBody>,
+    responses: Vec<(String, response::ExpectedResponse)>,
+}
+
+/// An OpenAPI document, compiled for validating requests and responses.
+#[derive(Debug)]
+pub struct OpenApi {
+    document: Arc<Document>,
+    routes: Routes,
+    operations: HashMap<(String, Method), Operation>,
+}
+
+impl OpenApi {
+    /// Load an OpenAPI document from JSON.
+    pub fn from_json(document: &str) -> Result<Self, SpecError> {
+        let document = serde_json::from_str(document).map_err(|e| SpecError(e.to_string()))?;
+        Self::from_value(document)
+    }
+
+    /// Load an OpenAPI document from YAML.
+    pub fn from_yaml(document: &str) -> Result<Self, SpecError> {
+        let document = serde_yaml::from_str(document).map_err(|e| SpecError(e.to_string()))?;
+        Self::from_value(document)
+    }
+
+    /// Load an OpenAPI document.
+    pub fn from_value(document: Value) -> Result<Self, SpecError> {
+        let document = Arc::new(Document::new(document).map_err(|e| SpecError(e.to_string()))?);
+        let root = document.root();
+        let paths = root
+            .get("paths")
+            .and_then(Value::as_object)
+            .ok_or_else(|| SpecError("no paths".to_string()))?;
+        let base_path = base_path(root);
+
+        let mut routes = Routes::new();
+        let mut operations = HashMap::new();
+        for (template, path_item) in paths {
+            let template = format!(
+                "/{}",
+                format!("{}{}", base_path, template).trim_matches('/')
+            );
+            let path_item = document.deref(path_item);
+            let shared = path_item.get("parameters");
+            for (method, operation) in path_item.as_object().into_iter().flatten() {
+                let method = match method.as_str() {
+                    "get" => Method::GET,
+                    "put" => Method::PUT,
+                    "post" => Method::POST,
+                    "delete" => Method::DELETE,
+                    "options" => Method::OPTIONS,
+                    "head" => Method::HEAD,
+                    "patch" => Method::PATCH,
+                    "trace" => Method::TRACE,
+                    _ => continue,
+                };
+                let operation = Operation {
+                    id: operation
+                        .get("operationId")
+                        .and_then(Value::as_str)
+                        .map(str::to_string),
+                    template: template.clone(),
+                    parameters: parameters(&document, shared, operation.get("parameters")),
+                    body: request_body(&document, operation.get("requestBody"))?,
+                    responses: response::responses(&document, operation.get("responses"))?,
+                };
+                routes = routes.route(&template, [method.clone()]);
+                operations.insert((template.clone(), method), operation);
+            }
+        }
+
+        Ok(OpenApi {
+            document,
+            routes,
+            operations,
+        })
+    }
+
+    /// A schema from the `components` of the document, for validating values
+    /// outside the request, such as a field a handler has decoded.
+    pub fn schema(&self, name: &str) -> Option<Schema> {
+        let pointer = format!("/components/schemas/{}", escape(name));
+        let schema = self.document.root().pointer(&pointer)?.clone();
+        Some(Schema::new(self.document.clone(), schema))
+    }
+
+    /// The query parameters of each operation with an `operationId`, for
+    /// `StrictQueryService` to reject any others.
+    pub fn query_allowlist(&self) -> QueryAllowlist {
+        self.operations
+            .values()
+            .filter_map(|operation| Some((operation.id.as_deref()?, operation)))
+            .fold(QueryAllowlist::new(), |allowlist, (id, operation)| {
+                let names = operation
+                    .parameters
+                    .iter()
+                    .filter(|parameter| parameter.location == Location::Query)
+                    .map(|parameter| parameter.name.clone());
+                allowlist.operation(id, names)
+            })
+    }
+
+    /// Find the operation a request is for.
+    fn operation<B>(&self, req: &Request<B>) -> Option<&Operation> {
+        let template = self.routes.template(req.uri().path())?;
+        self.operations
+            .get(&(template.to_string(), req.method().clone()))
+    }
+
+    /// Check a request, with its body, against the operation it is for.
+    fn validate_request<B>(
+        operation: &Operation,
+        req: &Request<B>,
+        body: &[u8],
+    ) -> Result<(), Rejection> {
+        let mut errors = ValidationErrors::new();
+
+        let path_params = path_params(&operation.template, req.uri().path());
+        let query = QueryParams::from_uri(req.uri());
+        let cookies = Cookies::from_headers(req.headers());
+        for parameter in &operation.parameters {
+            let values: Vec<String> = match parameter.location {
+                Location::Path => path_params
+                    .get(&parameter.name)
+                    .cloned()
+                    .into_iter()
+                    .collect(),
+                Location::Query => query.get_all(&parameter.name).map(str::to_string).collect(),
+                Location::Header => req
+                    .headers()
+                    .get_all(parameter.name.as_str())
+                    .iter()
+                    .filter_map(|value| value.to_str().ok())
+                    .map(str::to_string)
+                    .collect(),
+                Location::Cookie => cookies
+                    .get(&parameter.name)
+                    .map(str::to_string)
+                    .into_iter()
+                    .collect(),
+                Location::Body => Vec::new(),
+            };
+            errors.extend(parameter.validate(&values, Direction::Request));
+        }
+
+        if let Some(request_body) = &operation.body {
+            errors.extend(validate_body(req, body, request_body)?);
+        }
+
+        errors.into_result().map_err(Rejection::Invalid)
+    }
+}
+
+/// The path of the first server of a document, such as `/api/v1`.
+fn base_path(root: &Value) -> String {
+    let url = root
+        .pointer("/servers/0/url")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("", |start| &rest[start..]),
+        None => url,
+    };
+    if path.contains('{') {
+        return String::new();
+    }
+    path.trim_end_matches('/').to_string()
+}
+
+/// Compile the parameters of an operation, including those shared by its
+/// path, which it may override.
+fn parameters(
+    document: &Arc<Document>,
+    shared: Option<&Value>,
+    own: Option<&Value>,
+) -> Vec<Parameter> {
+    let mut parameters: Vec<Parameter> = Vec::new();
+    let all = [shared, own]
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_array)
+        .flatten();
+    for parameter in all {
+        let parameter = document.deref(parameter);
+        let (Some(name), Some(location)) = (
+            parameter.get("name").and_then(Value::as_str),
+            parameter
+                .get("in")
+                .and_then(Value::as_str)
+                .and_then(Location::parse),
+        ) else {
+            continue;
+        };
+        let default_explode = matches!(location, Location::Query | Location::Cookie);
+        let parameter = Parameter {
+            // Header names are case-insensitive
+            name: match location {
+                Location::Header => name.to_ascii_lowercase(),
+                _ => name.to_string(),
+            },
+            location,
+            required: location == Location::Path
+                || parameter.get("required") == Some(&Value::Bool(true)),
+            explode: parameter
+                .get("explode")
+                .and_then(Value::as_bool)
+                .unwrap_or(default_explode),
+            schema: parameter
+                .get("schema")
+                .map(|schema| Schema::new(document.clone(), schema.clone())),
+        };
+        parameters.retain(|p| p.name != parameter.name || p.location != parameter.location);
+        parameters.push(parameter);
+    }
+    parameters
+}
+
+/// Compile the request body of an operation.
+fn request_body(
+    document: &Arc<Document>,
+    body: Option<&Value>,
+) -> Result<Option<RequestBody>, SpecError> {
+    let Some(body) = body.map(|body| document.deref(body)) else {
+        return Ok(None);
+    };
+    Ok(Some(RequestBody {
+        required: body.get("required") == Some(&Value::Bool(true)),
+        content: content(document, body.get("content"))?,
+    }))
+}
+
+/// Compile the media types and schemas of a request or response body.
+fn content(
+    document: &Arc<Document>,
+    content: Option<&Value>,
+) -> Result<Vec<(MediaType, Option<Schema>)>, SpecError> {
+    let mut compiled = Vec::new();
+    for (media_type, media) in content.and_then(Value::as_object).into_iter().flatten() {
+        let media_type = media_type
+            .parse()
+            .map_err(|e| SpecError(format!("{}: {}", media_type, e)))?;
+        let schema = media
+            .get("schema")
+            .map(|schema| Schema::new(document.clone(), schema.clone()));
+        compiled.push((media_type, schema));
+    }
+    Ok(compiled)
+}
+
+/// The decoded values of the parameters in a path, by name.
+fn path_params(template: &str, path: &str) -> HashMap<String, String> {
+    let segments = |s: &str| {
+        s.trim_matches('/')
+            .split('/')
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    segments(template)
+        .into_iter()
+        .zip(segments(path))
+        .filter_map(|(template, value)| {
+            let name = template.strip_prefix('{')?.strip_suffix('}')?;
+            Some((name.to_string(), decode_segment(&value).into_owned()))
+        })
+        .collect()
+}
+
+/// Convert a parameter string to the JSON type of its schema, leaving it as
+/// a string if it can't be converted, so validation reports the type.
+fn coerce(value: &str, schema: Option<&Schema>) -> Value {
+    match schema.and_then(Schema::primitive_type) {
+        Some("integer") => value
+            .parse::<i64>()
+            .map(Value::from)
+            .or_else(|_| value.parse::<u64>().map(Value::from))
+            .unwrap_or_else(|_| Value::from(value)),
+        Some("number") => value
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map_or_else(|| Value::from(value), Value::Number),
+        Some("boolean") => match value {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => Value::from(value),
+        },
+        _ => Value::from(value),
+    }
+}
+
+/// The JSON value of a parameter, or `None` if it's an object, which isn't
+/// validated.
+fn parameter_value(values: &[String], schema: &Schema, explode: bool) -> Option<Value> {
+    match schema.primitive_type() {
+        Some("array") => {
+            let items = schema.items();
+            let values: Vec<&str> = if explode {
+                values.iter().map(String::as_str).collect()
+            } else {
+                values.iter().flat_map(|value| value.split(',')).collect()
+            };
+            Some(Value::Array(
+                values
+                    .into_iter()
+                    .map(|value| coerce(value, items.as_ref()))
+                    .collect(),
+            ))
+        }
+        Some("object") => None,
+        _ => Some(coerce(&values[0], Some(schema))),
+    }
+}
+
+/// Whether a media type is JSON, such as `application/json` or
+/// `application/problem+json`.
+fn is_json(media_type: &MediaType) -> bool {
+    media_type.subtype() == "json" || media_type.subtype().ends_with("+json")
+}
+
+/// Check a request body against the request body of its operation.
+fn validate_body<B>(
+    req: &Request<B>,
+    body: &[u8],
+    request_body: &RequestBody,
+) -> Result<ValidationErrors, Rejection> {
+    if body.is_empty() {
+        return Ok(if request_body.required {
+            ValidationError::new("", "required", "is required".to_string()).into()
+        } else {
+            ValidationErrors::new()
+        });
+    }
+
+    let content_type: Option<MediaType> = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok());
+    let schema = content_type.as_ref().and_then(|content_type| {
+        request_body
+            .content
+            .iter()
+            .find(|(media_type, _)| media_type.includes(content_type))
+            .map(|(_, schema)| schema)
+    });
+    let (Some(content_type), Some(schema)) = (content_type, schema) else {
+        return Err(Rejection::UnsupportedMediaType);
+    };
+    let Some(schema) = schema.as_ref().filter(|_| is_json(&content_type)) else {
+        return Ok(ValidationErrors::new());
+    };
+
+    match serde_json::from_slice::<Value>(body) {
+        Ok(value) => Ok(schema
+            .validate_as(&value, "", Direction::Request)
+            .err()
+            .unwrap_or_default()),
+        Err(e) => Ok(ValidationError::new("", "json", format!("is not valid JSON: {}", e)).into()),
+    }
+}
+
+/// Why a request was rejected.
+#[derive(Debug)]
+enum Rejection {
+    Invalid(ValidationErrors),
+    UnsupportedMediaType,
+}
+
+impl Rejection {
+    fn into_response<B: From<String>>(self, x_span_id: &str) -> Response<B> {
+        match self {
+            Rejection::Invalid(errors) => {
+                let message = format!("Invalid request: {}", errors);
+                let mut response = coded_json_error(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_request",
+                    &message,
+                    Some(x_span_id),
+                );
+                // For `ProblemService` to list the errors
+                response.extensions_mut().insert(errors);
+                response
+            }
+            Rejection::UnsupportedMediaType => json_error(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "Unsupported Content-Type",
+                Some(x_span_id),
+            ),
+        }
+    }
+}
+
+/// Middleware wrapper service which validates requests against an OpenAPI
+/// document.
+#[derive(Debug)]
+pub struct ValidationMakeService<T> {
+    inner: T,
+    spec: Arc<OpenApi>,
+    max_body_size: u64,
+}
+
+impl<T> ValidationMakeService<T> {
+    /// Create a new ValidationMakeService, validating requests against
+    /// `spec`, with request bodies of up to 10MiB.
+    pub fn new(inner: T, spec: impl Into<Arc<OpenApi>>) -> Self {
+        ValidationMakeService {
+            inner,
+            spec: spec.into(),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Set the maximum size of request body, in bytes, which will be buffered
+    /// for validation. Longer bodies are rejected with `413 Content Too
+    /// Large`.
+    pub fn max_body_size(mut self, max_body_size: u64) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+}
+
+impl<Inner, Target> Service<Target> for ValidationMakeService<Inner>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = ValidationService<Inner::Response>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        use futures::FutureExt;
+        let spec = self.spec.clone();
+        let max_body_size = self.max_body_size;
+        Box::pin(self.inner.call(target).map(move |s| {
+            Ok(ValidationService {
+                inner: s?,
+                spec,
+                max_body_size,
+            })
+        }))
+    }
+}
+
+/// Middleware wrapper service which validates requests against an OpenAPI
+/// document. Servers will normally want to use `ValidationMakeService`,
+/// which will create a `ValidationService` for each connection.
+#[derive(Debug, Clone)]
+pub struct ValidationService<T> {
+    inner: T,
+    spec: Arc<OpenApi>,
+    max_body_size: u64,
+}
+
+impl<T> ValidationService<T> {
+    /// Create a new ValidationService, validating requests against `spec`,
+    /// with request bodies of up to 10MiB.
+    pub fn new(inner: T, spec: impl Into<Arc<OpenApi>>) -> Self {
+        ValidationService {
+            inner,
+            spec: spec.into(),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Set the maximum size of request body, in bytes, which will be buffered
+    /// for validation. Longer bodies are rejected with `413 Content Too
+    /// Large`.
+    pub fn max_body_size(mut self, max_body_size: u64) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+}
+
+impl<T, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for ValidationService<T>
+where
+    C: Has<XSpanIdString> + Send + 'static,
+    T: Service<(Request<Either<Full<Bytes>, ReqBody>>, C), Response = Response<ResBody>>
+        + Clone
+        + Send
+        + 'static,
+    T::Future: Send + 'static,
+    T::Error: Send + 'static,
+    ReqBody: Body + Send + 'static,
+    ReqBody::Data: Send,
+    ResBody: From<String> + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let inner = self.inner.clone();
+        let spec = self.spec.clone();
+        let max_body_size = self.max_body_size;
+        Box::pin(async move {
+            let x_span_id = Has::<XSpanIdString>::get(&context).0.clone();
+
+            // Requests matching no operation, or whose operation has no
+            // request body to check, are passed on without buffering the body
+            let Some(operation) = spec.operation(&req) else {
+                return inner.call((req.map(Either::Right), context)).await;
+            };
+            if operation.body.is_none() {
+                return match OpenApi::validate_request(operation, &req, &[]) {
+                    Ok(()) => inner.call((req.map(Either::Right), context)).await,
+                    Err(rejection) => Ok(rejection.into_response(&x_span_id)),
+                };
+            }
+
+            let (parts, body) = req.into_parts();
+            let body = match collect_limited(body, max_body_size).await {
+                Ok(body) => body,
+                Err(CollectError::TooLarge(limit)) => {
+                    let message = format!("Request body larger than {} bytes", limit);
+                    return Ok(json_error(
+                        StatusCode::PAYLOAD_TOO_LARGE,
+                        &message,
+                        Some(&x_span_id),
+                    ));
+                }
+                Err(_) => {
+                    return Ok(json_error(
+                        StatusCode::BAD_REQUEST,
+                        "Failed to read request body",
+                        Some(&x_span_id),
+                    ))
+                }
+            };
+            let req = Request::from_parts(parts, ());
+
+            match OpenApi::validate_request(operation, &req, &body) {
+                Ok(()) => {
+                    let req = req.map(|()| Either::Left(Full::new(body)));
+                    inner.call((req, context)).await
+                }
+                Err(rejection) => Ok(rejection.into_response(&x_span_id)),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContextBuilder, EmptyContext, Push};
+
+    const SPEC: &str = r##"
+openapi: 3.0.3
+servers:
+  - url: https://example.com/api/v1
+paths:
+  /pets/{petId}:
+    parameters:
+      - name: petId
+        in: path
+        schema: {type: integer, minimum: 1}
+    put:
+      operationId: updatePet
+      parameters:
+        - name: tags
+          in: query
+          explode: false
+          schema: {type: array, items: {type: string, enum: [cat, dog]}}
+        - name: X-Request-Id
+          in: header
+          required: true
+          schema: {type: string}
+      requestBody:
+        required: true
+        content:
+          application/json:
+            schema: {$ref: "#/components/schemas/Pet"}
+components:
+  schemas:
+    Pet:
+      type: object
+      required: [name]
+      properties:
+        name: {type: string}
+"##;
+
+    #[derive(Clone)]
+    struct TestApi;
+
+    /// Responds with whether the request body was buffered.
+    impl<B, C> Service<(Request<Either<Full<Bytes>, B>>, C)> for TestApi {
+        type Response = Response<String>;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, _): (Request<Either<Full<Bytes>, B>>, C)) -> Self::Future {
+            let body = match req.body() {
+                Either::Left(_) => "ok",
+                Either::Right(_) => "streamed",
+            };
+            futures::future::ok(Response::new(body.to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn validates_requests() {
+        let service =
+            ValidationService::new(TestApi, OpenApi::from_yaml(SPEC).unwrap()).max_body_size(16);
+        let call = |uri: &str, headers: &[(&str, &str)], body: &'static str| {
+            let mut request = Request::put(uri);
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+            let context: ContextBuilder<XSpanIdString, EmptyContext> =
+                EmptyContext.push(XSpanIdString("span".to_string()));
+            let body = Full::new(Bytes::from_static(body.as_bytes()));
+            service.call((request.body(body).unwrap(), context))
+        };
+        let json = ("content-type", "application/json");
+
+        let response = call(
+            "/api/v1/pets/1?tags=cat,dog",
+            &[json, ("x-request-id", "1")],
+            r#"{"name":"Rex"}"#,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.body(), "ok");
+
+        let response = call("/api/v1/pets/0?tags=cat,fish", &[json], r#"{"name":1}"#)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.body(),
+            "{\"code\":400,\"message\":\"Invalid request: \
+             path /petId: must be at least 1; \
+             query /tags/1: must be one of the allowed values; \
+             header /x-request-id: is required; \
+             body /name: must be of type string\",\"x-span-id\":\"span\"}"
+        );
+
+        let response = call("/api/v1/pets/1", &[("x-request-id", "1")], "Rex")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let response = call(
+            "/api/v1/pets/1",
+            &[json, ("x-request-id", "1")],
+            r#"{"name":"Rex the dog"}"#,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Requests for unknown operations are passed on, without buffering
+        let response = call("/pets/1", &[], "").await.unwrap();
+        assert_eq!(response.body(), "streamed");
+    }
+
+    #[test]
+    fn reports_errors_and_unknown_parameters() {
+        let spec = OpenApi::from_yaml(SPEC).unwrap();
+        let mut errors = ValidationErrors::new();
+        errors.check(
+            constraints::maximum("/limit", 500, 100, false).map_err(|e| e.at(Location::Query)),
+        );
+        if let Err(schema_errors) = spec.schema("Pet").unwrap().validate(&serde_json::json!({})) {
+            errors.extend(schema_errors);
+        }
+
+        let query = QueryParams::parse("tags=cat&fliter=1");
+        let allowlist = spec.query_allowlist();
+        assert_eq!(allowlist.unknown("updatePet", &query), Some(vec!["fliter"]));
+
+        let problem = errors.into_problem();
+        assert_eq!(problem.status, Some(400));
+        assert_eq!(
+            problem.extensions["errors"],
+            serde_json::json!([
+                {
+                    "location": "query",
+                    "pointer": "/limit",
+                    "code": "maximum",
+                    "message": "must be at most 100"
+                },
+                {
+                    "location": "body",
+                    "pointer": "/name",
+                    "code": "required",
+                    "message": "is required"
+                }
+            ])
+        );
+    }
+}
@@ -0,0 +1,453 @@
+//! Validation of responses against the OpenAPI document of a service.
+//!
+//! `ResponseValidationService` checks the responses of the API against the
+//! operations of the document, to catch drift between the document and the
+//! implementation in CI and staging:
+//!
+//! - The status code must be declared, exactly, by a range such as `2XX`, or
+//!   by a `default` response.
+//! - Required headers must be present, and headers must match their schemas.
+//! - The `Content-Type` must be declared, and JSON bodies must match their
+//!   schemas.
+//!
+//! Violations are logged, or the response is replaced with
+//! `500 Internal Server Error`, depending on the `ResponseMode`. Only the
+//! bodies of responses which have a schema to check are buffered, up to the
+//! service's `max_body_size` - longer bodies are reported as a violation,
+//! and sent unchecked. Bodies of unknown length, and streaming media types
+//! such as `text/event-stream`, are never buffered.
+
+use super::{content, is_json, Direction, OpenApi, Operation, Parameter, SpecError};
+use super::{Document, Location, Schema, DEFAULT_MAX_BODY_SIZE};
+use crate::body_ext::collect_limited;
+use crate::media_type::MediaType;
+use crate::response::json_error;
+use crate::{Has, XSpanIdString};
+use futures::future::BoxFuture;
+use http_body_util::{Either, Full};
+use hyper::body::{Body, Bytes};
+use hyper::header::CONTENT_TYPE;
+use hyper::service::Service;
+use hyper::{Request, Response, StatusCode};
+use serde_json::Value;
+use std::sync::Arc;
+
+const LOG_TARGET: &str = "swagger::validation";
+
+/// Media types whose bodies are streamed, so are never buffered to be
+/// checked, even if they are JSON.
+const STREAMING_TYPES: [&str; 4] = [
+    "application/json-seq",
+    "application/stream+json",
+    "application/x-ndjson",
+    "text/event-stream",
+];
+
+/// What to do with responses which don't conform to the API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResponseMode {
+    /// Log a warning, and send the response anyway.
+    #[default]
+    Log,
+    /// Log a warning, and send `500 Internal Server Error` instead.
+    Fail,
+}
+
+/// A response declared by an operation.
+#[derive(Debug)]
+pub(super) struct ExpectedResponse {
+    headers: Vec<Parameter>,
+    content: Vec<(MediaType, Option<Schema>)>,
+}
+
+/// Compile the responses of an operation, by status code pattern.
+pub(super) fn responses(
+    document: &Arc<Document>,
+    responses: Option<&Value>,
+) -> Result<Vec<(String, ExpectedResponse)>, SpecError> {
+    let mut compiled = Vec::new();
+    for (status, response) in responses.and_then(Value::as_object).into_iter().flatten() {
+        let response = document.deref(response);
+        let headers = response
+            .get("headers")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+            // The Content-Type is described by the content
+            .filter(|(name, _)| !name.eq_ignore_ascii_case("content-type"))
+            .map(|(name, header)| {
+                let header = document.deref(header);
+                Parameter {
+                    name: name.to_ascii_lowercase(),
+                    location: Location::Header,
+                    required: header.get("required") == Some(&Value::Bool(true)),
+                    explode: false,
+                    schema: header
+                        .get("schema")
+                        .map(|schema| Schema::new(document.clone(), schema.clone())),
+                }
+            })
+            .collect();
+        let response = ExpectedResponse {
+            headers,
+            content: content(document, response.get("content"))?,
+        };
+        compiled.push((status.to_ascii_uppercase(), response));
+    }
+    Ok(compiled)
+}
+
+/// Find the response declared for a status code, preferring an exact match
+/// to a range, and a range to the default.
+fn expected(operation: &Operation, status: StatusCode) -> Option<&ExpectedResponse> {
+    let code = status.as_str();
+    let range = format!("{}XX", &code[..1]);
+    [code, range.as_str(), "DEFAULT"].iter().find_map(|key| {
+        operation
+            .responses
+            .iter()
+            .find(|(status, _)| status == key)
+            .map(|(_, response)| response)
+    })
+}
+
+/// The schema a response body must match, if any, and any problems with the
+/// status code, headers and `Content-Type`.
+fn check_head<B>(operation: &Operation, response: &Response<B>) -> (Option<Schema>, Vec<String>) {
+    let Some(expected) = expected(operation, response.status()) else {
+        let problem = format!("status {} is not declared", response.status().as_u16());
+        return (None, vec![problem]);
+    };
+
+    let mut problems = Vec::new();
+    for header in &expected.headers {
+        let values: Vec<String> = response
+            .headers()
+            .get_all(header.name.as_str())
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .map(str::to_string)
+            .collect();
+        let errors = header.validate(&values, Direction::Response);
+        problems.extend(errors.iter().map(ToString::to_string));
+    }
+
+    let content_type: Option<MediaType> = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok());
+    let Some(content_type) = content_type else {
+        return (None, problems);
+    };
+    let declared = expected
+        .content
+        .iter()
+        .find(|(media_type, _)| media_type.includes(&content_type));
+    match declared {
+        Some((_, schema)) => {
+            let streaming = STREAMING_TYPES.contains(&content_type.essence().as_str());
+            let schema = schema
+                .clone()
+                .filter(|_| is_json(&content_type) && !streaming);
+            (schema, problems)
+        }
+        None => {
+            problems.push(format!("content type {} is not declared", content_type));
+            (None, problems)
+        }
+    }
+}
+
+/// Middleware wrapper service which validates responses against an OpenAPI
+/// document.
+#[derive(Debug)]
+pub struct ResponseValidationMakeService<T> {
+    inner: T,
+    spec: Arc<OpenApi>,
+    mode: ResponseMode,
+    max_body_size: u64,
+}
+
+impl<T> ResponseValidationMakeService<T> {
+    /// Create a new ResponseValidationMakeService, validating responses
+    /// against `spec`, logging violations, and checking bodies of up to 10MiB.
+    pub fn new(inner: T, spec: impl Into<Arc<OpenApi>>) -> Self {
+        ResponseValidationMakeService {
+            inner,
+            spec: spec.into(),
+            mode: ResponseMode::default(),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Set what to do with responses which don't conform to the API.
+    pub fn mode(mut self, mode: ResponseMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the maximum size of response body, in bytes, which will be
+    /// buffered to be checked. Longer bodies are reported as a violation.
+    pub fn max_body_size(mut self, max_body_size: u64) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+}
+
+impl<Inner, Target> Service<Target> for ResponseValidationMakeService<Inner>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = ResponseValidationService<Inner::Response>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        use futures::FutureExt;
+        let spec = self.spec.clone();
+        let mode = self.mode;
+        let max_body_size = self.max_body_size;
+        Box::pin(self.inner.call(target).map(move |s| {
+            Ok(ResponseValidationService {
+                inner: s?,
+                spec,
+                mode,
+                max_body_size,
+            })
+        }))
+    }
+}
+
+/// Middleware wrapper service which validates responses against an OpenAPI
+/// document. Servers will normally want to use
+/// `ResponseValidationMakeService`, which will create a
+/// `ResponseValidationService` for each connection.
+#[derive(Debug, Clone)]
+pub struct ResponseValidationService<T> {
+    inner: T,
+    spec: Arc<OpenApi>,
+    mode: ResponseMode,
+    max_body_size: u64,
+}
+
+impl<T> ResponseValidationService<T> {
+    /// Create a new ResponseValidationService, validating responses against
+    /// `spec`, logging violations, and checking bodies of up to 10MiB.
+    pub fn new(inner: T, spec: impl Into<Arc<OpenApi>>) -> Self {
+        ResponseValidationService {
+            inner,
+            spec: spec.into(),
+            mode: ResponseMode::default(),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Set what to do with responses which don't conform to the API.
+    pub fn mode(mut self, mode: ResponseMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the maximum size of response body, in bytes, which will be
+    /// buffered to be checked. Longer bodies are reported as a violation.
+    pub fn max_body_size(mut self, max_body_size: u64) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+}
+
+impl<T, ReqBody, ResBody, C> Service<(Request<ReqBody>, C)> for ResponseValidationService<T>
+where
+    C: Has<XSpanIdString>,
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+    ResBody: Body<Data = Bytes> + Send + 'static,
+{
+    type Response = Response<Either<Full<Bytes>, ResBody>>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let key = self
+            .spec
+            .routes
+            .template(req.uri().path())
+            .map(|template| (template.to_string(), req.method().clone()));
+        let description = format!("{} {}", req.method(), req.uri().path());
+        let x_span_id = Has::<XSpanIdString>::get(&context).0.clone();
+        let spec = self.spec.clone();
+        let mode = self.mode;
+        let max_body_size = self.max_body_size;
+        let response = self.inner.call((req, context));
+
+        Box::pin(async move {
+            let response = response.await?;
+            let operation = key.and_then(|key| spec.operations.get(&key));
+            // HEAD responses have no body to check
+            let Some(operation) = operation.filter(|_| !description.starts_with("HEAD ")) else {
+                return Ok(response.map(Either::Right));
+            };
+
+            let (schema, mut problems) = check_head(operation, &response);
+            // Bodies of unknown length are streams, and aren't checked, and
+            // bodies which may be longer than the limit aren't buffered
+            let size_hint = response.body().size_hint();
+            let too_large = size_hint.lower() > max_body_size
+                || size_hint.upper().is_some_and(|upper| upper > max_body_size);
+            let schema = match schema {
+                Some(_) if too_large => {
+                    problems.push(format!(
+                        "body: may be larger than {} bytes, so was not checked",
+                        max_body_size
+                    ));
+                    None
+                }
+                schema => schema.filter(|_| size_hint.upper().is_some()),
+            };
+            let response = match schema {
+                Some(schema) => {
+                    let (parts, body) = response.into_parts();
+                    let body = collect_limited(body, max_body_size)
+                        .await
+                        .unwrap_or_default();
+                    match serde_json::from_slice::<Value>(&body) {
+                        Ok(value) => {
+                            if let Err(errors) = schema.validate_as(&value, "", Direction::Response)
+                            {
+                                problems.extend(errors.iter().map(ToString::to_string));
+                            }
+                        }
+                        Err(e) => problems.push(format!("body: is not valid JSON: {}", e)),
+                    }
+                    Response::from_parts(parts, Either::Left(Full::new(body)))
+                }
+                None => response.map(Either::Right),
+            };
+
+            if problems.is_empty() {
+                return Ok(response);
+            }
+            log::warn!(
+                target: LOG_TARGET,
+                "Response to {} does not conform to the API: {} - X-Span-ID: {}",
+                description,
+                problems.join("; "),
+                x_span_id
+            );
+            Ok(match mode {
+                ResponseMode::Log => response,
+                ResponseMode::Fail => json_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Internal Server Error",
+                    Some(&x_span_id),
+                )
+                .map(Either::Left),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContextBuilder, EmptyContext, Push};
+    use http_body_util::BodyExt;
+    use hyper::Method;
+
+    const SPEC: &str = r##"{
+        "openapi": "3.1.0",
+        "paths": {"/pets/{petId}": {"get": {"responses": {
+            "200": {
+                "headers": {"ETag": {"required": true, "schema": {"type": "string"}}},
+                "content": {"application/json": {"schema": {
+                    "type": "object",
+                    "required": ["id", "password"],
+                    "properties": {
+                        "id": {"type": "integer"},
+                        "password": {"type": "string", "writeOnly": true}
+                    }
+                }}}
+            },
+            "4XX": {"content": {"application/json": {}}}
+        }}}}
+    }"##;
+
+    #[derive(Clone)]
+    struct TestApi;
+
+    impl<C> Service<(Request<()>, C)> for TestApi {
+        type Response = Response<Full<Bytes>>;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, _): (Request<()>, C)) -> Self::Future {
+            let (status, etag, body) = match req.uri().path() {
+                "/pets/1" => (200, Some("\"1\""), r#"{"id":1}"#),
+                "/pets/2" => (200, None, r#"{"id":"2"}"#),
+                "/pets/3" => (404, None, r#"{"code":404}"#),
+                _ => (500, None, ""),
+            };
+            let mut response = Response::builder()
+                .status(status)
+                .header(CONTENT_TYPE, "application/json");
+            if let Some(etag) = etag {
+                response = response.header("etag", etag);
+            }
+            futures::future::ok(response.body(Full::from(body)).unwrap())
+        }
+    }
+
+    #[tokio::test]
+    async fn validates_responses() {
+        let spec = OpenApi::from_json(SPEC).unwrap();
+        let service = ResponseValidationService::new(TestApi, spec).mode(ResponseMode::Fail);
+        let call = |uri: &str| {
+            let context: ContextBuilder<XSpanIdString, EmptyContext> =
+                EmptyContext.push(XSpanIdString("span".to_string()));
+            service.call((Request::get(uri).body(()).unwrap(), context))
+        };
+
+        let response = call("/pets/1").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, r#"{"id":1}"#);
+        assert_eq!(
+            call("/pets/3").await.unwrap().status(),
+            StatusCode::NOT_FOUND
+        );
+
+        // Missing ETag, and wrong type of id
+        let response = call("/pets/2").await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        // Undeclared status code
+        let response = call("/pets/4").await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        // Bodies too long to check
+        let spec = OpenApi::from_json(SPEC).unwrap();
+        let service = ResponseValidationService::new(TestApi, spec)
+            .mode(ResponseMode::Fail)
+            .max_body_size(4);
+        let context: ContextBuilder<XSpanIdString, EmptyContext> =
+            EmptyContext.push(XSpanIdString("span".to_string()));
+        let request = Request::get("/pets/1").body(()).unwrap();
+        let response = service.call((request, context)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+        let spec = OpenApi::from_json(SPEC).unwrap();
+        let operation = spec
+            .operations
+            .get(&("/pets/{petId}".to_string(), Method::GET))
+            .unwrap();
+        let mut response = Response::new(());
+        *response.status_mut() = StatusCode::OK;
+        *response.headers_mut() = headers;
+        let (schema, problems) = check_head(operation, &response);
+        assert!(schema.is_some());
+        assert_eq!(problems, ["header /etag: is required"]);
+    }
+}
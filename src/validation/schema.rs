@@ -0,0 +1,570 @@
+//! Validation of JSON values against the schemas of an OpenAPI document.
+//!
+//! The OpenAPI 3.0 and 3.1 dialects of JSON Schema are both supported, with
+//! the keywords commonly used to describe API models: `type` (including
+//! `nullable`), `enum`, `const`, the numeric, string, array and object
+//! constraints, and the `allOf`, `anyOf`, `oneOf` and `not` combinators.
+//! References are resolved against the whole document, so recursive schemas
+//! work. Other keywords, such as `format`, are ignored.
+
+use super::{constraints, ValidationError, ValidationErrors};
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Limit on how deeply schemas are followed, to catch reference cycles which
+/// don't consume any of the value.
+const MAX_DEPTH: usize = 64;
+
+/// Which way a value is being sent, as required `readOnly` properties are
+/// only sent in responses, and required `writeOnly` properties only in
+/// requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Direction {
+    Request,
+    Response,
+}
+
+/// An OpenAPI document, with the regular expressions of its `pattern`
+/// keywords compiled.
+#[derive(Debug)]
+pub(crate) struct Document {
+    root: Value,
+    patterns: HashMap<String, Regex>,
+}
+
+impl Document {
+    /// Compile the patterns of a document, failing if any is invalid.
+    pub(crate) fn new(root: Value) -> Result<Self, regex::Error> {
+        let mut patterns = HashMap::new();
+        let mut stack = vec![&root];
+        while let Some(value) = stack.pop() {
+            match value {
+                Value::Object(object) => {
+                    if let Some(Value::String(pattern)) = object.get("pattern") {
+                        if !patterns.contains_key(pattern) {
+                            patterns.insert(pattern.clone(), Regex::new(pattern)?);
+                        }
+                    }
+                    stack.extend(object.values());
+                }
+                Value::Array(array) => stack.extend(array),
+                _ => {}
+            }
+        }
+        Ok(Document { root, patterns })
+    }
+
+    /// The document.
+    pub(crate) fn root(&self) -> &Value {
+        &self.root
+    }
+
+    /// Follow a local reference, such as `#/components/schemas/Pet`.
+    pub(crate) fn resolve<'a>(&'a self, reference: &str) -> Option<&'a Value> {
+        self.root.pointer(reference.strip_prefix('#')?)
+    }
+
+    /// Follow any chain of references from a value.
+    pub(crate) fn deref<'a>(&'a self, mut value: &'a Value) -> &'a Value {
+        for _ in 0..MAX_DEPTH {
+            match value.get("$ref").and_then(Value::as_str) {
+                Some(reference) => match self.resolve(reference) {
+                    Some(target) => value = target,
+                    None => break,
+                },
+                None => break,
+            }
+        }
+        value
+    }
+}
+
+/// A schema from an OpenAPI document.
+#[derive(Clone)]
+pub struct Schema {
+    document: Arc<Document>,
+    schema: Value,
+}
+
+impl fmt::Debug for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Schema").field(&self.schema).finish()
+    }
+}
+
+impl Schema {
+    pub(crate) fn new(document: Arc<Document>, schema: Value) -> Self {
+        Schema { document, schema }
+    }
+
+    /// The type of the schema, such as `integer`, if it has a single
+    /// non-null type.
+    pub(crate) fn primitive_type(&self) -> Option<&str> {
+        primitive_type(&self.document, &self.schema)
+    }
+
+    /// The schema of the items of an array schema.
+    pub(crate) fn items(&self) -> Option<Schema> {
+        let items = self.document.deref(&self.schema).get("items")?;
+        Some(Schema::new(self.document.clone(), items.clone()))
+    }
+
+    /// Validate a value sent in a request, returning every violation.
+    pub fn validate(&self, value: &Value) -> Result<(), ValidationErrors> {
+        self.validate_as(value, "", Direction::Request)
+    }
+
+    /// Validate a value, reporting violations at paths under `pointer`.
+    pub(crate) fn validate_as(
+        &self,
+        value: &Value,
+        pointer: &str,
+        direction: Direction,
+    ) -> Result<(), ValidationErrors> {
+        let mut validator = Validator {
+            document: &self.document,
+            direction,
+            errors: Vec::new(),
+        };
+        validator.check(&self.schema, value, pointer, 0);
+        validator
+            .errors
+            .into_iter()
+            .collect::<ValidationErrors>()
+            .into_result()
+    }
+}
+
+/// The single non-null type of a schema, if any.
+fn primitive_type<'a>(document: &'a Document, schema: &'a Value) -> Option<&'a str> {
+    let schema = document.deref(schema);
+    match schema.get("type") {
+        Some(Value::String(type_)) => Some(type_),
+        Some(Value::Array(types)) => {
+            let mut types = types
+                .iter()
+                .filter_map(Value::as_str)
+                .filter(|t| *t != "null");
+            let type_ = types.next();
+            types.next().is_none().then_some(type_).flatten()
+        }
+        _ => schema
+            .get("allOf")
+            .and_then(Value::as_array)
+            .and_then(|all_of| all_of.iter().find_map(|s| primitive_type(document, s))),
+    }
+}
+
+/// Escape a property name for use in a JSON pointer.
+pub(crate) fn escape(name: &str) -> String {
+    name.replace('~', "~0").replace('/', "~1")
+}
+
+/// The JSON type names a value has. Integers are also numbers.
+fn is_type(value: &Value, type_: &str) -> bool {
+    match type_ {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => match value {
+            Value::Number(n) => {
+                n.is_i64() || n.is_u64() || n.as_f64().is_some_and(|f| f.fract() == 0.0)
+            }
+            _ => false,
+        },
+        _ => true,
+    }
+}
+
+/// Whether two JSON values are equal, treating `1` and `1.0` as equal.
+fn json_eq(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| json_eq(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key).is_some_and(|b| json_eq(a, b)))
+        }
+        (a, b) => a == b,
+    }
+}
+
+/// A JSON value compared with `json_eq`.
+struct JsonEq<'a>(&'a Value);
+
+impl PartialEq for JsonEq<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        json_eq(self.0, other.0)
+    }
+}
+
+struct Validator<'a> {
+    document: &'a Document,
+    direction: Direction,
+    errors: Vec<ValidationError>,
+}
+
+impl Validator<'_> {
+    fn error(&mut self, pointer: &str, code: &'static str, message: String) {
+        self.errors
+            .push(ValidationError::new(pointer, code, message));
+    }
+
+    fn record(&mut self, result: Result<(), ValidationError>) {
+        self.errors.extend(result.err());
+    }
+
+    /// Whether a value is valid against a schema, without recording errors.
+    fn is_valid(&self, schema: &Value, value: &Value, depth: usize) -> bool {
+        let mut validator = Validator {
+            document: self.document,
+            direction: self.direction,
+            errors: Vec::new(),
+        };
+        validator.check(schema, value, "", depth);
+        validator.errors.is_empty()
+    }
+
+    fn check(&mut self, schema: &Value, value: &Value, pointer: &str, depth: usize) {
+        if depth > MAX_DEPTH {
+            self.error(pointer, "depth", "schema is nested too deeply".to_string());
+            return;
+        }
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => {
+                return self.error(pointer, "false", "no value is allowed".to_string());
+            }
+            Value::Object(schema) => schema,
+            _ => return,
+        };
+
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            match self.document.resolve(reference) {
+                Some(target) => self.check(target, value, pointer, depth + 1),
+                None => self.error(
+                    pointer,
+                    "$ref",
+                    format!("unresolved reference {}", reference),
+                ),
+            }
+            // In OpenAPI 3.0, siblings of `$ref` are ignored
+            if schema.len() == 1 {
+                return;
+            }
+        }
+
+        if value.is_null() && schema.get("nullable") == Some(&Value::Bool(true)) {
+            return;
+        }
+
+        match schema.get("type") {
+            Some(Value::String(type_)) if !is_type(value, type_) => {
+                return self.error(pointer, "type", format!("must be of type {}", type_));
+            }
+            Some(Value::Array(types))
+                if !types
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .any(|t| is_type(value, t)) =>
+            {
+                let types: Vec<_> = types.iter().filter_map(Value::as_str).collect();
+                return self.error(
+                    pointer,
+                    "type",
+                    format!("must be of type {}", types.join(" or ")),
+                );
+            }
+            _ => {}
+        }
+
+        if let Some(Value::Array(values)) = schema.get("enum") {
+            let values: Vec<_> = values.iter().map(JsonEq).collect();
+            self.record(constraints::enumeration(pointer, &JsonEq(value), &values));
+        }
+        if let Some(constant) = schema.get("const") {
+            if !json_eq(constant, value) {
+                self.error(pointer, "const", format!("must be {}", constant));
+            }
+        }
+
+        match value {
+            Value::Number(number) => {
+                if let Some(number) = number.as_f64() {
+                    self.check_number(schema, number, pointer);
+                }
+            }
+            Value::String(string) => self.check_string(schema, string, pointer),
+            Value::Array(items) => self.check_array(schema, items, pointer, depth),
+            Value::Object(object) => self.check_object(schema, object, pointer, depth),
+            _ => {}
+        }
+
+        self.check_combinators(schema, value, pointer, depth);
+    }
+
+    fn check_number(
+        &mut self,
+        schema: &serde_json::Map<String, Value>,
+        number: f64,
+        pointer: &str,
+    ) {
+        let exclusive = |name| schema.get(name) == Some(&Value::Bool(true));
+        if let Some(minimum) = schema.get("minimum").and_then(Value::as_f64) {
+            let exclusive = exclusive("exclusiveMinimum");
+            self.record(constraints::minimum(pointer, number, minimum, exclusive));
+        }
+        if let Some(maximum) = schema.get("maximum").and_then(Value::as_f64) {
+            let exclusive = exclusive("exclusiveMaximum");
+            self.record(constraints::maximum(pointer, number, maximum, exclusive));
+        }
+        // OpenAPI 3.1 exclusive bounds are numbers
+        if let Some(minimum) = schema.get("exclusiveMinimum").and_then(Value::as_f64) {
+            self.record(constraints::minimum(pointer, number, minimum, true));
+        }
+        if let Some(maximum) = schema.get("exclusiveMaximum").and_then(Value::as_f64) {
+            self.record(constraints::maximum(pointer, number, maximum, true));
+        }
+        if let Some(multiple) = schema.get("multipleOf").and_then(Value::as_f64) {
+            self.record(constraints::multiple_of(pointer, number, multiple));
+        }
+    }
+
+    fn check_string(
+        &mut self,
+        schema: &serde_json::Map<String, Value>,
+        string: &str,
+        pointer: &str,
+    ) {
+        if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+            self.record(constraints::min_length(pointer, string, min as usize));
+        }
+        if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+            self.record(constraints::max_length(pointer, string, max as usize));
+        }
+        if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+            if let Some(regex) = self.document.patterns.get(pattern) {
+                self.record(constraints::pattern(pointer, string, regex));
+            }
+        }
+    }
+
+    fn check_array(
+        &mut self,
+        schema: &serde_json::Map<String, Value>,
+        items: &[Value],
+        pointer: &str,
+        depth: usize,
+    ) {
+        if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+            self.record(constraints::min_items(pointer, items, min as usize));
+        }
+        if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+            self.record(constraints::max_items(pointer, items, max as usize));
+        }
+        if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+            let items: Vec<_> = items.iter().map(JsonEq).collect();
+            self.record(constraints::unique_items(pointer, &items));
+        }
+        if let Some(item_schema) = schema.get("items") {
+            for (index, item) in items.iter().enumerate() {
+                let pointer = format!("{}/{}", pointer, index);
+                self.check(item_schema, item, &pointer, depth + 1);
+            }
+        }
+    }
+
+    fn check_object(
+        &mut self,
+        schema: &serde_json::Map<String, Value>,
+        object: &serde_json::Map<String, Value>,
+        pointer: &str,
+        depth: usize,
+    ) {
+        let properties = schema.get("properties").and_then(Value::as_object);
+        let property = |name: &str| properties.and_then(|p| p.get(name));
+        let document = self.document;
+        let excluded = match self.direction {
+            Direction::Request => "readOnly",
+            Direction::Response => "writeOnly",
+        };
+        let excluded = |name: &str| {
+            property(name).is_some_and(|p| {
+                p.get(excluded) == Some(&Value::Bool(true))
+                    || document.deref(p).get(excluded) == Some(&Value::Bool(true))
+            })
+        };
+
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for name in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(name) && !excluded(name) {
+                    let pointer = format!("{}/{}", pointer, escape(name));
+                    self.error(&pointer, "required", "is required".to_string());
+                }
+            }
+        }
+
+        let length = object.len() as u64;
+        if let Some(min) = schema.get("minProperties").and_then(Value::as_u64) {
+            if length < min {
+                self.error(
+                    pointer,
+                    "minProperties",
+                    format!("must have at least {} properties", min),
+                );
+            }
+        }
+        if let Some(max) = schema.get("maxProperties").and_then(Value::as_u64) {
+            if length > max {
+                self.error(
+                    pointer,
+                    "maxProperties",
+                    format!("must have at most {} properties", max),
+                );
+            }
+        }
+
+        let additional = schema.get("additionalProperties");
+        for (name, value) in object {
+            let pointer = format!("{}/{}", pointer, escape(name));
+            match (property(name), additional) {
+                (Some(property), _) => self.check(property, value, &pointer, depth + 1),
+                (None, Some(additional)) => self.check(additional, value, &pointer, depth + 1),
+                (None, None) => {}
+            }
+        }
+    }
+
+    fn check_combinators(
+        &mut self,
+        schema: &serde_json::Map<String, Value>,
+        value: &Value,
+        pointer: &str,
+        depth: usize,
+    ) {
+        if let Some(Value::Array(all_of)) = schema.get("allOf") {
+            for subschema in all_of {
+                self.check(subschema, value, pointer, depth + 1);
+            }
+        }
+        if let Some(Value::Array(any_of)) = schema.get("anyOf") {
+            if !any_of.iter().any(|s| self.is_valid(s, value, depth + 1)) {
+                self.error(
+                    pointer,
+                    "anyOf",
+                    "must match at least one schema in anyOf".to_string(),
+                );
+            }
+        }
+        if let Some(Value::Array(one_of)) = schema.get("oneOf") {
+            let matches = one_of
+                .iter()
+                .filter(|s| self.is_valid(s, value, depth + 1))
+                .count();
+            if matches != 1 {
+                self.error(
+                    pointer,
+                    "oneOf",
+                    "must match exactly one schema in oneOf".to_string(),
+                );
+            }
+        }
+        if let Some(not) = schema.get("not") {
+            if self.is_valid(not, value, depth + 1) {
+                self.error(
+                    pointer,
+                    "not",
+                    "must not match the schema in not".to_string(),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema(document: Value, schema: Value) -> Schema {
+        Schema::new(Arc::new(Document::new(document).unwrap()), schema)
+    }
+
+    fn errors(schema: &Schema, value: Value) -> Vec<String> {
+        match schema.validate(&value) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    #[test]
+    fn validates_values() {
+        let document = json!({
+            "components": {"schemas": {
+                "Pet": {
+                    "type": "object",
+                    "required": ["id", "name"],
+                    "properties": {
+                        "id": {"type": "integer", "readOnly": true},
+                        "name": {"type": "string", "minLength": 1, "pattern": "^[A-Z]"},
+                        "tag": {"type": "string", "nullable": true, "enum": ["cat", "dog"]},
+                        "age": {"type": "number", "minimum": 0, "exclusiveMaximum": 50},
+                        "children": {
+                            "type": "array",
+                            "uniqueItems": true,
+                            "items": {"$ref": "#/components/schemas/Pet"}
+                        }
+                    },
+                    "additionalProperties": false
+                }
+            }}
+        });
+        let pet = schema(document, json!({"$ref": "#/components/schemas/Pet"}));
+
+        assert!(errors(&pet, json!({"name": "Rex", "tag": null, "age": 3})).is_empty());
+        assert_eq!(
+            errors(
+                &pet,
+                json!({
+                    "name": "rex",
+                    "tag": "fish",
+                    "age": 50,
+                    "children": [{"name": ""}, {"name": ""}],
+                    "colour": "brown"
+                })
+            ),
+            [
+                "body /age: must be less than 50",
+                "body /children: must not contain duplicate items",
+                "body /children/0/name: must be at least 1 characters long",
+                "body /children/0/name: must match the pattern ^[A-Z]",
+                "body /children/1/name: must be at least 1 characters long",
+                "body /children/1/name: must match the pattern ^[A-Z]",
+                "body /colour: no value is allowed",
+                "body /name: must match the pattern ^[A-Z]",
+                "body /tag: must be one of the allowed values",
+            ]
+        );
+        assert_eq!(errors(&pet, json!([])), ["body: must be of type object"]);
+
+        let one_of = schema(
+            json!({}),
+            json!({"oneOf": [{"type": ["integer", "null"]}, {"type": "number", "multipleOf": 0.5}]}),
+        );
+        assert!(errors(&one_of, json!(1.5)).is_empty());
+        assert!(errors(&one_of, json!(null)).is_empty());
+        assert_eq!(
+            errors(&one_of, json!(2)),
+            ["body: must match exactly one schema in oneOf"]
+        );
+    }
+}
@@ -0,0 +1,220 @@
+//! Hyper service maintaining the `Vary` header of responses.
+//!
+//! A response which depends on request headers - through content
+//! negotiation, compression, language or authentication - must list them in
+//! its `Vary` header, or caches may serve it to the wrong clients.
+//!
+//! `VaryService` adds a `VaryHeaders` entry to the context of each request,
+//! in which inner middleware and the API implementation register the request
+//! headers they look at. These are merged with any `Vary` headers already on
+//! the response - such as those set by `CorsService` and
+//! `CompressionService` - into a single `Vary` header without duplicates. If
+//! any header is `*`, the response varies on everything, so the header is
+//! just `*`.
+//!
+//! ```rust
+//! # use hyper::header::ACCEPT_LANGUAGE;
+//! # use swagger::vary::VaryHeaders;
+//! # use swagger::Has;
+//! # fn get_greeting<C: Has<VaryHeaders>>(context: &C) {
+//! // In the API implementation
+//! context.get().add(ACCEPT_LANGUAGE);
+//! # }
+//! ```
+
+use crate::Push;
+use futures::future::{BoxFuture, FutureExt};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, VARY};
+use hyper::service::Service;
+use hyper::{Request, Response};
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+/// The request headers a response depends on, added to the context of each
+/// request by `VaryService`.
+#[derive(Clone, Default)]
+pub struct VaryHeaders(Arc<Mutex<Vec<HeaderName>>>);
+
+impl VaryHeaders {
+    /// Register a request header the response depends on.
+    pub fn add(&self, name: HeaderName) {
+        let mut names = self.0.lock().expect("vary headers lock poisoned");
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    /// The registered headers, in the order they were added.
+    pub fn names(&self) -> Vec<HeaderName> {
+        self.0.lock().expect("vary headers lock poisoned").clone()
+    }
+}
+
+impl fmt::Debug for VaryHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("VaryHeaders").field(&self.names()).finish()
+    }
+}
+
+/// Merge the `Vary` headers of a response with extra header names into a
+/// single `Vary` header.
+pub fn merge_vary<I: IntoIterator<Item = HeaderName>>(headers: &mut HeaderMap, extra: I) {
+    let mut names: Vec<String> = Vec::new();
+    let existing = headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_string());
+    let extra = extra.into_iter().map(|name| name.as_str().to_string());
+    for name in existing.chain(extra) {
+        if !name.is_empty() && !names.iter().any(|n| n.eq_ignore_ascii_case(&name)) {
+            names.push(name);
+        }
+    }
+
+    if names.is_empty() {
+        return;
+    }
+    let value = if names.iter().any(|name| name == "*") {
+        HeaderValue::from_static("*")
+    } else {
+        match HeaderValue::from_str(&names.join(", ")) {
+            Ok(value) => value,
+            Err(_) => return,
+        }
+    };
+    headers.insert(VARY, value);
+}
+
+/// Middleware wrapper service which merges the request headers registered in
+/// `VaryHeaders` into the `Vary` header of responses.
+#[derive(Debug)]
+pub struct VaryMakeService<T, C> {
+    inner: T,
+    marker: PhantomData<fn(C)>,
+}
+
+impl<T, C> VaryMakeService<T, C> {
+    /// Create a new VaryMakeService.
+    pub fn new(inner: T) -> Self {
+        VaryMakeService {
+            inner,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<Inner, C, Target> Service<Target> for VaryMakeService<Inner, C>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+{
+    type Response = VaryService<Inner::Response, C>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        Box::pin(self.inner.call(target).map(|s| Ok(VaryService::new(s?))))
+    }
+}
+
+/// Middleware wrapper service which merges the request headers registered in
+/// `VaryHeaders` into the `Vary` header of responses. Servers will normally
+/// want to use `VaryMakeService`, which will create a `VaryService` for each
+/// connection.
+#[derive(Debug)]
+pub struct VaryService<T, C> {
+    inner: T,
+    marker: PhantomData<fn(C)>,
+}
+
+impl<T, C> VaryService<T, C> {
+    /// Create a new VaryService.
+    pub fn new(inner: T) -> Self {
+        VaryService {
+            inner,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Clone, C> Clone for VaryService<T, C> {
+    fn clone(&self) -> Self {
+        Self::new(self.inner.clone())
+    }
+}
+
+impl<T, C, ReqBody, ResBody> Service<(Request<ReqBody>, C)> for VaryService<T, C>
+where
+    C: Push<VaryHeaders>,
+    T: Service<(Request<ReqBody>, C::Result), Response = Response<ResBody>>,
+    T::Future: Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        let vary = VaryHeaders::default();
+        let response = self.inner.call((req, context.push(vary.clone())));
+        Box::pin(response.map(move |response| {
+            let mut response = response?;
+            merge_vary(response.headers_mut(), vary.names());
+            Ok(response)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Has, XSpanIdString};
+    use hyper::header::{ACCEPT, ACCEPT_LANGUAGE, AUTHORIZATION};
+
+    crate::new_context_type!(TestContext, TestEmptyContext, VaryHeaders, XSpanIdString);
+
+    struct TestApi;
+
+    impl<C: Has<VaryHeaders>> Service<(Request<()>, C)> for TestApi {
+        type Response = Response<()>;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, (req, context): (Request<()>, C)) -> Self::Future {
+            let vary: &VaryHeaders = context.get();
+            vary.add(ACCEPT);
+            vary.add(ACCEPT_LANGUAGE);
+            if req.uri().path() == "/any" {
+                vary.add(HeaderName::from_static("*"));
+            }
+            let response = Response::builder()
+                .header(VARY, "Origin, accept")
+                .header(VARY, "Accept-Encoding")
+                .body(())
+                .unwrap();
+            futures::future::ok(response)
+        }
+    }
+
+    #[tokio::test]
+    async fn merges_vary_headers() {
+        let service = VaryService::<_, TestEmptyContext>::new(TestApi);
+
+        let request = Request::get("/").body(()).unwrap();
+        let response = service.call((request, TestEmptyContext)).await.unwrap();
+        let vary: Vec<_> = response.headers().get_all(VARY).iter().collect();
+        assert_eq!(vary, ["Origin, accept, Accept-Encoding, accept-language"]);
+
+        let request = Request::get("/any").body(()).unwrap();
+        let response = service.call((request, TestEmptyContext)).await.unwrap();
+        assert_eq!(response.headers()[VARY], "*");
+
+        let mut headers = HeaderMap::new();
+        merge_vary(&mut headers, []);
+        assert!(headers.is_empty());
+        merge_vary(&mut headers, [AUTHORIZATION]);
+        assert_eq!(headers[VARY], "authorization");
+    }
+}
@@ -0,0 +1,338 @@
+//! Module for routing requests between several versions of an API.
+//!
+//! Use by passing `hyper::server::MakeService` instances for each API version
+//! to a `VersionRouterMakeService`. Each request is dispatched based on the
+//! version requested in its `X-API-Version` header or, if that is absent, on a
+//! `version` parameter in its `Accept` header, e.g.
+//! `Accept: application/json; version=2`.
+//!
+//! Example Usage
+//! =============
+//!
+//! ```ignore
+//! let mut make_service = VersionRouterMakeService::new().default_version("1");
+//! make_service.push("1", Box::new(api_v1_make_service));
+//! make_service.push("2", Box::new(api_v2_make_service));
+//!
+//! // use as you would any `MakeService` instance
+//! ```
+use crate::composites::{CompositedMakeService, CompositedService};
+use crate::response::json_error;
+use crate::XSpanIdString;
+use futures::future::{BoxFuture, TryFutureExt};
+use hyper::header::{HeaderName, ACCEPT};
+use hyper::service::Service;
+use hyper::{Request, Response, StatusCode};
+use std::fmt;
+use std::sync::Arc;
+
+/// Header used to request an API version.
+pub const X_API_VERSION: &str = "X-API-Version";
+
+/// Configuration for selecting the version of the API to handle a request.
+#[derive(Debug, Clone)]
+struct VersionSelector {
+    header: HeaderName,
+    media_type_parameter: String,
+    default_version: Option<String>,
+    reject_unknown: bool,
+}
+
+impl VersionSelector {
+    /// Find the version requested by the `Accept` header, if any.
+    fn requested_by_accept<'a, B>(&self, req: &'a Request<B>) -> Option<&'a str> {
+        req.headers()
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .flat_map(|media_range| media_range.split(';').skip(1))
+            .filter_map(|param| param.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case(&self.media_type_parameter))
+            .map(|(_, value)| value.trim().trim_matches('"'))
+    }
+
+    /// Select the version to handle the request, from those available.
+    ///
+    /// Returns `None` if the request should be rejected.
+    fn select<'a, B>(&'a self, req: &'a Request<B>, available: &[String]) -> Option<&'a str> {
+        let requested = req
+            .headers()
+            .get(&self.header)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .or_else(|| self.requested_by_accept(req));
+
+        match requested {
+            Some(version) if available.iter().any(|v| v == version) => Some(version),
+            Some(_) if self.reject_unknown => None,
+            _ => self.default_version.as_deref(),
+        }
+    }
+}
+
+/// `MakeService` for a single API version.
+type VersionEntry<Target, ReqBody, ResBody, Error, MakeError> = (
+    String,
+    Box<dyn CompositedMakeService<Target, ReqBody, ResBody, Error, MakeError> + Send>,
+);
+
+/// Routes requests between `MakeService`s for different API versions.
+///
+/// If the request doesn't specify a version, the default version is used. If
+/// there is no default, or the request asks for an unknown version, the
+/// request is rejected with `406 Not Acceptable`. Alternatively, requests for
+/// unknown versions can be passed to the default version by calling
+/// `reject_unknown_versions(false)`.
+pub struct VersionRouterMakeService<Target, ReqBody, ResBody, Error, MakeError> {
+    versions: Vec<VersionEntry<Target, ReqBody, ResBody, Error, MakeError>>,
+    selector: VersionSelector,
+}
+
+impl<Target, ReqBody, ResBody, Error, MakeError>
+    VersionRouterMakeService<Target, ReqBody, ResBody, Error, MakeError>
+{
+    /// Create an empty `VersionRouterMakeService`, using the `X-API-Version`
+    /// header and the `version` media type parameter to select a version.
+    pub fn new() -> Self {
+        VersionRouterMakeService {
+            versions: Vec::new(),
+            selector: VersionSelector {
+                header: HeaderName::from_static("x-api-version"),
+                media_type_parameter: "version".to_string(),
+                default_version: None,
+                reject_unknown: true,
+            },
+        }
+    }
+
+    /// Add the `MakeService` for an API version.
+    pub fn push<V: Into<String>>(
+        &mut self,
+        version: V,
+        make_service: Box<
+            dyn CompositedMakeService<Target, ReqBody, ResBody, Error, MakeError> + Send,
+        >,
+    ) {
+        self.versions.push((version.into(), make_service));
+    }
+
+    /// Set the version used for requests which don't specify one.
+    pub fn default_version<V: Into<String>>(mut self, version: V) -> Self {
+        self.selector.default_version = Some(version.into());
+        self
+    }
+
+    /// Set the header used to request a version.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.selector.header = header;
+        self
+    }
+
+    /// Set the name of the `Accept` media type parameter used to request a version.
+    pub fn media_type_parameter<P: Into<String>>(mut self, parameter: P) -> Self {
+        self.selector.media_type_parameter = parameter.into();
+        self
+    }
+
+    /// Configure whether requests for unknown versions are rejected with
+    /// `406 Not Acceptable` (the default), or passed to the default version.
+    pub fn reject_unknown_versions(mut self, reject: bool) -> Self {
+        self.selector.reject_unknown = reject;
+        self
+    }
+}
+
+impl<Target, ReqBody, ResBody, Error, MakeError> Default
+    for VersionRouterMakeService<Target, ReqBody, ResBody, Error, MakeError>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Target, ReqBody, ResBody, Error, MakeError> fmt::Debug
+    for VersionRouterMakeService<Target, ReqBody, ResBody, Error, MakeError>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let versions: Vec<&str> = self.versions.iter().map(|(v, _)| v.as_str()).collect();
+        f.debug_struct("VersionRouterMakeService")
+            .field("versions", &versions)
+            .field("selector", &self.selector)
+            .finish()
+    }
+}
+
+impl<Target, ReqBody, ResBody, Error, MakeError> Service<Target>
+    for VersionRouterMakeService<Target, ReqBody, ResBody, Error, MakeError>
+where
+    Target: Clone,
+    ReqBody: 'static,
+    ResBody: 'static,
+    MakeError: Send + 'static,
+    Error: 'static,
+{
+    type Response = VersionRouter<ReqBody, ResBody, Error>;
+    type Error = MakeError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let services = self.versions.iter().map(|(version, make_service)| {
+            let version = version.clone();
+            make_service
+                .call(target.clone())
+                .map_ok(move |service| (version, service))
+        });
+        let selector = Arc::new(self.selector.clone());
+
+        Box::pin(
+            futures::future::try_join_all(services).map_ok(move |services| {
+                let (versions, services) = services.into_iter().unzip();
+                VersionRouter {
+                    versions,
+                    services,
+                    selector,
+                }
+            }),
+        )
+    }
+}
+
+/// Routes requests between services for different API versions. Servers will
+/// normally want to use `VersionRouterMakeService`, which will create a
+/// `VersionRouter` for each connection.
+pub struct VersionRouter<ReqBody, ResBody, Error> {
+    versions: Vec<String>,
+    services: Vec<Box<dyn CompositedService<ReqBody, ResBody, Error> + Send>>,
+    selector: Arc<VersionSelector>,
+}
+
+impl<ReqBody, ResBody, Error> fmt::Debug for VersionRouter<ReqBody, ResBody, Error> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VersionRouter")
+            .field("versions", &self.versions)
+            .field("selector", &self.selector)
+            .finish()
+    }
+}
+
+impl<ReqBody, ResBody, Error> Service<Request<ReqBody>> for VersionRouter<ReqBody, ResBody, Error>
+where
+    ResBody: From<String> + Send + 'static,
+    Error: Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, req: Request<ReqBody>) -> Self::Future {
+        let index = self
+            .selector
+            .select(&req, &self.versions)
+            .and_then(|version| self.versions.iter().position(|v| v == version));
+
+        match index {
+            Some(index) => self.services[index].call(req),
+            None => {
+                let x_span_id = XSpanIdString::get_or_generate(&req);
+                Box::pin(futures::future::ok(json_error(
+                    StatusCode::NOT_ACCEPTABLE,
+                    "Requested API version is not available",
+                    Some(&x_span_id.0),
+                )))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type TestRouter = VersionRouterMakeService<(), (), String, (), ()>;
+
+    struct MakeVersionService(&'static str);
+
+    impl<Target> Service<Target> for MakeVersionService {
+        type Response = VersionService;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _target: Target) -> Self::Future {
+            futures::future::ok(VersionService(self.0))
+        }
+    }
+
+    struct VersionService(&'static str);
+
+    impl Service<Request<()>> for VersionService {
+        type Response = Response<String>;
+        type Error = ();
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _req: Request<()>) -> Self::Future {
+            futures::future::ok(Response::new(self.0.to_string()))
+        }
+    }
+
+    async fn send(
+        router: &VersionRouter<(), String, ()>,
+        headers: &[(&str, &str)],
+    ) -> Response<String> {
+        let mut req = Request::get("/pets");
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        Service::call(router, req.body(()).unwrap()).await.unwrap()
+    }
+
+    fn make_router() -> TestRouter {
+        let mut make_service = TestRouter::new();
+        make_service.push("1", Box::new(MakeVersionService("v1")));
+        make_service.push("2", Box::new(MakeVersionService("v2")));
+        make_service
+    }
+
+    #[tokio::test]
+    async fn routes_by_version() {
+        let make_service = make_router().default_version("1");
+        let router = Service::call(&make_service, ()).await.unwrap();
+
+        assert_eq!(send(&router, &[]).await.body(), "v1");
+        assert_eq!(send(&router, &[(X_API_VERSION, "2")]).await.body(), "v2");
+        assert_eq!(
+            send(
+                &router,
+                &[("accept", "text/plain, application/json; version=\"2\"")]
+            )
+            .await
+            .body(),
+            "v2"
+        );
+        assert_eq!(
+            send(&router, &[(X_API_VERSION, "3")]).await.status(),
+            StatusCode::NOT_ACCEPTABLE
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_versions_use_default() {
+        let make_service = make_router()
+            .default_version("2")
+            .reject_unknown_versions(false);
+        let router = Service::call(&make_service, ()).await.unwrap();
+
+        assert_eq!(send(&router, &[(X_API_VERSION, "3")]).await.body(), "v2");
+    }
+
+    #[tokio::test]
+    async fn rejects_without_default() {
+        let make_service = make_router();
+        let router = Service::call(&make_service, ()).await.unwrap();
+
+        assert_eq!(
+            send(&router, &[]).await.status(),
+            StatusCode::NOT_ACCEPTABLE
+        );
+    }
+}
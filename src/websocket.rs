@@ -0,0 +1,332 @@
+//! Hyper service handing WebSocket connections to a handler, alongside an API.
+//!
+//! `WebSocketService` recognises WebSocket opening handshakes (RFC 6455)
+//! among the requests to an API, answers them with `101 Switching
+//! Protocols`, and passes the upgraded connection to a [`WebSocketHandler`],
+//! together with the request and its context. Other requests are passed on
+//! to the API, so REST and WebSocket endpoints can share a listener.
+//!
+//! Only the handshake is handled here - the handler reads and writes
+//! WebSocket frames on the upgraded connection, for example using
+//! `tokio-tungstenite`'s `WebSocketStream::from_raw_socket`. Handshakes over
+//! HTTP/2 (RFC 8441) aren't supported. Upgraded connections aren't drained
+//! when the server shuts down, so handlers should watch the server's shutdown
+//! token.
+//!
+//! ```rust
+//! # use swagger::websocket::{WebSocketIo, WebSocketService};
+//! # use swagger::{Has, XSpanIdString};
+//! # use hyper::http::request::Parts;
+//! # fn wrap<T, C: Has<XSpanIdString>>(api: T) {
+//! let service = WebSocketService::new(api, |io: WebSocketIo, request: Parts, context: C| async move {
+//!     let x_span_id: &XSpanIdString = context.get();
+//!     // Serve the WebSocket connection on `io`
+//! });
+//! # }
+//! ```
+
+use crate::response::json_error;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use futures::future::{BoxFuture, Either, FutureExt};
+use hyper::header::{
+    HeaderMap, HeaderName, HeaderValue, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY,
+    SEC_WEBSOCKET_VERSION, UPGRADE,
+};
+use hyper::http::request::Parts;
+use hyper::service::Service;
+use hyper::upgrade::Upgraded;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use sha1::{Digest, Sha1};
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+
+/// The GUID appended to the client's key to compute `Sec-WebSocket-Accept`.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The upgraded I/O of a WebSocket connection.
+pub type WebSocketIo = TokioIo<Upgraded>;
+
+/// Handler for WebSocket connections, implemented for functions of the
+/// upgraded I/O, the request and its context.
+pub trait WebSocketHandler<C>: Send + Sync + 'static {
+    /// Whether to accept a WebSocket handshake. Requests which aren't accepted
+    /// are passed on to the wrapped service.
+    ///
+    /// Defaults to accepting all handshakes.
+    fn accepts(&self, _request: &Parts, _context: &C) -> bool {
+        true
+    }
+
+    /// Serve an upgraded connection.
+    fn call(&self, io: WebSocketIo, request: Parts, context: C) -> BoxFuture<'static, ()>;
+}
+
+impl<F, Fut, C> WebSocketHandler<C> for F
+where
+    F: Fn(WebSocketIo, Parts, C) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    fn call(&self, io: WebSocketIo, request: Parts, context: C) -> BoxFuture<'static, ()> {
+        Box::pin(self(io, request, context))
+    }
+}
+
+/// Whether a header contains a token, case-insensitively, in any of its
+/// comma-separated values.
+fn has_token(headers: &HeaderMap, name: HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(token))
+}
+
+/// The `Sec-WebSocket-Accept` value for a `Sec-WebSocket-Key`.
+fn accept_key(key: &[u8]) -> HeaderValue {
+    let mut sha1 = Sha1::new();
+    sha1.update(key);
+    sha1.update(WEBSOCKET_GUID);
+    HeaderValue::from_str(&STANDARD.encode(sha1.finalize())).expect("Base64 is a valid header")
+}
+
+/// Middleware wrapper service handing WebSocket connections to a handler.
+pub struct WebSocketMakeService<T, H> {
+    inner: T,
+    handler: Arc<H>,
+}
+
+impl<T, H> WebSocketMakeService<T, H> {
+    /// Create a new WebSocketMakeService.
+    pub fn new(inner: T, handler: H) -> Self {
+        WebSocketMakeService {
+            inner,
+            handler: Arc::new(handler),
+        }
+    }
+}
+
+impl<T: fmt::Debug, H> fmt::Debug for WebSocketMakeService<T, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocketMakeService")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<Inner, H, Target> Service<Target> for WebSocketMakeService<Inner, H>
+where
+    Inner: Service<Target>,
+    Inner::Future: Send + 'static,
+    H: Send + Sync + 'static,
+{
+    type Response = WebSocketService<Inner::Response, H>;
+    type Error = Inner::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn call(&self, target: Target) -> Self::Future {
+        let handler = self.handler.clone();
+        Box::pin(
+            self.inner
+                .call(target)
+                .map(|s| Ok(WebSocketService { inner: s?, handler })),
+        )
+    }
+}
+
+/// Middleware wrapper service handing WebSocket connections to a handler.
+/// Servers will normally want to use `WebSocketMakeService`, which will
+/// create a `WebSocketService` for each connection.
+pub struct WebSocketService<T, H> {
+    inner: T,
+    handler: Arc<H>,
+}
+
+impl<T, H> WebSocketService<T, H> {
+    /// Create a new WebSocketService.
+    pub fn new(inner: T, handler: H) -> Self {
+        WebSocketService {
+            inner,
+            handler: Arc::new(handler),
+        }
+    }
+}
+
+impl<T: Clone, H> Clone for WebSocketService<T, H> {
+    fn clone(&self) -> Self {
+        WebSocketService {
+            inner: self.inner.clone(),
+            handler: self.handler.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug, H> fmt::Debug for WebSocketService<T, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocketService")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<T, H, C, ReqBody, ResBody> Service<(Request<ReqBody>, C)> for WebSocketService<T, H>
+where
+    T: Service<(Request<ReqBody>, C), Response = Response<ResBody>>,
+    H: WebSocketHandler<C>,
+    C: Send + 'static,
+    ResBody: From<String>,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = Either<T::Future, futures::future::Ready<Result<Self::Response, Self::Error>>>;
+
+    fn call(&self, (req, context): (Request<ReqBody>, C)) -> Self::Future {
+        if !has_token(req.headers(), UPGRADE, "websocket")
+            || !has_token(req.headers(), CONNECTION, "upgrade")
+        {
+            return Either::Left(self.inner.call((req, context)));
+        }
+
+        let (parts, body) = req.into_parts();
+        if !self.handler.accepts(&parts, &context) {
+            return Either::Left(self.inner.call((Request::from_parts(parts, body), context)));
+        }
+
+        let key = parts.headers.get(SEC_WEBSOCKET_KEY);
+        let response = if parts.method != Method::GET || key.is_none() {
+            json_error(StatusCode::BAD_REQUEST, "Invalid WebSocket handshake", None)
+        } else if parts
+            .headers
+            .get(SEC_WEBSOCKET_VERSION)
+            .map(|v| v.as_bytes())
+            != Some(b"13")
+        {
+            let mut response = json_error(
+                StatusCode::UPGRADE_REQUIRED,
+                "Unsupported WebSocket version",
+                None,
+            );
+            response
+                .headers_mut()
+                .insert(SEC_WEBSOCKET_VERSION, HeaderValue::from_static("13"));
+            response
+        } else {
+            let accept = key.map(|key| accept_key(key.as_bytes()));
+
+            // The upgrade is taken from the request's extensions
+            let mut request = Request::from_parts(parts, ());
+            let on_upgrade = hyper::upgrade::on(&mut request);
+            let (parts, ()) = request.into_parts();
+            let handler = self.handler.clone();
+            tokio::spawn(async move {
+                if let Ok(upgraded) = on_upgrade.await {
+                    handler.call(TokioIo::new(upgraded), parts, context).await;
+                }
+            });
+
+            let mut response = Response::new(ResBody::from(String::new()));
+            *response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+            let headers = response.headers_mut();
+            headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+            headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+            headers.extend(accept.map(|accept| (SEC_WEBSOCKET_ACCEPT, accept)));
+            response
+        };
+        Either::Right(futures::future::ok(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::Server;
+    use crate::{AddContextMakeService, ContextBuilder, EmptyContext, Has, XSpanIdString};
+    use http_body_util::Full;
+    use hyper::body::{Bytes, Incoming};
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    type TestContext = ContextBuilder<XSpanIdString, EmptyContext>;
+
+    struct MakeTestApi;
+
+    impl Service<SocketAddr> for MakeTestApi {
+        type Response = TestApi;
+        type Error = Infallible;
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _target: SocketAddr) -> Self::Future {
+            futures::future::ok(TestApi)
+        }
+    }
+
+    struct TestApi;
+
+    impl<C> Service<(Request<Incoming>, C)> for TestApi {
+        type Response = Response<Full<Bytes>>;
+        type Error = Infallible;
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn call(&self, _req: (Request<Incoming>, C)) -> Self::Future {
+            futures::future::ok(Response::new(Full::from("api")))
+        }
+    }
+
+    /// Send a request, returning the response head.
+    async fn send(stream: &mut TcpStream, request: &str) -> String {
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await.unwrap());
+        }
+        String::from_utf8(head).unwrap().to_lowercase()
+    }
+
+    #[tokio::test]
+    async fn hands_upgraded_connections_to_handler() {
+        let handler = |mut io: WebSocketIo, request: Parts, context: TestContext| async move {
+            let x_span_id: &XSpanIdString = context.get();
+            let greeting = format!("{} {}", request.uri.path(), x_span_id.0);
+            io.write_all(greeting.as_bytes()).await.unwrap();
+            let mut message = [0; 4];
+            io.read_exact(&mut message).await.unwrap();
+            io.write_all(&message).await.unwrap();
+        };
+        let make_service = AddContextMakeService::<_, EmptyContext>::new(
+            WebSocketMakeService::new(MakeTestApi, handler),
+        );
+        let server = Server::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.serve_with_shutdown(make_service, futures::future::pending()));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let head = send(&mut stream, "GET /api HTTP/1.1\r\nhost: localhost\r\n\r\n").await;
+        assert!(head.starts_with("http/1.1 200"));
+        let mut body = [0; 3];
+        stream.read_exact(&mut body).await.unwrap();
+        assert_eq!(&body, b"api");
+
+        let head = send(
+            &mut stream,
+            "GET /events HTTP/1.1\r\nhost: localhost\r\nconnection: keep-alive, Upgrade\r\n\
+             upgrade: websocket\r\nsec-websocket-key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+             sec-websocket-version: 13\r\nx-span-id: span\r\n\r\n",
+        )
+        .await;
+        assert!(head.starts_with("http/1.1 101"));
+        assert!(head.contains("sec-websocket-accept: s3pplmbitxaq9kygzzhzrbk+xoo=\r\n"));
+
+        let mut greeting = [0; 12];
+        stream.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(&greeting, b"/events span");
+        stream.write_all(b"ping").await.unwrap();
+        let mut message = [0; 4];
+        stream.read_exact(&mut message).await.unwrap();
+        assert_eq!(&message, b"ping");
+    }
+}
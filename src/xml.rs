@@ -0,0 +1,305 @@
+//! XML request and response bodies.
+//!
+//! `to_string` and `from_str` convert between serde types and XML documents,
+//! using `XmlOptions` for the parts of an OpenAPI `xml` annotation which
+//! can't be expressed with serde attributes - the name and namespaces of the
+//! root element. Attributes are mapped with `#[serde(rename = "@name")]`,
+//! and element names with `#[serde(rename = "name")]`. Prefixes are ignored
+//! when reading documents, so prefixed elements are mapped with
+//! `#[serde(rename(serialize = "prefix:name", deserialize = "name"))]`.
+//!
+//! Arrays with `wrapped: true` are mapped with `xml_wrapped!`.
+//!
+//! ```rust
+//! # use serde::{Deserialize, Serialize};
+//! # use swagger::xml::{self, XmlOptions};
+//! swagger::xml_wrapped!(photo_urls, "photoUrl");
+//!
+//! #[derive(Debug, PartialEq, Deserialize, Serialize)]
+//! struct Pet {
+//!     #[serde(rename = "@id")]
+//!     id: u64,
+//!     name: String,
+//!     #[serde(rename = "photoUrls", with = "photo_urls")]
+//!     photo_urls: Vec<String>,
+//! }
+//!
+//! let pet = Pet {
+//!     id: 1,
+//!     name: "Fluffy".to_string(),
+//!     photo_urls: vec!["a.png".to_string(), "b.png".to_string()],
+//! };
+//!
+//! let options = XmlOptions::new("Pet").namespace("http://example.com/schema");
+//! let body = xml::to_string(&pet, &options).unwrap();
+//! assert_eq!(
+//!     body,
+//!     "<Pet xmlns=\"http://example.com/schema\" id=\"1\"><name>Fluffy</name>\
+//!      <photoUrls><photoUrl>a.png</photoUrl><photoUrl>b.png</photoUrl></photoUrls></Pet>"
+//! );
+//! assert_eq!(xml::from_str::<Pet>(&body).unwrap(), pet);
+//! ```
+
+use serde::de::{DeserializeOwned, Deserializer, MapAccess, Visitor};
+use serde::ser::{SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// The `application/xml` media type.
+pub const APPLICATION_XML: &str = "application/xml";
+
+/// Whether a `Content-Type` is XML: `application/xml`, `text/xml`, or a type
+/// with the `+xml` suffix such as `application/atom+xml`.
+pub fn is_xml(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence == APPLICATION_XML || essence == "text/xml" || essence.ends_with("+xml")
+}
+
+/// Options for writing an XML document.
+#[derive(Clone, Debug)]
+pub struct XmlOptions {
+    root: String,
+    namespaces: Vec<(Option<String>, String)>,
+    declaration: bool,
+}
+
+impl XmlOptions {
+    /// Write documents with the given root element, e.g. `Pet` or `ex:Pet`.
+    pub fn new(root: &str) -> Self {
+        XmlOptions {
+            root: root.to_string(),
+            namespaces: Vec::new(),
+            declaration: false,
+        }
+    }
+
+    /// Declare the default namespace on the root element.
+    pub fn namespace(mut self, uri: &str) -> Self {
+        self.namespaces.push((None, uri.to_string()));
+        self
+    }
+
+    /// Declare a namespace prefix on the root element.
+    pub fn prefixed_namespace(mut self, prefix: &str, uri: &str) -> Self {
+        self.namespaces
+            .push((Some(prefix.to_string()), uri.to_string()));
+        self
+    }
+
+    /// Start documents with an `<?xml version="1.0" encoding="UTF-8"?>`
+    /// declaration.
+    pub fn declaration(mut self, declaration: bool) -> Self {
+        self.declaration = declaration;
+        self
+    }
+}
+
+/// Error returned when an XML document can't be written or read.
+#[derive(Debug)]
+pub struct XmlError(String);
+
+impl fmt::Display for XmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid XML: {}", self.0)
+    }
+}
+
+impl std::error::Error for XmlError {}
+
+/// Serialize `value` as an XML document.
+pub fn to_string<T: Serialize>(value: &T, options: &XmlOptions) -> Result<String, XmlError> {
+    let body = quick_xml::se::to_string_with_root(&options.root, value)
+        .map_err(|e| XmlError(e.to_string()))?;
+
+    let mut xml = String::with_capacity(body.len() + 64);
+    if options.declaration {
+        xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+    }
+
+    // The serializer always starts with the root element's name
+    let (start, rest) = body.split_at(options.root.len() + 1);
+    xml.push_str(start);
+    for (prefix, uri) in &options.namespaces {
+        let uri = quick_xml::escape::escape(uri.as_str());
+        match prefix {
+            Some(prefix) => xml.push_str(&format!(" xmlns:{}=\"{}\"", prefix, uri)),
+            None => xml.push_str(&format!(" xmlns=\"{}\"", uri)),
+        }
+    }
+    xml.push_str(rest);
+    Ok(xml)
+}
+
+/// Deserialize an XML document. The root element's name isn't checked.
+pub fn from_str<T: DeserializeOwned>(xml: &str) -> Result<T, XmlError> {
+    quick_xml::de::from_str(xml).map_err(|e| XmlError(e.to_string()))
+}
+
+/// Deserialize an XML document, e.g. a collected request body.
+pub fn from_bytes<T: DeserializeOwned>(xml: &[u8]) -> Result<T, XmlError> {
+    let xml = std::str::from_utf8(xml).map_err(|e| XmlError(e.to_string()))?;
+    from_str(xml)
+}
+
+/// Serialize an array inside a wrapper element, with each item in an `item`
+/// element. Used by `xml_wrapped!`.
+pub fn serialize_wrapped<S, T>(
+    items: &[T],
+    item: &'static str,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: Serialize,
+{
+    let mut wrapper = serializer.serialize_struct("Wrapper", 1)?;
+    wrapper.serialize_field(item, items)?;
+    wrapper.end()
+}
+
+/// Deserialize an array from a wrapper element, with each item in an `item`
+/// element. Used by `xml_wrapped!`.
+pub fn deserialize_wrapped<'de, D, T>(
+    item: &'static str,
+    deserializer: D,
+) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    struct WrapperVisitor<T> {
+        item: &'static str,
+        marker: PhantomData<T>,
+    }
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for WrapperVisitor<T> {
+        type Value = Vec<T>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "a wrapper element of <{}> elements", self.item)
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut items = Vec::new();
+            while let Some(key) = map.next_key::<String>()? {
+                // Prefixes are ignored when reading
+                if local_name(&key) == local_name(self.item) {
+                    items.extend(map.next_value::<Vec<T>>()?);
+                } else {
+                    map.next_value::<serde::de::IgnoredAny>()?;
+                }
+            }
+            Ok(items)
+        }
+
+        // An empty wrapper element
+        fn visit_str<E: serde::de::Error>(self, _: &str) -> Result<Self::Value, E> {
+            Ok(Vec::new())
+        }
+
+        fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+            Ok(Vec::new())
+        }
+    }
+
+    deserializer.deserialize_map(WrapperVisitor {
+        item,
+        marker: PhantomData,
+    })
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+/// Define a module for use with `#[serde(with = "...")]` which maps a `Vec`
+/// to an OpenAPI array with `xml: { wrapped: true }` - i.e. the field's
+/// element contains an `item` element for each entry.
+///
+/// ```rust
+/// swagger::xml_wrapped!(tags, "tag");
+///
+/// #[derive(serde::Serialize, serde::Deserialize)]
+/// struct Pet {
+///     #[serde(with = "tags")]
+///     tags: Vec<String>,
+/// }
+/// ```
+#[macro_export]
+macro_rules! xml_wrapped {
+    ($module:ident, $item:literal) => {
+        mod $module {
+            #[allow(clippy::ptr_arg)]
+            pub fn serialize<S, T>(items: &Vec<T>, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+                T: serde::Serialize,
+            {
+                $crate::xml::serialize_wrapped(items, $item, serializer)
+            }
+
+            pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+                T: serde::Deserialize<'de>,
+            {
+                $crate::xml::deserialize_wrapped($item, deserializer)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::xml_wrapped!(tags, "ex:tag");
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Pet {
+        #[serde(rename(serialize = "ex:name", deserialize = "name"))]
+        name: String,
+        #[serde(rename(serialize = "ex:tags", deserialize = "tags"), with = "tags")]
+        tags: Vec<String>,
+        #[serde(rename(serialize = "ex:photo", deserialize = "photo"), default)]
+        photos: Vec<String>,
+    }
+
+    #[test]
+    fn writes_namespaces_and_wrappers() {
+        let pet = Pet {
+            name: "Rex & co".to_string(),
+            tags: vec![],
+            photos: vec!["a.png".to_string(), "b.png".to_string()],
+        };
+        let options = XmlOptions::new("ex:Pet")
+            .prefixed_namespace("ex", "http://example.com/?a=1&b=2")
+            .declaration(true);
+
+        let body = to_string(&pet, &options).unwrap();
+        assert_eq!(
+            body,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+             <ex:Pet xmlns:ex=\"http://example.com/?a=1&amp;b=2\"><ex:name>Rex &amp; co</ex:name>\
+             <ex:tags/><ex:photo>a.png</ex:photo><ex:photo>b.png</ex:photo></ex:Pet>"
+        );
+        assert_eq!(from_str::<Pet>(&body).unwrap(), pet);
+
+        let pet: Pet = from_bytes(
+            b"<ex:Pet><ex:name>Rex</ex:name>\
+              <ex:tags><ex:tag>good</ex:tag><ex:tag>dog</ex:tag></ex:tags></ex:Pet>",
+        )
+        .unwrap();
+        assert_eq!(pet.tags, ["good", "dog"]);
+        assert!(pet.photos.is_empty());
+
+        assert!(is_xml("application/atom+xml; charset=utf-8"));
+        assert!(!is_xml("application/json"));
+    }
+}